@@ -0,0 +1,60 @@
+/*
+ * The C ABI of rush, available with the `ffi` feature on Unix.
+ *
+ * Functions return 0 (or a non-negative result) on success and -1 on failure, in
+ * which case rush_last_error() describes what went wrong. Strings are
+ * NUL-terminated UTF-8.
+ */
+
+#ifndef RUSH_H
+#define RUSH_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The result of rush_run_command(). */
+typedef struct RushOutput {
+    /* The exit code, or -1 if the program was terminated by a signal */
+    int exit_code;
+    /* Everything the program wrote to standard output */
+    char *stdout;
+    /* Everything the program wrote to standard error */
+    char *stderr;
+} RushOutput;
+
+/*
+ * The message of the last failed call on this thread, or NULL if no call failed
+ * yet. The string is owned by the library and valid until the next failing call
+ * on this thread.
+ */
+const char *rush_last_error(void);
+
+/* Copy the directory `source` with everything in it to `destination`. */
+int rush_copy_tree(const char *source, const char *destination);
+
+/*
+ * Ensure a file at `path` with exactly `content` and, if `mode` is not negative,
+ * these permission bits. Returns 1 if the file was changed and 0 if it already was
+ * as desired.
+ */
+int rush_ensure_file(const char *path, const char *content, long mode);
+
+/*
+ * Run the program argv[0] with the arguments argv[1..], where `argv` ends with
+ * NULL, to completion. If `timeout_milliseconds` is not 0, the program is killed
+ * after that long and the call fails. A non-zero exit code is not a failure.
+ * Fills `output`, which is then released with rush_output_free().
+ */
+int rush_run_command(const char *const *argv, uint64_t timeout_milliseconds, RushOutput *output);
+
+/* Release the strings of `output` and set them to NULL. */
+void rush_output_free(RushOutput *output);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,196 @@
+//! This module contains parsing of environment files, i.e. `KEY=value` lines like in
+//! `.env` files, systemd's `EnvironmentFile=` or `/etc/os-release`.
+
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
+/// Why a line of an environment file is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineError {
+    /// The line contains no `=`
+    MissingEquals,
+    /// The name before the `=` is not a valid variable name
+    InvalidName,
+    /// A quoted value is not closed
+    UnclosedQuote,
+    /// Something other than a comment follows a quoted value
+    TrailingCharacters,
+}
+
+impl core::fmt::Display for LineError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let message = match self {
+            Self::MissingEquals => "the line is not of the form KEY=value",
+            Self::InvalidName => "the variable name is invalid",
+            Self::UnclosedQuote => "a quoted value is not closed",
+            Self::TrailingCharacters => "characters follow a quoted value",
+        };
+        write!(f, "{message}")
+    }
+}
+
+impl core::error::Error for LineError {}
+
+/// An invalid line of an environment file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseError {
+    /// The number of the line, starting at 1
+    pub line:  usize,
+    /// Why the line is invalid
+    pub error: LineError,
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Parse one line of an environment file. Empty lines and comments starting with
+/// `#` yield [`None`].
+///
+/// A line may start with `export`. Values in single quotes are taken literally;
+/// in double quotes, a backslash escapes `"`, `\`, `$` and `` ` `` and `\n` is a
+/// newline. Unquoted values are trimmed and end at ` #`.
+///
+/// # Errors
+///
+/// Returns an error if the line is not empty, not a comment and not a valid
+/// assignment.
+pub fn parse_line(line: &str) -> Result<Option<(String, String)>, LineError> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let line = line
+        .strip_prefix("export")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map_or(line, str::trim_start);
+    let (name, value) = line.split_once('=').ok_or(LineError::MissingEquals)?;
+    let name = name.trim_end();
+    let mut characters = name.chars();
+    if !characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        || !characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+    {
+        return Err(LineError::InvalidName);
+    }
+
+    let value = value.trim_start();
+    let value = if let Some(quote @ ('"' | '\'')) = value.chars().next() {
+        parse_quoted(&value[1..], quote)?
+    } else {
+        let end = value.find(" #").unwrap_or(value.len());
+        String::from(value[..end].trim_end())
+    };
+    Ok(Some((String::from(name), value)))
+}
+
+/// Parse the rest of a value after its opening `quote`.
+fn parse_quoted(rest: &str, quote: char) -> Result<String, LineError> {
+    let mut value = String::new();
+    let mut characters = rest.char_indices();
+    while let Some((index, character)) = characters.next() {
+        match character {
+            character if character == quote => {
+                let trailing = rest[index + 1..].trim_start();
+                if trailing.is_empty() || trailing.starts_with('#') {
+                    return Ok(value);
+                }
+                return Err(LineError::TrailingCharacters);
+            },
+            '\\' if quote == '"' => match characters.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, escaped @ ('"' | '\\' | '$' | '`'))) => value.push(escaped),
+                Some((_, other)) => {
+                    value.push('\\');
+                    value.push(other);
+                },
+                None => break,
+            },
+            character => value.push(character),
+        }
+    }
+    Err(LineError::UnclosedQuote)
+}
+
+/// Parse all lines of an environment file, see [`parse_line`].
+///
+/// ```
+/// let variables = rush_core::env::parse("# Deployment\nexport PORT=8080\nNAME=\"my app\"\n")
+///     .unwrap();
+/// assert_eq!(variables, [("PORT".into(), "8080".into()), ("NAME".into(), "my app".into())]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an error for the first invalid line.
+pub fn parse(content: &str) -> Result<Vec<(String, String)>, ParseError> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            parse_line(line)
+                .map_err(|error| ParseError {
+                    line: index + 1,
+                    error,
+                })
+                .transpose()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod env_test {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let parsed = parse_line;
+        assert_eq!(parsed("  "), Ok(None));
+        assert_eq!(parsed("# comment"), Ok(None));
+        assert_eq!(
+            parsed("KEY = value # comment"),
+            Ok(Some((String::from("KEY"), String::from("value"))))
+        );
+        assert_eq!(
+            parsed("exported=1"),
+            Ok(Some((String::from("exported"), String::from("1"))))
+        );
+        assert_eq!(
+            parsed(r#"GREETING="say \"hi\"\n\$HOME" # comment"#),
+            Ok(Some((
+                String::from("GREETING"),
+                String::from("say \"hi\"\n$HOME")
+            )))
+        );
+        assert_eq!(
+            parsed(r"LITERAL='\n $HOME'"),
+            Ok(Some((String::from("LITERAL"), String::from(r"\n $HOME"))))
+        );
+        assert_eq!(
+            parsed("EMPTY="),
+            Ok(Some((String::from("EMPTY"), String::new())))
+        );
+        assert_eq!(parsed("no assignment"), Err(LineError::MissingEquals));
+        assert_eq!(parsed("1KEY=value"), Err(LineError::InvalidName));
+        assert_eq!(parsed("KEY=\"open"), Err(LineError::UnclosedQuote));
+        assert_eq!(parsed("KEY='a' b"), Err(LineError::TrailingCharacters));
+    }
+
+    #[test]
+    fn files() {
+        assert_eq!(
+            parse("A=1\n\nB\n"),
+            Err(ParseError {
+                line:  3,
+                error: LineError::MissingEquals,
+            })
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! This module contains glob matching.
+
+use alloc::vec::Vec;
+
+/// Whether `text` matches the glob `pattern`. `*` matches any characters except
+/// `/`, `**` any characters and `?` a single character except `/`. Matching is
+/// case-sensitive.
+///
+/// ```
+/// assert!(rush_core::glob::matches("img/*.png", "img/logo.png"));
+/// assert!(!rush_core::glob::matches("*.png", "img/logo.png"));
+/// assert!(rush_core::glob::matches("**/*.png", "img/logo.png"));
+/// ```
+#[must_use]
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_characters(&pattern, &text)
+}
+
+/// Whether the characters `text` match the glob `pattern`, see [`matches`].
+fn matches_characters(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` also matches no directory at all.
+            rest.strip_prefix(&['/'])
+                .is_some_and(|rest| matches_characters(rest, text))
+                || (0..=text.len()).any(|start| matches_characters(rest, &text[start..]))
+        },
+        ['*', rest @ ..] => {
+            for start in 0..=text.len() {
+                if matches_characters(rest, &text[start..]) {
+                    return true;
+                }
+                if text.get(start) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        },
+        ['?', rest @ ..] => {
+            text.first().is_some_and(|character| *character != '/')
+                && matches_characters(rest, &text[1..])
+        },
+        [character, rest @ ..] => {
+            text.first() == Some(character) && matches_characters(rest, &text[1..])
+        },
+    }
+}
+
+#[cfg(test)]
+mod glob_test {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(matches("*.png", "logo.png"));
+        assert!(!matches("*.png", "img/logo.png"));
+        assert!(matches("img/*.png", "img/logo.png"));
+        assert!(matches("**/*.png", "logo.png"));
+        assert!(matches("**/*.png", "a/b/logo.png"));
+        assert!(matches("a/**", "a/b/c"));
+        assert!(matches("logo-?.png", "logo-2.png"));
+        assert!(!matches("logo-?.png", "logo-10.png"));
+    }
+}
@@ -0,0 +1,16 @@
+//! The pure logic of rush: path normalization, glob matching, parsing environment
+//! files, splitting shell words and parsing units.
+//!
+//! Nothing in here touches the operating system, so the crate is `no_std`
+//! compatible and only needs an allocator. Disable the default `std` feature to
+//! use it without std, e.g. in firmware build tooling.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod env;
+pub mod glob;
+pub mod path;
+pub mod shell;
+pub mod units;
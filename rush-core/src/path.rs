@@ -0,0 +1,100 @@
+//! This module contains lexical path normalization.
+
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
+/// Normalize the `/`-separated `path` lexically, without considering symbolic links.
+///
+/// Empty and `.` components are removed and `..` removes the component before it.
+/// A `..` at the start of a relative path is kept; at the root, it is dropped.
+///
+/// ```
+/// use rush_core::path::normalize;
+/// assert_eq!(normalize("/srv//app/./releases/../current/"), "/srv/app/current");
+/// assert_eq!(normalize("../a/b/../c"), "../a/c");
+/// assert_eq!(normalize("a/.."), ".");
+/// ```
+#[must_use]
+pub fn normalize(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {},
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            },
+            ".." if absolute => {},
+            component => components.push(component),
+        }
+    }
+
+    let joined = components.join("/");
+    if absolute {
+        alloc::format!("/{joined}")
+    } else if joined.is_empty() {
+        String::from(".")
+    } else {
+        joined
+    }
+}
+
+/// Normalize `path` lexically like [`normalize`], for paths of the operating
+/// system, which need not be valid UTF-8.
+#[cfg(feature = "std")]
+#[must_use]
+pub fn normalize_os(path: &std::path::Path) -> std::path::PathBuf {
+    use std::path::Component;
+
+    let mut normalized = std::path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            },
+            Component::ParentDir if normalized.has_root() => {},
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod path_test {
+    use super::*;
+
+    #[test]
+    fn normalize_strings() {
+        assert_eq!(normalize("/"), "/");
+        assert_eq!(normalize("/.."), "/");
+        assert_eq!(normalize("/a/b/../../.."), "/");
+        assert_eq!(normalize(""), ".");
+        assert_eq!(normalize("../../a"), "../../a");
+        assert_eq!(normalize("a/../../b"), "../b");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn normalize_paths() {
+        use std::path::Path;
+
+        assert_eq!(
+            normalize_os(Path::new("/srv/../etc/./passwd")),
+            Path::new("/etc/passwd")
+        );
+        assert_eq!(normalize_os(Path::new("/..")), Path::new("/"));
+        assert_eq!(normalize_os(Path::new("../a/../b")), Path::new("../b"));
+        assert_eq!(normalize_os(Path::new("a/..")), Path::new("."));
+    }
+}
@@ -0,0 +1,131 @@
+//! This module contains splitting command lines into words like a shell does.
+
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
+/// Why a command line cannot be split into words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SplitError {
+    /// The line ends in a backslash
+    TrailingBackslash,
+    /// A `${` is not closed by `}`
+    UnclosedBrace,
+    /// A quote of this kind is not closed
+    UnclosedQuote(char),
+}
+
+impl core::fmt::Display for SplitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TrailingBackslash => write!(f, "a backslash ends the line"),
+            Self::UnclosedBrace => write!(f, "a '${{' is not closed"),
+            Self::UnclosedQuote(quote) => write!(f, "a {quote} quote is not closed"),
+        }
+    }
+}
+
+impl core::error::Error for SplitError {}
+
+/// Split `line` into words like a shell.
+///
+/// Whitespace separates words, single and double quotes group them, a backslash
+/// escapes the next character outside of single quotes and `$NAME` or `${NAME}`
+/// expands to `variable(NAME)` outside of single quotes, or to nothing if that is
+/// [`None`]. A `#` at the start of a word begins a comment.
+///
+/// ```
+/// let words = rush_core::shell::split(r#"echo "Hello, $USER" '$HOME'"#, |_| Some("root".into()));
+/// assert_eq!(words.unwrap(), ["echo", "Hello, root", "$HOME"]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if a quote or `${` is not closed or if the line ends in a
+/// backslash.
+pub fn split(
+    line: &str,
+    variable: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>, SplitError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut characters = line.chars().peekable();
+    while let Some(character) = characters.next() {
+        match (quote, character) {
+            (None, '#') if word.is_none() => break,
+            (None, character) if character.is_whitespace() => {
+                words.extend(word.take());
+            },
+            (None, '\'' | '"') => {
+                quote = Some(character);
+                word.get_or_insert_with(String::new);
+            },
+            (Some(open), character) if character == open => quote = None,
+            (None | Some('"'), '\\') => {
+                let escaped = characters.next().ok_or(SplitError::TrailingBackslash)?;
+                word.get_or_insert_with(String::new).push(escaped);
+            },
+            (None | Some('"'), '$') => {
+                let braced = characters.next_if_eq(&'{').is_some();
+                let mut name = String::new();
+                while let Some(character) =
+                    characters.next_if(|character| character.is_alphanumeric() || *character == '_')
+                {
+                    name.push(character);
+                }
+                if braced && characters.next() != Some('}') {
+                    return Err(SplitError::UnclosedBrace);
+                }
+                let word = word.get_or_insert_with(String::new);
+                if name.is_empty() && !braced {
+                    word.push('$');
+                } else if let Some(value) = variable(&name) {
+                    word.push_str(&value);
+                }
+            },
+            (_, character) => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+    if let Some(open) = quote {
+        return Err(SplitError::UnclosedQuote(open));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod shell_test {
+    use super::*;
+
+    #[test]
+    fn words() {
+        let split =
+            |line: &str| split(line, |name| (name == "NAME").then(|| String::from("world")));
+        assert_eq!(
+            split(r#"write "a file" 'it''s $NAME' "${NAME}s" \$5 $ $UNSET # comment"#),
+            Ok(alloc::vec![
+                String::from("write"),
+                String::from("a file"),
+                String::from("its $NAME"),
+                String::from("worlds"),
+                String::from("$5"),
+                String::from("$"),
+                String::new(),
+            ])
+        );
+        assert_eq!(
+            split("a '' b"),
+            Ok(alloc::vec![
+                String::from("a"),
+                String::new(),
+                String::from("b")
+            ])
+        );
+        assert_eq!(split("   "), Ok(alloc::vec![]));
+        assert_eq!(split("echo 'open"), Err(SplitError::UnclosedQuote('\'')));
+        assert_eq!(split("echo ${NAME"), Err(SplitError::UnclosedBrace));
+        assert_eq!(split("echo \\"), Err(SplitError::TrailingBackslash));
+    }
+}
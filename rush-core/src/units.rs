@@ -0,0 +1,271 @@
+//! This module contains parsing and formatting of byte sizes and durations as people
+//! write them, e.g. `1.5 GiB` or `1h30m`.
+
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+};
+
+/// Why a size or duration cannot be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnitError {
+    /// There is nothing to parse
+    Empty,
+    /// A number is missing or malformed
+    InvalidNumber,
+    /// The unit is not known
+    UnknownUnit,
+    /// The value does not fit into the result
+    Overflow,
+}
+
+impl core::fmt::Display for UnitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let message = match self {
+            Self::Empty => "the value is empty",
+            Self::InvalidNumber => "a number is missing or malformed",
+            Self::UnknownUnit => "the unit is not known",
+            Self::Overflow => "the value is too large",
+        };
+        write!(f, "{message}")
+    }
+}
+
+impl core::error::Error for UnitError {}
+
+/// The prefixes of byte units, in increasing order.
+const PREFIXES: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+/// Split `text` into the number at its start and the rest.
+fn split_number(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|character: char| !character.is_ascii_digit() && character != '.')
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+/// Parse a byte size like `512`, `4K`, `1.5 GiB` or `10MB`.
+///
+/// Units are case-insensitive; `K`, `Ki` and `KiB` are powers of 1024 and `kB` is a
+/// power of 1000, and likewise up to `E`. Fractions are rounded down to whole bytes.
+///
+/// ```
+/// use rush_core::units::parse_bytes;
+/// assert_eq!(parse_bytes("1.5 GiB"), Ok(1_610_612_736));
+/// assert_eq!(parse_bytes("10MB"), Ok(10_000_000));
+/// assert_eq!(parse_bytes("4k"), Ok(4096));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `text` is not a size or if it does not fit into 64 bits.
+pub fn parse_bytes(text: &str) -> Result<u64, UnitError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(UnitError::Empty);
+    }
+    let (number, unit) = split_number(text);
+    let unit = unit.trim_start().to_ascii_uppercase();
+    let multiplier = match unit.as_str() {
+        "" | "B" => 1,
+        unit => {
+            let mut characters = unit.chars();
+            let prefix = characters.next().ok_or(UnitError::UnknownUnit)?;
+            let exponent = PREFIXES
+                .iter()
+                .position(|candidate| *candidate == prefix)
+                .ok_or(UnitError::UnknownUnit)?;
+            let base: u128 = match characters.as_str() {
+                "" | "I" | "IB" => 1024,
+                "B" => 1000,
+                _ => return Err(UnitError::UnknownUnit),
+            };
+            (0..=exponent).fold(1, |multiplier, _| multiplier * base)
+        },
+    };
+
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return Err(UnitError::InvalidNumber);
+    }
+    let parse = |digits: &str| -> Result<u128, UnitError> {
+        if digits.is_empty() {
+            return Ok(0);
+        }
+        digits.parse().map_err(|_| UnitError::Overflow)
+    };
+    let mut value = parse(whole)?
+        .checked_mul(multiplier)
+        .ok_or(UnitError::Overflow)?;
+    if !fraction.is_empty() {
+        let digits = u32::try_from(fraction.len()).map_err(|_| UnitError::InvalidNumber)?;
+        let scale = 10_u128.checked_pow(digits).ok_or(UnitError::Overflow)?;
+        value += parse(fraction)?
+            .checked_mul(multiplier)
+            .ok_or(UnitError::Overflow)?
+            / scale;
+    }
+    u64::try_from(value).map_err(|_| UnitError::Overflow)
+}
+
+/// Format `bytes` with the largest power-of-1024 unit that keeps the value at 1 or
+/// above, with at most one decimal.
+///
+/// ```
+/// use rush_core::units::format_bytes;
+/// assert_eq!(format_bytes(512), "512 B");
+/// assert_eq!(format_bytes(1_610_612_736), "1.5 GiB");
+/// assert_eq!(format_bytes(2048), "2 KiB");
+/// ```
+#[must_use]
+pub fn format_bytes(bytes: u64) -> String {
+    let mut unit = 1_u128;
+    let mut prefix = None;
+    for candidate in PREFIXES {
+        if u128::from(bytes) < unit * 1024 {
+            break;
+        }
+        unit *= 1024;
+        prefix = Some(candidate);
+    }
+    let Some(prefix) = prefix else {
+        return format!("{bytes} B");
+    };
+
+    let tenths = (u128::from(bytes) * 10 + unit / 2) / unit;
+    let (whole, decimal) = (tenths / 10, tenths % 10);
+    if decimal == 0 {
+        format!("{whole} {prefix}iB")
+    } else {
+        format!("{whole}.{decimal} {prefix}iB")
+    }
+}
+
+/// Parse a duration like `90`, `90s`, `250ms`, `1h30m` or `2d 12h`. The units are
+/// `ms`, `s`, `m`, `h` and `d`; a number without unit means seconds.
+///
+/// ```
+/// use rush_core::units::parse_duration;
+/// use core::time::Duration;
+/// assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+/// assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `text` is not a duration or if it is too long.
+pub fn parse_duration(text: &str) -> Result<core::time::Duration, UnitError> {
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(UnitError::Empty);
+    }
+    let mut milliseconds: u64 = 0;
+    while !rest.is_empty() {
+        let (number, after) = split_number(rest);
+        if number.is_empty() || number.contains('.') {
+            return Err(UnitError::InvalidNumber);
+        }
+        let value: u64 = number.parse().map_err(|_| UnitError::Overflow)?;
+        let after = after.trim_start();
+        let unit_length = after
+            .find(|character: char| !character.is_ascii_alphabetic())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_length);
+        let multiplier = match unit {
+            "ms" => 1,
+            "" | "s" => 1_000,
+            "m" => 60_000,
+            "h" => 3_600_000,
+            "d" => 86_400_000,
+            _ => return Err(UnitError::UnknownUnit),
+        };
+        milliseconds = value
+            .checked_mul(multiplier)
+            .and_then(|value| milliseconds.checked_add(value))
+            .ok_or(UnitError::Overflow)?;
+        rest = after.trim_start();
+    }
+    Ok(core::time::Duration::from_millis(milliseconds))
+}
+
+/// Format `duration` compactly like `1h30m5s`, or in milliseconds if it is shorter
+/// than a second. Parts shorter than a millisecond are dropped.
+///
+/// ```
+/// use rush_core::units::format_duration;
+/// use core::time::Duration;
+/// assert_eq!(format_duration(Duration::from_secs(5405)), "1h30m5s");
+/// assert_eq!(format_duration(Duration::from_millis(250)), "250ms");
+/// ```
+#[must_use]
+pub fn format_duration(duration: core::time::Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds == 0 {
+        return format!("{}ms", duration.subsec_millis());
+    }
+
+    let mut formatted = String::new();
+    let parts = [
+        (seconds / 86_400, 'd'),
+        (seconds / 3_600 % 24, 'h'),
+        (seconds / 60 % 60, 'm'),
+        (seconds % 60, 's'),
+    ];
+    for (value, unit) in parts {
+        if value > 0 {
+            formatted.push_str(&value.to_string());
+            formatted.push(unit);
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod units_test {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        assert_eq!(parse_bytes("0"), Ok(0));
+        assert_eq!(parse_bytes(" 512 B "), Ok(512));
+        assert_eq!(parse_bytes("2KiB"), Ok(2048));
+        assert_eq!(parse_bytes("2 kb"), Ok(2000));
+        assert_eq!(parse_bytes(".5K"), Ok(512));
+        assert_eq!(parse_bytes("16E"), Err(UnitError::Overflow));
+        assert_eq!(parse_bytes(""), Err(UnitError::Empty));
+        assert_eq!(parse_bytes("GiB"), Err(UnitError::InvalidNumber));
+        assert_eq!(parse_bytes("1.2.3"), Err(UnitError::InvalidNumber));
+        assert_eq!(parse_bytes("3 bananas"), Err(UnitError::UnknownUnit));
+
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1 KiB");
+        assert_eq!(format_bytes(u64::MAX), "16 EiB");
+    }
+
+    #[test]
+    fn durations() {
+        use core::time::Duration;
+
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(
+            parse_duration("2d 12h 5s"),
+            Ok(Duration::from_secs(216_005))
+        );
+        assert_eq!(
+            parse_duration("1m30s500ms"),
+            Ok(Duration::from_millis(90_500))
+        );
+        assert_eq!(parse_duration(" "), Err(UnitError::Empty));
+        assert_eq!(parse_duration("1.5h"), Err(UnitError::InvalidNumber));
+        assert_eq!(parse_duration("h"), Err(UnitError::InvalidNumber));
+        assert_eq!(parse_duration("3 weeks"), Err(UnitError::UnknownUnit));
+
+        assert_eq!(format_duration(Duration::ZERO), "0ms");
+        assert_eq!(format_duration(Duration::from_secs(216_005)), "2d12h5s");
+        assert_eq!(format_duration(Duration::from_millis(90_500)), "1m30s");
+    }
+}
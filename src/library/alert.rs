@@ -0,0 +1,313 @@
+//! This module contains functionality for telling someone that something happened,
+//! e.g. that an unattended script failed.
+//!
+//! Alerts are sent, with the `http` feature, to chat webhooks (Slack, Discord,
+//! Microsoft Teams or any endpoint accepting JSON) or, with the `smtp` feature, via
+//! email.
+
+/// Describes possible errors when sending alerts.
+#[derive(Debug, thiserror::Error)]
+pub enum AlertError {
+    #[cfg(feature = "http")]
+    #[error("Sending the webhook request failed: {0}")]
+    Webhook(String),
+    #[cfg(feature = "smtp")]
+    #[error("Sending the email failed: {0}")]
+    Email(String),
+}
+
+/// A [`Result`] whose error variant is a [`AlertError`].
+pub type AlertResult<T> = Result<T, AlertError>;
+
+/// How long to wait for a webhook endpoint to accept the connection.
+#[cfg(feature = "http")]
+const WEBHOOK_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long to wait for a webhook endpoint to answer once connected.
+#[cfg(feature = "http")]
+const WEBHOOK_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Describes the kind of service behind a webhook URL, which determines the shape
+/// of the JSON payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preset {
+    /// Slack incoming webhooks, payload `{"text": ...}`
+    Slack,
+    /// Discord webhooks, payload `{"content": ...}`
+    Discord,
+    /// Microsoft Teams incoming webhooks, payload `{"title": ..., "text": ...}`
+    Teams,
+    /// Any other endpoint, payload `{"subject": ..., "message": ...}`
+    Generic,
+}
+
+impl Preset {
+    /// Build the JSON payload this kind of service expects.
+    #[must_use]
+    pub fn payload(self, subject: &str, message: &str) -> serde_json::Value {
+        match self {
+            Self::Slack => serde_json::json!({ "text": format!("*{subject}*\n{message}") }),
+            Self::Discord => serde_json::json!({ "content": format!("**{subject}**\n{message}") }),
+            Self::Teams => serde_json::json!({ "title": subject, "text": message }),
+            Self::Generic => serde_json::json!({ "subject": subject, "message": message }),
+        }
+    }
+}
+
+/// The settings required to send an email over SMTP.
+#[cfg(feature = "smtp")]
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Email {
+    /// The SMTP relay, connected to with TLS on the submission port
+    pub server:   String,
+    /// The user to authenticate as
+    pub username: String,
+    /// The password to authenticate with
+    pub password: String,
+    /// The sender address, e.g. `"Backups <backups@example.com>"`
+    pub from:     String,
+    /// The recipient addresses
+    pub to:       Vec<String>,
+}
+
+#[cfg(feature = "smtp")]
+impl std::fmt::Debug for Email {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Email")
+            .field("server", &self.server)
+            .field("username", &self.username)
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where an alert is sent to.
+///
+/// Webhook URLs usually contain a secret token, so only their origin (scheme and
+/// host) shows up in the [`std::fmt::Debug`] output, in logs and in errors.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// A webhook URL with the kind of service behind it
+    #[cfg(feature = "http")]
+    Webhook { preset: Preset, url: String },
+    /// One or more email recipients
+    #[cfg(feature = "smtp")]
+    Email(Email),
+}
+
+impl std::fmt::Debug for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "http")]
+            Self::Webhook { preset, url } => f
+                .debug_struct("Webhook")
+                .field("preset", preset)
+                .field("origin", &origin(url))
+                .finish_non_exhaustive(),
+            #[cfg(feature = "smtp")]
+            Self::Email(email) => f.debug_tuple("Email").field(email).finish(),
+        }
+    }
+}
+
+/// The scheme and host of `url`, without the path that carries the secret token of
+/// most webhooks.
+#[cfg(feature = "http")]
+fn origin(url: &str) -> &str {
+    let host_start = url.find("://").map_or(0, |index| index + 3);
+    url[host_start..]
+        .find(['/', '?', '#'])
+        .map_or(url, |index| &url[..host_start + index])
+}
+
+/// POST `payload` as JSON to `url`.
+///
+/// # Errors
+///
+/// Returns an error if the request cannot be sent, the server does not answer in
+/// time or does not answer with a success status code.
+#[cfg(feature = "http")]
+pub fn send_webhook(url: &str, payload: &serde_json::Value) -> AlertResult<()> {
+    log::trace!("Sending webhook request to '{}'", origin(url));
+    ureq::AgentBuilder::new()
+        .timeout_connect(WEBHOOK_CONNECT_TIMEOUT)
+        .timeout_read(WEBHOOK_READ_TIMEOUT)
+        .build()
+        .post(url)
+        .send_json(payload)
+        .map_err(|error| {
+            // The `Display` implementation of ureq's errors contains the URL.
+            AlertError::Webhook(match error {
+                ureq::Error::Status(code, _) => format!("the server answered with status {code}"),
+                ureq::Error::Transport(transport) => transport.message().map_or_else(
+                    || transport.kind().to_string(),
+                    |message| format!("{}: {message}", transport.kind()),
+                ),
+            })
+        })?;
+    Ok(())
+}
+
+/// Send an email with `subject` and `message` as plain-text body.
+#[cfg(feature = "smtp")]
+fn send_email(email: &Email, subject: &str, message: &str) -> AlertResult<()> {
+    use lettre::Transport;
+
+    let to_error = |error: &dyn std::fmt::Display| AlertError::Email(error.to_string());
+    let mut builder = lettre::Message::builder()
+        .from(email.from.parse().map_err(|error| to_error(&error))?)
+        .subject(subject);
+    for recipient in &email.to {
+        builder = builder.to(recipient.parse().map_err(|error| to_error(&error))?);
+    }
+    let mail = builder
+        .body(message.to_string())
+        .map_err(|error| to_error(&error))?;
+
+    lettre::SmtpTransport::relay(&email.server)
+        .map_err(|error| to_error(&error))?
+        .credentials(lettre::transport::smtp::authentication::Credentials::new(
+            email.username.clone(),
+            email.password.clone(),
+        ))
+        .build()
+        .send(&mail)
+        .map_err(|error| to_error(&error))?;
+    Ok(())
+}
+
+/// Send an alert with `subject` and `message` to `target`.
+///
+/// # Errors
+///
+/// Returns an error if the alert could not be delivered.
+pub fn send(target: &Target, subject: &str, message: &str) -> AlertResult<()> {
+    log::debug!("Sending alert '{subject}'");
+    match target {
+        #[cfg(feature = "http")]
+        Target::Webhook { preset, url } => send_webhook(url, &preset.payload(subject, message)),
+        #[cfg(feature = "smtp")]
+        Target::Email(email) => send_email(email, subject, message),
+    }
+}
+
+/// Run `step` and send an alert to `target` if it fails. The result of `step` is
+/// returned unchanged; failing to deliver the alert is only logged.
+///
+/// This is meant to wrap the body of an unattended script:
+///
+/// ```no_run
+/// # #[cfg(feature = "http")] {
+/// # use rush::alert::{on_failure_alert, Preset, Target};
+/// let target = Target::Webhook {
+///     preset: Preset::Slack,
+///     url:    String::from("https://hooks.slack.com/services/..."),
+/// };
+/// on_failure_alert(&target, "Nightly backup failed", || -> Result<(), String> {
+///     // ...
+///     Ok(())
+/// })
+/// .unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns the error of `step`.
+pub fn on_failure_alert<T, E: std::fmt::Display>(
+    target: &Target,
+    subject: &str,
+    step: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    step().inspect_err(|error| {
+        if let Err(alert_error) = send(target, subject, &error.to_string()) {
+            log::warn!("Could not send alert '{subject}': {alert_error}");
+        }
+    })
+}
+
+#[cfg(test)]
+mod alert_test {
+    use super::*;
+
+    #[test]
+    fn payloads() {
+        assert_eq!(
+            Preset::Slack.payload("Backup", "failed"),
+            serde_json::json!({ "text": "*Backup*\nfailed" })
+        );
+        assert_eq!(
+            Preset::Discord.payload("Backup", "failed"),
+            serde_json::json!({ "content": "**Backup**\nfailed" })
+        );
+        assert_eq!(
+            Preset::Generic.payload("Backup", "failed"),
+            serde_json::json!({ "subject": "Backup", "message": "failed" })
+        );
+    }
+
+    #[cfg(feature = "smtp")]
+    #[test]
+    fn email_debug_hides_password() {
+        let email = Email {
+            server:   String::from("smtp.example.com"),
+            username: String::from("alerts"),
+            password: String::from("secret"),
+            from:     String::from("alerts@example.com"),
+            to:       vec![String::from("ops@example.com")],
+        };
+        assert!(!format!("{email:?}").contains("secret"));
+        assert!(!format!("{:?}", Target::Email(email)).contains("secret"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn webhook_url_stays_secret() {
+        let url = "http://127.0.0.1:1/services/T000/B000/secret";
+        assert_eq!(origin(url), "http://127.0.0.1:1");
+        assert_eq!(
+            origin("hooks.example.com?token=secret"),
+            "hooks.example.com"
+        );
+
+        let target = Target::Webhook {
+            preset: Preset::Slack,
+            url:    url.to_string(),
+        };
+        assert!(!format!("{target:?}").contains("secret"));
+        let error = send(&target, "Backup", "failed").unwrap_err();
+        assert!(!error.to_string().contains("secret"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn on_failure_alert_sends_webhook() {
+        use std::io::{
+            Read,
+            Write,
+        };
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = Target::Webhook {
+            preset: Preset::Generic,
+            url:    format!("http://{}/hook", listener.local_addr().unwrap()),
+        };
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0; 4096];
+            let length = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&request[..length]).into_owned()
+        });
+
+        let result: Result<(), &str> = on_failure_alert(&target, "Job", || Err("disk full"));
+        assert_eq!(result, Err("disk full"));
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook"));
+
+        let result: Result<u8, &str> = on_failure_alert(&target, "Job", || Ok(1));
+        assert_eq!(result, Ok(1));
+    }
+}
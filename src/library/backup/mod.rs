@@ -0,0 +1,981 @@
+//! This module contains a framework for the classic backup script.
+//!
+//! Such a script archives some directories, encrypts the archive, stores it
+//! somewhere else together with its checksum, deletes old copies and reports how it
+//! went.
+//!
+//! A [`Job`] is usually described declaratively in a JSON file and run by cron or
+//! a systemd timer. A job that fails half-way (e.g. because the upload was
+//! interrupted) is resumed by the next run instead of starting over. Copies are
+//! checked with [`Job::verify`] and restored in disaster-recovery drills with
+//! [`Job::verify_restore`].
+//!
+//! ```no_run
+//! # use rush::backup::Job;
+//! let job = Job::from_json(
+//!     r#"{
+//!         "name": "www",
+//!         "sources": ["/var/www", "/etc/nginx"],
+//!         "exclude": ["*.log"],
+//!         "destination": { "directory": "/mnt/backups/www" },
+//!         "keep": 14,
+//!         "metrics": "/var/lib/node_exporter/backup-www.prom"
+//!     }"#,
+//! )
+//! .unwrap();
+//! println!("{}", job.run().unwrap());
+//! ```
+
+mod restore;
+
+pub use restore::{
+    CheckOutcome,
+    Drill,
+    DrillReport,
+};
+
+use crate::{
+    fs::{
+        FSError,
+        FSResult,
+    },
+    metrics::{
+        MetricsError,
+        Textfile,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+    state::{
+        StateError,
+        StateFile,
+    },
+};
+
+/// The extension of the archives created by [`Job::run`].
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+/// The extension of encrypted archives.
+const ENCRYPTED_EXTENSION: &str = ".tar.gz.age";
+/// The extension of the file holding the checksum of a copy.
+const CHECKSUM_EXTENSION: &str = ".sha256";
+/// The length of the timestamp in the names of copies, e.g. `20241006T120000Z`.
+const TIMESTAMP_LENGTH: usize = 16;
+
+/// Describes possible errors when running backup jobs.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum BackupError {
+    #[error("The backup job is not valid: {0}")]
+    InvalidJob(String),
+    #[error("There is no copy of backup job '{0}' at the destination")]
+    NoCopy(String),
+    #[error("The checksum of '{0}' does not match the recorded one")]
+    ChecksumMismatch(String),
+    #[error("'{0}' is encrypted, but no key to decrypt it was given")]
+    MissingKey(String),
+    #[error("The sandbox '{0}' is not empty")]
+    SandboxNotEmpty(String),
+    #[error("Running the archiver failed: {0}")]
+    Process(#[from] ProcessError),
+    #[cfg(feature = "encryption")]
+    #[error("Encrypting the archive failed: {0}")]
+    Encryption(#[from] crate::crypto::encryption::EncryptionError),
+    #[cfg(feature = "object-store")]
+    #[error("Transferring the copy failed: {0}")]
+    ObjectStore(#[from] crate::net::object_store::ObjectStoreError),
+    #[error("Recording the progress failed: {0}")]
+    State(#[from] StateError),
+    #[error("Reporting metrics failed: {0}")]
+    Metrics(#[from] MetricsError),
+    #[error("Accessing a file failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`BackupError`].
+pub type BackupResult<T> = Result<T, BackupError>;
+
+/// Where the copies of a backup are stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Destination {
+    /// A local directory, e.g. a mounted NFS share or USB disk
+    Directory(std::path::PathBuf),
+    /// A prefix in S3-compatible object storage (e.g. `s3://bucket/backups/`), which
+    /// is accessed with credentials from the environment (see
+    /// [`crate::net::object_store::ObjectStore::from_env`])
+    #[cfg(feature = "object-store")]
+    ObjectStore(String),
+}
+
+impl std::fmt::Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Directory(path) => write!(f, "'{}'", path.display()),
+            #[cfg(feature = "object-store")]
+            Self::ObjectStore(prefix) => write!(f, "'{prefix}'"),
+        }
+    }
+}
+
+impl Destination {
+    /// The location of the copy `name` below the object storage prefix.
+    #[cfg(feature = "object-store")]
+    fn location(prefix: &str, name: &str) -> BackupResult<crate::net::object_store::Location> {
+        let mut location: crate::net::object_store::Location = prefix.parse()?;
+        if !location.key.is_empty() && !location.key.ends_with('/') {
+            location.key.push('/');
+        }
+        location.key.push_str(name);
+        Ok(location)
+    }
+
+    /// Store the file at `path` as `name`.
+    fn put(&self, path: &std::path::Path, name: &str) -> BackupResult<()> {
+        log::debug!("Storing '{}' as '{name}' in {self}", path.display());
+        match self {
+            Self::Directory(directory) => {
+                std::fs::create_dir_all(directory).map_err(FSError::from)?;
+                // Copy atomically, so an interrupted copy is never mistaken for a
+                // complete one.
+                let mut source = std::fs::File::open(path).map_err(FSError::from)?;
+                crate::fs::write_atomic_with(
+                    &directory.join(name),
+                    None,
+                    |writer| -> FSResult<()> {
+                        std::io::copy(&mut source, writer)?;
+                        Ok(())
+                    },
+                )?;
+            },
+            #[cfg(feature = "object-store")]
+            Self::ObjectStore(prefix) => {
+                use crate::fs::Object as _;
+
+                crate::net::object_store::ObjectStore::from_env()?
+                    .put(&crate::fs::File::new(path), &Self::location(prefix, name)?)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Fetch the copy `name` to `target`.
+    fn get(&self, name: &str, target: &std::path::Path) -> BackupResult<()> {
+        log::debug!("Fetching '{name}' from {self}");
+        match self {
+            Self::Directory(directory) => {
+                std::fs::copy(directory.join(name), target).map_err(FSError::from)?;
+            },
+            #[cfg(feature = "object-store")]
+            Self::ObjectStore(prefix) => {
+                crate::net::object_store::ObjectStore::from_env()?
+                    .get(&Self::location(prefix, name)?, target)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// The names of all files stored at the destination.
+    fn list(&self) -> BackupResult<Vec<String>> {
+        match self {
+            Self::Directory(directory) => {
+                let entries = match std::fs::read_dir(directory) {
+                    Ok(entries) => entries,
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok(Vec::new())
+                    },
+                    Err(error) => return Err(FSError::from(error).into()),
+                };
+                let mut names = Vec::new();
+                for entry in entries {
+                    let entry = entry.map_err(FSError::from)?;
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+                Ok(names)
+            },
+            #[cfg(feature = "object-store")]
+            Self::ObjectStore(prefix) => {
+                let prefix = Self::location(prefix, "")?;
+                Ok(crate::net::object_store::ObjectStore::from_env()?
+                    .list(&prefix)?
+                    .into_iter()
+                    .filter_map(|object| {
+                        object
+                            .location
+                            .key
+                            .strip_prefix(&prefix.key)
+                            .filter(|name| !name.contains('/'))
+                            .map(ToString::to_string)
+                    })
+                    .collect())
+            },
+        }
+    }
+
+    /// Delete the file `name`. Deleting a file that does not exist is not an error.
+    fn delete(&self, name: &str) -> BackupResult<()> {
+        log::debug!("Deleting '{name}' from {self}");
+        match self {
+            Self::Directory(directory) => match std::fs::remove_file(directory.join(name)) {
+                Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                    Err(FSError::from(error).into())
+                },
+                _ => Ok(()),
+            },
+            #[cfg(feature = "object-store")]
+            Self::ObjectStore(prefix) => Ok(crate::net::object_store::ObjectStore::from_env()?
+                .delete(&Self::location(prefix, name)?)?),
+        }
+    }
+}
+
+/// The steps of a run that have been completed, in order.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+enum Stage {
+    /// The sources have been archived
+    Archived,
+    /// The archive has been encrypted (if configured) and its checksum written
+    Sealed,
+    /// The copy and its checksum have been stored at the destination
+    Stored,
+}
+
+/// A run that has not completed yet.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Pending {
+    /// The name of the copy the run creates
+    name:  String,
+    /// The last step that was completed
+    stage: Stage,
+}
+
+/// What is remembered between runs, in the staging directory.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct Progress {
+    /// The run to resume, if the last one did not complete
+    pending: Option<Pending>,
+}
+
+/// Describes the outcome of a successful [`Job::run`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Report {
+    /// The name of the copy that was stored, e.g. `www-20241006T120000Z.tar.gz`
+    pub name:     String,
+    /// The size of the copy in bytes
+    pub size:     u64,
+    /// The SHA-256 checksum of the copy, hex-encoded
+    pub sha256:   String,
+    /// Whether an interrupted run was resumed instead of starting over
+    pub resumed:  bool,
+    /// The names of the old copies that were deleted
+    pub pruned:   Vec<String>,
+    /// How long the run took
+    pub duration: std::time::Duration,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Stored '{}' ({} bytes, SHA-256 {}) in {:.1}s",
+            self.name,
+            self.size,
+            self.sha256,
+            self.duration.as_secs_f64()
+        )?;
+        if self.resumed {
+            write!(f, ", resuming an interrupted run")?;
+        }
+        write!(f, "; deleted {} old copies", self.pruned.len())
+    }
+}
+
+/// Describes the outcome of a successful [`Job::verify`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Verification {
+    /// The name of the copy that was verified
+    pub name:    String,
+    /// The SHA-256 checksum of the copy, hex-encoded
+    pub sha256:  String,
+    /// The number of entries in the archive, or [`None`] if the copy is encrypted
+    /// and its content could not be listed
+    pub entries: Option<usize>,
+}
+
+/// A backup job: which files to archive, where to store the copies and how many of
+/// them to keep.
+///
+/// Jobs are either built in code or deserialized, e.g. with [`Job::from_json`]; see
+/// the module documentation for an example.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// The name of the job, which prefixes the names of its copies
+    name:        String,
+    /// The files and directories to archive
+    sources:     Vec<std::path::PathBuf>,
+    /// Patterns (in the syntax of `tar --exclude`) of files to leave out
+    #[serde(default)]
+    exclude:     Vec<String>,
+    /// Where the copies are stored
+    destination: Destination,
+    /// How many copies to keep at the destination, or all if not set
+    #[serde(default)]
+    keep:        Option<usize>,
+    /// The age public key (`age1...`) copies are encrypted to, which requires the
+    /// `encryption` feature
+    #[serde(default)]
+    encrypt_to:  Option<String>,
+    /// Where archives are prepared and progress is recorded
+    #[serde(default)]
+    staging:     Option<std::path::PathBuf>,
+    /// The textfile metrics are reported to
+    #[serde(default)]
+    metrics:     Option<std::path::PathBuf>,
+}
+
+impl Job {
+    /// Create a job called `name` that stores copies of `sources` at `destination`.
+    pub fn new<I, P>(name: impl Into<String>, sources: I, destination: Destination) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<std::path::Path>,
+    {
+        Self {
+            name: name.into(),
+            sources: sources
+                .into_iter()
+                .map(|source| source.as_ref().to_path_buf())
+                .collect(),
+            exclude: Vec::new(),
+            destination,
+            keep: None,
+            encrypt_to: None,
+            staging: None,
+            metrics: None,
+        }
+    }
+
+    /// Parse a job from its JSON description.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON does not describe a valid job.
+    pub fn from_json(json: &str) -> BackupResult<Self> {
+        let job: Self = serde_json::from_str(json)
+            .map_err(|error| BackupError::InvalidJob(error.to_string()))?;
+        job.validate()?;
+        Ok(job)
+    }
+
+    /// Read a job from the JSON file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not describe a valid job.
+    pub fn load(path: impl AsRef<std::path::Path>) -> BackupResult<Self> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(FSError::from)?)
+    }
+
+    /// Leave out files matching `pattern` (in the syntax of `tar --exclude`).
+    #[must_use]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Keep only the newest `copies` copies at the destination.
+    #[must_use]
+    pub const fn keep(mut self, copies: usize) -> Self {
+        self.keep = Some(copies);
+        self
+    }
+
+    /// Encrypt copies to the age public key `recipient` (`age1...`), so that only the
+    /// holder of the secret key can read them.
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn encrypt_to(mut self, recipient: impl Into<String>) -> Self {
+        self.encrypt_to = Some(recipient.into());
+        self
+    }
+
+    /// Prepare archives and record progress in `directory` instead of a directory
+    /// below the system's temporary directory. It needs room for two copies.
+    #[must_use]
+    pub fn staging(mut self, directory: impl AsRef<std::path::Path>) -> Self {
+        self.staging = Some(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// Report the outcome of runs to the `node_exporter` textfile at `path`, see
+    /// [`Textfile`].
+    #[must_use]
+    pub fn metrics(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.metrics = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The name of the job.
+    #[must_use]
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Check that the job can be run.
+    fn validate(&self) -> BackupResult<()> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || "-_.".contains(character));
+        if !valid_name {
+            return Err(BackupError::InvalidJob(format!(
+                "'{}' is not a valid name (use letters, digits, '-', '_' and '.')",
+                self.name
+            )));
+        }
+        if self.sources.is_empty() {
+            return Err(BackupError::InvalidJob(String::from(
+                "there are no sources",
+            )));
+        }
+        #[cfg(not(feature = "encryption"))]
+        if self.encrypt_to.is_some() {
+            return Err(BackupError::InvalidJob(String::from(
+                "encrypting copies requires the 'encryption' feature",
+            )));
+        }
+        if self.keep == Some(0) {
+            return Err(BackupError::InvalidJob(String::from(
+                "at least one copy has to be kept",
+            )));
+        }
+        Ok(())
+    }
+
+    /// The directory archives are prepared in.
+    fn staging_directory(&self) -> std::path::PathBuf {
+        self.staging
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(format!("rush-backup-{}", self.name)))
+    }
+
+    /// The extension of the copies of this job.
+    const fn extension(&self) -> &'static str {
+        if self.encrypt_to.is_some() {
+            ENCRYPTED_EXTENSION
+        } else {
+            ARCHIVE_EXTENSION
+        }
+    }
+
+    /// Whether `name` is a copy made by this job. The timestamp in the names sorts
+    /// copies from oldest to newest.
+    fn is_copy(&self, name: &str) -> bool {
+        name.strip_prefix(&self.name)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(self.extension()))
+            .is_some_and(|timestamp| {
+                timestamp.len() == TIMESTAMP_LENGTH
+                    && timestamp.ends_with('Z')
+                    && timestamp.chars().next().is_some_and(|c| c.is_ascii_digit())
+            })
+    }
+
+    /// The names of all copies at the destination, from oldest to newest.
+    fn copies(&self) -> BackupResult<Vec<String>> {
+        let mut copies: Vec<String> = self
+            .destination
+            .list()?
+            .into_iter()
+            .filter(|name| self.is_copy(name))
+            .collect();
+        copies.sort_unstable();
+        Ok(copies)
+    }
+
+    /// Archive the sources to `archive`.
+    fn archive(&self, archive: &std::path::Path) -> BackupResult<()> {
+        log::info!(
+            "Archiving {} sources of backup job '{}'",
+            self.sources.len(),
+            self.name
+        );
+        let mut command = Command::new("tar")
+            .args(["--create", "--gzip", "--file"])
+            .arg(archive.to_string_lossy());
+        for pattern in &self.exclude {
+            command = command.arg(format!("--exclude={pattern}"));
+        }
+        command
+            .arg("--")
+            .args(self.sources.iter().map(|source| source.to_string_lossy()))
+            .run()?;
+        Ok(())
+    }
+
+    /// Encrypt the archive (if configured) to `copy` and write its checksum next to
+    /// it. Returns the checksum.
+    fn seal(&self, copy: &std::path::Path) -> BackupResult<String> {
+        let name = copy.file_name().unwrap_or_default().to_string_lossy();
+        #[cfg(feature = "encryption")]
+        if let Some(recipient) = &self.encrypt_to {
+            let archive = copy.with_file_name(archive_name(&name));
+            log::debug!("Encrypting the archive of backup job '{}'", self.name);
+            let mut input = std::fs::File::open(&archive).map_err(FSError::from)?;
+            let output = std::fs::File::create(copy).map_err(FSError::from)?;
+            crate::crypto::encryption::encrypt(
+                &mut input,
+                &output,
+                &crate::crypto::encryption::Key::X25519(recipient.clone()),
+            )?;
+            output.sync_all().map_err(FSError::from)?;
+            std::fs::remove_file(&archive).map_err(FSError::from)?;
+        }
+
+        log::debug!(
+            "Writing the checksum of '{name}' of backup job '{}'",
+            self.name
+        );
+        let sha256 = sha256(copy)?;
+        std::fs::write(checksum_path(copy), format!("{sha256}  {name}\n"))
+            .map_err(FSError::from)?;
+        Ok(sha256)
+    }
+
+    /// Delete all but the newest copies at the destination, as configured.
+    fn prune(&self) -> BackupResult<Vec<String>> {
+        let Some(keep) = self.keep else {
+            return Ok(Vec::new());
+        };
+        let copies = self.copies()?;
+        let pruned = copies[..copies.len().saturating_sub(keep)].to_vec();
+        for name in &pruned {
+            log::info!("Deleting old copy '{name}' of backup job '{}'", self.name);
+            self.destination.delete(name)?;
+            self.destination
+                .delete(&format!("{name}{CHECKSUM_EXTENSION}"))?;
+        }
+        Ok(pruned)
+    }
+
+    /// Run the job: archive the sources, encrypt the archive (if configured), write
+    /// its checksum, store both at the destination and delete old copies. If the
+    /// previous run did not complete, it is resumed with the step that failed.
+    ///
+    /// If metrics are configured, the outcome is reported as
+    /// `backup_last_success_timestamp_seconds`, `backup_size_bytes`,
+    /// `backup_duration_seconds` and `backup_runs_total`, labelled with the job.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job is not valid or if any step fails.
+    pub fn run(&self) -> BackupResult<Report> {
+        let started = std::time::Instant::now();
+        let outcome = self.run_steps(started);
+        self.report(&outcome)?;
+        outcome
+    }
+
+    /// The steps of [`Job::run`], without reporting metrics.
+    fn run_steps(&self, started: std::time::Instant) -> BackupResult<Report> {
+        self.validate()?;
+        let staging = self.staging_directory();
+        std::fs::create_dir_all(&staging).map_err(FSError::from)?;
+        let progress = StateFile::<Progress>::new(staging.join("progress.json"));
+        let save =
+            |pending: Option<Pending>| progress.update(|progress| progress.pending = pending);
+
+        // A pending run can only be resumed if its files are still staged.
+        let pending = progress.load()?.pending.filter(|pending| {
+            let copy = staging.join(&pending.name);
+            match pending.stage {
+                Stage::Archived => staging.join(archive_name(&pending.name)).exists(),
+                Stage::Sealed | Stage::Stored => copy.exists() && checksum_path(&copy).exists(),
+            }
+        });
+        let resumed = pending.is_some();
+        let (name, stage) = pending.map_or_else(
+            || {
+                let timestamp =
+                    crate::library::time::DateTime::from_system_time(std::time::SystemTime::now())
+                        .to_iso8601_basic();
+                (
+                    format!("{}-{timestamp}{}", self.name, self.extension()),
+                    None,
+                )
+            },
+            |pending| {
+                log::info!(
+                    "Resuming the interrupted run of backup job '{}' after {:?}",
+                    self.name,
+                    pending.stage
+                );
+                (pending.name, Some(pending.stage))
+            },
+        );
+        let archive = staging.join(archive_name(&name));
+        let copy = staging.join(&name);
+        let checksum = checksum_path(&copy);
+        let advance = |next: Stage| {
+            save(Some(Pending {
+                name:  name.clone(),
+                stage: next,
+            }))
+        };
+
+        if stage.is_none() {
+            self.archive(&archive)?;
+            advance(Stage::Archived)?;
+        }
+        let sha256 = if stage < Some(Stage::Sealed) {
+            let sha256 = self.seal(&copy)?;
+            advance(Stage::Sealed)?;
+            sha256
+        } else {
+            read_checksum(&checksum)?
+        };
+        if stage < Some(Stage::Stored) {
+            log::info!("Storing '{name}' in {}", self.destination);
+            // The checksum is stored last, so its presence marks a complete copy.
+            self.destination.put(&copy, &name)?;
+            self.destination
+                .put(&checksum, &format!("{name}{CHECKSUM_EXTENSION}"))?;
+            advance(Stage::Stored)?;
+        }
+        let pruned = self.prune()?;
+
+        let size = std::fs::metadata(&copy).map_err(FSError::from)?.len();
+        for path in [&copy, &checksum] {
+            std::fs::remove_file(path).map_err(FSError::from)?;
+        }
+        save(None)?;
+        Ok(Report {
+            name,
+            size,
+            sha256,
+            resumed,
+            pruned,
+            duration: started.elapsed(),
+        })
+    }
+
+    /// Report the outcome of a run to the metrics textfile, if configured.
+    fn report(&self, outcome: &BackupResult<Report>) -> BackupResult<()> {
+        let Some(path) = &self.metrics else {
+            return Ok(());
+        };
+        let mut textfile = Textfile::load(path)?;
+        let job = [("job", self.name.as_str())];
+        if let Ok(report) = outcome {
+            textfile.set_gauge_to_current_time("backup_last_success_timestamp_seconds", &job)?;
+            textfile.set_gauge("backup_size_bytes", &job, bytes_as_f64(report.size))?;
+            textfile.set_gauge(
+                "backup_duration_seconds",
+                &job,
+                report.duration.as_secs_f64(),
+            )?;
+        }
+        let result = if outcome.is_ok() {
+            "success"
+        } else {
+            "failure"
+        };
+        textfile.increment_counter(
+            "backup_runs_total",
+            &[("job", &self.name), ("result", result)],
+            1.0,
+        )?;
+        textfile.write()?;
+        Ok(())
+    }
+
+    /// Verify the newest copy at the destination: fetch it, compare its checksum
+    /// with the stored one and, unless it is encrypted, list the content of the
+    /// archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no copy, if the copy or its checksum cannot be
+    /// fetched, if the checksums do not match or if the archive is damaged.
+    pub fn verify(&self) -> BackupResult<Verification> {
+        self.validate()?;
+        let name = self
+            .copies()?
+            .pop()
+            .ok_or_else(|| BackupError::NoCopy(self.name.clone()))?;
+        log::info!("Verifying '{name}' in {}", self.destination);
+
+        let staging = self.staging_directory();
+        std::fs::create_dir_all(&staging).map_err(FSError::from)?;
+        let copy = staging.join(format!("verify-{name}"));
+        let verified = self.fetch(&name, &copy).and_then(|sha256| {
+            let entries = if name.ends_with(ARCHIVE_EXTENSION) {
+                let listing = Command::new("tar")
+                    .args(["--list", "--gzip", "--file"])
+                    .arg(copy.to_string_lossy())
+                    .run()?;
+                Some(listing.stdout.lines().count())
+            } else {
+                None
+            };
+            Ok(Verification {
+                name: name.clone(),
+                sha256,
+                entries,
+            })
+        });
+        let _ = std::fs::remove_file(&copy);
+        verified
+    }
+
+    /// Fetch the copy `name` from the destination to `target` and compare its
+    /// checksum with the stored one. Returns the checksum.
+    fn fetch(&self, name: &str, target: &std::path::Path) -> BackupResult<String> {
+        let checksum = checksum_path(target);
+        let fetched = self
+            .destination
+            .get(name, target)
+            .and_then(|()| {
+                self.destination
+                    .get(&format!("{name}{CHECKSUM_EXTENSION}"), &checksum)
+            })
+            .and_then(|()| {
+                let sha256 = sha256(target)?;
+                if sha256 == read_checksum(&checksum)? {
+                    Ok(sha256)
+                } else {
+                    Err(BackupError::ChecksumMismatch(name.to_string()))
+                }
+            });
+        let _ = std::fs::remove_file(&checksum);
+        if fetched.is_err() {
+            let _ = std::fs::remove_file(target);
+        }
+        fetched
+    }
+}
+
+/// The name of the unencrypted archive a copy called `name` is made from.
+fn archive_name(name: &str) -> String {
+    format!(
+        "{}{ARCHIVE_EXTENSION}",
+        name.split_once(ARCHIVE_EXTENSION)
+            .map_or(name, |(stem, _)| stem)
+    )
+}
+
+/// The path of the file holding the checksum of the file at `path`.
+fn checksum_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut checksum = path.as_os_str().to_owned();
+    checksum.push(CHECKSUM_EXTENSION);
+    checksum.into()
+}
+
+/// Read the checksum from a file in the format of `sha256sum`.
+fn read_checksum(path: &std::path::Path) -> BackupResult<String> {
+    let content = std::fs::read_to_string(path).map_err(FSError::from)?;
+    Ok(content
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Compute the SHA-256 checksum of the file at `path`, hex-encoded.
+fn sha256(path: &std::path::Path) -> BackupResult<String> {
+    use sha2::Digest as _;
+    use std::fmt::Write as _;
+
+    let mut hasher = sha2::Sha256::new();
+    let mut file = std::fs::File::open(path).map_err(FSError::from)?;
+    std::io::copy(&mut file, &mut hasher).map_err(FSError::from)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        }))
+}
+
+/// Convert a number of bytes to a metric value without losing precision for sizes
+/// below 2^53 bytes.
+fn bytes_as_f64(bytes: u64) -> f64 {
+    let high = u32::try_from(bytes >> 32).unwrap_or_default();
+    let low = u32::try_from(bytes & u64::from(u32::MAX)).unwrap_or_default();
+    f64::from(high).mul_add(f64::from(u32::MAX) + 1.0, f64::from(low))
+}
+
+#[cfg(test)]
+mod backup_test {
+    use super::*;
+
+    /// Create a directory with a few files to back up.
+    fn sources() -> std::path::PathBuf {
+        let sources = crate::fs::generate_test_path();
+        std::fs::create_dir_all(sources.join("logs")).unwrap();
+        std::fs::write(sources.join("index.html"), "<h1>Hello</h1>").unwrap();
+        std::fs::write(sources.join("logs/access.log"), "GET /").unwrap();
+        sources
+    }
+
+    #[test]
+    fn configuration() -> BackupResult<()> {
+        let job = Job::from_json(
+            r#"{
+                "name": "www",
+                "sources": ["/var/www"],
+                "destination": { "directory": "/mnt/backups" },
+                "keep": 7
+            }"#,
+        )?;
+        assert_eq!(
+            job,
+            Job::new(
+                "www",
+                ["/var/www"],
+                Destination::Directory("/mnt/backups".into())
+            )
+            .keep(7)
+        );
+
+        assert!(job.is_copy("www-20241006T120000Z.tar.gz"));
+        assert!(!job.is_copy("www-20241006T120000Z.tar.gz.sha256"));
+        assert!(!job.is_copy("www-db-20241006T120000Z.tar.gz"));
+        assert_eq!(
+            archive_name("www-20241006T120000Z.tar.gz.age"),
+            "www-20241006T120000Z.tar.gz"
+        );
+
+        for invalid in [
+            r#"{ "name": "www", "sources": [], "destination": { "directory": "/" } }"#,
+            r#"{ "name": "a/b", "sources": ["/"], "destination": { "directory": "/" } }"#,
+            r#"{ "name": "www", "sources": ["/"], "destination": { "ftp": "/" } }"#,
+            r#"{ "name": "www", "sources": ["/"], "destination": { "directory": "/" }, "kep": 1 }"#,
+        ] {
+            assert!(
+                matches!(Job::from_json(invalid), Err(BackupError::InvalidJob(_))),
+                "{invalid} should be invalid"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn run_prune_and_verify() -> BackupResult<()> {
+        let sources = sources();
+        let destination = crate::fs::generate_test_path();
+        let staging = crate::fs::generate_test_path();
+        let job = Job::new(
+            "www",
+            [&sources],
+            Destination::Directory(destination.clone()),
+        )
+        .exclude("*.log")
+        .keep(2)
+        .staging(&staging);
+        assert_eq!(job.verify(), Err(BackupError::NoCopy(String::from("www"))));
+
+        // Two older copies, of which the oldest is pruned.
+        std::fs::create_dir_all(&destination).unwrap();
+        for old in ["www-20200101T000000Z.tar.gz", "www-20210101T000000Z.tar.gz"] {
+            std::fs::write(destination.join(old), "old").unwrap();
+            std::fs::write(destination.join(format!("{old}.sha256")), "0").unwrap();
+        }
+
+        let report = job.run()?;
+        assert!(!report.resumed);
+        assert_eq!(report.pruned, ["www-20200101T000000Z.tar.gz"]);
+        assert_eq!(
+            job.copies()?,
+            ["www-20210101T000000Z.tar.gz", report.name.as_str()]
+        );
+        assert!(destination.join(format!("{}.sha256", report.name)).exists());
+        // Nothing is left behind but the progress.
+        assert_eq!(std::fs::read_dir(&staging).unwrap().count(), 1);
+
+        let verification = job.verify()?;
+        assert_eq!(verification.name, report.name);
+        assert_eq!(verification.sha256, report.sha256);
+        // The directory and the HTML file, but not the excluded log.
+        assert_eq!(verification.entries, Some(3));
+
+        std::fs::write(destination.join(&report.name), "damaged").unwrap();
+        assert_eq!(
+            job.verify(),
+            Err(BackupError::ChecksumMismatch(report.name))
+        );
+
+        for directory in [sources, destination, staging] {
+            std::fs::remove_dir_all(directory).unwrap();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn resume() -> BackupResult<()> {
+        let sources = sources();
+        let destination = crate::fs::generate_test_path();
+        let staging = crate::fs::generate_test_path();
+
+        // The destination cannot be created, since a file is in the way.
+        std::fs::write(&destination, "").unwrap();
+        let failing = Job::new(
+            "www",
+            [&sources],
+            Destination::Directory(destination.join("www")),
+        )
+        .staging(&staging);
+        assert!(matches!(failing.run(), Err(BackupError::FS(_))));
+
+        std::fs::remove_file(&destination).unwrap();
+        let report = failing.run()?;
+        assert!(report.resumed);
+        assert_eq!(failing.verify()?.sha256, report.sha256);
+
+        // The next run starts over.
+        assert!(!failing.run()?.resumed);
+
+        for directory in [sources, destination, staging] {
+            std::fs::remove_dir_all(directory).unwrap();
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encryption() -> BackupResult<()> {
+        use crate::crypto::encryption::Key;
+
+        let sources = sources();
+        let destination = crate::fs::generate_test_path();
+        let key = Key::generate();
+        let job = Job::new(
+            "www",
+            [&sources],
+            Destination::Directory(destination.clone()),
+        )
+        .encrypt_to(key.public_key()?)
+        .staging(crate::fs::generate_test_path());
+
+        let report = job.run()?;
+        assert!(report.name.ends_with(".tar.gz.age"));
+        let verification = job.verify()?;
+        assert_eq!(verification.entries, None);
+
+        let mut archive = Vec::new();
+        crate::crypto::encryption::decrypt(
+            std::fs::File::open(destination.join(&report.name)).map_err(FSError::from)?,
+            &mut archive,
+            &key,
+        )?;
+        // The gzip magic number.
+        assert_eq!(archive[..2], [0x1F, 0x8B]);
+
+        for directory in [sources, destination, job.staging_directory()] {
+            std::fs::remove_dir_all(directory).unwrap();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+//! This module contains functionality for disaster-recovery drills: restoring a copy
+//! of a backup into a sandbox and checking that what comes back is usable.
+
+use super::{
+    archive_name,
+    BackupError,
+    BackupResult,
+    Job,
+};
+use crate::{
+    fs::FSError,
+    process::Command,
+};
+
+/// A check run on the restored files, which are passed as the directory they were
+/// restored to. The error describes what is wrong.
+type Check<'a> = dyn Fn(&std::path::Path) -> Result<(), String> + 'a;
+
+/// Decrypts the copy at the first path to the archive at the second one.
+type Decrypt = dyn Fn(&std::path::Path, &std::path::Path) -> BackupResult<()>;
+
+/// The outcome of one check of a [`Drill`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CheckOutcome {
+    /// The name of the check
+    pub name:     String,
+    /// What the check returned
+    pub result:   Result<(), String>,
+    /// How long the check took
+    pub duration: std::time::Duration,
+}
+
+/// Describes the outcome of a [`Drill`] that restored a copy. Whether the restored
+/// files are usable is up to the checks, see [`DrillReport::is_success`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DrillReport {
+    /// The name of the copy that was restored
+    pub name:     String,
+    /// The SHA-256 checksum of the copy, hex-encoded
+    pub sha256:   String,
+    /// The number of files and directories that were restored
+    pub restored: usize,
+    /// The outcomes of the checks, in the order they were added
+    pub checks:   Vec<CheckOutcome>,
+    /// How long the whole drill took
+    pub duration: std::time::Duration,
+}
+
+impl DrillReport {
+    /// Whether all checks passed.
+    #[must_use]
+    pub fn is_success(&self) -> bool { self.checks.iter().all(|check| check.result.is_ok()) }
+
+    /// The checks that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &CheckOutcome> {
+        self.checks.iter().filter(|check| check.result.is_err())
+    }
+}
+
+impl std::fmt::Display for DrillReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.chars().count())
+            .chain(std::iter::once("CHECK".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:width$}  STATUS  DURATION", "CHECK")?;
+        for check in &self.checks {
+            write!(
+                f,
+                "{:width$}  {:6}  {:>7.1}s",
+                check.name,
+                if check.result.is_ok() { "ok" } else { "FAILED" },
+                check.duration.as_secs_f64()
+            )?;
+            match &check.result {
+                Ok(()) => writeln!(f)?,
+                Err(error) => writeln!(f, "  {error}")?,
+            }
+        }
+        writeln!(
+            f,
+            "\nRestored '{}' ({} entries) in {:.1}s, {} of {} checks passed.",
+            self.name,
+            self.restored,
+            self.duration.as_secs_f64(),
+            self.checks.len() - self.failed().count(),
+            self.checks.len()
+        )
+    }
+}
+
+/// A disaster-recovery drill, created by [`Job::verify_restore`]: restore a copy
+/// into a sandbox directory and run checks on the restored files.
+///
+/// The copy is checked against its stored checksum before it is restored. Files are
+/// restored below the sandbox with the paths they were archived with, minus the
+/// leading `/`, so `/var/www/index.html` is restored to
+/// `<sandbox>/var/www/index.html`.
+///
+/// ```no_run
+/// # use rush::backup::Job;
+/// let job = Job::load("/etc/backup/www.json").unwrap();
+/// let report = job
+///     .verify_restore("/var/tmp/www-drill")
+///     .check("the start page is there", |root| {
+///         let page = std::fs::read_to_string(root.join("var/www/index.html"))
+///             .map_err(|error| error.to_string())?;
+///         page.contains("<html")
+///             .then_some(())
+///             .ok_or_else(|| String::from("it is not HTML"))
+///     })
+///     .run()
+///     .unwrap();
+/// print!("{report}");
+/// assert!(report.is_success());
+/// ```
+pub struct Drill<'a> {
+    /// The job whose copy is restored
+    job:          &'a Job,
+    /// The directory the copy is restored to
+    sandbox:      std::path::PathBuf,
+    /// The copy to restore, or the newest one
+    copy:         Option<String>,
+    /// The checks, with their names
+    checks:       Vec<(String, Box<Check<'a>>)>,
+    /// How encrypted copies are decrypted
+    decrypt:      Option<Box<Decrypt>>,
+    /// Whether to leave the restored files in the sandbox afterwards
+    keep_sandbox: bool,
+}
+
+impl std::fmt::Debug for Drill<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Drill")
+            .field("job", &self.job.name)
+            .field("sandbox", &self.sandbox)
+            .field("copy", &self.copy)
+            .field(
+                "checks",
+                &self.checks.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("keep_sandbox", &self.keep_sandbox)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Job {
+    /// Prepare a disaster-recovery drill that restores the newest copy of this job
+    /// into `sandbox`, which must not exist or be empty. See [`Drill`].
+    #[must_use]
+    pub fn verify_restore(&self, sandbox: impl AsRef<std::path::Path>) -> Drill<'_> {
+        Drill {
+            job:          self,
+            sandbox:      sandbox.as_ref().to_path_buf(),
+            copy:         None,
+            checks:       Vec::new(),
+            decrypt:      None,
+            keep_sandbox: false,
+        }
+    }
+}
+
+impl<'a> Drill<'a> {
+    /// Restore the copy called `name` (e.g. `www-20241006T120000Z.tar.gz`) instead
+    /// of the newest one.
+    #[must_use]
+    pub fn copy(mut self, name: impl Into<String>) -> Self {
+        self.copy = Some(name.into());
+        self
+    }
+
+    /// Run `check` on the restored files, which are passed as the sandbox directory.
+    /// The check returns an error describing what is wrong.
+    #[must_use]
+    pub fn check<F>(mut self, name: impl Into<String>, check: F) -> Self
+    where
+        F: Fn(&std::path::Path) -> Result<(), String> + 'a,
+    {
+        self.checks.push((name.into(), Box::new(check)));
+        self
+    }
+
+    /// Decrypt encrypted copies with the secret key `key`.
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn decrypt_with(mut self, key: crate::crypto::encryption::Key) -> Self {
+        self.decrypt = Some(Box::new(move |copy, archive| {
+            let input = std::fs::File::open(copy).map_err(FSError::from)?;
+            let mut output = std::fs::File::create(archive).map_err(FSError::from)?;
+            crate::crypto::encryption::decrypt(input, &mut output, &key)?;
+            Ok(())
+        }));
+        self
+    }
+
+    /// Whether to leave the restored files in the sandbox after the drill, e.g. to
+    /// inspect them by hand. By default, the sandbox is deleted.
+    #[must_use]
+    pub const fn keep_sandbox(mut self, keep: bool) -> Self {
+        self.keep_sandbox = keep;
+        self
+    }
+
+    /// Fetch the copy, restore it into the sandbox and run the checks. Checks that
+    /// fail do not make the drill fail, they are recorded in the report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job is not valid, if the sandbox is not empty, if
+    /// there is no copy, if the copy cannot be fetched or its checksum does not
+    /// match, if an encrypted copy cannot be decrypted or if restoring it fails.
+    pub fn run(&self) -> BackupResult<DrillReport> {
+        let started = std::time::Instant::now();
+        self.job.validate()?;
+        let not_empty =
+            std::fs::read_dir(&self.sandbox).is_ok_and(|mut entries| entries.next().is_some());
+        if not_empty {
+            return Err(BackupError::SandboxNotEmpty(
+                self.sandbox.to_string_lossy().into_owned(),
+            ));
+        }
+        let name = match &self.copy {
+            Some(name) => name.clone(),
+            None => self
+                .job
+                .copies()?
+                .pop()
+                .ok_or_else(|| BackupError::NoCopy(self.job.name.clone()))?,
+        };
+
+        log::info!(
+            "Restoring '{name}' of backup job '{}' into '{}'",
+            self.job.name,
+            self.sandbox.display()
+        );
+        let restored = self.restore(&name);
+        let report = restored.map(|(sha256, restored)| DrillReport {
+            checks: self.run_checks(),
+            name,
+            sha256,
+            restored,
+            duration: started.elapsed(),
+        });
+        if !self.keep_sandbox {
+            let _ = std::fs::remove_dir_all(&self.sandbox);
+        }
+        report
+    }
+
+    /// Fetch the copy `name` into the sandbox and extract it. Returns the checksum
+    /// of the copy and the number of restored entries.
+    fn restore(&self, name: &str) -> BackupResult<(String, usize)> {
+        std::fs::create_dir_all(&self.sandbox).map_err(FSError::from)?;
+        // The copy is kept in a hidden directory, so it does not show up among the
+        // restored files.
+        let downloads = self.sandbox.join(".drill");
+        std::fs::create_dir_all(&downloads).map_err(FSError::from)?;
+        let copy = downloads.join(name);
+        let sha256 = self.job.fetch(name, &copy)?;
+
+        let archive = downloads.join(archive_name(name));
+        if copy != archive {
+            self.decrypt(name, &copy, &archive)?;
+        }
+        let listing = Command::new("tar")
+            .args(["--extract", "--verbose", "--gzip", "--file"])
+            .arg(archive.to_string_lossy())
+            .arg("--directory")
+            .arg(self.sandbox.to_string_lossy())
+            .run()?;
+        std::fs::remove_dir_all(&downloads).map_err(FSError::from)?;
+        Ok((sha256, listing.stdout.lines().count()))
+    }
+
+    /// Decrypt the copy `name` at `copy` to `archive`.
+    fn decrypt(
+        &self,
+        name: &str,
+        copy: &std::path::Path,
+        archive: &std::path::Path,
+    ) -> BackupResult<()> {
+        let decrypt = self
+            .decrypt
+            .as_ref()
+            .ok_or_else(|| BackupError::MissingKey(name.to_string()))?;
+        decrypt(copy, archive)
+    }
+
+    /// Run all checks on the sandbox.
+    fn run_checks(&self) -> Vec<CheckOutcome> {
+        self.checks
+            .iter()
+            .map(|(name, check)| {
+                let started = std::time::Instant::now();
+                let result = check(&self.sandbox);
+                if let Err(error) = &result {
+                    log::warn!("Check '{name}' of the restored files failed: {error}");
+                }
+                CheckOutcome {
+                    name: name.clone(),
+                    result,
+                    duration: started.elapsed(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod restore_test {
+    use super::*;
+    use crate::backup::Destination;
+
+    /// Back up a directory with a single file. Returns the job, the directory and
+    /// where it is restored to relative to the sandbox.
+    fn backed_up(job: impl FnOnce(Job) -> Job) -> BackupResult<(Job, std::path::PathBuf)> {
+        let sources = crate::fs::generate_test_path();
+        std::fs::create_dir_all(&sources).unwrap();
+        std::fs::write(sources.join("index.html"), "<html>").unwrap();
+        let job = job(Job::new(
+            "www",
+            [&sources],
+            Destination::Directory(crate::fs::generate_test_path()),
+        )
+        .staging(crate::fs::generate_test_path()));
+        job.run()?;
+        Ok((job, sources))
+    }
+
+    /// Remove everything [`backed_up`] created.
+    fn clean_up(job: &Job, sources: &std::path::Path) {
+        for directory in [sources, &job.staging_directory()] {
+            std::fs::remove_dir_all(directory).unwrap();
+        }
+        match &job.destination {
+            Destination::Directory(destination) => std::fs::remove_dir_all(destination).unwrap(),
+            #[cfg(feature = "object-store")]
+            Destination::ObjectStore(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn drill() -> BackupResult<()> {
+        let (job, sources) = backed_up(|job| job)?;
+        let restored = sources.strip_prefix("/").unwrap().join("index.html");
+        let sandbox = crate::fs::generate_test_path();
+
+        let report = job
+            .verify_restore(&sandbox)
+            .check("the page is restored", |root| {
+                let page = std::fs::read_to_string(root.join(&restored))
+                    .map_err(|error| error.to_string())?;
+                (page == "<html>")
+                    .then_some(())
+                    .ok_or_else(|| String::from("the content differs"))
+            })
+            .check("the database is restored", |root| {
+                root.join("var/lib/db")
+                    .exists()
+                    .then_some(())
+                    .ok_or_else(|| String::from("it is missing"))
+            })
+            .keep_sandbox(true)
+            .run()?;
+        assert_eq!(report.name, job.copies()?[0]);
+        assert!(report.restored >= 2);
+        assert!(!report.is_success());
+        let failed: Vec<&str> = report.failed().map(|check| check.name.as_str()).collect();
+        assert_eq!(failed, ["the database is restored"]);
+        assert!(report.to_string().contains("1 of 2 checks passed"));
+        // Only the restored files are left.
+        assert!(!sandbox.join(".drill").exists());
+
+        assert_eq!(
+            job.verify_restore(&sandbox).run(),
+            Err(BackupError::SandboxNotEmpty(
+                sandbox.to_string_lossy().into_owned()
+            ))
+        );
+        std::fs::remove_dir_all(&sandbox).unwrap();
+
+        let report = job.verify_restore(&sandbox).run()?;
+        assert!(report.is_success());
+        assert!(!sandbox.exists());
+        assert!(matches!(
+            job.verify_restore(&sandbox)
+                .copy("www-20200101T000000Z.tar.gz")
+                .run(),
+            Err(BackupError::FS(FSError::NonExistent))
+        ));
+        assert!(!sandbox.exists());
+
+        clean_up(&job, &sources);
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted() -> BackupResult<()> {
+        use crate::crypto::encryption::Key;
+
+        let key = Key::generate();
+        let (job, sources) = backed_up(|job| job.encrypt_to(key.public_key().unwrap()))?;
+        let sandbox = crate::fs::generate_test_path();
+        assert!(matches!(
+            job.verify_restore(&sandbox).run(),
+            Err(BackupError::MissingKey(_))
+        ));
+        let restored = sources.strip_prefix("/").unwrap().join("index.html");
+        let report = job
+            .verify_restore(&sandbox)
+            .decrypt_with(key)
+            .check("the page is restored", |root| {
+                std::fs::metadata(root.join(&restored))
+                    .map(|_| ())
+                    .map_err(|error| error.to_string())
+            })
+            .run()?;
+        assert!(report.is_success());
+
+        clean_up(&job, &sources);
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+//! This module contains functionality for benchmarking commands, similar to
+//! [hyperfine](https://github.com/sharkdp/hyperfine) but without leaving Rust.
+//!
+//! A command is run a few times to warm up caches, then the wall-clock and CPU time
+//! of every further run is measured and summarized in a [`Report`]. Reports can be
+//! exported as JSON or as a Markdown table.
+
+use crate::process::{
+    Command,
+    ProcessError,
+};
+
+/// Describes possible errors when benchmarking.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum BenchError {
+    #[error("Running the benchmarked command failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("At least one iteration is required")]
+    NoIterations,
+}
+
+/// A [`Result`] whose error variant is a [`BenchError`].
+pub type BenchResult<T> = Result<T, BenchError>;
+
+/// Summary statistics of a series of measurements, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Statistics {
+    /// The arithmetic mean
+    pub mean:   f64,
+    /// The sample standard deviation, which is 0 for a single measurement
+    pub stddev: f64,
+    /// The median
+    pub median: f64,
+    /// The smallest measurement
+    pub min:    f64,
+    /// The largest measurement
+    pub max:    f64,
+}
+
+impl Statistics {
+    /// Summarize `values`, which must not be empty.
+    fn new(values: &[f64]) -> Self {
+        let count = f64::from(u32::try_from(values.len()).unwrap_or(u32::MAX));
+        let mean = values.iter().sum::<f64>() / count;
+        let stddev = if values.len() > 1 {
+            (values
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f64>()
+                / (count - 1.0))
+                .sqrt()
+        } else {
+            0.0
+        };
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        // For an odd count, both indices point to the middle element.
+        let count_sorted = sorted.len();
+        let median = sorted[(count_sorted - 1) / 2].mul_add(0.5, sorted[count_sorted / 2] * 0.5);
+
+        Self {
+            mean,
+            stddev,
+            median,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// The result of benchmarking a command.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Report {
+    /// The benchmarked command
+    pub command: String,
+    /// The wall-clock time of the runs
+    pub wall:    Statistics,
+    /// The CPU time the command spent in user mode (0 on platforms other than Unix)
+    pub user:    Statistics,
+    /// The CPU time the command spent in the kernel (0 on platforms other than Unix)
+    pub system:  Statistics,
+    /// The wall-clock time of every run, in seconds
+    pub times:   Vec<f64>,
+}
+
+impl Report {
+    /// Serialize the report as pretty-printed JSON.
+    #[must_use]
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).unwrap_or_default() }
+
+    /// Render the report as a Markdown table, see [`markdown`].
+    #[must_use]
+    pub fn to_markdown(&self) -> String { markdown(std::slice::from_ref(self)) }
+}
+
+/// Render `reports` as a Markdown table with one row per command, in the format of
+/// hyperfine's `--export-markdown`. The last column relates each command's mean to
+/// that of the fastest one.
+#[must_use]
+pub fn markdown(reports: &[Report]) -> String {
+    use std::fmt::Write;
+
+    let fastest = reports
+        .iter()
+        .map(|report| report.wall.mean)
+        .fold(f64::INFINITY, f64::min);
+    let mut table = String::from(
+        "| Command | Mean [ms] | Min [ms] | Max [ms] | Relative |\n|:---|---:|---:|---:|---:|\n",
+    );
+    for report in reports {
+        let _ = writeln!(
+            table,
+            "| `{}` | {:.1} ± {:.1} | {:.1} | {:.1} | {:.2} |",
+            report.command.replace('|', "\\|"),
+            report.wall.mean * 1000.0,
+            report.wall.stddev * 1000.0,
+            report.wall.min * 1000.0,
+            report.wall.max * 1000.0,
+            report.wall.mean / fastest
+        );
+    }
+    table
+}
+
+/// The user and system CPU time of all children of this process that have exited.
+#[cfg(unix)]
+fn children_cpu_time() -> (std::time::Duration, std::time::Duration) {
+    use nix::sys::{
+        resource::{
+            getrusage,
+            UsageWho,
+        },
+        time::TimeValLike as _,
+    };
+
+    let to_duration = |time: nix::sys::time::TimeVal| {
+        std::time::Duration::from_micros(u64::try_from(time.num_microseconds()).unwrap_or_default())
+    };
+    getrusage(UsageWho::RUSAGE_CHILDREN).map_or(
+        (std::time::Duration::ZERO, std::time::Duration::ZERO),
+        |usage| {
+            (
+                to_duration(usage.user_time()),
+                to_duration(usage.system_time()),
+            )
+        },
+    )
+}
+
+/// CPU time cannot be measured on this platform.
+#[cfg(not(unix))]
+const fn children_cpu_time() -> (std::time::Duration, std::time::Duration) {
+    (std::time::Duration::ZERO, std::time::Duration::ZERO)
+}
+
+/// Describes how a command is benchmarked. Nothing is run until [`Benchmark::run`]
+/// is called.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    /// The benchmarked command
+    command:         Command,
+    /// The number of unmeasured runs before the measured ones
+    warmup:          usize,
+    /// The number of measured runs
+    iterations:      usize,
+    /// Whether runs that exit with a non-zero exit code are measured as well
+    ignore_failures: bool,
+}
+
+impl Benchmark {
+    /// Benchmark `command` with `iterations` measured runs after one warmup run.
+    #[must_use]
+    pub const fn new(command: Command, iterations: usize) -> Self {
+        Self {
+            command,
+            warmup: 1,
+            iterations,
+            ignore_failures: false,
+        }
+    }
+
+    /// Run the command `warmup` times before measuring, e.g. to fill caches.
+    #[must_use]
+    pub const fn warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Whether to measure runs that exit with a non-zero exit code instead of
+    /// aborting the benchmark.
+    #[must_use]
+    pub const fn ignore_failures(mut self, enabled: bool) -> Self {
+        self.ignore_failures = enabled;
+        self
+    }
+
+    /// Run the command once, discarding its output, and return its wall-clock, user
+    /// and system time.
+    fn run_once(&self) -> BenchResult<[f64; 3]> {
+        use std::process::Stdio;
+
+        let (user_before, system_before) = children_cpu_time();
+        let start = std::time::Instant::now();
+        let output = self
+            .command
+            .start(|command| {
+                command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped());
+            })?
+            .wait_with_output()?;
+        let wall = start.elapsed();
+        let (user_after, system_after) = children_cpu_time();
+
+        if !output.status.success() && !self.ignore_failures {
+            return Err(ProcessError::Failed {
+                code:   output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }
+            .into());
+        }
+        Ok([
+            wall.as_secs_f64(),
+            user_after.saturating_sub(user_before).as_secs_f64(),
+            system_after.saturating_sub(system_before).as_secs_f64(),
+        ])
+    }
+
+    /// Run the benchmark. The output of the command is discarded. CPU times are
+    /// only accurate if this process does not run other programs at the same time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no iterations were requested, if the command could not be
+    /// started or if it failed (unless failures are ignored).
+    pub fn run(&self) -> BenchResult<Report> {
+        if self.iterations == 0 {
+            return Err(BenchError::NoIterations);
+        }
+        log::debug!(
+            "Benchmarking {} ({} warmup runs, {} iterations)",
+            self.command,
+            self.warmup,
+            self.iterations
+        );
+
+        for _ in 0..self.warmup {
+            self.run_once()?;
+        }
+        let mut wall = Vec::with_capacity(self.iterations);
+        let mut user = Vec::with_capacity(self.iterations);
+        let mut system = Vec::with_capacity(self.iterations);
+        for _ in 0..self.iterations {
+            let [run_wall, run_user, run_system] = self.run_once()?;
+            wall.push(run_wall);
+            user.push(run_user);
+            system.push(run_system);
+        }
+
+        Ok(Report {
+            command: std::iter::once(self.command.program())
+                .chain(self.command.arguments().iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" "),
+            wall:    Statistics::new(&wall),
+            user:    Statistics::new(&user),
+            system:  Statistics::new(&system),
+            times:   wall,
+        })
+    }
+}
+
+/// Benchmark `command` with `iterations` measured runs after one warmup run, see
+/// [`Benchmark`].
+///
+/// # Errors
+///
+/// Returns an error if no iterations were requested, if the command could not be
+/// started or if it failed.
+pub fn run(command: Command, iterations: usize) -> BenchResult<Report> {
+    Benchmark::new(command, iterations).run()
+}
+
+#[cfg(test)]
+mod bench_test {
+    use super::*;
+
+    #[test]
+    fn statistics() {
+        let statistics = Statistics::new(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert!((statistics.mean - 5.0).abs() < f64::EPSILON);
+        assert!((statistics.stddev - 2.138_089_935_299_395).abs() < 1e-9);
+        assert!((statistics.median - 4.5).abs() < f64::EPSILON);
+        assert!((statistics.min - 2.0).abs() < f64::EPSILON);
+        assert!((statistics.max - 9.0).abs() < f64::EPSILON);
+        assert!(Statistics::new(&[1.0]).stddev.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn run_command() -> BenchResult<()> {
+        let report = run(Command::new("sleep").arg("0.01"), 3)?;
+        assert_eq!(report.command, "sleep 0.01");
+        assert_eq!(report.times.len(), 3);
+        assert!(report.wall.min >= 0.01);
+        assert!(report.to_markdown().contains("| `sleep 0.01` |"));
+        assert!(report.to_json().contains("\"stddev\""));
+
+        assert_eq!(run(Command::new("true"), 0), Err(BenchError::NoIterations));
+        assert!(matches!(
+            run(Command::new("false"), 1),
+            Err(BenchError::Process(ProcessError::Failed { .. }))
+        ));
+        assert!(Benchmark::new(Command::new("false"), 1)
+            .ignore_failures(true)
+            .run()
+            .is_ok());
+        Ok(())
+    }
+}
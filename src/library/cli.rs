@@ -0,0 +1,906 @@
+//! This module contains functionality for describing the command line interface of
+//! a program, so that shell completions and documentation can be generated from it.
+//!
+//! A [`Command`] lists the flags, options, positional arguments and subcommands a
+//! program accepts. It does not parse arguments. From it, completions for `bash`,
+//! `zsh` and `fish` ([`Command::completions`]), a man page ([`Command::man_page`])
+//! and Markdown help ([`Command::markdown`]) are generated, usually by a hidden
+//! subcommand or in a release script.
+//!
+//! ```
+//! # use rush::cli::{Argument, Command, Shell};
+//! let cli = Command::new("deployer")
+//!     .version("1.2.0")
+//!     .about("Deploy services to hosts")
+//!     .argument(Argument::flag("verbose").short('v').help("Log more"))
+//!     .subcommand(
+//!         Command::new("run")
+//!             .about("Deploy a service")
+//!             .argument(
+//!                 Argument::option("environment", "ENVIRONMENT")
+//!                     .short('e')
+//!                     .values(["staging", "production"]),
+//!             )
+//!             .argument(Argument::positional("MANIFEST").path()),
+//!     );
+//!
+//! println!("{}", cli.completions(Shell::Bash));
+//! assert!(cli.man_page().starts_with(".TH \"DEPLOYER\" \"1\""));
+//! ```
+
+use std::fmt::Write as _;
+
+/// Describes possible errors when dealing with command line interfaces.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum CliError {
+    #[error("'{0}' is not a supported shell (bash, zsh or fish)")]
+    UnknownShell(String),
+}
+
+/// A [`Result`] whose error variant is a [`CliError`].
+pub type CliResult<T> = Result<T, CliError>;
+
+/// A shell that completions are generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shell {
+    /// The Bourne Again Shell
+    Bash,
+    /// The Z shell
+    Zsh,
+    /// The friendly interactive shell
+    Fish,
+}
+
+impl Shell {
+    /// The name of the completion file for the program `name`, as the shell expects
+    /// it in its completion directory (e.g. `_name` for `zsh`).
+    #[must_use]
+    pub fn file_name(self, name: &str) -> String {
+        match self {
+            Self::Bash => name.to_string(),
+            Self::Zsh => format!("_{name}"),
+            Self::Fish => format!("{name}.fish"),
+        }
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+        })
+    }
+}
+
+impl std::str::FromStr for Shell {
+    type Err = CliError;
+
+    fn from_str(shell: &str) -> Result<Self, Self::Err> {
+        match shell {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => Err(CliError::UnknownShell(shell.to_string())),
+        }
+    }
+}
+
+/// A flag, an option with a value or a positional argument of a [`Command`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Argument {
+    /// The long name, without `--`; positional arguments have none
+    long:   Option<String>,
+    /// The short name, without `-`
+    short:  Option<char>,
+    /// The name of the value, e.g. `FILE`; flags have none
+    value:  Option<String>,
+    /// What the argument does
+    help:   String,
+    /// The values the argument accepts, if there is a fixed set
+    values: Vec<String>,
+    /// Whether the value is a path, which is completed with file names
+    path:   bool,
+}
+
+impl Argument {
+    /// A flag without a value, e.g. `--verbose`.
+    pub fn flag(long: impl Into<String>) -> Self {
+        Self {
+            long:   Some(long.into()),
+            short:  None,
+            value:  None,
+            help:   String::new(),
+            values: vec![],
+            path:   false,
+        }
+    }
+
+    /// An option with a value, e.g. `--output FILE`.
+    pub fn option(long: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            value: Some(value.into()),
+            ..Self::flag(long)
+        }
+    }
+
+    /// A positional argument, e.g. `FILE`.
+    pub fn positional(value: impl Into<String>) -> Self {
+        Self {
+            long: None,
+            ..Self::option("", value)
+        }
+    }
+
+    /// Set the short name, e.g. `v` for `-v`.
+    #[must_use]
+    pub const fn short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    /// Set the help text.
+    #[must_use]
+    pub fn help(mut self, help: impl Into<String>) -> Self {
+        self.help = help.into();
+        self
+    }
+
+    /// Set the values the argument accepts. Each value must be a single word.
+    #[must_use]
+    pub fn values<I, S>(mut self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.values = values.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Complete the value with file names.
+    #[must_use]
+    pub const fn path(mut self) -> Self {
+        self.path = true;
+        self
+    }
+
+    /// The ways to spell the option, e.g. `["-o", "--output"]`.
+    fn spellings(&self) -> Vec<String> {
+        self.short
+            .map(|short| format!("-{short}"))
+            .into_iter()
+            .chain(self.long.iter().map(|long| format!("--{long}")))
+            .collect()
+    }
+
+    /// How the argument is shown in help, e.g. `-o, --output <FILE>`.
+    fn display(&self) -> String {
+        let value = self.value.as_ref().map(|value| format!("<{value}>"));
+        if self.long.is_none() {
+            return value.unwrap_or_default();
+        }
+        let spellings = self.spellings().join(", ");
+        value.map_or_else(|| spellings.clone(), |value| format!("{spellings} {value}"))
+    }
+
+    /// The help text, followed by the accepted values.
+    fn full_help(&self) -> String {
+        if self.values.is_empty() {
+            self.help.clone()
+        } else {
+            format!(
+                "{} [possible values: {}]",
+                self.help,
+                self.values.join(", ")
+            )
+            .trim_start()
+            .to_string()
+        }
+    }
+}
+
+/// The command line interface of a program or of one of its subcommands.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Command {
+    /// The name the command is called with
+    name:        String,
+    /// The version of the program
+    version:     Option<String>,
+    /// What the command does, in one line
+    about:       String,
+    /// A longer description for the man page and the Markdown help
+    description: String,
+    /// The flags, options and positional arguments, in order
+    arguments:   Vec<Argument>,
+    /// The subcommands
+    subcommands: Vec<Self>,
+}
+
+impl Command {
+    /// Describe the command `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name:        name.into(),
+            version:     None,
+            about:       String::new(),
+            description: String::new(),
+            arguments:   vec![],
+            subcommands: vec![],
+        }
+    }
+
+    /// Set the version of the program.
+    #[must_use]
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set what the command does, in one line.
+    #[must_use]
+    pub fn about(mut self, about: impl Into<String>) -> Self {
+        self.about = about.into();
+        self
+    }
+
+    /// Set a longer description. Paragraphs are separated by empty lines.
+    #[must_use]
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Add a flag, an option or a positional argument.
+    #[must_use]
+    pub fn argument(mut self, argument: Argument) -> Self {
+        self.arguments.push(argument);
+        self
+    }
+
+    /// Add a subcommand.
+    #[must_use]
+    pub fn subcommand(mut self, subcommand: Self) -> Self {
+        self.subcommands.push(subcommand);
+        self
+    }
+
+    /// The flags and options.
+    fn options(&self) -> impl Iterator<Item = &Argument> {
+        self.arguments
+            .iter()
+            .filter(|argument| argument.long.is_some())
+    }
+
+    /// The positional arguments.
+    fn positionals(&self) -> impl Iterator<Item = &Argument> {
+        self.arguments
+            .iter()
+            .filter(|argument| argument.long.is_none())
+    }
+
+    /// This command and all subcommands below it, with their identifiers (the
+    /// names of their ancestors and themselves joined by `__`) and their paths
+    /// (joined by spaces).
+    fn walk<'a>(&'a self, id: &str, path: &str, commands: &mut Vec<(String, String, &'a Self)>) {
+        commands.push((id.to_string(), path.to_string(), self));
+        for subcommand in &self.subcommands {
+            subcommand.walk(
+                &format!("{id}__{}", subcommand.name),
+                &format!("{path} {}", subcommand.name),
+                commands,
+            );
+        }
+    }
+
+    /// All commands, starting with this one.
+    fn commands(&self) -> Vec<(String, String, &Self)> {
+        let mut commands = vec![];
+        self.walk(&self.name.replace('-', "_"), &self.name, &mut commands);
+        commands
+    }
+
+    /// The usage line of the command called as `path`.
+    fn usage(&self, path: &str) -> String {
+        let mut usage = path.to_string();
+        if self.options().next().is_some() {
+            usage.push_str(" [OPTIONS]");
+        }
+        for positional in self.positionals() {
+            let _ = write!(usage, " {}", positional.display());
+        }
+        if !self.subcommands.is_empty() {
+            usage.push_str(" <COMMAND>");
+        }
+        usage
+    }
+
+    /// Generate the completion script for `shell`.
+    ///
+    /// Install it as [`Shell::file_name`] in the shell's completion directory, e.g.
+    /// `/usr/share/bash-completion/completions`, a directory in `$fpath` for `zsh`,
+    /// or `~/.config/fish/completions`.
+    #[must_use]
+    pub fn completions(&self, shell: Shell) -> String {
+        match shell {
+            Shell::Bash => self.bash(),
+            Shell::Zsh => self.zsh(),
+            Shell::Fish => self.fish(),
+        }
+    }
+
+    /// Generate the completion script for `bash`.
+    fn bash(&self) -> String {
+        let commands = self.commands();
+        let mut script = String::new();
+        let _ = writeln!(script, "_{}() {{", commands[0].0);
+        script.push_str(concat!(
+            "    local cur prev command i\n",
+            "    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n",
+            "    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+        ));
+        let _ = writeln!(script, "    command=\"{}\"", commands[0].0);
+        script.push_str(concat!(
+            "    for ((i = 1; i < COMP_CWORD; i++)); do\n",
+            "        case \"${command}__${COMP_WORDS[i]}\" in\n",
+        ));
+        for (id, _, command) in &commands {
+            for subcommand in &command.subcommands {
+                let _ = writeln!(
+                    script,
+                    "            {id}__{0}) command=\"{id}__{0}\" ;;",
+                    subcommand.name
+                );
+            }
+        }
+        script.push_str("        esac\n    done\n    case \"$command\" in\n");
+
+        for (id, _, command) in &commands {
+            let _ = writeln!(script, "        {id})");
+            let with_values = command
+                .options()
+                .filter(|option| option.value.is_some())
+                .collect::<Vec<_>>();
+            if !with_values.is_empty() {
+                script.push_str("            case \"$prev\" in\n");
+                for option in with_values {
+                    let _ = writeln!(
+                        script,
+                        "                {}) {}; return ;;",
+                        option.spellings().join("|"),
+                        bash_reply(option)
+                    );
+                }
+                script.push_str("            esac\n");
+            }
+
+            let words = command
+                .options()
+                .flat_map(Argument::spellings)
+                .chain(
+                    command
+                        .subcommands
+                        .iter()
+                        .map(|subcommand| subcommand.name.clone()),
+                )
+                .chain(
+                    command
+                        .positionals()
+                        .flat_map(|positional| positional.values.clone()),
+                )
+                .collect::<Vec<_>>();
+            let _ = writeln!(
+                script,
+                "            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                words.join(" ")
+            );
+            if command.positionals().any(|positional| positional.path) {
+                script.push_str("            COMPREPLY+=($(compgen -f -- \"$cur\"))\n");
+            }
+            script.push_str("            ;;\n");
+        }
+        let _ = write!(
+            script,
+            "    esac\n}}\n\ncomplete -F _{} {}\n",
+            commands[0].0, self.name
+        );
+        script
+    }
+
+    /// Generate the completion script for `zsh`.
+    fn zsh(&self) -> String {
+        let commands = self.commands();
+        let mut script = format!("#compdef {}\n", self.name);
+        for (id, _, command) in &commands {
+            let mut specifications = command.options().map(zsh_option).collect::<Vec<_>>();
+            specifications.extend(command.positionals().map(|positional| {
+                format!(
+                    "':{}:{}'",
+                    zsh_escape(positional.value.as_deref().unwrap_or_default()),
+                    zsh_action(positional)
+                )
+            }));
+            if !command.subcommands.is_empty() {
+                specifications.push(String::from("': :->command'"));
+                specifications.push(String::from("'*:: :->argument'"));
+            }
+
+            let _ = write!(
+                script,
+                "\n_{id}() {{\n    local context state state_descr line\n    typeset -A \
+                 opt_args\n    _arguments -C"
+            );
+            for specification in specifications {
+                let _ = write!(script, " \\\n        {specification}");
+            }
+            script.push('\n');
+
+            if !command.subcommands.is_empty() {
+                script.push_str(
+                    "    case $state in\n        command)\n            _values 'command'",
+                );
+                for subcommand in &command.subcommands {
+                    let _ = write!(
+                        script,
+                        " \\\n                '{}[{}]'",
+                        subcommand.name,
+                        zsh_escape(&subcommand.about)
+                    );
+                }
+                let _ = writeln!(
+                    script,
+                    "\n            ;;\n        argument)\n            case $line[{}] in",
+                    command.positionals().count() + 1
+                );
+                for subcommand in &command.subcommands {
+                    let _ = writeln!(
+                        script,
+                        "                {0}) _{id}__{0} ;;",
+                        subcommand.name
+                    );
+                }
+                script.push_str("            esac\n            ;;\n    esac\n");
+            }
+            script.push_str("}\n");
+        }
+        let _ = write!(script, "\n_{} \"$@\"\n", commands[0].0);
+        script
+    }
+
+    /// Generate the completion script for `fish`.
+    fn fish(&self) -> String {
+        let commands = self.commands();
+        let mut script = String::new();
+        for (_, path, command) in &commands {
+            // The subcommand that was typed last decides what is completed.
+            let condition = if path == &self.name {
+                (!command.subcommands.is_empty()).then(|| String::from("__fish_use_subcommand"))
+            } else {
+                let mut condition = format!("__fish_seen_subcommand_from {}", command.name);
+                if !command.subcommands.is_empty() {
+                    let _ = write!(
+                        condition,
+                        "; and not __fish_seen_subcommand_from {}",
+                        command
+                            .subcommands
+                            .iter()
+                            .map(|subcommand| subcommand.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    );
+                }
+                Some(condition)
+            };
+            let prefix = condition.map_or_else(
+                || format!("complete -c {}", self.name),
+                |condition| format!("complete -c {} -n '{condition}'", self.name),
+            );
+
+            for subcommand in &command.subcommands {
+                let _ = writeln!(
+                    script,
+                    "{prefix} -f -a {} -d '{}'",
+                    subcommand.name,
+                    fish_escape(&subcommand.about)
+                );
+            }
+            for option in command.options() {
+                let mut line = prefix.clone();
+                if let Some(short) = option.short {
+                    let _ = write!(line, " -s {short}");
+                }
+                if let Some(long) = &option.long {
+                    let _ = write!(line, " -l {long}");
+                }
+                if option.value.is_some() {
+                    line.push_str(&fish_value(option));
+                }
+                finish_line(&mut line, &option.help);
+                script.push_str(&line);
+            }
+            for positional in command.positionals() {
+                if !positional.values.is_empty() || positional.path {
+                    let mut line = format!("{prefix}{}", fish_value(positional));
+                    finish_line(&mut line, &positional.help);
+                    script.push_str(&line);
+                }
+            }
+        }
+        script
+    }
+
+    /// Generate a man page in section 1, in `roff` format. Subcommands are
+    /// documented in the section `COMMANDS`.
+    #[must_use]
+    pub fn man_page(&self) -> String {
+        let mut page = format!(
+            ".TH \"{}\" \"1\" \"\" \"{}\" \"User Commands\"\n.SH NAME\n{}",
+            self.name.to_uppercase(),
+            roff(&self.version.as_ref().map_or_else(
+                || self.name.clone(),
+                |version| format!("{} {version}", self.name)
+            )),
+            roff(&self.name)
+        );
+        if !self.about.is_empty() {
+            let _ = write!(page, " \\- {}", roff(&self.about));
+        }
+        let _ = write!(page, "\n.SH SYNOPSIS\n{}\n", roff(&self.usage(&self.name)));
+        if !self.description.is_empty() {
+            page.push_str(".SH DESCRIPTION\n");
+            page.push_str(&roff_paragraphs(&self.description));
+        }
+        roff_arguments(&mut page, self, false);
+
+        let commands = self.commands();
+        if commands.len() > 1 {
+            page.push_str(".SH COMMANDS\n");
+        }
+        for (_, path, command) in commands.iter().skip(1) {
+            let _ = write!(
+                page,
+                ".SS \"{}\"\n{}\n.PP\n{}\n",
+                roff(path),
+                roff(&command.about),
+                roff(&command.usage(path))
+            );
+            if !command.description.is_empty() {
+                page.push_str(".PP\n");
+                page.push_str(&roff_paragraphs(&command.description));
+            }
+            roff_arguments(&mut page, command, true);
+        }
+        page
+    }
+
+    /// Generate help in Markdown, e.g. for a `README` or a documentation site.
+    /// Subcommands get their own sections.
+    #[must_use]
+    pub fn markdown(&self) -> String {
+        let mut markdown = String::new();
+        for (level, (_, path, command)) in self.commands().iter().enumerate() {
+            let heading = if level == 0 { "#" } else { "##" };
+            let _ = writeln!(markdown, "{heading} `{path}`\n");
+            if level == 0 {
+                if let Some(version) = &self.version {
+                    let _ = writeln!(markdown, "Version {version}\n");
+                }
+            }
+            if !command.about.is_empty() {
+                let _ = writeln!(markdown, "{}\n", command.about);
+            }
+            if !command.description.is_empty() {
+                let _ = writeln!(markdown, "{}\n", command.description.trim());
+            }
+            let _ = writeln!(
+                markdown,
+                "{heading}# Usage\n\n```text\n{}\n```\n",
+                command.usage(path)
+            );
+            for (title, arguments) in [
+                ("Arguments", command.positionals().collect::<Vec<_>>()),
+                ("Options", command.options().collect()),
+            ] {
+                if !arguments.is_empty() {
+                    let _ = writeln!(markdown, "{heading}# {title}\n");
+                    for argument in arguments {
+                        let _ = writeln!(
+                            markdown,
+                            "- `{}`: {}",
+                            argument.display(),
+                            argument.full_help()
+                        );
+                    }
+                    markdown.push('\n');
+                }
+            }
+            if !command.subcommands.is_empty() {
+                let _ = writeln!(markdown, "{heading}# Commands\n");
+                for subcommand in &command.subcommands {
+                    let _ = writeln!(markdown, "- `{}`: {}", subcommand.name, subcommand.about);
+                }
+                markdown.push('\n');
+            }
+        }
+        markdown.truncate(markdown.trim_end().len());
+        markdown.push('\n');
+        markdown
+    }
+}
+
+/// How `bash` completes the value of `argument`.
+fn bash_reply(argument: &Argument) -> String {
+    if !argument.values.is_empty() {
+        format!(
+            "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            argument.values.join(" ")
+        )
+    } else if argument.path {
+        String::from("COMPREPLY=($(compgen -f -- \"$cur\"))")
+    } else {
+        String::from("COMPREPLY=()")
+    }
+}
+
+/// Escape `text` for a single-quoted `zsh` specification.
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+/// How `zsh` completes the value of `argument`.
+fn zsh_action(argument: &Argument) -> String {
+    if !argument.values.is_empty() {
+        format!("({})", argument.values.join(" "))
+    } else if argument.path {
+        String::from("_files")
+    } else {
+        String::from(" ")
+    }
+}
+
+/// The `_arguments` specification of the flag or option `option`.
+fn zsh_option(option: &Argument) -> String {
+    let help = format!("[{}]", zsh_escape(&option.help));
+    let action = option.value.as_ref().map_or_else(String::new, |value| {
+        format!(":{}:{}", zsh_escape(value), zsh_action(option))
+    });
+    let long = option.long.as_deref().unwrap_or_default();
+    let (short_suffix, long_suffix) = if option.value.is_some() {
+        ("+", "=")
+    } else {
+        ("", "")
+    };
+    option.short.map_or_else(
+        || format!("'--{long}{long_suffix}{help}{action}'"),
+        |short| {
+            format!(
+                "'(-{short} \
+                 --{long})'{{-{short}{short_suffix},--{long}{long_suffix}}}'{help}{action}'"
+            )
+        },
+    )
+}
+
+/// Escape `text` for a single-quoted `fish` argument.
+fn fish_escape(text: &str) -> String { text.replace('\\', "\\\\").replace('\'', "\\'") }
+
+/// The `complete` arguments for the value of `argument`.
+fn fish_value(argument: &Argument) -> String {
+    if !argument.values.is_empty() {
+        format!(" -x -a '{}'", fish_escape(&argument.values.join(" ")))
+    } else if argument.path {
+        String::from(" -r -F")
+    } else {
+        String::from(" -x")
+    }
+}
+
+/// Finish a `complete` line with the description `help`, if there is one.
+fn finish_line(line: &mut String, help: &str) {
+    if !help.is_empty() {
+        let _ = write!(line, " -d '{}'", fish_escape(help));
+    }
+    line.push('\n');
+}
+
+/// Escape `text` for `roff`.
+fn roff(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with(['.', '\'']) {
+        format!("\\&{escaped}")
+    } else {
+        escaped
+    }
+}
+
+/// Format paragraphs separated by empty lines for `roff`.
+fn roff_paragraphs(text: &str) -> String {
+    text.trim()
+        .split("\n\n")
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .map(|line| roff(line.trim()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n.PP\n")
+        + "\n"
+}
+
+/// Document the arguments of `command`, under sections for the program itself and
+/// under bold headings for subcommands.
+fn roff_arguments(page: &mut String, command: &Command, is_subcommand: bool) {
+    for (title, arguments) in [
+        ("ARGUMENTS", command.positionals().collect::<Vec<_>>()),
+        ("OPTIONS", command.options().collect()),
+    ] {
+        if arguments.is_empty() {
+            continue;
+        }
+        if is_subcommand {
+            let _ = writeln!(page, ".PP\n\\fB{title}\\fR");
+        } else {
+            let _ = writeln!(page, ".SH {title}");
+        }
+        for argument in arguments {
+            let _ = writeln!(
+                page,
+                ".TP\n\\fB{}\\fR\n{}",
+                roff(&argument.display()),
+                roff(&argument.full_help())
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod cli_test {
+    use super::*;
+
+    /// A program with options, positional arguments and nested subcommands.
+    fn example() -> Command {
+        Command::new("deployer")
+            .version("1.2.0")
+            .about("Deploy services to hosts")
+            .description("Reads a manifest and deploys it.\n\nHosts are updated one by one.")
+            .argument(Argument::flag("verbose").short('v').help("Log more"))
+            .argument(
+                Argument::option("config", "FILE")
+                    .path()
+                    .help("Use another config file"),
+            )
+            .subcommand(
+                Command::new("run")
+                    .about("Deploy a service")
+                    .argument(
+                        Argument::option("environment", "ENVIRONMENT")
+                            .short('e')
+                            .values(["staging", "production"])
+                            .help("Where to deploy [default: staging]"),
+                    )
+                    .argument(
+                        Argument::positional("MANIFEST")
+                            .path()
+                            .help("The service's manifest"),
+                    ),
+            )
+            .subcommand(
+                Command::new("hosts")
+                    .about("Manage hosts")
+                    .subcommand(Command::new("list").about("List all hosts")),
+            )
+    }
+
+    #[test]
+    fn shells() {
+        assert_eq!("zsh".parse::<Shell>(), Ok(Shell::Zsh));
+        assert_eq!(
+            "tcsh".parse::<Shell>(),
+            Err(CliError::UnknownShell(String::from("tcsh")))
+        );
+        assert_eq!(Shell::Zsh.file_name("deployer"), "_deployer");
+        assert_eq!(Shell::Fish.file_name("deployer"), "deployer.fish");
+    }
+
+    #[test]
+    fn bash() {
+        let script = example().completions(Shell::Bash);
+        assert!(script.contains("deployer__hosts) command=\"deployer__hosts\" ;;"));
+        assert!(script.contains("deployer__hosts__list) command=\"deployer__hosts__list\" ;;"));
+        assert!(script.contains(
+            "-e|--environment) COMPREPLY=($(compgen -W \"staging production\" -- \"$cur\")); \
+             return ;;"
+        ));
+        assert!(script.contains("compgen -W \"-v --verbose --config run hosts\""));
+        assert!(script.ends_with("complete -F _deployer deployer\n"));
+
+        // The script must at least be valid syntax.
+        let path = crate::fs::generate_test_path();
+        std::fs::write(&path, &script).unwrap();
+        crate::process::Command::new("bash")
+            .arg("-n")
+            .arg(path.to_string_lossy())
+            .run()
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn zsh() {
+        let script = example().completions(Shell::Zsh);
+        assert!(script.starts_with("#compdef deployer\n"));
+        assert!(script.contains("'(-v --verbose)'{-v,--verbose}'[Log more]'"));
+        assert!(script.contains("'--config=[Use another config file]:FILE:_files'"));
+        assert!(script.contains(
+            "'(-e --environment)'{-e+,--environment=}'[Where to deploy \\[default\\: \
+             staging\\]]:ENVIRONMENT:(staging production)'"
+        ));
+        assert!(script.contains("'hosts[Manage hosts]'"));
+        assert!(script.contains("list) _deployer__hosts__list ;;"));
+        assert!(script.ends_with("_deployer \"$@\"\n"));
+    }
+
+    #[test]
+    fn fish() {
+        let script = example().completions(Shell::Fish);
+        assert!(script.contains(
+            "complete -c deployer -n '__fish_use_subcommand' -f -a run -d 'Deploy a service'\n"
+        ));
+        assert!(script.contains(
+            "complete -c deployer -n '__fish_use_subcommand' -s v -l verbose -d 'Log more'\n"
+        ));
+        assert!(script.contains(
+            "complete -c deployer -n '__fish_seen_subcommand_from run' -s e -l environment -x -a \
+             'staging production'"
+        ));
+        assert!(script.contains(
+            "complete -c deployer -n '__fish_seen_subcommand_from run' -r -F -d 'The service\\'s \
+             manifest'\n"
+        ));
+        assert!(script.contains(
+            "complete -c deployer -n '__fish_seen_subcommand_from hosts; and not \
+             __fish_seen_subcommand_from list' -f -a list"
+        ));
+    }
+
+    #[test]
+    fn man_page() {
+        let page = example().man_page();
+        assert!(page.starts_with(
+            ".TH \"DEPLOYER\" \"1\" \"\" \"deployer 1.2.0\" \"User Commands\"\n.SH NAME\ndeployer \
+             \\- Deploy services to hosts\n.SH SYNOPSIS\ndeployer [OPTIONS] <COMMAND>\n"
+        ));
+        assert!(page.contains(
+            ".SH DESCRIPTION\nReads a manifest and deploys it.\n.PP\nHosts are updated one by \
+             one.\n"
+        ));
+        assert!(page.contains(".SH OPTIONS\n.TP\n\\fB\\-v, \\-\\-verbose\\fR\nLog more\n"));
+        assert!(page.contains(".SS \"deployer hosts list\"\nList all hosts\n"));
+        assert!(page
+            .contains(".PP\n\\fBARGUMENTS\\fR\n.TP\n\\fB<MANIFEST>\\fR\nThe service's manifest\n"));
+    }
+
+    #[test]
+    fn markdown() {
+        let markdown = example().markdown();
+        assert!(markdown.starts_with(
+            "# `deployer`\n\nVersion 1.2.0\n\nDeploy services to hosts\n\nReads a manifest"
+        ));
+        assert!(markdown.contains("## Usage\n\n```text\ndeployer [OPTIONS] <COMMAND>\n```\n"));
+        assert!(markdown.contains(
+            "## `deployer run`\n\nDeploy a service\n\n### Usage\n\n```text\ndeployer run \
+             [OPTIONS] <MANIFEST>\n```\n"
+        ));
+        assert!(markdown.contains(
+            "- `-e, --environment <ENVIRONMENT>`: Where to deploy [default: staging] [possible \
+             values: staging, production]\n"
+        ));
+        assert!(markdown.contains("### Commands\n\n- `list`: List all hosts\n"));
+        assert!(markdown.ends_with("```text\ndeployer hosts list\n```\n"));
+    }
+}
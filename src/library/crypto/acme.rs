@@ -0,0 +1,489 @@
+//! This module contains functionality for obtaining and renewing certificates from
+//! ACME certificate authorities like Let's Encrypt.
+//!
+//! The protocol is spoken by an established client, `certbot` or `lego`, which
+//! needs to be installed. [`Renewal`] decides whether a certificate is due, runs the
+//! client, copies the certificate and key to where services expect them and runs
+//! reload commands. Run it regularly, e.g. from a systemd timer:
+//!
+//! ```no_run
+//! # use rush::{crypto::acme::{Challenge, Client, Renewal}, process::Command};
+//! let outcome = Renewal::new(Client::Lego, &["example.com"], "admin@example.com")
+//!     .challenge(Challenge::Webroot("/var/www/html".into()))
+//!     .deploy_to("/etc/nginx/tls/example.com.crt", "/etc/nginx/tls/example.com.key")
+//!     .reload(Command::new("systemctl").args(["reload", "nginx"]))
+//!     .run()
+//!     .unwrap();
+//! println!("{outcome}");
+//! ```
+
+use super::cert::{
+    self,
+    CertError,
+};
+use crate::{
+    fs::{
+        FSError,
+        FSResult,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Where `certbot` keeps its configuration and certificates by default.
+const CERTBOT_DIRECTORY: &str = "/etc/letsencrypt";
+/// Where `lego` keeps its accounts and certificates, unless configured otherwise.
+const LEGO_DIRECTORY: &str = "/var/lib/lego";
+/// Renew certificates this many days before they expire by default, which is what
+/// Let's Encrypt recommends.
+const RENEW_WITHIN_DAYS: i64 = 30;
+/// The directory URL of Let's Encrypt's staging environment.
+const STAGING_URL: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+/// Describes possible errors when obtaining certificates.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum AcmeError {
+    #[error("At least one domain is required")]
+    NoDomains,
+    #[error("Running the ACME client failed: {0}")]
+    Client(#[from] ProcessError),
+    #[error("The certificate is not valid: {0}")]
+    Certificate(#[from] CertError),
+    #[error("Deploying the certificate failed: {0}")]
+    FS(#[from] FSError),
+    #[error("The certificate was renewed, but reloading with '{command}' failed: {error}")]
+    Reload {
+        command: String,
+        error:   ProcessError,
+    },
+}
+
+/// A [`Result`] whose error variant is a [`AcmeError`].
+pub type AcmeResult<T> = Result<T, AcmeError>;
+
+/// The ACME client that talks to the certificate authority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Client {
+    /// The EFF's `certbot`
+    Certbot,
+    /// `lego`, a single binary without dependencies
+    Lego,
+}
+
+/// How the certificate authority verifies control over the domains.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Challenge {
+    /// Place the HTTP-01 challenge in this directory, which a running web server
+    /// serves under `/.well-known/acme-challenge/`
+    Webroot(std::path::PathBuf),
+    /// Answer the HTTP-01 challenge with a temporary server on port 80, which must
+    /// be free
+    Standalone,
+    /// Create the DNS-01 challenge record with this provider of the client (e.g.
+    /// `cloudflare`); its credentials are read from the environment
+    Dns(String),
+}
+
+/// What [`Renewal::run`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The certificate was not due; it is valid for this many more days
+    Current(i64),
+    /// The certificate was obtained or renewed and is valid for this many days
+    Renewed(i64),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Current(days) => write!(f, "The certificate is valid for {days} more days"),
+            Self::Renewed(days) => write!(
+                f,
+                "The certificate was renewed and is valid for {days} days"
+            ),
+        }
+    }
+}
+
+/// Describes a certificate to keep valid. Nothing happens until [`Renewal::run`] is
+/// called. See the module documentation for an example.
+#[derive(Debug, Clone)]
+pub struct Renewal {
+    /// The client to run
+    client:            Client,
+    /// The domains the certificate is for; the first one names it
+    domains:           Vec<String>,
+    /// The email address of the account, where expiry warnings are sent to
+    email:             String,
+    /// How control over the domains is proven
+    challenge:         Challenge,
+    /// Where the client keeps accounts and certificates
+    directory:         std::path::PathBuf,
+    /// Whether to use the staging environment of Let's Encrypt
+    staging:           bool,
+    /// Renew certificates that expire within this many days
+    renew_within_days: i64,
+    /// Where to copy the certificate (with its chain) and the key to
+    deploy:            Vec<(std::path::PathBuf, std::path::PathBuf)>,
+    /// The commands that make services pick the new certificate up
+    reload:            Vec<Command>,
+}
+
+impl Renewal {
+    /// Keep a certificate for `domains` valid, using `client` with the account of
+    /// `email`. By default, the HTTP-01 challenge is answered by a standalone server.
+    pub fn new(client: Client, domains: &[&str], email: impl Into<String>) -> Self {
+        let directory = match client {
+            Client::Certbot => CERTBOT_DIRECTORY,
+            Client::Lego => LEGO_DIRECTORY,
+        };
+        Self {
+            client,
+            domains: domains.iter().map(ToString::to_string).collect(),
+            email: email.into(),
+            challenge: Challenge::Standalone,
+            directory: std::path::PathBuf::from(directory),
+            staging: false,
+            renew_within_days: RENEW_WITHIN_DAYS,
+            deploy: Vec::new(),
+            reload: Vec::new(),
+        }
+    }
+
+    /// Prove control over the domains with `challenge`.
+    #[must_use]
+    pub fn challenge(mut self, challenge: Challenge) -> Self {
+        self.challenge = challenge;
+        self
+    }
+
+    /// Let the client keep its accounts and certificates in `directory` instead of
+    /// `/etc/letsencrypt` (`certbot`) or `/var/lib/lego` (`lego`).
+    #[must_use]
+    pub fn directory(mut self, directory: impl Into<std::path::PathBuf>) -> Self {
+        self.directory = directory.into();
+        self
+    }
+
+    /// Whether to use the staging environment of Let's Encrypt, whose certificates
+    /// are not trusted but whose rate limits are generous. Use it while testing.
+    #[must_use]
+    pub const fn staging(mut self, staging: bool) -> Self {
+        self.staging = staging;
+        self
+    }
+
+    /// Renew the certificate once it expires within `days` (30 by default).
+    #[must_use]
+    pub const fn renew_within(mut self, days: i64) -> Self {
+        self.renew_within_days = days;
+        self
+    }
+
+    /// Copy the certificate (with its chain) to `certificate_file` and the key to
+    /// `key_file` after it was renewed, and whenever one of them is missing. The key
+    /// is only readable by its owner.
+    #[must_use]
+    pub fn deploy_to(
+        mut self,
+        certificate_file: impl Into<std::path::PathBuf>,
+        key_file: impl Into<std::path::PathBuf>,
+    ) -> Self {
+        self.deploy.push((certificate_file.into(), key_file.into()));
+        self
+    }
+
+    /// Run `command` after the certificate was renewed and deployed, e.g. to reload
+    /// a web server.
+    #[must_use]
+    pub fn reload(mut self, command: Command) -> Self {
+        self.reload.push(command);
+        self
+    }
+
+    /// The name of the certificate, which is its first domain.
+    fn name(&self) -> AcmeResult<&str> {
+        self.domains
+            .first()
+            .map(String::as_str)
+            .ok_or(AcmeError::NoDomains)
+    }
+
+    /// The certificate (with its chain) and the key as stored by the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no domain was given.
+    pub fn files(&self) -> AcmeResult<(std::path::PathBuf, std::path::PathBuf)> {
+        let name = self.name()?;
+        Ok(match self.client {
+            Client::Certbot => {
+                let live = self.directory.join("live").join(name);
+                (live.join("fullchain.pem"), live.join("privkey.pem"))
+            },
+            Client::Lego => {
+                // `lego` cannot use `*` of wildcard domains in file names.
+                let certificates = self.directory.join("certificates");
+                let name = name.replace('*', "_");
+                (
+                    certificates.join(format!("{name}.crt")),
+                    certificates.join(format!("{name}.key")),
+                )
+            },
+        })
+    }
+
+    /// The certificate as stored by the client, if there is a valid one.
+    fn current(&self) -> AcmeResult<Option<cert::Certificate>> {
+        let (certificate_file, _) = self.files()?;
+        Ok(std::fs::read_to_string(certificate_file)
+            .ok()
+            .and_then(|pem| cert::Certificate::from_pem(&pem).ok()))
+    }
+
+    /// Whether `certificate` is valid for all domains.
+    fn covers(&self, certificate: &cert::Certificate) -> bool {
+        self.domains
+            .iter()
+            .all(|domain| certificate.subject_alt_names.contains(domain))
+    }
+
+    /// The number of days the current certificate is valid for if it does not need
+    /// to be renewed: it exists, covers all domains and does not expire soon.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no domain was given.
+    pub fn days_left(&self) -> AcmeResult<Option<i64>> {
+        Ok(self
+            .current()?
+            .filter(|certificate| self.covers(certificate))
+            .map(|certificate| certificate.days_until_expiry())
+            .filter(|days| *days > self.renew_within_days))
+    }
+
+    /// The invocation of the client that obtains the certificate or, if `renew` is
+    /// set, renews it.
+    fn command(&self, renew: bool) -> Command {
+        let directory = self.directory.to_string_lossy();
+        match self.client {
+            Client::Certbot => {
+                let mut command = Command::new("certbot")
+                    .args(["certonly", "--non-interactive", "--agree-tos", "--email"])
+                    .arg(&self.email)
+                    .args(["--config-dir", &directory, "--cert-name"])
+                    .arg(&self.domains[0]);
+                for domain in &self.domains {
+                    command = command.arg("--domain").arg(domain);
+                }
+                command = match &self.challenge {
+                    Challenge::Webroot(webroot) => command
+                        .args(["--webroot", "--webroot-path"])
+                        .arg(webroot.to_string_lossy()),
+                    Challenge::Standalone => command.arg("--standalone"),
+                    Challenge::Dns(provider) => command.arg(format!("--dns-{provider}")),
+                };
+                if self.staging {
+                    command = command.arg("--staging");
+                }
+                // The decision to renew has already been made.
+                if renew {
+                    command = command.arg("--force-renewal");
+                }
+                command
+            },
+            Client::Lego => {
+                let mut command = Command::new("lego")
+                    .args(["--accept-tos", "--email"])
+                    .arg(&self.email)
+                    .args(["--path", &directory]);
+                for domain in &self.domains {
+                    command = command.arg("--domains").arg(domain);
+                }
+                command = match &self.challenge {
+                    Challenge::Webroot(webroot) => command
+                        .args(["--http", "--http.webroot"])
+                        .arg(webroot.to_string_lossy()),
+                    Challenge::Standalone => command.arg("--http"),
+                    Challenge::Dns(provider) => command.args(["--dns", provider]),
+                };
+                if self.staging {
+                    command = command.args(["--server", STAGING_URL]);
+                }
+                if renew {
+                    // `lego` renews certificates with fewer days left than this.
+                    command.args(["renew", "--days", &(self.renew_within_days + 1).to_string()])
+                } else {
+                    command.arg("run")
+                }
+            },
+        }
+    }
+
+    /// Copy the certificate and key to every deployment target.
+    fn deploy(&self) -> AcmeResult<()> {
+        let (certificate_file, key_file) = self.files()?;
+        let certificate = std::fs::read(&certificate_file).map_err(FSError::from)?;
+        let key = std::fs::read(&key_file).map_err(FSError::from)?;
+        for (certificate_target, key_target) in &self.deploy {
+            log::debug!(
+                "Deploying the certificate to '{}'",
+                certificate_target.display()
+            );
+            write_atomically(certificate_target, &certificate, 0o644)?;
+            write_atomically(key_target, &key, 0o600)?;
+        }
+        Ok(())
+    }
+
+    /// Make sure the certificate is valid for more than the configured number of
+    /// days, obtaining or renewing it if necessary. After a renewal, the certificate
+    /// is deployed and the reload commands are run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no domain was given, if the client fails, or if deploying
+    /// or reloading fails.
+    pub fn run(&self) -> AcmeResult<Outcome> {
+        let name = self.name()?;
+        if let Some(days) = self.days_left()? {
+            log::debug!("The certificate for '{name}' is valid for {days} more days");
+            let missing = self
+                .deploy
+                .iter()
+                .any(|(certificate, key)| !certificate.exists() || !key.exists());
+            if missing {
+                self.deploy()?;
+            }
+            return Ok(Outcome::Current(days));
+        }
+
+        // Certificates for other domains are replaced rather than renewed.
+        let renew = self
+            .current()?
+            .is_some_and(|certificate| self.covers(&certificate));
+        log::info!(
+            "{} the certificate for '{name}'",
+            if renew { "Renewing" } else { "Obtaining" }
+        );
+        self.command(renew).run()?;
+        let (certificate_file, _) = self.files()?;
+        let pem = std::fs::read_to_string(certificate_file).map_err(FSError::from)?;
+        let days = cert::Certificate::from_pem(&pem)?.days_until_expiry();
+
+        self.deploy()?;
+        for command in &self.reload {
+            command.run().map_err(|error| AcmeError::Reload {
+                command: command.to_string(),
+                error,
+            })?;
+        }
+        Ok(Outcome::Renewed(days))
+    }
+}
+
+/// Replace `path` atomically with `content`, with the permission bits `mode`.
+fn write_atomically(path: &std::path::Path, content: &[u8], mode: u32) -> AcmeResult<()> {
+    use std::io::Write as _;
+
+    crate::fs::write_atomic_with(path, Some(mode), |writer| -> FSResult<()> {
+        Ok(writer.write_all(content)?)
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod acme_test {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let renewal = Renewal::new(
+            Client::Certbot,
+            &["example.com", "www.example.com"],
+            "a@b.c",
+        )
+        .challenge(Challenge::Webroot("/srv/www".into()))
+        .staging(true);
+        assert_eq!(
+            renewal.command(true).to_string(),
+            "'certbot certonly --non-interactive --agree-tos --email a@b.c --config-dir \
+             /etc/letsencrypt --cert-name example.com --domain example.com --domain \
+             www.example.com --webroot --webroot-path /srv/www --staging --force-renewal'"
+        );
+        assert_eq!(
+            renewal.files(),
+            Ok((
+                "/etc/letsencrypt/live/example.com/fullchain.pem".into(),
+                "/etc/letsencrypt/live/example.com/privkey.pem".into()
+            ))
+        );
+
+        let renewal = Renewal::new(Client::Lego, &["*.example.com"], "a@b.c")
+            .challenge(Challenge::Dns(String::from("cloudflare")))
+            .directory("/tmp/lego");
+        assert_eq!(
+            renewal.command(false).to_string(),
+            "'lego --accept-tos --email a@b.c --path /tmp/lego --domains *.example.com --dns \
+             cloudflare run'"
+        );
+        assert_eq!(
+            renewal.files(),
+            Ok((
+                "/tmp/lego/certificates/_.example.com.crt".into(),
+                "/tmp/lego/certificates/_.example.com.key".into()
+            ))
+        );
+        assert_eq!(
+            Renewal::new(Client::Lego, &[], "a@b.c").run(),
+            Err(AcmeError::NoDomains)
+        );
+    }
+
+    #[test]
+    fn renewal_decision() -> AcmeResult<()> {
+        let directory = crate::fs::generate_test_path();
+        std::fs::create_dir_all(directory.join("certificates")).map_err(FSError::from)?;
+        let renewal = Renewal::new(Client::Lego, &["example.com"], "a@b.c")
+            .directory(&directory)
+            .renew_within(3650);
+        // Without a certificate, one has to be obtained.
+        assert_eq!(renewal.days_left(), Ok(None));
+
+        // The test certificate is valid until 2034, which is within ten years.
+        let (certificate_file, key_file) = renewal.files()?;
+        std::fs::write(&certificate_file, cert::cert_test::CERTIFICATE).map_err(FSError::from)?;
+        std::fs::write(&key_file, "key").map_err(FSError::from)?;
+        assert_eq!(renewal.days_left(), Ok(None));
+
+        // Current certificates are deployed if a target is missing.
+        let target = directory.join("deployed.crt");
+        let renewal = renewal
+            .renew_within(30)
+            .deploy_to(&target, directory.join("deployed.key"));
+        assert!(matches!(renewal.days_left(), Ok(Some(_))));
+        assert!(matches!(renewal.run(), Ok(Outcome::Current(_))));
+        assert_eq!(
+            std::fs::read_to_string(&target).map_err(FSError::from)?,
+            cert::cert_test::CERTIFICATE
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = std::fs::metadata(directory.join("deployed.key"))
+                .map_err(FSError::from)?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // A certificate that does not cover all domains is due.
+        let renewal = Renewal::new(Client::Lego, &["example.com", "example.org"], "a@b.c")
+            .directory(&directory);
+        assert_eq!(renewal.days_left(), Ok(None));
+        std::fs::remove_dir_all(&directory).map_err(FSError::from)?;
+        Ok(())
+    }
+}
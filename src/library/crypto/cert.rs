@@ -0,0 +1,355 @@
+//! This module contains functionality for inspecting X.509 certificates in PEM
+//! files and, with the `self-signed` feature, for generating self-signed
+//! certificates for development environments.
+
+use crate::fs::{
+    FSError,
+    File,
+    Object,
+};
+
+/// Describes possible errors when dealing with certificates.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum CertError {
+    #[error("The PEM data could not be read: {0}")]
+    InvalidPem(String),
+    #[error("The PEM data does not contain a certificate")]
+    NoCertificate,
+    #[error("The certificate could not be parsed: {0}")]
+    InvalidCertificate(String),
+    #[cfg(feature = "self-signed")]
+    #[error("The certificate could not be generated: {0}")]
+    Generation(String),
+    #[error("Accessing the certificate file failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`CertError`].
+pub type CertResult<T> = Result<T, CertError>;
+
+/// The properties of a certificate that rotation and monitoring scripts need.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Certificate {
+    /// The distinguished name of the subject, e.g. `CN=example.com`
+    pub subject:           String,
+    /// The distinguished name of the issuer
+    pub issuer:            String,
+    /// The serial number as colon-separated hex bytes
+    pub serial:            String,
+    /// DNS names, IP addresses, email addresses and URIs the certificate is valid
+    /// for
+    pub subject_alt_names: Vec<String>,
+    /// The certificate is not valid before this point in time
+    pub not_before:        std::time::SystemTime,
+    /// The certificate is not valid after this point in time
+    pub not_after:         std::time::SystemTime,
+    /// Whether the certificate belongs to a certificate authority
+    pub is_ca:             bool,
+}
+
+/// Convert a Unix timestamp, which may lie before the epoch, to a point in time.
+fn system_time(timestamp: i64) -> std::time::SystemTime {
+    let offset = std::time::Duration::from_secs(timestamp.unsigned_abs());
+    if timestamp < 0 {
+        std::time::UNIX_EPOCH - offset
+    } else {
+        std::time::UNIX_EPOCH + offset
+    }
+}
+
+/// Render a subject alternative name the way `openssl` does, without the type
+/// prefix. Names of other types are skipped.
+fn general_name(name: &x509_parser::extensions::GeneralName) -> Option<String> {
+    use x509_parser::extensions::GeneralName;
+
+    match name {
+        GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => {
+            Some((*name).to_string())
+        },
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 => <[u8; 4]>::try_from(*bytes)
+                .ok()
+                .map(|octets| std::net::Ipv4Addr::from(octets).to_string()),
+            16 => <[u8; 16]>::try_from(*bytes)
+                .ok()
+                .map(|octets| std::net::Ipv6Addr::from(octets).to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Certificate {
+    /// Parse all certificates in `pem`, in the order they appear (usually the leaf
+    /// first, followed by its chain).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid PEM, a certificate cannot be parsed
+    /// or if there is no certificate at all.
+    pub fn all_from_pem(pem: &str) -> CertResult<Vec<Self>> {
+        let mut certificates = Vec::new();
+        for block in x509_parser::pem::Pem::iter_from_buffer(pem.as_bytes()) {
+            let block = block.map_err(|error| CertError::InvalidPem(error.to_string()))?;
+            if block.label != "CERTIFICATE" {
+                continue;
+            }
+            let certificate = block
+                .parse_x509()
+                .map_err(|error| CertError::InvalidCertificate(error.to_string()))?;
+            certificates.push(Self::from_x509(&certificate)?);
+        }
+
+        if certificates.is_empty() {
+            return Err(CertError::NoCertificate);
+        }
+        Ok(certificates)
+    }
+
+    /// Parse the first certificate in `pem`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid PEM, the certificate cannot be
+    /// parsed or if there is no certificate at all.
+    pub fn from_pem(pem: &str) -> CertResult<Self> {
+        Self::all_from_pem(pem)?
+            .into_iter()
+            .next()
+            .ok_or(CertError::NoCertificate)
+    }
+
+    /// Extract the interesting properties of a parsed certificate.
+    fn from_x509(certificate: &x509_parser::certificate::X509Certificate) -> CertResult<Self> {
+        let subject_alt_names = certificate
+            .subject_alternative_name()
+            .map_err(|error| CertError::InvalidCertificate(error.to_string()))?
+            .map(|extension| {
+                extension
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(general_name)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            serial: certificate.raw_serial_as_string(),
+            subject_alt_names,
+            not_before: system_time(certificate.validity().not_before.timestamp()),
+            not_after: system_time(certificate.validity().not_after.timestamp()),
+            is_ca: certificate.is_ca(),
+        })
+    }
+
+    /// The number of whole days until the certificate expires. This is negative if
+    /// it has already expired.
+    #[must_use]
+    pub fn days_until_expiry(&self) -> i64 {
+        /// The number of seconds in a day
+        const DAY: i64 = 24 * 60 * 60;
+
+        let now = std::time::SystemTime::now();
+        let seconds = match self.not_after.duration_since(now) {
+            Ok(remaining) => i64::try_from(remaining.as_secs()).unwrap_or(i64::MAX),
+            Err(error) => -i64::try_from(error.duration().as_secs()).unwrap_or(i64::MAX),
+        };
+        seconds.div_euclid(DAY)
+    }
+
+    /// Whether the certificate is valid right now.
+    #[must_use]
+    pub fn is_valid_now(&self) -> bool {
+        let now = std::time::SystemTime::now();
+        self.not_before <= now && now <= self.not_after
+    }
+}
+
+/// Read the first certificate in the PEM file at `pem_file`, which is the leaf
+/// certificate in a chain file.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or does not contain a valid
+/// certificate.
+pub fn inspect(pem_file: impl AsRef<std::path::Path>) -> CertResult<Certificate> {
+    log::trace!("Inspecting certificate '{}'", pem_file.as_ref().display());
+    Certificate::from_pem(&File::new(pem_file).read()?)
+}
+
+/// A freshly generated certificate and its private key, both PEM-encoded.
+#[cfg(feature = "self-signed")]
+#[derive(Clone, PartialEq, Eq)]
+pub struct SelfSigned {
+    /// The certificate
+    pub certificate: String,
+    /// The private key (ECDSA P-256, PKCS #8)
+    pub private_key: String,
+}
+
+#[cfg(feature = "self-signed")]
+impl std::fmt::Debug for SelfSigned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SelfSigned")
+            .field("certificate", &self.certificate)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "self-signed")]
+impl SelfSigned {
+    /// Generate a certificate for `names` (DNS names or IP addresses) that is valid
+    /// from today on for `days` days. The first name is also used as common name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `names` is empty or contains an invalid name.
+    pub fn generate(names: &[&str], days: u32) -> CertResult<Self> {
+        use crate::library::time::DateTime;
+
+        let to_error = |error: rcgen::Error| CertError::Generation(error.to_string());
+        let date = |time: std::time::SystemTime| {
+            let date = DateTime::from_system_time(time);
+            rcgen::date_time_ymd(
+                i32::try_from(date.year).unwrap_or(i32::MAX),
+                u8::try_from(date.month).unwrap_or(1),
+                u8::try_from(date.day).unwrap_or(1),
+            )
+        };
+
+        let common_name = names
+            .first()
+            .ok_or_else(|| CertError::Generation(String::from("no names were given")))?;
+        log::debug!("Generating self-signed certificate for '{common_name}'");
+
+        let mut parameters = rcgen::CertificateParams::new(
+            names.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        )
+        .map_err(to_error)?;
+        parameters
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, *common_name);
+        let now = std::time::SystemTime::now();
+        parameters.not_before = date(now);
+        parameters.not_after =
+            date(now + std::time::Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+
+        let key_pair = rcgen::KeyPair::generate().map_err(to_error)?;
+        let certificate = parameters.self_signed(&key_pair).map_err(to_error)?;
+        Ok(Self {
+            certificate: certificate.pem(),
+            private_key: key_pair.serialize_pem(),
+        })
+    }
+
+    /// Write the certificate to `certificate_file` and the private key to
+    /// `key_file`. The key file is only readable by its owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files cannot be written.
+    #[cfg(unix)]
+    pub fn write(
+        &self,
+        certificate_file: impl AsRef<std::path::Path>,
+        key_file: impl AsRef<std::path::Path>,
+    ) -> CertResult<()> {
+        use std::{
+            io::Write,
+            os::unix::fs::{
+                OpenOptionsExt,
+                PermissionsExt,
+            },
+        };
+
+        std::fs::write(certificate_file, &self.certificate).map_err(FSError::from)?;
+
+        // The key must never be readable by others, not even for a moment.
+        let mut key = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(key_file.as_ref())
+            .map_err(FSError::from)?;
+        key.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .map_err(FSError::from)?;
+        key.write_all(self.private_key.as_bytes())
+            .map_err(FSError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(super) mod cert_test {
+    use super::*;
+
+    /// A self-signed certificate for `example.com` and `127.0.0.1`, valid from
+    /// 2024-01-01 to 2034-01-01.
+    pub(in crate::library::crypto) const CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----
+MIIBoDCCAUWgAwIBAgIUWoNpHtr+fyFT8IJYOuefjHc3GrswCgYIKoZIzj0EAwIw
+FjEUMBIGA1UEAwwLZXhhbXBsZS5jb20wHhcNMjQwMTAxMDAwMDAwWhcNMzQwMTAx
+MDAwMDAwWjAWMRQwEgYDVQQDDAtleGFtcGxlLmNvbTBZMBMGByqGSM49AgEGCCqG
+SM49AwEHA0IABOaZWKitl1z6zvJnXOd0f9o2/qW1HLDyIaOulrHtEsOM/Ul1tib2
+m/+wKJCviYo11rBwLNCrUFHnPPg6cU6dWmajcTBvMB0GA1UdDgQWBBSjuwDZiDon
+SM8Lvwtbi6wyM8VNYjAfBgNVHSMEGDAWgBSjuwDZiDonSM8Lvwtbi6wyM8VNYjAP
+BgNVHRMBAf8EBTADAQH/MBwGA1UdEQQVMBOCC2V4YW1wbGUuY29thwR/AAABMAoG
+CCqGSM49BAMCA0kAMEYCIQCWbyJ9Qe7RIX+/SXMcBG+T7UjJA9w8ReIoJ8OZeyGJ
+qQIhANL1l8hP7GbYtIlhs6/gNyNgheuhFFzn8CJU/sz3z7b2
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn inspect_pem() -> CertResult<()> {
+        let certificate = Certificate::from_pem(CERTIFICATE)?;
+        assert_eq!(certificate.subject, "CN=example.com");
+        assert_eq!(certificate.issuer, "CN=example.com");
+        assert_eq!(certificate.subject_alt_names, ["example.com", "127.0.0.1"]);
+        assert_eq!(certificate.not_before, system_time(1_704_067_200));
+        assert_eq!(certificate.not_after, system_time(2_019_686_400));
+        assert!(certificate.is_ca);
+
+        let mut expired = certificate;
+        expired.not_after = std::time::SystemTime::now() - std::time::Duration::from_secs(90);
+        assert_eq!(expired.days_until_expiry(), -1);
+        assert!(!expired.is_valid_now());
+
+        assert_eq!(
+            Certificate::from_pem("no certificate here"),
+            Err(CertError::NoCertificate)
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "self-signed")]
+    #[test]
+    fn generate_and_inspect() -> CertResult<()> {
+        let generated = SelfSigned::generate(&["localhost", "127.0.0.1"], 30)?;
+        assert!(!format!("{generated:?}").contains("PRIVATE KEY"));
+
+        let directory = crate::fs::TempDir::create()?;
+        let (certificate_file, key_file) = (
+            directory.path().join("cert.pem"),
+            directory.path().join("key.pem"),
+        );
+        generated.write(&certificate_file, &key_file)?;
+        let certificate = inspect(&certificate_file)?;
+        assert_eq!(certificate.subject, "CN=localhost");
+        assert_eq!(certificate.subject_alt_names, ["localhost", "127.0.0.1"]);
+        assert!(certificate.is_valid_now());
+        assert!((28..=30).contains(&certificate.days_until_expiry()));
+
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key_file)
+                .map_err(FSError::from)?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! This module contains functionality for encrypting and decrypting data in the
+//! [age](https://age-encryption.org/v1) format.
+//!
+//! Data is encrypted either with a passphrase or with an X25519 key. Files are
+//! usually encrypted with [`crate::fs::File::encrypt_to`]. Encrypted files can be
+//! decrypted with the `age` command line tool and vice versa.
+
+use crate::fs::FSError;
+
+/// Describes possible errors when encrypting or decrypting.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum EncryptionError {
+    #[error("The key is not valid: {0}")]
+    InvalidKey(String),
+    #[error("Encrypting failed: {0}")]
+    Encryption(String),
+    #[error("Decrypting failed: {0}")]
+    Decryption(String),
+    #[error("Accessing a file failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`EncryptionError`].
+pub type EncryptionResult<T> = Result<T, EncryptionError>;
+
+/// The secret data is encrypted with or decrypted with.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A passphrase, from which the actual key is derived with scrypt. This takes
+    /// about a second on purpose, so it is meant for passphrases chosen by humans.
+    Passphrase(String),
+    /// An X25519 key in age's encoding. For encrypting, this may be the public key
+    /// (`age1...`) or the secret key (`AGE-SECRET-KEY-1...`); decrypting needs the
+    /// secret key.
+    X25519(String),
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+            // Public keys are not secret and help to tell keys apart.
+            Self::X25519(key) if key.starts_with("age1") => {
+                f.debug_tuple("X25519").field(key).finish()
+            },
+            Self::X25519(_) => f.write_str("X25519(..)"),
+        }
+    }
+}
+
+impl Key {
+    /// Generate a new X25519 secret key.
+    #[must_use]
+    pub fn generate() -> Self {
+        use age::secrecy::ExposeSecret;
+
+        Self::X25519(
+            age::x25519::Identity::generate()
+                .to_string()
+                .expose_secret()
+                .to_string(),
+        )
+    }
+
+    /// The public key (`age1...`) that belongs to this X25519 key. Data encrypted to
+    /// the public key can only be decrypted with the secret key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this is a passphrase or not a valid key.
+    pub fn public_key(&self) -> EncryptionResult<String> {
+        match self {
+            Self::Passphrase(_) => Err(EncryptionError::InvalidKey(String::from(
+                "a passphrase has no public key",
+            ))),
+            Self::X25519(key) if key.starts_with("age1") => Ok(key.clone()),
+            Self::X25519(_) => Ok(self.identity()?.to_public().to_string()),
+        }
+    }
+
+    /// Parse an X25519 secret key.
+    fn identity(&self) -> EncryptionResult<age::x25519::Identity> {
+        match self {
+            Self::X25519(key) if !key.starts_with("age1") => key
+                .trim()
+                .parse()
+                .map_err(|error: &str| EncryptionError::InvalidKey(error.to_string())),
+            _ => Err(EncryptionError::InvalidKey(String::from(
+                "decrypting requires a passphrase or a secret key",
+            ))),
+        }
+    }
+
+    /// The encryptor that encrypts to this key.
+    fn encryptor(&self) -> EncryptionResult<age::Encryptor> {
+        match self {
+            Self::Passphrase(passphrase) => Ok(age::Encryptor::with_user_passphrase(
+                passphrase.as_str().into(),
+            )),
+            Self::X25519(_) => {
+                let public_key = self.public_key()?;
+                log::trace!("Encrypting to public key '{public_key}'");
+                let recipient: age::x25519::Recipient = public_key
+                    .parse()
+                    .map_err(|error: &str| EncryptionError::InvalidKey(error.to_string()))?;
+                age::Encryptor::with_recipients(std::iter::once(&recipient as _))
+                    .map_err(|error| EncryptionError::Encryption(error.to_string()))
+            },
+        }
+    }
+}
+
+/// Encrypt everything `input` yields to `key` and write the result to `output`.
+///
+/// # Errors
+///
+/// Returns an error if the key is invalid or if reading or writing fails.
+pub fn encrypt(
+    input: &mut impl std::io::Read,
+    output: impl std::io::Write,
+    key: &Key,
+) -> EncryptionResult<()> {
+    let to_error = |error: std::io::Error| EncryptionError::Encryption(error.to_string());
+
+    let mut writer = key.encryptor()?.wrap_output(output).map_err(to_error)?;
+    std::io::copy(input, &mut writer).map_err(to_error)?;
+    // Without this, the last chunk is missing and the output cannot be decrypted.
+    writer.finish().map_err(to_error)?;
+    Ok(())
+}
+
+/// Decrypt everything `input` yields with `key` and write the plaintext to `output`.
+///
+/// The plaintext is authenticated chunk by chunk. If the input was tampered with,
+/// an error is returned, but the chunks before the tampered one have already been
+/// written to `output`.
+///
+/// # Errors
+///
+/// Returns an error if the key is invalid or wrong, if the input is not in the age
+/// format or was tampered with, or if reading or writing fails.
+pub fn decrypt(
+    input: impl std::io::Read,
+    output: &mut impl std::io::Write,
+    key: &Key,
+) -> EncryptionResult<()> {
+    let to_error = |error: &dyn std::fmt::Display| EncryptionError::Decryption(error.to_string());
+
+    let decryptor = age::Decryptor::new(input).map_err(|error| to_error(&error))?;
+    let mut reader = match key {
+        Key::Passphrase(passphrase) => {
+            if !decryptor.is_scrypt() {
+                return Err(EncryptionError::Decryption(String::from(
+                    "the data is not encrypted with a passphrase",
+                )));
+            }
+            let identity = age::scrypt::Identity::new(passphrase.as_str().into());
+            decryptor.decrypt(std::iter::once(&identity as _))
+        },
+        Key::X25519(_) => {
+            let identity = key.identity()?;
+            decryptor.decrypt(std::iter::once(&identity as _))
+        },
+    }
+    .map_err(|error| to_error(&error))?;
+
+    std::io::copy(&mut reader, output).map_err(|error| to_error(&error))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod encryption_test {
+    use super::*;
+
+    #[test]
+    fn keys() -> EncryptionResult<()> {
+        let key = Key::generate();
+        let public_key = key.public_key()?;
+        assert!(public_key.starts_with("age1"));
+        assert_eq!(Key::X25519(public_key.clone()).public_key()?, public_key);
+
+        let debug = format!("{key:?}");
+        assert!(!debug.contains("AGE-SECRET-KEY"));
+        assert!(format!("{:?}", Key::X25519(public_key.clone())).contains(&public_key));
+        assert!(!format!("{:?}", Key::Passphrase(String::from("hunter2"))).contains("hunter2"));
+
+        let mut ciphertext = Vec::new();
+        encrypt(
+            &mut &b"Top secret"[..],
+            &mut ciphertext,
+            &Key::X25519(public_key.clone()),
+        )?;
+        let mut plaintext = Vec::new();
+        decrypt(ciphertext.as_slice(), &mut plaintext, &key)?;
+        assert_eq!(plaintext, b"Top secret");
+
+        // Neither another key nor the public key can decrypt.
+        assert!(decrypt(ciphertext.as_slice(), &mut Vec::new(), &Key::generate()).is_err());
+        assert!(matches!(
+            decrypt(
+                ciphertext.as_slice(),
+                &mut Vec::new(),
+                &Key::X25519(public_key)
+            ),
+            Err(EncryptionError::InvalidKey(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+//! This module contains functionality for dealing with certificates, keys and other
+//! cryptographic material.
+
+#[cfg(not(target_os = "wasi"))]
+pub mod acme;
+pub mod cert;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod random;
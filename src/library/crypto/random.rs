@@ -0,0 +1,289 @@
+//! This module contains functionality for generating secrets like tokens, passwords
+//! and UUIDs. All randomness comes from the operating system's CSPRNG.
+
+/// Lower-case letters allowed in passwords.
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+/// Upper-case letters allowed in passwords.
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// Digits allowed in passwords.
+const DIGITS: &str = "0123456789";
+/// Symbols allowed in passwords. Quotes, backslashes, backticks and spaces are left
+/// out so that passwords can be pasted into shells and configuration files as they
+/// are.
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+/// Characters that are easily confused with each other when read.
+const AMBIGUOUS: &str = "Il1O0o|";
+
+/// Describes possible errors when generating secrets.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum RandomError {
+    #[error("The operating system's random number generator failed: {0}")]
+    Unavailable(String),
+    #[error("The password policy cannot be satisfied: {0}")]
+    InvalidPolicy(String),
+}
+
+/// A [`Result`] whose error variant is a [`RandomError`].
+pub type RandomResult<T> = Result<T, RandomError>;
+
+/// A class of characters a password can consist of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digits,
+    Symbols,
+}
+
+impl CharacterClass {
+    /// All characters of this class.
+    const fn characters(self) -> &'static str {
+        match self {
+            Self::Lowercase => LOWERCASE,
+            Self::Uppercase => UPPERCASE,
+            Self::Digits => DIGITS,
+            Self::Symbols => SYMBOLS,
+        }
+    }
+}
+
+/// Describes which characters a password generated by [`password`] consists of. At
+/// least one character of every enabled class is used. By default, all classes are
+/// enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PasswordPolicy {
+    /// The classes characters are taken from
+    classes:         Vec<CharacterClass>,
+    /// Leave out characters that are easily confused, like `l` and `1`
+    avoid_ambiguous: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            classes:         vec![
+                CharacterClass::Lowercase,
+                CharacterClass::Uppercase,
+                CharacterClass::Digits,
+                CharacterClass::Symbols,
+            ],
+            avoid_ambiguous: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Create a policy that uses all character classes.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Do not use characters of `class`.
+    #[must_use]
+    pub fn without(mut self, class: CharacterClass) -> Self {
+        self.classes.retain(|enabled| *enabled != class);
+        self
+    }
+
+    /// Whether to leave out characters that are easily confused when read, which is
+    /// useful for passwords that are typed in by hand.
+    #[must_use]
+    pub const fn avoid_ambiguous(mut self, enabled: bool) -> Self {
+        self.avoid_ambiguous = enabled;
+        self
+    }
+
+    /// The characters of every enabled class.
+    fn classes(&self) -> Vec<Vec<char>> {
+        self.classes
+            .iter()
+            .map(|class| {
+                class
+                    .characters()
+                    .chars()
+                    .filter(|character| !(self.avoid_ambiguous && AMBIGUOUS.contains(*character)))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Fill `buffer` with random bytes.
+///
+/// # Errors
+///
+/// Returns an error if the operating system's random number generator fails.
+pub fn fill(buffer: &mut [u8]) -> RandomResult<()> {
+    getrandom::getrandom(buffer).map_err(|error| RandomError::Unavailable(error.to_string()))
+}
+
+/// Return `length` random bytes.
+///
+/// # Errors
+///
+/// Returns an error if the operating system's random number generator fails.
+pub fn bytes(length: usize) -> RandomResult<Vec<u8>> {
+    let mut buffer = vec![0; length];
+    fill(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Return a uniformly distributed random number in `0..bound`. `bound` must not be
+/// zero.
+pub(crate) fn below(bound: usize) -> RandomResult<usize> {
+    let bound = u64::try_from(bound).unwrap_or(u64::MAX);
+    // Values at or above the largest multiple of `bound` would make small results
+    // more likely, so they are drawn again.
+    let limit = u64::MAX - u64::MAX % bound;
+    loop {
+        let mut buffer = [0; 8];
+        fill(&mut buffer)?;
+        let value = u64::from_le_bytes(buffer);
+        if value < limit {
+            return Ok(usize::try_from(value % bound).unwrap_or_default());
+        }
+    }
+}
+
+/// Encode `bytes` as lower-case hex.
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Return a token of `length` random bytes, hex-encoded (so the token is twice as
+/// long as `length`). This is the equivalent of `openssl rand -hex <length>`.
+///
+/// # Errors
+///
+/// Returns an error if the operating system's random number generator fails.
+pub fn token_hex(length: usize) -> RandomResult<String> { Ok(hex(&bytes(length)?)) }
+
+/// Generate a password of `length` characters according to `policy`.
+///
+/// # Errors
+///
+/// Returns an error if the policy enables no character class, if `length` is too
+/// short to use every enabled class or if the operating system's random number
+/// generator fails.
+pub fn password(length: usize, policy: &PasswordPolicy) -> RandomResult<String> {
+    let classes = policy.classes();
+    if classes.is_empty() {
+        return Err(RandomError::InvalidPolicy(String::from(
+            "no character class is enabled",
+        )));
+    }
+    if length < classes.len() {
+        return Err(RandomError::InvalidPolicy(format!(
+            "{} character classes do not fit into {length} characters",
+            classes.len()
+        )));
+    }
+
+    // One character of every class first, the rest from all classes combined.
+    let mut password = Vec::with_capacity(length);
+    for class in &classes {
+        password.push(class[below(class.len())?]);
+    }
+    let all: Vec<char> = classes.concat();
+    while password.len() < length {
+        password.push(all[below(all.len())?]);
+    }
+
+    // Shuffle (Fisher-Yates) so that the guaranteed characters are not always at
+    // the front.
+    for index in (1..password.len()).rev() {
+        password.swap(index, below(index + 1)?);
+    }
+    Ok(password.into_iter().collect())
+}
+
+/// Generate a random (version 4) UUID in its hyphenated form, e.g.
+/// `3f2b8c1e-9a4d-4e7f-b6a1-0c5d2e8f9a3b`.
+///
+/// # Errors
+///
+/// Returns an error if the operating system's random number generator fails.
+pub fn uuid() -> RandomResult<String> {
+    let mut bytes = [0; 16];
+    fill(&mut bytes)?;
+    // Set the version (4) and the variant (RFC 4122).
+    bytes[6] = (bytes[6] & 0x0F) | 0x40;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+
+    let hex = hex(&bytes);
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+#[cfg(test)]
+mod random_test {
+    use super::*;
+
+    #[test]
+    fn tokens_and_uuids() -> RandomResult<()> {
+        let token = token_hex(16)?;
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|character| character.is_ascii_hexdigit()));
+        assert_ne!(token, token_hex(16)?);
+
+        let uuid = uuid()?;
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(&uuid[14..15], "4");
+        assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+        Ok(())
+    }
+
+    #[test]
+    fn passwords() -> RandomResult<()> {
+        for _ in 0..100 {
+            let password = password(8, &PasswordPolicy::new())?;
+            assert_eq!(password.chars().count(), 8);
+            assert!(password
+                .chars()
+                .any(|character| character.is_ascii_lowercase()));
+            assert!(password
+                .chars()
+                .any(|character| character.is_ascii_uppercase()));
+            assert!(password.chars().any(|character| character.is_ascii_digit()));
+            assert!(password
+                .chars()
+                .any(|character| SYMBOLS.contains(character)));
+        }
+
+        let policy = PasswordPolicy::new()
+            .without(CharacterClass::Symbols)
+            .avoid_ambiguous(true);
+        let password = password(200, &policy)?;
+        assert!(password
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() && !AMBIGUOUS.contains(character)));
+
+        assert!(matches!(
+            super::password(3, &PasswordPolicy::new()),
+            Err(RandomError::InvalidPolicy(_))
+        ));
+        let nothing = PasswordPolicy::new()
+            .without(CharacterClass::Lowercase)
+            .without(CharacterClass::Uppercase)
+            .without(CharacterClass::Digits)
+            .without(CharacterClass::Symbols);
+        assert!(matches!(
+            super::password(8, &nothing),
+            Err(RandomError::InvalidPolicy(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! This module contains deadlines, which give a whole section of a script a single
+//! time budget.
+//!
+//! A [`Deadline`] installed on a thread limits everything that waits there: running
+//! programs, downloads from forges, waiting for locks and queued jobs, [`sleep`] and
+//! [`retry`]. Workers started with [`crate::scope`] inherit it. Once the deadline
+//! has passed, these operations fail with their module's `DeadlineExceeded` error,
+//! which wraps [`DeadlineExceeded`]; a program that is still running is killed.
+//!
+//! ```
+//! use rush::{
+//!     deadline::Deadline,
+//!     process::{Command, ProcessError},
+//! };
+//!
+//! let _deadline = Deadline::after(std::time::Duration::from_millis(100)).install();
+//! assert!(matches!(
+//!     Command::new("sleep").arg("5").output(),
+//!     Err(ProcessError::DeadlineExceeded(_))
+//! ));
+//! ```
+
+/// The error of an operation that did not finish before the [`Deadline`].
+#[derive(Debug, Clone, Copy, thiserror::Error, PartialEq, Eq, Hash)]
+#[error("The deadline has passed")]
+pub struct DeadlineExceeded;
+
+/// A point in time by which operations have to be finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline {
+    /// When the time budget is used up
+    instant: std::time::Instant,
+}
+
+impl Deadline {
+    /// A deadline `budget` from now.
+    #[must_use]
+    pub fn after(budget: std::time::Duration) -> Self {
+        let now = std::time::Instant::now();
+        // Budgets too large to represent are as good as no deadline at all.
+        let far_future = || now + std::time::Duration::from_secs(u64::from(u32::MAX));
+        Self {
+            instant: now.checked_add(budget).unwrap_or_else(far_future),
+        }
+    }
+
+    /// A deadline at `instant`.
+    #[must_use]
+    pub const fn at(instant: std::time::Instant) -> Self { Self { instant } }
+
+    /// When the time budget is used up.
+    #[must_use]
+    pub const fn instant(&self) -> std::time::Instant { self.instant }
+
+    /// The time left until the deadline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the deadline has passed.
+    pub fn remaining(&self) -> Result<std::time::Duration, DeadlineExceeded> {
+        let remaining = self
+            .instant
+            .saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            Err(DeadlineExceeded)
+        } else {
+            Ok(remaining)
+        }
+    }
+
+    /// Put this deadline in effect on this thread. If a deadline is already in effect,
+    /// the earlier of both applies. The previous deadline is restored when the
+    /// returned guard is dropped.
+    pub fn install(self) -> InstalledDeadline {
+        let previous = THREAD.get();
+        THREAD.set(Some(previous.map_or(self, |previous| previous.min(self))));
+        InstalledDeadline { previous }
+    }
+}
+
+thread_local! {
+    /// The deadline in effect on this thread, if any.
+    static THREAD: std::cell::Cell<Option<Deadline>> = const { std::cell::Cell::new(None) };
+}
+
+/// A deadline installed by [`Deadline::install`]; dropping it restores the deadline
+/// in effect before.
+#[derive(Debug)]
+#[must_use = "the deadline is lifted when the guard is dropped"]
+pub struct InstalledDeadline {
+    /// The deadline in effect on this thread before, if any
+    previous: Option<Deadline>,
+}
+
+impl Drop for InstalledDeadline {
+    fn drop(&mut self) { THREAD.set(self.previous); }
+}
+
+/// The deadline in effect on this thread, if any.
+#[must_use]
+pub fn current() -> Option<Deadline> { THREAD.get() }
+
+/// Fail if the deadline in effect on this thread has passed.
+///
+/// # Errors
+///
+/// Returns an error if the deadline has passed.
+pub fn check() -> Result<(), DeadlineExceeded> {
+    current().map_or(Ok(()), |deadline| deadline.remaining().map(drop))
+}
+
+/// Shorten `timeout` to the time left until the deadline in effect on this thread.
+/// Without a deadline and `timeout`, there is no limit.
+///
+/// # Errors
+///
+/// Returns an error if the deadline has passed.
+pub(crate) fn limit(
+    timeout: Option<std::time::Duration>,
+) -> Result<Option<std::time::Duration>, DeadlineExceeded> {
+    let Some(deadline) = current() else {
+        return Ok(timeout);
+    };
+    let remaining = deadline.remaining()?;
+    Ok(Some(
+        timeout.map_or(remaining, |timeout| timeout.min(remaining)),
+    ))
+}
+
+/// Sleep for `duration`, but not past the deadline in effect on this thread.
+///
+/// # Errors
+///
+/// Returns an error if the deadline passes before `duration` is over.
+pub fn sleep(duration: std::time::Duration) -> Result<(), DeadlineExceeded> {
+    let Some(remaining) = limit(None)? else {
+        std::thread::sleep(duration);
+        return Ok(());
+    };
+    if duration < remaining {
+        std::thread::sleep(duration);
+        Ok(())
+    } else {
+        std::thread::sleep(remaining);
+        Err(DeadlineExceeded)
+    }
+}
+
+/// Run `attempt` up to `attempts` times, sleeping `delay` in between, until it
+/// succeeds. Returns the first success or the last error.
+///
+/// # Errors
+///
+/// Returns the error of the last attempt, or [`DeadlineExceeded`] (converted into
+/// `E`) if the deadline in effect on this thread passes before the next attempt.
+pub fn retry<T, E>(
+    attempts: usize,
+    delay: std::time::Duration,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: From<DeadlineExceeded> + std::fmt::Display,
+{
+    let mut number = 1;
+    loop {
+        check()?;
+        match attempt() {
+            Ok(result) => return Ok(result),
+            Err(error) if number >= attempts => return Err(error),
+            Err(error) => log::debug!("Attempt {number} of {attempts} failed: {error}"),
+        }
+        sleep(delay)?;
+        number += 1;
+    }
+}
+
+#[cfg(test)]
+mod deadline_test {
+    use super::*;
+
+    /// A short duration for the tests.
+    const SHORT: std::time::Duration = std::time::Duration::from_millis(50);
+
+    #[test]
+    fn install() {
+        assert_eq!(current(), None);
+        let outer = Deadline::after(SHORT * 100);
+        let guard = outer.install();
+        {
+            let _later = Deadline::after(SHORT * 200).install();
+            assert_eq!(current(), Some(outer));
+            let inner = Deadline::after(SHORT);
+            let _earlier = inner.install();
+            assert_eq!(current(), Some(inner));
+        }
+        assert_eq!(current(), Some(outer));
+        drop(guard);
+        assert_eq!(current(), None);
+    }
+
+    #[test]
+    fn sleep_and_limit() {
+        assert_eq!(limit(Some(SHORT)), Ok(Some(SHORT)));
+        let _deadline = Deadline::after(SHORT * 2).install();
+        assert!(limit(None).unwrap().unwrap() <= SHORT * 2);
+        assert_eq!(limit(Some(SHORT / 2)), Ok(Some(SHORT / 2)));
+        assert_eq!(sleep(SHORT), Ok(()));
+        assert_eq!(sleep(SHORT * 10), Err(DeadlineExceeded));
+        assert_eq!(check(), Err(DeadlineExceeded));
+    }
+
+    /// The error of an attempt in the tests.
+    #[derive(Debug, thiserror::Error, PartialEq, Eq)]
+    enum AttemptError {
+        #[error("The attempt failed")]
+        Failed,
+        #[error(transparent)]
+        DeadlineExceeded(#[from] DeadlineExceeded),
+    }
+
+    #[test]
+    fn retries() {
+        let mut calls = 0;
+        let result = retry(3, std::time::Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 {
+                Err(AttemptError::Failed)
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(3));
+
+        let result: Result<(), _> =
+            retry(2, std::time::Duration::ZERO, || Err(AttemptError::Failed));
+        assert_eq!(result, Err(AttemptError::Failed));
+
+        let _deadline = Deadline::after(SHORT).install();
+        let result: Result<(), _> = retry(usize::MAX, SHORT / 5, || Err(AttemptError::Failed));
+        assert_eq!(
+            result,
+            Err(AttemptError::DeadlineExceeded(DeadlineExceeded))
+        );
+    }
+
+    #[test]
+    fn workers_inherit_the_deadline() {
+        let deadline = Deadline::after(SHORT * 100);
+        let _installed = deadline.install();
+        let inherited =
+            crate::scope(|scope| scope.spawn(|_| Ok::<_, DeadlineExceeded>(current())).join());
+        assert_eq!(inherited, Ok(Some(Some(deadline))));
+    }
+}
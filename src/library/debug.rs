@@ -0,0 +1,315 @@
+//! This module contains collecting what is needed to investigate a crash, as
+//! incident-response runbooks do.
+//!
+//! [`collect_crash_artifacts`] gathers the most recent core dump of a program or
+//! systemd unit (from `coredumpctl`), its recent journal lines, its unit file and
+//! status, and copies of configuration files into a single archive that can be
+//! attached to a ticket. The archive is a [support bundle](crate::diagnostics), so
+//! text in it is redacted and whatever cannot be collected (e.g. because there is
+//! no core dump) is listed in its manifest instead of failing the whole collection.
+//!
+//! ```no_run
+//! use rush::debug::{
+//!     collect_crash_artifacts_with,
+//!     CollectOptions,
+//!     Subject,
+//! };
+//!
+//! let artifacts = collect_crash_artifacts_with(
+//!     &Subject::unit("nginx.service"),
+//!     "/var/tmp/incidents",
+//!     &CollectOptions::new()
+//!         .journal_lines(5000)
+//!         .config("/etc/nginx/nginx.conf"),
+//! )
+//! .unwrap();
+//! println!("Attach {} to the ticket", artifacts.archive.display());
+//! for (artifact, reason) in &artifacts.missing {
+//!     println!("Could not collect {artifact}: {reason}");
+//! }
+//! ```
+
+use crate::{
+    diagnostics::{
+        Bundle,
+        DiagnosticsError,
+    },
+    fs::{
+        Directory,
+        FSError,
+        Object as _,
+        TempDir,
+    },
+    process::Command,
+};
+
+/// Describes possible errors when collecting crash artifacts.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum DebugError {
+    #[error("Accessing the artifacts failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Archiving the artifacts failed: {0}")]
+    Bundle(#[from] DiagnosticsError),
+}
+
+/// A [`Result`] whose error variant is a [`DebugError`].
+pub type DebugResult<T> = Result<T, DebugError>;
+
+/// How many journal lines are collected by default.
+const DEFAULT_JOURNAL_LINES: usize = 1000;
+
+/// What crashed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subject {
+    /// A systemd unit, e.g. `nginx.service`
+    Unit(String),
+    /// A program, by the name of its executable, e.g. `nginx`
+    Process(String),
+    /// A process, by its ID
+    Pid(u32),
+}
+
+impl Subject {
+    /// The systemd unit `name`, e.g. `nginx.service`.
+    pub fn unit(name: impl AsRef<str>) -> Self { Self::Unit(name.as_ref().to_string()) }
+
+    /// The program whose executable is called `name`, e.g. `nginx`.
+    pub fn process(name: impl AsRef<str>) -> Self { Self::Process(name.as_ref().to_string()) }
+
+    /// The argument selecting the journal lines of the subject.
+    fn journal_match(&self) -> String {
+        match self {
+            Self::Unit(unit) => format!("--unit={unit}"),
+            Self::Process(name) => format!("_COMM={name}"),
+            Self::Pid(pid) => format!("_PID={pid}"),
+        }
+    }
+
+    /// The argument selecting the core dumps of the subject.
+    fn coredump_match(&self) -> String {
+        match self {
+            Self::Unit(unit) => format!("COREDUMP_UNIT={unit}"),
+            Self::Process(name) => format!("COREDUMP_COMM={name}"),
+            Self::Pid(pid) => format!("COREDUMP_PID={pid}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Subject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unit(name) | Self::Process(name) => write!(f, "{name}"),
+            Self::Pid(pid) => write!(f, "{pid}"),
+        }
+    }
+}
+
+/// Describes what [`collect_crash_artifacts_with`] collects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CollectOptions {
+    /// How many of the most recent journal lines are collected
+    journal_lines: usize,
+    /// Whether the most recent core dump is collected
+    core_dump:     bool,
+    /// The configuration files that are copied
+    config_files:  Vec<std::path::PathBuf>,
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self { Self::new() }
+}
+
+impl CollectOptions {
+    /// Collect the 1000 most recent journal lines and the most recent core dump,
+    /// but no configuration files.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            journal_lines: DEFAULT_JOURNAL_LINES,
+            core_dump:     true,
+            config_files:  Vec::new(),
+        }
+    }
+
+    /// Collect the `lines` most recent journal lines.
+    #[must_use]
+    pub const fn journal_lines(mut self, lines: usize) -> Self {
+        self.journal_lines = lines;
+        self
+    }
+
+    /// Whether the most recent core dump is collected, which can be large. Its
+    /// metadata and backtrace are collected anyway.
+    #[must_use]
+    pub const fn core_dump(mut self, enabled: bool) -> Self {
+        self.core_dump = enabled;
+        self
+    }
+
+    /// Copy the configuration file `path` into the archive. Can be given multiple
+    /// times.
+    #[must_use]
+    pub fn config(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.config_files.push(path.as_ref().to_path_buf());
+        self
+    }
+}
+
+/// The outcome of [`collect_crash_artifacts_with`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CrashArtifacts {
+    /// The archive holding the artifacts
+    pub archive:   std::path::PathBuf,
+    /// The paths of the artifacts in the archive
+    pub collected: Vec<String>,
+    /// The artifacts that could not be collected, and why
+    pub missing:   Vec<(String, String)>,
+}
+
+/// Collect the artifacts of the crash of `subject` into an archive in
+/// `target_directory`, see [`collect_crash_artifacts_with`].
+///
+/// # Errors
+///
+/// Returns an error if the archive cannot be created.
+pub fn collect_crash_artifacts(
+    subject: &Subject,
+    target_directory: impl AsRef<std::path::Path>,
+) -> DebugResult<CrashArtifacts> {
+    collect_crash_artifacts_with(subject, target_directory, &CollectOptions::new())
+}
+
+/// Collect the artifacts of the crash of `subject` described by `options` into an
+/// archive in `target_directory`, named like
+/// `crash-nginx.service-20241006T120000Z.tar.gz`.
+///
+/// The archive contains `coredump.txt` (the metadata and backtrace from
+/// `coredumpctl info`), `core` (the core dump), `journal.log`, `unit.txt` and
+/// `status.txt` (for units), the configuration files below `config/` at their
+/// original paths, and `manifest.txt` listing what could not be collected.
+///
+/// # Errors
+///
+/// Returns an error if the archive cannot be created; artifacts that cannot be
+/// collected are listed in [`CrashArtifacts::missing`] instead.
+pub fn collect_crash_artifacts_with(
+    subject: &Subject,
+    target_directory: impl AsRef<std::path::Path>,
+    options: &CollectOptions,
+) -> DebugResult<CrashArtifacts> {
+    let target_directory = target_directory.as_ref();
+    log::info!("Collecting crash artifacts of {}", subject);
+    let mut bundle = Bundle::new(format!("crash-{subject}"))
+        .fact("subject", subject.to_string())
+        .command(
+            "coredump.txt",
+            Command::new("coredumpctl").args(["info", "--no-pager", &subject.coredump_match()]),
+        );
+
+    // `coredumpctl` writes the core dump itself, so it is dumped next to the archive
+    // first and included from there.
+    let dumps = if options.core_dump {
+        Directory::new(target_directory).create_on_fs_recursive()?;
+        let dumps = TempDir::create_in(target_directory)?;
+        let core = dumps.path().join("core");
+        let dumped = Command::new("coredumpctl")
+            .args(["dump", "--no-pager", "--output"])
+            .arg(core.to_string_lossy())
+            .arg(subject.coredump_match())
+            .run();
+        bundle = match dumped {
+            Ok(_) => bundle.file_as("core", core),
+            Err(error) => bundle.unavailable("core", error.to_string()),
+        };
+        Some(dumps)
+    } else {
+        None
+    };
+
+    bundle = bundle.command(
+        "journal.log",
+        Command::new("journalctl")
+            .args(["--no-pager", "--lines"])
+            .arg(options.journal_lines.to_string())
+            .arg(subject.journal_match()),
+    );
+    if let Subject::Unit(unit) = &subject {
+        bundle = bundle
+            .command("unit.txt", Command::new("systemctl").args(["cat", unit]))
+            .command(
+                "status.txt",
+                Command::new("systemctl").args(["status", "--no-pager", "--full", unit]),
+            );
+    }
+    for config in &options.config_files {
+        let relative = config.strip_prefix("/").unwrap_or(config);
+        let name = std::path::Path::new("config").join(relative);
+        bundle = bundle.file_as(name.to_string_lossy(), config);
+    }
+
+    let archive = bundle.build(target_directory)?;
+    drop(dumps);
+    Ok(CrashArtifacts {
+        archive:   archive.path,
+        collected: archive.collected,
+        missing:   archive.missing,
+    })
+}
+
+#[cfg(test)]
+mod debug_test {
+    use super::*;
+    use crate::fs::TempFile;
+
+    #[test]
+    fn subjects() {
+        assert_eq!(
+            Subject::unit("nginx.service").journal_match(),
+            "--unit=nginx.service"
+        );
+        assert_eq!(
+            Subject::process("nginx").coredump_match(),
+            "COREDUMP_COMM=nginx"
+        );
+        assert_eq!(Subject::Pid(42).journal_match(), "_PID=42");
+        assert_eq!(Subject::Pid(42).to_string(), "42");
+    }
+
+    #[test]
+    fn collect() -> DebugResult<()> {
+        let target = TempDir::create()?;
+        let config = TempFile::create()?;
+        config.overwrite("worker_processes 4;\n")?;
+        let options = CollectOptions::new()
+            .journal_lines(10)
+            .core_dump(false)
+            .config(config.path())
+            .config("/this/does/not/exist.conf");
+
+        let artifacts = collect_crash_artifacts_with(
+            &Subject::process("rush-test-crash"),
+            target.path(),
+            &options,
+        )?;
+        assert!(artifacts.archive.starts_with(target.path()));
+        let config_name = format!("config{}", config.path().display());
+        assert!(artifacts.collected.contains(&config_name));
+        assert!(artifacts.collected.contains(&String::from("manifest.txt")));
+        assert!(artifacts
+            .missing
+            .iter()
+            .any(|(name, _)| name == "config/this/does/not/exist.conf"));
+
+        let listing = Command::new("tar")
+            .args(["--list", "--gzip", "--file"])
+            .arg(artifacts.archive.to_string_lossy())
+            .run()
+            .map_err(DiagnosticsError::from)?
+            .stdout;
+        assert!(listing.contains(&format!("./{config_name}")));
+        assert!(listing.contains("./manifest.txt"));
+        // Only the archive is left behind.
+        assert_eq!(target.entries()?.count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+//! This module contains building support bundles: archives of the files, command
+//! outputs and facts needed to diagnose a problem on a host, which can be attached
+//! to a ticket.
+//!
+//! Scripts register what a [`Bundle`] includes, and [`Bundle::build`] gathers it
+//! into a compressed archive with a manifest. Text is redacted with
+//! [`crate::secrets::redact`] on the way, so that neither secrets the script
+//! retrieved nor values that look like secrets leave the host. Gathering is
+//! best-effort: whatever cannot be gathered is listed in the manifest instead of
+//! failing the whole bundle.
+//!
+//! ```no_run
+//! use rush::{
+//!     diagnostics::Bundle,
+//!     process::Command,
+//! };
+//!
+//! let archive = Bundle::new("support-web01")
+//!     .fact("ticket", "OPS-1234")
+//!     .file("/etc/nginx/nginx.conf")
+//!     .command("disks.txt", Command::new("df").arg("-h"))
+//!     .command("nginx.log", Command::new("journalctl").args(["-u", "nginx", "-n", "500"]))
+//!     .build("/var/tmp/support")
+//!     .unwrap();
+//! println!("Attach {} to the ticket", archive.path.display());
+//! ```
+
+use crate::{
+    fs::{
+        Directory,
+        FSError,
+        Object as _,
+        TempDir,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Describes possible errors when building a support bundle.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum DiagnosticsError {
+    #[error("Accessing the bundle failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Archiving the bundle failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`DiagnosticsError`].
+pub type DiagnosticsResult<T> = Result<T, DiagnosticsError>;
+
+/// The file in the archive listing what it contains and what is missing.
+const MANIFEST: &str = "manifest.txt";
+/// How many bytes at the start of a file are checked to tell text from binary.
+const BINARY_CHECK: usize = 8 * 1024;
+
+/// Where the content of an entry of a bundle comes from.
+#[derive(Debug, Clone)]
+enum Source {
+    /// A file, copied into the bundle
+    File(std::path::PathBuf),
+    /// The standard output of a command
+    Command(Command),
+    /// Nothing, because gathering it failed for the given reason
+    Unavailable(String),
+}
+
+/// What a support bundle includes, see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// The name of the bundle, which the archive is named after
+    name:    String,
+    /// The entries of the bundle by their path in the archive
+    entries: Vec<(String, Source)>,
+    /// The facts listed in the manifest
+    facts:   Vec<(String, String)>,
+    /// Whether text is redacted
+    redact:  bool,
+}
+
+/// The outcome of [`Bundle::build`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Archive {
+    /// The path of the archive
+    pub path:      std::path::PathBuf,
+    /// The paths of the entries in the archive
+    pub collected: Vec<String>,
+    /// The entries in which something was redacted
+    pub redacted:  Vec<String>,
+    /// The entries that could not be gathered, and why
+    pub missing:   Vec<(String, String)>,
+}
+
+impl Bundle {
+    /// An empty bundle called `name`, which redacts text.
+    pub fn new(name: impl AsRef<str>) -> Self {
+        Self {
+            name:    name.as_ref().to_string(),
+            entries: Vec::new(),
+            facts:   Vec::new(),
+            redact:  true,
+        }
+    }
+
+    /// Include a copy of the file at `path`, below `files/` at its original path.
+    #[must_use]
+    pub fn file(self, path: impl AsRef<std::path::Path>) -> Self {
+        let path = path.as_ref();
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        let name = std::path::Path::new("files").join(relative);
+        self.file_as(name.to_string_lossy(), path)
+    }
+
+    /// Include a copy of the file at `path` as `name` in the archive.
+    #[must_use]
+    pub fn file_as(mut self, name: impl AsRef<str>, path: impl AsRef<std::path::Path>) -> Self {
+        self.entries.push((
+            name.as_ref().to_string(),
+            Source::File(path.as_ref().to_path_buf()),
+        ));
+        self
+    }
+
+    /// Include the standard output of `command` as `name` in the archive. The
+    /// command is run when the bundle is built. Programs like `systemctl status`
+    /// exit with a non-zero exit code while printing what is needed, so their output
+    /// is included unless it is empty.
+    #[must_use]
+    pub fn command(mut self, name: impl AsRef<str>, command: Command) -> Self {
+        self.entries
+            .push((name.as_ref().to_string(), Source::Command(command)));
+        self
+    }
+
+    /// List `key` with `value` in the manifest, e.g. the ticket the bundle is for.
+    #[must_use]
+    pub fn fact(mut self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        self.facts
+            .push((key.as_ref().to_string(), value.as_ref().to_string()));
+        self
+    }
+
+    /// List `name` as missing in the manifest because of `reason`, for what a
+    /// script failed to gather itself.
+    #[must_use]
+    pub fn unavailable(mut self, name: impl AsRef<str>, reason: impl AsRef<str>) -> Self {
+        self.entries.push((
+            name.as_ref().to_string(),
+            Source::Unavailable(reason.as_ref().to_string()),
+        ));
+        self
+    }
+
+    /// Whether text (files, command outputs and facts) is redacted, which it is by
+    /// default. Binary files are never redacted.
+    #[must_use]
+    pub const fn redact(mut self, enabled: bool) -> Self {
+        self.redact = enabled;
+        self
+    }
+
+    /// Gather everything the bundle includes into an archive in `target_directory`,
+    /// named like `support-web01-20241006T120000Z.tar.gz`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive cannot be created; entries that cannot be
+    /// gathered are listed in [`Archive::missing`] instead.
+    pub fn build(
+        &self,
+        target_directory: impl AsRef<std::path::Path>,
+    ) -> DiagnosticsResult<Archive> {
+        let target_directory = target_directory.as_ref();
+        log::info!("Building support bundle '{}'", self.name);
+        Directory::new(target_directory).create_on_fs_recursive()?;
+        let staging = TempDir::create_in(target_directory)?;
+        let mut gatherer = Gatherer {
+            staging: staging.path(),
+            redact:  self.redact,
+            archive: Archive {
+                path:      std::path::PathBuf::new(),
+                collected: Vec::new(),
+                redacted:  Vec::new(),
+                missing:   Vec::new(),
+            },
+        };
+
+        for (name, source) in &self.entries {
+            match source {
+                Source::File(path) => gatherer.file(name, path),
+                Source::Command(command) => gatherer.command(name, command),
+                Source::Unavailable(reason) => {
+                    gatherer
+                        .archive
+                        .missing
+                        .push((name.clone(), reason.clone()));
+                },
+            }
+        }
+        let manifest = self.manifest(&gatherer.archive);
+        gatherer.store(MANIFEST, |path| std::fs::write(path, manifest));
+
+        let timestamp =
+            crate::library::time::DateTime::from_system_time(std::time::SystemTime::now())
+                .to_iso8601_basic();
+        let path = target_directory.join(format!(
+            "{}-{timestamp}.tar.gz",
+            crate::fs::sanitize_filename(&self.name)
+        ));
+        Command::new("tar")
+            .args(["--create", "--gzip", "--file"])
+            .arg(path.to_string_lossy())
+            .arg("--directory")
+            .arg(staging.path().to_string_lossy())
+            .arg(".")
+            .run()?;
+        log::info!("Built support bundle '{}'", path.display());
+        Ok(Archive {
+            path,
+            ..gatherer.archive
+        })
+    }
+
+    /// The manifest listing the facts, what was gathered and what was not.
+    fn manifest(&self, archive: &Archive) -> String {
+        use std::fmt::Write as _;
+        let redact = |text: &str| {
+            if self.redact {
+                crate::secrets::redact(text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut manifest = format!(
+            "Support bundle '{}', built at {}\n\nFacts:\n",
+            self.name,
+            crate::library::time::DateTime::from_system_time(std::time::SystemTime::now())
+                .to_rfc3339()
+        );
+        for (key, value) in &self.facts {
+            let _ = writeln!(manifest, "  {key}: {}", redact(value));
+        }
+        manifest.push_str("\nCollected:\n");
+        for name in &archive.collected {
+            let note = if archive.redacted.contains(name) {
+                " (redacted)"
+            } else {
+                ""
+            };
+            let _ = writeln!(manifest, "  {name}{note}");
+        }
+        manifest.push_str("\nMissing:\n");
+        for (name, reason) in &archive.missing {
+            let _ = writeln!(manifest, "  {name}: {}", redact(reason));
+        }
+        manifest
+    }
+}
+
+/// Gathers the entries of a bundle into a staging directory.
+struct Gatherer<'a> {
+    /// The directory the entries are gathered in
+    staging: &'a std::path::Path,
+    /// See [`Bundle::redact`]
+    redact:  bool,
+    /// What was gathered so far
+    archive: Archive,
+}
+
+impl Gatherer<'_> {
+    /// Redact `text` if enabled, recording `name` if something was redacted.
+    fn redact(&mut self, name: &str, text: String) -> String {
+        if !self.redact {
+            return text;
+        }
+        let redacted = crate::secrets::redact(&text);
+        if redacted != text {
+            self.archive.redacted.push(String::from(name));
+        }
+        redacted
+    }
+
+    /// Store a copy of the file at `source` as `name`. Text is redacted, binary
+    /// files (those with a NUL byte near the start) are copied as they are. Bytes of
+    /// text that are not valid UTF-8 are replaced with `U+FFFD`, so that the rest
+    /// of the text is still redacted.
+    fn file(&mut self, name: &str, source: &std::path::Path) {
+        use std::io::Read as _;
+
+        let mut start = Vec::with_capacity(BINARY_CHECK);
+        let binary = std::fs::File::open(source)
+            .and_then(|file| file.take(BINARY_CHECK as u64).read_to_end(&mut start))
+            .map(|_| start.contains(&0));
+        match binary {
+            Ok(false) if self.redact => match std::fs::read(source) {
+                Ok(content) => {
+                    let text = String::from_utf8(content).unwrap_or_else(|error| {
+                        String::from_utf8_lossy(error.as_bytes()).into_owned()
+                    });
+                    let text = self.redact(name, text);
+                    self.store(name, |path| std::fs::write(path, text));
+                },
+                Err(error) => self.fail(name, &FSError::from(error)),
+            },
+            Ok(_) => self.store(name, |path| std::fs::copy(source, path).map(drop)),
+            Err(error) => self.fail(name, &FSError::from(error)),
+        }
+    }
+
+    /// Store the standard output of `command` as `name`.
+    fn command(&mut self, name: &str, command: &Command) {
+        let outcome = command
+            .output()
+            .map_err(|error| error.to_string())
+            .and_then(|output| {
+                if output.stdout.trim().is_empty() {
+                    Err(format!(
+                        "{} printed nothing: {}",
+                        command.program(),
+                        output.stderr.trim()
+                    ))
+                } else {
+                    Ok(output.stdout)
+                }
+            });
+        match outcome {
+            Ok(content) => {
+                let content = self.redact(name, content);
+                self.store(name, |path| std::fs::write(path, content));
+            },
+            Err(reason) => self.archive.missing.push((String::from(name), reason)),
+        }
+    }
+
+    /// Store the entry `name` with `write`, which is given the path to write it to.
+    fn store(&mut self, name: &str, write: impl FnOnce(&std::path::Path) -> std::io::Result<()>) {
+        let path = self.staging.join(name);
+        let outcome = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| write(&path))
+            .map_err(FSError::from);
+        match outcome {
+            Ok(()) => self.archive.collected.push(String::from(name)),
+            Err(error) => self.fail(name, &error),
+        }
+    }
+
+    /// Record that `name` could not be gathered because of `error`.
+    fn fail(&mut self, name: &str, error: &FSError) {
+        log::debug!("Could not gather '{}': {}", name, error);
+        self.archive.redacted.retain(|redacted| redacted != name);
+        self.archive
+            .missing
+            .push((String::from(name), error.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod diagnostics_test {
+    use super::*;
+
+    #[test]
+    fn build() -> DiagnosticsResult<()> {
+        let target = TempDir::create()?;
+        let sources = TempDir::create()?;
+        let config = sources.path().join("app.conf");
+        std::fs::write(&config, "port = 8080\npassword = hunter22\n").map_err(FSError::from)?;
+        let binary = sources.path().join("data.bin");
+        std::fs::write(&binary, b"password=\0\xff").map_err(FSError::from)?;
+        let latin1 = sources.path().join("latin1.conf");
+        std::fs::write(&latin1, b"user = j\xfcrgen\npassword = hunter22\n")
+            .map_err(FSError::from)?;
+
+        let archive = Bundle::new("support test")
+            .fact("ticket", "OPS-1234")
+            .file(&config)
+            .file_as("data.bin", &binary)
+            .file_as("latin1.conf", &latin1)
+            .file("/this/does/not/exist.conf")
+            .command("echo.txt", Command::new("echo").arg("token=abc123"))
+            .command("nothing.txt", Command::new("true"))
+            .unavailable("core", "no core dump")
+            .build(target.path())?;
+
+        let config_name = format!("files{}", config.display());
+        assert!(archive.path.starts_with(target.path()));
+        assert_eq!(
+            archive.collected,
+            [
+                &config_name,
+                "data.bin",
+                "latin1.conf",
+                "echo.txt",
+                MANIFEST
+            ]
+        );
+        assert_eq!(archive.redacted, [&config_name, "latin1.conf", "echo.txt"]);
+        let missing: Vec<&str> = archive
+            .missing
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            missing,
+            ["files/this/does/not/exist.conf", "nothing.txt", "core"]
+        );
+
+        let extracted = TempDir::create()?;
+        Command::new("tar")
+            .args(["--extract", "--gzip", "--file"])
+            .arg(archive.path.to_string_lossy())
+            .arg("--directory")
+            .arg(extracted.path().to_string_lossy())
+            .run()?;
+        let read = |name: &str| std::fs::read(extracted.path().join(name)).unwrap();
+        assert_eq!(read(&config_name), b"port = 8080\npassword = [REDACTED]\n");
+        assert_eq!(read("data.bin"), b"password=\0\xff");
+        assert_eq!(
+            read("latin1.conf"),
+            "user = j\u{fffd}rgen\npassword = [REDACTED]\n".as_bytes()
+        );
+        assert_eq!(read("echo.txt"), b"token=[REDACTED]\n");
+        let manifest = String::from_utf8(read(MANIFEST)).unwrap();
+        assert!(manifest.contains("ticket: OPS-1234"));
+        assert!(manifest.contains("echo.txt (redacted)"));
+        assert!(manifest.contains("core: no core dump"));
+        // Only the archive is left behind.
+        assert_eq!(target.entries()?.count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+//! This module contains a dotfiles manager in the spirit of GNU Stow: [`link_tree`]
+//! mirrors a source tree into a target directory as symbolic links, and
+//! [`unlink_tree`] removes them again.
+//!
+//! Directories are created as real directories in the target, and every file gets
+//! its own link to the absolute path of its source. Files that are in the way are
+//! handled according to the [`Conflict`] strategy.
+//!
+//! ```no_run
+//! use rush::dotfiles::{
+//!     link_tree,
+//!     Conflict,
+//!     LinkOptions,
+//! };
+//!
+//! let home = std::env::var("HOME").unwrap();
+//! let options = LinkOptions::new()
+//!     .conflict(Conflict::Backup)
+//!     .ignore("README.md");
+//! for change in link_tree("dotfiles", &home, &options).unwrap() {
+//!     println!("{change}");
+//! }
+//! ```
+
+use crate::fs::FSError;
+
+/// Describes possible errors when linking or unlinking trees.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum DotfilesError {
+    #[error("Something else is in the way at '{}'", .0.display())]
+    Conflict(std::path::PathBuf),
+    #[error("Accessing the filesystem failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`DotfilesError`].
+pub type DotfilesResult<T> = Result<T, DotfilesError>;
+
+/// What [`link_tree`] does when something other than the desired link exists where
+/// a link or directory should be created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Conflict {
+    /// Stop with [`DotfilesError::Conflict`]
+    #[default]
+    Fail,
+    /// Leave it alone and do not create the link
+    Skip,
+    /// Rename it to `<name>.bak` (or a free variation of that) and create the link
+    Backup,
+    /// Move a file into the source tree, replacing the file there, and create the
+    /// link, like `stow --adopt`; other conflicts fail
+    Adopt,
+}
+
+/// Describes how [`link_tree`] mirrors a tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkOptions {
+    /// What to do with things that are in the way
+    conflict: Conflict,
+    /// Glob patterns of paths relative to the source that are not linked
+    ignore:   Vec<String>,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self { Self::new() }
+}
+
+impl LinkOptions {
+    /// Fail on conflicts and ignore only `.git`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            conflict: Conflict::Fail,
+            ignore:   vec![String::from(".git")],
+        }
+    }
+
+    /// What to do with things that are in the way.
+    #[must_use]
+    pub const fn conflict(mut self, conflict: Conflict) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    /// Do not link files or directories whose path relative to the source matches
+    /// the glob `pattern`, e.g. `README*` or `**/.DS_Store`.
+    #[must_use]
+    pub fn ignore(mut self, pattern: impl Into<String>) -> Self {
+        self.ignore.push(pattern.into());
+        self
+    }
+
+    /// Whether `relative` is ignored.
+    fn ignores(&self, relative: &std::path::Path) -> bool {
+        let relative = relative.to_string_lossy();
+        self.ignore
+            .iter()
+            .any(|pattern| rush_core::glob::matches(pattern, &relative))
+    }
+}
+
+/// A change [`link_tree`] or [`unlink_tree`] made in the target directory.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Change {
+    /// A link was created at this path
+    Linked(std::path::PathBuf),
+    /// What was in the way at `path` was moved to `backup`
+    BackedUp {
+        /// Where the link was created
+        path:   std::path::PathBuf,
+        /// Where the previous content is now
+        backup: std::path::PathBuf,
+    },
+    /// The file at this path was moved into the source tree
+    Adopted(std::path::PathBuf),
+    /// What was in the way at this path was left alone
+    Skipped(std::path::PathBuf),
+    /// The link at this path was removed
+    Unlinked(std::path::PathBuf),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linked(path) => write!(f, "Linked '{}'", path.display()),
+            Self::BackedUp { path, backup } => write!(
+                f,
+                "Backed up '{}' to '{}'",
+                path.display(),
+                backup.display()
+            ),
+            Self::Adopted(path) => write!(f, "Adopted '{}'", path.display()),
+            Self::Skipped(path) => write!(f, "Skipped '{}'", path.display()),
+            Self::Unlinked(path) => write!(f, "Unlinked '{}'", path.display()),
+        }
+    }
+}
+
+/// The metadata of `path` without following symbolic links, or [`None`] if nothing
+/// exists at `path`.
+fn metadata(path: &std::path::Path) -> DotfilesResult<Option<std::fs::Metadata>> {
+    match path.symlink_metadata() {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(FSError::from(error).into()),
+    }
+}
+
+/// The entries of `directory`, sorted so that the changes are reported in a stable
+/// order.
+fn entries(directory: &std::path::Path) -> DotfilesResult<Vec<std::fs::DirEntry>> {
+    let mut entries = std::fs::read_dir(directory)
+        .and_then(Iterator::collect::<std::io::Result<Vec<_>>>)
+        .map_err(FSError::from)?;
+    entries.sort_by_key(std::fs::DirEntry::path);
+    Ok(entries)
+}
+
+/// Deal with what is in the way at `path`, where the file `source` (or a directory,
+/// if [`None`]) is to be linked. Returns whether the link is to be created.
+fn resolve(
+    path: &std::path::Path,
+    source: Option<&std::path::Path>,
+    conflict: Conflict,
+    changes: &mut Vec<Change>,
+) -> DotfilesResult<bool> {
+    match conflict {
+        Conflict::Fail => Err(DotfilesError::Conflict(path.to_path_buf())),
+        Conflict::Skip => {
+            log::debug!("Skipping '{}', which is in the way", path.display());
+            changes.push(Change::Skipped(path.to_path_buf()));
+            Ok(false)
+        },
+        Conflict::Backup => {
+            let mut backup = path.as_os_str().to_os_string();
+            backup.push(".bak");
+            let backup = crate::fs::unique_target(backup);
+            std::fs::rename(path, &backup).map_err(FSError::from)?;
+            changes.push(Change::BackedUp {
+                path: path.to_path_buf(),
+                backup,
+            });
+            Ok(true)
+        },
+        Conflict::Adopt => {
+            let is_file = metadata(path)?.is_some_and(|metadata| metadata.is_file());
+            let Some(source) = source.filter(|_| is_file) else {
+                return Err(DotfilesError::Conflict(path.to_path_buf()));
+            };
+            // The source tree may be on another filesystem, so the file is copied.
+            std::fs::copy(path, source).map_err(FSError::from)?;
+            std::fs::remove_file(path).map_err(FSError::from)?;
+            changes.push(Change::Adopted(path.to_path_buf()));
+            Ok(true)
+        },
+    }
+}
+
+/// Mirror `directory`, which is inside `source`, into `target`.
+fn link_directory(
+    source: &std::path::Path,
+    directory: &std::path::Path,
+    target: &std::path::Path,
+    options: &LinkOptions,
+    changes: &mut Vec<Change>,
+) -> DotfilesResult<()> {
+    for entry in entries(directory)? {
+        let path = entry.path();
+        let relative = path.strip_prefix(source).unwrap_or(&path);
+        if options.ignores(relative) {
+            continue;
+        }
+        let link = target.join(relative);
+        let existing = metadata(&link)?;
+
+        if entry.file_type().map_err(FSError::from)?.is_dir() {
+            if !existing.as_ref().is_some_and(std::fs::Metadata::is_dir) {
+                if existing.is_some() && !resolve(&link, None, options.conflict, changes)? {
+                    continue;
+                }
+                std::fs::create_dir(&link).map_err(FSError::from)?;
+            }
+            link_directory(source, &path, target, options, changes)?;
+            continue;
+        }
+
+        if let Some(existing) = existing {
+            let linked = existing.is_symlink()
+                && std::fs::read_link(&link).is_ok_and(|destination| destination == path);
+            if linked || !resolve(&link, Some(&path), options.conflict, changes)? {
+                continue;
+            }
+        }
+        std::os::unix::fs::symlink(&path, &link).map_err(FSError::from)?;
+        log::trace!("Linked '{}' to '{}'", link.display(), path.display());
+        changes.push(Change::Linked(link));
+    }
+    Ok(())
+}
+
+/// Mirror the tree `source` into `target` as symbolic links, creating `target` and
+/// the directories in it as needed. Returns the changes that were made.
+///
+/// Links that already point to the right file are left alone, so linking again
+/// changes nothing. Everything else that is in the way is handled as `options`
+/// say.
+///
+/// # Errors
+///
+/// Returns an error if a conflict is not resolved or if accessing the filesystem
+/// fails. Changes made before the failure are kept.
+pub fn link_tree(
+    source: impl AsRef<std::path::Path>,
+    target: impl AsRef<std::path::Path>,
+    options: &LinkOptions,
+) -> DotfilesResult<Vec<Change>> {
+    let source = std::fs::canonicalize(source).map_err(FSError::from)?;
+    let target = target.as_ref();
+    log::debug!("Linking '{}' into '{}'", source.display(), target.display());
+    std::fs::create_dir_all(target).map_err(FSError::from)?;
+    let mut changes = vec![];
+    link_directory(&source, &source, target, options, &mut changes)?;
+    Ok(changes)
+}
+
+/// Remove the links in `directory`, which is inside `source`, from `target`.
+/// Directories that are left empty are removed as well.
+fn unlink_directory(
+    source: &std::path::Path,
+    directory: &std::path::Path,
+    target: &std::path::Path,
+    changes: &mut Vec<Change>,
+) -> DotfilesResult<()> {
+    for entry in entries(directory)? {
+        let path = entry.path();
+        let link = target.join(path.strip_prefix(source).unwrap_or(&path));
+        let Some(existing) = metadata(&link)? else {
+            continue;
+        };
+
+        if entry.file_type().map_err(FSError::from)?.is_dir() {
+            if existing.is_dir() {
+                unlink_directory(source, &path, target, changes)?;
+                if entries(&link)?.is_empty() {
+                    std::fs::remove_dir(&link).map_err(FSError::from)?;
+                }
+            }
+        } else if existing.is_symlink()
+            && std::fs::read_link(&link).is_ok_and(|destination| destination == path)
+        {
+            std::fs::remove_file(&link).map_err(FSError::from)?;
+            changes.push(Change::Unlinked(link));
+        }
+    }
+    Ok(())
+}
+
+/// Remove the links [`link_tree`] created from `source` in `target`. Returns the
+/// links that were removed.
+///
+/// Directories that are left empty are removed as well. Everything else, including
+/// `target` itself, is left alone.
+///
+/// # Errors
+///
+/// Returns an error if accessing the filesystem fails.
+pub fn unlink_tree(
+    source: impl AsRef<std::path::Path>,
+    target: impl AsRef<std::path::Path>,
+) -> DotfilesResult<Vec<Change>> {
+    let source = std::fs::canonicalize(source).map_err(FSError::from)?;
+    let target = target.as_ref();
+    log::debug!(
+        "Unlinking '{}' from '{}'",
+        source.display(),
+        target.display()
+    );
+    let mut changes = vec![];
+    unlink_directory(&source, &source, target, &mut changes)?;
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod dotfiles_test {
+    use super::*;
+
+    /// Create the files `paths` with their names as content below `root`.
+    fn create(root: &std::path::Path, paths: &[&str]) {
+        for path in paths {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, path.file_name().unwrap().as_encoded_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn link_and_unlink() -> DotfilesResult<()> {
+        let root = crate::fs::generate_test_path();
+        let (source, target) = (root.join("source"), root.join("target"));
+        create(
+            &source,
+            &[".bashrc", ".config/nvim/init.lua", ".git/HEAD", "README.md"],
+        );
+        create(&target, &[".config/other"]);
+        let source = std::fs::canonicalize(source).unwrap();
+
+        let options = LinkOptions::new().ignore("README*");
+        assert_eq!(
+            link_tree(&source, &target, &options)?,
+            [
+                Change::Linked(target.join(".bashrc")),
+                Change::Linked(target.join(".config/nvim/init.lua")),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_link(target.join(".bashrc")).unwrap(),
+            source.join(".bashrc")
+        );
+        assert!(!target.join(".git").exists() && !target.join("README.md").exists());
+        assert!(link_tree(&source, &target, &options)?.is_empty());
+
+        assert_eq!(
+            unlink_tree(&source, &target)?,
+            [
+                Change::Unlinked(target.join(".bashrc")),
+                Change::Unlinked(target.join(".config/nvim/init.lua")),
+            ]
+        );
+        assert!(!target.join(".config/nvim").exists());
+        assert!(target.join(".config/other").exists());
+
+        std::fs::remove_dir_all(root).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn conflicts() -> DotfilesResult<()> {
+        let root = crate::fs::generate_test_path();
+        let (source, target) = (root.join("source"), root.join("target"));
+        create(&source, &[".bashrc", ".profile", ".vim/vimrc"]);
+        create(&target, &[".bashrc", ".vim"]);
+        std::fs::write(target.join(".bashrc"), "local").unwrap();
+        let source = std::fs::canonicalize(source).unwrap();
+
+        assert_eq!(
+            link_tree(&source, &target, &LinkOptions::new()),
+            Err(DotfilesError::Conflict(target.join(".bashrc")))
+        );
+        let skip = LinkOptions::new().conflict(Conflict::Skip);
+        assert_eq!(
+            link_tree(&source, &target, &skip)?,
+            [
+                Change::Skipped(target.join(".bashrc")),
+                Change::Linked(target.join(".profile")),
+                Change::Skipped(target.join(".vim")),
+            ]
+        );
+        assert_eq!(
+            link_tree(&source, &target, &skip.conflict(Conflict::Adopt)),
+            Err(DotfilesError::Conflict(target.join(".vim")))
+        );
+        assert_eq!(
+            std::fs::read_to_string(source.join(".bashrc")).unwrap(),
+            "local"
+        );
+
+        let backup = LinkOptions::new().conflict(Conflict::Backup);
+        assert_eq!(
+            link_tree(&source, &target, &backup)?,
+            [
+                Change::BackedUp {
+                    path:   target.join(".vim"),
+                    backup: target.join(".vim.bak"),
+                },
+                Change::Linked(target.join(".vim/vimrc")),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join(".vim.bak")).unwrap(),
+            ".vim"
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+//! This module contains functionality for shipping files inside the binary.
+//!
+//! Installers and bootstrap tools often need a payload (configuration templates,
+//! scripts, unit files, ...) next to their own code. With [`embed!`](crate::embed!),
+//! a directory is embedded into the binary at compile time; at runtime,
+//! [`Extract`] writes it to a target [`Directory`] with the permissions it should
+//! have.
+//!
+//! ```
+//! # use rush::prelude::*;
+//! # use rush::embed::Extract;
+//! rush::embed!(Payload, "src/library/embed/fixtures");
+//!
+//! let target = Directory::new(std::env::temp_dir().join("rush-embed-doc"));
+//! let extracted = Extract::<Payload>::new(&target)
+//!     .mode("bin", 0o755)
+//!     .run()
+//!     .unwrap();
+//! println!("{extracted}");
+//! # target.delete_from_fs().unwrap();
+//! ```
+
+#[doc(hidden)] pub use rust_embed;
+pub use rust_embed::RustEmbed;
+
+use crate::fs::{
+    Directory,
+    FSError,
+    FSResult,
+    Object as _,
+};
+
+/// The permissions of extracted files without a more specific rule.
+const DEFAULT_FILE_MODE: u32 = 0o644;
+/// The permissions of extracted directories.
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+
+/// Describes possible errors when extracting embedded files.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum EmbedError {
+    #[error("No embedded file is at or below '{0}'")]
+    NotEmbedded(String),
+    #[error("Extracting embedded files failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is an [`EmbedError`].
+pub type EmbedResult<T> = Result<T, EmbedError>;
+
+/// Embed the directory `folder` (relative to the directory of the crate's
+/// `Cargo.toml`) into the binary as the unit struct `name`. Its files are extracted
+/// with [`Extract`].
+///
+/// In debug builds, too, the files are embedded and not read from disk. The folder
+/// must exist at compile time. `rush` must be a dependency under this name.
+///
+/// ```
+/// rush::embed!(
+///     /// The files the installer ships
+///     pub(crate) Payload,
+///     "src/library/embed/fixtures"
+/// );
+///
+/// assert!(Payload::get("share/greeting.txt").is_some());
+/// ```
+#[macro_export]
+macro_rules! embed {
+    ($(#[$meta:meta])* $visibility:vis $name:ident, $folder:tt) => {
+        $(#[$meta])*
+        #[derive($crate::embed::RustEmbed)]
+        #[folder = $folder]
+        #[crate_path = "rush::embed::rust_embed"]
+        $visibility struct $name;
+    };
+}
+
+/// Whether `name` (a path inside the embedded directory) is `prefix` or inside the
+/// directory `prefix`. Every name is below the empty prefix.
+fn is_below(name: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_matches('/');
+    prefix.is_empty()
+        || name == prefix
+        || name
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Set the permissions of `path` to `mode` on Unix. Elsewhere, modes are ignored.
+fn set_mode(path: &std::path::Path, mode: u32) -> EmbedResult<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(FSError::from)?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+/// The outcome of [`Extract::run`], with paths relative to the target directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Extracted {
+    /// The files that were written because they were missing or different
+    pub written:   Vec<String>,
+    /// The files that already had the embedded content
+    pub unchanged: Vec<String>,
+}
+
+impl std::fmt::Display for Extracted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for name in &self.written {
+            writeln!(f, "written    {name}")?;
+        }
+        for name in &self.unchanged {
+            writeln!(f, "unchanged  {name}")?;
+        }
+        write!(
+            f,
+            "{} written, {} unchanged",
+            self.written.len(),
+            self.unchanged.len()
+        )
+    }
+}
+
+/// Extracts the files embedded as `E` (see [`embed!`](crate::embed!)) to a
+/// directory.
+///
+/// Files are only written if their content differs, so extracting is idempotent.
+/// Every file is written to a temporary file next to it first and then renamed, so
+/// a file is never seen half-written or with the wrong permissions.
+#[derive(Debug)]
+pub struct Extract<E> {
+    /// The directory the files are extracted to
+    target:         std::path::PathBuf,
+    /// Only the files at or below this path are extracted
+    only:           String,
+    /// The permissions of files without a more specific rule
+    file_mode:      u32,
+    /// The permissions of the directories that are created
+    directory_mode: u32,
+    /// Paths and the permissions of the files at or below them; the last match wins
+    modes:          Vec<(String, u32)>,
+    /// The embedded files
+    embedded:       std::marker::PhantomData<E>,
+}
+
+impl<E: RustEmbed> Extract<E> {
+    /// Extract all embedded files to `target`, which is created if it does not
+    /// exist.
+    #[must_use]
+    pub fn new(target: &Directory) -> Self {
+        Self {
+            target:         target.path().clone(),
+            only:           String::new(),
+            file_mode:      DEFAULT_FILE_MODE,
+            directory_mode: DEFAULT_DIRECTORY_MODE,
+            modes:          vec![],
+            embedded:       std::marker::PhantomData,
+        }
+    }
+
+    /// Only extract the file `path` or the files in the directory `path`. Their
+    /// paths in the target directory stay the same.
+    #[must_use]
+    pub fn only(mut self, path: impl Into<String>) -> Self {
+        self.only = path.into();
+        self
+    }
+
+    /// Set the permissions of files without a more specific rule (default `0o644`).
+    #[must_use]
+    pub const fn file_mode(mut self, mode: u32) -> Self {
+        self.file_mode = mode;
+        self
+    }
+
+    /// Set the permissions of the directories that are created (default `0o755`).
+    #[must_use]
+    pub const fn directory_mode(mut self, mode: u32) -> Self {
+        self.directory_mode = mode;
+        self
+    }
+
+    /// Set the permissions of the file `path` or the files in the directory `path`,
+    /// e.g. `0o755` for scripts or `0o600` for secrets. If several rules match a
+    /// file, the last one wins.
+    #[must_use]
+    pub fn mode(mut self, path: impl Into<String>, mode: u32) -> Self {
+        self.modes.push((path.into(), mode));
+        self
+    }
+
+    /// The permissions of the embedded file `name`.
+    fn mode_of(&self, name: &str) -> u32 {
+        self.modes
+            .iter()
+            .rev()
+            .find(|(path, _)| is_below(name, path))
+            .map_or(self.file_mode, |(_, mode)| *mode)
+    }
+
+    /// Create `directory` and its missing parents with the directory mode.
+    fn create_directory(&self, directory: &std::path::Path) -> EmbedResult<()> {
+        if directory.is_dir() {
+            return Ok(());
+        }
+        if let Some(parent) = directory.parent() {
+            self.create_directory(parent)?;
+        }
+        std::fs::create_dir(directory).map_err(FSError::from)?;
+        set_mode(directory, self.directory_mode)
+    }
+
+    /// Whether the file at `path` has the content `data` and the permissions
+    /// `mode`.
+    fn is_unchanged(path: &std::path::Path, data: &[u8], mode: u32) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            if std::fs::metadata(path).map_or(true, |metadata| {
+                metadata.permissions().mode() & 0o7777 != mode
+            }) {
+                return false;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        std::fs::read(path).is_ok_and(|content| content == data)
+    }
+
+    /// Extract the embedded files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no embedded file matches [`Extract::only`] or if a file
+    /// or directory cannot be written.
+    pub fn run(&self) -> EmbedResult<Extracted> {
+        use std::io::Write as _;
+
+        let mut names = E::iter()
+            .filter(|name| is_below(name, &self.only))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Err(EmbedError::NotEmbedded(self.only.clone()));
+        }
+        names.sort();
+
+        log::debug!(
+            "Extracting {} embedded files to '{}'",
+            names.len(),
+            self.target.display()
+        );
+        let mut extracted = Extracted::default();
+        for name in names {
+            let Some(file) = E::get(&name) else {
+                continue;
+            };
+            let path = self.target.join(name.as_ref());
+            let mode = self.mode_of(&name);
+            if Self::is_unchanged(&path, &file.data, mode) {
+                extracted.unchanged.push(name.into_owned());
+                continue;
+            }
+
+            if let Some(parent) = path.parent() {
+                self.create_directory(parent)?;
+            }
+            crate::fs::write_atomic_with(&path, Some(mode), |writer| -> FSResult<()> {
+                Ok(writer.write_all(&file.data)?)
+            })?;
+            log::trace!("Extracted '{}'", path.display());
+            extracted.written.push(name.into_owned());
+        }
+        Ok(extracted)
+    }
+}
+
+/// Extract all files embedded as `E` to `target` with the default permissions.
+/// See [`Extract`] for more options.
+///
+/// # Errors
+///
+/// Returns an error if extracting fails, see [`Extract::run`].
+pub fn extract<E: RustEmbed>(target: &Directory) -> EmbedResult<Extracted> {
+    Extract::<E>::new(target).run()
+}
+
+#[cfg(test)]
+mod embed_test {
+    use super::*;
+
+    /// The files in `fixtures/`
+    #[derive(RustEmbed)]
+    #[folder = "src/library/embed/fixtures"]
+    #[crate_path = "crate::embed::rust_embed"]
+    struct Fixtures;
+
+    #[test]
+    fn below() {
+        assert!(is_below("bin/hello", ""));
+        assert!(is_below("bin/hello", "bin"));
+        assert!(is_below("bin/hello", "bin/"));
+        assert!(is_below("bin/hello", "bin/hello"));
+        assert!(!is_below("binary/hello", "bin"));
+        assert!(!is_below("bin/hello", "share"));
+    }
+
+    #[test]
+    fn extract_with_modes() -> EmbedResult<()> {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let target = Directory::new(crate::fs::generate_test_path());
+        let mode = |name: &str| {
+            std::fs::metadata(target.path().join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+
+        let extracted = Extract::<Fixtures>::new(&target)
+            .file_mode(0o640)
+            .mode("bin", 0o755)
+            .run()?;
+        assert_eq!(extracted.written, ["bin/hello", "share/greeting.txt"]);
+        assert_eq!(mode("bin/hello"), 0o755);
+        assert_eq!(mode("share/greeting.txt"), 0o640);
+        assert_eq!(mode("share"), DEFAULT_DIRECTORY_MODE);
+        assert_eq!(
+            std::fs::read_to_string(target.path().join("share/greeting.txt")).unwrap(),
+            "Hello from the payload.\n"
+        );
+        let output =
+            crate::process::Command::new(target.path().join("bin/hello").to_string_lossy())
+                .run()
+                .unwrap();
+        assert_eq!(output.stdout, "hello\n");
+
+        // Extracting again only writes what changed.
+        std::fs::write(target.path().join("share/greeting.txt"), "changed").unwrap();
+        let extracted = Extract::<Fixtures>::new(&target)
+            .file_mode(0o640)
+            .mode("bin", 0o755)
+            .run()?;
+        assert_eq!(extracted.written, ["share/greeting.txt"]);
+        assert_eq!(extracted.unchanged, ["bin/hello"]);
+        // Changed permissions count as a change, too.
+        assert_eq!(
+            extract::<Fixtures>(&target)?.written,
+            ["bin/hello", "share/greeting.txt"]
+        );
+        assert_eq!(mode("bin/hello"), DEFAULT_FILE_MODE);
+
+        target.delete_from_fs()?;
+        Ok(())
+    }
+
+    #[test]
+    fn only() -> EmbedResult<()> {
+        let target = Directory::new(crate::fs::generate_test_path());
+        let extracted = Extract::<Fixtures>::new(&target).only("share").run()?;
+        assert_eq!(extracted.written, ["share/greeting.txt"]);
+        assert!(!target.path().join("bin").exists());
+        assert_eq!(
+            Extract::<Fixtures>::new(&target).only("etc").run(),
+            Err(EmbedError::NotEmbedded(String::from("etc")))
+        );
+
+        target.delete_from_fs()?;
+        Ok(())
+    }
+}
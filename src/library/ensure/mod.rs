@@ -0,0 +1,678 @@
+//! This module contains a declarative layer for provisioning: scripts describe the
+//! [`Resource`]s a machine should have, and only the differences are changed.
+//!
+//! Changes happen in two phases. [`plan`] compares the resources with the machine
+//! and returns a [`Plan`] of pending changes, which can be printed, serialized and
+//! confirmed. [`apply`] then executes the plan. [`ensure`] does both at once.
+//! Serialized plans contain digests instead of file contents, so they can be
+//! archived or shown for review without leaking secrets.
+//!
+//! ```no_run
+//! # use rush::ensure::{apply, plan, Resource};
+//! let resources = [
+//!     Resource::Directory {
+//!         path: "/etc/app".into(),
+//!         mode: Some(0o755),
+//!     },
+//!     Resource::File {
+//!         path:    "/etc/app/config.toml".into(),
+//!         content: String::from("port = 8080\n"),
+//!         mode:    Some(0o644),
+//!     },
+//! ];
+//! let plan = plan(&resources).unwrap();
+//! println!("{plan}");
+//! apply(&plan).unwrap();
+//! ```
+
+use crate::{
+    fs::{
+        FSError,
+        ObjectType,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+mod role;
+mod state;
+
+pub use role::{
+    Parameters,
+    Profile,
+    Role,
+};
+pub use state::State;
+
+/// Describes possible errors when planning or applying changes.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum EnsureError {
+    #[error("Accessing the filesystem failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running a program failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("The machine changed since the plan was made: {0}")]
+    StalePlan(String),
+    #[error("Could not parse the state file: {0}")]
+    InvalidState(String),
+    #[error("The role '{0}' is not known")]
+    UnknownRole(String),
+    #[error("Roles depend on each other in a cycle: {0}")]
+    RoleCycle(String),
+    #[error("The role '{0}' requires the parameter '{1}'")]
+    MissingParameter(String, String),
+}
+
+/// A [`Result`] whose error variant is an [`EnsureError`].
+pub type EnsureResult<T> = Result<T, EnsureError>;
+
+/// Something a machine should have (or not have).
+///
+/// When serialized, the content of a file is replaced by its SHA-256 digest
+/// (`content_sha256`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Resource {
+    /// A regular file with exactly `content` and, if given, permission bits `mode`
+    File {
+        path:    std::path::PathBuf,
+        #[serde(rename = "content_sha256", serialize_with = "serialize_sha256")]
+        content: String,
+        mode:    Option<u32>,
+    },
+    /// A directory (created with its parents) with, if given, permission bits `mode`
+    Directory {
+        path: std::path::PathBuf,
+        mode: Option<u32>,
+    },
+    /// A symbolic link at `path` pointing to `target`
+    Symlink {
+        path:   std::path::PathBuf,
+        target: std::path::PathBuf,
+    },
+    /// A local user account, created with a home directory
+    User { name: String },
+    /// Nothing at `path`; files, links and whole directories are removed
+    Absent { path: std::path::PathBuf },
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File { path, .. } => write!(f, "file '{}'", path.to_string_lossy()),
+            Self::Directory { path, .. } => write!(f, "directory '{}'", path.to_string_lossy()),
+            Self::Symlink { path, target } => write!(
+                f,
+                "symbolic link '{}' -> '{}'",
+                path.to_string_lossy(),
+                target.to_string_lossy()
+            ),
+            Self::User { name } => write!(f, "user '{name}'"),
+            Self::Absent { path } => write!(f, "'{}'", path.to_string_lossy()),
+        }
+    }
+}
+
+/// What needs to happen to a [`Resource`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// The resource does not exist yet
+    Create,
+    /// The resource exists but differs in the listed properties (e.g. `content`)
+    Update { reasons: Vec<String> },
+    /// The object at the resource's path has to be removed
+    Remove,
+}
+
+/// The permission bits of `metadata`.
+fn mode_of(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+/// Set the permission bits of `path` to `mode`, if given.
+fn set_mode(path: &std::path::Path, mode: Option<u32>) -> EnsureResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(FSError::from)?;
+    }
+    Ok(())
+}
+
+/// Create the parent directories of `path`.
+fn create_parent(path: &std::path::Path) -> EnsureResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(FSError::from)?;
+    }
+    Ok(())
+}
+
+/// The metadata of `path` without following symbolic links, or [`None`] if nothing
+/// exists at `path`.
+fn metadata(path: &std::path::Path) -> EnsureResult<Option<std::fs::Metadata>> {
+    match path.symlink_metadata() {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(FSError::from(error).into()),
+    }
+}
+
+/// The hex-encoded SHA-256 digest of `content`.
+fn sha256(content: &[u8]) -> String {
+    use sha2::Digest;
+    use std::fmt::Write;
+
+    sha2::Sha256::digest(content)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Serialize `content` as its digest, see [`sha256`].
+fn serialize_sha256<S: serde::Serializer>(content: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&sha256(content.as_bytes()))
+}
+
+/// Turn a list of differing properties into an [`Action`].
+fn update(reasons: Vec<&str>) -> Option<Action> {
+    (!reasons.is_empty()).then(|| Action::Update {
+        reasons: reasons.into_iter().map(String::from).collect(),
+    })
+}
+
+impl Resource {
+    /// The path of the resource, or [`None`] for a user.
+    fn path(&self) -> Option<&std::path::Path> {
+        match self {
+            Self::File { path, .. }
+            | Self::Directory { path, .. }
+            | Self::Symlink { path, .. }
+            | Self::Absent { path } => Some(path),
+            Self::User { .. } => None,
+        }
+    }
+
+    /// What identifies the resource on the machine: its path, or the name of a user.
+    fn key(&self) -> String {
+        match self {
+            Self::File { path, .. }
+            | Self::Directory { path, .. }
+            | Self::Symlink { path, .. }
+            | Self::Absent { path } => path.to_string_lossy().into_owned(),
+            Self::User { name } => format!("user:{name}"),
+        }
+    }
+
+    /// Whether something exists at the resource's path (or the user exists),
+    /// regardless of whether it matches the resource.
+    fn exists(&self) -> EnsureResult<bool> {
+        match self {
+            Self::File { path, .. }
+            | Self::Directory { path, .. }
+            | Self::Symlink { path, .. }
+            | Self::Absent { path } => Ok(metadata(path)?.is_some()),
+            Self::User { name } => Ok(Command::new("id").arg("-u").arg(name).output()?.success()),
+        }
+    }
+
+    /// Compare the resource with the machine. Returns [`None`] if nothing needs to
+    /// change.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the machine cannot be inspected, or if an object of a
+    /// different type is in the way.
+    pub fn check(&self) -> EnsureResult<Option<Action>> {
+        let type_mismatch =
+            |path: &std::path::Path| FSError::TypeMismatch(ObjectType::from(&path.to_path_buf()));
+        match self {
+            Self::File {
+                path,
+                content,
+                mode,
+            } => {
+                let Some(metadata) = metadata(path)? else {
+                    return Ok(Some(Action::Create));
+                };
+                if !metadata.is_file() {
+                    return Err(type_mismatch(path).into());
+                }
+                let mut reasons = Vec::new();
+                if std::fs::read(path).map_err(FSError::from)? != content.as_bytes() {
+                    reasons.push("content");
+                }
+                if mode.is_some_and(|mode| mode != mode_of(&metadata)) {
+                    reasons.push("mode");
+                }
+                Ok(update(reasons))
+            },
+            Self::Directory { path, mode } => match metadata(path)? {
+                None => Ok(Some(Action::Create)),
+                Some(metadata) if !metadata.is_dir() => Err(type_mismatch(path).into()),
+                Some(metadata) => Ok(update(
+                    mode.filter(|mode| *mode != mode_of(&metadata))
+                        .map(|_| "mode")
+                        .into_iter()
+                        .collect(),
+                )),
+            },
+            Self::Symlink { path, target } => match metadata(path)? {
+                None => Ok(Some(Action::Create)),
+                Some(metadata) if !metadata.is_symlink() => Err(type_mismatch(path).into()),
+                Some(_) => {
+                    let current = std::fs::read_link(path).map_err(FSError::from)?;
+                    Ok(update(
+                        (current != *target)
+                            .then_some("target")
+                            .into_iter()
+                            .collect(),
+                    ))
+                },
+            },
+            Self::User { .. } => Ok((!self.exists()?).then_some(Action::Create)),
+            Self::Absent { .. } => Ok(self.exists()?.then_some(Action::Remove)),
+        }
+    }
+
+    /// Perform `action` on the resource, usually as determined by
+    /// [`Resource::check`].
+    ///
+    /// Removing a directory only removes its content for [`Resource::Absent`]. Any
+    /// other resource is removed because it is not declared anymore (see
+    /// [`State`]), and a non-empty directory in its place is left alone, as its
+    /// content may not be managed by the script.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the machine cannot be changed, or a directory to remove
+    /// is not empty.
+    pub fn apply(&self, action: &Action) -> EnsureResult<()> {
+        log::info!(
+            "{}",
+            Change {
+                resource: self.clone(),
+                action:   action.clone(),
+            }
+        );
+        match (self, action) {
+            (
+                Self::File {
+                    path,
+                    content,
+                    mode,
+                },
+                Action::Create | Action::Update { .. },
+            ) => {
+                use std::{
+                    io::Write,
+                    os::unix::fs::OpenOptionsExt,
+                };
+
+                // The mode is set before the content is written, so the content is
+                // never readable with broader permissions.
+                if matches!(action, Action::Update { .. }) {
+                    set_mode(path, *mode)?;
+                }
+                create_parent(path)?;
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(mode.unwrap_or(0o666))
+                    .open(path)
+                    .and_then(|mut file| file.write_all(content.as_bytes()))
+                    .map_err(FSError::from)?;
+                // The umask may have cleared bits of the mode when creating the file.
+                set_mode(path, *mode)
+            },
+            (Self::Directory { path, mode }, Action::Create | Action::Update { .. }) => {
+                std::fs::create_dir_all(path).map_err(FSError::from)?;
+                set_mode(path, *mode)
+            },
+            (Self::Symlink { path, target }, Action::Create | Action::Update { .. }) => {
+                if matches!(action, Action::Update { .. }) {
+                    std::fs::remove_file(path).map_err(FSError::from)?;
+                }
+                create_parent(path)?;
+                std::os::unix::fs::symlink(target, path).map_err(FSError::from)?;
+                Ok(())
+            },
+            (Self::User { name }, Action::Create | Action::Update { .. }) => {
+                Command::new("useradd")
+                    .arg("--create-home")
+                    .arg(name)
+                    .run()?;
+                Ok(())
+            },
+            (Self::User { name }, Action::Remove) => {
+                Command::new("userdel").arg(name).run()?;
+                Ok(())
+            },
+            (
+                Self::File { path, .. }
+                | Self::Directory { path, .. }
+                | Self::Symlink { path, .. }
+                | Self::Absent { path },
+                Action::Remove,
+            ) => {
+                match metadata(path)? {
+                    Some(metadata) if metadata.is_dir() && matches!(self, Self::Absent { .. }) => {
+                        std::fs::remove_dir_all(path)
+                    },
+                    Some(metadata) if metadata.is_dir() => std::fs::remove_dir(path),
+                    Some(_) => std::fs::remove_file(path),
+                    None => Ok(()),
+                }
+                .map_err(FSError::from)?;
+                Ok(())
+            },
+            (Self::Absent { .. }, _) => Ok(()),
+        }
+    }
+}
+
+/// Bring the machine into the state described by `resources`, changing only what
+/// differs. Resources are ensured in order.
+///
+/// # Errors
+///
+/// Returns an error if the machine cannot be inspected or changed. Resources before
+/// the failing one remain changed.
+pub fn ensure(resources: &[Resource]) -> EnsureResult<()> {
+    for resource in resources {
+        if let Some(action) = resource.check()? {
+            resource.apply(&action)?;
+        }
+    }
+    Ok(())
+}
+
+/// A pending change: a [`Resource`] and what needs to happen to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct Change {
+    /// The resource that is changed
+    pub resource: Resource,
+    /// What needs to happen to the resource
+    #[serde(flatten)]
+    pub action:   Action,
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.action {
+            Action::Create => write!(f, "+ {}", self.resource),
+            Action::Update { reasons } => {
+                write!(f, "~ {} ({})", self.resource, reasons.join(", "))
+            },
+            Action::Remove => write!(f, "- {}", self.resource),
+        }
+    }
+}
+
+/// The changes required to bring the machine into the desired state.
+///
+/// Plans are returned by [`plan`]. Their [`std::fmt::Display`] implementation renders
+/// a summary for humans, [`Plan::to_json`] a machine-readable representation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct Plan {
+    /// The pending changes, in the order they are applied
+    pub changes: Vec<Change>,
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes. The machine is up to date.");
+        }
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        let count = |wanted: fn(&Action) -> bool| {
+            self.changes
+                .iter()
+                .filter(|change| wanted(&change.action))
+                .count()
+        };
+        writeln!(
+            f,
+            "\nPlan: {} to create, {} to update, {} to remove.",
+            count(|action| matches!(action, Action::Create)),
+            count(|action| matches!(action, Action::Update { .. })),
+            count(|action| matches!(action, Action::Remove))
+        )
+    }
+}
+
+impl Plan {
+    /// Whether nothing needs to change.
+    #[must_use]
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+
+    /// Serialize the plan as pretty-printed JSON, e.g. to archive it with a change
+    /// request. File contents are represented by their digest, so the JSON cannot be
+    /// turned back into a plan; apply the plan it was made from instead.
+    #[must_use]
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).unwrap_or_default() }
+}
+
+/// Compare `resources` with the machine and return the changes required to bring it
+/// into the desired state. Nothing is changed.
+///
+/// # Errors
+///
+/// Returns an error if the machine cannot be inspected, or if an object of a
+/// different type is in the way of a resource.
+pub fn plan(resources: &[Resource]) -> EnsureResult<Plan> {
+    let mut changes = Vec::new();
+    for resource in resources {
+        if let Some(action) = resource.check()? {
+            changes.push(Change {
+                resource: resource.clone(),
+                action,
+            });
+        }
+    }
+    Ok(Plan { changes })
+}
+
+/// Execute the changes of `plan` in order. Every resource is checked again before it
+/// is changed, so a plan that no longer matches the machine is not applied blindly.
+///
+/// # Errors
+///
+/// Returns [`EnsureError::StalePlan`] if the machine changed since the plan was
+/// made, or an error if the machine cannot be changed. Changes before the failing
+/// one remain applied.
+pub fn apply(plan: &Plan) -> EnsureResult<()> {
+    for change in &plan.changes {
+        let current = match change.action {
+            // Removals come from a state file and need not match the resource.
+            Action::Remove => change.resource.exists()?.then_some(Action::Remove),
+            _ => change.resource.check()?,
+        };
+        if current.as_ref() != Some(&change.action) {
+            return Err(EnsureError::StalePlan(change.to_string()));
+        }
+        change.resource.apply(&change.action)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod resource_test {
+    use super::*;
+
+    #[test]
+    fn ensure_resources() -> EnsureResult<()> {
+        let root = crate::fs::generate_test_path();
+        std::fs::create_dir_all(root.join("old")).map_err(FSError::from)?;
+        std::fs::write(root.join("config"), "old\n").map_err(FSError::from)?;
+
+        let resources = [
+            Resource::Directory {
+                path: root.join("etc"),
+                mode: Some(0o750),
+            },
+            Resource::File {
+                path:    root.join("config"),
+                content: String::from("new\n"),
+                mode:    Some(0o600),
+            },
+            Resource::Symlink {
+                path:   root.join("current"),
+                target: root.join("config"),
+            },
+            Resource::Absent {
+                path: root.join("old"),
+            },
+        ];
+        assert_eq!(
+            resources[1].check()?,
+            Some(Action::Update {
+                reasons: vec![String::from("content"), String::from("mode")],
+            })
+        );
+        assert_eq!(resources[2].check()?, Some(Action::Create));
+
+        ensure(&resources)?;
+        for resource in &resources {
+            assert_eq!(resource.check()?, None);
+        }
+        assert_eq!(
+            std::fs::read_to_string(root.join("current")).map_err(FSError::from)?,
+            "new\n"
+        );
+        assert!(!root.join("old").exists());
+        assert!(matches!(
+            Resource::File {
+                path:    root.join("etc"),
+                content: String::new(),
+                mode:    None,
+            }
+            .check(),
+            Err(EnsureError::FS(FSError::TypeMismatch(
+                ObjectType::Directory
+            )))
+        ));
+
+        std::fs::remove_dir_all(root).map_err(FSError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod plan_test {
+    use super::*;
+
+    #[test]
+    fn plan_and_apply() -> EnsureResult<()> {
+        let root = crate::fs::generate_test_path();
+        std::fs::create_dir_all(root.join("old")).map_err(FSError::from)?;
+        std::fs::write(root.join("config"), "old\n").map_err(FSError::from)?;
+
+        let resources = [
+            Resource::Directory {
+                path: root.join("etc"),
+                mode: Some(0o750),
+            },
+            Resource::File {
+                path:    root.join("config"),
+                content: String::from("new\n"),
+                mode:    None,
+            },
+            Resource::Symlink {
+                path:   root.join("current"),
+                target: root.join("config"),
+            },
+            Resource::Absent {
+                path: root.join("old"),
+            },
+        ];
+
+        let plan = plan(&resources)?;
+        assert_eq!(plan.changes.len(), 4);
+        assert_eq!(
+            plan.changes[1].action,
+            Action::Update {
+                reasons: vec![String::from("content")],
+            }
+        );
+        assert!(plan
+            .to_string()
+            .ends_with("Plan: 2 to create, 1 to update, 1 to remove.\n"));
+
+        apply(&plan)?;
+        assert!(super::plan(&resources)?.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(root.join("current")).map_err(FSError::from)?,
+            "new\n"
+        );
+        assert!(!root.join("old").exists());
+
+        std::fs::remove_dir_all(root).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    #[test]
+    fn stale_plan() -> EnsureResult<()> {
+        let path = crate::fs::generate_test_path();
+        let resources = [Resource::File {
+            path:    path.clone(),
+            content: String::from("content"),
+            mode:    None,
+        }];
+
+        let plan = plan(&resources)?;
+        std::fs::write(&path, "changed meanwhile").map_err(FSError::from)?;
+        assert!(matches!(apply(&plan), Err(EnsureError::StalePlan(_))));
+
+        std::fs::remove_file(path).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    #[test]
+    fn serialization() {
+        let plan = Plan {
+            changes: vec![
+                Change {
+                    resource: Resource::User {
+                        name: String::from("app"),
+                    },
+                    action:   Action::Create,
+                },
+                Change {
+                    resource: Resource::File {
+                        path:    std::path::PathBuf::from("/etc/app/secret"),
+                        content: String::from("password=hunter2"),
+                        mode:    Some(0o600),
+                    },
+                    action:   Action::Create,
+                },
+            ],
+        };
+        let json: serde_json::Value = serde_json::from_str(&plan.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "changes": [
+                    { "resource": { "type": "user", "name": "app" }, "action": "create" },
+                    {
+                        "resource": {
+                            "type": "file",
+                            "path": "/etc/app/secret",
+                            "content_sha256": sha256(b"password=hunter2"),
+                            "mode": 0o600
+                        },
+                        "action": "create"
+                    }
+                ]
+            })
+        );
+    }
+}
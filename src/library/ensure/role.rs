@@ -0,0 +1,311 @@
+//! This module contains roles, which group resources of the ensure layer into
+//! named, reusable units that can be composed.
+
+use super::{
+    EnsureError,
+    EnsureResult,
+    Plan,
+    Resource,
+};
+
+/// The parameters a role is instantiated with, keyed by their name.
+pub type Parameters = std::collections::BTreeMap<String, String>;
+
+/// The function producing the resources of a role from its parameters.
+type ResourceFn = std::sync::Arc<dyn Fn(&Parameters) -> Vec<Resource> + Send + Sync>;
+
+/// A named group of resources, e.g. "webserver" or "monitoring-agent".
+///
+/// The resources are produced by a function of the role's parameters, so the same
+/// role can be reused with different settings. Parameters the function relies on
+/// are declared with [`Role::requires`], and are guaranteed to be set when it is
+/// called. Roles can depend on other roles, whose resources are then ensured first.
+///
+/// ```
+/// # use rush::ensure::{Resource, Role};
+/// let webserver = Role::new("webserver", |parameters| {
+///     vec![Resource::File {
+///         path:    "/etc/nginx/conf.d/port.conf".into(),
+///         content: format!("listen {};\n", parameters["port"]),
+///         mode:    Some(0o644),
+///     }]
+/// })
+/// .requires("port")
+/// .depends_on("base");
+///
+/// assert!(webserver.resources().is_err());
+/// assert!(webserver.parameter("port", "8080").resources().is_ok());
+/// ```
+#[derive(Clone)]
+pub struct Role {
+    /// The name of the role
+    name:         String,
+    /// The names of the roles that have to be ensured before this one
+    dependencies: Vec<String>,
+    /// The parameters the resources are produced with
+    parameters:   Parameters,
+    /// The names of the parameters that have to be set
+    required:     Vec<String>,
+    /// Produces the resources of the role
+    resources:    ResourceFn,
+}
+
+impl std::fmt::Debug for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Role")
+            .field("name", &self.name)
+            .field("dependencies", &self.dependencies)
+            .field("parameters", &self.parameters)
+            .field("required", &self.required)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Role {
+    /// Create a role called `name` whose resources are produced by `resources`.
+    pub fn new(
+        name: impl AsRef<str>,
+        resources: impl Fn(&Parameters) -> Vec<Resource> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name:         name.as_ref().to_string(),
+            dependencies: Vec::new(),
+            parameters:   Parameters::new(),
+            required:     Vec::new(),
+            resources:    std::sync::Arc::new(resources),
+        }
+    }
+
+    /// Set the parameter `name` to `value`, overriding a previously set value. This
+    /// is used both to define defaults and to customize a shared role.
+    #[must_use]
+    pub fn parameter(mut self, name: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        self.parameters
+            .insert(name.as_ref().to_string(), value.as_ref().to_string());
+        self
+    }
+
+    /// Require the parameter `name` to be set before the resources are produced,
+    /// instead of leaving it to the resource function to cope with its absence.
+    #[must_use]
+    pub fn requires(mut self, name: impl AsRef<str>) -> Self {
+        self.required.push(name.as_ref().to_string());
+        self
+    }
+
+    /// Ensure the role called `role` before this one.
+    #[must_use]
+    pub fn depends_on(mut self, role: impl AsRef<str>) -> Self {
+        self.dependencies.push(role.as_ref().to_string());
+        self
+    }
+
+    /// The name of the role.
+    #[must_use]
+    pub fn name(&self) -> &str { &self.name }
+
+    /// The resources of this role alone, without those of its dependencies.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a required parameter is not set.
+    pub fn resources(&self) -> EnsureResult<Vec<Resource>> {
+        if let Some(missing) = self
+            .required
+            .iter()
+            .find(|name| !self.parameters.contains_key(*name))
+        {
+            return Err(EnsureError::MissingParameter(
+                self.name.clone(),
+                missing.clone(),
+            ));
+        }
+        Ok((self.resources)(&self.parameters))
+    }
+}
+
+/// A set of roles that can refer to each other, e.g. everything a fleet of machines
+/// is provisioned from. A machine then applies a selection of the roles.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// The known roles, keyed by their name
+    roles: std::collections::BTreeMap<String, Role>,
+}
+
+impl Profile {
+    /// Create a profile without any roles.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Add `role`, replacing a role with the same name.
+    #[must_use]
+    pub fn role(mut self, role: Role) -> Self {
+        self.roles.insert(role.name.clone(), role);
+        self
+    }
+
+    /// Add the role called `name` and its dependencies to `order`, dependencies
+    /// first. `visiting` contains the roles currently being resolved to detect
+    /// cycles.
+    fn resolve<'a>(
+        &'a self,
+        name: &str,
+        visiting: &mut Vec<&'a str>,
+        order: &mut Vec<&'a Role>,
+    ) -> EnsureResult<()> {
+        let role = self
+            .roles
+            .get(name)
+            .ok_or_else(|| EnsureError::UnknownRole(name.to_string()))?;
+        if order.iter().any(|resolved| resolved.name == name) {
+            return Ok(());
+        }
+        if visiting.contains(&role.name.as_str()) {
+            visiting.push(&role.name);
+            return Err(EnsureError::RoleCycle(visiting.join(" -> ")));
+        }
+
+        visiting.push(&role.name);
+        for dependency in &role.dependencies {
+            self.resolve(dependency, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(role);
+        Ok(())
+    }
+
+    /// The resources of the roles called `names` and all roles they depend on, in
+    /// the order they need to be ensured. Every role is included only once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a role is unknown, roles depend on each other in a cycle
+    /// or a role misses a required parameter.
+    pub fn resources(&self, names: &[&str]) -> EnsureResult<Vec<Resource>> {
+        let mut order = Vec::new();
+        for name in names {
+            self.resolve(name, &mut Vec::new(), &mut order)?;
+        }
+        log::debug!(
+            "Resolved roles: {}",
+            order
+                .iter()
+                .map(|role| role.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let mut resources = Vec::new();
+        for role in order {
+            resources.extend(role.resources()?);
+        }
+        Ok(resources)
+    }
+
+    /// Compare the resources of the roles called `names` (and their dependencies)
+    /// with the machine, see [`super::plan`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the roles cannot be resolved or the machine cannot be
+    /// inspected.
+    pub fn plan(&self, names: &[&str]) -> EnsureResult<Plan> {
+        super::plan(&self.resources(names)?)
+    }
+
+    /// Bring the machine into the state described by the roles called `names` (and
+    /// their dependencies), see [`super::ensure`]. To also remove what earlier runs
+    /// managed but the roles do not declare anymore, pass [`Profile::resources`] to
+    /// [`super::State`] instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the roles cannot be resolved or the machine cannot be
+    /// changed.
+    pub fn apply(&self, names: &[&str]) -> EnsureResult<()> {
+        super::ensure(&self.resources(names)?)
+    }
+}
+
+#[cfg(test)]
+mod profile_test {
+    use super::*;
+
+    /// A role with a single directory resource named after the role.
+    fn role(name: &str) -> Role {
+        Role::new(name, |parameters| {
+            vec![Resource::Directory {
+                path: std::path::PathBuf::from(&parameters["path"]),
+                mode: None,
+            }]
+        })
+        .requires("path")
+        .parameter("path", format!("/{name}"))
+    }
+
+    /// The paths of `resources`.
+    fn paths(resources: &[Resource]) -> Vec<String> {
+        resources
+            .iter()
+            .map(|resource| match resource {
+                Resource::Directory { path, .. } => path.to_string_lossy().into_owned(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn composition() -> EnsureResult<()> {
+        let profile = Profile::new()
+            .role(role("base"))
+            .role(role("webserver").depends_on("base"))
+            .role(
+                role("monitoring")
+                    .depends_on("base")
+                    .parameter("path", "/opt/monitoring"),
+            );
+
+        assert_eq!(
+            paths(&profile.resources(&["webserver", "monitoring"])?),
+            ["/base", "/webserver", "/opt/monitoring"]
+        );
+        assert_eq!(paths(&profile.resources(&["base"])?), ["/base"]);
+        Ok(())
+    }
+
+    #[test]
+    fn resolution_errors() {
+        let profile = Profile::new()
+            .role(role("a").depends_on("b"))
+            .role(role("b").depends_on("a"))
+            .role(role("c").depends_on("missing"))
+            .role(Role::new("d", |_| Vec::new()).requires("port"));
+        assert_eq!(
+            profile.resources(&["a"]),
+            Err(EnsureError::RoleCycle(String::from("a -> b -> a")))
+        );
+        assert_eq!(
+            profile.resources(&["c"]),
+            Err(EnsureError::UnknownRole(String::from("missing")))
+        );
+        assert_eq!(
+            profile.resources(&["d"]),
+            Err(EnsureError::MissingParameter(
+                String::from("d"),
+                String::from("port")
+            ))
+        );
+    }
+
+    #[test]
+    fn apply() -> EnsureResult<()> {
+        let root = crate::fs::generate_test_path();
+        let profile =
+            Profile::new().role(role("app").parameter("path", root.join("app").to_string_lossy()));
+        profile.apply(&["app"])?;
+        assert!(root.join("app").is_dir());
+        assert!(profile.plan(&["app"])?.is_empty());
+
+        std::fs::remove_dir_all(root).map_err(crate::fs::FSError::from)?;
+        Ok(())
+    }
+}
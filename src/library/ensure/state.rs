@@ -0,0 +1,424 @@
+//! This module contains the state file of the ensure layer, which records the
+//! resources a script manages across runs.
+
+use super::{
+    Action,
+    Change,
+    EnsureError,
+    EnsureResult,
+    Plan,
+    Resource,
+};
+use crate::fs::{
+    FSError,
+    FSResult,
+};
+
+/// The version of the state file format written by this module.
+const FORMAT_VERSION: u32 = 1;
+
+/// A managed resource as recorded in the state file. File contents are only
+/// recorded as a digest, so secrets never end up in the state file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    /// A [`Resource::File`]
+    File {
+        /// The path of the file
+        path:           std::path::PathBuf,
+        /// The SHA-256 digest of the content of the file
+        content_sha256: String,
+        /// The permission bits of the file, if given
+        mode:           Option<u32>,
+    },
+    /// A [`Resource::Directory`]
+    Directory {
+        /// The path of the directory
+        path: std::path::PathBuf,
+        /// The permission bits of the directory, if given
+        mode: Option<u32>,
+    },
+    /// A [`Resource::Symlink`]
+    Symlink {
+        /// The path of the link
+        path:   std::path::PathBuf,
+        /// The path the link points to
+        target: std::path::PathBuf,
+    },
+    /// A [`Resource::User`]
+    User {
+        /// The name of the user
+        name: String,
+    },
+}
+
+impl Record {
+    /// The record of `resource`, or [`None`] for [`Resource::Absent`], which is not
+    /// managed.
+    fn new(resource: &Resource) -> Option<Self> {
+        match resource {
+            Resource::File {
+                path,
+                content,
+                mode,
+            } => Some(Self::File {
+                path:           path.clone(),
+                content_sha256: super::sha256(content.as_bytes()),
+                mode:           *mode,
+            }),
+            Resource::Directory { path, mode } => Some(Self::Directory {
+                path: path.clone(),
+                mode: *mode,
+            }),
+            Resource::Symlink { path, target } => Some(Self::Symlink {
+                path:   path.clone(),
+                target: target.clone(),
+            }),
+            Resource::User { name } => Some(Self::User { name: name.clone() }),
+            Resource::Absent { .. } => None,
+        }
+    }
+
+    /// The resource to remove when the record is not declared anymore. As the
+    /// content of a file is not recorded, the resource of a file is empty, which
+    /// does not matter for removing it.
+    fn resource(&self) -> Resource {
+        match self {
+            Self::File { path, mode, .. } => Resource::File {
+                path:    path.clone(),
+                content: String::new(),
+                mode:    *mode,
+            },
+            Self::Directory { path, mode } => Resource::Directory {
+                path: path.clone(),
+                mode: *mode,
+            },
+            Self::Symlink { path, target } => Resource::Symlink {
+                path:   path.clone(),
+                target: target.clone(),
+            },
+            Self::User { name } => Resource::User { name: name.clone() },
+        }
+    }
+}
+
+/// The content of the state file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Content {
+    /// The format version, for future migrations
+    version:   u32,
+    /// The resources managed by the script
+    resources: Vec<Record>,
+}
+
+/// Records which resources a script manages across runs.
+///
+/// With the state, resources the script no longer declares (e.g. because they were
+/// renamed) can be removed, and changes made to managed resources by someone else
+/// between runs can be detected. The state file is only readable by its owner and
+/// records file contents as digests.
+///
+/// ```no_run
+/// # use rush::ensure::{apply, State, Resource};
+/// # let resources: Vec<Resource> = vec![];
+/// let mut state = State::load("/var/lib/provisioning/state.json").unwrap();
+/// let plan = state.plan(&resources).unwrap();
+/// println!("{plan}");
+/// apply(&plan).unwrap();
+/// state.record(&resources).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    /// The state file
+    path:      std::path::PathBuf,
+    /// The resources recorded in the state file
+    resources: Vec<Record>,
+}
+
+impl State {
+    /// Load the state file at `path`. A missing file is treated as an empty state,
+    /// which is what the first run of a script sees.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn load(path: impl AsRef<std::path::Path>) -> EnsureResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let resources = match std::fs::read_to_string(&path) {
+            Ok(json) => {
+                let content: Content = serde_json::from_str(&json)
+                    .map_err(|error| EnsureError::InvalidState(error.to_string()))?;
+                if content.version != FORMAT_VERSION {
+                    return Err(EnsureError::InvalidState(format!(
+                        "unsupported version {}",
+                        content.version
+                    )));
+                }
+                content.resources
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(FSError::from(error).into()),
+        };
+        Ok(Self { path, resources })
+    }
+
+    /// Whether nothing is recorded, e.g. on the first run.
+    #[must_use]
+    pub fn is_empty(&self) -> bool { self.resources.is_empty() }
+
+    /// Whether the path (or user name) of `resource` is recorded as managed.
+    #[must_use]
+    pub fn is_managed(&self, resource: &Resource) -> bool {
+        let key = resource.key();
+        self.resources
+            .iter()
+            .any(|record| record.resource().key() == key)
+    }
+
+    /// Record `resources` as the managed resources and write the state file. The
+    /// file is replaced atomically, so an interrupted run never leaves a truncated
+    /// state behind. [`Resource::Absent`] is not recorded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state file cannot be written.
+    pub fn record(&mut self, resources: &[Resource]) -> EnsureResult<()> {
+        use std::io::Write as _;
+
+        self.resources = resources.iter().filter_map(Record::new).collect();
+        let content = Content {
+            version:   FORMAT_VERSION,
+            resources: self.resources.clone(),
+        };
+        let json = serde_json::to_string_pretty(&content).unwrap_or_default();
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(FSError::from)?;
+        }
+        crate::fs::write_atomic_with(&self.path, Some(0o600), |writer| -> FSResult<()> {
+            Ok(writer.write_all(json.as_bytes())?)
+        })?;
+        Ok(())
+    }
+
+    /// The recorded resources that are not among `resources` anymore, as a plan
+    /// removing them. Resources whose path (or user name) is still declared by
+    /// another resource are kept, and so are directories containing a declared path.
+    /// Nested paths are removed before their parents.
+    #[must_use]
+    pub fn unmanaged(&self, resources: &[Resource]) -> Plan {
+        let declared = resources.iter().map(Resource::key).collect::<Vec<_>>();
+        let declared_paths = resources
+            .iter()
+            .filter_map(Resource::path)
+            .collect::<Vec<_>>();
+        let mut changes = self
+            .resources
+            .iter()
+            .map(Record::resource)
+            .filter(|resource| !declared.contains(&resource.key()))
+            .filter(|resource| {
+                resource.path().is_none_or(|path| {
+                    !declared_paths
+                        .iter()
+                        .any(|declared| declared.starts_with(path))
+                })
+            })
+            .map(|resource| Change {
+                resource,
+                action: Action::Remove,
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by_key(|change| {
+            std::cmp::Reverse(
+                change
+                    .resource
+                    .path()
+                    .map_or(0, |path| path.components().count()),
+            )
+        });
+        Plan { changes }
+    }
+
+    /// The changes required to bring the machine into the state described by
+    /// `resources`, including the removal of resources that are recorded but no
+    /// longer declared.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the machine cannot be inspected, or if an object of a
+    /// different type is in the way of a resource.
+    pub fn plan(&self, resources: &[Resource]) -> EnsureResult<Plan> {
+        let mut plan = super::plan(resources)?;
+        for change in self.unmanaged(resources).changes {
+            if change.resource.exists()? {
+                plan.changes.push(change);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Remove the recorded resources that are not among `resources` anymore and
+    /// record `resources` as managed. Returns the plan that was applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a resource cannot be removed or the state file cannot be
+    /// written.
+    pub fn cleanup_unmanaged(&mut self, resources: &[Resource]) -> EnsureResult<Plan> {
+        let mut plan = self.unmanaged(resources);
+        let mut existing = Vec::with_capacity(plan.changes.len());
+        for change in plan.changes {
+            if change.resource.exists()? {
+                existing.push(change);
+            }
+        }
+        plan.changes = existing;
+
+        super::apply(&plan)?;
+        self.record(resources)?;
+        Ok(plan)
+    }
+
+    /// The changes required to bring those of `resources` that are declared exactly
+    /// as in the last run back into their recorded state, i.e. modifications made by
+    /// someone else since then. Resources declared differently than in the last run
+    /// are changes of the script, not drift. An empty plan means nothing drifted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the machine cannot be inspected, or if an object of a
+    /// different type is in the way of a resource.
+    pub fn drift(&self, resources: &[Resource]) -> EnsureResult<Plan> {
+        let unchanged = resources
+            .iter()
+            .filter(|resource| {
+                Record::new(resource).is_some_and(|record| self.resources.contains(&record))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        super::plan(&unchanged)
+    }
+}
+
+#[cfg(test)]
+mod state_test {
+    use super::*;
+
+    #[test]
+    fn cleanup_and_drift() -> EnsureResult<()> {
+        let root = crate::fs::generate_test_path();
+        let state_path = root.join("state.json");
+        let file = |name: &str| Resource::File {
+            path:    root.join(name),
+            content: String::from("content"),
+            mode:    None,
+        };
+
+        let mut state = State::load(&state_path)?;
+        assert!(state.is_empty());
+        let first_run = [file("old-name"), file("kept")];
+        crate::ensure::apply(&state.plan(&first_run)?)?;
+        state.record(&first_run)?;
+
+        // The script renamed a resource: the old one is removed.
+        let mut state = State::load(&state_path)?;
+        assert!(first_run.iter().all(|resource| state.is_managed(resource)));
+        let second_run = [file("new-name"), file("kept")];
+        let plan = state.plan(&second_run)?;
+        assert_eq!(
+            plan.to_string().lines().collect::<Vec<_>>()[..2],
+            [
+                format!("+ file '{}'", root.join("new-name").to_string_lossy()),
+                format!("- file '{}'", root.join("old-name").to_string_lossy()),
+            ]
+        );
+        let removed = state.cleanup_unmanaged(&second_run)?;
+        assert_eq!(removed.changes.len(), 1);
+        assert!(!root.join("old-name").exists());
+
+        crate::ensure::apply(&crate::ensure::plan(&second_run)?)?;
+        assert!(state.drift(&second_run)?.is_empty());
+
+        // Someone edited a managed file between runs.
+        std::fs::write(root.join("kept"), "edited").map_err(FSError::from)?;
+        assert_eq!(
+            state.drift(&second_run)?.changes[0].action,
+            Action::Update {
+                reasons: vec![String::from("content")],
+            }
+        );
+        // A resource the script changed itself did not drift.
+        let third_run = [
+            file("new-name"),
+            Resource::File {
+                path:    root.join("kept"),
+                content: String::from("new content"),
+                mode:    None,
+            },
+        ];
+        assert!(state.drift(&third_run)?.is_empty());
+
+        std::fs::remove_dir_all(root).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    #[test]
+    fn no_secrets() -> EnsureResult<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = crate::fs::generate_test_path();
+        let mut state = State::load(root.join("state.json"))?;
+        state.record(&[Resource::File {
+            path:    root.join("credentials"),
+            content: String::from("password=hunter2"),
+            mode:    Some(0o600),
+        }])?;
+
+        let json = std::fs::read_to_string(root.join("state.json")).map_err(FSError::from)?;
+        assert!(!json.contains("hunter2"));
+        assert!(json.contains(&crate::ensure::sha256(b"password=hunter2")));
+        let metadata = std::fs::metadata(root.join("state.json")).map_err(FSError::from)?;
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        std::fs::remove_dir_all(root).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    #[test]
+    fn nested_resources() -> EnsureResult<()> {
+        let root = crate::fs::generate_test_path();
+        let directory = Resource::Directory {
+            path: root.join("app"),
+            mode: None,
+        };
+        let file = Resource::File {
+            path:    root.join("app/config"),
+            content: String::from("content"),
+            mode:    None,
+        };
+
+        let mut state = State::load(root.join("state.json"))?;
+        crate::ensure::ensure(&[directory.clone(), file.clone()])?;
+        state.record(&[directory.clone(), file.clone()])?;
+
+        // The directory is not declared anymore, but still holds a declared file.
+        assert!(state.unmanaged(std::slice::from_ref(&file)).is_empty());
+        state.cleanup_unmanaged(std::slice::from_ref(&file))?;
+        assert!(root.join("app/config").exists());
+
+        // Removing everything removes the file before its directory.
+        state.record(&[directory.clone(), file])?;
+        assert_eq!(state.cleanup_unmanaged(&[])?.changes.len(), 2);
+        assert!(!root.join("app").exists());
+
+        // Content the script does not manage is never removed.
+        crate::ensure::ensure(std::slice::from_ref(&directory))?;
+        std::fs::write(root.join("app/foreign"), "content").map_err(FSError::from)?;
+        assert!(directory.apply(&Action::Remove).is_err());
+        assert!(root.join("app/foreign").exists());
+
+        std::fs::remove_dir_all(root).map_err(FSError::from)?;
+        Ok(())
+    }
+}
@@ -5,6 +5,9 @@ pub enum EnvironmentError {
     NonExistent,
     #[error("The requested object already exists")]
     AlreadyExists,
+    #[cfg(not(target_os = "wasi"))]
+    #[error("Running the command failed: {0}")]
+    Process(#[from] crate::process::ProcessError),
     #[error("A completely unexpected error occurred")]
     Unknown(String),
 }
@@ -16,6 +19,11 @@ impl From<std::env::VarError> for EnvironmentError {
 /// A [`Result`] whose error variant is a [`EnvironmentError`].
 pub type EnvironmentResult<T> = Result<T, EnvironmentError>;
 
+/// Variables a shell maintains itself, which [`Environment::from_command_exports`]
+/// does not import.
+#[cfg(not(target_os = "wasi"))]
+const SHELL_VARIABLES: [&str; 4] = ["_", "OLDPWD", "PWD", "SHLVL"];
+
 /// TODO
 #[derive(Debug)]
 pub struct Environment {
@@ -30,7 +38,14 @@ impl Environment {
     }
 
     pub fn add_from_process_environment(&mut self, var_name: &str) -> EnvironmentResult<()> {
-        match std::env::var(var_name) {
+        let value = crate::instrument::observe(
+            crate::instrument::Subsystem::Environment,
+            "read",
+            &var_name,
+            None,
+            || std::env::var(var_name),
+        );
+        match value {
             Ok(value) => self.inner.insert(var_name.to_string(), value),
             Err(error) => {
                 log::warn!(
@@ -43,6 +58,40 @@ impl Environment {
         Ok(())
     }
 
+    /// Run the shell snippet `command` in a subshell and import the environment it
+    /// leaves behind. This is the safe replacement for `source vendor-sdk/env.sh`,
+    /// which would run the script in our own process.
+    ///
+    /// Variables the shell maintains itself, like `PWD`, are not imported.
+    ///
+    /// ```no_run
+    /// # use rush::environment::Environment;
+    /// let sdk = Environment::from_command_exports(". vendor-sdk/env.sh").unwrap();
+    /// println!("The SDK is in {:?}", sdk.get("SDK_ROOT"));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subshell cannot be run or `command` fails.
+    #[cfg(not(target_os = "wasi"))]
+    pub fn from_command_exports(command: &str) -> EnvironmentResult<Self> {
+        // `eval` runs the snippet in the shell whose environment is printed, so that
+        // sourced scripts take effect.
+        let output = crate::process::Command::new("sh")
+            .args(["-c", "eval \"$1\" && exec env -0", "sh", command])
+            .run()?;
+        let mut environment = Self::new();
+        for entry in output.stdout.split('\0') {
+            let Some((var_name, var_value)) = entry.split_once('=') else {
+                continue;
+            };
+            if !SHELL_VARIABLES.contains(&var_name) {
+                environment.add(var_name, var_value)?;
+            }
+        }
+        Ok(environment)
+    }
+
     pub fn parse_whole_process_environment(&mut self) -> EnvironmentResult<()> {
         for (var_name, var_value) in std::env::vars() {
             self.add(&var_name, &var_value)?;
@@ -58,6 +107,12 @@ impl Environment {
         Ok(())
     }
 
+    /// The value of `var_name`, if it was added.
+    #[must_use]
+    pub fn get(&self, var_name: &str) -> Option<&str> {
+        self.inner.get(var_name).map(String::as_str)
+    }
+
     pub fn add_with_default(&mut self, var_name: &str, default: &str) -> EnvironmentResult<()> {
         if self.add_from_process_environment(var_name).is_err() {
             self.add(var_name, default)?;
@@ -67,7 +122,38 @@ impl Environment {
     }
 
     pub fn export_to_process_environment(var_name: &str, var_value: &str) -> EnvironmentResult<()> {
-        std::env::set_var(var_name, var_value);
+        crate::instrument::observe(
+            crate::instrument::Subsystem::Environment,
+            "export",
+            &var_name,
+            None,
+            || {
+                std::env::set_var(var_name, var_value);
+                Ok(())
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod environment_test {
+    use super::*;
+
+    #[test]
+    fn from_command_exports() -> EnvironmentResult<()> {
+        let environment = Environment::from_command_exports(concat!(
+            "export SDK_ROOT=/opt/sdk\n",
+            "SDK_BANNER=$(printf 'multiple\\nlines') && export SDK_BANNER\n",
+            "cd /",
+        ))?;
+        assert_eq!(environment.get("SDK_ROOT"), Some("/opt/sdk"));
+        assert_eq!(environment.get("SDK_BANNER"), Some("multiple\nlines"));
+        assert_eq!(environment.get("PWD"), None);
+
+        assert!(matches!(
+            Environment::from_command_exports("false"),
+            Err(EnvironmentError::Process(_))
+        ));
         Ok(())
     }
 }
@@ -0,0 +1,380 @@
+//! This module contains a small, stable C ABI for the high-level operations.
+//!
+//! Programs written in other languages call into the same implementations. The
+//! declarations are in `include/rush.h`; build the library with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib` (or
+//! `staticlib`).
+//!
+//! Functions return `0` (or a non-negative result) on success and `-1` on failure,
+//! in which case [`rush_last_error`] describes what went wrong. Strings are
+//! NUL-terminated UTF-8. Strings returned by the library are owned by the caller
+//! and released with the matching `*_free` function.
+//!
+//! With the `python` feature, [`python`] exposes the same operations as a Python
+//! extension module.
+
+use std::{
+    cell::RefCell,
+    ffi::{
+        c_char,
+        c_int,
+        c_long,
+        CStr,
+        CString,
+    },
+};
+
+#[cfg(feature = "python")]
+pub mod python;
+
+use crate::{
+    ensure::{
+        EnsureResult,
+        Resource,
+    },
+    fs::FSResult,
+    process::{
+        Command,
+        Output,
+        ProcessResult,
+    },
+};
+
+/// The result of a failed call.
+const FAILURE: c_int = -1;
+
+thread_local! {
+    /// The message of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Convert `text` into a string owned by the caller, dropping NUL characters,
+/// which C strings cannot contain.
+fn to_c(text: &str) -> *mut c_char {
+    CString::new(text.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// Record `error` as the last error of this thread and return [`FAILURE`].
+fn fail(error: impl std::fmt::Display) -> c_int {
+    let message = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    FAILURE
+}
+
+/// Read the string argument `name` from `pointer`.
+///
+/// # Safety
+///
+/// `pointer` is NULL or points to a NUL-terminated string.
+unsafe fn argument<'a>(pointer: *const c_char, name: &str) -> Result<&'a str, String> {
+    if pointer.is_null() {
+        return Err(format!("The argument '{name}' is NULL"));
+    }
+    // SAFETY: The caller guarantees that the non-NULL pointer is a C string.
+    unsafe { CStr::from_ptr(pointer) }
+        .to_str()
+        .map_err(|_| format!("The argument '{name}' is not valid UTF-8"))
+}
+
+/// Copy the directory `source` with everything in it to `destination`, which is
+/// created if needed. Symbolic links are recreated, not followed.
+fn copy_tree(source: &std::path::Path, destination: &std::path::Path) -> FSResult<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Ensure a file at `path` with `content` and, if given, permission bits `mode`.
+/// Returns whether the file was changed.
+fn ensure_file(path: &str, content: &str, mode: Option<u32>) -> EnsureResult<bool> {
+    let resource = Resource::File {
+        path: path.into(),
+        content: content.to_string(),
+        mode,
+    };
+    let Some(action) = resource.check()? else {
+        return Ok(false);
+    };
+    resource.apply(&action)?;
+    Ok(true)
+}
+
+/// Run the program with the arguments in `argv` to completion, killing it after
+/// `timeout` if given.
+fn run_command(argv: &[String], timeout: Option<std::time::Duration>) -> ProcessResult<Output> {
+    let Some((program, arguments)) = argv.split_first() else {
+        return Err(crate::process::ProcessError::NotFound);
+    };
+    let command = Command::new(program).args(arguments);
+    match timeout {
+        Some(timeout) => command.timeout(timeout).output(),
+        None => command.output(),
+    }
+}
+
+/// The message of the last failed call on this thread, or NULL if no call failed
+/// yet. The string is owned by the library and valid until the next failing call on
+/// this thread.
+#[no_mangle]
+#[must_use]
+pub extern "C" fn rush_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Copy the directory `source` with everything in it to `destination`. Returns `0`
+/// on success.
+///
+/// # Safety
+///
+/// Both arguments are NULL or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rush_copy_tree(
+    source: *const c_char,
+    destination: *const c_char,
+) -> c_int {
+    // SAFETY: The caller upholds the contract of `argument`.
+    let arguments = unsafe {
+        (
+            argument(source, "source"),
+            argument(destination, "destination"),
+        )
+    };
+    let (source, destination) = match arguments {
+        (Ok(source), Ok(destination)) => (source, destination),
+        (Err(error), _) | (_, Err(error)) => return fail(error),
+    };
+    match copy_tree(source.as_ref(), destination.as_ref()) {
+        Ok(()) => 0,
+        Err(error) => fail(error),
+    }
+}
+
+/// Ensure a file at `path` with exactly `content` and, if `mode` is not negative,
+/// these permission bits. Returns `1` if the file was changed and `0` if it already
+/// was as desired.
+///
+/// # Safety
+///
+/// `path` and `content` are NULL or point to NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rush_ensure_file(
+    path: *const c_char,
+    content: *const c_char,
+    mode: c_long,
+) -> c_int {
+    // SAFETY: The caller upholds the contract of `argument`.
+    let arguments = unsafe { (argument(path, "path"), argument(content, "content")) };
+    let (path, content) = match arguments {
+        (Ok(path), Ok(content)) => (path, content),
+        (Err(error), _) | (_, Err(error)) => return fail(error),
+    };
+    let mode = match u32::try_from(mode) {
+        Ok(mode) => Some(mode),
+        Err(_) if mode < 0 => None,
+        Err(_) => return fail(format!("The mode {mode:o} is invalid")),
+    };
+    match ensure_file(path, content, mode) {
+        Ok(changed) => c_int::from(changed),
+        Err(error) => fail(error),
+    }
+}
+
+/// The result of [`rush_run_command`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct RushOutput {
+    /// The exit code, or `-1` if the program was terminated by a signal
+    pub exit_code: c_int,
+    /// Everything the program wrote to standard output
+    pub stdout:    *mut c_char,
+    /// Everything the program wrote to standard error
+    pub stderr:    *mut c_char,
+}
+
+/// Run the program `argv[0]` with the arguments `argv[1..]` to completion.
+///
+/// `argv` ends with NULL. If `timeout_milliseconds` is not `0`, the program is killed
+/// after that long and the call fails. A non-zero exit code is _not_ a failure.
+/// Returns `0` on success and fills `output`, which is then released with
+/// [`rush_output_free`].
+///
+/// # Safety
+///
+/// `argv` is NULL or a NULL-terminated array of NUL-terminated strings; `output` is
+/// NULL or points to writable memory for a [`RushOutput`].
+#[no_mangle]
+pub unsafe extern "C" fn rush_run_command(
+    argv: *const *const c_char,
+    timeout_milliseconds: u64,
+    output: *mut RushOutput,
+) -> c_int {
+    if argv.is_null() || output.is_null() {
+        return fail("The arguments 'argv' and 'output' must not be NULL");
+    }
+    let mut arguments = vec![];
+    for index in 0.. {
+        // SAFETY: The array is NULL-terminated, so every index up to the NULL is valid.
+        let pointer = unsafe { *argv.add(index) };
+        if pointer.is_null() {
+            break;
+        }
+        // SAFETY: The caller guarantees that all entries are C strings.
+        match unsafe { argument(pointer, "argv") } {
+            Ok(argument) => arguments.push(argument.to_string()),
+            Err(error) => return fail(error),
+        }
+    }
+
+    let timeout =
+        (timeout_milliseconds > 0).then(|| std::time::Duration::from_millis(timeout_milliseconds));
+    match run_command(&arguments, timeout) {
+        Ok(result) => {
+            let result = RushOutput {
+                exit_code: result.code.unwrap_or(-1),
+                stdout:    to_c(&result.stdout),
+                stderr:    to_c(&result.stderr),
+            };
+            // SAFETY: The caller guarantees that `output` is writable.
+            unsafe { output.write(result) };
+            0
+        },
+        Err(error) => fail(error),
+    }
+}
+
+/// Release the strings of an `output` filled by [`rush_run_command`] and set them
+/// to NULL.
+///
+/// # Safety
+///
+/// `output` is NULL or points to a [`RushOutput`] filled by [`rush_run_command`]
+/// whose strings were not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rush_output_free(output: *mut RushOutput) {
+    // SAFETY: The caller guarantees that a non-NULL `output` is valid.
+    let Some(output) = (unsafe { output.as_mut() }) else {
+        return;
+    };
+    for string in [&mut output.stdout, &mut output.stderr] {
+        if !string.is_null() {
+            // SAFETY: The string was created by `to_c` and is released only once.
+            drop(unsafe { CString::from_raw(*string) });
+            *string = std::ptr::null_mut();
+        }
+    }
+}
+
+#[cfg(test)]
+mod ffi_test {
+    use super::*;
+
+    /// A C string for the test, which outlives the call it is passed to.
+    fn c_string(text: &str) -> CString { CString::new(text).unwrap() }
+
+    /// The last error of this thread.
+    fn last_error() -> String {
+        // SAFETY: The library returns NULL or a valid C string.
+        unsafe { CStr::from_ptr(rush_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn copy_and_ensure() {
+        let directory = crate::fs::generate_test_path();
+        let source = directory.join("source");
+        std::fs::create_dir_all(source.join("nested")).unwrap();
+        std::fs::write(source.join("nested/file"), "content").unwrap();
+        std::os::unix::fs::symlink("nested/file", source.join("link")).unwrap();
+        let destination = directory.join("destination");
+
+        let path = |path: &std::path::Path| c_string(path.to_str().unwrap());
+        // SAFETY: Both arguments are C strings.
+        let result = unsafe { rush_copy_tree(path(&source).as_ptr(), path(&destination).as_ptr()) };
+        assert_eq!(result, 0);
+        assert_eq!(
+            std::fs::read_to_string(destination.join("nested/file")).unwrap(),
+            "content"
+        );
+        assert_eq!(
+            std::fs::read_link(destination.join("link")).unwrap(),
+            std::path::Path::new("nested/file")
+        );
+
+        let file = path(&directory.join("ensured"));
+        let content = c_string("content\n");
+        // SAFETY: Both arguments are C strings.
+        let ensure = || unsafe { rush_ensure_file(file.as_ptr(), content.as_ptr(), 0o600) };
+        assert_eq!(ensure(), 1);
+        assert_eq!(ensure(), 0);
+
+        // SAFETY: NULL is allowed.
+        let result = unsafe { rush_copy_tree(std::ptr::null(), file.as_ptr()) };
+        assert_eq!(result, FAILURE);
+        assert_eq!(last_error(), "The argument 'source' is NULL");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn run_command() {
+        let arguments = [
+            c_string("sh"),
+            c_string("-c"),
+            c_string("echo out; echo err >&2; exit 3"),
+        ];
+        let mut argv: Vec<*const c_char> =
+            arguments.iter().map(|argument| argument.as_ptr()).collect();
+        argv.push(std::ptr::null());
+        let mut output = RushOutput {
+            exit_code: 0,
+            stdout:    std::ptr::null_mut(),
+            stderr:    std::ptr::null_mut(),
+        };
+
+        // SAFETY: `argv` is NULL-terminated and `output` is writable.
+        assert_eq!(
+            unsafe { rush_run_command(argv.as_ptr(), 5000, &raw mut output) },
+            0
+        );
+        assert_eq!(output.exit_code, 3);
+        // SAFETY: The library filled in C strings.
+        let (stdout, stderr) =
+            unsafe { (CStr::from_ptr(output.stdout), CStr::from_ptr(output.stderr)) };
+        assert_eq!(
+            (stdout.to_str().unwrap(), stderr.to_str().unwrap()),
+            ("out\n", "err\n")
+        );
+        // SAFETY: The strings were not released yet.
+        unsafe { rush_output_free(&raw mut output) };
+        assert!(output.stdout.is_null() && output.stderr.is_null());
+
+        let arguments = [c_string("sleep"), c_string("5")];
+        let mut argv: Vec<*const c_char> =
+            arguments.iter().map(|argument| argument.as_ptr()).collect();
+        argv.push(std::ptr::null());
+        // SAFETY: `argv` is NULL-terminated and `output` is writable.
+        assert_eq!(
+            unsafe { rush_run_command(argv.as_ptr(), 100, &raw mut output) },
+            FAILURE
+        );
+        assert!(last_error().contains("did not finish"));
+    }
+}
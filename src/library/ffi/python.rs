@@ -0,0 +1,95 @@
+//! This module contains the Python extension module `rush`, which exposes the same
+//! operations as the C ABI. Build it with maturin and the `python` feature, enabling
+//! `pyo3/extension-module` for wheels:
+//!
+//! ```python
+//! import rush
+//!
+//! rush.copy_tree("/srv/app/releases/42", "/srv/app/current")
+//! changed = rush.ensure_file("/etc/app/config.toml", "port = 8080\n", 0o644)
+//! code, stdout, stderr = rush.run(["systemctl", "restart", "app"], timeout=30.0)
+//! ```
+//!
+//! Failures raise `OSError`, or `TimeoutError` if a program ran out of time. The
+//! global interpreter lock is released while an operation runs.
+
+use pyo3::{
+    exceptions::{
+        PyOSError,
+        PyTimeoutError,
+        PyValueError,
+    },
+    prelude::*,
+};
+
+use crate::process::ProcessError;
+
+/// Copy the directory `source` with everything in it to `destination`.
+///
+/// # Errors
+///
+/// Raises `OSError` if copying fails.
+#[pyfunction]
+pub fn copy_tree(
+    py: Python<'_>,
+    source: std::path::PathBuf,
+    destination: std::path::PathBuf,
+) -> PyResult<()> {
+    py.detach(move || super::copy_tree(&source, &destination))
+        .map_err(|error| PyOSError::new_err(error.to_string()))
+}
+
+/// Ensure a file at `path` with exactly `content` and, if given, permission bits
+/// `mode`. Returns whether the file was changed.
+///
+/// # Errors
+///
+/// Raises `OSError` if the file cannot be read or written.
+#[pyfunction]
+#[pyo3(signature = (path, content, mode = None))]
+pub fn ensure_file(
+    py: Python<'_>,
+    path: String,
+    content: String,
+    mode: Option<u32>,
+) -> PyResult<bool> {
+    py.detach(move || super::ensure_file(&path, &content, mode))
+        .map_err(|error| PyOSError::new_err(error.to_string()))
+}
+
+/// Run the program `argv[0]` with the arguments `argv[1:]` to completion.
+///
+/// The program is killed after `timeout` seconds if given. Returns the exit code
+/// (`None` if the program was terminated by a signal), standard output and
+/// standard error.
+///
+/// # Errors
+///
+/// Raises `ValueError` if `timeout` is negative or too large, `TimeoutError` if the
+/// program ran out of time, and `OSError` if it cannot be run.
+#[pyfunction]
+#[pyo3(signature = (argv, timeout = None))]
+pub fn run(
+    py: Python<'_>,
+    argv: Vec<String>,
+    timeout: Option<f64>,
+) -> PyResult<(Option<i32>, String, String)> {
+    let timeout = timeout
+        .map(std::time::Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(|error| PyValueError::new_err(error.to_string()))?;
+    match py.detach(move || super::run_command(&argv, timeout)) {
+        Ok(output) => Ok((output.code, output.stdout, output.stderr)),
+        Err(error @ ProcessError::TimedOut(_)) => Err(PyTimeoutError::new_err(error.to_string())),
+        Err(error) => Err(PyOSError::new_err(error.to_string())),
+    }
+}
+
+/// The extension module.
+#[pymodule]
+fn rush(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(copy_tree, module)?)?;
+    module.add_function(wrap_pyfunction!(ensure_file, module)?)?;
+    module.add_function(wrap_pyfunction!(run, module)?)?;
+    Ok(())
+}
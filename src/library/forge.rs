@@ -0,0 +1,458 @@
+//! This module contains functionality for automating releases on GitHub and GitLab
+//! (including GitHub Enterprise and self-hosted GitLab instances).
+//!
+//! Only what release scripts need is covered: creating releases, uploading and
+//! downloading assets, finding the latest release and commenting on pull requests
+//! (merge requests on GitLab).
+
+use crate::fs::{
+    FSError,
+    File,
+    Object as _,
+};
+
+/// How long to wait for the API to accept a connection.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long a single read or write may stall. There is no limit for whole requests
+/// because uploads of large assets take their time.
+const IO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(90);
+/// The version of the GitHub REST API that is used.
+const GITHUB_API_VERSION: &str = "2022-11-28";
+/// The name of the GitLab generic package assets are uploaded to.
+const GITLAB_ASSET_PACKAGE: &str = "release-assets";
+
+/// Describes possible errors when talking to a forge.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ForgeError {
+    #[error("The request for '{0}' failed: {1}")]
+    Request(String, String),
+    #[error("'{0}' does not exist")]
+    NotFound(String),
+    #[error("The response could not be understood: {0}")]
+    InvalidResponse(String),
+    #[error("Reading the asset failed: {0}")]
+    FS(#[from] FSError),
+    #[error("The request was aborted: {0}")]
+    DeadlineExceeded(#[from] crate::deadline::DeadlineExceeded),
+}
+
+/// A [`Result`] whose error variant is a [`ForgeError`].
+pub type ForgeResult<T> = Result<T, ForgeError>;
+
+/// The kind of forge a [`Client`] talks to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+/// A release, as returned by [`Client::create_release`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Release {
+    /// The tag the release belongs to
+    pub tag:  String,
+    /// The title of the release
+    pub name: String,
+    /// The ID of the release (GitHub only; GitLab identifies releases by tag)
+    pub id:   Option<u64>,
+    /// The web page of the release
+    pub url:  String,
+}
+
+/// An authenticated client for the API of a repository (GitHub) or project
+/// (GitLab).
+#[derive(Clone)]
+pub struct Client {
+    /// The kind of forge
+    forge:      Forge,
+    /// The base URL of the API, without a trailing slash
+    api:        String,
+    /// The repository (`owner/name`) or project (`group/name`)
+    repository: String,
+    /// The access token
+    token:      String,
+    /// The HTTP agent, which enforces timeouts
+    agent:      ureq::Agent,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("forge", &self.forge)
+            .field("api", &self.api)
+            .field("repository", &self.repository)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Create a client for `repository` (`owner/name`) on github.com, authenticated
+    /// with `token`.
+    pub fn github(repository: impl Into<String>, token: impl Into<String>) -> Self {
+        Self::new(Forge::GitHub, "https://api.github.com", repository, token)
+    }
+
+    /// Create a client for `project` (`group/name`) on gitlab.com, authenticated
+    /// with `token` (a personal, group or project access token).
+    pub fn gitlab(project: impl Into<String>, token: impl Into<String>) -> Self {
+        Self::new(Forge::GitLab, "https://gitlab.com/api/v4", project, token)
+    }
+
+    /// Create a client for a forge whose API is served at `api`, e.g.
+    /// `https://github.example.com/api/v3` or `https://gitlab.example.com/api/v4`.
+    pub fn new(
+        forge: Forge,
+        api: impl AsRef<str>,
+        repository: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        Self {
+            forge,
+            api: api.as_ref().trim_end_matches('/').to_string(),
+            repository: repository.into(),
+            token: token.into(),
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout_read(IO_TIMEOUT)
+                .timeout_write(IO_TIMEOUT)
+                .build(),
+        }
+    }
+
+    /// The URL of the repository's API endpoint `path`.
+    fn url(&self, path: &str) -> String {
+        match self.forge {
+            Forge::GitHub => format!("{}/repos/{}{path}", self.api, self.repository),
+            Forge::GitLab => format!(
+                "{}/projects/{}{path}",
+                self.api,
+                crate::net::uri_encode(&self.repository, false)
+            ),
+        }
+    }
+
+    /// Build an authenticated request to `url`, which is aborted when the
+    /// [`Deadline`](crate::deadline::Deadline) in effect passes.
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let mut request = self.agent.request(method, url);
+        if let Some(deadline) = crate::deadline::current() {
+            request = request.timeout(deadline.remaining().unwrap_or_default());
+        }
+        match self.forge {
+            Forge::GitHub => request
+                .set("Authorization", &format!("Bearer {}", self.token))
+                .set("Accept", "application/vnd.github+json")
+                .set("X-GitHub-Api-Version", GITHUB_API_VERSION),
+            Forge::GitLab => request.set("PRIVATE-TOKEN", &self.token),
+        }
+    }
+
+    /// Parse the `response` to a request. `what` describes the request in errors.
+    fn send(
+        what: &str,
+        response: Result<ureq::Response, ureq::Error>,
+    ) -> ForgeResult<serde_json::Value> {
+        match response {
+            Ok(response) => response
+                .into_json()
+                .map_err(|error| ForgeError::InvalidResponse(error.to_string())),
+            Err(ureq::Error::Status(404, _)) => Err(ForgeError::NotFound(what.to_string())),
+            Err(ureq::Error::Status(code, response)) => {
+                let message = response.into_string().unwrap_or_default();
+                Err(ForgeError::Request(
+                    what.to_string(),
+                    format!("the server answered with status {code}: {}", message.trim()),
+                ))
+            },
+            Err(ureq::Error::Transport(transport)) => {
+                crate::deadline::check()?;
+                Err(ForgeError::Request(what.to_string(), transport.to_string()))
+            },
+        }
+    }
+
+    /// Create a release for the existing tag `tag` with title `name` and
+    /// release notes `notes` (Markdown).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, e.g. because the release already
+    /// exists.
+    pub fn create_release(&self, tag: &str, name: &str, notes: &str) -> ForgeResult<Release> {
+        log::debug!("Creating release '{tag}' of '{}'", self.repository);
+        // GitHub calls the release notes `body`, GitLab `description`.
+        let notes_key = match self.forge {
+            Forge::GitHub => "body",
+            Forge::GitLab => "description",
+        };
+        let response = Self::send(
+            &format!("release '{tag}'"),
+            self.request("POST", &self.url("/releases"))
+                .send_json(serde_json::json!({ "tag_name": tag, "name": name, notes_key: notes })),
+        )?;
+        parse_release(self.forge, &response)
+    }
+
+    /// The tag of the latest release. Drafts and pre-releases are not considered on
+    /// GitHub.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or there is no release.
+    pub fn latest_release_tag(&self) -> ForgeResult<String> {
+        let path = match self.forge {
+            Forge::GitHub => "/releases/latest",
+            Forge::GitLab => "/releases/permalink/latest",
+        };
+        let response = Self::send(
+            "the latest release",
+            self.request("GET", &self.url(path)).call(),
+        )?;
+        Ok(parse_release(self.forge, &response)?.tag)
+    }
+
+    /// Attach `file` to `release` and return the URL it can be downloaded from. On
+    /// GitLab, the file is stored in the project's generic package registry and
+    /// linked from the release.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or the upload fails.
+    pub fn upload_asset(&self, release: &Release, file: &File) -> ForgeResult<String> {
+        let name = file
+            .path()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or(FSError::NonExistent)?;
+        log::debug!("Uploading {file} to release '{}'", release.tag);
+        let content = std::fs::File::open(file.path()).map_err(FSError::from)?;
+        let length = file.metadata()?.size.to_string();
+        let what = format!("asset '{name}'");
+
+        match self.forge {
+            Forge::GitHub => {
+                let id = release.id.ok_or_else(|| {
+                    ForgeError::InvalidResponse(String::from("the release has no ID"))
+                })?;
+                let url = format!(
+                    "{}/repos/{}/releases/{id}/assets?name={}",
+                    upload_api(&self.api),
+                    self.repository,
+                    crate::net::uri_encode(&name, false)
+                );
+                let response = Self::send(
+                    &what,
+                    self.request("POST", &url)
+                        .set("Content-Type", "application/octet-stream")
+                        .set("Content-Length", &length)
+                        .send(content),
+                )?;
+                json_string(&response, "browser_download_url")
+            },
+            Forge::GitLab => {
+                let url = self.url(&format!(
+                    "/packages/generic/{GITLAB_ASSET_PACKAGE}/{}/{}",
+                    crate::net::uri_encode(&release.tag, false),
+                    crate::net::uri_encode(&name, false)
+                ));
+                Self::send(
+                    &what,
+                    self.request("PUT", &url)
+                        .set("Content-Length", &length)
+                        .send(content),
+                )?;
+                Self::send(
+                    &what,
+                    self.request(
+                        "POST",
+                        &self.url(&format!(
+                            "/releases/{}/assets/links",
+                            crate::net::uri_encode(&release.tag, false)
+                        )),
+                    )
+                    .send_json(serde_json::json!({
+                        "name": name,
+                        "url": url,
+                        "link_type": "package"
+                    })),
+                )?;
+                Ok(url)
+            },
+        }
+    }
+
+    /// Download the asset called `name` of the release with tag `tag` to `target`,
+    /// overwriting it if it exists. On GitLab, assets are looked up in the package
+    /// registry, where [`Client::upload_asset`] stores them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the release or asset does not exist, the download fails or
+    /// `target` cannot be written, or if the
+    /// [`Deadline`](crate::deadline::Deadline) in effect passes.
+    pub fn download_asset(
+        &self,
+        tag: &str,
+        name: &str,
+        target: impl AsRef<std::path::Path>,
+    ) -> ForgeResult<()> {
+        log::debug!("Downloading asset '{name}' of release '{tag}'");
+        let what = format!("asset '{name}' of release '{tag}'");
+        let url = match self.forge {
+            Forge::GitHub => {
+                let release = Self::send(
+                    &format!("release '{tag}'"),
+                    self.request(
+                        "GET",
+                        &self.url(&format!(
+                            "/releases/tags/{}",
+                            crate::net::uri_encode(tag, false)
+                        )),
+                    )
+                    .call(),
+                )?;
+                // The API URL of an asset works for private repositories as well,
+                // unlike `browser_download_url`.
+                release["assets"]
+                    .as_array()
+                    .and_then(|assets| assets.iter().find(|asset| asset["name"] == name))
+                    .ok_or_else(|| ForgeError::NotFound(what.clone()))
+                    .and_then(|asset| json_string(asset, "url"))?
+            },
+            Forge::GitLab => self.url(&format!(
+                "/packages/generic/{GITLAB_ASSET_PACKAGE}/{}/{}",
+                crate::net::uri_encode(tag, false),
+                crate::net::uri_encode(name, false)
+            )),
+        };
+
+        let response = match self
+            .request("GET", &url)
+            .set("Accept", "application/octet-stream")
+            .call()
+        {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Err(ForgeError::NotFound(what)),
+            Err(error) => {
+                crate::deadline::check()?;
+                return Err(ForgeError::Request(what, error.to_string()));
+            },
+        };
+        let mut writer = std::fs::File::create(target).map_err(FSError::from)?;
+        std::io::copy(&mut response.into_reader(), &mut writer).map_err(|error| {
+            crate::deadline::check().map_or_else(ForgeError::from, |()| FSError::from(error).into())
+        })?;
+        Ok(())
+    }
+
+    /// Post `body` (Markdown) as a comment on the pull request (merge request on
+    /// GitLab) with number `number`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub fn comment(&self, number: u64, body: &str) -> ForgeResult<()> {
+        log::debug!("Commenting on #{number} of '{}'", self.repository);
+        let path = match self.forge {
+            // Pull requests are issues as far as comments are concerned.
+            Forge::GitHub => format!("/issues/{number}/comments"),
+            Forge::GitLab => format!("/merge_requests/{number}/notes"),
+        };
+        Self::send(
+            &format!("#{number}"),
+            self.request("POST", &self.url(&path))
+                .send_json(serde_json::json!({ "body": body })),
+        )?;
+        Ok(())
+    }
+}
+
+/// The base URL of GitHub's upload API that belongs to the REST API at `api`.
+fn upload_api(api: &str) -> String {
+    if api == "https://api.github.com" {
+        String::from("https://uploads.github.com")
+    } else {
+        // GitHub Enterprise serves uploads at `/api/uploads` instead of `/api/v3`.
+        api.strip_suffix("/v3")
+            .map_or_else(|| api.to_string(), |base| format!("{base}/uploads"))
+    }
+}
+
+/// The string member `name` of `value`.
+fn json_string(value: &serde_json::Value, name: &str) -> ForgeResult<String> {
+    value[name]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| ForgeError::InvalidResponse(format!("no '{name}' in the response")))
+}
+
+/// Parse a release as returned by the API of `forge`.
+fn parse_release(forge: Forge, value: &serde_json::Value) -> ForgeResult<Release> {
+    Ok(Release {
+        tag:  json_string(value, "tag_name")?,
+        name: value["name"].as_str().unwrap_or_default().to_string(),
+        id:   value["id"].as_u64(),
+        url:  match forge {
+            Forge::GitHub => json_string(value, "html_url")?,
+            Forge::GitLab => value["_links"]["self"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod forge_test {
+    use super::*;
+
+    #[test]
+    fn urls_and_releases() -> ForgeResult<()> {
+        let github = Client::github("georglauterbach/rush", "ghp_secret");
+        assert_eq!(
+            github.url("/releases"),
+            "https://api.github.com/repos/georglauterbach/rush/releases"
+        );
+        assert!(!format!("{github:?}").contains("ghp_secret"));
+        let gitlab = Client::gitlab("group/rush", "glpat-secret");
+        assert_eq!(
+            gitlab.url("/releases"),
+            "https://gitlab.com/api/v4/projects/group%2Frush/releases"
+        );
+
+        assert_eq!(
+            upload_api("https://api.github.com"),
+            "https://uploads.github.com"
+        );
+        assert_eq!(
+            upload_api("https://github.example.com/api/v3"),
+            "https://github.example.com/api/uploads"
+        );
+
+        let release = parse_release(
+            Forge::GitHub,
+            &serde_json::json!({
+                "id": 42,
+                "tag_name": "v1.0.0",
+                "name": "1.0.0",
+                "html_url": "https://github.com/georglauterbach/rush/releases/tag/v1.0.0"
+            }),
+        )?;
+        assert_eq!(release.id, Some(42));
+        assert_eq!(release.tag, "v1.0.0");
+        let release = parse_release(
+            Forge::GitLab,
+            &serde_json::json!({
+                "tag_name": "v1.0.0",
+                "name": "1.0.0",
+                "_links": { "self": "https://gitlab.com/group/rush/-/releases/v1.0.0" }
+            }),
+        )?;
+        assert_eq!(release.id, None);
+        assert_eq!(
+            release.url,
+            "https://gitlab.com/group/rush/-/releases/v1.0.0"
+        );
+        Ok(())
+    }
+}
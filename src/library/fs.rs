@@ -1,8 +1,108 @@
 //! This module contains functionality for manipulating the filesystem in an easy
 //! manner.
 
+mod atomic;
+mod backend;
+mod blocks;
+mod chaos;
+mod compare;
+mod copy;
+mod encoding;
+mod entries;
+mod follow;
+mod glob;
+mod hash;
+mod guarded;
+mod instrumented;
+mod kind;
+mod lines;
+mod metadata;
+mod mime;
+mod names;
+mod organize;
+#[cfg(unix)]
+mod owner;
+mod permissions;
+mod rename;
+mod split;
+mod stream;
+mod temp;
+mod times;
+mod walk;
+
+pub(crate) use atomic::write_atomic_with;
+pub use backend::MemoryBackend;
+pub use blocks::{
+    ensure_block,
+    remove_block,
+};
+pub use chaos::{
+    Failure,
+    FailureInjection,
+    Fault,
+    InjectedFailures,
+    Operation,
+};
+pub use compare::DirectoryDiff;
+pub use copy::{
+    CopyOptions,
+    CopyProgress,
+};
+pub use encoding::{
+    Encoding,
+    LineEnding,
+};
+pub use entries::{
+    Entries,
+    Entry,
+};
+pub(crate) use follow::file_identity;
+pub use follow::Follow;
+pub use glob::glob;
+pub use hash::Algorithm;
+pub use kind::FileKind;
+pub use lines::{
+    SortOptions,
+    TextStats,
+};
+pub use metadata::Metadata;
+pub use mime::mime_type_of_extension;
+pub use names::{
+    sanitize_filename,
+    unique_target,
+};
+pub use organize::{
+    bucket_by_extension,
+    bucket_by_first_letter,
+    bucket_by_month,
+    Collision,
+};
+#[cfg(unix)] pub use owner::{
+    Account,
+    Owner,
+};
+pub use permissions::Permissions;
+pub use rename::{
+    BulkRename,
+    Rename,
+    RenameConflict,
+};
+pub use split::SplitBy;
+pub use stream::{
+    Reader,
+    Writer,
+};
+pub use temp::{
+    TempDir,
+    TempFile,
+};
+pub use walk::{
+    walk,
+    Walk,
+};
+
 /// Describes possible errors when dealing with the filesystem.
-#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq, Hash)]
 pub enum FSError {
     #[error("The requested object does not exist")]
     NonExistent,
@@ -14,6 +114,21 @@ pub enum FSError {
     TypeMismatch(ObjectType),
     #[error("You lack permissions for this operation")]
     PermissionDenied,
+    #[error("The operation cannot cross filesystems")]
+    CrossDevice,
+    #[cfg(feature = "csv")]
+    #[error("The CSV data is not valid: {0}")]
+    InvalidCsv(String),
+    #[error("The content is not valid in or cannot be represented in {0}")]
+    InvalidEncoding(String),
+    #[error("The pattern is not valid: {0}")]
+    InvalidPattern(String),
+    #[error("The permission mode is not valid: {0}")]
+    InvalidMode(String),
+    #[error("There is no user or group '{0}'")]
+    UnknownAccount(String),
+    #[error("The operation is not allowed: {0}")]
+    PolicyViolation(crate::policy::Violation),
     #[error("A completely unexpected error occurred")]
     Unknown(String),
 }
@@ -21,6 +136,18 @@ pub enum FSError {
 impl From<std::io::Error> for FSError {
     fn from(error: std::io::Error) -> Self {
         use std::io::ErrorKind;
+        // Injected failures and policy violations carry the exact error to report.
+        if let Some(error) = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Self>())
+        {
+            return error.clone();
+        }
+        // `ErrorKind::CrossesDevices` is not stable yet.
+        #[cfg(unix)]
+        if error.raw_os_error() == Some(nix::errno::Errno::EXDEV as i32) {
+            return Self::CrossDevice;
+        }
         match error.kind() {
             ErrorKind::AlreadyExists => Self::AlreadyExists,
             ErrorKind::NotFound => Self::NonExistent,
@@ -35,7 +162,7 @@ impl From<std::io::Error> for FSError {
 pub type FSResult<T> = Result<T, FSError>;
 
 #[cfg(test)]
-fn generate_test_path() -> std::path::PathBuf {
+pub(crate) fn generate_test_path() -> std::path::PathBuf {
     use rand::Rng;
     loop {
         let mut tmp_dir = std::env::temp_dir();
@@ -54,7 +181,7 @@ fn generate_test_path() -> std::path::PathBuf {
 
 /// Describes what type the filesystem object has. Extensively used in the [`Object`]
 /// trait.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ObjectType {
     File,
     Directory,
@@ -138,6 +265,102 @@ pub trait Object: Sized + std::fmt::Display {
     ///
     /// This method relies on [`exists!()`] and propagates its errors, if there are any.
     fn exists_and_is_empty(&self) -> FSResult<bool>;
+
+    /// Read the metadata of the object, e.g. its size and modification time,
+    /// following symbolic links.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let file = File::new("/notes.txt");
+    /// file.write_new("remember").unwrap();
+    /// assert_eq!(file.metadata().unwrap().size, 8);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if the object does not exist, or another
+    /// error if its metadata cannot be read.
+    fn metadata(&self) -> FSResult<Metadata> { metadata::read(self.path()) }
+
+    /// Read the permissions of the object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object does not exist or the backend has no
+    /// permissions (like [`MemoryBackend`]).
+    fn permissions(&self) -> FSResult<Permissions> { permissions::read(self.path()) }
+
+    /// Set the permissions of the object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object does not exist or the permissions cannot be
+    /// changed.
+    fn set_permissions(&self, permissions: Permissions) -> FSResult<()> {
+        permissions::write(self.path(), permissions)
+    }
+
+    /// Change the permissions of the object like `chmod` does, e.g. with `u+x` or
+    /// `644` (see [`Permissions::change`]), and return the new permissions.
+    ///
+    /// ```no_run
+    /// # use rush::prelude::*;
+    /// let script = File::new("/usr/local/bin/backup.sh");
+    /// script.write_new("#!/bin/sh\n").unwrap();
+    /// script.chmod("a+x").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::InvalidMode`] if `mode` is not valid, or an error if the
+    /// permissions cannot be read or changed.
+    fn chmod(&self, mode: &str) -> FSResult<Permissions> { permissions::change(self.path(), mode) }
+
+    /// Read which user and group own the object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object does not exist or the backend has no owners
+    /// (like [`MemoryBackend`]).
+    #[cfg(unix)]
+    fn owner(&self) -> FSResult<Owner> { owner::read(self.path()) }
+
+    /// Change which user and group own the object, like `chown` does. Both are
+    /// given by name or ID, or are [`Account::Unchanged`].
+    ///
+    /// ```no_run
+    /// # use rush::prelude::*;
+    /// File::new("/etc/app/config.toml")
+    ///     .set_owner("app", fs::Account::Unchanged)
+    ///     .unwrap();
+    /// Directory::new("/srv/app").chown_recursive("app", 1000).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::UnknownAccount`] if the user or group does not exist, or an
+    /// error if the owner cannot be changed (usually because of missing privileges).
+    #[cfg(unix)]
+    fn set_owner(&self, user: impl Into<Account>, group: impl Into<Account>) -> FSResult<()> {
+        owner::change(self.path(), &user.into(), &group.into())
+    }
+
+    /// Set the modification and/or access time of the object, following symbolic
+    /// links; those that are [`None`] are kept. See [`File::touch`] for setting both
+    /// to now.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if the object does not exist, or an error if
+    /// the timestamps cannot be changed.
+    fn set_times(
+        &self,
+        modified: Option<std::time::SystemTime>,
+        accessed: Option<std::time::SystemTime>,
+    ) -> FSResult<()> {
+        times::write(self.path(), modified, accessed)
+    }
 }
 
 /// Describes a file (not a symbolic link) on the filesystem.
@@ -166,22 +389,19 @@ impl Object for File {
     fn path_mut(&mut self) -> &mut std::path::PathBuf { &mut self.path }
 
     fn exists(&self) -> FSResult<bool> {
-        if self.path.exists() {
-            if self.path.is_file() {
-                Ok(true)
-            } else {
+        match backend::with(|backend| backend.object_type(&self.path)) {
+            None => Ok(false),
+            Some(ObjectType::File) => Ok(true),
+            Some(object_type) => {
                 log::warn!("File path {} does not point to a file", self);
-                Err(FSError::TypeMismatch((&self.path).into()))
-            }
-        } else {
-            Ok(false)
+                Err(FSError::TypeMismatch(object_type))
+            },
         }
     }
 
     fn create_on_fs(&self) -> FSResult<()> {
         log::trace!("Creating file {}", self);
-        if self.path.exists() {
-            self.exists()?;
+        if self.exists()? {
             log::trace!("File {} already exists", self);
             return Ok(());
         }
@@ -191,7 +411,7 @@ impl Object for File {
     fn create_on_fs_recursive(&self) -> FSResult<()> {
         log::trace!("Recursively creating file with path {}", self);
         if let Some(path) = self.path.parent() {
-            std::fs::create_dir_all(path)?;
+            backend::with(|backend| backend.create_dir_all(path))?;
         }
         self.create_on_fs()
     }
@@ -203,13 +423,13 @@ impl Object for File {
             return Ok(());
         }
 
-        std::fs::remove_file(&self.path)?;
+        backend::with(|backend| backend.remove_file(&self.path))?;
         Ok(())
     }
 
     fn move_to(self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
         log::trace!("Moving file {} to {}", self, Self::path_to_str(&target));
-        if let Err(error) = std::fs::rename(&self.path, &target) {
+        if let Err(error) = backend::with(|backend| backend.rename(&self.path, target.as_ref())) {
             log::debug!(
                 "Could not rename file from {} to {}: {} - trying copy-delete next",
                 self,
@@ -224,7 +444,7 @@ impl Object for File {
 
     fn copy_to(&self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
         log::trace!("Copying file {} to {}", self, Self::path_to_str(&target));
-        std::fs::copy(&self.path, &target)?;
+        backend::with(|backend| backend.copy(&self.path, target.as_ref()))?;
         Ok(Self::new(target))
     }
 
@@ -233,31 +453,20 @@ impl Object for File {
             return Ok(false);
         }
 
-        if let Ok(data) = self.path.metadata() {
-            Ok(data.len() == 0)
-        } else {
-            Ok(false)
-        }
+        Ok(backend::with(|backend| backend.len(&self.path)).is_ok_and(|length| length == 0))
     }
 }
 
 impl File {
     /// Generic implementation for writing to a file. The current implementation does
     /// not use buffering or async/await.
-    fn write_to_file(&self, content: impl AsRef<str>, append: bool) -> FSResult<()> {
-        use std::io::Write;
-        let mut file = std::fs::OpenOptions::new()
-            .write(true)
-            .append(append)
-            .truncate(!append)
-            .create(true)
-            .open(&self.path)?;
-        file.write_all(content.as_ref().as_bytes())?;
+    fn write_to_file(&self, content: impl AsRef<[u8]>, append: bool) -> FSResult<()> {
+        backend::with(|backend| backend.write(&self.path, content.as_ref(), append))?;
         Ok(())
     }
 
     /// Write content to a new file. Returns with [`Err`] if the file already existed.
-    pub fn write_new(&self, content: impl AsRef<str>) -> FSResult<()> {
+    pub fn write_new(&self, content: impl AsRef<[u8]>) -> FSResult<()> {
         log::trace!("Creating new file {} with content", self);
         if self.exists()? {
             return Err(FSError::AlreadyExists);
@@ -267,7 +476,7 @@ impl File {
 
     /// Append content to a file. If the file does not exist yet, it is created.
     /// If the parent directories do not exist, they are created.
-    pub fn append(&self, content: impl AsRef<str>) -> FSResult<()> {
+    pub fn append(&self, content: impl AsRef<[u8]>) -> FSResult<()> {
         log::trace!("Appending content to {}", self);
         self.exists()?;
         self.write_to_file(content, true)
@@ -275,26 +484,309 @@ impl File {
 
     /// Overwrite a file with content. If the file does not exist yet, it is created.
     /// If the parent directories do not exist, they are created.
-    pub fn overwrite(&self, content: impl AsRef<str>) -> FSResult<()> {
+    pub fn overwrite(&self, content: impl AsRef<[u8]>) -> FSResult<()> {
         log::trace!("Overwriting contents of {}", self);
         self.exists()?;
         self.write_to_file(content, false)
     }
 
+    /// Overwrite a file with binary content, e.g. an image or an archive. If the file
+    /// does not exist yet, it is created.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let icon = File::new("/favicon.ico");
+    /// icon.write_bytes([0, 0, 1, 0]).unwrap();
+    /// icon.append_bytes(vec![1, 0]).unwrap();
+    /// assert_eq!(icon.read_bytes().unwrap(), [0, 0, 1, 0, 1, 0]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path is not a file or the file cannot be written.
+    pub fn write_bytes(&self, content: impl AsRef<[u8]>) -> FSResult<()> {
+        log::trace!("Overwriting contents of {} with bytes", self);
+        self.exists()?;
+        self.write_to_file(content, false)
+    }
+
+    /// Append binary content to a file. If the file does not exist yet, it is
+    /// created.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path is not a file or the file cannot be written.
+    pub fn append_bytes(&self, content: impl AsRef<[u8]>) -> FSResult<()> {
+        log::trace!("Appending bytes to {}", self);
+        self.exists()?;
+        self.write_to_file(content, true)
+    }
+
+    /// Replace the content of the file with `content` so that readers either see the
+    /// old or the new content, but never partial content, even if the system crashes
+    /// midway. The content is written to a temporary file in the same directory,
+    /// flushed to disk and then renamed over the file. While it is written, only its
+    /// owner can access the temporary file. The permissions of an existing file are
+    /// kept, its owner is not; a new file gets the default permissions.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let config = File::new("/app.conf");
+    /// config.write_atomic("port = 8080\n").unwrap();
+    /// config.write_atomic("port = 8443\n").unwrap();
+    /// assert_eq!(config.read().unwrap(), "port = 8443\n");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the temporary file cannot be written or renamed, in which
+    /// case the file is left untouched.
+    pub fn write_atomic(&self, content: impl AsRef<[u8]>) -> FSResult<()> {
+        use std::io::Write as _;
+
+        log::trace!("Atomically overwriting contents of {}", self);
+        write_atomic_with(&self.path, None, |writer| -> FSResult<()> {
+            Ok(writer.write_all(content.as_ref())?)
+        })
+    }
+
     pub fn read(&self) -> FSResult<String> {
+        String::from_utf8(self.read_bytes()?)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidData).into())
+    }
+
+    /// Read the whole content of the file as bytes, which unlike [`File::read`] do
+    /// not have to be valid UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if the file does not exist, or an error if it
+    /// cannot be read.
+    pub fn read_bytes(&self) -> FSResult<Vec<u8>> {
         if !self.exists()? {
             return Err(FSError::NonExistent);
         }
 
-        Ok(std::fs::read_to_string(&self.path)?)
+        Ok(backend::with(|backend| backend.read(&self.path))?)
     }
 
-    pub fn size(&self) -> u64 {
-        if let Ok(data) = self.path.metadata() {
-            data.len()
-        } else {
-            0
+    /// Create a hard link to this file at `target` and return it. Both paths then
+    /// refer to the same content, which is only stored once.
+    ///
+    /// ```no_run
+    /// # use rush::prelude::*;
+    /// let latest = File::new("/srv/backups/2024-06-01/data.tar");
+    /// latest.hard_link_to("/srv/backups/2024-06-02/data.tar").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if this file does not exist,
+    /// [`FSError::AlreadyExists`] if `target` exists, [`FSError::CrossDevice`] if
+    /// `target` is on another filesystem, or another error if the link cannot be
+    /// created (e.g. with a [`MemoryBackend`], which has no hard links).
+    pub fn hard_link_to(&self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        log::trace!(
+            "Hard linking file {} to {}",
+            self,
+            Self::path_to_str(&target)
+        );
+        if !self.exists()? {
+            return Err(FSError::NonExistent);
         }
+        backend::with(|backend| backend.hard_link(&self.path, target.as_ref()))?;
+        Ok(Self::new(target))
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl File {
+    /// Run `transform` from this file into `target`, which is replaced atomically
+    /// (see [`write_atomic_with`]) only if `transform` succeeds. This way, `target`
+    /// never contains partial output, and the output is only accessible to its
+    /// owner until it is complete.
+    fn transform_to(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        transform: impl FnOnce(
+            &mut Box<dyn std::io::Read>,
+            &mut Writer,
+        ) -> crate::crypto::encryption::EncryptionResult<()>,
+    ) -> crate::crypto::encryption::EncryptionResult<Self> {
+        let target = target.as_ref();
+        let mut input = backend::with(|backend| backend.open(&self.path)).map_err(FSError::from)?;
+        write_atomic_with(target, None, |output| transform(&mut input, output))?;
+        Ok(Self::new(target))
+    }
+
+    /// Encrypt this file with `key` (in the age format) and write the result to
+    /// `target`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is invalid or if reading or writing fails.
+    pub fn encrypt_to(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        key: &crate::crypto::encryption::Key,
+    ) -> crate::crypto::encryption::EncryptionResult<Self> {
+        log::trace!("Encrypting file {} to {}", self, Self::path_to_str(&target));
+        self.transform_to(target, |input, output| {
+            crate::crypto::encryption::encrypt(input, output, key)
+        })
+    }
+
+    /// Decrypt this file, which has to be in the age format, with `key` and write
+    /// the plaintext to `target`. If decrypting fails, `target` is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is invalid or wrong, if the file was tampered with
+    /// or if reading or writing fails.
+    pub fn decrypt_to(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        key: &crate::crypto::encryption::Key,
+    ) -> crate::crypto::encryption::EncryptionResult<Self> {
+        log::trace!("Decrypting file {} to {}", self, Self::path_to_str(&target));
+        self.transform_to(target, |input, output| {
+            crate::crypto::encryption::decrypt(input, output, key)
+        })
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for FSError {
+    fn from(error: csv::Error) -> Self {
+        let message = error.to_string();
+        match error.into_kind() {
+            csv::ErrorKind::Io(error) => error.into(),
+            _ => Self::InvalidCsv(message),
+        }
+    }
+}
+
+/// Describes the dialect of CSV files read with [`File::read_csv_with`] and written
+/// with [`File::write_csv_with`].
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CsvOptions {
+    /// The byte separating fields
+    delimiter: u8,
+    /// Whether the first record holds the column names
+    headers:   bool,
+}
+
+#[cfg(feature = "csv")]
+impl Default for CsvOptions {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(feature = "csv")]
+impl CsvOptions {
+    /// Fields are separated by commas and the first record holds the column names.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            delimiter: b',',
+            headers:   true,
+        }
+    }
+
+    /// Separate fields by `delimiter`, e.g. `b';'` for exports of spreadsheets in
+    /// many European locales or `b'\t'` for tab-separated values.
+    #[must_use]
+    pub const fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the first record holds the column names. Without headers, records
+    /// are (de)serialized by position, so structs have to list their fields in the
+    /// order of the columns.
+    #[must_use]
+    pub const fn headers(mut self, enabled: bool) -> Self {
+        self.headers = enabled;
+        self
+    }
+}
+
+#[cfg(feature = "csv")]
+impl File {
+    /// Read all records of this CSV file, see [`File::read_csv_with`]. The file has
+    /// to be comma-separated and start with a header record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a record cannot be
+    /// deserialized into `T`.
+    pub fn read_csv<T: serde::de::DeserializeOwned>(&self) -> FSResult<Vec<T>> {
+        self.read_csv_with(&CsvOptions::new())
+    }
+
+    /// Read all records of this CSV file, deserializing each into `T`. With headers,
+    /// struct fields are matched with columns by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a record cannot be
+    /// deserialized into `T`.
+    pub fn read_csv_with<T: serde::de::DeserializeOwned>(
+        &self,
+        options: &CsvOptions,
+    ) -> FSResult<Vec<T>> {
+        log::trace!("Reading CSV records from {}", self);
+        let content = backend::with(|backend| backend.read(&self.path))?;
+        csv::ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .has_headers(options.headers)
+            .from_reader(content.as_slice())
+            .deserialize()
+            .map(|record| record.map_err(FSError::from))
+            .collect()
+    }
+
+    /// Write `records` to this CSV file, see [`File::write_csv_with`]. The file is
+    /// comma-separated and starts with a header record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written or a record cannot be
+    /// serialized.
+    pub fn write_csv<T: serde::Serialize>(
+        &self,
+        records: impl IntoIterator<Item = T>,
+    ) -> FSResult<()> {
+        self.write_csv_with(records, &CsvOptions::new())
+    }
+
+    /// Write `records` to this CSV file, replacing its contents. With headers, the
+    /// column names are taken from the field names of the first record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written or a record cannot be
+    /// serialized.
+    pub fn write_csv_with<T: serde::Serialize>(
+        &self,
+        records: impl IntoIterator<Item = T>,
+        options: &CsvOptions,
+    ) -> FSResult<()> {
+        log::trace!("Writing CSV records to {}", self);
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .has_headers(options.headers)
+            .from_writer(Vec::new());
+        for record in records {
+            writer.serialize(record)?;
+        }
+        let content = writer
+            .into_inner()
+            .map_err(|error| FSError::from(error.into_error()))?;
+        backend::with(|backend| backend.write(&self.path, &content, false))?;
+        Ok(())
     }
 }
 
@@ -380,12 +872,137 @@ mod file_test {
         Ok(())
     }
 
+    #[test]
+    fn hard_link_to() -> FSResult<()> {
+        let file = File::new(generate_test_path());
+        file.write_new("Snapshot")?;
+        let link = file.hard_link_to(generate_test_path())?;
+        assert_eq!(link.read()?, "Snapshot");
+        file.append(" contents")?;
+        assert_eq!(link.read()?, "Snapshot contents");
+        #[cfg(unix)]
+        {
+            assert_eq!(link.metadata()?.links, Some(2));
+            assert_eq!(link.metadata()?.inode, file.metadata()?.inode);
+            assert_eq!(
+                FSError::from(std::io::Error::from_raw_os_error(
+                    nix::errno::Errno::EXDEV as i32
+                )),
+                FSError::CrossDevice
+            );
+        }
+        assert_eq!(
+            file.hard_link_to(link.path()).map(|_| ()),
+            Err(FSError::AlreadyExists)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bytes() -> FSResult<()> {
+        let file = File::new(generate_test_path());
+        assert_eq!(file.read_bytes(), Err(FSError::NonExistent));
+        file.write_bytes([0xFF, 0xFE, 0x00])?;
+        file.append_bytes(b"\n")?;
+        assert_eq!(file.read_bytes()?, [0xFF, 0xFE, 0x00, b'\n']);
+        assert!(file.read().is_err());
+        file.write_bytes("text")?;
+        assert_eq!(file.read()?, "text");
+        file.overwrite([0x1F, 0x8B])?;
+        file.append(vec![0x08])?;
+        assert_eq!(file.read_bytes()?, [0x1F, 0x8B, 0x08]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_atomic() -> FSResult<()> {
+        let directory = TempDir::create()?;
+        let file = File::new(directory.path().join("app.conf"));
+        file.write_atomic("port = 8080\n")?;
+        #[cfg(unix)]
+        file.chmod("600")?;
+        file.write_atomic("port = 8443\n")?;
+        assert_eq!(file.read()?, "port = 8443\n");
+        #[cfg(unix)]
+        assert_eq!(file.permissions()?.mode(), 0o600);
+        assert_eq!(directory.entries()?.count(), 1);
+
+        let memory = MemoryBackend::install();
+        let file = File::new("/app.conf");
+        file.write_atomic("port = 8080\n")?;
+        let _failures = FailureInjection::new()
+            .fail_path(file.path(), std::io::ErrorKind::PermissionDenied)
+            .only([Operation::Rename])
+            .install();
+        assert_eq!(
+            file.write_atomic("port = 8443\n"),
+            Err(FSError::PermissionDenied)
+        );
+        assert_eq!(file.read()?, "port = 8080\n");
+        assert_eq!(memory.paths(), [std::path::PathBuf::from("/app.conf")]);
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypt_decrypt() -> crate::crypto::encryption::EncryptionResult<()> {
+        use crate::crypto::encryption::Key;
+
+        let file = File::new(generate_test_path());
+        file.write_new("Backup contents")?;
+        let key = Key::Passphrase(String::from("correct horse battery staple"));
+
+        let encrypted = file.encrypt_to(generate_test_path(), &key)?;
+        assert!(!std::fs::read(encrypted.path())
+            .map_err(FSError::from)?
+            .windows(6)
+            .any(|window| window == b"Backup"));
+
+        let decrypted = encrypted.decrypt_to(generate_test_path(), &key)?;
+        assert_eq!(decrypted.read()?, "Backup contents");
+
+        let target = generate_test_path();
+        assert!(encrypted
+            .decrypt_to(&target, &Key::Passphrase(String::from("wrong")))
+            .is_err());
+        assert!(!target.exists());
+        Ok(())
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv() -> FSResult<()> {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Host {
+            name: String,
+            port: u16,
+        }
+
+        let file = File::new(generate_test_path());
+        file.write_new("port;name\n22;alpha\n2222;\"beta;gamma\"\n")?;
+        let options = CsvOptions::new().delimiter(b';');
+        let hosts: Vec<Host> = file.read_csv_with(&options)?;
+        assert_eq!(hosts[1].name, "beta;gamma");
+        assert_eq!(hosts[1].port, 2222);
+
+        file.write_csv(&hosts)?;
+        assert_eq!(file.read()?, "name,port\nalpha,22\nbeta;gamma,2222\n");
+        assert_eq!(file.read_csv::<Host>()?, hosts);
+
+        file.overwrite("name,port\nalpha,not-a-port\n")?;
+        assert!(matches!(
+            file.read_csv::<Host>(),
+            Err(FSError::InvalidCsv(_))
+        ));
+        Ok(())
+    }
+
     #[test]
     fn write() -> FSResult<()> {
         let file = File::new(generate_test_path());
         const MESSAGE: &str = "This is a very fine message!";
         file.write_new(MESSAGE)?;
-        assert_eq!(file.size(), MESSAGE.len() as u64);
+        assert_eq!(file.metadata()?.size, MESSAGE.len() as u64);
 
         let file = File::new(generate_test_path());
         file.create_on_fs()?;
@@ -400,6 +1017,7 @@ mod file_test {
 }
 
 /// Describes a directory on the filesystem.
+#[derive(Debug)]
 pub struct Directory {
     path: std::path::PathBuf,
 }
@@ -424,34 +1042,32 @@ impl Object for Directory {
     fn path_mut(&mut self) -> &mut std::path::PathBuf { &mut self.path }
 
     fn exists(&self) -> FSResult<bool> {
-        if self.path.exists() {
-            if self.path.is_dir() {
-                Ok(true)
-            } else {
+        match backend::with(|backend| backend.object_type(&self.path)) {
+            None => Ok(false),
+            Some(ObjectType::Directory) => Ok(true),
+            Some(object_type) => {
                 log::warn!("Directory path {} does not point to a directory", self);
-                Err(FSError::TypeMismatch((&self.path).into()))
-            }
-        } else {
-            Ok(false)
+                Err(FSError::TypeMismatch(object_type))
+            },
         }
     }
 
     fn create_on_fs(&self) -> FSResult<()> {
         log::trace!("Creating directory {}", self);
-        std::fs::create_dir(&self.path)?;
+        backend::with(|backend| backend.create_dir(&self.path))?;
         Ok(())
     }
 
     fn create_on_fs_recursive(&self) -> FSResult<()> {
         log::trace!("Recursively creating directory with path {}", self);
-        std::fs::create_dir_all(&self.path)?;
+        backend::with(|backend| backend.create_dir_all(&self.path))?;
         Ok(())
     }
 
     fn delete_from_fs(&self) -> FSResult<()> {
         log::trace!("Deleting directory {}", self);
         if self.exists()? {
-            std::fs::remove_dir_all(&self.path)?;
+            backend::with(|backend| backend.remove_dir_all(&self.path))?;
         }
         Ok(())
     }
@@ -462,7 +1078,7 @@ impl Object for Directory {
             self,
             Self::path_to_str(&target)
         );
-        if let Err(error) = std::fs::rename(&self.path, &target) {
+        if let Err(error) = backend::with(|backend| backend.rename(&self.path, target.as_ref())) {
             log::debug!(
                 "Could not rename directory from {} to {}: {} - trying copy-delete next",
                 self,
@@ -481,8 +1097,7 @@ impl Object for Directory {
             self,
             Self::path_to_str(&target)
         );
-        std::fs::copy(&self.path, &target)?;
-        Ok(Self::new(target))
+        self.copy_with(target, &CopyOptions::new(), |_| {})
     }
 
     fn exists_and_is_empty(&self) -> FSResult<bool> {
@@ -490,12 +1105,49 @@ impl Object for Directory {
             return Ok(false);
         }
 
-        if let Ok(mut entry) = self.path.read_dir() {
-            Ok(entry.next().is_none())
-        } else {
-            Ok(false)
-        }
+        Ok(backend::with(|backend| backend.read_dir(&self.path))
+            .is_ok_and(|entries| entries.is_empty()))
     }
 }
 
-// struct SymbolicLink;
+/// Describes a symbolic link on the filesystem (not what it links to).
+#[derive(Debug)]
+pub struct SymbolicLink {
+    /// The path of the link itself
+    path: std::path::PathBuf,
+}
+
+impl std::fmt::Display for SymbolicLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}'", self.path.to_string_lossy())
+    }
+}
+
+impl SymbolicLink {
+    /// Describe the symbolic link at `path`, which does not have to exist.
+    pub fn new(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The path of the link itself.
+    #[must_use]
+    pub const fn path(&self) -> &std::path::PathBuf { &self.path }
+
+    /// What the link points to, as stored in the link (i.e. possibly relative to
+    /// the directory of the link).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if nothing exists at the path and
+    /// [`FSError::TypeMismatch`] if it is not a symbolic link.
+    pub fn target(&self) -> FSResult<std::path::PathBuf> {
+        backend::with(|backend| backend.read_link(&self.path))?.ok_or_else(|| {
+            FSError::TypeMismatch(
+                backend::with(|backend| backend.object_type(&self.path))
+                    .unwrap_or(ObjectType::Unknown),
+            )
+        })
+    }
+}
@@ -0,0 +1,157 @@
+//! This module contains replacing files atomically with content that is written
+//! piece by piece, which [`File::write_atomic`](super::File::write_atomic) and the
+//! other modules replacing files build on.
+
+use super::{
+    backend,
+    FSError,
+    Object as _,
+    ObjectType,
+    TempFile,
+    Writer,
+};
+
+/// The umask assumed where the umask of the process cannot be read.
+const DEFAULT_UMASK: u32 = 0o022;
+
+/// The permission bits new files get by default: `0o666` less the umask of the
+/// process. Only Linux allows reading the umask without changing it; elsewhere,
+/// the usual umask `022` is assumed.
+fn default_mode() -> u32 {
+    let umask = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Umask:"))
+                .and_then(|umask| u32::from_str_radix(umask.trim(), 8).ok())
+        })
+        .unwrap_or(DEFAULT_UMASK);
+    0o666 & !umask
+}
+
+/// Set the permission bits of the file at `path` to `mode`, unless the backend has
+/// no permissions.
+fn set_mode(path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+    match backend::with(|backend| backend.set_mode(path, mode)) {
+        Err(error) if error.kind() == std::io::ErrorKind::Unsupported => Ok(()),
+        result => result,
+    }
+}
+
+/// Replace the file at `path` with the content `write` writes, so that readers
+/// either see the old or the new content, but never partial content, even if the
+/// system crashes midway.
+///
+/// The content is written to a temporary file in the same directory, which only
+/// its owner can access while it is written. The file then gets the permission
+/// bits `mode` if given, otherwise the permissions of the file it replaces, or the
+/// default permissions of new files. It is flushed to disk and renamed over `path`.
+/// If `write` fails, the temporary file is removed and `path` is left untouched.
+pub fn write_atomic_with<E: From<FSError>>(
+    path: &std::path::Path,
+    mode: Option<u32>,
+    write: impl FnOnce(&mut Writer) -> Result<(), E>,
+) -> Result<(), E> {
+    let existed = match backend::with(|backend| backend.object_type(path)) {
+        None => false,
+        Some(ObjectType::File) => true,
+        Some(object_type) => return Err(FSError::TypeMismatch(object_type).into()),
+    };
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => std::path::Path::new("."),
+    };
+
+    let temporary = TempFile::create_in(directory)?;
+    let mut writer = temporary.open_writer(true)?;
+    write(&mut writer)?;
+    writer.finish()?;
+    match mode {
+        Some(mode) => set_mode(temporary.path(), mode),
+        None if existed => {
+            backend::with(|backend| backend.copy_metadata(path, temporary.path(), true, false))
+        },
+        None => set_mode(temporary.path(), default_mode()),
+    }
+    .map_err(FSError::from)?;
+    backend::with(|backend| backend.sync(temporary.path())).map_err(FSError::from)?;
+    backend::with(|backend| backend.rename(temporary.path(), path)).map_err(FSError::from)?;
+    let _ = temporary.persist();
+    // Make the rename itself durable.
+    backend::with(|backend| backend.sync(directory)).map_err(FSError::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod atomic_test {
+    use super::{
+        super::{
+            FSResult,
+            File,
+            TempDir,
+        },
+        *,
+    };
+
+    #[test]
+    fn streamed_content_and_modes() -> FSResult<()> {
+        use std::io::Write as _;
+        #[cfg(unix)] use std::os::unix::fs::PermissionsExt as _;
+
+        let directory = TempDir::create()?;
+        let entries = || -> FSResult<Vec<std::fs::DirEntry>> {
+            Ok(std::fs::read_dir(directory.path())?.collect::<Result<_, _>>()?)
+        };
+        let path = directory.path().join("state.json");
+        write_atomic_with(&path, Some(0o640), |writer| -> FSResult<()> {
+            // While it is written, only the temporary file exists and only its
+            // owner can access it.
+            let entries = entries()?;
+            assert_eq!(entries.len(), 1);
+            #[cfg(unix)]
+            assert_eq!(entries[0].metadata()?.permissions().mode() & 0o777, 0o600);
+            for line in 0..1000 {
+                writeln!(writer, "{line}")?;
+            }
+            Ok(())
+        })?;
+        let file = File::new(&path);
+        assert_eq!(file.read()?.lines().count(), 1000);
+        #[cfg(unix)]
+        assert_eq!(file.permissions()?.mode(), 0o640);
+
+        write_atomic_with(&path, None, |writer| -> FSResult<()> {
+            Ok(writer.write_all(b"{}")?)
+        })?;
+        assert_eq!(file.read()?, "{}");
+        #[cfg(unix)]
+        assert_eq!(file.permissions()?.mode(), 0o640);
+
+        let result = write_atomic_with(&path, None, |_| Err(FSError::PermissionDenied));
+        assert_eq!(result, Err(FSError::PermissionDenied));
+        assert_eq!(file.read()?, "{}");
+        assert_eq!(entries()?.len(), 1);
+
+        assert_eq!(
+            write_atomic_with(directory.path(), None, |_| -> FSResult<()> { Ok(()) }),
+            Err(FSError::TypeMismatch(ObjectType::Directory))
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_files_get_default_permissions() -> FSResult<()> {
+        let directory = TempDir::create()?;
+        let plain = File::new(directory.path().join("plain"));
+        plain.write_new("")?;
+        let atomic = directory.path().join("atomic");
+        write_atomic_with(&atomic, None, |_| -> FSResult<()> { Ok(()) })?;
+        assert_eq!(
+            File::new(atomic).permissions()?.mode(),
+            plain.permissions()?.mode()
+        );
+        Ok(())
+    }
+}
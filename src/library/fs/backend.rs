@@ -0,0 +1,829 @@
+//! This module contains the backends [`File`](super::File) and
+//! [`Directory`](super::Directory) operate through: the real filesystem, or an
+//! in-memory one for unit tests.
+
+use super::{
+    Metadata,
+    ObjectType,
+};
+
+/// The operations [`File`](super::File) and [`Directory`](super::Directory) need
+/// from a filesystem. Errors are reported like [`std::fs`] reports them.
+pub(super) trait FsBackend {
+    /// What `path` points to (following symbolic links), or [`None`] if nothing
+    /// exists there.
+    fn object_type(&self, path: &std::path::Path) -> Option<ObjectType>;
+
+    /// Open the file at `path` for reading.
+    fn open(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>>;
+
+    /// Open the file at `path` on disk for reading, e.g. to keep reading it after it
+    /// was moved away. Backends without files on disk do not support this.
+    fn open_file(&self, path: &std::path::Path) -> std::io::Result<std::fs::File>;
+
+    /// Read the whole file at `path`.
+    fn read(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        use std::io::Read as _;
+        let mut content = Vec::new();
+        self.open(path)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    /// Write `content` to the file at `path`, creating it if needed. The content is
+    /// appended if `append` is set and replaces the file's content otherwise.
+    fn write(&self, path: &std::path::Path, content: &[u8], append: bool) -> std::io::Result<()>;
+
+    /// Create an empty file at `path`, failing if something exists there already.
+    /// Where permissions exist, the file gets the bits `mode` (less the umask).
+    fn create_new(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()>;
+
+    /// Flush the file or directory at `path` to the storage device.
+    fn sync(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Create the directory `path`, whose parent has to exist.
+    fn create_dir(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Create the directory `path` and all its missing parents.
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Delete the file at `path`.
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Delete the directory `path` and everything in it.
+    fn remove_dir_all(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Rename `from` to `to`.
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()>;
+
+    /// Copy the file `from` to `to` and return the number of bytes copied.
+    fn copy(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<u64>;
+
+    /// The size of the file at `path` in bytes.
+    fn len(&self, path: &std::path::Path) -> std::io::Result<u64>;
+
+    /// The metadata of `path`, following symbolic links.
+    fn metadata(&self, path: &std::path::Path) -> std::io::Result<Metadata>;
+
+    /// The paths of the entries directly in the directory `path`.
+    fn read_dir(&self, path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>>;
+
+    /// What the symbolic link at `path` points to, or [`None`] if `path` is not a
+    /// symbolic link.
+    fn read_link(&self, path: &std::path::Path) -> std::io::Result<Option<std::path::PathBuf>>;
+
+    /// The absolute path of `path` with `.`, `..` and symbolic links resolved.
+    fn canonicalize(&self, path: &std::path::Path) -> std::io::Result<std::path::PathBuf>;
+
+    /// Create a symbolic link at `link` pointing to `target`.
+    fn symlink(&self, target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()>;
+
+    /// Create a hard link at `link` to the file `original`.
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()>;
+
+    /// Copy the permissions and/or the access and modification times of `from` to
+    /// `to`.
+    fn copy_metadata(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+        permissions: bool,
+        timestamps: bool,
+    ) -> std::io::Result<()>;
+
+    /// The permission bits of `path`, following symbolic links.
+    fn mode(&self, path: &std::path::Path) -> std::io::Result<u32>;
+
+    /// Set the permission bits of `path` to `mode`, following symbolic links.
+    fn set_mode(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()>;
+
+    /// The user and group ID owning `path`, following symbolic links.
+    fn owner(&self, path: &std::path::Path) -> std::io::Result<(u32, u32)>;
+
+    /// Change the user and/or group owning `path`, following symbolic links.
+    fn set_owner(
+        &self,
+        path: &std::path::Path,
+        user: Option<u32>,
+        group: Option<u32>,
+    ) -> std::io::Result<()>;
+
+    /// Set the access and/or modification time of `path`, following symbolic links.
+    fn set_times(
+        &self,
+        path: &std::path::Path,
+        accessed: Option<std::time::SystemTime>,
+        modified: Option<std::time::SystemTime>,
+    ) -> std::io::Result<()>;
+}
+
+/// The real filesystem, through [`std::fs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Std;
+
+impl FsBackend for Std {
+    fn object_type(&self, path: &std::path::Path) -> Option<ObjectType> {
+        path.exists().then(|| (&path.to_path_buf()).into())
+    }
+
+    fn open(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+
+    fn open_file(&self, path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        std::fs::File::open(path)
+    }
+
+    fn read(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> { std::fs::read(path) }
+
+    fn write(&self, path: &std::path::Path, content: &[u8], append: bool) -> std::io::Result<()> {
+        use std::io::Write as _;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .create(true)
+            .open(path)?
+            .write_all(content)
+    }
+
+    fn create_new(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+        options.open(path).map(drop)
+    }
+
+    fn sync(&self, path: &std::path::Path) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return std::fs::File::open(path)?.sync_all();
+        // Elsewhere, only files opened for writing can be flushed.
+        #[cfg(not(unix))]
+        if path.is_dir() {
+            Ok(())
+        } else {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .sync_all()
+        }
+    }
+
+    fn create_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<u64> {
+        std::fs::copy(from, to)
+    }
+
+    fn len(&self, path: &std::path::Path) -> std::io::Result<u64> {
+        Ok(std::fs::metadata(path)?.len())
+    }
+
+    fn metadata(&self, path: &std::path::Path) -> std::io::Result<Metadata> {
+        Ok((&std::fs::metadata(path)?).into())
+    }
+
+    fn read_dir(&self, path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn read_link(&self, path: &std::path::Path) -> std::io::Result<Option<std::path::PathBuf>> {
+        if std::fs::symlink_metadata(path)?.is_symlink() {
+            std::fs::read_link(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn canonicalize(&self, path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        std::fs::canonicalize(path)
+    }
+
+    fn symlink(&self, target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(target, link);
+        #[cfg(not(unix))]
+        {
+            let _ = (target, link);
+            Err(error(std::io::ErrorKind::Unsupported))
+        }
+    }
+
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        std::fs::hard_link(original, link)
+    }
+
+    fn copy_metadata(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+        permissions: bool,
+        timestamps: bool,
+    ) -> std::io::Result<()> {
+        let metadata = std::fs::metadata(from)?;
+        // Timestamps first, as the permissions may forbid opening `to` afterwards.
+        if timestamps {
+            let times = std::fs::FileTimes::new()
+                .set_accessed(metadata.accessed()?)
+                .set_modified(metadata.modified()?);
+            std::fs::File::open(to)?.set_times(times)?;
+        }
+        if permissions {
+            std::fs::set_permissions(to, metadata.permissions())?;
+        }
+        Ok(())
+    }
+
+    fn mode(&self, path: &std::path::Path) -> std::io::Result<u32> {
+        let permissions = std::fs::metadata(path)?.permissions();
+        #[cfg(unix)]
+        return Ok(std::os::unix::fs::PermissionsExt::mode(&permissions) & 0o7777);
+        // Elsewhere, only whether an object is read-only is known.
+        #[cfg(not(unix))]
+        Ok(if permissions.readonly() { 0o444 } else { 0o666 })
+    }
+
+    fn set_mode(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        #[cfg(unix)]
+        let permissions = std::os::unix::fs::PermissionsExt::from_mode(mode);
+        #[cfg(not(unix))]
+        let permissions = {
+            let mut permissions = std::fs::metadata(path)?.permissions();
+            permissions.set_readonly(mode & 0o222 == 0);
+            permissions
+        };
+        std::fs::set_permissions(path, permissions)
+    }
+
+    fn owner(&self, path: &std::path::Path) -> std::io::Result<(u32, u32)> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt as _;
+            let metadata = std::fs::metadata(path)?;
+            Ok((metadata.uid(), metadata.gid()))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            Err(error(std::io::ErrorKind::Unsupported))
+        }
+    }
+
+    fn set_owner(
+        &self,
+        path: &std::path::Path,
+        user: Option<u32>,
+        group: Option<u32>,
+    ) -> std::io::Result<()> {
+        #[cfg(unix)]
+        return std::os::unix::fs::chown(path, user, group);
+        #[cfg(not(unix))]
+        {
+            let _ = (path, user, group);
+            Err(error(std::io::ErrorKind::Unsupported))
+        }
+    }
+
+    fn set_times(
+        &self,
+        path: &std::path::Path,
+        accessed: Option<std::time::SystemTime>,
+        modified: Option<std::time::SystemTime>,
+    ) -> std::io::Result<()> {
+        let mut times = std::fs::FileTimes::new();
+        if let Some(accessed) = accessed {
+            times = times.set_accessed(accessed);
+        }
+        if let Some(modified) = modified {
+            times = times.set_modified(modified);
+        }
+        std::fs::File::open(path)?.set_times(times)
+    }
+}
+
+/// An entry of the in-memory filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    /// A file and its content
+    File(Vec<u8>),
+    /// A directory
+    Directory,
+}
+
+/// The in-memory filesystem. The root directory always exists; every other entry is
+/// stored under its absolute, normalized path.
+#[derive(Debug, Default)]
+struct Memory {
+    /// The files and directories
+    nodes: std::cell::RefCell<std::collections::BTreeMap<std::path::PathBuf, Node>>,
+}
+
+/// Make `path` absolute (relative to the current working directory, which is not
+/// accessed) and resolve `.` and `..` lexically.
+pub(super) fn normalize(path: &std::path::Path) -> std::path::PathBuf {
+    if path.is_absolute() {
+        return rush_core::path::normalize_os(path);
+    }
+    let absolute = std::env::current_dir()
+        .unwrap_or_else(|_| std::path::PathBuf::from("/"))
+        .join(path);
+    rush_core::path::normalize_os(&absolute)
+}
+
+/// An error of `kind`, like [`std::fs`] reports it.
+fn error(kind: std::io::ErrorKind) -> std::io::Error { std::io::Error::from(kind) }
+
+impl Memory {
+    /// What is stored at the normalized `path`.
+    fn node(&self, path: &std::path::Path) -> Option<Node> {
+        if path.parent().is_none() {
+            return Some(Node::Directory);
+        }
+        self.nodes.borrow().get(path).cloned()
+    }
+
+    /// Fail unless the parent of the normalized `path` is a directory.
+    fn require_parent(&self, path: &std::path::Path) -> std::io::Result<()> {
+        match path.parent().map(|parent| self.node(parent)) {
+            Some(Some(Node::Directory)) => Ok(()),
+            Some(Some(Node::File(_))) => Err(error(std::io::ErrorKind::NotADirectory)),
+            _ => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+
+    /// The content of the file at the normalized `path`.
+    fn content(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        match self.node(path) {
+            Some(Node::File(content)) => Ok(content),
+            Some(Node::Directory) => Err(error(std::io::ErrorKind::IsADirectory)),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+impl FsBackend for Memory {
+    fn object_type(&self, path: &std::path::Path) -> Option<ObjectType> {
+        self.node(&normalize(path)).map(|node| match node {
+            Node::File(_) => ObjectType::File,
+            Node::Directory => ObjectType::Directory,
+        })
+    }
+
+    fn open(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+        Ok(Box::new(std::io::Cursor::new(
+            self.content(&normalize(path))?,
+        )))
+    }
+
+    fn open_file(&self, _path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn write(&self, path: &std::path::Path, content: &[u8], append: bool) -> std::io::Result<()> {
+        let path = normalize(path);
+        self.require_parent(&path)?;
+        let mut nodes = self.nodes.borrow_mut();
+        match nodes.entry(path).or_insert_with(|| Node::File(Vec::new())) {
+            Node::File(existing) => {
+                if !append {
+                    existing.clear();
+                }
+                existing.extend_from_slice(content);
+                Ok(())
+            },
+            Node::Directory => Err(error(std::io::ErrorKind::IsADirectory)),
+        }
+    }
+
+    fn create_new(&self, path: &std::path::Path, _mode: u32) -> std::io::Result<()> {
+        // There are no permissions to set.
+        let path = normalize(path);
+        if self.node(&path).is_some() {
+            return Err(error(std::io::ErrorKind::AlreadyExists));
+        }
+        self.require_parent(&path)?;
+        self.nodes.borrow_mut().insert(path, Node::File(Vec::new()));
+        Ok(())
+    }
+
+    fn sync(&self, path: &std::path::Path) -> std::io::Result<()> {
+        // Everything is in memory already.
+        match self.node(&normalize(path)) {
+            Some(_) => Ok(()),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn create_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let path = normalize(path);
+        if self.node(&path).is_some() {
+            return Err(error(std::io::ErrorKind::AlreadyExists));
+        }
+        self.require_parent(&path)?;
+        self.nodes.borrow_mut().insert(path, Node::Directory);
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let path = normalize(path);
+        let mut ancestors = path.ancestors().collect::<Vec<_>>();
+        ancestors.reverse();
+        for ancestor in ancestors {
+            match self.node(ancestor) {
+                Some(Node::Directory) => {},
+                Some(Node::File(_)) => return Err(error(std::io::ErrorKind::AlreadyExists)),
+                None => {
+                    self.nodes
+                        .borrow_mut()
+                        .insert(ancestor.to_path_buf(), Node::Directory);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let path = normalize(path);
+        self.content(&path)?;
+        self.nodes.borrow_mut().remove(&path);
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let path = normalize(path);
+        match self.node(&path) {
+            Some(Node::Directory) => {},
+            Some(Node::File(_)) => return Err(error(std::io::ErrorKind::NotADirectory)),
+            None => return Err(error(std::io::ErrorKind::NotFound)),
+        }
+        self.nodes
+            .borrow_mut()
+            .retain(|candidate, _| !candidate.starts_with(&path));
+        Ok(())
+    }
+
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let Some(node) = self.node(&from) else {
+            return Err(error(std::io::ErrorKind::NotFound));
+        };
+        self.require_parent(&to)?;
+        match (&node, self.node(&to)) {
+            (Node::File(_), Some(Node::Directory)) => {
+                return Err(error(std::io::ErrorKind::IsADirectory))
+            },
+            (Node::Directory, Some(Node::File(_))) => {
+                return Err(error(std::io::ErrorKind::NotADirectory))
+            },
+            _ => {},
+        }
+        if from.parent().is_none() || to.starts_with(&from) && to != from {
+            return Err(error(std::io::ErrorKind::InvalidInput));
+        }
+
+        let mut nodes = self.nodes.borrow_mut();
+        let moved = nodes
+            .keys()
+            .filter(|candidate| candidate.starts_with(&from))
+            .cloned()
+            .collect::<Vec<_>>();
+        nodes.retain(|candidate, _| !candidate.starts_with(&to));
+        for path in moved {
+            if let Some(node) = nodes.remove(&path) {
+                let target = match path.strip_prefix(&from) {
+                    Ok(relative) if relative.as_os_str().is_empty() => to.clone(),
+                    Ok(relative) => to.join(relative),
+                    Err(_) => path,
+                };
+                nodes.insert(target, node);
+            }
+        }
+        Ok(())
+    }
+
+    fn copy(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<u64> {
+        let content = self.content(&normalize(from))?;
+        let length = content.len() as u64;
+        self.write(to, &content, false)?;
+        Ok(length)
+    }
+
+    fn len(&self, path: &std::path::Path) -> std::io::Result<u64> {
+        match self.node(&normalize(path)) {
+            Some(Node::File(content)) => Ok(content.len() as u64),
+            Some(Node::Directory) => Ok(0),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn metadata(&self, path: &std::path::Path) -> std::io::Result<Metadata> {
+        let (object_type, size) = match self.node(&normalize(path)) {
+            Some(Node::File(content)) => (ObjectType::File, content.len() as u64),
+            Some(Node::Directory) => (ObjectType::Directory, 0),
+            None => return Err(error(std::io::ErrorKind::NotFound)),
+        };
+        // There are neither timestamps nor inodes.
+        Ok(Metadata {
+            object_type,
+            size,
+            created: None,
+            modified: None,
+            accessed: None,
+            inode: None,
+            device: None,
+            links: None,
+        })
+    }
+
+    fn read_dir(&self, path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+        let path = normalize(path);
+        match self.node(&path) {
+            Some(Node::Directory) => Ok(self
+                .nodes
+                .borrow()
+                .keys()
+                .filter(|candidate| candidate.parent() == Some(path.as_path()))
+                .cloned()
+                .collect()),
+            Some(Node::File(_)) => Err(error(std::io::ErrorKind::NotADirectory)),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn read_link(&self, path: &std::path::Path) -> std::io::Result<Option<std::path::PathBuf>> {
+        match self.node(&normalize(path)) {
+            Some(_) => Ok(None),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn canonicalize(&self, path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        // There are no symbolic links to resolve.
+        let path = normalize(path);
+        match self.node(&path) {
+            Some(_) => Ok(path),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn symlink(&self, _target: &std::path::Path, _link: &std::path::Path) -> std::io::Result<()> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn hard_link(
+        &self,
+        _original: &std::path::Path,
+        _link: &std::path::Path,
+    ) -> std::io::Result<()> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn copy_metadata(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+        _permissions: bool,
+        _timestamps: bool,
+    ) -> std::io::Result<()> {
+        // There are neither permissions nor timestamps to copy.
+        for path in [from, to] {
+            if self.node(&normalize(path)).is_none() {
+                return Err(error(std::io::ErrorKind::NotFound));
+            }
+        }
+        Ok(())
+    }
+
+    fn mode(&self, _path: &std::path::Path) -> std::io::Result<u32> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn set_mode(&self, _path: &std::path::Path, _mode: u32) -> std::io::Result<()> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn owner(&self, _path: &std::path::Path) -> std::io::Result<(u32, u32)> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn set_owner(
+        &self,
+        _path: &std::path::Path,
+        _user: Option<u32>,
+        _group: Option<u32>,
+    ) -> std::io::Result<()> {
+        Err(error(std::io::ErrorKind::Unsupported))
+    }
+
+    fn set_times(
+        &self,
+        path: &std::path::Path,
+        _accessed: Option<std::time::SystemTime>,
+        _modified: Option<std::time::SystemTime>,
+    ) -> std::io::Result<()> {
+        // There are no timestamps to set.
+        match self.node(&normalize(path)) {
+            Some(_) => Ok(()),
+            None => Err(error(std::io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+thread_local! {
+    /// The in-memory filesystem installed on this thread, if any.
+    static MEMORY: std::cell::RefCell<Option<std::rc::Rc<Memory>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Run `operation` with the backend of this thread: the in-memory filesystem if a
+/// [`MemoryBackend`] is installed, and the real filesystem otherwise. Failures are
+/// injected if [`FailureInjection`](super::FailureInjection) is installed, operations
+/// violating the [`Policy`](crate::policy::Policy) in effect are refused, and every
+/// operation is reported to [`crate::instrument`].
+pub(super) fn with<T>(operation: impl FnOnce(&dyn FsBackend) -> T) -> T {
+    let guarded = |backend: &dyn FsBackend| {
+        super::guarded::wrap(backend, |backend| {
+            super::instrumented::wrap(backend, operation)
+        })
+    };
+    match MEMORY.with_borrow(Clone::clone) {
+        Some(memory) => super::chaos::wrap(memory.as_ref(), guarded),
+        None => super::chaos::wrap(&Std, guarded),
+    }
+}
+
+/// An in-memory filesystem for unit tests.
+///
+/// While it is installed, [`File`](super::File) and [`Directory`](super::Directory)
+/// operate on it instead of the disk; dropping it removes it again, together with
+/// everything that was written.
+///
+/// The in-memory filesystem starts empty (apart from `/`) and is only seen by the
+/// thread that installed it, so tests running in parallel do not interfere. It has
+/// no symbolic links, permissions or timestamps.
+/// Relative paths are resolved against the current working directory without
+/// accessing it. Following a file with [`File::follow`](super::File::follow) needs a
+/// file on disk and fails with it installed. Functions taking plain paths, like
+/// [`bucket_by_month`](super::bucket_by_month), still use the disk.
+///
+/// ```
+/// # use rush::prelude::*;
+/// let memory = fs::MemoryBackend::install();
+/// let config = File::new("/etc/app/config.toml");
+/// config.create_on_fs_recursive().unwrap();
+/// config.overwrite("port = 8080\n").unwrap();
+/// assert_eq!(config.read().unwrap(), "port = 8080\n");
+/// assert!(!std::path::Path::new("/etc/app/config.toml").exists());
+/// # drop(config);
+/// # drop(memory);
+/// ```
+#[derive(Debug)]
+#[must_use = "the in-memory filesystem is removed when it is dropped"]
+pub struct MemoryBackend {
+    /// The in-memory filesystem
+    memory:   std::rc::Rc<Memory>,
+    /// The in-memory filesystem that was installed before, if any
+    previous: Option<std::rc::Rc<Memory>>,
+}
+
+impl MemoryBackend {
+    /// Install an empty in-memory filesystem on this thread. An in-memory
+    /// filesystem installed before is restored when this one is dropped.
+    pub fn install() -> Self {
+        let memory = std::rc::Rc::new(Memory::default());
+        let previous = MEMORY.replace(Some(std::rc::Rc::clone(&memory)));
+        Self { memory, previous }
+    }
+
+    /// All files and directories (apart from `/`), sorted.
+    #[must_use]
+    pub fn paths(&self) -> Vec<std::path::PathBuf> {
+        self.memory.nodes.borrow().keys().cloned().collect()
+    }
+}
+
+impl Drop for MemoryBackend {
+    fn drop(&mut self) { MEMORY.set(self.previous.take()); }
+}
+
+#[cfg(test)]
+mod backend_test {
+    use super::{
+        super::{
+            generate_test_path,
+            Directory,
+            FSError,
+            FSResult,
+            File,
+            Object as _,
+        },
+        *,
+    };
+
+    #[test]
+    fn files_and_directories() -> FSResult<()> {
+        let root = generate_test_path();
+        let memory = MemoryBackend::install();
+        let directory = Directory::new(root.join("a/b"));
+        directory.create_on_fs_recursive()?;
+        assert!(directory.exists()?);
+        assert!(directory.exists_and_is_empty()?);
+
+        let file = File::new(root.join("a/b/file.txt"));
+        file.write_new("Hello")?;
+        file.append(", there")?;
+        assert_eq!(file.read()?, "Hello, there");
+        assert_eq!(file.metadata()?.size, 12);
+        assert_eq!(file.write_new("again"), Err(FSError::AlreadyExists));
+        assert!(matches!(
+            Directory::new(file.path()).exists(),
+            Err(FSError::TypeMismatch(ObjectType::File))
+        ));
+        assert_eq!(
+            File::new(root.join("missing/file.txt")).overwrite("nothing"),
+            Err(FSError::NonExistent)
+        );
+
+        let copy = file.copy_to(root.join("a/copy.txt"))?;
+        let moved = Directory::new(root.join("a")).move_to(root.join("c"))?;
+        assert!(!file.exists()?);
+        let moved_file = File::new(root.join("c/b/file.txt"));
+        assert_eq!(moved_file.read()?, "Hello, there");
+        let paths = memory
+            .paths()
+            .into_iter()
+            .filter(|path| path.starts_with(&root))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                root.clone(),
+                root.join("c"),
+                root.join("c/b"),
+                root.join("c/b/file.txt"),
+                root.join("c/copy.txt"),
+            ]
+        );
+        assert!(!root.exists());
+
+        moved.delete_from_fs()?;
+        assert!(!copy.exists()?);
+        drop((file, copy));
+        drop(memory);
+        assert!(!moved_file.exists()?);
+        Ok(())
+    }
+
+    #[test]
+    fn canonicalize() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let root = generate_test_path();
+        with(|backend| backend.create_dir_all(&root.join("a")))?;
+        assert_eq!(
+            with(|backend| backend.canonicalize(&root.join("a/../a/.")))?,
+            root.join("a")
+        );
+        assert_eq!(
+            with(|backend| backend.canonicalize(&root.join("missing")))
+                .map_err(|error| error.kind()),
+            Err(std::io::ErrorKind::NotFound)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nested_installations() -> FSResult<()> {
+        let outer = MemoryBackend::install();
+        let file = File::new(generate_test_path());
+        file.create_on_fs_recursive()?;
+        {
+            let _inner = MemoryBackend::install();
+            assert!(!file.exists()?);
+        }
+        assert!(file.exists()?);
+        assert_eq!(outer.paths().last(), Some(file.path()));
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+//! This module contains marked blocks: text in a configuration file that is owned
+//! by a script and enclosed in marker comments, so that it can be updated or removed
+//! later without touching the rest of the file.
+//!
+//! ```text
+//! # BEGIN <marker>
+//! <content>
+//! # END <marker>
+//! ```
+//!
+//! The markers use `#` comments, which suits shell profiles and most configuration
+//! formats. A marker is a single line.
+
+use super::{
+    backend,
+    FSResult,
+    ObjectType,
+};
+
+/// The lines enclosing the block named `marker`.
+fn marker_lines(marker: &str) -> (String, String) {
+    (format!("# BEGIN {marker}"), format!("# END {marker}"))
+}
+
+/// Replace the block named `marker` in `text` with `content`, or remove it if
+/// `content` is [`None`]. A missing block is appended at the end.
+fn replace_block(text: &str, marker: &str, content: Option<&str>) -> String {
+    let (begin, end) = marker_lines(marker);
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let is = |line: &str, expected: &str| line.trim_end() == expected;
+    let found = lines
+        .iter()
+        .position(|line| is(line, &begin))
+        .and_then(|start| {
+            lines[start..]
+                .iter()
+                .position(|line| is(line, &end))
+                .map(|length| (start, start + length))
+        });
+
+    let block = content.map(|content| {
+        let content = content.trim_end_matches('\n');
+        if content.is_empty() {
+            format!("{begin}\n{end}\n")
+        } else {
+            format!("{begin}\n{content}\n{end}\n")
+        }
+    });
+    let (before, after) = match found {
+        Some((start, stop)) => (lines[..start].concat(), lines[stop + 1..].concat()),
+        None => (text.to_string(), String::new()),
+    };
+    let mut replaced = before;
+    if let Some(block) = block {
+        if !replaced.is_empty() && !replaced.ends_with('\n') {
+            replaced.push('\n');
+        }
+        replaced.push_str(&block);
+    }
+    replaced.push_str(&after);
+    replaced
+}
+
+/// The content of the file at `path`, or [`None`] if it does not exist.
+fn read(path: &std::path::Path) -> FSResult<Option<String>> {
+    match backend::with(|backend| backend.object_type(path)) {
+        None => Ok(None),
+        Some(ObjectType::File) => {
+            let content = backend::with(|backend| backend.read(path))?;
+            String::from_utf8(content)
+                .map(Some)
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidData).into())
+        },
+        Some(object_type) => Err(super::FSError::TypeMismatch(object_type)),
+    }
+}
+
+/// Ensure that the file at `path` contains the block named `marker` with exactly
+/// `content`. Returns whether the file was changed.
+///
+/// An existing block is replaced in place; otherwise, the block is appended and the
+/// file and its parent directories are created if needed.
+///
+/// ```
+/// # use rush::prelude::*;
+/// let _memory = fs::MemoryBackend::install();
+/// let path = "/home/user/.bashrc";
+/// assert!(fs::ensure_block(path, "rush: path", "export PATH=\"$HOME/bin:$PATH\"").unwrap());
+/// assert!(!fs::ensure_block(path, "rush: path", "export PATH=\"$HOME/bin:$PATH\"").unwrap());
+/// assert!(fs::remove_block(path, "rush: path").unwrap());
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if reading or writing the file fails or if it is not UTF-8.
+pub fn ensure_block(
+    path: impl AsRef<std::path::Path>,
+    marker: &str,
+    content: &str,
+) -> FSResult<bool> {
+    let path = path.as_ref();
+    let current = read(path)?;
+    let text = current.as_deref().unwrap_or_default();
+    let updated = replace_block(text, marker, Some(content));
+    if current.is_some() && updated == text {
+        return Ok(false);
+    }
+
+    log::trace!("Writing block '{marker}' to '{}'", path.to_string_lossy());
+    if let Some(parent) = path.parent() {
+        backend::with(|backend| backend.create_dir_all(parent))?;
+    }
+    backend::with(|backend| backend.write(path, updated.as_bytes(), false))?;
+    Ok(true)
+}
+
+/// Remove the block named `marker` from the file at `path`. Returns whether the
+/// file was changed; a missing file or block is not an error.
+///
+/// # Errors
+///
+/// Returns an error if reading or writing the file fails or if it is not UTF-8.
+pub fn remove_block(path: impl AsRef<std::path::Path>, marker: &str) -> FSResult<bool> {
+    let path = path.as_ref();
+    let Some(text) = read(path)? else {
+        return Ok(false);
+    };
+    let updated = replace_block(&text, marker, None);
+    if updated == text {
+        return Ok(false);
+    }
+
+    log::trace!(
+        "Removing block '{marker}' from '{}'",
+        path.to_string_lossy()
+    );
+    backend::with(|backend| backend.write(path, updated.as_bytes(), false))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod blocks_test {
+    use super::*;
+
+    #[test]
+    fn replace() {
+        let text = "alias ll='ls -l'\n# BEGIN a\nold\n# END a\nexport EDITOR=vim";
+        assert_eq!(
+            replace_block(text, "a", Some("new\nlines\n")),
+            "alias ll='ls -l'\n# BEGIN a\nnew\nlines\n# END a\nexport EDITOR=vim"
+        );
+        assert_eq!(
+            replace_block(text, "a", None),
+            "alias ll='ls -l'\nexport EDITOR=vim"
+        );
+        assert_eq!(
+            replace_block(text, "b", Some("added")),
+            format!("{text}\n# BEGIN b\nadded\n# END b\n")
+        );
+        assert_eq!(replace_block("", "b", None), "");
+        // Without its end, a begin marker does not start a block.
+        assert_eq!(
+            replace_block("# BEGIN a\n", "a", Some("x")),
+            "# BEGIN a\n# BEGIN a\nx\n# END a\n"
+        );
+    }
+
+    #[test]
+    fn ensure_and_remove() -> FSResult<()> {
+        let _memory = super::super::MemoryBackend::install();
+        let path = super::super::generate_test_path().join("nested/profile");
+        assert!(!remove_block(&path, "a")?);
+        assert!(ensure_block(&path, "a", "first")?);
+        assert!(!ensure_block(&path, "a", "first\n")?);
+        assert!(ensure_block(&path, "b", "second")?);
+        assert!(ensure_block(&path, "a", "changed")?);
+        assert_eq!(
+            read(&path)?.as_deref(),
+            Some("# BEGIN a\nchanged\n# END a\n# BEGIN b\nsecond\n# END b\n")
+        );
+        assert!(remove_block(&path, "a")?);
+        assert!(!remove_block(&path, "a")?);
+        assert_eq!(
+            read(&path)?.as_deref(),
+            Some("# BEGIN b\nsecond\n# END b\n")
+        );
+        Ok(())
+    }
+}
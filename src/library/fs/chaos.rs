@@ -0,0 +1,506 @@
+//! This module contains a failure injection layer on top of the filesystem backends,
+//! so that rollback and retry paths of scripts can be tested deterministically.
+
+use super::{
+    backend::FsBackend,
+    FSError,
+    Metadata,
+    ObjectType,
+};
+
+/// A filesystem operation failures can be injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operation {
+    /// Reading a file or its size
+    Read,
+    /// Writing or appending to a file
+    Write,
+    /// Creating a directory
+    Create,
+    /// Removing a file or directory
+    Remove,
+    /// Moving a file or directory
+    Rename,
+    /// Copying a file
+    Copy,
+    /// Listing the entries of a directory
+    List,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Create => "create",
+            Self::Remove => "remove",
+            Self::Rename => "rename",
+            Self::Copy => "copy",
+            Self::List => "list",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The error an injected failure produces.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// An I/O error of this kind, converted to a [`FSError`] like a real one
+    Io(std::io::ErrorKind),
+    /// Exactly this [`FSError`]
+    Fs(FSError),
+}
+
+impl From<std::io::ErrorKind> for Fault {
+    fn from(kind: std::io::ErrorKind) -> Self { Self::Io(kind) }
+}
+
+impl From<FSError> for Fault {
+    fn from(error: FSError) -> Self { Self::Fs(error) }
+}
+
+impl Fault {
+    /// The I/O error the backend reports for this fault.
+    fn to_io_error(&self) -> std::io::Error {
+        match self {
+            Self::Io(kind) => std::io::Error::from(*kind),
+            Self::Fs(error) => std::io::Error::other(error.clone()),
+        }
+    }
+}
+
+/// A failure that was injected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Failure {
+    /// The operation that failed
+    pub operation: Operation,
+    /// The path the operation was performed on
+    pub path:      std::path::PathBuf,
+    /// The error the operation failed with
+    pub fault:     Fault,
+}
+
+/// Makes [`File`](super::File) and [`Directory`](super::Directory) operations fail on
+/// purpose.
+///
+/// Failures are injected for operations on chosen paths (and everything below them)
+/// and randomly for a percentage of all other operations. The random failures are
+/// drawn from a seeded generator, so the same seed fails the same operations every
+/// run. Checking whether a path exists never fails.
+///
+/// Like [`MemoryBackend`](super::MemoryBackend), which it can be combined with, the
+/// failures only apply to the thread that installed them.
+///
+/// ```
+/// # use rush::prelude::*;
+/// use rush::fs::{FSError, FailureInjection, Operation};
+///
+/// let memory = fs::MemoryBackend::install();
+/// let failures = FailureInjection::new()
+///     .fail_path("/srv/app/current", std::io::ErrorKind::PermissionDenied)
+///     .only([Operation::Write])
+///     .install();
+/// let release = File::new("/srv/app/current/VERSION");
+/// assert_eq!(release.create_on_fs_recursive(), Err(FSError::PermissionDenied));
+/// assert_eq!(failures.failures().len(), 1);
+/// # drop(release);
+/// # drop(failures);
+/// # drop(memory);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailureInjection {
+    /// The seed random failures are drawn with
+    seed:       u64,
+    /// The percentage of operations that fail randomly, and with what
+    random:     Option<(u8, Fault)>,
+    /// The paths operations on which fail, and with what
+    paths:      Vec<(std::path::PathBuf, Fault)>,
+    /// The operations failures are injected into, or all if [`None`]
+    operations: Option<Vec<Operation>>,
+}
+
+impl Default for FailureInjection {
+    fn default() -> Self { Self::new() }
+}
+
+impl FailureInjection {
+    /// No failures, with seed 0 for random failures.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            seed:       0,
+            random:     None,
+            paths:      vec![],
+            operations: None,
+        }
+    }
+
+    /// Draw random failures with `seed`.
+    #[must_use]
+    pub const fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Fail `percent` percent of the operations with `fault`. Values above 100 fail
+    /// every operation.
+    #[must_use]
+    pub fn fail_randomly(mut self, percent: u8, fault: impl Into<Fault>) -> Self {
+        self.random = Some((percent.min(100), fault.into()));
+        self
+    }
+
+    /// Fail every operation on `path` or a path below it with `fault`. Paths are
+    /// compared as given, without resolving them.
+    #[must_use]
+    pub fn fail_path(mut self, path: impl AsRef<std::path::Path>, fault: impl Into<Fault>) -> Self {
+        self.paths.push((path.as_ref().to_path_buf(), fault.into()));
+        self
+    }
+
+    /// Only inject failures into `operations`.
+    #[must_use]
+    pub fn only(mut self, operations: impl IntoIterator<Item = Operation>) -> Self {
+        self.operations = Some(operations.into_iter().collect());
+        self
+    }
+
+    /// Start injecting failures on this thread. Failures injected before are
+    /// replaced until the returned guard is dropped.
+    pub fn install(self) -> InjectedFailures {
+        let active = std::rc::Rc::new(Active {
+            state:       std::cell::Cell::new(self.seed),
+            failures:    std::cell::RefCell::new(vec![]),
+            description: self,
+        });
+        let previous = ACTIVE.replace(Some(std::rc::Rc::clone(&active)));
+        InjectedFailures { active, previous }
+    }
+}
+
+/// Failure injection that is installed on a thread.
+#[derive(Debug)]
+struct Active {
+    /// What to inject
+    description: FailureInjection,
+    /// The state of the generator random failures are drawn from
+    state:       std::cell::Cell<u64>,
+    /// The failures injected so far
+    failures:    std::cell::RefCell<Vec<Failure>>,
+}
+
+impl Active {
+    /// A pseudo-random number in `0..100`, drawn with `SplitMix64`.
+    fn roll(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.state.set(state);
+        let mut value = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (value ^ (value >> 31)) % 100
+    }
+
+    /// Decide whether `operation` on `paths` fails, and record the failure.
+    fn check(&self, operation: Operation, paths: &[&std::path::Path]) -> std::io::Result<()> {
+        let description = &self.description;
+        if description
+            .operations
+            .as_ref()
+            .is_some_and(|operations| !operations.contains(&operation))
+        {
+            return Ok(());
+        }
+
+        let targeted = paths.iter().find_map(|path| {
+            description
+                .paths
+                .iter()
+                .find(|(target, _)| path.starts_with(target))
+                .map(|(_, fault)| (*path, fault))
+        });
+        let failure = targeted.or_else(|| {
+            let (percent, fault) = description.random.as_ref()?;
+            let path = *paths.first()?;
+            (self.roll() < u64::from(*percent)).then_some((path, fault))
+        });
+
+        if let Some((path, fault)) = failure {
+            log::debug!(
+                "Injecting failure into {} of '{}'",
+                operation,
+                path.display()
+            );
+            self.failures.borrow_mut().push(Failure {
+                operation,
+                path: path.to_path_buf(),
+                fault: fault.clone(),
+            });
+            return Err(fault.to_io_error());
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// The failure injection installed on this thread, if any.
+    static ACTIVE: std::cell::RefCell<Option<std::rc::Rc<Active>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Injects failures into `backend` while [`FailureInjection`] is installed.
+struct Faulty<'a> {
+    /// The backend operations are passed on to
+    backend: &'a dyn FsBackend,
+    /// What to inject
+    active:  &'a Active,
+}
+
+impl FsBackend for Faulty<'_> {
+    fn object_type(&self, path: &std::path::Path) -> Option<ObjectType> {
+        self.backend.object_type(path)
+    }
+
+    fn open(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.open(path)
+    }
+
+    fn open_file(&self, path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.open_file(path)
+    }
+
+    fn read(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.read(path)
+    }
+
+    fn write(&self, path: &std::path::Path, content: &[u8], append: bool) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[path])?;
+        self.backend.write(path, content, append)
+    }
+
+    fn create_new(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[path])?;
+        self.backend.create_new(path, mode)
+    }
+
+    fn sync(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[path])?;
+        self.backend.sync(path)
+    }
+
+    fn create_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Create, &[path])?;
+        self.backend.create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Create, &[path])?;
+        self.backend.create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Remove, &[path])?;
+        self.backend.remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Remove, &[path])?;
+        self.backend.remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Rename, &[from, to])?;
+        self.backend.rename(from, to)
+    }
+
+    fn copy(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<u64> {
+        self.active.check(Operation::Copy, &[from, to])?;
+        self.backend.copy(from, to)
+    }
+
+    fn len(&self, path: &std::path::Path) -> std::io::Result<u64> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.len(path)
+    }
+
+    fn metadata(&self, path: &std::path::Path) -> std::io::Result<Metadata> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.metadata(path)
+    }
+
+    fn read_dir(&self, path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+        self.active.check(Operation::List, &[path])?;
+        self.backend.read_dir(path)
+    }
+
+    fn read_link(&self, path: &std::path::Path) -> std::io::Result<Option<std::path::PathBuf>> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.canonicalize(path)
+    }
+
+    fn symlink(&self, target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Create, &[link])?;
+        self.backend.symlink(target, link)
+    }
+
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        self.active.check(Operation::Create, &[link])?;
+        self.backend.hard_link(original, link)
+    }
+
+    fn copy_metadata(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+        permissions: bool,
+        timestamps: bool,
+    ) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[to])?;
+        self.backend
+            .copy_metadata(from, to, permissions, timestamps)
+    }
+
+    fn mode(&self, path: &std::path::Path) -> std::io::Result<u32> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.mode(path)
+    }
+
+    fn set_mode(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[path])?;
+        self.backend.set_mode(path, mode)
+    }
+
+    fn owner(&self, path: &std::path::Path) -> std::io::Result<(u32, u32)> {
+        self.active.check(Operation::Read, &[path])?;
+        self.backend.owner(path)
+    }
+
+    fn set_owner(
+        &self,
+        path: &std::path::Path,
+        user: Option<u32>,
+        group: Option<u32>,
+    ) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[path])?;
+        self.backend.set_owner(path, user, group)
+    }
+
+    fn set_times(
+        &self,
+        path: &std::path::Path,
+        accessed: Option<std::time::SystemTime>,
+        modified: Option<std::time::SystemTime>,
+    ) -> std::io::Result<()> {
+        self.active.check(Operation::Write, &[path])?;
+        self.backend.set_times(path, accessed, modified)
+    }
+}
+
+/// Run `operation` with `backend`, injecting failures if [`FailureInjection`] is
+/// installed on this thread.
+pub(super) fn wrap<T>(backend: &dyn FsBackend, operation: impl FnOnce(&dyn FsBackend) -> T) -> T {
+    match ACTIVE.with_borrow(Clone::clone) {
+        Some(active) => operation(&Faulty {
+            backend,
+            active: &active,
+        }),
+        None => operation(backend),
+    }
+}
+
+/// Failure injection installed by [`FailureInjection::install`]; dropping it stops
+/// injecting failures.
+#[derive(Debug)]
+#[must_use = "failures are no longer injected when this is dropped"]
+pub struct InjectedFailures {
+    /// The installed failure injection
+    active:   std::rc::Rc<Active>,
+    /// The failure injection that was installed before, if any
+    previous: Option<std::rc::Rc<Active>>,
+}
+
+impl InjectedFailures {
+    /// The failures injected so far, in order.
+    #[must_use]
+    pub fn failures(&self) -> Vec<Failure> { self.active.failures.borrow().clone() }
+}
+
+impl Drop for InjectedFailures {
+    fn drop(&mut self) { ACTIVE.set(self.previous.take()); }
+}
+
+#[cfg(test)]
+mod chaos_test {
+    use super::*;
+    use crate::fs::{
+        generate_test_path,
+        Directory,
+        FSResult,
+        File,
+        MemoryBackend,
+        Object as _,
+    };
+
+    #[test]
+    fn paths_and_operations() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let root = Directory::new(generate_test_path());
+        root.create_on_fs_recursive()?;
+        let file = File::new(root.path().join("state.json"));
+        file.write_new("{}")?;
+
+        let failures = FailureInjection::new()
+            .fail_path(file.path(), FSError::Unknown(String::from("disk full")))
+            .only([Operation::Write, Operation::Copy])
+            .install();
+        assert_eq!(file.read()?, "{}");
+        assert_eq!(
+            file.overwrite("{ \"a\": 1 }"),
+            Err(FSError::Unknown(String::from("disk full")))
+        );
+        assert!(file.copy_to(root.path().join("copy.json")).is_err());
+        assert_eq!(
+            failures
+                .failures()
+                .iter()
+                .map(|failure| failure.operation)
+                .collect::<Vec<_>>(),
+            [Operation::Write, Operation::Copy]
+        );
+
+        drop(failures);
+        file.overwrite("{ \"a\": 1 }")?;
+        assert_eq!(file.read()?, "{ \"a\": 1 }");
+        Ok(())
+    }
+
+    #[test]
+    fn random_failures_are_deterministic() {
+        let run = |seed| {
+            let _memory = MemoryBackend::install();
+            let root = Directory::new(generate_test_path());
+            root.create_on_fs_recursive().unwrap();
+            let _failures = FailureInjection::new()
+                .seed(seed)
+                .fail_randomly(30, std::io::ErrorKind::Interrupted)
+                .only([Operation::Create, Operation::Write])
+                .install();
+            (0..50)
+                .map(|index| File::new(root.path().join(index.to_string())).write_new("x"))
+                .map(|result| result.is_err())
+                .collect::<Vec<_>>()
+        };
+
+        let first = run(7);
+        assert_eq!(first, run(7));
+        assert_ne!(first, run(8));
+        let failed = first.iter().filter(|failed| **failed).count();
+        assert!((5..30).contains(&failed), "{failed} of 50 failed");
+    }
+}
@@ -0,0 +1,305 @@
+//! This module contains comparing files ([`File::content_equals`]) and directory
+//! trees ([`Directory::diff`]) without running `cmp` or `diff`, e.g. to restart a
+//! service only if its generated configuration changed.
+//!
+//! ```
+//! # use rush::prelude::*;
+//! let _memory = fs::MemoryBackend::install();
+//! let generated = File::new("/nginx.conf.new");
+//! let deployed = File::new("/nginx.conf");
+//! generated.overwrite("worker_processes 4;\n").unwrap();
+//! if !generated.content_equals(&deployed).unwrap() {
+//!     generated.copy_to(deployed.path()).unwrap();
+//!     // Restart the service here.
+//! }
+//! assert!(generated.content_equals(&deployed).unwrap());
+//! ```
+
+use super::{
+    backend,
+    Directory,
+    FSError,
+    FSResult,
+    File,
+    Object,
+    ObjectType,
+};
+
+/// How many bytes of each file are compared at once.
+const CHUNK: usize = 64 * 1024;
+
+/// How the content of two directory trees differs, see [`Directory::diff`]. The
+/// paths are relative to the roots of the trees and sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DirectoryDiff {
+    /// The entries only the other tree has; for a directory, its content is not
+    /// listed separately
+    pub added:   Vec<std::path::PathBuf>,
+    /// The entries only this tree has; for a directory, its content is not listed
+    /// separately
+    pub removed: Vec<std::path::PathBuf>,
+    /// The entries both trees have, but with different content, types or link
+    /// targets
+    pub changed: Vec<std::path::PathBuf>,
+}
+
+impl DirectoryDiff {
+    /// Whether the trees have the same content.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// What an entry of a directory tree is, with symbolic links not followed.
+#[derive(Debug, PartialEq, Eq)]
+enum Entry {
+    /// A file
+    File,
+    /// A directory
+    Directory,
+    /// A symbolic link to the given target
+    Link(std::path::PathBuf),
+}
+
+impl Entry {
+    /// What the entry at `path` is.
+    fn of(path: &std::path::Path) -> FSResult<Self> {
+        if let Some(target) = backend::with(|backend| backend.read_link(path))? {
+            return Ok(Self::Link(target));
+        }
+        match backend::with(|backend| backend.object_type(path)) {
+            Some(ObjectType::File) => Ok(Self::File),
+            Some(ObjectType::Directory) => Ok(Self::Directory),
+            Some(object_type) => Err(FSError::TypeMismatch(object_type)),
+            None => Err(FSError::NonExistent),
+        }
+    }
+}
+
+/// Read from `reader` into `buffer` until it is full or the content ends, and
+/// return how many bytes were read.
+fn fill(reader: &mut dyn std::io::Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Whether the files at `a` and `b` have the same content, which is read as a
+/// stream and only as far as needed.
+fn same_content(a: &std::path::Path, b: &std::path::Path) -> FSResult<bool> {
+    if backend::with(|backend| backend.len(a))? != backend::with(|backend| backend.len(b))? {
+        return Ok(false);
+    }
+    let mut a = backend::with(|backend| backend.open(a))?;
+    let mut b = backend::with(|backend| backend.open(b))?;
+    let (mut chunk_a, mut chunk_b) = (vec![0; CHUNK], vec![0; CHUNK]);
+    loop {
+        let read = fill(a.as_mut(), &mut chunk_a)?;
+        if read != fill(b.as_mut(), &mut chunk_b)? || chunk_a[..read] != chunk_b[..read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// The sorted names of the entries of the directory at `path`.
+fn names(path: &std::path::Path) -> FSResult<Vec<std::ffi::OsString>> {
+    let mut names: Vec<_> = backend::with(|backend| backend.read_dir(path))?
+        .into_iter()
+        .filter_map(|entry| entry.file_name().map(std::ffi::OsStr::to_os_string))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Add how the directories `ours` and `theirs` differ to `diff`, with `relative`
+/// being their path relative to the roots of the trees.
+fn diff_directories(
+    ours: &std::path::Path,
+    theirs: &std::path::Path,
+    relative: &std::path::Path,
+    diff: &mut DirectoryDiff,
+) -> FSResult<()> {
+    let our_names = names(ours)?;
+    let their_names = names(theirs)?;
+    for name in &their_names {
+        if our_names.binary_search(name).is_err() {
+            diff.added.push(relative.join(name));
+        }
+    }
+    for name in our_names {
+        let path = relative.join(&name);
+        if their_names.binary_search(&name).is_err() {
+            diff.removed.push(path);
+            continue;
+        }
+        let (ours, theirs) = (ours.join(&name), theirs.join(&name));
+        match (Entry::of(&ours)?, Entry::of(&theirs)?) {
+            (Entry::Directory, Entry::Directory) => {
+                diff_directories(&ours, &theirs, &path, diff)?;
+            },
+            (Entry::File, Entry::File) => {
+                if !same_content(&ours, &theirs)? {
+                    diff.changed.push(path);
+                }
+            },
+            (our_entry, their_entry) => {
+                if our_entry != their_entry {
+                    diff.changed.push(path);
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+impl File {
+    /// Whether this file and `other` have the same content. The files are read as a
+    /// stream and only until the first difference; files of different sizes are
+    /// not read at all.
+    ///
+    /// A file that does not exist differs from one that does, so that a generated
+    /// file can be compared to one that is not deployed yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if neither file exists, or an error if one
+    /// of them cannot be read.
+    pub fn content_equals(&self, other: &Self) -> FSResult<bool> {
+        log::trace!("Comparing the content of {} and {}", self, other);
+        match (self.exists()?, other.exists()?) {
+            (true, true) => same_content(&self.path, &other.path),
+            (false, false) => Err(FSError::NonExistent),
+            _ => Ok(false),
+        }
+    }
+}
+
+impl Directory {
+    /// How the content of `other` differs from the content of this directory tree,
+    /// from the point of view of this one: what `other` added, what it removed and
+    /// what it changed. Symbolic links are not followed, their targets are compared
+    /// instead. Permissions, owners and times are not compared.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// Directory::new("/old").create_on_fs().unwrap();
+    /// Directory::new("/new").create_on_fs().unwrap();
+    /// File::new("/old/a.conf").overwrite("a").unwrap();
+    /// File::new("/new/a.conf").overwrite("b").unwrap();
+    /// File::new("/new/b.conf").overwrite("b").unwrap();
+    ///
+    /// let diff = Directory::new("/old").diff(&Directory::new("/new")).unwrap();
+    /// assert_eq!(diff.added, [std::path::Path::new("b.conf")]);
+    /// assert_eq!(diff.changed, [std::path::Path::new("a.conf")]);
+    /// assert!(diff.removed.is_empty());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if one of the directories does not exist, or
+    /// an error if an entry cannot be read.
+    pub fn diff(&self, other: &Self) -> FSResult<DirectoryDiff> {
+        log::trace!("Comparing the content of {} and {}", self, other);
+        if !self.exists()? || !other.exists()? {
+            return Err(FSError::NonExistent);
+        }
+        let mut diff = DirectoryDiff::default();
+        diff_directories(&self.path, &other.path, std::path::Path::new(""), &mut diff)?;
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod compare_test {
+    use super::*;
+    use crate::fs::MemoryBackend;
+
+    #[test]
+    fn files() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let (a, b) = (File::new("/a.txt"), File::new("/b.txt"));
+        assert_eq!(a.content_equals(&b), Err(FSError::NonExistent));
+        a.overwrite("x".repeat(CHUNK + 1))?;
+        assert!(!a.content_equals(&b)?);
+        assert!(!b.content_equals(&a)?);
+
+        b.overwrite("x".repeat(CHUNK + 1))?;
+        assert!(a.content_equals(&b)?);
+        b.overwrite(format!("{}y", "x".repeat(CHUNK)))?;
+        assert!(!a.content_equals(&b)?);
+        b.overwrite("x")?;
+        assert!(!a.content_equals(&b)?);
+        assert!(a.content_equals(&a)?);
+        Ok(())
+    }
+
+    #[test]
+    fn directories() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        for directory in [
+            "/old/etc",
+            "/old/var/cache",
+            "/old/same",
+            "/new/etc",
+            "/new/same",
+            "/new/srv",
+        ] {
+            Directory::new(directory).create_on_fs_recursive()?;
+        }
+        let files: Vec<File> = [
+            ("/old/etc/hosts", "localhost"),
+            ("/new/etc/hosts", "localhost"),
+            ("/old/etc/motd", "welcome"),
+            ("/new/etc/motd", "goodbye"),
+            ("/old/same/file", "same"),
+            ("/new/same/file", "same"),
+            ("/old/kind", "a file"),
+            ("/new/srv/index.html", "<html>"),
+        ]
+        .into_iter()
+        .map(|(path, content)| {
+            let file = File::new(path);
+            file.overwrite(content).map(|()| file)
+        })
+        .collect::<FSResult<_>>()?;
+        Directory::new("/new/kind").create_on_fs()?;
+
+        let diff = Directory::new("/old").diff(&Directory::new("/new"))?;
+        let paths = |paths: &[&str]| -> Vec<std::path::PathBuf> {
+            paths.iter().map(std::path::PathBuf::from).collect()
+        };
+        assert_eq!(
+            diff,
+            DirectoryDiff {
+                added:   paths(&["srv"]),
+                removed: paths(&["var"]),
+                changed: paths(&["etc/motd", "kind"]),
+            }
+        );
+        assert!(!diff.is_empty());
+        assert!(Directory::new("/old/same")
+            .diff(&Directory::new("/new/same"))?
+            .is_empty());
+        assert_eq!(
+            Directory::new("/old").diff(&Directory::new("/missing")),
+            Err(FSError::NonExistent)
+        );
+        drop(files);
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+//! This module contains copying directories recursively, the library equivalent of
+//! `cp -r` (and `cp -a` with [`CopyOptions`]).
+
+use super::{
+    backend,
+    Directory,
+    FSError,
+    FSResult,
+    Object as _,
+    ObjectType,
+};
+
+/// What [`Directory::copy_with`] preserves besides the contents. By default,
+/// copies get the permissions and timestamps new files get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CopyOptions {
+    /// Whether permissions are copied
+    permissions: bool,
+    /// Whether access and modification times are copied
+    timestamps:  bool,
+}
+
+impl CopyOptions {
+    /// Preserve neither permissions nor timestamps.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            permissions: false,
+            timestamps:  false,
+        }
+    }
+
+    /// Whether the copies get the permissions of the originals.
+    #[must_use]
+    pub const fn preserve_permissions(mut self, enabled: bool) -> Self {
+        self.permissions = enabled;
+        self
+    }
+
+    /// Whether the copies get the access and modification times of the originals.
+    #[must_use]
+    pub const fn preserve_timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled;
+        self
+    }
+
+    /// Whether any metadata is preserved.
+    const fn preserves_metadata(self) -> bool { self.permissions || self.timestamps }
+}
+
+/// How far [`Directory::copy_with`] is, passed to its callback after each entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CopyProgress<'a> {
+    /// The entry just copied, relative to the copied directory
+    pub path:          &'a std::path::Path,
+    /// The number of entries (files, directories and symbolic links) copied so far
+    pub entries:       u64,
+    /// The number of entries to copy
+    pub total_entries: u64,
+    /// The number of bytes of files copied so far
+    pub bytes:         u64,
+    /// The number of bytes of files to copy
+    pub total_bytes:   u64,
+}
+
+/// An entry below the copied directory.
+#[derive(Debug)]
+enum Entry {
+    /// A directory
+    Directory,
+    /// A file of the given size
+    File(u64),
+    /// A symbolic link with the given target
+    Link(std::path::PathBuf),
+}
+
+/// The entries below `root.join(relative)` with their paths relative to `root`,
+/// parents before their children.
+fn walk(
+    root: &std::path::Path,
+    relative: &std::path::Path,
+    entries: &mut Vec<(std::path::PathBuf, Entry)>,
+) -> FSResult<()> {
+    let mut paths = backend::with(|backend| backend.read_dir(&root.join(relative)))?;
+    paths.sort();
+    for path in paths {
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let relative = relative.join(name);
+        // Symbolic links are copied as links, even if they point to directories.
+        if let Some(target) = backend::with(|backend| backend.read_link(&path))? {
+            entries.push((relative, Entry::Link(target)));
+            continue;
+        }
+        match backend::with(|backend| backend.object_type(&path)) {
+            Some(ObjectType::Directory) => {
+                entries.push((relative.clone(), Entry::Directory));
+                walk(root, &relative, entries)?;
+            },
+            Some(ObjectType::File) => {
+                let size = backend::with(|backend| backend.len(&path))?;
+                entries.push((relative, Entry::File(size)));
+            },
+            _ => log::debug!(
+                "Not copying '{}' as it is neither file nor directory",
+                path.display()
+            ),
+        }
+    }
+    Ok(())
+}
+
+impl Directory {
+    /// Copy this directory with everything in it to `target`, like `cp -r`, and
+    /// call `progress` after each copied entry. Symbolic links are copied as links
+    /// with the same target. `target` and missing parents are created; files that
+    /// exist in `target` already are overwritten.
+    ///
+    /// [`Object::copy_to`](super::Object::copy_to) copies with
+    /// [`CopyOptions::new`] and without progress reporting.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// File::new("/photos/2024/a.jpg").create_on_fs_recursive().unwrap();
+    /// let mut copied = Vec::new();
+    /// Directory::new("/photos")
+    ///     .copy_with("/backup/photos", &fs::CopyOptions::new(), |progress| {
+    ///         copied.push(progress.path.to_path_buf());
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(copied.len(), 2);
+    /// assert!(File::new("/backup/photos/2024/a.jpg").exists().unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this directory does not exist, or if reading an entry,
+    /// creating or copying it, or preserving its metadata fails. Entries copied
+    /// before stay copied.
+    pub fn copy_with(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        options: &CopyOptions,
+        mut progress: impl FnMut(&CopyProgress),
+    ) -> FSResult<Self> {
+        let target = target.as_ref();
+        log::trace!(
+            "Copying directory {} to {}",
+            self,
+            Self::path_to_str(target)
+        );
+        if !self.exists()? {
+            return Err(FSError::NonExistent);
+        }
+
+        let mut entries = Vec::new();
+        walk(&self.path, std::path::Path::new(""), &mut entries)?;
+        let total_bytes = entries
+            .iter()
+            .map(|(_, entry)| match entry {
+                Entry::File(size) => *size,
+                _ => 0,
+            })
+            .sum();
+        let total_entries = entries.len() as u64;
+
+        backend::with(|backend| backend.create_dir_all(target))?;
+        let (mut copied, mut bytes) = (0, 0);
+        for (relative, entry) in &entries {
+            let (from, to) = (self.path.join(relative), target.join(relative));
+            match entry {
+                Entry::Directory => backend::with(|backend| backend.create_dir_all(&to))?,
+                Entry::File(_) => {
+                    bytes += backend::with(|backend| backend.copy(&from, &to))?;
+                    if options.preserves_metadata() {
+                        backend::with(|backend| {
+                            backend.copy_metadata(
+                                &from,
+                                &to,
+                                options.permissions,
+                                options.timestamps,
+                            )
+                        })?;
+                    }
+                },
+                Entry::Link(link_target) => {
+                    match backend::with(|backend| backend.remove_file(&to)) {
+                        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                            return Err(error.into())
+                        },
+                        _ => {},
+                    }
+                    backend::with(|backend| backend.symlink(link_target, &to))?;
+                },
+            }
+            copied += 1;
+            progress(&CopyProgress {
+                path: relative,
+                entries: copied,
+                total_entries,
+                bytes,
+                total_bytes,
+            });
+        }
+
+        // Directories last and deepest first, as copying into a directory changes
+        // its modification time.
+        if options.preserves_metadata() {
+            let directories = entries
+                .iter()
+                .filter(|(_, entry)| matches!(entry, Entry::Directory))
+                .map(|(relative, _)| relative.as_path())
+                .rev()
+                .chain(std::iter::once(std::path::Path::new("")));
+            for relative in directories {
+                backend::with(|backend| {
+                    backend.copy_metadata(
+                        &self.path.join(relative),
+                        &target.join(relative),
+                        options.permissions,
+                        options.timestamps,
+                    )
+                })?;
+            }
+        }
+        Ok(Self::new(target))
+    }
+}
+
+#[cfg(test)]
+mod copy_test {
+    use super::{
+        super::{
+            generate_test_path,
+            MemoryBackend,
+        },
+        *,
+    };
+
+    /// Write `content` to `path`, creating its parents.
+    fn write(path: &std::path::Path, content: &str) -> FSResult<()> {
+        backend::with(|backend| backend.create_dir_all(path.parent().unwrap_or(path)))?;
+        backend::with(|backend| backend.write(path, content.as_bytes(), false))?;
+        Ok(())
+    }
+
+    #[test]
+    fn copies_recursively() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let root = generate_test_path();
+        write(&root.join("a.txt"), "a")?;
+        write(&root.join("sub/b.txt"), "bb")?;
+        write(&root.join("sub/deeper/c.txt"), "ccc")?;
+        backend::with(|backend| backend.create_dir_all(&root.join("empty")))?;
+
+        let target = generate_test_path().join("copy");
+        let mut reports = Vec::new();
+        let copy = Directory::new(&root).copy_with(&target, &CopyOptions::new(), |progress| {
+            reports.push((
+                progress.path.to_path_buf(),
+                progress.entries,
+                progress.bytes,
+            ));
+            assert_eq!((progress.total_entries, progress.total_bytes), (6, 6));
+        })?;
+        assert_eq!(copy.path(), &target);
+        assert_eq!(
+            reports,
+            [
+                ("a.txt", 1, 1),
+                ("empty", 2, 1),
+                ("sub", 3, 1),
+                ("sub/b.txt", 4, 3),
+                ("sub/deeper", 5, 3),
+                ("sub/deeper/c.txt", 6, 6),
+            ]
+            .map(|(path, entries, bytes)| (
+                std::path::PathBuf::from(path),
+                entries,
+                bytes
+            ))
+        );
+        assert_eq!(
+            backend::with(|backend| backend.read(&target.join("sub/deeper/c.txt")))?,
+            b"ccc"
+        );
+        assert_eq!(
+            backend::with(|backend| backend.object_type(&target.join("empty"))),
+            Some(ObjectType::Directory)
+        );
+
+        let again = Directory::new(&root).copy_to(&target)?;
+        assert!(again.exists()?);
+        assert_eq!(
+            Directory::new(generate_test_path()).copy_to(&target).err(),
+            Some(FSError::NonExistent)
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserves_links_and_metadata() -> FSResult<()> {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let root = generate_test_path();
+        std::fs::create_dir_all(root.join("sub"))?;
+        std::fs::write(root.join("sub/script.sh"), "#!/bin/sh\n")?;
+        std::fs::set_permissions(
+            root.join("sub/script.sh"),
+            std::fs::Permissions::from_mode(0o750),
+        )?;
+        std::os::unix::fs::symlink("sub", root.join("link"))?;
+        let modified =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        std::fs::File::open(root.join("sub"))?
+            .set_times(std::fs::FileTimes::new().set_modified(modified))?;
+
+        let target = generate_test_path();
+        let options = CopyOptions::new()
+            .preserve_permissions(true)
+            .preserve_timestamps(true);
+        Directory::new(&root).copy_with(&target, &options, |_| {})?;
+
+        assert_eq!(
+            std::fs::read_link(target.join("link"))?,
+            std::path::Path::new("sub")
+        );
+        let script = std::fs::metadata(target.join("sub/script.sh"))?;
+        assert_eq!(script.permissions().mode() & 0o777, 0o750);
+        assert_eq!(std::fs::metadata(target.join("sub"))?.modified()?, modified);
+
+        Directory::new(&root).copy_to(&target)?;
+        assert!(std::fs::symlink_metadata(target.join("link"))?.is_symlink());
+
+        std::fs::remove_dir_all(&root)?;
+        std::fs::remove_dir_all(&target)?;
+        Ok(())
+    }
+}
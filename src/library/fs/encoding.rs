@@ -0,0 +1,413 @@
+//! This module contains functionality for detecting and converting the character
+//! encoding of text files, e.g. of exports that arrive as Latin-1 or UTF-16 from
+//! Windows systems, and for cleaning up their byte order marks and line endings.
+
+use super::{
+    backend,
+    write_atomic_with,
+    FSError,
+    FSResult,
+    File,
+};
+
+/// How many bytes are inspected to detect the encoding of a file.
+const DETECTION_SAMPLE: u64 = 1024 * 1024;
+/// The characters Windows-1252 assigns to the bytes `0x80` to `0x9F`, where it
+/// differs from Latin-1. Unassigned bytes map to the control characters of the same
+/// value, like browsers do.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// The byte order marks of the encodings that have one.
+const BOMS: [(&[u8], Encoding); 3] = [
+    (&[0xEF, 0xBB, 0xBF], Encoding::Utf8),
+    (&[0xFF, 0xFE], Encoding::Utf16Le),
+    (&[0xFE, 0xFF], Encoding::Utf16Be),
+];
+
+/// A character encoding of text files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// UTF-8, which this library (and Rust) uses internally
+    Utf8,
+    /// UTF-16 in little-endian byte order, which Windows uses
+    Utf16Le,
+    /// UTF-16 in big-endian byte order
+    Utf16Be,
+    /// ISO-8859-1, which maps every byte to the code point of the same value
+    Latin1,
+    /// Windows-1252, Latin-1 with printable characters (like `€`) in `0x80..=0x9F`
+    Windows1252,
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Latin1 => "ISO-8859-1",
+            Self::Windows1252 => "Windows-1252",
+        })
+    }
+}
+
+/// How lines of text files end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    /// A line feed (`\n`), used on Unix
+    Lf,
+    /// A carriage return and a line feed (`\r\n`), used on Windows
+    CrLf,
+}
+
+impl LineEnding {
+    /// The line ending most lines in `bytes` end with, or [`None`] if `bytes`
+    /// contain no line feed. If both are equally common, [`LineEnding::Lf`] is
+    /// returned.
+    #[must_use]
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        let (lf, crlf) = bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte == b'\n')
+            .fold((0_usize, 0_usize), |(lf, crlf), (index, _)| {
+                if index > 0 && bytes[index - 1] == b'\r' {
+                    (lf, crlf + 1)
+                } else {
+                    (lf + 1, crlf)
+                }
+            });
+        match (lf, crlf) {
+            (0, 0) => None,
+            (lf, crlf) if crlf > lf => Some(Self::CrLf),
+            _ => Some(Self::Lf),
+        }
+    }
+
+    /// The bytes a line ends with.
+    #[must_use]
+    pub const fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::CrLf => b"\r\n",
+        }
+    }
+}
+
+impl Encoding {
+    /// The encoding the byte order mark at the start of `bytes` belongs to, and
+    /// the length of the mark.
+    #[must_use]
+    pub fn from_bom(bytes: &[u8]) -> Option<(Self, usize)> {
+        BOMS.iter()
+            .find(|(bom, _)| bytes.starts_with(bom))
+            .map(|(bom, encoding)| (*encoding, bom.len()))
+    }
+
+    /// Guess the encoding of `bytes`, which may end in the middle of a character.
+    /// A byte order mark is trusted; otherwise, UTF-16 is recognized by the zero
+    /// bytes of ASCII characters, and text that is not valid UTF-8 is assumed to be
+    /// Windows-1252 if it uses the bytes where it differs from Latin-1.
+    #[must_use]
+    pub fn detect(bytes: &[u8]) -> Self {
+        if let Some((encoding, _)) = Self::from_bom(bytes) {
+            return encoding;
+        }
+
+        let pairs = bytes.len() / 2;
+        let zeros_at = |offset: usize| {
+            bytes
+                .iter()
+                .skip(offset)
+                .step_by(2)
+                .take(pairs)
+                .filter(|byte| **byte == 0)
+                .count()
+        };
+        // ASCII characters, which most text consists of, have a zero high byte.
+        if pairs > 0 && zeros_at(1) * 3 > pairs {
+            return Self::Utf16Le;
+        }
+        if pairs > 0 && zeros_at(0) * 3 > pairs {
+            return Self::Utf16Be;
+        }
+
+        match std::str::from_utf8(bytes) {
+            Ok(_) => Self::Utf8,
+            // The sample may cut off the last character.
+            Err(error) if error.error_len().is_none() => Self::Utf8,
+            Err(_) if bytes.iter().any(|byte| (0x80..=0x9F).contains(byte)) => Self::Windows1252,
+            Err(_) => Self::Latin1,
+        }
+    }
+
+    /// Decode `bytes` into a string. A byte order mark is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` are not valid in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> FSResult<String> {
+        let invalid = || FSError::InvalidEncoding(self.to_string());
+        match self {
+            Self::Utf8 => {
+                let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+                String::from_utf8(bytes.to_vec()).map_err(|_| invalid())
+            },
+            Self::Utf16Le | Self::Utf16Be => {
+                if !bytes.chunks_exact(2).remainder().is_empty() {
+                    return Err(invalid());
+                }
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self == Self::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                let text = char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(|_| invalid())?;
+                Ok(text
+                    .strip_prefix('\u{FEFF}')
+                    .map_or_else(|| text.clone(), String::from))
+            },
+            Self::Latin1 => Ok(bytes.iter().map(|byte| char::from(*byte)).collect()),
+            Self::Windows1252 => Ok(bytes
+                .iter()
+                .map(|byte| match byte {
+                    0x80..=0x9F => WINDOWS_1252[usize::from(byte - 0x80)],
+                    _ => char::from(*byte),
+                })
+                .collect()),
+        }
+    }
+
+    /// Encode `text` in this encoding. UTF-16 starts with a byte order mark, since
+    /// Windows programs rely on it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `text` contains characters this encoding cannot
+    /// represent.
+    pub fn encode(self, text: &str) -> FSResult<Vec<u8>> {
+        let unrepresentable =
+            |character: char| FSError::InvalidEncoding(format!("{self} (character {character:?})"));
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Utf16Le | Self::Utf16Be => Ok(std::iter::once(0xFEFF)
+                .chain(text.encode_utf16())
+                .flat_map(|unit| {
+                    if self == Self::Utf16Le {
+                        unit.to_le_bytes()
+                    } else {
+                        unit.to_be_bytes()
+                    }
+                })
+                .collect()),
+            Self::Latin1 => text
+                .chars()
+                .map(|character| u8::try_from(character).map_err(|_| unrepresentable(character)))
+                .collect(),
+            Self::Windows1252 => text
+                .chars()
+                .map(|character| {
+                    if let Some(index) = WINDOWS_1252.iter().position(|other| *other == character) {
+                        return Ok(0x80 + u8::try_from(index).unwrap_or_default());
+                    }
+                    match u8::try_from(character) {
+                        Ok(byte) if !(0x80..=0x9F).contains(&byte) => Ok(byte),
+                        _ => Err(unrepresentable(character)),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl File {
+    /// The first `length` bytes of this file, or fewer if it is shorter.
+    pub(crate) fn sample(&self, length: u64) -> FSResult<Vec<u8>> {
+        use std::io::Read as _;
+
+        let mut sample = Vec::new();
+        backend::with(|backend| backend.open(&self.path))?
+            .take(length)
+            .read_to_end(&mut sample)?;
+        Ok(sample)
+    }
+
+    /// Guess the encoding of this file from its first megabyte, see
+    /// [`Encoding::detect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn detect_encoding(&self) -> FSResult<Encoding> {
+        Ok(Encoding::detect(&self.sample(DETECTION_SAMPLE)?))
+    }
+
+    /// The encoding whose byte order mark this file starts with, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn bom(&self) -> FSResult<Option<Encoding>> {
+        Ok(Encoding::from_bom(&self.sample(3)?).map(|(encoding, _)| encoding))
+    }
+
+    /// Remove the byte order mark this file starts with, which confuses many Unix
+    /// tools (e.g. a shebang is not recognized after it). Returns whether there was
+    /// one. The file is rewritten as a stream next to itself and renamed
+    /// afterwards.
+    ///
+    /// Removing the byte order mark of UTF-16 files is possible, but programs then
+    /// have to guess the byte order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or writing this file fails.
+    pub fn strip_bom(&self) -> FSResult<bool> {
+        use std::io::Read as _;
+
+        let Some((encoding, length)) = Encoding::from_bom(&self.sample(3)?) else {
+            return Ok(false);
+        };
+        log::trace!("Removing {encoding} byte order mark of {}", self);
+        let mut file = backend::with(|backend| backend.open(&self.path))?;
+        file.read_exact(&mut vec![0; length])?;
+        write_atomic_with(&self.path, None, |writer| -> FSResult<()> {
+            std::io::copy(&mut file.by_ref(), writer)?;
+            Ok(())
+        })?;
+        Ok(true)
+    }
+
+    /// The line ending most lines of this file's first megabyte end with, see
+    /// [`LineEnding::detect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn detect_line_ending(&self) -> FSResult<Option<LineEnding>> {
+        Ok(LineEnding::detect(&self.sample(DETECTION_SAMPLE)?))
+    }
+
+    /// Make every line of this file end with `ending`. A last line without a line
+    /// feed is left as it is, and so are carriage returns that are not followed by
+    /// a line feed. The file is rewritten as a stream next to itself and renamed
+    /// afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or writing this file fails.
+    pub fn normalize_line_endings(&self, ending: LineEnding) -> FSResult<()> {
+        use std::io::{
+            BufRead as _,
+            Write as _,
+        };
+
+        log::trace!("Normalizing line endings of {} to {ending:?}", self);
+        let mut reader =
+            std::io::BufReader::new(backend::with(|backend| backend.open(&self.path))?);
+        write_atomic_with(&self.path, None, |writer| -> FSResult<()> {
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line)? > 0 {
+                if let Some(content) = line
+                    .strip_suffix(b"\r\n")
+                    .or_else(|| line.strip_suffix(b"\n"))
+                {
+                    writer.write_all(content)?;
+                    writer.write_all(ending.as_bytes())?;
+                } else {
+                    writer.write_all(&line)?;
+                }
+                line.clear();
+            }
+            Ok(())
+        })
+    }
+
+    /// Convert this file from encoding `from` to encoding `to` in place. If the
+    /// conversion fails, the file is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not valid in `from`, if it contains
+    /// characters `to` cannot represent or if reading or writing it fails.
+    pub fn convert_encoding(&self, from: Encoding, to: Encoding) -> FSResult<()> {
+        use std::io::Write as _;
+
+        log::trace!("Converting {} from {from} to {to}", self);
+        let content =
+            to.encode(&from.decode(&backend::with(|backend| backend.read(&self.path))?)?)?;
+        write_atomic_with(&self.path, None, |writer| -> FSResult<()> {
+            Ok(writer.write_all(&content)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod encoding_test {
+    use super::*;
+    use crate::fs::{
+        generate_test_path,
+        Object as _,
+    };
+
+    #[test]
+    fn detect_and_convert() -> FSResult<()> {
+        assert_eq!(Encoding::detect("Grüße".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(&"Grüße".as_bytes()[..3]), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"Gr\xFC\xDFe"), Encoding::Latin1);
+        assert_eq!(Encoding::detect(b"5 \x80"), Encoding::Windows1252);
+        assert_eq!(Encoding::detect(b"a\0b\0c\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\xFE\xFF\0a"), Encoding::Utf16Be);
+
+        let file = File::new(generate_test_path());
+        std::fs::write(file.path(), b"\xFF\xFEG\0r\0\xFC\0\xDF\0e\0 \0\xAC\x20")?;
+        assert_eq!(file.detect_encoding()?, Encoding::Utf16Le);
+        file.convert_encoding(Encoding::Utf16Le, Encoding::Utf8)?;
+        assert_eq!(file.read()?, "Grüße €");
+
+        file.convert_encoding(Encoding::Utf8, Encoding::Windows1252)?;
+        assert_eq!(std::fs::read(file.path())?, b"Gr\xFC\xDFe \x80");
+        assert_eq!(
+            file.convert_encoding(Encoding::Windows1252, Encoding::Latin1),
+            Err(FSError::InvalidEncoding(String::from(
+                "ISO-8859-1 (character '€')"
+            )))
+        );
+        assert_eq!(std::fs::read(file.path())?, b"Gr\xFC\xDFe \x80");
+        Ok(())
+    }
+
+    #[test]
+    fn boms_and_line_endings() -> FSResult<()> {
+        assert_eq!(LineEnding::detect(b"a"), None);
+        assert_eq!(LineEnding::detect(b"a\r\nb\r\nc\n"), Some(LineEnding::CrLf));
+        assert_eq!(LineEnding::detect(b"\na\r\n"), Some(LineEnding::Lf));
+
+        let file = File::new(generate_test_path());
+        std::fs::write(file.path(), b"\xEF\xBB\xBF#!/bin/sh\r\necho\r\rx\n\r\nend")?;
+        assert_eq!(file.bom()?, Some(Encoding::Utf8));
+        assert_eq!(file.detect_line_ending()?, Some(LineEnding::CrLf));
+        assert!(file.strip_bom()?);
+        assert!(!file.strip_bom()?);
+        assert_eq!(file.bom()?, None);
+
+        file.normalize_line_endings(LineEnding::Lf)?;
+        assert_eq!(std::fs::read(file.path())?, b"#!/bin/sh\necho\r\rx\n\nend");
+        file.normalize_line_endings(LineEnding::CrLf)?;
+        assert_eq!(
+            std::fs::read(file.path())?,
+            b"#!/bin/sh\r\necho\r\rx\r\n\r\nend"
+        );
+        Ok(())
+    }
+}
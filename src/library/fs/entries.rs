@@ -0,0 +1,233 @@
+//! This module contains enumerating what is inside a directory
+//! ([`Directory::entries`] and its typed variants).
+
+use super::{
+    backend,
+    Directory,
+    FSResult,
+    File,
+    ObjectType,
+    SymbolicLink,
+};
+
+/// An object directly in a directory, see [`Directory::entries`].
+#[derive(Debug)]
+pub enum Entry {
+    /// A file
+    File(File),
+    /// A directory
+    Directory(Directory),
+    /// A symbolic link, whatever it links to
+    SymbolicLink(SymbolicLink),
+}
+
+impl Entry {
+    /// The path of the entry.
+    #[must_use]
+    pub const fn path(&self) -> &std::path::PathBuf {
+        match self {
+            Self::File(file) => &file.path,
+            Self::Directory(directory) => &directory.path,
+            Self::SymbolicLink(link) => &link.path,
+        }
+    }
+
+    /// What kind of object the entry is.
+    #[must_use]
+    pub const fn object_type(&self) -> ObjectType {
+        match self {
+            Self::File(_) => ObjectType::File,
+            Self::Directory(_) => ObjectType::Directory,
+            Self::SymbolicLink(_) => ObjectType::SymbolicLink,
+        }
+    }
+}
+
+/// The entries of a directory, see [`Directory::entries`]. `T` is [`Entry`] or,
+/// for the typed variants, [`File`], [`Directory`] or [`SymbolicLink`].
+#[derive(Debug)]
+pub struct Entries<T> {
+    /// The entries not returned yet
+    entries: std::vec::IntoIter<Entry>,
+    /// Picks the entries to return
+    select:  fn(Entry) -> Option<T>,
+}
+
+impl<T> Entries<T> {
+    /// Return the entries sorted by path instead of in the order the filesystem
+    /// lists them, which is arbitrary.
+    #[must_use]
+    pub fn sorted(self) -> Self {
+        let mut entries: Vec<_> = self.entries.collect();
+        entries.sort_by(|first, second| first.path().cmp(second.path()));
+        Self {
+            entries: entries.into_iter(),
+            select:  self.select,
+        }
+    }
+}
+
+impl<T> Iterator for Entries<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> { self.entries.by_ref().find_map(self.select) }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (0, Some(self.entries.len())) }
+}
+
+impl Directory {
+    /// Read the entries of this directory and keep those `select` picks.
+    fn read_entries<T>(&self, select: fn(Entry) -> Option<T>) -> FSResult<Entries<T>> {
+        log::trace!("Reading the entries of directory {}", self);
+        let paths = backend::with(|backend| backend.read_dir(&self.path))?;
+        let mut entries = Vec::with_capacity(paths.len());
+        for path in paths {
+            if backend::with(|backend| backend.read_link(&path))?.is_some() {
+                entries.push(Entry::SymbolicLink(SymbolicLink { path }));
+                continue;
+            }
+            match backend::with(|backend| backend.object_type(&path)) {
+                Some(ObjectType::File) => entries.push(Entry::File(File { path })),
+                Some(ObjectType::Directory) => entries.push(Entry::Directory(Self { path })),
+                _ => log::debug!("Skipping '{}' of unknown type", path.display()),
+            }
+        }
+        Ok(Entries {
+            entries: entries.into_iter(),
+            select,
+        })
+    }
+
+    /// The files, directories and symbolic links directly in this directory.
+    /// Symbolic links are not followed, so a link to a directory is an
+    /// [`Entry::SymbolicLink`]. Other objects (e.g. sockets) are skipped.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// File::new("/project/src/main.rs").create_on_fs_recursive().unwrap();
+    /// File::new("/project/Cargo.toml").create_on_fs().unwrap();
+    /// let names: Vec<_> = Directory::new("/project")
+    ///     .entries()
+    ///     .unwrap()
+    ///     .sorted()
+    ///     .map(|entry| entry.object_type().to_string())
+    ///     .collect();
+    /// assert_eq!(names, ["file", "directory"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this directory cannot be read.
+    pub fn entries(&self) -> FSResult<Entries<Entry>> { self.read_entries(Some) }
+
+    /// The files directly in this directory, see [`Directory::entries`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this directory cannot be read.
+    pub fn files(&self) -> FSResult<Entries<File>> {
+        self.read_entries(|entry| match entry {
+            Entry::File(file) => Some(file),
+            _ => None,
+        })
+    }
+
+    /// The directories directly in this directory, see [`Directory::entries`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this directory cannot be read.
+    pub fn subdirectories(&self) -> FSResult<Entries<Self>> {
+        self.read_entries(|entry| match entry {
+            Entry::Directory(directory) => Some(directory),
+            _ => None,
+        })
+    }
+
+    /// The symbolic links directly in this directory, see [`Directory::entries`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this directory cannot be read.
+    pub fn symlinks(&self) -> FSResult<Entries<SymbolicLink>> {
+        self.read_entries(|entry| match entry {
+            Entry::SymbolicLink(link) => Some(link),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod entries_test {
+    use super::{
+        super::{
+            generate_test_path,
+            FSError,
+            Object as _,
+        },
+        *,
+    };
+
+    #[cfg(unix)]
+    #[test]
+    fn typed_and_sorted() -> FSResult<()> {
+        let root = generate_test_path();
+        std::fs::create_dir_all(root.join("b"))?;
+        std::fs::create_dir_all(root.join("a"))?;
+        std::fs::write(root.join("c.txt"), "c")?;
+        std::os::unix::fs::symlink("b", root.join("link"))?;
+        let directory = Directory::new(&root);
+
+        let entries: Vec<_> = directory
+            .entries()?
+            .sorted()
+            .map(|entry| {
+                let item = (entry.path().clone(), entry.object_type());
+                // Dropping a `File` deletes it in tests.
+                std::mem::forget(entry);
+                item
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (root.join("a"), ObjectType::Directory),
+                (root.join("b"), ObjectType::Directory),
+                (root.join("c.txt"), ObjectType::File),
+                (root.join("link"), ObjectType::SymbolicLink),
+            ]
+        );
+        assert_eq!(
+            SymbolicLink::new(root.join("c.txt")).target(),
+            Err(FSError::TypeMismatch(ObjectType::File))
+        );
+        assert_eq!(
+            directory
+                .files()?
+                .map(|file| {
+                    let path = file.path().clone();
+                    std::mem::forget(file);
+                    path
+                })
+                .collect::<Vec<_>>(),
+            [root.join("c.txt")]
+        );
+
+        // Listing other kinds drops (and so deletes) the files in tests.
+        let subdirectories: Vec<_> = directory
+            .subdirectories()?
+            .sorted()
+            .map(|subdirectory| subdirectory.path)
+            .collect();
+        assert_eq!(subdirectories, [root.join("a"), root.join("b")]);
+        let links = directory
+            .symlinks()?
+            .map(|link| link.target())
+            .collect::<FSResult<Vec<_>>>()?;
+        assert_eq!(links, [std::path::PathBuf::from("b")]);
+        assert!(Directory::new(root.join("missing")).entries().is_err());
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! This module contains following a file as lines are appended to it, the library
+//! equivalent of `tail -F`.
+
+use super::{
+    backend,
+    FSError,
+    FSResult,
+    File,
+};
+
+/// How long to wait between checks for new lines by default.
+const DEFAULT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// What identifies a file independently of its path (device and inode), to notice
+/// that the path now refers to a different file.
+#[cfg(unix)]
+#[allow(
+    clippy::unnecessary_wraps,
+    reason = "there is no identity on other systems"
+)]
+pub fn file_identity(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt as _;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// What identifies a file independently of its path. Systems other than Unix offer
+/// nothing suitable, so there is no identity to compare.
+#[cfg(not(unix))]
+pub const fn file_identity(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> { None }
+
+/// The lines appended to a file, see [`File::follow`].
+///
+/// Iterating blocks until the next line is complete and never ends; use
+/// [`Iterator::take`], [`Iterator::take_while`] or [`Follow::poll`] to stop.
+///
+/// Like `tail -F`, following survives rotation and truncation. If the path refers
+/// to a different file (it was moved away and recreated), the rest of the old file
+/// is read and the new one is followed from its start. If the file shrinks (it was
+/// truncated), it is followed from its start again. If the file does not exist,
+/// following waits for it to be created. On systems other than Unix, a rotated file
+/// is only noticed once it shrinks.
+#[derive(Debug)]
+pub struct Follow {
+    /// The path of the followed file
+    path:          std::path::PathBuf,
+    /// The file currently read, if it exists
+    file:          Option<std::fs::File>,
+    /// The identity of the file currently read, if the system offers one
+    identity:      Option<(u64, u64)>,
+    /// How far the file currently read has been read
+    offset:        u64,
+    /// The start of a line whose line feed has not been appended yet
+    pending:       Vec<u8>,
+    /// Complete lines not returned yet
+    lines:         std::collections::VecDeque<String>,
+    /// How long to wait between checks for new lines
+    poll_interval: std::time::Duration,
+}
+
+impl Follow {
+    /// Follow from the start of the file instead of its end, i.e. return the lines
+    /// that are already in the file first.
+    #[must_use]
+    pub const fn from_start(mut self) -> Self {
+        self.offset = 0;
+        self
+    }
+
+    /// How long to wait between checks for new lines, 250 milliseconds by default.
+    #[must_use]
+    pub const fn poll_interval(mut self, interval: std::time::Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Read what was appended to `file` since the last read and queue the lines
+    /// that are complete.
+    fn read_from(&mut self, mut file: &std::fs::File) -> FSResult<()> {
+        use std::io::{
+            Read as _,
+            Seek as _,
+        };
+
+        file.seek(std::io::SeekFrom::Start(self.offset))?;
+        let mut content = Vec::new();
+        let read = file.read_to_end(&mut content)?;
+        self.offset += read as u64;
+        self.pending.extend_from_slice(&content);
+        while let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.lines
+                .push_back(String::from_utf8_lossy(&line[..end]).into_owned());
+        }
+        Ok(())
+    }
+
+    /// Check the file for new lines once, without waiting, and return all lines
+    /// that are complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if checking or reading the file fails.
+    pub fn poll(&mut self) -> FSResult<Vec<String>> {
+        let current = match backend::with(|backend| backend.metadata(&self.path)) {
+            Ok(metadata) => Some(metadata.device.zip(metadata.inode)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        // Without identities, a file replaced at the path cannot be told apart.
+        let replaced =
+            current.is_none_or(|identity| identity.is_some() && identity != self.identity);
+
+        if self.file.is_some() && replaced {
+            log::debug!(
+                "File '{}' was rotated, following the new file",
+                self.path.display()
+            );
+            if let Some(file) = self.file.take() {
+                self.read_from(&file)?;
+            }
+            self.pending.clear();
+            self.offset = 0;
+        }
+        if self.file.is_none() && current.is_some() {
+            match backend::with(|backend| backend.open_file(&self.path)) {
+                Ok(file) => {
+                    self.identity = file_identity(&file.metadata()?);
+                    self.file = Some(file);
+                },
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {},
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if let Some(file) = self.file.take() {
+            if file.metadata()?.len() < self.offset {
+                log::debug!(
+                    "File '{}' was truncated, following it from its start",
+                    self.path.display()
+                );
+                self.pending.clear();
+                self.offset = 0;
+            }
+            let result = self.read_from(&file);
+            self.file = Some(file);
+            result?;
+        }
+        Ok(self.lines.drain(..).collect())
+    }
+}
+
+impl Iterator for Follow {
+    type Item = FSResult<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Some(Ok(line));
+            }
+            match self.poll() {
+                Ok(lines) if lines.is_empty() => std::thread::sleep(self.poll_interval),
+                Ok(lines) => self.lines.extend(lines),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+impl File {
+    /// Follow this file like `tail -F`, starting at its current end (or at its
+    /// start with [`Follow::from_start`]), see [`Follow`]. The file does not have to
+    /// exist yet. Lines are returned without their line feed; bytes that are not
+    /// valid UTF-8 are replaced.
+    ///
+    /// Following needs the file on disk, so it is not supported while a
+    /// [`MemoryBackend`](super::MemoryBackend) is installed.
+    ///
+    /// ```no_run
+    /// # use rush::prelude::*;
+    /// for line in File::new("/var/log/app.log").follow().unwrap() {
+    ///     let line = line.unwrap();
+    ///     if line.contains("ready") {
+    ///         break;
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be opened.
+    pub fn follow(&self) -> FSResult<Follow> {
+        log::trace!("Following file {}", self);
+        let (file, identity, offset) = match backend::with(|backend| backend.open_file(&self.path))
+        {
+            Ok(file) => {
+                let metadata = file.metadata()?;
+                if metadata.is_dir() {
+                    return Err(FSError::TypeMismatch(super::ObjectType::Directory));
+                }
+                (Some(file), file_identity(&metadata), metadata.len())
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => (None, None, 0),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Follow {
+            path: self.path.clone(),
+            file,
+            identity,
+            offset,
+            pending: Vec::new(),
+            lines: std::collections::VecDeque::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        })
+    }
+}
+
+#[cfg(test)]
+mod follow_test {
+    use super::{
+        super::{
+            generate_test_path,
+            Object as _,
+        },
+        *,
+    };
+
+    #[test]
+    fn appends_truncation_and_rotation() -> FSResult<()> {
+        let file = File::new(generate_test_path());
+        let mut missing = file.follow()?;
+        assert!(missing.poll()?.is_empty());
+
+        file.write_new("old\n")?;
+        let mut follow = file
+            .follow()?
+            .poll_interval(std::time::Duration::from_millis(1));
+        assert_eq!(missing.poll()?, ["old"]);
+        assert!(follow.poll()?.is_empty());
+
+        file.append("one\ntw")?;
+        assert_eq!(follow.poll()?, ["one"]);
+        file.append("o\nthree\n")?;
+        assert_eq!(follow.next(), Some(Ok(String::from("two"))));
+        assert_eq!(follow.next(), Some(Ok(String::from("three"))));
+
+        file.overwrite("new\n")?;
+        assert_eq!(follow.poll()?, ["new"]);
+
+        let rotated = File::new(generate_test_path());
+        file.append("last\n")?;
+        std::fs::rename(file.path(), rotated.path())?;
+        file.write_new("first\n")?;
+        assert_eq!(follow.poll()?, ["last", "first"]);
+
+        assert_eq!(
+            file.follow()?.from_start().take(1).collect::<Vec<_>>(),
+            [Ok(String::from("first"))]
+        );
+        Ok(())
+    }
+    #[test]
+    fn needs_a_file_on_disk() {
+        let _memory = super::super::MemoryBackend::install();
+        let file = File::new("/app.log");
+        file.overwrite("line\n").unwrap();
+        assert!(file.follow().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! This module contains expanding glob patterns into the objects they match, like a
+//! shell does ([`glob`] and [`Directory::glob`]).
+
+use super::{
+    walk,
+    Directory,
+    Entry,
+    FSError,
+    FSResult,
+};
+
+/// The entries below `directory` (the current directory if empty) matching
+/// `pattern`, sorted by path.
+fn expand(directory: &std::path::Path, pattern: &str) -> FSResult<Vec<Entry>> {
+    log::trace!(
+        "Expanding glob pattern '{}' in '{}'",
+        pattern,
+        directory.display()
+    );
+    if pattern.is_empty() {
+        return Err(FSError::InvalidPattern(String::from(
+            "the pattern is empty",
+        )));
+    }
+    let Some(first_wildcard) = pattern.find(['*', '?']) else {
+        // Without wildcards, the pattern matches itself if it exists.
+        let path = directory.join(pattern);
+        let parent = path.parent().unwrap_or(&path).to_path_buf();
+        return Ok(walk(parent)
+            .max_depth(1)
+            .follow_symlinks(true)
+            .filter_entries(move |entry, _| entry == path)
+            .filter_map(Result::ok)
+            .collect());
+    };
+
+    // Everything up to the last `/` before the first wildcard is walked from.
+    let (base, rest) = match pattern[..first_wildcard].rfind('/') {
+        Some(0) => ("/", &pattern[1..]),
+        Some(separator) => (&pattern[..separator], &pattern[separator + 1..]),
+        None => ("", pattern),
+    };
+    let root = directory.join(base);
+    let components = rest.split('/').filter(|component| !component.is_empty());
+    let depth = components.clone().count();
+
+    let mut walk = walk(&root)
+        .follow_symlinks(true)
+        .matching(rest)
+        .skip_hidden(
+            !components
+                .clone()
+                .any(|component| component.starts_with('.')),
+        );
+    if !rest.contains("**") {
+        walk = walk.min_depth(depth).max_depth(depth);
+    }
+
+    let mut entries: Vec<_> = walk
+        .filter_map(|entry| {
+            entry
+                .map_err(|error| log::debug!("Skipping a directory while globbing: {}", error))
+                .ok()
+        })
+        .collect();
+    entries.sort_by(|first, second| first.path().cmp(second.path()));
+    Ok(entries)
+}
+
+/// Expand the glob `pattern` into the files, directories and symbolic links it
+/// matches, sorted by path, like a shell does.
+///
+/// Relative patterns are relative to the current directory and return relative
+/// paths.
+/// `*` matches any characters except `/`, `**` any characters (so `**/` matches any
+/// number of directories) and `?` a single character except `/`. Like in a shell,
+/// hidden entries are only matched if the pattern has a component starting with
+/// `.`, symbolic links are followed and directories that cannot be read are
+/// skipped. A pattern without wildcards matches itself if it exists.
+///
+/// ```
+/// # use rush::prelude::*;
+/// let _memory = fs::MemoryBackend::install();
+/// File::new("/logs/app/today.log").create_on_fs_recursive().unwrap();
+/// File::new("/logs/db/2024/old.log").create_on_fs_recursive().unwrap();
+/// File::new("/logs/db/notes.txt").create_on_fs().unwrap();
+/// let logs: Vec<_> = fs::glob("/logs/**/*.log")
+///     .unwrap()
+///     .iter()
+///     .map(|entry| entry.path().clone())
+///     .collect();
+/// assert_eq!(
+///     logs,
+///     ["/logs/app/today.log", "/logs/db/2024/old.log"].map(std::path::PathBuf::from)
+/// );
+/// ```
+///
+/// # Errors
+///
+/// Returns [`FSError::InvalidPattern`] if the pattern is empty. Directories that
+/// cannot be read are skipped instead of failing.
+pub fn glob(pattern: &str) -> FSResult<Vec<Entry>> { expand(std::path::Path::new(""), pattern) }
+
+impl Directory {
+    /// Expand the glob `pattern` relative to this directory, see [`glob`]. The
+    /// returned paths start with the path of this directory, whose own path is
+    /// taken literally.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::InvalidPattern`] if the pattern is empty.
+    pub fn glob(&self, pattern: &str) -> FSResult<Vec<Entry>> { expand(&self.path, pattern) }
+}
+
+#[cfg(test)]
+mod glob_test {
+    use super::{
+        super::{
+            backend,
+            generate_test_path,
+            MemoryBackend,
+            Object as _,
+            ObjectType,
+        },
+        *,
+    };
+
+    #[test]
+    fn expands() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let root = generate_test_path();
+        let directory = Directory::new(&root);
+        for file in [
+            "a.log",
+            "b.txt",
+            "logs/c.log",
+            "logs/2024/d.log",
+            ".hidden/e.log",
+        ] {
+            let path = root.join(file);
+            backend::with(|backend| backend.create_dir_all(path.parent().unwrap_or(&root)))?;
+            backend::with(|backend| backend.write(&path, b"", false))?;
+        }
+        // Dropping a returned `File` deletes it in tests, so they are kept.
+        let kept = std::cell::RefCell::new(Vec::new());
+        let expand = |pattern: &str| -> FSResult<Vec<(String, ObjectType)>> {
+            let entries = directory.glob(pattern)?;
+            let expanded = entries
+                .iter()
+                .map(|entry| {
+                    let path = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                    (path.to_string_lossy().into_owned(), entry.object_type())
+                })
+                .collect();
+            kept.borrow_mut().extend(entries);
+            Ok(expanded)
+        };
+        let paths = |pattern: &str| -> FSResult<Vec<String>> {
+            Ok(expand(pattern)?.into_iter().map(|(path, _)| path).collect())
+        };
+
+        assert_eq!(paths("*.log")?, ["a.log"]);
+        assert_eq!(paths("*")?, ["a.log", "b.txt", "logs"]);
+        assert_eq!(paths("logs/*.log")?, ["logs/c.log"]);
+        assert_eq!(
+            paths("**/*.log")?,
+            ["a.log", "logs/2024/d.log", "logs/c.log"]
+        );
+        assert_eq!(
+            paths("logs/**")?,
+            ["logs/2024", "logs/2024/d.log", "logs/c.log"]
+        );
+        assert_eq!(paths("*/*")?, ["logs/2024", "logs/c.log"]);
+        assert_eq!(paths(".*/?.log")?, [".hidden/e.log"]);
+        assert_eq!(
+            expand("logs")?,
+            [(String::from("logs"), ObjectType::Directory)]
+        );
+        assert_eq!(paths("b.txt")?, ["b.txt"]);
+        assert!(paths("missing/*")?.is_empty());
+        assert!(paths("missing")?.is_empty());
+        assert!(matches!(
+            directory.glob(""),
+            Err(FSError::InvalidPattern(_))
+        ));
+
+        let absolute = glob(&format!("{}/*.txt", root.display()))?;
+        assert_eq!(
+            absolute.iter().map(Entry::path).collect::<Vec<_>>(),
+            [&root.join("b.txt")]
+        );
+        drop((absolute, kept));
+        Ok(())
+    }
+}
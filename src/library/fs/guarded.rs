@@ -0,0 +1,205 @@
+//! This module enforces the [`Policy`](crate::policy::Policy) in effect on the
+//! operations of the filesystem backends.
+
+use super::{
+    backend::{
+        normalize,
+        FsBackend,
+    },
+    FSError,
+    Metadata,
+    ObjectType,
+};
+use crate::policy::{
+    check,
+    Violation,
+};
+
+/// Enforces the policy in effect on the operations of `backend`.
+struct Guarded<'a> {
+    /// The backend allowed operations are passed on to
+    backend: &'a dyn FsBackend,
+}
+
+/// The I/O error reporting `violation`, which converts to
+/// [`FSError::PolicyViolation`].
+fn violated(violation: Violation) -> std::io::Error {
+    std::io::Error::other(FSError::PolicyViolation(violation))
+}
+
+impl Guarded<'_> {
+    /// Check whether `paths` may be accessed.
+    fn allow(paths: &[&std::path::Path]) -> std::io::Result<()> {
+        for path in paths {
+            let path = normalize(path);
+            check(|policy| policy.check_path(&path)).map_err(violated)?;
+        }
+        Ok(())
+    }
+
+    /// Check whether `size` bytes may be written to `path`.
+    fn allow_size(path: &std::path::Path, size: u64) -> std::io::Result<()> {
+        let path = normalize(path);
+        check(|policy| policy.check_file_size(&path, size)).map_err(violated)
+    }
+}
+
+impl FsBackend for Guarded<'_> {
+    fn object_type(&self, path: &std::path::Path) -> Option<ObjectType> {
+        self.backend.object_type(path)
+    }
+
+    fn open(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+        Self::allow(&[path])?;
+        self.backend.open(path)
+    }
+
+    fn open_file(&self, path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        Self::allow(&[path])?;
+        self.backend.open_file(path)
+    }
+
+    fn read(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        Self::allow(&[path])?;
+        self.backend.read(path)
+    }
+
+    fn write(&self, path: &std::path::Path, content: &[u8], append: bool) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        let existing = if append {
+            self.backend.len(path).unwrap_or(0)
+        } else {
+            0
+        };
+        Self::allow_size(path, existing + content.len() as u64)?;
+        self.backend.write(path, content, append)
+    }
+
+    fn create_new(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.create_new(path, mode)
+    }
+
+    fn sync(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.sync(path)
+    }
+
+    fn create_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[from, to])?;
+        self.backend.rename(from, to)
+    }
+
+    fn copy(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<u64> {
+        Self::allow(&[from, to])?;
+        Self::allow_size(to, self.backend.len(from)?)?;
+        self.backend.copy(from, to)
+    }
+
+    fn len(&self, path: &std::path::Path) -> std::io::Result<u64> {
+        Self::allow(&[path])?;
+        self.backend.len(path)
+    }
+
+    fn metadata(&self, path: &std::path::Path) -> std::io::Result<Metadata> {
+        Self::allow(&[path])?;
+        self.backend.metadata(path)
+    }
+
+    fn read_dir(&self, path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+        Self::allow(&[path])?;
+        self.backend.read_dir(path)
+    }
+
+    fn read_link(&self, path: &std::path::Path) -> std::io::Result<Option<std::path::PathBuf>> {
+        Self::allow(&[path])?;
+        self.backend.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        Self::allow(&[path])?;
+        self.backend.canonicalize(path)
+    }
+
+    fn symlink(&self, target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[link])?;
+        self.backend.symlink(target, link)
+    }
+
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        Self::allow(&[original, link])?;
+        self.backend.hard_link(original, link)
+    }
+
+    fn copy_metadata(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+        permissions: bool,
+        timestamps: bool,
+    ) -> std::io::Result<()> {
+        Self::allow(&[from, to])?;
+        self.backend
+            .copy_metadata(from, to, permissions, timestamps)
+    }
+
+    fn mode(&self, path: &std::path::Path) -> std::io::Result<u32> {
+        Self::allow(&[path])?;
+        self.backend.mode(path)
+    }
+
+    fn set_mode(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.set_mode(path, mode)
+    }
+
+    fn owner(&self, path: &std::path::Path) -> std::io::Result<(u32, u32)> {
+        Self::allow(&[path])?;
+        self.backend.owner(path)
+    }
+
+    fn set_owner(
+        &self,
+        path: &std::path::Path,
+        user: Option<u32>,
+        group: Option<u32>,
+    ) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.set_owner(path, user, group)
+    }
+
+    fn set_times(
+        &self,
+        path: &std::path::Path,
+        accessed: Option<std::time::SystemTime>,
+        modified: Option<std::time::SystemTime>,
+    ) -> std::io::Result<()> {
+        Self::allow(&[path])?;
+        self.backend.set_times(path, accessed, modified)
+    }
+}
+
+/// Run `operation` with `backend`, enforcing the policy in effect.
+pub(super) fn wrap<T>(backend: &dyn FsBackend, operation: impl FnOnce(&dyn FsBackend) -> T) -> T {
+    operation(&Guarded { backend })
+}
@@ -0,0 +1,273 @@
+//! This module contains computing checksums of files ([`File::hash`]) and digests
+//! of whole directory trees ([`Directory::hash`]), e.g. for verifying downloads or
+//! detecting whether provisioned files drifted.
+//!
+//! ```
+//! # use rush::prelude::*;
+//! let _memory = fs::MemoryBackend::install();
+//! let file = File::new("/release.tar");
+//! file.overwrite("abc").unwrap();
+//! assert_eq!(
+//!     file.hash(fs::Algorithm::Sha256).unwrap(),
+//!     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+//! );
+//! ```
+
+use super::{
+    backend,
+    Directory,
+    FSError,
+    FSResult,
+    File,
+    Object,
+    ObjectType,
+};
+
+/// The hash functions checksums can be computed with.
+///
+/// SHA-1 and MD5 are broken and only suited for comparing against checksums that
+/// are published with them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// SHA-256, what `sha256sum` computes
+    Sha256,
+    /// SHA-1, what `sha1sum` computes
+    Sha1,
+    /// MD5, what `md5sum` computes
+    Md5,
+    /// BLAKE3, what `b3sum` computes
+    Blake3,
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "SHA-256",
+            Self::Sha1 => "SHA-1",
+            Self::Md5 => "MD5",
+            Self::Blake3 => "BLAKE3",
+        })
+    }
+}
+
+/// The state of a hash function while content is fed into it.
+enum Hasher {
+    /// The state of SHA-256
+    Sha256(sha2::Sha256),
+    /// The state of SHA-1
+    Sha1(sha1::Sha1),
+    /// The state of MD5
+    Md5(md5::Md5),
+    /// The state of BLAKE3
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl std::io::Write for Hasher {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        use sha2::Digest as _;
+        match self {
+            Self::Sha256(hasher) => hasher.update(buffer),
+            Self::Sha1(hasher) => hasher.update(buffer),
+            Self::Md5(hasher) => hasher.update(buffer),
+            Self::Blake3(hasher) => {
+                hasher.update(buffer);
+            },
+        }
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+impl Hasher {
+    /// Start hashing with `algorithm`.
+    fn new(algorithm: Algorithm) -> Self {
+        use sha2::Digest as _;
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            Algorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            Algorithm::Md5 => Self::Md5(md5::Md5::new()),
+            Algorithm::Blake3 => Self::Blake3(Box::default()),
+        }
+    }
+
+    /// The hex-encoded digest of the content fed in.
+    fn finish(self) -> String {
+        use sha2::Digest as _;
+        use std::fmt::Write as _;
+
+        let digest = match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Md5(hasher) => hasher.finalize().to_vec(),
+            Self::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+        digest
+            .iter()
+            .fold(String::with_capacity(digest.len() * 2), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            })
+    }
+}
+
+/// The hex-encoded digest of the content of the file at `path`, which is read as a
+/// stream.
+fn hash_file(path: &std::path::Path, algorithm: Algorithm) -> FSResult<String> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut content = backend::with(|backend| backend.open(path))?;
+    std::io::copy(&mut content, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+/// The hex-encoded digest of the directory at `path`: the digest of a listing of
+/// its entries, sorted by name, each with its type and the digest of its content
+/// (files and directories) or its target (symbolic links).
+fn hash_directory(path: &std::path::Path, algorithm: Algorithm) -> FSResult<String> {
+    use std::io::Write as _;
+
+    let mut entries = backend::with(|backend| backend.read_dir(path))?;
+    entries.sort();
+    let mut hasher = Hasher::new(algorithm);
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().as_encoded_bytes();
+        // Symbolic links are not followed, so that cycles do not matter.
+        let (kind, digest) =
+            if let Some(target) = backend::with(|backend| backend.read_link(&entry))? {
+                ("link", target.as_os_str().as_encoded_bytes().to_vec())
+            } else {
+                match backend::with(|backend| backend.object_type(&entry)) {
+                    Some(ObjectType::Directory) => {
+                        ("dir", hash_directory(&entry, algorithm)?.into_bytes())
+                    },
+                    Some(ObjectType::File) => ("file", hash_file(&entry, algorithm)?.into_bytes()),
+                    Some(object_type) => return Err(FSError::TypeMismatch(object_type)),
+                    None => return Err(FSError::NonExistent),
+                }
+            };
+        hasher.write_all(kind.as_bytes())?;
+        hasher.write_all(b" ")?;
+        hasher.write_all(name)?;
+        hasher.write_all(b"\0")?;
+        hasher.write_all(&digest)?;
+        hasher.write_all(b"\n")?;
+    }
+    Ok(hasher.finish())
+}
+
+impl File {
+    /// The hex-encoded checksum of the content of this file, as `sha256sum` and the
+    /// like print it. The file is read as a stream, so it may be larger than the
+    /// available memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if the file does not exist, or an error if it
+    /// cannot be read.
+    pub fn hash(&self, algorithm: Algorithm) -> FSResult<String> {
+        log::trace!("Computing the {} checksum of {}", algorithm, self);
+        if !self.exists()? {
+            return Err(FSError::NonExistent);
+        }
+        hash_file(&self.path, algorithm)
+    }
+}
+
+impl Directory {
+    /// The hex-encoded digest of this directory tree, which is the same for two
+    /// trees if and only if they contain the same names, types and content.
+    ///
+    /// Every directory is hashed as the listing of its entries, sorted by name, each
+    /// with its type and the digest of its content (which, for subdirectories, is
+    /// their own digest). Symbolic links are not followed, their target is hashed
+    /// instead. Permissions, owners and times are not part of the digest.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// Directory::new("/a").create_on_fs().unwrap();
+    /// Directory::new("/b").create_on_fs().unwrap();
+    /// File::new("/a/config.toml").overwrite("port = 80").unwrap();
+    /// File::new("/b/config.toml").overwrite("port = 80").unwrap();
+    /// let a = Directory::new("/a").hash(fs::Algorithm::Blake3).unwrap();
+    /// assert_eq!(a, Directory::new("/b").hash(fs::Algorithm::Blake3).unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if the directory does not exist, or an error
+    /// if an entry cannot be read.
+    pub fn hash(&self, algorithm: Algorithm) -> FSResult<String> {
+        log::trace!("Computing the {} digest of {}", algorithm, self);
+        if !self.exists()? {
+            return Err(FSError::NonExistent);
+        }
+        hash_directory(&self.path, algorithm)
+    }
+}
+
+#[cfg(test)]
+mod hash_test {
+    use super::*;
+    use crate::fs::MemoryBackend;
+
+    #[test]
+    fn files() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let file = File::new("/abc.txt");
+        file.overwrite("abc")?;
+        for (algorithm, expected) in [
+            (
+                Algorithm::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (Algorithm::Sha1, "a9993e364706816aba3e25717850c26c9cd0d89d"),
+            (Algorithm::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+            (
+                Algorithm::Blake3,
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+        ] {
+            assert_eq!(file.hash(algorithm)?, expected, "{algorithm}");
+        }
+        assert_eq!(
+            File::new("/missing.txt").hash(Algorithm::Sha256),
+            Err(FSError::NonExistent)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn directories() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let mut files = Vec::new();
+        for root in ["/a", "/b"] {
+            files.push(File::new(format!("{root}/etc/hosts")));
+            files.push(File::new(format!("{root}/etc/motd")));
+            Directory::new(format!("{root}/etc")).create_on_fs_recursive()?;
+            Directory::new(format!("{root}/var")).create_on_fs_recursive()?;
+        }
+        for (file, content) in files.iter().zip(["localhost", "welcome"].repeat(2)) {
+            file.overwrite(content)?;
+        }
+        let digest = |root: &str| Directory::new(root).hash(Algorithm::Sha256);
+        assert_eq!(digest("/a")?, digest("/b")?);
+
+        files[3].overwrite("goodbye")?;
+        assert_ne!(digest("/a")?, digest("/b")?);
+        files[3].overwrite("welcome")?;
+        assert_eq!(digest("/a")?, digest("/b")?);
+
+        let motd = files.pop().unwrap().move_to("/b/etc/issue")?;
+        assert_ne!(digest("/a")?, digest("/b")?);
+        files.push(motd.move_to("/b/etc/motd")?);
+        assert_eq!(digest("/a")?, digest("/b")?);
+
+        // An empty file and an empty directory with the same name differ.
+        Directory::new("/a/empty").create_on_fs()?;
+        let empty = File::new("/b/empty");
+        empty.overwrite("")?;
+        assert_ne!(digest("/a")?, digest("/b")?);
+        Ok(())
+    }
+}
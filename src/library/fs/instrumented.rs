@@ -0,0 +1,212 @@
+//! This module reports the operations of the filesystem backends to the callbacks
+//! subscribed with [`crate::instrument::subscribe`].
+
+use super::{
+    backend::FsBackend,
+    Metadata,
+    ObjectType,
+    Operation,
+};
+use crate::instrument::{
+    observe,
+    Subsystem,
+};
+
+/// Reports the operations of `backend`.
+struct Instrumented<'a> {
+    /// The backend operations are passed on to
+    backend: &'a dyn FsBackend,
+}
+
+impl Instrumented<'_> {
+    /// Perform `operation` on `path` (and `destination`) with `run`, reporting it.
+    fn observe<T>(
+        operation: Operation,
+        path: &std::path::Path,
+        destination: Option<&std::path::Path>,
+        run: impl FnOnce() -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let name = match operation {
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Create => "create",
+            Operation::Remove => "remove",
+            Operation::Rename => "rename",
+            Operation::Copy => "copy",
+            Operation::List => "list",
+        };
+        let destination = destination.map(std::path::Path::display);
+        observe(
+            Subsystem::FileSystem,
+            name,
+            &path.display(),
+            destination
+                .as_ref()
+                .map(|display| display as &dyn std::fmt::Display),
+            run,
+        )
+    }
+}
+
+impl FsBackend for Instrumented<'_> {
+    fn object_type(&self, path: &std::path::Path) -> Option<ObjectType> {
+        observe(
+            Subsystem::FileSystem,
+            "exists",
+            &path.display(),
+            None,
+            || Ok::<_, std::convert::Infallible>(self.backend.object_type(path)),
+        )
+        .unwrap_or_else(|never| match never {})
+    }
+
+    fn open(&self, path: &std::path::Path) -> std::io::Result<Box<dyn std::io::Read>> {
+        Self::observe(Operation::Read, path, None, || self.backend.open(path))
+    }
+
+    fn open_file(&self, path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        Self::observe(Operation::Read, path, None, || self.backend.open_file(path))
+    }
+
+    fn read(&self, path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        Self::observe(Operation::Read, path, None, || self.backend.read(path))
+    }
+
+    fn write(&self, path: &std::path::Path, content: &[u8], append: bool) -> std::io::Result<()> {
+        Self::observe(Operation::Write, path, None, || {
+            self.backend.write(path, content, append)
+        })
+    }
+
+    fn create_new(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        Self::observe(Operation::Write, path, None, || {
+            self.backend.create_new(path, mode)
+        })
+    }
+
+    fn sync(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Write, path, None, || self.backend.sync(path))
+    }
+
+    fn create_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Create, path, None, || {
+            self.backend.create_dir(path)
+        })
+    }
+
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Create, path, None, || {
+            self.backend.create_dir_all(path)
+        })
+    }
+
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Remove, path, None, || {
+            self.backend.remove_file(path)
+        })
+    }
+
+    fn remove_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Remove, path, None, || {
+            self.backend.remove_dir_all(path)
+        })
+    }
+
+    fn rename(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Rename, from, Some(to), || {
+            self.backend.rename(from, to)
+        })
+    }
+
+    fn copy(&self, from: &std::path::Path, to: &std::path::Path) -> std::io::Result<u64> {
+        Self::observe(Operation::Copy, from, Some(to), || {
+            self.backend.copy(from, to)
+        })
+    }
+
+    fn len(&self, path: &std::path::Path) -> std::io::Result<u64> {
+        Self::observe(Operation::Read, path, None, || self.backend.len(path))
+    }
+
+    fn metadata(&self, path: &std::path::Path) -> std::io::Result<Metadata> {
+        Self::observe(Operation::Read, path, None, || self.backend.metadata(path))
+    }
+
+    fn read_dir(&self, path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+        Self::observe(Operation::List, path, None, || self.backend.read_dir(path))
+    }
+
+    fn read_link(&self, path: &std::path::Path) -> std::io::Result<Option<std::path::PathBuf>> {
+        Self::observe(Operation::Read, path, None, || self.backend.read_link(path))
+    }
+
+    fn canonicalize(&self, path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        Self::observe(Operation::Read, path, None, || self.backend.canonicalize(path))
+    }
+
+    fn symlink(&self, target: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Create, link, Some(target), || {
+            self.backend.symlink(target, link)
+        })
+    }
+
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        Self::observe(Operation::Create, link, Some(original), || {
+            self.backend.hard_link(original, link)
+        })
+    }
+
+    fn copy_metadata(
+        &self,
+        from: &std::path::Path,
+        to: &std::path::Path,
+        permissions: bool,
+        timestamps: bool,
+    ) -> std::io::Result<()> {
+        Self::observe(Operation::Write, to, Some(from), || {
+            self.backend
+                .copy_metadata(from, to, permissions, timestamps)
+        })
+    }
+
+    fn mode(&self, path: &std::path::Path) -> std::io::Result<u32> {
+        Self::observe(Operation::Read, path, None, || self.backend.mode(path))
+    }
+
+    fn set_mode(&self, path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+        Self::observe(Operation::Write, path, None, || {
+            self.backend.set_mode(path, mode)
+        })
+    }
+
+    fn owner(&self, path: &std::path::Path) -> std::io::Result<(u32, u32)> {
+        Self::observe(Operation::Read, path, None, || self.backend.owner(path))
+    }
+
+    fn set_owner(
+        &self,
+        path: &std::path::Path,
+        user: Option<u32>,
+        group: Option<u32>,
+    ) -> std::io::Result<()> {
+        Self::observe(Operation::Write, path, None, || {
+            self.backend.set_owner(path, user, group)
+        })
+    }
+
+    fn set_times(
+        &self,
+        path: &std::path::Path,
+        accessed: Option<std::time::SystemTime>,
+        modified: Option<std::time::SystemTime>,
+    ) -> std::io::Result<()> {
+        Self::observe(Operation::Write, path, None, || {
+            self.backend.set_times(path, accessed, modified)
+        })
+    }
+}
+
+/// Run `operation` with `backend`, reporting its operations.
+pub(super) fn wrap<T>(backend: &dyn FsBackend, operation: impl FnOnce(&dyn FsBackend) -> T) -> T {
+    operation(&Instrumented { backend })
+}
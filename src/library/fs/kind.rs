@@ -0,0 +1,217 @@
+//! This module contains functionality for detecting what a file actually contains
+//! by its leading bytes ("magic numbers"), like `file` does, instead of trusting
+//! its extension.
+
+use super::{
+    Encoding,
+    FSResult,
+    File,
+};
+
+/// How many bytes are inspected to detect the kind of a file. Tar archives need
+/// the first 262.
+const SNIFF_SAMPLE: u64 = 8 * 1024;
+
+/// What a file contains, see [`File::detect_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    /// An empty file
+    Empty,
+    /// An ELF executable, shared library or object file (Linux, BSD)
+    Elf,
+    /// A PE executable or DLL (Windows)
+    Pe,
+    /// A Mach-O executable or library (macOS), including universal binaries
+    MachO,
+    /// A WebAssembly module
+    Wasm,
+    /// A tar archive
+    Tar,
+    /// A gzip-compressed file
+    Gzip,
+    /// A bzip2-compressed file
+    Bzip2,
+    /// An xz-compressed file
+    Xz,
+    /// A Zstandard-compressed file
+    Zstd,
+    /// A zip archive, which includes JAR, DOCX and similar files
+    Zip,
+    /// A 7-Zip archive
+    SevenZip,
+    /// A PNG image
+    Png,
+    /// A JPEG image
+    Jpeg,
+    /// A GIF image
+    Gif,
+    /// A WebP image
+    Webp,
+    /// A PDF document
+    Pdf,
+    /// A database of [SQLite](https://sqlite.org)
+    Sqlite,
+    /// A script starting with a shebang (`#!`)
+    Script,
+    /// Text in the given encoding
+    Text(Encoding),
+    /// Binary data of an unknown kind
+    Binary,
+}
+
+impl FileKind {
+    /// Whether the content is (compressed) archive data, which the `tar`, `unzip`
+    /// or decompression tools can handle.
+    #[must_use]
+    pub const fn is_archive(self) -> bool {
+        matches!(
+            self,
+            Self::Tar
+                | Self::Gzip
+                | Self::Bzip2
+                | Self::Xz
+                | Self::Zstd
+                | Self::Zip
+                | Self::SevenZip
+        )
+    }
+
+    /// Whether the content is a program the operating system can load.
+    #[must_use]
+    pub const fn is_executable(self) -> bool {
+        matches!(self, Self::Elf | Self::Pe | Self::MachO | Self::Script)
+    }
+
+    /// Whether the content is text, including scripts.
+    #[must_use]
+    pub const fn is_text(self) -> bool { matches!(self, Self::Script | Self::Text(_)) }
+
+    /// Detect the kind of content from its leading `bytes`. At least the first 262
+    /// bytes are needed to recognize tar archives, and text is recognized more
+    /// reliably with a few kilobytes.
+    #[must_use]
+    pub fn detect(bytes: &[u8]) -> Self {
+        /// Signatures at the start of the content.
+        const MAGIC: [(&[u8], FileKind); 17] = [
+            (b"\x7fELF", FileKind::Elf),
+            (b"\xfe\xed\xfa\xce", FileKind::MachO),
+            (b"\xfe\xed\xfa\xcf", FileKind::MachO),
+            (b"\xce\xfa\xed\xfe", FileKind::MachO),
+            (b"\xcf\xfa\xed\xfe", FileKind::MachO),
+            (b"\xca\xfe\xba\xbe", FileKind::MachO),
+            (b"\0asm", FileKind::Wasm),
+            (b"\x1f\x8b", FileKind::Gzip),
+            (b"BZh", FileKind::Bzip2),
+            (b"\xfd7zXZ\0", FileKind::Xz),
+            (b"\x28\xb5\x2f\xfd", FileKind::Zstd),
+            (b"7z\xbc\xaf\x27\x1c", FileKind::SevenZip),
+            (b"\x89PNG\r\n\x1a\n", FileKind::Png),
+            (b"\xff\xd8\xff", FileKind::Jpeg),
+            (b"%PDF-", FileKind::Pdf),
+            (b"SQLite format 3\0", FileKind::Sqlite),
+            (b"#!", FileKind::Script),
+        ];
+
+        if bytes.is_empty() {
+            return Self::Empty;
+        }
+        if let Some((_, kind)) = MAGIC.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+            return *kind;
+        }
+        // Empty zip archives start with the end of central directory record.
+        if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            return Self::Zip;
+        }
+        if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            return Self::Gif;
+        }
+        if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+            return Self::Webp;
+        }
+        if bytes.get(257..262) == Some(b"ustar") {
+            return Self::Tar;
+        }
+        if bytes.starts_with(b"MZ") {
+            return Self::Pe;
+        }
+
+        let encoding = Encoding::detect(bytes);
+        let characters: Vec<u32> = match encoding {
+            // Binary data with many zero bytes looks like UTF-16, so it is decoded
+            // and checked like other text. The sample may cut off a character.
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                match encoding.decode(&bytes[..bytes.len() - bytes.len() % 2]) {
+                    Ok(text) => text.chars().map(u32::from).collect(),
+                    Err(_) => return Self::Binary,
+                }
+            },
+            _ => bytes.iter().map(|byte| u32::from(*byte)).collect(),
+        };
+        // Text contains no NUL characters and few control characters besides
+        // whitespace and the escape character of terminal colors.
+        let control = characters
+            .iter()
+            .filter(|character| {
+                **character < 0x20 && ![0x09, 0x0A, 0x0C, 0x0D, 0x1B].contains(*character)
+            })
+            .fold(0_usize, |count, _| count + 1);
+        if characters.contains(&0) || control * 10 > characters.len() {
+            Self::Binary
+        } else {
+            Self::Text(encoding)
+        }
+    }
+}
+
+impl File {
+    /// Detect what this file contains from its first kilobytes, see
+    /// [`FileKind::detect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn detect_kind(&self) -> FSResult<FileKind> {
+        Ok(FileKind::detect(&self.sample(SNIFF_SAMPLE)?))
+    }
+}
+
+#[cfg(test)]
+mod kind_test {
+    use super::*;
+    use crate::fs::{
+        generate_test_path,
+        Object as _,
+    };
+
+    #[test]
+    fn detect() -> FSResult<()> {
+        assert_eq!(FileKind::detect(b""), FileKind::Empty);
+        assert_eq!(FileKind::detect(b"\x7fELF\x02\x01\x01"), FileKind::Elf);
+        assert_eq!(FileKind::detect(b"MZ\x90\0"), FileKind::Pe);
+        assert_eq!(FileKind::detect(b"\x1f\x8b\x08\0"), FileKind::Gzip);
+        assert_eq!(FileKind::detect(b"PK\x03\x04\x14\0"), FileKind::Zip);
+        assert_eq!(FileKind::detect(b"RIFF\0\0\0\0WEBPVP8 "), FileKind::Webp);
+        assert_eq!(FileKind::detect(b"%PDF-1.7\n"), FileKind::Pdf);
+        assert_eq!(FileKind::detect(b"#!/bin/sh\n"), FileKind::Script);
+        assert_eq!(FileKind::detect(b"\0\x01\x02\x03"), FileKind::Binary);
+        assert_eq!(
+            FileKind::detect(b"\x1b[1mbold\x1b[0m\n"),
+            FileKind::Text(Encoding::Utf8)
+        );
+        assert_eq!(
+            FileKind::detect(b"\xff\xfeh\0i\0"),
+            FileKind::Text(Encoding::Utf16Le)
+        );
+
+        let mut tar = vec![0; 512];
+        tar[..9].copy_from_slice(b"README.md");
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(FileKind::detect(&tar), FileKind::Tar);
+        assert!(FileKind::Tar.is_archive() && !FileKind::Tar.is_text());
+
+        let file = File::new(generate_test_path());
+        std::fs::write(file.path(), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+        assert_eq!(file.detect_kind()?, FileKind::Png);
+        Ok(())
+    }
+}
@@ -0,0 +1,585 @@
+//! This module contains line-oriented operations on text files, the library
+//! equivalents of `sort`, `uniq -c`, `wc -l`, `shuf` and `cut`.
+//!
+//! Files are read as a stream. Sorting and shuffling keep at most a configurable
+//! amount of lines in memory; larger files are split into sorted (or shuffled) runs
+//! that are written to temporary files next to the target and merged afterwards.
+//! Lines are compared byte-wise, like `sort` does with `LC_ALL=C`.
+
+use super::{
+    backend,
+    write_atomic_with,
+    FSError,
+    FSResult,
+    File,
+    Object as _,
+    TempFile,
+};
+
+/// How many bytes of lines are kept in memory by default before a run is written.
+const DEFAULT_MEMORY: usize = 64 * 1024 * 1024;
+/// The characters a number used as sort key may consist of.
+const NUMBER_CHARACTERS: &str = "+-.0123456789eE";
+
+/// The counts `wc` reports for a file, see [`File::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TextStats {
+    /// The number of lines, including a last line without a line feed
+    pub lines:           u64,
+    /// The number of words, i.e. runs of characters that are not whitespace
+    pub words:           u64,
+    /// The number of bytes
+    pub bytes:           u64,
+    /// The number of characters of the longest line
+    pub max_line_length: u64,
+}
+
+/// Describes how [`File::sort_lines`] orders lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SortOptions {
+    /// Compare the keys as numbers instead of as text
+    numeric: bool,
+    /// Sort in descending order
+    reverse: bool,
+    /// Keep only the first of several lines with equal keys
+    unique:  bool,
+    /// Sort by this field (starting at 1) instead of the whole line, with the
+    /// delimiter separating fields (runs of whitespace if [`None`])
+    field:   Option<(usize, Option<u8>)>,
+    /// How many bytes of lines are kept in memory
+    memory:  usize,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self { Self::new() }
+}
+
+impl SortOptions {
+    /// Sort whole lines as text in ascending order.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            numeric: false,
+            reverse: false,
+            unique:  false,
+            field:   None,
+            memory:  DEFAULT_MEMORY,
+        }
+    }
+
+    /// Whether to compare keys as numbers, like `sort -n`. Keys that do not start
+    /// with a number count as 0.
+    #[must_use]
+    pub const fn numeric(mut self, enabled: bool) -> Self {
+        self.numeric = enabled;
+        self
+    }
+
+    /// Whether to sort in descending order, like `sort -r`.
+    #[must_use]
+    pub const fn reverse(mut self, enabled: bool) -> Self {
+        self.reverse = enabled;
+        self
+    }
+
+    /// Whether to keep only the first of several lines with equal keys, like
+    /// `sort -u`.
+    #[must_use]
+    pub const fn unique(mut self, enabled: bool) -> Self {
+        self.unique = enabled;
+        self
+    }
+
+    /// Sort by field `index` (starting at 1) instead of the whole line, like
+    /// `sort -k <index>,<index> -t <delimiter>`. Without a delimiter, fields are
+    /// separated by runs of whitespace.
+    #[must_use]
+    pub const fn field(mut self, index: usize, delimiter: Option<u8>) -> Self {
+        self.field = Some((index, delimiter));
+        self
+    }
+
+    /// Keep at most `bytes` of lines in memory, like `sort -S`.
+    #[must_use]
+    pub const fn memory(mut self, bytes: usize) -> Self {
+        self.memory = bytes;
+        self
+    }
+
+    /// The part of `line` lines are compared by.
+    fn key<'line>(&self, line: &'line [u8]) -> &'line [u8] {
+        self.field.map_or(line, |(index, delimiter)| {
+            field(line, index, delimiter).unwrap_or_default()
+        })
+    }
+
+    /// Compare two lines. Lines with equal keys are considered equal, so that
+    /// sorting is stable.
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        let (a, b) = (self.key(a), self.key(b));
+        let ordering = if self.numeric {
+            number(a).total_cmp(&number(b))
+        } else {
+            a.cmp(b)
+        };
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Field `index` (starting at 1) of `line`. Without a delimiter, fields are
+/// separated by runs of whitespace.
+fn field(line: &[u8], index: usize, delimiter: Option<u8>) -> Option<&[u8]> {
+    let index = index.checked_sub(1)?;
+    delimiter.map_or_else(
+        || {
+            line.split(u8::is_ascii_whitespace)
+                .filter(|field| !field.is_empty())
+                .nth(index)
+        },
+        |delimiter| line.split(|byte| *byte == delimiter).nth(index),
+    )
+}
+
+/// The number `key` starts with, or 0. Only the longest prefix that is a valid
+/// number counts, so `3eggs` is 3, `10-20` is 10 and `1.2.3` is 1.2.
+fn number(key: &[u8]) -> f64 {
+    let key = String::from_utf8_lossy(key);
+    let key = key.trim_start();
+    let end = key
+        .find(|character| !NUMBER_CHARACTERS.contains(character))
+        .unwrap_or(key.len());
+    // The number characters are ASCII, so every index up to `end` is a boundary.
+    (1..=end)
+        .rev()
+        .find_map(|end| key[..end].parse().ok())
+        .unwrap_or_default()
+}
+
+/// A source of lines, without their line feeds.
+type Lines = Box<dyn Iterator<Item = FSResult<Vec<u8>>>>;
+
+/// Read the lines of the file at `path`.
+fn read_lines(path: &std::path::Path) -> FSResult<Lines> {
+    use std::io::BufRead as _;
+
+    let reader = std::io::BufReader::new(backend::with(|backend| backend.open(path))?);
+    Ok(Box::new(
+        reader.split(b'\n').map(|line| line.map_err(FSError::from)),
+    ))
+}
+
+/// Write `line` and a line feed.
+fn write_line(writer: &mut impl std::io::Write, line: &[u8]) -> FSResult<()> {
+    writer.write_all(line)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// The runs a file was split into. The last run is kept in memory, the others are
+/// temporary files, which are removed when this value is dropped.
+struct Runs {
+    /// The temporary files holding all but the last run, with their line counts
+    files: Vec<(TempFile, usize)>,
+    /// The last run
+    last:  Vec<Vec<u8>>,
+}
+
+impl Runs {
+    /// The lines of every run and how many there are.
+    fn into_sources(mut self) -> FSResult<(Vec<Lines>, Vec<usize>, Self)> {
+        let mut sources = Vec::with_capacity(self.files.len() + 1);
+        let mut counts = Vec::with_capacity(self.files.len() + 1);
+        for (file, count) in &self.files {
+            sources.push(read_lines(file.path())?);
+            counts.push(*count);
+        }
+        let last = std::mem::take(&mut self.last);
+        counts.push(last.len());
+        sources.push(Box::new(last.into_iter().map(Ok)));
+        // The files are removed once the caller is done with the sources.
+        Ok((sources, counts, self))
+    }
+}
+
+impl std::fmt::Debug for Runs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runs")
+            .field("files", &self.files)
+            .finish_non_exhaustive()
+    }
+}
+
+impl File {
+    /// Split this file into runs of at most `memory` bytes of lines, which are
+    /// passed to `prepare` (e.g. to sort them) before they are written next to
+    /// `target`.
+    fn split_into_runs(
+        &self,
+        target: &std::path::Path,
+        memory: usize,
+        prepare: impl Fn(&mut Vec<Vec<u8>>) -> FSResult<()>,
+    ) -> FSResult<Runs> {
+        let mut runs = Runs {
+            files: Vec::new(),
+            last:  Vec::new(),
+        };
+        let directory = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => std::path::Path::new("."),
+        };
+        let mut size = 0;
+        for line in read_lines(&self.path)? {
+            let line = line?;
+            size += line.len() + std::mem::size_of::<Vec<u8>>();
+            runs.last.push(line);
+            if size < memory {
+                continue;
+            }
+
+            prepare(&mut runs.last)?;
+            let file = TempFile::create_in(directory)?;
+            let mut writer = file.open_writer(false)?;
+            runs.files.push((file, runs.last.len()));
+            for line in runs.last.drain(..) {
+                write_line(&mut writer, &line)?;
+            }
+            writer.finish()?;
+            size = 0;
+        }
+        prepare(&mut runs.last)?;
+        log::trace!("Split {} into {} runs", self, runs.files.len() + 1);
+        Ok(runs)
+    }
+
+    /// Sort the lines of this file according to `options` and write them to
+    /// `target`, which may be this file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file or writing `target` (or the temporary
+    /// runs next to it) fails.
+    pub fn sort_lines(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        options: &SortOptions,
+    ) -> FSResult<Self> {
+        log::trace!("Sorting lines of {}", self);
+        let target = target.as_ref();
+        let runs = self.split_into_runs(target, options.memory, |run| {
+            run.sort_by(|a, b| options.compare(a, b));
+            Ok(())
+        })?;
+        let (mut sources, _, _runs) = runs.into_sources()?;
+
+        write_atomic_with(target, None, |writer| -> FSResult<()> {
+            let mut heads = sources
+                .iter_mut()
+                .map(|source| source.next().transpose())
+                .collect::<FSResult<Vec<_>>>()?;
+            let mut previous: Option<Vec<u8>> = None;
+            loop {
+                // Earlier runs hold earlier lines, so picking the first of equal
+                // lines keeps the sort stable.
+                let Some(index) = heads
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, head)| head.as_ref().map(|head| (index, head)))
+                    .reduce(|smallest, candidate| {
+                        if options.compare(candidate.1, smallest.1).is_lt() {
+                            candidate
+                        } else {
+                            smallest
+                        }
+                    })
+                    .map(|(index, _)| index)
+                else {
+                    return Ok(());
+                };
+
+                let line = std::mem::replace(&mut heads[index], sources[index].next().transpose()?)
+                    .unwrap_or_default();
+                if options.unique
+                    && previous
+                        .as_ref()
+                        .is_some_and(|previous| options.compare(previous, &line).is_eq())
+                {
+                    continue;
+                }
+                write_line(writer, &line)?;
+                previous = Some(line);
+            }
+        })?;
+        Ok(Self::new(target))
+    }
+
+    /// Count how often every distinct line occurs, like `sort | uniq -c | sort -rn`.
+    /// The most frequent lines come first; lines that occur equally often are
+    /// sorted. Only the distinct lines are kept in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn unique_lines(&self) -> FSResult<Vec<(String, u64)>> {
+        let mut counts = std::collections::HashMap::<Vec<u8>, u64>::new();
+        for line in read_lines(&self.path)? {
+            *counts.entry(line?).or_default() += 1;
+        }
+
+        let mut counts: Vec<_> = counts
+            .into_iter()
+            .map(|(line, count)| (String::from_utf8_lossy(&line).into_owned(), count))
+            .collect();
+        counts.sort_by(|(line_a, count_a), (line_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| line_a.cmp(line_b))
+        });
+        Ok(counts)
+    }
+
+    /// Count the lines of this file. Unlike `wc -l`, a last line without a line
+    /// feed is counted as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn count_lines(&self) -> FSResult<u64> {
+        use std::io::Read as _;
+
+        let mut file = backend::with(|backend| backend.open(&self.path))?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut count = 0;
+        let mut last = b'\n';
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            count = buffer[..read]
+                .iter()
+                .fold(count, |count, byte| count + u64::from(*byte == b'\n'));
+            last = buffer[read - 1];
+        }
+        Ok(count + u64::from(last != b'\n'))
+    }
+
+    /// Count the lines, words and bytes of this file and the length of its longest
+    /// line, like `wc -l -w -c -L`. The file is expected to be UTF-8; lengths are
+    /// measured in characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn stats(&self) -> FSResult<TextStats> {
+        use std::io::Read as _;
+
+        let mut file = backend::with(|backend| backend.open(&self.path))?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut stats = TextStats::default();
+        let mut in_word = false;
+        let mut line_length = 0;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            stats.bytes += read as u64;
+            for byte in &buffer[..read] {
+                if *byte == b'\n' {
+                    stats.lines += 1;
+                    stats.max_line_length = stats.max_line_length.max(line_length);
+                    line_length = 0;
+                } else if *byte & 0xC0 != 0x80 {
+                    // Continuation bytes of UTF-8 characters are not counted.
+                    line_length += 1;
+                }
+                let is_space = byte.is_ascii_whitespace();
+                stats.words += u64::from(in_word && is_space);
+                in_word = !is_space;
+            }
+        }
+
+        stats.words += u64::from(in_word);
+        if line_length > 0 {
+            stats.lines += 1;
+            stats.max_line_length = stats.max_line_length.max(line_length);
+        }
+        Ok(stats)
+    }
+
+    /// Shuffle the lines of this file randomly, like `shuf`, and write them to
+    /// `target`, which may be this file. Every order is equally likely.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file, writing `target` (or the temporary
+    /// runs next to it) or generating random numbers fails.
+    pub fn shuffle_lines(&self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        use crate::crypto::random::below;
+
+        let random = |bound| below(bound).map_err(|error| FSError::Unknown(error.to_string()));
+
+        log::trace!("Shuffling lines of {}", self);
+        let target = target.as_ref();
+        let runs = self.split_into_runs(target, DEFAULT_MEMORY, |run| {
+            for index in (1..run.len()).rev() {
+                run.swap(index, random(index + 1)?);
+            }
+            Ok(())
+        })?;
+        let (mut sources, mut counts, _runs) = runs.into_sources()?;
+
+        write_atomic_with(target, None, |writer| -> FSResult<()> {
+            // Taking the next line from a run with a probability proportional to the
+            // lines it has left interleaves the shuffled runs uniformly.
+            let mut remaining: usize = counts.iter().sum();
+            while remaining > 0 {
+                let mut pick = random(remaining)?;
+                let index = counts
+                    .iter()
+                    .position(|count| {
+                        if pick < *count {
+                            true
+                        } else {
+                            pick -= count;
+                            false
+                        }
+                    })
+                    .unwrap_or_default();
+                let line = sources[index].next().transpose()?.unwrap_or_default();
+                write_line(writer, &line)?;
+                counts[index] -= 1;
+                remaining -= 1;
+            }
+            Ok(())
+        })?;
+        Ok(Self::new(target))
+    }
+
+    /// Extract the fields `columns` (starting at 1) of every line and write them to
+    /// `target`, like `cut -f` (with a delimiter) or `awk '{ print $1, $3 }'`
+    /// (without one, fields being separated by runs of whitespace). The extracted
+    /// fields are joined by the delimiter, or by a space. Missing fields are empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file or writing `target` fails.
+    pub fn extract_columns(
+        &self,
+        target: impl AsRef<std::path::Path>,
+        columns: &[usize],
+        delimiter: Option<u8>,
+    ) -> FSResult<Self> {
+        log::trace!("Extracting columns {columns:?} of {}", self);
+        let target = target.as_ref();
+        let lines = read_lines(&self.path)?;
+        write_atomic_with(target, None, |writer| -> FSResult<()> {
+            let separator = [delimiter.unwrap_or(b' ')];
+            for line in lines {
+                let line = line?;
+                let fields: Vec<_> = columns
+                    .iter()
+                    .map(|column| field(&line, *column, delimiter).unwrap_or_default())
+                    .collect();
+                write_line(writer, &fields.join(&separator[..]))?;
+            }
+            Ok(())
+        })?;
+        Ok(Self::new(target))
+    }
+}
+
+#[cfg(test)]
+mod lines_test {
+    use super::*;
+    use crate::fs::generate_test_path;
+
+    #[test]
+    fn numbers() {
+        for (key, expected) in [
+            ("42", 42.0),
+            ("  -1.5 apples", -1.5),
+            ("1e3", 1000.0),
+            ("3eggs", 3.0),
+            ("10-20", 10.0),
+            ("1.2.3", 1.2),
+            ("+7", 7.0),
+            ("-", 0.0),
+            ("e5", 0.0),
+            ("apples", 0.0),
+            ("", 0.0),
+        ] {
+            assert!(
+                (number(key.as_bytes()) - expected).abs() < f64::EPSILON,
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn sort_and_unique() -> FSResult<()> {
+        let file = File::new(generate_test_path());
+        file.write_new("b 10\na 9\nc 100\na 9\nd 2\n")?;
+        // A tiny memory budget forces several runs to be merged.
+        let sorted = file.sort_lines(generate_test_path(), &SortOptions::new().memory(1))?;
+        assert_eq!(sorted.read()?, "a 9\na 9\nb 10\nc 100\nd 2\n");
+
+        let options = SortOptions::new()
+            .field(2, None)
+            .numeric(true)
+            .reverse(true)
+            .unique(true)
+            .memory(40);
+        let sorted = file.sort_lines(generate_test_path(), &options)?;
+        assert_eq!(sorted.read()?, "c 100\nb 10\na 9\nd 2\n");
+
+        assert_eq!(
+            file.unique_lines()?,
+            [
+                (String::from("a 9"), 2),
+                (String::from("b 10"), 1),
+                (String::from("c 100"), 1),
+                (String::from("d 2"), 1)
+            ]
+        );
+        assert_eq!(file.count_lines()?, 5);
+        assert_eq!(
+            file.stats()?,
+            TextStats {
+                lines:           5,
+                words:           10,
+                bytes:           23,
+                max_line_length: 5,
+            }
+        );
+        file.append("e")?;
+        assert_eq!(file.count_lines()?, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn shuffle_and_extract() -> FSResult<()> {
+        use std::fmt::Write as _;
+
+        let file = File::new(generate_test_path());
+        let content = (0..1000).fold(String::new(), |mut content, number| {
+            let _ = writeln!(content, "{number},x{number}");
+            content
+        });
+        file.write_new(&content)?;
+
+        let shuffled = file.shuffle_lines(generate_test_path())?;
+        assert_ne!(shuffled.read()?, content);
+        let sorted = shuffled.sort_lines(
+            generate_test_path(),
+            &SortOptions::new().field(1, Some(b',')).numeric(true),
+        )?;
+        assert_eq!(sorted.read()?, content);
+
+        let columns = file.extract_columns(generate_test_path(), &[2, 1, 3], Some(b','))?;
+        assert!(columns.read()?.starts_with("x0,0,\nx1,1,\n"));
+        Ok(())
+    }
+}
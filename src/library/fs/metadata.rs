@@ -0,0 +1,126 @@
+//! This module contains what is known about an object on the filesystem besides its
+//! content ([`Metadata`] and [`Object::metadata`](super::Object::metadata)).
+
+use super::{
+    backend,
+    FSResult,
+    ObjectType,
+};
+
+/// What is known about a file or directory, see
+/// [`Object::metadata`](super::Object::metadata).
+///
+/// Values a platform or backend does not provide are [`None`], e.g. the inode
+/// outside of Unix or any timestamp in a [`MemoryBackend`](super::MemoryBackend).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Metadata {
+    /// What kind of object it is
+    pub object_type: ObjectType,
+    /// The size in bytes; for directories, what the filesystem reports
+    pub size:        u64,
+    /// When the object was created
+    pub created:     Option<std::time::SystemTime>,
+    /// When the content was last modified
+    pub modified:    Option<std::time::SystemTime>,
+    /// When the content was last read
+    pub accessed:    Option<std::time::SystemTime>,
+    /// The inode number
+    pub inode:       Option<u64>,
+    /// The ID of the device the object is on
+    pub device:      Option<u64>,
+    /// The number of hard links to the object
+    pub links:       Option<u64>,
+}
+
+impl From<&std::fs::Metadata> for Metadata {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        #[cfg(unix)]
+        let (inode, device, links) = {
+            use std::os::unix::fs::MetadataExt as _;
+            (
+                Some(metadata.ino()),
+                Some(metadata.dev()),
+                Some(metadata.nlink()),
+            )
+        };
+        #[cfg(not(unix))]
+        let (inode, device, links) = (None, None, None);
+
+        Self {
+            object_type: if file_type.is_file() {
+                ObjectType::File
+            } else if file_type.is_dir() {
+                ObjectType::Directory
+            } else if file_type.is_symlink() {
+                ObjectType::SymbolicLink
+            } else {
+                ObjectType::Unknown
+            },
+            size: metadata.len(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+            accessed: metadata.accessed().ok(),
+            inode,
+            device,
+            links,
+        }
+    }
+}
+
+/// The metadata of `path`, following symbolic links.
+pub(super) fn read(path: &std::path::Path) -> FSResult<Metadata> {
+    Ok(backend::with(|backend| backend.metadata(path))?)
+}
+
+#[cfg(test)]
+mod metadata_test {
+    use super::{
+        super::{
+            generate_test_path,
+            Directory,
+            FSError,
+            File,
+            MemoryBackend,
+            Object as _,
+        },
+        *,
+    };
+
+    #[test]
+    fn files_and_directories() -> FSResult<()> {
+        let root = generate_test_path();
+        std::fs::create_dir_all(&root)?;
+        std::fs::write(root.join("file"), "content")?;
+
+        let file = File::new(root.join("file"));
+        let metadata = file.metadata()?;
+        assert_eq!(metadata.object_type, ObjectType::File);
+        assert_eq!(metadata.size, 7);
+        assert!(metadata.modified.is_some());
+        #[cfg(unix)]
+        {
+            assert_eq!(metadata.links, Some(1));
+            assert!(metadata.inode.is_some());
+            assert_eq!(metadata.device, Directory::new(&root).metadata()?.device);
+        }
+        std::mem::forget(file);
+        assert_eq!(
+            Directory::new(&root).metadata()?.object_type,
+            ObjectType::Directory
+        );
+        assert_eq!(
+            File::new(root.join("missing")).metadata(),
+            Err(FSError::NonExistent)
+        );
+        std::fs::remove_dir_all(&root)?;
+
+        let _memory = MemoryBackend::install();
+        let file = File::new("/file");
+        file.write_new("content")?;
+        let metadata = file.metadata()?;
+        assert_eq!(metadata.size, 7);
+        assert_eq!(metadata.modified, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! This module contains functionality for working with file extensions and MIME
+//! types, which web-asset and media scripts use to decide what to do with a file.
+
+use super::{
+    Directory,
+    FSResult,
+    File,
+    FileKind,
+    Object as _,
+};
+
+/// The MIME types of common extensions, which are lower-case.
+const MIME_TYPES: [(&str, &str); 56] = [
+    ("7z", "application/x-7z-compressed"),
+    ("avi", "video/x-msvideo"),
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("bz2", "application/x-bzip2"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("flac", "audio/flac"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("jar", "application/java-archive"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("m4a", "audio/mp4"),
+    ("md", "text/markdown"),
+    ("mjs", "text/javascript"),
+    ("mkv", "video/x-matroska"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("ogg", "audio/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("py", "text/x-python"),
+    ("rs", "text/x-rust"),
+    ("sh", "application/x-sh"),
+    ("sqlite", "application/vnd.sqlite3"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tgz", "application/gzip"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("toml", "application/toml"),
+    ("tsv", "text/tab-separated-values"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("xml", "application/xml"),
+    ("xz", "application/x-xz"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+];
+/// The MIME type of data of an unknown kind.
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// The MIME type files with the extension `extension` (without the leading dot,
+/// in any case) usually have, if it is a common one.
+#[must_use]
+pub fn mime_type_of_extension(extension: &str) -> Option<&'static str> {
+    let extension = extension.to_ascii_lowercase();
+    MIME_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+impl FileKind {
+    /// The MIME type of this kind of content, if it has a specific one. Text,
+    /// scripts and unknown binary data do not.
+    #[must_use]
+    pub const fn mime_type(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Elf => "application/x-executable",
+            Self::Pe => "application/vnd.microsoft.portable-executable",
+            Self::MachO => "application/x-mach-binary",
+            Self::Wasm => "application/wasm",
+            Self::Tar => "application/x-tar",
+            Self::Gzip => "application/gzip",
+            Self::Bzip2 => "application/x-bzip2",
+            Self::Xz => "application/x-xz",
+            Self::Zstd => "application/zstd",
+            Self::Zip => "application/zip",
+            Self::SevenZip => "application/x-7z-compressed",
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+            Self::Pdf => "application/pdf",
+            Self::Sqlite => "application/vnd.sqlite3",
+            Self::Empty | Self::Script | Self::Text(_) | Self::Binary => return None,
+        })
+    }
+}
+
+impl File {
+    /// The extension of this file (without the leading dot), if it has one.
+    #[must_use]
+    pub fn extension(&self) -> Option<String> {
+        self.path
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+    }
+
+    /// The file next to this one whose extension is `extension` instead. An empty
+    /// `extension` removes the extension.
+    #[must_use]
+    pub fn with_extension(&self, extension: impl AsRef<std::ffi::OsStr>) -> Self {
+        Self::new(self.path.with_extension(extension))
+    }
+
+    /// The MIME type of this file. Content with a clear signature (see
+    /// [`File::detect_kind`]) decides, except for zip archives, which are also the
+    /// container of many formats (like JAR and DOCX); otherwise, the extension
+    /// decides. Without a known extension, text is `text/plain` and everything else
+    /// `application/octet-stream`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file fails.
+    pub fn mime_type(&self) -> FSResult<&'static str> {
+        let kind = self.detect_kind()?;
+        let by_extension = self
+            .extension()
+            .and_then(|extension| mime_type_of_extension(&extension));
+        let by_kind = kind.mime_type().filter(|_| kind != FileKind::Zip);
+        let fallback = if kind.is_text() {
+            "text/plain"
+        } else {
+            OCTET_STREAM
+        };
+        Ok(by_kind
+            .or(by_extension)
+            .or_else(|| kind.mime_type())
+            .unwrap_or(fallback))
+    }
+}
+
+impl Directory {
+    /// The files directly in this directory, grouped by their lower-cased
+    /// extension. Files without an extension are grouped under the empty string.
+    /// Subdirectories are not descended into.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this directory fails.
+    pub fn group_by_extension(&self) -> FSResult<std::collections::BTreeMap<String, Vec<File>>> {
+        let mut groups = std::collections::BTreeMap::<String, Vec<File>>::new();
+        let entries = super::backend::with(|backend| {
+            backend.read_dir(&self.path).map(|entries| {
+                entries
+                    .into_iter()
+                    .filter(|entry| backend.object_type(entry) == Some(super::ObjectType::File))
+                    .collect::<Vec<_>>()
+            })
+        })?;
+        for entry in entries {
+            let file = File::new(entry);
+            let extension = file.extension().unwrap_or_default().to_lowercase();
+            groups.entry(extension).or_default().push(file);
+        }
+        for files in groups.values_mut() {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod mime_test {
+    use super::*;
+    use crate::fs::generate_test_path;
+
+    #[test]
+    fn extensions_and_mime_types() -> FSResult<()> {
+        assert_eq!(mime_type_of_extension("PNG"), Some("image/png"));
+        assert_eq!(mime_type_of_extension("unknown"), None);
+
+        let directory = Directory::new(generate_test_path());
+        directory.create_on_fs_recursive()?;
+        let style = File::new(directory.path().join("style.css"));
+        style.write_new("body { color: red; }")?;
+        assert_eq!(style.extension().as_deref(), Some("css"));
+        assert_eq!(style.mime_type()?, "text/css");
+
+        // The content wins over a wrong extension, except for zip archives.
+        let image = style.with_extension("txt");
+        std::fs::write(image.path(), b"\x89PNG\r\n\x1a\n")?;
+        assert_eq!(image.mime_type()?, "image/png");
+        let document = style.with_extension("DOCX");
+        std::fs::write(document.path(), b"PK\x03\x04")?;
+        assert!(document.mime_type()?.contains("wordprocessingml"));
+        let notes = style.with_extension("");
+        notes.write_new("Nothing special")?;
+        assert_eq!(notes.mime_type()?, "text/plain");
+        std::fs::create_dir(directory.path().join("sub.d"))?;
+
+        let groups = directory.group_by_extension()?;
+        let names: Vec<_> = groups
+            .iter()
+            .map(|(extension, files)| (extension.as_str(), files.len()))
+            .collect();
+        assert_eq!(names, [("", 1), ("css", 1), ("docx", 1), ("txt", 1)]);
+
+        drop(groups);
+        directory.delete_from_fs()
+    }
+}
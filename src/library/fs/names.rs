@@ -0,0 +1,170 @@
+//! This module contains functionality for turning arbitrary strings (like titles
+//! entered by users) into file names and for finding names that are not taken yet.
+
+/// The longest file name, in bytes, most filesystems allow.
+const MAX_NAME_LENGTH: usize = 255;
+/// The characters Windows does not allow in file names (besides control
+/// characters).
+const WINDOWS_INVALID: &str = "<>:\"/\\|?*";
+/// The names Windows reserves for devices, with or without an extension.
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Make `name` usable as a file name, following the rules of Windows if `windows`
+/// is set.
+fn sanitize(name: &str, windows: bool) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|character| {
+            if character == '/'
+                || character.is_control()
+                || (windows && WINDOWS_INVALID.contains(character))
+            {
+                '_'
+            } else {
+                character
+            }
+        })
+        .collect();
+
+    // Windows silently drops trailing dots and spaces.
+    let trimmed = sanitized.trim_start();
+    let trimmed = if windows {
+        trimmed.trim_end_matches(['.', ' '])
+    } else {
+        trimmed.trim_end()
+    };
+    sanitized = trimmed.to_string();
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if windows
+        && WINDOWS_RESERVED
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(0, '_');
+    }
+    if matches!(sanitized.as_str(), "" | "." | "..") {
+        sanitized = "_".repeat(sanitized.len().max(1));
+    }
+
+    if sanitized.len() > MAX_NAME_LENGTH {
+        // Shorten the part before the extension, if the extension is short.
+        let extension = sanitized
+            .rfind('.')
+            .filter(|index| *index > 0 && sanitized.len() - index <= 16)
+            .map_or_else(String::new, |index| sanitized[index..].to_string());
+        let mut end = MAX_NAME_LENGTH - extension.len();
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+        sanitized.push_str(&extension);
+    }
+    sanitized
+}
+
+/// Turn `name` into a valid file name for the current platform.
+///
+/// Path separators, control characters and (on Windows) the characters and device names
+/// Windows forbids are replaced or prefixed with `_`, surrounding whitespace is removed
+/// and the name is shortened to 255 bytes, keeping its extension. Names that
+/// would be empty, `.` or `..` become underscores.
+///
+/// ```
+/// assert_eq!(rush::fs::sanitize_filename(" Report 3/4 "), "Report 3_4");
+/// ```
+#[must_use]
+pub fn sanitize_filename(name: &str) -> String { sanitize(name, cfg!(windows)) }
+
+/// Return `path` if nothing exists there yet, or the first of `name (1).ext`,
+/// `name (2).ext`, ... in the same directory that does not exist, like browsers
+/// name downloads.
+///
+/// Another process may still take the name before it is used; open the file
+/// with [`std::fs::OpenOptions::create_new`] where this matters.
+#[must_use]
+pub fn unique_target(path: impl AsRef<std::path::Path>) -> std::path::PathBuf {
+    // Dangling symbolic links also occupy their name.
+    unique_among(path.as_ref(), |path| {
+        super::backend::with(|backend| {
+            backend.object_type(path).is_some()
+                || backend.read_link(path).is_ok_and(|target| target.is_some())
+        })
+    })
+}
+
+/// Like [`unique_target`], but whether a name is taken is decided by `exists`.
+pub(super) fn unique_among(
+    path: &std::path::Path,
+    exists: impl Fn(&std::path::Path) -> bool,
+) -> std::path::PathBuf {
+    if !exists(path) {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map_or_else(String::new, |extension| {
+        format!(".{}", extension.to_string_lossy())
+    });
+    (1..u64::MAX)
+        .map(|number| path.with_file_name(format!("{stem} ({number}){extension}")))
+        .find(|candidate| !exists(candidate))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod names_test {
+    use super::*;
+    use crate::fs::{
+        FSResult,
+        File,
+        MemoryBackend,
+        Object as _,
+    };
+
+    #[test]
+    fn sanitize_names() {
+        assert_eq!(sanitize("a/b\0c\n", false), "a_b_c_");
+        assert_eq!(
+            sanitize("What? <Yes>: \"no\". ", false),
+            "What? <Yes>: \"no\"."
+        );
+        assert_eq!(sanitize("What? <Yes>: \"no\". ", true), "What_ _Yes__ _no_");
+        assert_eq!(sanitize("con.txt", true), "_con.txt");
+        assert_eq!(sanitize("con.txt", false), "con.txt");
+        assert_eq!(sanitize("..", false), "__");
+        assert_eq!(sanitize("   ", false), "_");
+
+        let long = sanitize(&format!("{}.tar.gz", "ä".repeat(200)), false);
+        assert!(long.len() <= MAX_NAME_LENGTH);
+        assert!(long.starts_with('ä'));
+        assert_eq!(std::path::Path::new(&long).extension(), Some("gz".as_ref()));
+    }
+
+    #[test]
+    fn unique_targets() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let directory = std::path::Path::new("/downloads");
+        let path = directory.join("report.txt");
+        assert_eq!(unique_target(&path), path);
+
+        let files = [
+            &path,
+            &directory.join("report (1).txt"),
+            &directory.join("README"),
+        ]
+        .map(File::new);
+        for file in &files {
+            file.create_on_fs_recursive()?;
+        }
+        assert_eq!(unique_target(&path), directory.join("report (2).txt"));
+        assert_eq!(
+            unique_target(directory.join("README")),
+            directory.join("README (1)")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! This module contains the usual maintenance of photo and download folders:
+//! flattening nested directories ([`Directory::flatten`]) and sorting files into
+//! subdirectories ([`Directory::organize_by`]).
+
+use super::{
+    backend,
+    names,
+    Directory,
+    FSError,
+    FSResult,
+    ObjectType,
+};
+
+/// What to do when a file is moved to a name that is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Collision {
+    /// Leave the file where it is
+    Skip,
+    /// Replace the file that has the name
+    Overwrite,
+    /// Move the file to the first free name of `name (1).ext`, `name (2).ext`, ...
+    #[default]
+    KeepBoth,
+    /// Stop with [`FSError::AlreadyExists`]
+    Fail,
+}
+
+/// Move the file `from` to `to`, resolving a taken `to` according to `collision`.
+/// Returns whether the file was moved.
+fn place(from: &std::path::Path, to: &std::path::Path, collision: Collision) -> FSResult<bool> {
+    let target = match backend::with(|backend| backend.object_type(to)) {
+        None => to.to_path_buf(),
+        Some(_) if collision == Collision::Skip => {
+            log::debug!(
+                "Not moving '{}' as '{}' exists",
+                from.display(),
+                to.display()
+            );
+            return Ok(false);
+        },
+        Some(ObjectType::File) if collision == Collision::Overwrite => to.to_path_buf(),
+        Some(object_type) if collision == Collision::Overwrite => {
+            return Err(FSError::TypeMismatch(object_type))
+        },
+        Some(_) if collision == Collision::Fail => return Err(FSError::AlreadyExists),
+        Some(_) => names::unique_among(to, |candidate| {
+            backend::with(|backend| backend.object_type(candidate)).is_some()
+        }),
+    };
+    backend::with(|backend| backend.rename(from, &target))?;
+    log::trace!("Moved '{}' to '{}'", from.display(), target.display());
+    Ok(true)
+}
+
+/// The files and the directories below `directory` (excluding itself), sorted.
+fn walk(
+    directory: &std::path::Path,
+    files: &mut Vec<std::path::PathBuf>,
+    directories: &mut Vec<std::path::PathBuf>,
+) -> FSResult<()> {
+    let mut entries = backend::with(|backend| backend.read_dir(directory))?;
+    entries.sort();
+    for entry in entries {
+        match backend::with(|backend| backend.object_type(&entry)) {
+            Some(ObjectType::Directory) => {
+                directories.push(entry.clone());
+                walk(&entry, files, directories)?;
+            },
+            Some(ObjectType::File) => files.push(entry),
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/// The lower-cased extension of `path`, e.g. `jpg` for `IMG_0001.JPG`, for use with
+/// [`Directory::organize_by`]. Files without an extension are left alone.
+#[must_use]
+pub fn bucket_by_extension(path: &std::path::Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// The upper-cased first letter or digit of the name of `path`, for use with
+/// [`Directory::organize_by`]. Names starting with anything else go to `#`.
+#[must_use]
+pub fn bucket_by_first_letter(path: &std::path::Path) -> Option<String> {
+    let first = path.file_name()?.to_string_lossy().chars().next()?;
+    Some(
+        if first.is_alphanumeric() {
+            first.to_uppercase().collect()
+        } else {
+            String::from("#")
+        },
+    )
+}
+
+/// The month (UTC) `path` was last modified in, e.g. `2024-10`, for use with
+/// [`Directory::organize_by`].
+///
+/// The modification time is read from disk, even if a
+/// [`MemoryBackend`](super::MemoryBackend) is installed.
+#[must_use]
+pub fn bucket_by_month(path: &std::path::Path) -> Option<String> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let date = crate::library::time::DateTime::from_system_time(modified);
+    Some(format!("{:04}-{:02}", date.year, date.month))
+}
+
+impl Directory {
+    /// Move all files in subdirectories of this directory (at any depth) directly
+    /// into it, resolving taken names according to `collision`. Subdirectories that
+    /// are empty afterwards are deleted. Returns the number of moved files.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// File::new("/downloads/2024/a.txt").create_on_fs_recursive().unwrap();
+    /// File::new("/downloads/2025/a.txt").create_on_fs_recursive().unwrap();
+    /// let moved = Directory::new("/downloads")
+    ///     .flatten(fs::Collision::KeepBoth)
+    ///     .unwrap();
+    /// assert_eq!(moved, 2);
+    /// assert!(File::new("/downloads/a (1).txt").exists().unwrap());
+    /// assert!(!Directory::new("/downloads/2024").exists().unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading a directory or moving a file fails, or if a
+    /// name is taken and `collision` is [`Collision::Fail`]. Files moved before
+    /// stay moved.
+    pub fn flatten(&self, collision: Collision) -> FSResult<usize> {
+        log::debug!("Flattening directory {}", self);
+        let (mut files, mut directories) = (Vec::new(), Vec::new());
+        walk(&self.path, &mut files, &mut directories)?;
+
+        let mut moved = 0;
+        for file in files
+            .iter()
+            .filter(|file| file.parent() != Some(self.path.as_path()))
+        {
+            let Some(name) = file.file_name() else {
+                continue;
+            };
+            if place(file, &self.path.join(name), collision)? {
+                moved += 1;
+            }
+        }
+
+        // Deepest first, so parents are empty once their children are gone.
+        directories.sort_by_key(|directory| std::cmp::Reverse(directory.components().count()));
+        for directory in directories {
+            let empty = backend::with(|backend| backend.read_dir(&directory))?.is_empty();
+            if empty {
+                backend::with(|backend| backend.remove_dir_all(&directory))?;
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Move each file directly in this directory into the subdirectory named by
+    /// `bucket`, creating it if needed. Files for which `bucket` returns [`None`]
+    /// stay where they are. Bucket names are made valid with
+    /// [`sanitize_filename`](super::sanitize_filename) and taken names are resolved
+    /// according to `collision`. Returns the number of moved files.
+    ///
+    /// [`bucket_by_extension`], [`bucket_by_first_letter`] and [`bucket_by_month`]
+    /// cover the common cases.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// File::new("/photos/IMG_0001.JPG").create_on_fs_recursive().unwrap();
+    /// File::new("/photos/notes").create_on_fs().unwrap();
+    /// let photos = Directory::new("/photos");
+    /// let moved = photos
+    ///     .organize_by(fs::bucket_by_extension, fs::Collision::Fail)
+    ///     .unwrap();
+    /// assert_eq!(moved, 1);
+    /// assert!(File::new("/photos/jpg/IMG_0001.JPG").exists().unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this directory, creating a subdirectory or
+    /// moving a file fails, or if a name is taken and `collision` is
+    /// [`Collision::Fail`]. Files moved before stay moved.
+    pub fn organize_by(
+        &self,
+        mut bucket: impl FnMut(&std::path::Path) -> Option<String>,
+        collision: Collision,
+    ) -> FSResult<usize> {
+        log::debug!("Organizing directory {}", self);
+        let mut entries = backend::with(|backend| backend.read_dir(&self.path))?;
+        entries.sort();
+
+        let mut moved = 0;
+        for entry in entries {
+            if backend::with(|backend| backend.object_type(&entry)) != Some(ObjectType::File) {
+                continue;
+            }
+            let (Some(name), Some(bucket_name)) = (entry.file_name(), bucket(&entry)) else {
+                continue;
+            };
+            let subdirectory = self.path.join(super::sanitize_filename(&bucket_name));
+            backend::with(|backend| backend.create_dir_all(&subdirectory))?;
+            if place(&entry, &subdirectory.join(name), collision)? {
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod organize_test {
+    use super::{
+        super::{
+            generate_test_path,
+            File,
+            MemoryBackend,
+            Object as _,
+        },
+        *,
+    };
+
+    /// Write `content` to `path`, creating its parents.
+    fn write(path: &std::path::Path, content: &str) -> FSResult<()> {
+        backend::with(|backend| backend.create_dir_all(path.parent().unwrap_or(path)))?;
+        backend::with(|backend| backend.write(path, content.as_bytes(), false))?;
+        Ok(())
+    }
+
+    #[test]
+    fn flatten() -> FSResult<()> {
+        let memory = MemoryBackend::install();
+        let root = generate_test_path();
+        let directory = Directory::new(&root);
+        write(&root.join("top.txt"), "top")?;
+        write(&root.join("a/top.txt"), "a")?;
+        write(&root.join("a/b/c/deep.txt"), "deep")?;
+        write(&root.join("d/top.txt"), "d")?;
+
+        assert_eq!(directory.flatten(Collision::Skip)?, 1);
+        assert!(backend::with(|backend| backend.object_type(&root.join("a/b"))).is_none());
+        assert_eq!(
+            backend::with(|backend| backend.read(&root.join("a/top.txt")))?,
+            b"a"
+        );
+        assert_eq!(
+            directory.flatten(Collision::Fail),
+            Err(FSError::AlreadyExists)
+        );
+        assert_eq!(directory.flatten(Collision::KeepBoth)?, 2);
+        assert_eq!(directory.flatten(Collision::Overwrite)?, 0);
+
+        let paths: Vec<_> = memory
+            .paths()
+            .into_iter()
+            .filter_map(|path| Some(path.strip_prefix(&root).ok()?.to_path_buf()))
+            .collect();
+        assert_eq!(
+            paths,
+            ["", "deep.txt", "top (1).txt", "top (2).txt", "top.txt"].map(std::path::PathBuf::from)
+        );
+        assert_eq!(
+            backend::with(|backend| backend.read(&root.join("top (2).txt")))?,
+            b"d"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn organize() -> FSResult<()> {
+        let memory = MemoryBackend::install();
+        let root = generate_test_path();
+        let directory = Directory::new(&root);
+        write(&root.join("IMG_1.JPG"), "1")?;
+        write(&root.join("img_2.jpg"), "2")?;
+        write(&root.join("README"), "")?;
+        write(&root.join("jpg/img_2.jpg"), "old")?;
+
+        assert_eq!(
+            directory.organize_by(bucket_by_extension, Collision::Overwrite)?,
+            2
+        );
+        assert_eq!(
+            backend::with(|backend| backend.read(&root.join("jpg/img_2.jpg")))?,
+            b"2"
+        );
+        assert!(backend::with(|backend| backend.object_type(&root.join("README"))).is_some());
+
+        assert_eq!(
+            directory.organize_by(|_| Some(String::from("a/b")), Collision::Fail)?,
+            1
+        );
+        assert!(backend::with(|backend| backend.object_type(&root.join("a_b/README"))).is_some());
+
+        assert_eq!(
+            bucket_by_first_letter(std::path::Path::new("/x/éclair.png")).as_deref(),
+            Some("É")
+        );
+        assert_eq!(
+            bucket_by_first_letter(std::path::Path::new("_draft")).as_deref(),
+            Some("#")
+        );
+
+        let file = File::new(generate_test_path());
+        drop(memory);
+        file.create_on_fs()?;
+        assert!(bucket_by_month(file.path()).is_some_and(|month| month.len() == 7));
+        Ok(())
+    }
+}
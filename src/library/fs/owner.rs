@@ -0,0 +1,206 @@
+//! This module contains reading and changing which user and group own objects
+//! ([`Owner`] and [`Object::set_owner`](super::Object::set_owner)).
+
+use super::{
+    backend,
+    walk,
+    Directory,
+    FSError,
+    FSResult,
+};
+
+/// The user and group owning a file or directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Owner {
+    /// The ID of the owning user
+    pub user:  u32,
+    /// The ID of the owning group
+    pub group: u32,
+}
+
+impl Owner {
+    /// The name of the owning user, if the user is known.
+    #[must_use]
+    pub fn user_name(&self) -> Option<String> {
+        nix::unistd::User::from_uid(self.user.into())
+            .ok()
+            .flatten()
+            .map(|user| user.name)
+    }
+
+    /// The name of the owning group, if the group is known.
+    #[must_use]
+    pub fn group_name(&self) -> Option<String> {
+        nix::unistd::Group::from_gid(self.group.into())
+            .ok()
+            .flatten()
+            .map(|group| group.name)
+    }
+}
+
+/// A user or group to own an object, see
+/// [`Object::set_owner`](super::Object::set_owner).
+///
+/// Names and IDs convert into accounts, e.g. `"www-data".into()` or `33.into()`. Like
+/// `chown` does, a name that is not known but is a number is used as the ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Account {
+    /// Keep the current user or group
+    Unchanged,
+    /// The user or group with this name
+    Name(String),
+    /// The user or group with this ID
+    Id(u32),
+}
+
+impl From<&str> for Account {
+    fn from(name: &str) -> Self { Self::Name(String::from(name)) }
+}
+
+impl From<String> for Account {
+    fn from(name: String) -> Self { Self::Name(name) }
+}
+
+impl From<u32> for Account {
+    fn from(id: u32) -> Self { Self::Id(id) }
+}
+
+impl Account {
+    /// The ID of the account, or [`None`] if it is unchanged. `lookup` finds the ID
+    /// of a name.
+    fn resolve(&self, lookup: impl FnOnce(&str) -> Option<u32>) -> FSResult<Option<u32>> {
+        match self {
+            Self::Unchanged => Ok(None),
+            Self::Id(id) => Ok(Some(*id)),
+            Self::Name(name) => lookup(name)
+                .or_else(|| name.parse().ok())
+                .map(Some)
+                .ok_or_else(|| FSError::UnknownAccount(name.clone())),
+        }
+    }
+
+    /// The ID of the user, or [`None`] if it is unchanged.
+    fn user_id(&self) -> FSResult<Option<u32>> {
+        self.resolve(|name| {
+            nix::unistd::User::from_name(name)
+                .ok()
+                .flatten()
+                .map(|user| user.uid.as_raw())
+        })
+    }
+
+    /// The ID of the group, or [`None`] if it is unchanged.
+    fn group_id(&self) -> FSResult<Option<u32>> {
+        self.resolve(|name| {
+            nix::unistd::Group::from_name(name)
+                .ok()
+                .flatten()
+                .map(|group| group.gid.as_raw())
+        })
+    }
+}
+
+/// The owner of `path`.
+pub(super) fn read(path: &std::path::Path) -> FSResult<Owner> {
+    let (user, group) = backend::with(|backend| backend.owner(path))?;
+    Ok(Owner { user, group })
+}
+
+/// The user and group IDs to change to, [`None`] for those that are unchanged.
+fn resolve(user: &Account, group: &Account) -> FSResult<(Option<u32>, Option<u32>)> {
+    Ok((user.user_id()?, group.group_id()?))
+}
+
+/// Change the owner of `path` to the resolved `user` and `group`.
+fn write(path: &std::path::Path, (user, group): (Option<u32>, Option<u32>)) -> FSResult<()> {
+    log::trace!(
+        "Changing the owner of '{}' to {:?}:{:?}",
+        path.display(),
+        user,
+        group
+    );
+    backend::with(|backend| backend.set_owner(path, user, group))?;
+    Ok(())
+}
+
+/// Change the owner of `path` to `user` and `group`.
+pub(super) fn change(path: &std::path::Path, user: &Account, group: &Account) -> FSResult<()> {
+    write(path, resolve(user, group)?)
+}
+
+impl Directory {
+    /// Change the owner of this directory and everything below it, see
+    /// [`Object::set_owner`](super::Object::set_owner).
+    ///
+    /// Symbolic links are neither changed nor followed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::UnknownAccount`] if the user or group does not exist, or an
+    /// error if an object cannot be changed. Objects handled before keep their
+    /// changes.
+    pub fn chown_recursive(
+        &self,
+        user: impl Into<Account>,
+        group: impl Into<Account>,
+    ) -> FSResult<()> {
+        let owner = resolve(&user.into(), &group.into())?;
+        log::trace!("Changing the owner below {}", self);
+        walk::visit_paths(&self.path, |path| write(path, owner))
+    }
+}
+
+#[cfg(test)]
+mod owner_test {
+    use super::{
+        super::{
+            generate_test_path,
+            File,
+            Object as _,
+        },
+        *,
+    };
+
+    #[test]
+    fn accounts() {
+        assert_eq!(Account::Unchanged.user_id(), Ok(None));
+        assert_eq!(Account::from(33).group_id(), Ok(Some(33)));
+        assert_eq!(Account::from("root").user_id(), Ok(Some(0)));
+        assert_eq!(Account::from("4711").user_id(), Ok(Some(4711)));
+        assert_eq!(
+            Account::from("no-such-user-at-all").user_id(),
+            Err(FSError::UnknownAccount(String::from("no-such-user-at-all")))
+        );
+        assert_eq!(
+            Owner { user: 0, group: 0 }.user_name().as_deref(),
+            Some("root")
+        );
+    }
+
+    #[test]
+    fn chown() -> FSResult<()> {
+        let root = generate_test_path();
+        std::fs::create_dir_all(root.join("sub"))?;
+        std::fs::write(root.join("sub/file"), "")?;
+        let user = nix::unistd::getuid().as_raw();
+        let group = nix::unistd::getgid().as_raw();
+
+        // Giving objects to their current owner works without privileges.
+        let file = File::new(root.join("sub/file"));
+        assert_eq!(file.owner()?, Owner { user, group });
+        file.set_owner(user, Account::Unchanged)?;
+        file.set_owner(Account::Unchanged, group)?;
+        assert_eq!(file.owner()?, Owner { user, group });
+        std::mem::forget(file);
+
+        let directory = Directory::new(&root);
+        directory.chown_recursive(user, group)?;
+        assert_eq!(directory.owner()?, Owner { user, group });
+        assert!(matches!(
+            directory.chown_recursive("no-such-user-at-all", group),
+            Err(FSError::UnknownAccount(_))
+        ));
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+//! This module contains reading and changing the permission bits of objects
+//! ([`Permissions`] and [`Object::chmod`](super::Object::chmod)).
+
+use super::{
+    backend,
+    walk,
+    Directory,
+    FSError,
+    FSResult,
+    ObjectType,
+};
+
+/// The bits each class of users can be given, including setuid, setgid and sticky.
+const USER: u32 = 0o4700;
+/// See [`USER`].
+const GROUP: u32 = 0o2070;
+/// See [`USER`].
+const OTHERS: u32 = 0o1007;
+
+/// The permission bits of a file or directory, e.g. `0o755` or `rwxr-xr-x`.
+///
+/// Besides read, write and execute for the owner, the group and others, these are
+/// the setuid, setgid and sticky bits.
+///
+/// ```
+/// # use rush::fs::Permissions;
+/// let permissions = Permissions::from_mode(0o644);
+/// assert_eq!(permissions.to_string(), "rw-r--r--");
+/// let executable = permissions.change("u+x,go-r", false).unwrap();
+/// assert_eq!(executable.mode(), 0o700);
+/// assert!(executable.is_executable());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Permissions {
+    /// The permission bits
+    mode: u32,
+}
+
+impl Permissions {
+    /// Permissions from the octal `mode`, e.g. `0o755`. Bits other than the
+    /// permission bits are ignored.
+    #[must_use]
+    pub const fn from_mode(mode: u32) -> Self {
+        Self {
+            mode: mode & 0o7777,
+        }
+    }
+
+    /// The permission bits as a number, e.g. `0o755`.
+    #[must_use]
+    pub const fn mode(self) -> u32 { self.mode }
+
+    /// Whether anyone may execute the object (or, for a directory, enter it).
+    #[must_use]
+    pub const fn is_executable(self) -> bool { self.mode & 0o111 != 0 }
+
+    /// Apply the change `mode` as `chmod` does and return the result.
+    ///
+    /// `mode` is either octal (e.g. `755`) or symbolic: comma-separated clauses of
+    /// who (any of `u`, `g`, `o` and `a`; all if omitted), an operator (`+`, `-` or
+    /// `=`) and the permissions (any of `r`, `w`, `x`, `X`, `s` and `t`, or one of
+    /// `u`, `g` and `o` to copy that class's permissions), e.g. `u+x,go-w` or
+    /// `a=rX`. `X` is execute for directories and for objects that are executable
+    /// already, so `is_directory` has to tell which kind of object is changed.
+    /// Unlike `chmod`, omitting who ignores the umask.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::InvalidMode`] if `mode` is neither octal nor symbolic.
+    pub fn change(self, mode: &str, is_directory: bool) -> FSResult<Self> {
+        let invalid = || FSError::InvalidMode(String::from(mode));
+        if !mode.is_empty()
+            && mode.len() <= 4
+            && mode.bytes().all(|byte| matches!(byte, b'0'..=b'7'))
+        {
+            return u32::from_str_radix(mode, 8)
+                .map(Self::from_mode)
+                .map_err(|_| invalid());
+        }
+
+        let mut current = self.mode;
+        for clause in mode.split(',') {
+            let operator = clause.find(['+', '-', '=']).ok_or_else(invalid)?;
+            let mut who = 0;
+            for class in clause[..operator].chars() {
+                who |= match class {
+                    'u' => USER,
+                    'g' => GROUP,
+                    'o' => OTHERS,
+                    'a' => 0o7777,
+                    _ => return Err(invalid()),
+                };
+            }
+            if who == 0 {
+                who = 0o7777;
+            }
+
+            // Each operator is followed by its permissions, e.g. `u+x-w`.
+            let mut rest = &clause[operator..];
+            while let Some(operator) = rest.chars().next() {
+                let end = rest[1..]
+                    .find(['+', '-', '='])
+                    .map_or(rest.len(), |end| end + 1);
+                let permissions = &rest[1..end];
+                rest = &rest[end..];
+
+                let bits = match permissions {
+                    "u" => (current & 0o700) >> 6,
+                    "g" => (current & 0o070) >> 3,
+                    "o" => current & 0o007,
+                    _ => {
+                        let mut bits = 0;
+                        for permission in permissions.chars() {
+                            bits |= match permission {
+                                'r' => 0o444,
+                                'w' => 0o222,
+                                'x' => 0o111,
+                                'X' if is_directory || current & 0o111 != 0 => 0o111,
+                                'X' => 0,
+                                's' => 0o6000,
+                                't' => 0o1000,
+                                _ => return Err(invalid()),
+                            };
+                        }
+                        bits
+                    },
+                };
+                // Copied permissions apply to every class, like `r`, `w` and `x`.
+                let bits = if matches!(permissions, "u" | "g" | "o") {
+                    bits * 0o111
+                } else {
+                    bits
+                } & who;
+                current = match operator {
+                    '+' => current | bits,
+                    '-' => current & !bits,
+                    _ => (current & !who) | bits,
+                };
+            }
+        }
+        Ok(Self::from_mode(current))
+    }
+}
+
+impl std::fmt::Display for Permissions {
+    /// Show the permissions as `ls -l` does, e.g. `rwxr-xr-x` or `rwsr-xr-t`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let special = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+        for (class, (special, symbol)) in special.into_iter().enumerate() {
+            let bits = (self.mode >> (6 - 3 * class)) & 0o7;
+            let execute = match (bits & 0o1 != 0, self.mode & special != 0) {
+                (true, true) => symbol,
+                (false, true) => symbol.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            };
+            write!(
+                f,
+                "{}{}{}",
+                if bits & 0o4 == 0 { '-' } else { 'r' },
+                if bits & 0o2 == 0 { '-' } else { 'w' },
+                execute
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The permissions of `path`.
+pub(super) fn read(path: &std::path::Path) -> FSResult<Permissions> {
+    Ok(Permissions::from_mode(backend::with(|backend| {
+        backend.mode(path)
+    })?))
+}
+
+/// Set the permissions of `path`.
+pub(super) fn write(path: &std::path::Path, permissions: Permissions) -> FSResult<()> {
+    log::trace!(
+        "Setting the permissions of '{}' to {}",
+        path.display(),
+        permissions
+    );
+    backend::with(|backend| backend.set_mode(path, permissions.mode()))?;
+    Ok(())
+}
+
+/// Apply the change `mode` to the permissions of `path` and return the result.
+pub(super) fn change(path: &std::path::Path, mode: &str) -> FSResult<Permissions> {
+    let is_directory =
+        backend::with(|backend| backend.object_type(path)) == Some(ObjectType::Directory);
+    let permissions = read(path)?.change(mode, is_directory)?;
+    write(path, permissions)?;
+    Ok(permissions)
+}
+
+impl Directory {
+    /// Apply the change `mode` (see [`Permissions::change`]) to this directory and
+    /// everything below it.
+    ///
+    /// Symbolic links are neither changed nor followed. Use a mode with `X`, e.g.
+    /// `a+rX`, to make directories but not files executable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::InvalidMode`] if `mode` is not valid, or an error if an
+    /// object cannot be read or changed. Objects handled before keep their changes.
+    pub fn chmod_recursive(&self, mode: &str) -> FSResult<()> {
+        log::trace!("Changing the permissions below {} with '{}'", self, mode);
+        walk::visit_paths(&self.path, |path| change(path, mode).map(|_| ()))
+    }
+}
+
+#[cfg(test)]
+mod permissions_test {
+    use super::{
+        super::{
+            generate_test_path,
+            Object as _,
+        },
+        *,
+    };
+
+    #[test]
+    fn symbolic_and_octal() -> FSResult<()> {
+        let change = |mode: u32, change: &str| {
+            Permissions::from_mode(mode)
+                .change(change, false)
+                .map(Permissions::mode)
+        };
+        assert_eq!(change(0o644, "755")?, 0o755);
+        assert_eq!(change(0o644, "u+x")?, 0o744);
+        assert_eq!(change(0o644, "+x")?, 0o755);
+        assert_eq!(change(0o777, "go-w")?, 0o755);
+        assert_eq!(change(0o777, "o=")?, 0o770);
+        assert_eq!(change(0o640, "g=u,o=g")?, 0o666);
+        assert_eq!(change(0o600, "u-w+x,a+r")?, 0o544);
+        assert_eq!(change(0o644, "a+X")?, 0o644);
+        assert_eq!(change(0o744, "a+X")?, 0o755);
+        assert_eq!(
+            Permissions::from_mode(0o644).change("a+X", true)?.mode(),
+            0o755
+        );
+        assert_eq!(change(0o755, "u+s,+t")?, 0o5755);
+        for invalid in ["", "u", "z+x", "u+q", "8", "07777"] {
+            assert_eq!(
+                change(0o644, invalid),
+                Err(FSError::InvalidMode(String::from(invalid)))
+            );
+        }
+
+        assert_eq!(Permissions::from_mode(0o755).to_string(), "rwxr-xr-x");
+        assert_eq!(Permissions::from_mode(0o4754).to_string(), "rwsr-xr--");
+        assert_eq!(Permissions::from_mode(0o1776).to_string(), "rwxrwxrwT");
+        assert!(!Permissions::from_mode(0o644).is_executable());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chmod() -> FSResult<()> {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let root = generate_test_path();
+        std::fs::create_dir_all(root.join("bin"))?;
+        std::fs::write(root.join("bin/script.sh"), "#!/bin/sh\n")?;
+        std::fs::set_permissions(
+            root.join("bin/script.sh"),
+            std::fs::Permissions::from_mode(0o644),
+        )?;
+        let mode = |path: &str| -> FSResult<u32> {
+            Ok(std::fs::metadata(root.join(path))?.permissions().mode() & 0o7777)
+        };
+
+        let script = super::super::File::new(root.join("bin/script.sh"));
+        assert_eq!(script.permissions()?.mode(), 0o644);
+        assert_eq!(script.chmod("u+x")?.mode(), 0o744);
+        assert_eq!(mode("bin/script.sh")?, 0o744);
+        script.set_permissions(Permissions::from_mode(0o600))?;
+        assert_eq!(mode("bin/script.sh")?, 0o600);
+
+        Directory::new(&root).chmod_recursive("go=rX")?;
+        assert_eq!(mode("bin")?, 0o755);
+        assert_eq!(mode("bin/script.sh")?, 0o644);
+        assert!(matches!(
+            Directory::new(&root).chmod_recursive("u+z"),
+            Err(FSError::InvalidMode(_))
+        ));
+        drop(script);
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+//! This module contains renaming many entries of a directory at once with a regular
+//! expression, the library equivalent of `rename 's/<pattern>/<replacement>/' *`.
+//!
+//! [`Directory::bulk_rename`] only plans the renames, so they can be previewed and
+//! checked for conflicts; [`BulkRename::apply`] carries them out.
+
+use super::{
+    backend,
+    Directory,
+    FSError,
+    FSResult,
+};
+
+/// Why a planned rename cannot be carried out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenameConflict {
+    /// Another entry is renamed to the same name
+    Duplicate,
+    /// An entry that is not renamed already has the new name
+    Exists,
+    /// The new name is empty, `.`, `..` or contains `/`
+    InvalidName,
+}
+
+impl std::fmt::Display for RenameConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            Self::Duplicate => "another entry gets the same name",
+            Self::Exists => "the name is taken",
+            Self::InvalidName => "the name is not valid",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// A single rename planned by [`Directory::bulk_rename`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rename {
+    /// The current name
+    pub from:     String,
+    /// The new name
+    pub to:       String,
+    /// Why the rename cannot be carried out, if it cannot
+    pub conflict: Option<RenameConflict>,
+}
+
+/// The renames planned by [`Directory::bulk_rename`], sorted by the current name.
+/// Its [`Display`](std::fmt::Display) implementation lists them as `old -> new`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BulkRename {
+    /// The directory the entries are in
+    directory: std::path::PathBuf,
+    /// The planned renames
+    renames:   Vec<Rename>,
+}
+
+impl BulkRename {
+    /// The planned renames.
+    #[must_use]
+    pub fn renames(&self) -> &[Rename] { &self.renames }
+
+    /// Whether any rename has a conflict, in which case [`BulkRename::apply`]
+    /// refuses to run.
+    #[must_use]
+    pub fn has_conflicts(&self) -> bool {
+        self.renames.iter().any(|rename| rename.conflict.is_some())
+    }
+
+    /// Carry out the renames. Entries are first moved to temporary names, so that
+    /// renaming `a` to `b` and `b` to `c` (or swapping names) works. Returns the
+    /// number of renamed entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::AlreadyExists`] without renaming anything if a rename has a
+    /// conflict, or an error if renaming fails.
+    pub fn apply(&self) -> FSResult<usize> {
+        if self.has_conflicts() {
+            return Err(FSError::AlreadyExists);
+        }
+        log::debug!(
+            "Renaming {} entries in '{}'",
+            self.renames.len(),
+            self.directory.display()
+        );
+        let temporary = |index: usize| {
+            self.directory
+                .join(format!(".rush-rename-{}-{index}", std::process::id()))
+        };
+        for (index, rename) in self.renames.iter().enumerate() {
+            let from = self.directory.join(&rename.from);
+            backend::with(|backend| backend.rename(&from, &temporary(index)))?;
+        }
+        for (index, rename) in self.renames.iter().enumerate() {
+            let to = self.directory.join(&rename.to);
+            backend::with(|backend| backend.rename(&temporary(index), &to))?;
+            log::trace!("Renamed '{}' to '{}'", rename.from, rename.to);
+        }
+        Ok(self.renames.len())
+    }
+}
+
+impl std::fmt::Display for BulkRename {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for rename in &self.renames {
+            write!(f, "{} -> {}", rename.from, rename.to)?;
+            if let Some(conflict) = rename.conflict {
+                write!(f, " ({conflict})")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Directory {
+    /// Plan renaming the entries directly in this directory whose name matches the
+    /// regular expression `pattern`.
+    ///
+    /// The first match in a name is replaced with `replacement`, in which `$1` or
+    /// `${name}` refer to capture groups (see [`regex::Regex::replace`]). Entries
+    /// whose name does not change are left out. Nothing is renamed until
+    /// [`BulkRename::apply`] is called.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let photos = Directory::new("/photos");
+    /// photos.create_on_fs().unwrap();
+    /// File::new("/photos/IMG_0001.JPG").create_on_fs().unwrap();
+    /// let renames = photos
+    ///     .bulk_rename(r"^IMG_(\d+)\.JPG$", "holiday-$1.jpg")
+    ///     .unwrap();
+    /// assert_eq!(renames.to_string(), "IMG_0001.JPG -> holiday-0001.jpg\n");
+    /// renames.apply().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regular expression or if
+    /// reading this directory fails.
+    pub fn bulk_rename(&self, pattern: &str, replacement: &str) -> FSResult<BulkRename> {
+        let pattern = regex::Regex::new(pattern)
+            .map_err(|error| FSError::InvalidPattern(error.to_string()))?;
+        let mut names: Vec<String> = backend::with(|backend| backend.read_dir(&self.path))?
+            .iter()
+            .filter_map(|entry| entry.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+
+        let mut renames: Vec<Rename> = names
+            .iter()
+            .filter_map(|name| {
+                let to = pattern.replace(name, replacement);
+                (to != name.as_str()).then(|| Rename {
+                    from:     name.clone(),
+                    to:       to.into_owned(),
+                    conflict: None,
+                })
+            })
+            .collect();
+
+        let sources: std::collections::BTreeSet<String> =
+            renames.iter().map(|rename| rename.from.clone()).collect();
+        let mut targets = std::collections::BTreeMap::<String, usize>::new();
+        for rename in &renames {
+            *targets.entry(rename.to.clone()).or_default() += 1;
+        }
+        for rename in &mut renames {
+            rename.conflict =
+                if matches!(rename.to.as_str(), "" | "." | "..") || rename.to.contains('/') {
+                    Some(RenameConflict::InvalidName)
+                } else if targets.get(&rename.to).is_some_and(|count| *count > 1) {
+                    Some(RenameConflict::Duplicate)
+                } else if names.binary_search(&rename.to).is_ok() && !sources.contains(&rename.to) {
+                    Some(RenameConflict::Exists)
+                } else {
+                    None
+                };
+        }
+        Ok(BulkRename {
+            directory: self.path.clone(),
+            renames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod rename_test {
+    use super::{
+        super::{
+            generate_test_path,
+            File,
+            MemoryBackend,
+            Object as _,
+        },
+        *,
+    };
+
+    #[test]
+    fn plan_and_apply() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let directory = Directory::new(generate_test_path());
+        directory.create_on_fs_recursive()?;
+        let _files = ["app.log", "app.log.old", "b.md", "notes.txt"]
+            .into_iter()
+            .map(|name| {
+                let file = File::new(directory.path().join(name));
+                file.write_new(name).map(|()| file)
+            })
+            .collect::<FSResult<Vec<_>>>()?;
+
+        let conflicts = directory.bulk_rename(r"^(b|app\.log)\.(md|old)$", "notes.txt")?;
+        assert!(conflicts.has_conflicts());
+        assert_eq!(
+            conflicts.to_string(),
+            "app.log.old -> notes.txt (another entry gets the same name)\nb.md -> notes.txt \
+             (another entry gets the same name)\n"
+        );
+        assert_eq!(conflicts.apply(), Err(FSError::AlreadyExists));
+        let taken = directory.bulk_rename(r"^b\.md$", "notes.txt")?;
+        assert_eq!(taken.renames()[0].conflict, Some(RenameConflict::Exists));
+        let invalid = directory.bulk_rename(r"^b\.md$", "sub/b.md")?;
+        assert_eq!(
+            invalid.renames()[0].conflict,
+            Some(RenameConflict::InvalidName)
+        );
+        assert!(matches!(
+            directory.bulk_rename("(", ""),
+            Err(FSError::InvalidPattern(_))
+        ));
+
+        // The new name of one entry is the old name of the next one.
+        let rotate = directory.bulk_rename(r"^app\.log(\.old)?$", "app.log.old$1")?;
+        assert_eq!(
+            rotate.to_string(),
+            "app.log -> app.log.old\napp.log.old -> app.log.old.old\n"
+        );
+        assert_eq!(rotate.apply()?, 2);
+        assert_eq!(
+            File::new(directory.path().join("app.log.old")).read()?,
+            "app.log"
+        );
+        assert_eq!(
+            File::new(directory.path().join("app.log.old.old")).read()?,
+            "app.log.old"
+        );
+        assert!(directory.bulk_rename("^nothing$", "")?.renames().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! This module contains splitting files into numbered parts and joining them again,
+//! the library equivalents of `split` and `cat`, e.g. to move huge artifacts through
+//! channels that limit the size of files.
+//!
+//! Parts are named after the file with a zero-padded number appended, e.g.
+//! `backup.tar.001`, `backup.tar.002`, ..., so that sorting them by name restores
+//! their order.
+
+use super::{
+    backend,
+    Directory,
+    FSError,
+    FSResult,
+    File,
+    Object as _,
+};
+
+/// How many bytes are read and written at once.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// How [`File::split`] cuts a file into parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SplitBy {
+    /// Parts of (at most) this many bytes, at least 1
+    Size(u64),
+    /// This many parts of equal size (the last one may be smaller), at least 1.
+    /// Files smaller than this many bytes result in fewer parts.
+    Count(u64),
+}
+
+/// Copy everything `reader` yields to the file at `target`, whose content is
+/// replaced, reading at most `limit` bytes. Returns the number of bytes copied.
+fn copy_into(
+    reader: &mut dyn std::io::Read,
+    target: &std::path::Path,
+    limit: u64,
+    buffer: &mut [u8],
+) -> FSResult<u64> {
+    backend::with(|backend| backend.write(target, &[], false))?;
+    let mut copied = 0;
+    while copied < limit {
+        let wanted = usize::try_from(limit - copied)
+            .map_or(buffer.len(), |remaining| remaining.min(buffer.len()));
+        let read = reader.read(&mut buffer[..wanted])?;
+        if read == 0 {
+            break;
+        }
+        backend::with(|backend| backend.write(target, &buffer[..read], true))?;
+        copied += read as u64;
+    }
+    Ok(copied)
+}
+
+impl File {
+    /// Split this file into numbered parts next to it, see the
+    /// [module documentation](self). Existing parts are overwritten. An empty file
+    /// results in a single, empty part, so that [`File::concat`] restores it.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let artifact = File::new("/artifact.bin");
+    /// artifact.write_new("0123456789").unwrap();
+    /// let parts = artifact.split(fs::SplitBy::Size(4)).unwrap();
+    /// assert_eq!(parts[2].path(), std::path::Path::new("/artifact.bin.003"));
+    /// assert_eq!(parts[2].read().unwrap(), "89");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading this file or writing a part fails.
+    pub fn split(&self, by: SplitBy) -> FSResult<Vec<Self>> {
+        let length = backend::with(|backend| backend.len(&self.path))?;
+        let chunk = match by {
+            SplitBy::Size(size) => size.max(1),
+            SplitBy::Count(count) => length.div_ceil(count.max(1)).max(1),
+        };
+        let count = length.div_ceil(chunk).max(1);
+        log::debug!("Splitting file {} into {} parts", self, count);
+
+        let name = self
+            .path
+            .file_name()
+            .ok_or(FSError::TypeMismatch(super::ObjectType::Unknown))?
+            .to_string_lossy()
+            .into_owned();
+        let width = count.to_string().len().max(3);
+        let mut reader = backend::with(|backend| backend.open(&self.path))?;
+        let buffer_size =
+            usize::try_from(chunk).map_or(BUFFER_SIZE, |chunk| chunk.min(BUFFER_SIZE));
+        let mut buffer = vec![0; buffer_size];
+        let mut parts = Vec::new();
+        for number in 1..=count {
+            let part = self.path.with_file_name(format!("{name}.{number:0width$}"));
+            copy_into(reader.as_mut(), &part, chunk, &mut buffer)?;
+            log::trace!("Wrote part '{}'", part.display());
+            parts.push(Self::new(part));
+        }
+        Ok(parts)
+    }
+
+    /// Write the contents of `parts`, in the given order, to this file, replacing
+    /// its content atomically (see [`File::write_atomic`]), so this file may be one
+    /// of `parts`. Returns the number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading a part or writing this file fails. This file is
+    /// left untouched then.
+    pub fn concat<P: AsRef<std::path::Path>>(
+        &self,
+        parts: impl IntoIterator<Item = P>,
+    ) -> FSResult<u64> {
+        log::debug!("Concatenating parts into file {}", self);
+        let mut written = 0;
+        super::write_atomic_with(&self.path, None, |writer| -> FSResult<()> {
+            for part in parts {
+                log::trace!("Appending part '{}'", part.as_ref().display());
+                let mut reader = backend::with(|backend| backend.open(part.as_ref()))?;
+                written += std::io::copy(&mut reader, writer)?;
+            }
+            Ok(())
+        })?;
+        Ok(written)
+    }
+}
+
+impl Directory {
+    /// Concatenate the files directly in this directory whose name matches the
+    /// glob `pattern` (see [`rush_core::glob::matches`]), sorted by name, into
+    /// `output`, see [`File::concat`]. `output` itself is never one of the parts.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let artifact = File::new("/artifact.bin");
+    /// artifact.write_new("0123456789").unwrap();
+    /// artifact.split(fs::SplitBy::Count(3)).unwrap();
+    /// let joined = Directory::new("/")
+    ///     .concat_matching("artifact.bin.*", "/joined.bin")
+    ///     .unwrap();
+    /// assert_eq!(joined.read().unwrap(), "0123456789");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if no file matches `pattern`, or an error if
+    /// reading this directory or a part or writing `output` fails.
+    pub fn concat_matching(
+        &self,
+        pattern: &str,
+        output: impl AsRef<std::path::Path>,
+    ) -> FSResult<File> {
+        let output = output.as_ref();
+        let normalized_output = backend::normalize(output);
+        let mut parts = backend::with(|backend| {
+            backend.read_dir(&self.path).map(|entries| {
+                entries
+                    .into_iter()
+                    .filter(|entry| {
+                        backend.object_type(entry) == Some(super::ObjectType::File)
+                            && backend::normalize(entry) != normalized_output
+                            && entry.file_name().is_some_and(|name| {
+                                rush_core::glob::matches(pattern, &name.to_string_lossy())
+                            })
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })?;
+        if parts.is_empty() {
+            return Err(FSError::NonExistent);
+        }
+        parts.sort();
+
+        let file = File::new(output);
+        file.concat(parts)?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod split_test {
+    use super::{
+        super::MemoryBackend,
+        *,
+    };
+
+    #[test]
+    fn split_and_concat() -> FSResult<()> {
+        let memory = MemoryBackend::install();
+        let directory = Directory::new(super::super::generate_test_path());
+        directory.create_on_fs_recursive()?;
+        let file = File::new(directory.path().join("data"));
+        file.write_new("0123456789")?;
+
+        let parts = file.split(SplitBy::Size(4))?;
+        let contents = parts.iter().map(File::read).collect::<FSResult<Vec<_>>>()?;
+        assert_eq!(contents, ["0123", "4567", "89"]);
+        assert!(parts[0].path().ends_with("data.001"));
+        assert_eq!(file.split(SplitBy::Count(3))?.len(), 3);
+        let bytes = file.split(SplitBy::Count(20))?;
+        assert_eq!(bytes.len(), 10);
+        assert!(bytes[9].path().ends_with("data.010"));
+
+        let joined = directory.concat_matching("data.0*", directory.path().join("data.00x"))?;
+        assert_eq!(joined.read()?, "0123456789");
+        assert!(matches!(
+            directory.concat_matching("missing.*", directory.path().join("out")),
+            Err(FSError::NonExistent)
+        ));
+
+        // The output may be one of the parts.
+        assert_eq!(bytes[0].concat([bytes[0].path(), file.path()])?, 11);
+        assert_eq!(bytes[0].read()?, "00123456789");
+        assert!(file.concat([directory.path().join("missing")]).is_err());
+        assert_eq!(file.read()?, "0123456789");
+        assert!(!memory.paths().iter().any(|path| path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(".rush-"))));
+
+        let empty = File::new(directory.path().join("empty"));
+        empty.create_on_fs()?;
+        assert_eq!(empty.split(SplitBy::Count(4))?.len(), 1);
+        Ok(())
+    }
+}
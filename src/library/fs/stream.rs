@@ -0,0 +1,208 @@
+//! This module contains buffered handles for reading and writing files piece by
+//! piece ([`Reader`] and [`Writer`]), so that large files can be processed without
+//! holding their whole content in memory.
+//!
+//! The handles implement [`std::io::Read`], [`std::io::BufRead`] and
+//! [`std::io::Write`]. Their errors carry the [`FSError`] describing the failure,
+//! which is recovered when converting them with `?` or [`FSError::from`].
+//!
+//! ```
+//! # use rush::prelude::*;
+//! use std::io::{
+//!     BufRead as _,
+//!     Write as _,
+//! };
+//!
+//! let _memory = fs::MemoryBackend::install();
+//! let mut writer = File::new("/numbers.txt").open_writer(false).unwrap();
+//! for number in 0..1000 {
+//!     writeln!(writer, "{number}").unwrap();
+//! }
+//! writer.finish().unwrap();
+//!
+//! let reader = File::new("/numbers.txt").open_reader().unwrap();
+//! assert_eq!(reader.lines().count(), 1000);
+//! ```
+
+use super::{
+    backend,
+    FSError,
+    FSResult,
+    File,
+    Object as _,
+};
+
+/// How many bytes are buffered before they are written to the file.
+const WRITE_BUFFER: usize = 64 * 1024;
+
+/// Wrap `error` so that it carries the [`FSError`] describing it.
+fn wrap(error: std::io::Error) -> std::io::Error { std::io::Error::other(FSError::from(error)) }
+
+/// A buffered handle reading a file, see [`File::open_reader`].
+pub struct Reader {
+    /// The path of the file being read
+    path:  std::path::PathBuf,
+    /// The buffered content of the file
+    inner: std::io::BufReader<Box<dyn std::io::Read>>,
+}
+
+impl std::fmt::Debug for Reader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reader")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::io::Read for Reader {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buffer).map_err(wrap)
+    }
+}
+
+impl std::io::BufRead for Reader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> { self.inner.fill_buf().map_err(wrap) }
+
+    fn consume(&mut self, amount: usize) { self.inner.consume(amount); }
+}
+
+/// Appends everything written to it to a file through the active backend.
+struct Sink {
+    /// The path of the file being written
+    path: std::path::PathBuf,
+}
+
+impl std::io::Write for Sink {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        backend::with(|backend| backend.write(&self.path, buffer, true)).map_err(wrap)?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+/// A buffered handle writing a file, see [`File::open_writer`].
+///
+/// Buffered content is written when the buffer is full, on [`flush`] and on
+/// [`Writer::finish`]. Dropping the writer writes it too, but errors are lost then.
+///
+/// [`flush`]: std::io::Write::flush
+pub struct Writer {
+    /// The buffered content that is not written yet
+    inner: std::io::BufWriter<Sink>,
+}
+
+impl std::fmt::Debug for Writer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Writer")
+            .field("path", &self.inner.get_ref().path)
+            .field("buffered", &self.inner.buffer().len())
+            .finish()
+    }
+}
+
+impl std::io::Write for Writer {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> { self.inner.write(buffer) }
+
+    fn flush(&mut self) -> std::io::Result<()> { self.inner.flush() }
+}
+
+impl Writer {
+    /// Write the remaining buffered content and close the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffered content cannot be written.
+    pub fn finish(mut self) -> FSResult<()> {
+        use std::io::Write as _;
+        Ok(self.inner.flush()?)
+    }
+}
+
+impl File {
+    /// Open the file for reading it piece by piece, e.g. line by line with
+    /// [`std::io::BufRead::lines`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::NonExistent`] if the file does not exist, or an error if it
+    /// cannot be opened.
+    pub fn open_reader(&self) -> FSResult<Reader> {
+        log::trace!("Opening {} for reading", self);
+        if !self.exists()? {
+            return Err(FSError::NonExistent);
+        }
+        let file = backend::with(|backend| backend.open(&self.path))?;
+        Ok(Reader {
+            path:  self.path.clone(),
+            inner: std::io::BufReader::new(file),
+        })
+    }
+
+    /// Open the file for writing it piece by piece. If `append` is `false`, the
+    /// file is emptied first. If the file does not exist yet, it is created.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path is not a file or the file cannot be written.
+    pub fn open_writer(&self, append: bool) -> FSResult<Writer> {
+        log::trace!("Opening {} for writing (append: {})", self, append);
+        self.exists()?;
+        // Create or empty the file right away, so that errors surface here.
+        backend::with(|backend| backend.write(&self.path, &[], append))?;
+        Ok(Writer {
+            inner: std::io::BufWriter::with_capacity(
+                WRITE_BUFFER,
+                Sink {
+                    path: self.path.clone(),
+                },
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod stream_test {
+    use super::*;
+    use crate::fs::MemoryBackend;
+    use std::io::{
+        BufRead as _,
+        Read as _,
+        Write as _,
+    };
+
+    #[test]
+    fn read_and_write() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let file = File::new("/large.txt");
+        let mut writer = file.open_writer(false)?;
+        for line in 0..20_000 {
+            writeln!(writer, "line {line}")?;
+        }
+        writer.finish()?;
+
+        let mut writer = file.open_writer(true)?;
+        writer.write_all(b"last")?;
+        drop(writer);
+
+        let mut lines = file.open_reader()?.lines();
+        assert_eq!(lines.next().transpose()?.as_deref(), Some("line 0"));
+        assert_eq!(lines.last().transpose()?.as_deref(), Some("last"));
+
+        file.open_writer(false)?.finish()?;
+        let mut content = Vec::new();
+        file.open_reader()?.read_to_end(&mut content)?;
+        assert!(content.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let _memory = MemoryBackend::install();
+        assert_eq!(
+            File::new("/missing.txt").open_reader().unwrap_err(),
+            FSError::NonExistent
+        );
+        assert!(File::new("/missing/file.txt").open_writer(true).is_err());
+    }
+}
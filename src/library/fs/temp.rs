@@ -0,0 +1,290 @@
+//! This module contains temporary files and directories, which delete themselves
+//! when they are dropped ([`TempFile`] and [`TempDir`]).
+//!
+//! ```
+//! # use rush::prelude::*;
+//! let directory = fs::TempDir::create().unwrap();
+//! let archive = fs::TempFile::create_in(directory.path()).unwrap();
+//! archive.overwrite("content").unwrap();
+//! let kept = archive.persist();
+//! assert!(kept.exists().unwrap());
+//! drop(directory);
+//! assert!(!kept.exists().unwrap());
+//! ```
+
+use super::{
+    backend,
+    Directory,
+    FSError,
+    FSResult,
+    File,
+    Object,
+    ObjectType,
+};
+
+/// How many attempts are made to find an unused path.
+const ATTEMPTS: usize = 16;
+
+/// Create an object with a new, random name in `directory` using `create`, which
+/// fails if the path exists already. Returns the path of the object. The name is
+/// hidden, so that programs scanning `directory` skip the object.
+fn create_unique(
+    directory: &std::path::Path,
+    create: impl Fn(&std::path::Path) -> std::io::Result<()>,
+) -> FSResult<std::path::PathBuf> {
+    for _ in 0..ATTEMPTS {
+        let name = crate::crypto::random::token_hex(8)
+            .map_err(|error| FSError::Unknown(error.to_string()))?;
+        let path = directory.join(format!(".rush-{name}"));
+        match create(&path) {
+            Ok(()) => return Ok(path),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {},
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Err(FSError::AlreadyExists)
+}
+
+/// A file that is deleted when it is dropped, unless it is [persisted](Self::persist).
+/// It dereferences to a [`File`] for reading and writing.
+#[derive(Debug)]
+pub struct TempFile {
+    /// The file; dropping it must not delete it a second time
+    file:    std::mem::ManuallyDrop<File>,
+    /// Whether the file is kept when this is dropped
+    persist: bool,
+}
+
+impl TempFile {
+    /// Create a new, empty file in the temporary directory of the system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create() -> FSResult<Self> { Self::create_in(std::env::temp_dir()) }
+
+    /// Create a new, empty file in `directory`, e.g. to rename it to its final name on
+    /// the same filesystem once it is complete. The file is only readable and
+    /// writable by its owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create_in(directory: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        let path = create_unique(directory.as_ref(), |path| {
+            backend::with(|backend| backend.create_new(path, 0o600))
+        })?;
+        log::trace!("Created temporary file '{}'", path.display());
+        Ok(Self::new(path))
+    }
+
+    /// Keep the file instead of deleting it when this is dropped.
+    #[must_use]
+    pub fn persist(mut self) -> File {
+        self.persist = true;
+        File::new(self.path())
+    }
+}
+
+impl std::ops::Deref for TempFile {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target { &self.file }
+}
+
+impl std::fmt::Display for TempFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { self.file.fmt(f) }
+}
+
+impl Object for TempFile {
+    const OBJECT_TYPE: ObjectType = ObjectType::File;
+
+    /// Take over the file at `path`, which is then deleted when this is dropped.
+    fn new(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            file:    std::mem::ManuallyDrop::new(File::new(path)),
+            persist: false,
+        }
+    }
+
+    fn path(&self) -> &std::path::PathBuf { self.file.path() }
+
+    fn path_mut(&mut self) -> &mut std::path::PathBuf { self.file.path_mut() }
+
+    fn exists(&self) -> FSResult<bool> { self.file.exists() }
+
+    fn create_on_fs(&self) -> FSResult<()> { self.file.create_on_fs() }
+
+    fn create_on_fs_recursive(&self) -> FSResult<()> { self.file.create_on_fs_recursive() }
+
+    fn delete_from_fs(&self) -> FSResult<()> { self.file.delete_from_fs() }
+
+    /// Move the file to `target`, where it is still deleted when this is dropped.
+    fn move_to(mut self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        let moved = File::new(self.path()).move_to(target)?;
+        self.file = std::mem::ManuallyDrop::new(moved);
+        Ok(self)
+    }
+
+    /// Copy the file to `target`. The copy is deleted when it is dropped, too.
+    fn copy_to(&self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        self.file.copy_to(&target)?;
+        Ok(Self::new(target))
+    }
+
+    fn exists_and_is_empty(&self) -> FSResult<bool> { self.file.exists_and_is_empty() }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.persist {
+            return;
+        }
+        if let Err(error) = self.file.delete_from_fs() {
+            log::warn!("Could not delete temporary file {}: {}", self, error);
+        }
+    }
+}
+
+/// A directory that is deleted with everything in it when it is dropped, unless it
+/// is [persisted](Self::persist). It dereferences to a [`Directory`].
+#[derive(Debug)]
+pub struct TempDir {
+    /// The directory
+    directory: Directory,
+    /// Whether the directory is kept when this is dropped
+    persist:   bool,
+}
+
+impl TempDir {
+    /// Create a new, empty directory in the temporary directory of the system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn create() -> FSResult<Self> { Self::create_in(std::env::temp_dir()) }
+
+    /// Create a new, empty directory in `directory`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn create_in(directory: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        let path = create_unique(directory.as_ref(), |path| {
+            backend::with(|backend| backend.create_dir(path))
+        })?;
+        log::trace!("Created temporary directory '{}'", path.display());
+        Ok(Self::new(path))
+    }
+
+    /// Keep the directory instead of deleting it when this is dropped.
+    #[must_use]
+    pub fn persist(mut self) -> Directory {
+        self.persist = true;
+        Directory::new(self.path())
+    }
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = Directory;
+
+    fn deref(&self) -> &Self::Target { &self.directory }
+}
+
+impl std::fmt::Display for TempDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { self.directory.fmt(f) }
+}
+
+impl Object for TempDir {
+    const OBJECT_TYPE: ObjectType = ObjectType::Directory;
+
+    /// Take over the directory at `path`, which is then deleted when this is dropped.
+    fn new(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            directory: Directory::new(path),
+            persist:   false,
+        }
+    }
+
+    fn path(&self) -> &std::path::PathBuf { self.directory.path() }
+
+    fn path_mut(&mut self) -> &mut std::path::PathBuf { self.directory.path_mut() }
+
+    fn exists(&self) -> FSResult<bool> { self.directory.exists() }
+
+    fn create_on_fs(&self) -> FSResult<()> { self.directory.create_on_fs() }
+
+    fn create_on_fs_recursive(&self) -> FSResult<()> { self.directory.create_on_fs_recursive() }
+
+    fn delete_from_fs(&self) -> FSResult<()> { self.directory.delete_from_fs() }
+
+    /// Move the directory to `target`, where it is still deleted when this is
+    /// dropped.
+    fn move_to(mut self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        self.directory = Directory::new(self.path()).move_to(target)?;
+        Ok(self)
+    }
+
+    /// Copy the directory to `target`. The copy is deleted when it is dropped, too.
+    fn copy_to(&self, target: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        self.directory.copy_to(&target)?;
+        Ok(Self::new(target))
+    }
+
+    fn exists_and_is_empty(&self) -> FSResult<bool> { self.directory.exists_and_is_empty() }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if self.persist {
+            return;
+        }
+        if let Err(error) = self.directory.delete_from_fs() {
+            log::warn!("Could not delete temporary directory {}: {}", self, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod temp_test {
+    use super::{
+        super::MemoryBackend,
+        *,
+    };
+
+    #[test]
+    fn cleanup_and_persist() -> FSResult<()> {
+        let directory = TempDir::create()?;
+        let path = directory.path().clone();
+        assert!(directory.exists_and_is_empty()?);
+
+        let file = TempFile::create_in(&path)?;
+        file.overwrite("content")?;
+        assert_eq!(file.read()?, "content");
+        let file_path = file.path().clone();
+        drop(file);
+        assert!(!file_path.exists());
+
+        let file = TempFile::create_in(&path)?;
+        let file = file.move_to(path.join("moved"))?;
+        assert!(path.join("moved").is_file());
+        let kept = file.persist();
+        assert!(path.join("moved").is_file());
+        std::mem::forget(kept);
+
+        let other = TempFile::create_in(&path)?;
+        assert_ne!(other.path(), &path.join("moved"));
+        drop(directory);
+        assert!(!path.exists());
+        // Objects that are gone already are fine to drop.
+        drop(other);
+
+        let _memory = MemoryBackend::install();
+        let directory = TempDir::create_in("/")?;
+        let path = directory.path().clone();
+        assert!(Directory::new(&path).exists()?);
+        drop(directory);
+        assert!(!Directory::new(&path).exists()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! This module contains creating files and changing the timestamps of objects like
+//! `touch` does ([`File::touch`] and [`Object::set_times`](super::Object::set_times)).
+
+use super::{
+    backend,
+    File,
+    FSResult,
+    Object as _,
+};
+
+/// Set the modification and/or access time of `path`; those that are [`None`] are
+/// kept.
+pub(super) fn write(
+    path: &std::path::Path,
+    modified: Option<std::time::SystemTime>,
+    accessed: Option<std::time::SystemTime>,
+) -> FSResult<()> {
+    log::trace!(
+        "Setting the timestamps of '{}' to {:?} (modified) and {:?} (accessed)",
+        path.display(),
+        modified,
+        accessed
+    );
+    backend::with(|backend| backend.set_times(path, accessed, modified))?;
+    Ok(())
+}
+
+impl File {
+    /// Create the file if it does not exist, and set its modification and access time
+    /// to now, like `touch` does. The content of an existing file is kept.
+    ///
+    /// ```
+    /// # use rush::prelude::*;
+    /// let _memory = fs::MemoryBackend::install();
+    /// let marker = File::new("/.bootstrapped");
+    /// marker.touch().unwrap();
+    /// assert!(marker.exists_and_is_empty().unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::TypeMismatch`](super::FSError::TypeMismatch) if something
+    /// other than a file exists at the path, or an error if the file cannot be created
+    /// (e.g. because its parent directory does not exist) or changed.
+    pub fn touch(&self) -> FSResult<()> {
+        log::trace!("Touching {}", self);
+        self.touch_if_absent()?;
+        let now = std::time::SystemTime::now();
+        write(&self.path, Some(now), Some(now))
+    }
+
+    /// Create the file, empty, if it does not exist. Unlike [`File::touch`], an
+    /// existing file is left alone, timestamps included. Returns whether the file was
+    /// created.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FSError::TypeMismatch`](super::FSError::TypeMismatch) if something
+    /// other than a file exists at the path, or an error if the file cannot be
+    /// created.
+    pub fn touch_if_absent(&self) -> FSResult<bool> {
+        if self.exists()? {
+            return Ok(false);
+        }
+        log::trace!("Creating empty file {}", self);
+        // Appending never truncates a file created in the meantime.
+        self.write_to_file("", true)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod times_test {
+    use super::{
+        super::{
+            generate_test_path,
+            Directory,
+            FSError,
+            MemoryBackend,
+        },
+        *,
+    };
+
+    #[test]
+    fn touch_and_set_times() -> FSResult<()> {
+        let root = generate_test_path();
+        std::fs::create_dir_all(&root)?;
+        std::fs::write(root.join("file"), "content")?;
+        let past = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+
+        let file = File::new(root.join("file"));
+        file.set_times(Some(past), Some(past))?;
+        assert_eq!(file.metadata()?.modified, Some(past));
+        assert_eq!(file.metadata()?.accessed, Some(past));
+        assert!(!file.touch_if_absent()?);
+        assert_eq!(file.metadata()?.modified, Some(past));
+        file.touch()?;
+        assert!(file.metadata()?.modified > Some(past));
+        assert_eq!(file.read()?, "content");
+        std::mem::forget(file);
+
+        let directory = Directory::new(&root);
+        directory.set_times(Some(past), None)?;
+        assert_eq!(directory.metadata()?.modified, Some(past));
+
+        let marker = File::new(root.join("marker"));
+        assert!(marker.touch_if_absent()?);
+        assert!(marker.exists_and_is_empty()?);
+        std::mem::forget(marker);
+        assert_eq!(
+            File::new(root.join("missing/marker")).touch(),
+            Err(FSError::NonExistent)
+        );
+        let not_a_file = File::new(&root);
+        assert!(matches!(not_a_file.touch(), Err(FSError::TypeMismatch(_))));
+        std::mem::forget(not_a_file);
+        std::fs::remove_dir_all(&root)?;
+
+        let _memory = MemoryBackend::install();
+        let marker = File::new("/marker");
+        marker.touch()?;
+        assert!(marker.exists_and_is_empty()?);
+        assert_eq!(
+            File::new("/missing").set_times(None, None),
+            Err(FSError::NonExistent)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+//! This module contains walking a directory tree recursively ([`walk`]), the library
+//! equivalent of `find`.
+
+use super::{
+    backend,
+    Directory,
+    Entry,
+    FSError,
+    FSResult,
+    File,
+    ObjectType,
+    SymbolicLink,
+};
+
+/// A predicate on the paths a [`Walk`] returns and what they are.
+type Filter = dyn Fn(&std::path::Path, ObjectType) -> bool;
+
+/// The entries below a directory, see [`walk`].
+///
+/// Directories are returned before their contents. Errors reading a directory are
+/// returned in place of its contents, and walking goes on with the next entry.
+pub struct Walk {
+    /// The directory that is walked
+    root:            std::path::PathBuf,
+    /// The depth of the shallowest entries that are returned
+    min_depth:       usize,
+    /// The depth of the deepest entries that are returned
+    max_depth:       usize,
+    /// Whether symbolic links are resolved
+    follow_symlinks: bool,
+    /// Whether hidden entries are skipped
+    skip_hidden:     bool,
+    /// Whether the entries of each directory are returned sorted by name
+    sorted:          bool,
+    /// Glob patterns of which returned entries have to match one
+    patterns:        Vec<String>,
+    /// Predicates returned entries have to satisfy
+    filters:         Vec<Box<Filter>>,
+    /// The directories being walked: the depth of their entries and the entries not
+    /// visited yet; [`None`] before walking started
+    stack:           Option<Vec<(usize, std::vec::IntoIter<std::path::PathBuf>)>>,
+    /// The directories visited so far when following symbolic links, to not walk
+    /// in circles
+    visited:         std::collections::HashSet<std::path::PathBuf>,
+    /// An error reading a directory, returned next
+    pending:         Option<FSError>,
+}
+
+impl std::fmt::Debug for Walk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Walk")
+            .field("root", &self.root)
+            .field("min_depth", &self.min_depth)
+            .field("max_depth", &self.max_depth)
+            .field("follow_symlinks", &self.follow_symlinks)
+            .field("skip_hidden", &self.skip_hidden)
+            .field("sorted", &self.sorted)
+            .field("patterns", &self.patterns)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Walk the directory tree below `path`, see [`Walk`].
+///
+/// The entries directly in `path` have depth 1; `path` itself is not returned. An
+/// empty `path` walks the current directory and returns relative paths. By
+/// default, everything is returned (including hidden entries) and symbolic links
+/// are not followed.
+///
+/// ```
+/// # use rush::prelude::*;
+/// let _memory = fs::MemoryBackend::install();
+/// File::new("/project/src/main.rs").create_on_fs_recursive().unwrap();
+/// File::new("/project/target/debug/build.rs").create_on_fs_recursive().unwrap();
+/// File::new("/project/.git/config").create_on_fs_recursive().unwrap();
+/// let sources: Vec<_> = fs::walk("/project")
+///     .skip_hidden(true)
+///     .matching("*.rs")
+///     .filter_entries(|path, _| !path.starts_with("/project/target"))
+///     .map(|entry| entry.unwrap().path().clone())
+///     .collect();
+/// assert_eq!(sources, [std::path::PathBuf::from("/project/src/main.rs")]);
+/// ```
+pub fn walk(path: impl AsRef<std::path::Path>) -> Walk {
+    Walk {
+        root:            path.as_ref().to_path_buf(),
+        min_depth:       1,
+        max_depth:       usize::MAX,
+        follow_symlinks: false,
+        skip_hidden:     false,
+        sorted:          false,
+        patterns:        Vec::new(),
+        filters:         Vec::new(),
+        stack:           None,
+        visited:         std::collections::HashSet::new(),
+        pending:         None,
+    }
+}
+
+/// Call `visit` with `root` and every path below it, each directory before its
+/// contents. Symbolic links below `root` are neither visited nor followed.
+///
+/// Unlike [`Walk`], this creates no objects and reads a directory only after
+/// `visit` returned for it, so `visit` may change the directory's permissions first.
+pub(super) fn visit_paths(
+    root: &std::path::Path,
+    mut visit: impl FnMut(&std::path::Path) -> FSResult<()>,
+) -> FSResult<()> {
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        visit(&path)?;
+        if backend::with(|backend| backend.object_type(&path)) != Some(ObjectType::Directory) {
+            continue;
+        }
+        for entry in backend::with(|backend| backend.read_dir(&path))? {
+            if backend::with(|backend| backend.read_link(&entry))?.is_none() {
+                pending.push(entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Walk {
+    /// Only return entries at least `depth` levels below the root, like `find
+    /// -mindepth`.
+    #[must_use]
+    pub const fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Neither return nor descend into entries more than `depth` levels below the
+    /// root, like `find -maxdepth`.
+    #[must_use]
+    pub const fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Whether symbolic links are resolved: links to directories are walked into
+    /// and links are returned as what they point to (links pointing nowhere stay
+    /// [`Entry::SymbolicLink`]). Directories are walked once, so circular links do
+    /// not lead to endless walks.
+    #[must_use]
+    pub const fn follow_symlinks(mut self, enabled: bool) -> Self {
+        self.follow_symlinks = enabled;
+        self
+    }
+
+    /// Whether hidden entries (names starting with `.`) are skipped, including
+    /// everything in hidden directories.
+    #[must_use]
+    pub const fn skip_hidden(mut self, enabled: bool) -> Self {
+        self.skip_hidden = enabled;
+        self
+    }
+
+    /// Return the entries of each directory sorted by name instead of in the order
+    /// the filesystem lists them, which is arbitrary.
+    #[must_use]
+    pub const fn sorted(mut self, enabled: bool) -> Self {
+        self.sorted = enabled;
+        self
+    }
+
+    /// Only return entries matching the glob `pattern`, or one of the patterns if
+    /// called several times. Patterns containing `/` are matched against the path
+    /// relative to the root, others against the name. Directories are walked into
+    /// regardless.
+    #[must_use]
+    pub fn matching(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Only return entries whose path and type satisfy `predicate`. Directories are
+    /// walked into regardless.
+    #[must_use]
+    pub fn filter_entries(
+        mut self,
+        predicate: impl Fn(&std::path::Path, ObjectType) -> bool + 'static,
+    ) -> Self {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// The paths in the directory `path`. An empty `path` is the current directory,
+    /// whose entries are returned as relative paths.
+    fn list(&self, path: &std::path::Path) -> FSResult<std::vec::IntoIter<std::path::PathBuf>> {
+        let mut paths = if path.as_os_str().is_empty() {
+            backend::with(|backend| backend.read_dir(std::path::Path::new(".")))?
+                .into_iter()
+                .filter_map(|path| path.file_name().map(std::path::PathBuf::from))
+                .collect()
+        } else {
+            backend::with(|backend| backend.read_dir(path))?
+        };
+        if self.sorted {
+            paths.sort();
+        }
+        Ok(paths.into_iter())
+    }
+
+    /// What `path` is, or [`None`] if it is neither file, directory nor link.
+    fn classify(&self, path: &std::path::Path) -> FSResult<Option<ObjectType>> {
+        let is_link = backend::with(|backend| backend.read_link(path))?.is_some();
+        if is_link && !self.follow_symlinks {
+            return Ok(Some(ObjectType::SymbolicLink));
+        }
+        Ok(match backend::with(|backend| backend.object_type(path)) {
+            Some(object_type @ (ObjectType::File | ObjectType::Directory)) => Some(object_type),
+            None if is_link => Some(ObjectType::SymbolicLink),
+            _ => None,
+        })
+    }
+
+    /// Whether the directory `path` has not been walked yet. Only tracked when
+    /// following symbolic links, as the tree is walked once otherwise.
+    fn first_visit(&mut self, path: &std::path::Path) -> bool {
+        if !self.follow_symlinks {
+            return true;
+        }
+        let canonical = backend::with(|backend| backend.canonicalize(path))
+            .unwrap_or_else(|_| path.to_path_buf());
+        self.visited.insert(canonical)
+    }
+
+    /// Whether `path`, which is an `object_type`, is returned. Checked before an
+    /// [`Entry`] is created, so entries that are not returned are never created.
+    fn wanted(&self, path: &std::path::Path, object_type: ObjectType) -> bool {
+        let matches = self.patterns.is_empty()
+            || self.patterns.iter().any(|pattern| {
+                let text = if pattern.contains('/') {
+                    path.strip_prefix(&self.root).unwrap_or(path)
+                } else {
+                    path.file_name().map_or(path, std::path::Path::new)
+                };
+                rush_core::glob::matches(pattern, &text.to_string_lossy())
+            });
+        matches && self.filters.iter().all(|filter| filter(path, object_type))
+    }
+}
+
+impl Iterator for Walk {
+    type Item = FSResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stack.is_none() {
+            log::trace!("Walking '{}'", self.root.display());
+            let root = self.root.clone();
+            self.first_visit(&root);
+            match self.list(&root) {
+                Ok(paths) => self.stack = Some(vec![(1, paths)]),
+                Err(error) => {
+                    self.stack = Some(Vec::new());
+                    return Some(Err(error));
+                },
+            }
+        }
+
+        loop {
+            if let Some(error) = self.pending.take() {
+                return Some(Err(error));
+            }
+            let stack = self.stack.as_mut()?;
+            let (depth, paths) = stack.last_mut()?;
+            let depth = *depth;
+            let Some(path) = paths.next() else {
+                stack.pop();
+                continue;
+            };
+            if self.skip_hidden
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+
+            let object_type = match self.classify(&path) {
+                Ok(Some(object_type)) => object_type,
+                Ok(None) => continue,
+                Err(error) => return Some(Err(error)),
+            };
+            if object_type == ObjectType::Directory
+                && depth < self.max_depth
+                && self.first_visit(&path)
+            {
+                match self.list(&path) {
+                    Ok(paths) => self
+                        .stack
+                        .get_or_insert_with(Vec::new)
+                        .push((depth + 1, paths)),
+                    Err(error) => self.pending = Some(error),
+                }
+            }
+            if depth >= self.min_depth && self.wanted(&path, object_type) {
+                return Some(Ok(match object_type {
+                    ObjectType::File => Entry::File(File { path }),
+                    ObjectType::Directory => Entry::Directory(Directory { path }),
+                    _ => Entry::SymbolicLink(SymbolicLink { path }),
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod walk_test {
+    use super::{
+        super::{
+            generate_test_path,
+            MemoryBackend,
+        },
+        *,
+    };
+
+    /// The paths of the entries `walk` returns, relative to `root`. The entries are
+    /// added to `kept`, as dropping a `File` deletes it in tests.
+    fn relative(
+        walk: Walk,
+        root: &std::path::Path,
+        kept: &mut Vec<Entry>,
+    ) -> FSResult<Vec<String>> {
+        let entries = walk.collect::<FSResult<Vec<_>>>()?;
+        let paths = entries
+            .iter()
+            .map(|entry| {
+                let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        kept.extend(entries);
+        Ok(paths)
+    }
+
+    #[test]
+    fn depth_hidden_and_filters() -> FSResult<()> {
+        let _memory = MemoryBackend::install();
+        let root = generate_test_path();
+        for file in ["a.rs", "src/b.rs", "src/deep/c.txt", ".hidden/d.rs"] {
+            let path = root.join(file);
+            backend::with(|backend| backend.create_dir_all(path.parent().unwrap_or(&root)))?;
+            backend::with(|backend| backend.write(&path, b"", false))?;
+        }
+        let mut kept = Vec::new();
+        let mut list = |walk: Walk| relative(walk.sorted(true), &root, &mut kept);
+
+        assert_eq!(
+            list(walk(&root))?,
+            [
+                ".hidden",
+                ".hidden/d.rs",
+                "a.rs",
+                "src",
+                "src/b.rs",
+                "src/deep",
+                "src/deep/c.txt"
+            ]
+        );
+        assert_eq!(
+            list(walk(&root).skip_hidden(true).max_depth(2))?,
+            ["a.rs", "src", "src/b.rs", "src/deep"]
+        );
+        assert_eq!(list(walk(&root).min_depth(3))?, ["src/deep/c.txt"]);
+        assert_eq!(
+            list(walk(&root).matching("*.rs"))?,
+            [".hidden/d.rs", "a.rs", "src/b.rs"]
+        );
+        assert_eq!(
+            list(walk(&root).matching("src/*"))?,
+            ["src/b.rs", "src/deep"]
+        );
+        assert_eq!(
+            list(
+                walk(&root).filter_entries(|_, object_type| object_type == ObjectType::Directory)
+            )?,
+            [".hidden", "src", "src/deep"]
+        );
+        assert!(walk(root.join("missing"))
+            .next()
+            .is_some_and(|entry| entry.is_err()));
+        drop(kept);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links() -> FSResult<()> {
+        let root = generate_test_path();
+        std::fs::create_dir_all(root.join("real"))?;
+        std::fs::write(root.join("real/file"), "")?;
+        std::os::unix::fs::symlink(&root, root.join("real/loop"))?;
+        std::os::unix::fs::symlink("missing", root.join("dangling"))?;
+        let mut kept = Vec::new();
+
+        assert_eq!(
+            relative(walk(&root).sorted(true), &root, &mut kept)?,
+            ["dangling", "real", "real/file", "real/loop"]
+        );
+        let entries = walk(&root)
+            .sorted(true)
+            .follow_symlinks(true)
+            .collect::<FSResult<Vec<_>>>()?;
+        // The link back to the root is returned as a directory but not walked.
+        assert_eq!(
+            entries.iter().map(Entry::object_type).collect::<Vec<_>>(),
+            [
+                ObjectType::SymbolicLink,
+                ObjectType::Directory,
+                ObjectType::File,
+                ObjectType::Directory,
+            ]
+        );
+
+        drop((kept, entries));
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,636 @@
+//! This module contains functionality for health checks: whether a port is open,
+//! a web page answers, a file is fresh, a disk has space left, a systemd unit is
+//! active or a process is running.
+//!
+//! Checks are declared in a [`Healthcheck`], in code or in JSON, and run in
+//! parallel. The [`Report`] aggregates their outcomes into a single [`Status`] with
+//! the exit codes of Nagios plugins, and renders as a table or as JSON for
+//! monitoring systems.
+//!
+//! ```no_run
+//! # use rush::health::{Check, Healthcheck, Severity};
+//! let report = Healthcheck::new()
+//!     .check("database", Check::Port(String::from("localhost:5432")))
+//!     .check("website", Check::http("https://example.com/health"))
+//!     .check("nginx", Check::Unit(String::from("nginx.service")))
+//!     .check_with(
+//!         "backups",
+//!         Check::file_fresh("/var/backups/last", std::time::Duration::from_secs(86_400)),
+//!         Severity::Warning,
+//!     )
+//!     .run();
+//! print!("{report}");
+//! std::process::exit(report.status().exit_code());
+//! ```
+
+/// How long network checks wait by default.
+const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Describes possible errors when declaring health checks.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum HealthError {
+    #[error("The health checks are not valid: {0}")]
+    Invalid(String),
+    #[error("Reading the health checks failed: {0}")]
+    FS(#[from] crate::fs::FSError),
+}
+
+/// A [`Result`] whose error variant is a [`HealthError`].
+pub type HealthResult<T> = Result<T, HealthError>;
+
+/// The outcome of a check, or of all checks together. The order is from best to
+/// worst.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The check passed
+    Ok,
+    /// A check of severity [`Severity::Warning`] failed
+    Warning,
+    /// The check could not be run, e.g. because a tool is missing
+    Unknown,
+    /// A check of severity [`Severity::Critical`] failed
+    Critical,
+}
+
+impl Status {
+    /// The exit code a Nagios plugin reports this status with.
+    #[must_use]
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Ok => 0,
+            Self::Warning => 1,
+            Self::Critical => 2,
+            Self::Unknown => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ok => write!(f, "OK"),
+            Self::Warning => write!(f, "WARNING"),
+            Self::Unknown => write!(f, "UNKNOWN"),
+            Self::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+/// How bad it is when a check fails.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The failure is reported as [`Status::Warning`]
+    Warning,
+    /// The failure is reported as [`Status::Critical`]
+    #[default]
+    Critical,
+}
+
+/// What is checked.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// A TCP connection to the address (`host:port`) can be established
+    Port(String),
+    /// A `GET` request to the URL is answered with the status (with the `http`
+    /// feature; otherwise the check cannot be run)
+    Http {
+        /// The URL to request
+        url:    String,
+        /// The expected status
+        #[serde(default = "default_http_status")]
+        status: u16,
+    },
+    /// The file exists and was modified at most this many seconds ago
+    FileFresh {
+        /// The file
+        path:            std::path::PathBuf,
+        /// The maximum age of the file, in seconds
+        max_age_seconds: u64,
+    },
+    /// The filesystem the path is on has at least this many bytes available
+    DiskFree {
+        /// A path on the filesystem, e.g. its mount point
+        path:                std::path::PathBuf,
+        /// The minimum space available to unprivileged users, in bytes
+        min_available_bytes: u64,
+    },
+    /// The systemd unit is active
+    Unit(String),
+    /// At least one process with the name (as in `/proc/<pid>/comm`) is running
+    Process(String),
+}
+
+/// The status [`Check::Http`] expects by default.
+const fn default_http_status() -> u16 { 200 }
+
+impl Check {
+    /// Check that a `GET` request to `url` is answered with status 200.
+    pub fn http(url: impl Into<String>) -> Self {
+        Self::Http {
+            url:    url.into(),
+            status: default_http_status(),
+        }
+    }
+
+    /// Check that the file at `path` was modified at most `max_age` ago.
+    pub fn file_fresh(path: impl AsRef<std::path::Path>, max_age: std::time::Duration) -> Self {
+        Self::FileFresh {
+            path:            path.as_ref().to_path_buf(),
+            max_age_seconds: max_age.as_secs(),
+        }
+    }
+
+    /// Check that the filesystem `path` is on has at least `bytes` available.
+    pub fn disk_free(path: impl AsRef<std::path::Path>, bytes: u64) -> Self {
+        Self::DiskFree {
+            path:                path.as_ref().to_path_buf(),
+            min_available_bytes: bytes,
+        }
+    }
+
+    /// Run the check. Returns whether it passed (or [`None`] if it could not be
+    /// run) and a message describing what was found.
+    fn run(&self, timeout: std::time::Duration) -> (Option<bool>, String) {
+        match self {
+            Self::Port(address) => check_port(address, timeout),
+            Self::Http { url, status } => check_http(url, *status, timeout),
+            Self::FileFresh {
+                path,
+                max_age_seconds,
+            } => check_file_fresh(path, *max_age_seconds),
+            Self::DiskFree {
+                path,
+                min_available_bytes,
+            } => crate::system::disk(path).map_or_else(
+                || {
+                    (
+                        None,
+                        format!("cannot determine the disk of '{}'", path.display()),
+                    )
+                },
+                |disk| {
+                    (
+                        Some(disk.available >= *min_available_bytes),
+                        format!(
+                            "{} bytes available on '{}'",
+                            disk.available,
+                            disk.mount_point.display()
+                        ),
+                    )
+                },
+            ),
+            Self::Unit(unit) => check_unit(unit),
+            Self::Process(name) => check_process(name),
+        }
+    }
+}
+
+/// Try to connect to `address`.
+fn check_port(address: &str, timeout: std::time::Duration) -> (Option<bool>, String) {
+    use std::net::ToSocketAddrs as _;
+
+    let addresses = match address.to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(error) => return (Some(false), format!("cannot resolve '{address}': {error}")),
+    };
+    let mut last_error = None;
+    for socket_address in addresses {
+        match std::net::TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(_) => return (Some(true), format!("connected to {socket_address}")),
+            Err(error) => last_error = Some(format!("{socket_address}: {error}")),
+        }
+    }
+    (
+        Some(false),
+        last_error.unwrap_or_else(|| format!("'{address}' has no addresses")),
+    )
+}
+
+/// Request `url` and compare the status with `expected`.
+#[cfg(feature = "http")]
+fn check_http(url: &str, expected: u16, timeout: std::time::Duration) -> (Option<bool>, String) {
+    let response = ureq::AgentBuilder::new()
+        .timeout_connect(timeout)
+        .timeout_read(timeout)
+        .build()
+        .get(url)
+        .call();
+    let status = match response {
+        Ok(response) => response.status(),
+        Err(ureq::Error::Status(status, _)) => status,
+        Err(ureq::Error::Transport(transport)) => {
+            return (
+                Some(false),
+                format!("requesting '{url}' failed: {transport}"),
+            )
+        },
+    };
+    (
+        Some(status == expected),
+        format!("'{url}' answered with status {status}"),
+    )
+}
+
+/// HTTP checks cannot be run without the `http` feature.
+#[cfg(not(feature = "http"))]
+fn check_http(url: &str, _expected: u16, _timeout: std::time::Duration) -> (Option<bool>, String) {
+    (
+        None,
+        format!("cannot request '{url}' without the `http` feature"),
+    )
+}
+
+/// Compare the modification time of `path` with `max_age_seconds`.
+fn check_file_fresh(path: &std::path::Path, max_age_seconds: u64) -> (Option<bool>, String) {
+    let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(error) => return (Some(false), format!("'{}': {error}", path.display())),
+    };
+    // Files modified in the future count as fresh.
+    let age = modified.elapsed().unwrap_or_default().as_secs();
+    (
+        Some(age <= max_age_seconds),
+        format!("'{}' was modified {age}s ago", path.display()),
+    )
+}
+
+/// Ask systemd whether `unit` is active.
+fn check_unit(unit: &str) -> (Option<bool>, String) {
+    match crate::process::Command::new("systemctl")
+        .args(["is-active", "--"])
+        .arg(unit)
+        .output()
+    {
+        // `systemctl is-active` exits with 0 only if the unit is active, and prints
+        // its state either way.
+        Ok(output) if !output.stdout.trim().is_empty() => (
+            Some(output.success()),
+            format!("'{unit}' is {}", output.stdout.trim()),
+        ),
+        Ok(output) => (None, format!("systemctl failed: {}", output.stderr.trim())),
+        Err(error) => (None, format!("cannot run systemctl: {error}")),
+    }
+}
+
+/// Count the processes called `name` in `/proc`.
+fn check_process(name: &str) -> (Option<bool>, String) {
+    // The kernel truncates names to 15 bytes.
+    let name = name.get(..15).unwrap_or(name);
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(error) => return (None, format!("cannot list processes: {error}")),
+    };
+    let count = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|pid| pid.bytes().all(|byte| byte.is_ascii_digit()))
+        })
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("comm")).ok())
+        .filter(|comm| comm.trim_end_matches('\n') == name)
+        .count();
+    (
+        Some(count > 0),
+        format!("{count} processes called '{name}' are running"),
+    )
+}
+
+/// A declared check, with its name and severity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+struct Entry {
+    /// The name of the check
+    name:     String,
+    /// What is checked
+    #[serde(flatten)]
+    check:    Check,
+    /// How bad it is when the check fails
+    #[serde(default)]
+    severity: Severity,
+}
+
+/// A set of checks that are run together.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Healthcheck {
+    /// The checks, in the order they are reported in
+    checks:          Vec<Entry>,
+    /// How long network checks wait, in seconds
+    #[serde(default)]
+    timeout_seconds: Option<u64>,
+}
+
+impl Healthcheck {
+    /// Create a set without checks.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Parse checks from JSON like
+    /// `{ "checks": [{ "name": "web", "http": { "url": "http://localhost" } }] }`.
+    /// Every check is an object with a `name`, one of the variants of [`Check`] and
+    /// optionally a `severity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON does not describe valid checks or if two checks
+    /// have the same name.
+    pub fn from_json(json: &str) -> HealthResult<Self> {
+        let healthcheck: Self =
+            serde_json::from_str(json).map_err(|error| HealthError::Invalid(error.to_string()))?;
+        let mut names = std::collections::BTreeSet::new();
+        for entry in &healthcheck.checks {
+            if !names.insert(&entry.name) {
+                return Err(HealthError::Invalid(format!(
+                    "the name '{}' is used more than once",
+                    entry.name
+                )));
+            }
+        }
+        Ok(healthcheck)
+    }
+
+    /// Read checks from the JSON file at `path`, see [`Healthcheck::from_json`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not describe valid checks.
+    pub fn load(path: impl AsRef<std::path::Path>) -> HealthResult<Self> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(crate::fs::FSError::from)?)
+    }
+
+    /// Add `check` called `name`, whose failure is critical.
+    #[must_use]
+    pub fn check(self, name: impl Into<String>, check: Check) -> Self {
+        self.check_with(name, check, Severity::Critical)
+    }
+
+    /// Add `check` called `name`, whose failure is as bad as `severity`.
+    #[must_use]
+    pub fn check_with(mut self, name: impl Into<String>, check: Check, severity: Severity) -> Self {
+        self.checks.push(Entry {
+            name: name.into(),
+            check,
+            severity,
+        });
+        self
+    }
+
+    /// Wait at most `timeout` for connections and responses (5 seconds by default).
+    #[must_use]
+    pub const fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout_seconds = Some(timeout.as_secs());
+        self
+    }
+
+    /// Run all checks in parallel and collect their outcomes.
+    #[must_use]
+    pub fn run(&self) -> Report {
+        let timeout = self
+            .timeout_seconds
+            .map_or(DEFAULT_TIMEOUT, std::time::Duration::from_secs);
+        let results = std::thread::scope(|scope| {
+            // All checks are started before the first one is waited for.
+            let mut running = Vec::with_capacity(self.checks.len());
+            for entry in &self.checks {
+                running.push((entry, scope.spawn(move || run_entry(entry, timeout))));
+            }
+            let mut results = Vec::with_capacity(running.len());
+            for (entry, handle) in running {
+                results.push(handle.join().unwrap_or_else(|_| CheckResult {
+                    name:     entry.name.clone(),
+                    status:   Status::Unknown,
+                    message:  String::from("the check panicked"),
+                    duration: std::time::Duration::ZERO,
+                }));
+            }
+            results
+        });
+        Report { results }
+    }
+}
+
+/// Run the check of `entry` and rate the outcome by its severity.
+fn run_entry(entry: &Entry, timeout: std::time::Duration) -> CheckResult {
+    let started = std::time::Instant::now();
+    let (passed, message) = entry.check.run(timeout);
+    let status = match (passed, entry.severity) {
+        (Some(true), _) => Status::Ok,
+        (Some(false), Severity::Warning) => Status::Warning,
+        (Some(false), Severity::Critical) => Status::Critical,
+        (None, _) => Status::Unknown,
+    };
+    log::debug!("Health check '{}': {status} ({message})", entry.name);
+    CheckResult {
+        name: entry.name.clone(),
+        status,
+        message,
+        duration: started.elapsed(),
+    }
+}
+
+/// Serialize a duration as fractional seconds.
+fn serialize_seconds<S: serde::Serializer>(
+    duration: &std::time::Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct CheckResult {
+    /// The name of the check
+    pub name:     String,
+    /// The outcome
+    pub status:   Status,
+    /// What was found, e.g. `'nginx.service' is failed`
+    pub message:  String,
+    /// How long the check took
+    #[serde(rename = "duration_seconds", serialize_with = "serialize_seconds")]
+    pub duration: std::time::Duration,
+}
+
+/// The outcomes of all checks of a [`Healthcheck`]. It renders as a table with
+/// [`std::fmt::Display`] and as JSON with [`Report::to_json`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Report {
+    /// The outcomes, in the order the checks were declared in
+    pub results: Vec<CheckResult>,
+}
+
+impl Report {
+    /// The worst status of all checks, or [`Status::Ok`] if there are none.
+    #[must_use]
+    pub fn status(&self) -> Status {
+        self.results
+            .iter()
+            .map(|result| result.status)
+            .max()
+            .unwrap_or(Status::Ok)
+    }
+
+    /// The outcomes of the checks that did not pass.
+    pub fn failed(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results
+            .iter()
+            .filter(|result| result.status != Status::Ok)
+    }
+
+    /// The overall status and all outcomes as JSON, e.g.
+    /// `{"status": "critical", "checks": [{"name": "web", "status": "critical",
+    /// "message": "...", "duration_seconds": 0.1}]}`.
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": self.status(),
+            "checks": self.results,
+        })
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .results
+            .iter()
+            .map(|result| result.name.chars().count())
+            .chain(std::iter::once("CHECK".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:width$}  STATUS    DURATION  MESSAGE", "CHECK")?;
+        for result in &self.results {
+            writeln!(
+                f,
+                "{:width$}  {:8}  {:>7.1}s  {}",
+                result.name,
+                result.status.to_string(),
+                result.duration.as_secs_f64(),
+                result.message
+            )?;
+        }
+        writeln!(
+            f,
+            "\n{}: {} of {} checks passed.",
+            self.status(),
+            self.results.len() - self.failed().count(),
+            self.results.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod health_test {
+    use super::*;
+
+    #[test]
+    fn declare() -> HealthResult<()> {
+        let healthcheck = Healthcheck::from_json(
+            r#"{
+                "checks": [
+                    { "name": "db", "port": "localhost:5432" },
+                    { "name": "web", "http": { "url": "http://localhost" } },
+                    {
+                        "name": "backup",
+                        "file_fresh": { "path": "/var/backups/last", "max_age_seconds": 90 },
+                        "severity": "warning"
+                    }
+                ],
+                "timeout_seconds": 2
+            }"#,
+        )?;
+        assert_eq!(
+            healthcheck,
+            Healthcheck::new()
+                .check("db", Check::Port(String::from("localhost:5432")))
+                .check("web", Check::http("http://localhost"))
+                .check_with(
+                    "backup",
+                    Check::file_fresh("/var/backups/last", std::time::Duration::from_secs(90)),
+                    Severity::Warning
+                )
+                .timeout(std::time::Duration::from_secs(2))
+        );
+
+        for invalid in [
+            r#"{ "checks": [{ "name": "db", "ping": "localhost" }] }"#,
+            r#"{ "checks": [{ "name": "a", "unit": "a" }, { "name": "a", "unit": "b" }] }"#,
+        ] {
+            assert!(matches!(
+                Healthcheck::from_json(invalid),
+                Err(HealthError::Invalid(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn run() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open = listener.local_addr().unwrap().to_string();
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let fresh = crate::fs::generate_test_path();
+        std::fs::write(&fresh, "").unwrap();
+        let this_process = std::fs::read_to_string("/proc/self/comm").unwrap();
+
+        let report = Healthcheck::new()
+            .check("open", Check::Port(open))
+            .check(
+                "fresh",
+                Check::file_fresh(&fresh, std::time::Duration::from_secs(90)),
+            )
+            .check("disk", Check::disk_free("/", 0))
+            .check("process", Check::Process(this_process.trim().to_string()))
+            .run();
+        assert_eq!(report.status(), Status::Ok);
+        assert_eq!(report.failed().count(), 0);
+
+        let report = Healthcheck::new()
+            .check("open", Check::Port(closed.clone()))
+            .check_with(
+                "missing",
+                Check::Process(String::from("no-such-process")),
+                Severity::Warning,
+            )
+            .check("web", Check::http(format!("http://{closed}/")))
+            .check("full", Check::disk_free("/", u64::MAX))
+            .timeout(std::time::Duration::from_secs(1))
+            .run();
+        let statuses: Vec<Status> = report.results.iter().map(|result| result.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                Status::Critical,
+                Status::Warning,
+                if cfg!(feature = "http") {
+                    Status::Critical
+                } else {
+                    Status::Unknown
+                },
+                Status::Critical
+            ]
+        );
+        assert_eq!(report.status().exit_code(), 2);
+        assert!(report
+            .to_string()
+            .contains("CRITICAL: 0 of 4 checks passed."));
+
+        let json = report.to_json();
+        assert_eq!(json["status"], "critical");
+        assert_eq!(json["checks"][1]["name"], "missing");
+        assert_eq!(json["checks"][1]["status"], "warning");
+        assert!(json["checks"][1]["duration_seconds"].is_f64());
+
+        std::fs::remove_file(fresh).unwrap();
+    }
+}
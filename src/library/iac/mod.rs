@@ -0,0 +1,4 @@
+//! This module contains functionality for provisioning infrastructure with
+//! infrastructure-as-code tools.
+
+pub mod terraform;
@@ -0,0 +1,235 @@
+//! This module contains functionality for running [Terraform](https://www.terraform.io)
+//! or [OpenTofu](https://opentofu.org), which need to be installed.
+//!
+//! All commands run non-interactively: input prompts are disabled and changes are
+//! applied without asking for approval, since nobody is there to answer.
+
+use crate::process::{
+    Command,
+    ProcessError,
+};
+
+/// Describes possible errors when running Terraform.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum TerraformError {
+    #[error("Running Terraform failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("The configuration has no output '{0}'")]
+    MissingOutput(String),
+    #[error("The output of Terraform could not be understood: {0}")]
+    InvalidOutput(String),
+}
+
+/// A [`Result`] whose error variant is a [`TerraformError`].
+pub type TerraformResult<T> = Result<T, TerraformError>;
+
+/// What a plan would do to the infrastructure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Plan {
+    /// The infrastructure matches the configuration
+    NoChanges,
+    /// Applying the plan would change the infrastructure
+    Changes,
+}
+
+/// An output value of the configuration.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct Output {
+    /// The value
+    pub value:     serde_json::Value,
+    /// Whether the value is marked as sensitive, i.e. is hidden in Terraform's
+    /// human-readable output
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+/// Runs Terraform (or its fork `tofu`) in a directory containing a configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Terraform {
+    /// The program to run, `terraform` or `tofu`
+    program:   String,
+    /// The directory containing the configuration
+    directory: std::path::PathBuf,
+    /// Input variables passed to `plan` and `apply`
+    variables: Vec<(String, String)>,
+    /// Files with input variables passed to `plan` and `apply`
+    var_files: Vec<std::path::PathBuf>,
+}
+
+impl Terraform {
+    /// Run `terraform` on the configuration in `directory`.
+    pub fn new(directory: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            program:   String::from("terraform"),
+            directory: directory.as_ref().to_path_buf(),
+            variables: Vec::new(),
+            var_files: Vec::new(),
+        }
+    }
+
+    /// Run `tofu` instead of `terraform` on the configuration in `directory`.
+    pub fn open_tofu(directory: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            program: String::from("tofu"),
+            ..Self::new(directory)
+        }
+    }
+
+    /// Set the input variable `name` to `value`.
+    #[must_use]
+    pub fn variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables.push((name.into(), value.into()));
+        self
+    }
+
+    /// Read input variables from the `.tfvars` file at `path`.
+    #[must_use]
+    pub fn var_file(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.var_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// An invocation of `subcommand` in the configuration's directory.
+    fn command(&self, subcommand: &str) -> Command {
+        Command::new(&self.program)
+            .current_dir(&self.directory)
+            .args([subcommand, "-input=false", "-no-color"])
+    }
+
+    /// The arguments passing the input variables.
+    fn variable_arguments(&self) -> Vec<String> {
+        self.var_files
+            .iter()
+            .map(|path| format!("-var-file={}", path.display()))
+            .chain(
+                self.variables
+                    .iter()
+                    .map(|(name, value)| format!("-var={name}={value}")),
+            )
+            .collect()
+    }
+
+    /// Initialize the working directory, i.e. install providers and modules and
+    /// configure the backend.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Terraform cannot be run or fails.
+    pub fn init(&self) -> TerraformResult<()> {
+        log::debug!("Initializing '{}'", self.directory.display());
+        self.command("init").run()?;
+        Ok(())
+    }
+
+    /// Compute which changes applying the configuration would make. If `out` is
+    /// given, the plan is saved there so that exactly this plan can be applied with
+    /// [`Terraform::apply`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Terraform cannot be run or fails.
+    pub fn plan(&self, out: Option<&std::path::Path>) -> TerraformResult<Plan> {
+        log::debug!("Planning '{}'", self.directory.display());
+        let mut command = self
+            .command("plan")
+            .arg("-detailed-exitcode")
+            .args(self.variable_arguments());
+        if let Some(out) = out {
+            command = command.arg(format!("-out={}", out.display()));
+        }
+
+        // With `-detailed-exitcode`, 2 means success with changes.
+        let output = command.output()?;
+        match output.code {
+            Some(0) => Ok(Plan::NoChanges),
+            Some(2) => Ok(Plan::Changes),
+            code => Err(ProcessError::Failed {
+                code,
+                stderr: output.stderr.trim().to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Apply the configuration without asking for approval. If `plan` is given, the
+    /// plan saved there by [`Terraform::plan`] is applied instead, and the input
+    /// variables are taken from it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Terraform cannot be run or fails.
+    pub fn apply(&self, plan: Option<&std::path::Path>) -> TerraformResult<()> {
+        log::debug!("Applying '{}'", self.directory.display());
+        let command = self.command("apply").arg("-auto-approve");
+        let command = match plan {
+            Some(plan) => command.arg(plan.display().to_string()),
+            None => command.args(self.variable_arguments()),
+        };
+        command.run()?;
+        Ok(())
+    }
+
+    /// All output values of the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Terraform cannot be run or fails.
+    pub fn outputs(&self) -> TerraformResult<std::collections::BTreeMap<String, Output>> {
+        let output = Command::new(&self.program)
+            .current_dir(&self.directory)
+            .args(["output", "-no-color", "-json"])
+            .run()?;
+        parse_outputs(&output.stdout)
+    }
+
+    /// The output value `name`, deserialized into `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Terraform cannot be run or fails, if there is no such
+    /// output or if it cannot be deserialized into `T`.
+    pub fn output<T: serde::de::DeserializeOwned>(&self, name: &str) -> TerraformResult<T> {
+        let output = self
+            .outputs()?
+            .remove(name)
+            .ok_or_else(|| TerraformError::MissingOutput(name.to_string()))?;
+        serde_json::from_value(output.value)
+            .map_err(|error| TerraformError::InvalidOutput(error.to_string()))
+    }
+}
+
+/// Parse the output of `terraform output -json`.
+fn parse_outputs(json: &str) -> TerraformResult<std::collections::BTreeMap<String, Output>> {
+    serde_json::from_str(json).map_err(|error| TerraformError::InvalidOutput(error.to_string()))
+}
+
+#[cfg(test)]
+mod terraform_test {
+    use super::*;
+
+    #[test]
+    fn arguments_and_outputs() -> TerraformResult<()> {
+        let terraform = Terraform::open_tofu("/srv/infra")
+            .var_file("prod.tfvars")
+            .variable("region", "eu-central-1");
+        assert_eq!(terraform.command("plan").program(), "tofu");
+        assert_eq!(
+            terraform.variable_arguments(),
+            ["-var-file=prod.tfvars", "-var=region=eu-central-1"]
+        );
+
+        let outputs = parse_outputs(
+            r#"{
+                "address": { "sensitive": false, "type": "string", "value": "10.0.0.1" },
+                "password": { "sensitive": true, "type": "string", "value": "hunter2" },
+                "ports": { "sensitive": false, "type": ["list", "number"], "value": [80, 443] }
+            }"#,
+        )?;
+        assert_eq!(outputs["address"].value, "10.0.0.1");
+        assert!(outputs["password"].sensitive);
+        let ports: Vec<u16> = serde_json::from_value(outputs["ports"].value.clone())
+            .map_err(|error| TerraformError::InvalidOutput(error.to_string()))?;
+        assert_eq!(ports, [80, 443]);
+        Ok(())
+    }
+}
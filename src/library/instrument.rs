@@ -0,0 +1,357 @@
+//! This module contains instrumentation hooks: subscribers receive an event for every
+//! filesystem, process and environment operation performed through this crate.
+//!
+//! Embedding applications use them for custom metrics and tracing spans, or to
+//! enforce strict-mode policies by panicking when an operation starts that is not
+//! allowed.
+//!
+//! ```
+//! use rush::instrument::{self, Phase, Subsystem};
+//!
+//! let _policy = instrument::subscribe(|event| {
+//!     if event.subsystem == Subsystem::FileSystem
+//!         && event.operation == "write"
+//!         && event.phase == Phase::Started
+//!         && !event.target.starts_with("/srv/app")
+//!     {
+//!         panic!("refusing to write to {}", event.target);
+//!     }
+//! });
+//! ```
+
+/// The part of this crate an operation was performed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Subsystem {
+    /// [`File`](crate::fs::File) and [`Directory`](crate::fs::Directory) operations
+    FileSystem,
+    /// Programs run with [`Command`](crate::process::Command)
+    Process,
+    /// Reading and exporting process environment variables
+    Environment,
+}
+
+impl std::fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::FileSystem => "fs",
+            Self::Process => "process",
+            Self::Environment => "environment",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Whether an operation is about to start or has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// The operation is about to start
+    Started,
+    /// The operation has finished
+    Finished {
+        /// How long the operation took
+        duration:  std::time::Duration,
+        /// Whether the operation succeeded
+        succeeded: bool,
+    },
+}
+
+/// An operation that is performed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// The part of this crate performing the operation
+    pub subsystem:   Subsystem,
+    /// The name of the operation, e.g. `write` or `run`
+    pub operation:   &'static str,
+    /// The path, quoted command line or variable name the operation is performed on
+    pub target:      String,
+    /// The second path of operations that have one, e.g. where a file is copied to
+    pub destination: Option<String>,
+    /// Whether the operation is about to start or has finished
+    pub phase:       Phase,
+    /// The thread performing the operation
+    pub thread:      std::thread::ThreadId,
+}
+
+/// A callback receiving events.
+type Callback = std::sync::Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// The subscribed callbacks, by identifier.
+static SUBSCRIBERS: std::sync::RwLock<Vec<(u64, Callback)>> = std::sync::RwLock::new(vec![]);
+/// The number of subscribed callbacks, so that operations are cheap without any.
+static SUBSCRIBED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+/// The identifier of the next subscription.
+static NEXT_IDENTIFIER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+thread_local! {
+    /// Whether this thread is running callbacks, whose own operations are not reported.
+    static NOTIFYING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Call `callback` for every operation from now on, on the thread performing the
+/// operation. Operations performed by callbacks themselves are not reported.
+///
+/// The callback is unsubscribed when the returned [`Subscription`] is dropped.
+pub fn subscribe(callback: impl Fn(&Event) + Send + Sync + 'static) -> Subscription {
+    use std::sync::atomic::Ordering;
+    let identifier = NEXT_IDENTIFIER.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut subscribers = SUBSCRIBERS
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        subscribers.push((identifier, std::sync::Arc::new(callback)));
+        SUBSCRIBED.store(subscribers.len(), Ordering::Release);
+    }
+    Subscription { identifier }
+}
+
+/// Pass `event` to every subscribed callback.
+fn notify(event: &Event) {
+    let callbacks = SUBSCRIBERS
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .iter()
+        .map(|(_, callback)| std::sync::Arc::clone(callback))
+        .collect::<Vec<_>>();
+    NOTIFYING.set(true);
+    // Reset the flag even if a callback panics, e.g. to enforce a policy.
+    let _reset = Reset;
+    for callback in callbacks {
+        callback(event);
+    }
+}
+
+/// Marks the end of [`notify`] when dropped.
+struct Reset;
+
+impl Drop for Reset {
+    fn drop(&mut self) { NOTIFYING.set(false); }
+}
+
+/// Perform `operation` of `subsystem` on `target` (and `destination`) with `run`,
+/// reporting it to the subscribed callbacks.
+pub(crate) fn observe<T, E>(
+    subsystem: Subsystem,
+    operation: &'static str,
+    target: &dyn std::fmt::Display,
+    destination: Option<&dyn std::fmt::Display>,
+    run: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    if SUBSCRIBED.load(std::sync::atomic::Ordering::Acquire) == 0 || NOTIFYING.get() {
+        return run();
+    }
+
+    let mut event = Event {
+        subsystem,
+        operation,
+        target: target.to_string(),
+        destination: destination.map(ToString::to_string),
+        phase: Phase::Started,
+        thread: std::thread::current().id(),
+    };
+    notify(&event);
+    let start = std::time::Instant::now();
+    let result = run();
+    event.phase = Phase::Finished {
+        duration:  start.elapsed(),
+        succeeded: result.is_ok(),
+    };
+    notify(&event);
+    result
+}
+
+/// A subscribed callback, see [`subscribe`]. Dropping it unsubscribes the callback.
+#[derive(Debug)]
+#[must_use = "the callback is unsubscribed when this is dropped"]
+pub struct Subscription {
+    /// The identifier of the callback
+    identifier: u64,
+}
+
+impl Subscription {
+    /// Keep the callback subscribed for the rest of the program.
+    pub const fn keep(self) { std::mem::forget(self); }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut subscribers = SUBSCRIBERS
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        subscribers.retain(|(identifier, _)| *identifier != self.identifier);
+        SUBSCRIBED.store(subscribers.len(), std::sync::atomic::Ordering::Release);
+    }
+}
+
+/// How often an operation was performed, and how long it took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Count {
+    /// The number of times the operation finished
+    pub calls:    u64,
+    /// The number of times the operation failed
+    pub failures: u64,
+    /// The time spent in the operation
+    pub duration: std::time::Duration,
+}
+
+/// Counters for every operation, kept up to date by a subscribed callback.
+///
+/// ```
+/// # use rush::prelude::*;
+/// use rush::instrument::{Counters, Subsystem};
+///
+/// let counters = Counters::subscribe();
+/// let _ = Directory::new("/nonexistent/parent/child").create_on_fs();
+/// assert!(counters.get(Subsystem::FileSystem, "create").failures >= 1);
+/// ```
+#[derive(Debug)]
+pub struct Counters {
+    /// The counts by subsystem and operation
+    counts:        std::sync::Arc<std::sync::Mutex<CountMap>>,
+    /// The subscription updating the counts
+    _subscription: Subscription,
+}
+
+/// The counts by subsystem and operation.
+type CountMap = std::collections::BTreeMap<(Subsystem, &'static str), Count>;
+
+impl Counters {
+    /// Start counting operations; counting stops when the counters are dropped.
+    #[must_use]
+    pub fn subscribe() -> Self {
+        let counts = std::sync::Arc::new(std::sync::Mutex::new(CountMap::new()));
+        let updated = std::sync::Arc::clone(&counts);
+        let subscription = subscribe(move |event| {
+            if let Phase::Finished {
+                duration,
+                succeeded,
+            } = event.phase
+            {
+                let mut counts = updated
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+                let count = counts
+                    .entry((event.subsystem, event.operation))
+                    .or_default();
+                count.calls += 1;
+                count.failures += u64::from(!succeeded);
+                count.duration += duration;
+                drop(counts);
+            }
+        });
+        Self {
+            counts,
+            _subscription: subscription,
+        }
+    }
+
+    /// The count of `operation` of `subsystem`.
+    #[must_use]
+    pub fn get(&self, subsystem: Subsystem, operation: &str) -> Count {
+        self.snapshot()
+            .into_iter()
+            .find(|((counted, name), _)| *counted == subsystem && *name == operation)
+            .map(|(_, count)| count)
+            .unwrap_or_default()
+    }
+
+    /// The counts of all operations performed so far, by subsystem and operation.
+    #[must_use]
+    pub fn snapshot(&self) -> CountMap {
+        self.counts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod instrument_test {
+    use super::*;
+    use crate::fs::{
+        generate_test_path,
+        Directory,
+        File,
+        Object as _,
+    };
+
+    #[test]
+    fn events_of_this_thread() {
+        let thread = std::thread::current().id();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let recorded = std::sync::Arc::clone(&events);
+        let subscription = subscribe(move |event| {
+            if event.thread == thread && matches!(event.phase, Phase::Finished { .. }) {
+                recorded.lock().unwrap().push((
+                    event.subsystem,
+                    event.operation,
+                    event.target.clone(),
+                ));
+            }
+        });
+
+        let path = generate_test_path();
+        let file = File::new(&path);
+        file.write_new("content").unwrap();
+        crate::process::Command::new("true").run().unwrap();
+        crate::environment::Environment::export_to_process_environment("RUSH_INSTRUMENT_TEST", "1")
+            .unwrap();
+        drop(subscription);
+        file.overwrite("not reported").unwrap();
+
+        let events = events.lock().unwrap().clone();
+        let path = path.display().to_string();
+        assert!(events.contains(&(Subsystem::FileSystem, "write", path.clone())));
+        assert!(events.contains(&(Subsystem::Process, "run", String::from("'true'"))));
+        assert_eq!(
+            events.last(),
+            Some(&(
+                Subsystem::Environment,
+                "export",
+                String::from("RUSH_INSTRUMENT_TEST")
+            ))
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|(_, operation, target)| *operation == "write" && *target == path)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn policies_stop_operations() {
+        let path = generate_test_path();
+        let forbidden = path.display().to_string();
+        let _policy = subscribe(move |event| {
+            if event.operation == "write"
+                && event.phase == Phase::Started
+                && event.target == forbidden
+            {
+                panic!("refusing to write to {}", event.target);
+            }
+        });
+
+        let file = File::new(&path);
+        let outcome = std::panic::catch_unwind(|| file.write_new("content"));
+        assert!(outcome.is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn counters() {
+        let counters = Counters::subscribe();
+        let file = File::new(generate_test_path());
+        file.write_new("content").unwrap();
+        assert!(file.read().is_ok());
+        assert!(Directory::new(generate_test_path().join("child"))
+            .create_on_fs()
+            .is_err());
+
+        assert!(counters.get(Subsystem::FileSystem, "read").calls >= 1);
+        assert!(counters.get(Subsystem::FileSystem, "create").failures >= 1);
+        assert!(counters
+            .snapshot()
+            .contains_key(&(Subsystem::FileSystem, "write")));
+    }
+}
@@ -0,0 +1,151 @@
+//! This module contains the parser for inventories in Ansible's INI format.
+
+use super::{
+    Builder,
+    InventoryError,
+    InventoryResult,
+    Variables,
+    UNGROUPED,
+};
+
+/// What the lines of a section describe.
+enum Section {
+    /// Hosts of the group, with their variables
+    Hosts(String),
+    /// Variables of the group
+    Variables(String),
+    /// Children of the group
+    Children(String),
+}
+
+/// Split `line` at whitespace, keeping quoted parts (with `"` or `'`) together and
+/// removing the quotes.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    for character in line.chars() {
+        match (quote, character) {
+            (Some(open), _) if character == open => quote = None,
+            (None, '"' | '\'') => quote = Some(character),
+            (None, _) if character.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            },
+            _ => current.push(character),
+        }
+    }
+    if quote.is_some() {
+        return Err(String::from("unterminated quote"));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+/// Parse `key=value` pairs.
+fn variables(tokens: &[String]) -> Result<Variables, String> {
+    tokens
+        .iter()
+        .map(|token| {
+            token
+                .split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| format!("'{token}' is not of the form key=value"))
+        })
+        .collect()
+}
+
+/// Parse an inventory in Ansible's INI format.
+pub(super) fn parse(content: &str) -> InventoryResult<Builder> {
+    let mut builder = Builder::default();
+    let mut section = Section::Hosts(String::from(UNGROUPED));
+
+    for (index, line) in content.lines().enumerate() {
+        let invalid = |message: String| InventoryError::Invalid(index + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| invalid(format!("'{line}' is not a valid section")))?;
+            let (group, kind) = header.split_once(':').unwrap_or((header, ""));
+            builder.group(group);
+            section = match kind {
+                "" => Section::Hosts(group.to_string()),
+                "vars" => Section::Variables(group.to_string()),
+                "children" => Section::Children(group.to_string()),
+                _ => return Err(invalid(format!("unknown section kind '{kind}'"))),
+            };
+            continue;
+        }
+
+        match &section {
+            Section::Hosts(group) => {
+                let tokens = tokenize(line).map_err(invalid)?;
+                let Some((pattern, rest)) = tokens.split_first() else {
+                    return Err(invalid(String::from("the host name is empty")));
+                };
+                let variables = variables(rest).map_err(invalid)?;
+                for host in super::expand_range(pattern).map_err(invalid)? {
+                    builder.add_host(group, &host, variables.clone());
+                }
+            },
+            Section::Variables(group) => {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| invalid(format!("'{line}' is not of the form key=value")))?;
+                let value = tokenize(value).map_err(invalid)?.join(" ");
+                builder
+                    .group(group)
+                    .variables
+                    .insert(key.trim().to_string(), value);
+            },
+            Section::Children(group) => {
+                let child = line.to_string();
+                builder.group(&child);
+                builder.group(group).children.insert(child);
+            },
+        }
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod ini_test {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("host a=1  b=\"x y\" c='z'"),
+            Ok(["host", "a=1", "b=x y", "c=z"].map(String::from).to_vec())
+        );
+        assert!(tokenize("host a=\"open").is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse("[web]\nhost port"),
+            Err(InventoryError::Invalid(2, _))
+        ));
+        assert!(matches!(
+            parse("[web:hosts]"),
+            Err(InventoryError::Invalid(1, _))
+        ));
+        assert!(matches!(
+            parse("[web:vars]\nport"),
+            Err(InventoryError::Invalid(2, _))
+        ));
+        assert_eq!(
+            parse("[a:children]\nb\n[b:children]\na").and_then(Builder::build),
+            Err(InventoryError::Cycle(String::from("a")))
+        );
+    }
+}
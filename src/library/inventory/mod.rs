@@ -0,0 +1,465 @@
+//! This module contains functionality for describing the hosts multi-host scripts
+//! manage, in the inventory formats of Ansible.
+//!
+//! An [`Inventory`] consists of hosts and groups. Groups contain hosts and other
+//! groups (their children), and every group is a child of `all`. Variables can be
+//! set on groups and on hosts; a host sees the variables of all groups it belongs
+//! to, where variables of more specific groups and of the host itself win. Hosts
+//! are picked with selection expressions like `group:web and not host:canary*`.
+//!
+//! ```no_run
+//! # use rush::inventory::Inventory;
+//! let inventory = Inventory::load("inventory.ini").unwrap();
+//! for host in inventory.select("group:web and not host:canary*").unwrap() {
+//!     println!("{} ({})", host.name(), host.address());
+//! }
+//! ```
+
+mod ini;
+mod selection;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+use crate::fs::FSError;
+
+/// Describes possible errors when dealing with inventories.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum InventoryError {
+    #[error("The inventory is not valid (line {0}): {1}")]
+    Invalid(usize, String),
+    #[error("The group '{0}' is its own child")]
+    Cycle(String),
+    #[error("The selection is not valid: {0}")]
+    InvalidSelection(String),
+    #[error("The group '{0}' does not exist")]
+    UnknownGroup(String),
+    #[error("The inventory format of '{0}' is not supported")]
+    UnsupportedFormat(String),
+    #[error("Reading the inventory failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`InventoryError`].
+pub type InventoryResult<T> = Result<T, InventoryError>;
+
+/// The group every host and every other group belongs to.
+const ALL: &str = "all";
+/// The group hosts belong to that are not in any other group.
+const UNGROUPED: &str = "ungrouped";
+
+/// Variables of hosts and groups, by name.
+pub type Variables = std::collections::BTreeMap<String, String>;
+
+/// A host of an [`Inventory`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// The name of the host
+    name:      String,
+    /// The variables of the host, including those of its groups
+    variables: Variables,
+    /// The groups the host belongs to, directly or through children
+    groups:    std::collections::BTreeSet<String>,
+}
+
+impl Host {
+    /// The name of the host in the inventory.
+    #[must_use]
+    pub fn name(&self) -> &str { &self.name }
+
+    /// The variables of the host, including those inherited from its groups.
+    #[must_use]
+    pub const fn variables(&self) -> &Variables { &self.variables }
+
+    /// The value of the variable `name`, if it is set.
+    #[must_use]
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// The groups the host belongs to, including `all` and the parents of its
+    /// groups.
+    #[must_use]
+    pub const fn groups(&self) -> &std::collections::BTreeSet<String> { &self.groups }
+
+    /// The address to connect to: the variable `ansible_host`, or the name.
+    #[must_use]
+    pub fn address(&self) -> &str { self.variable("ansible_host").unwrap_or(&self.name) }
+
+    /// The user to connect as, from the variable `ansible_user`.
+    #[must_use]
+    pub fn user(&self) -> Option<&str> { self.variable("ansible_user") }
+
+    /// The SSH port to connect to, from the variable `ansible_port`.
+    #[must_use]
+    pub fn port(&self) -> Option<u16> {
+        self.variable("ansible_port")
+            .and_then(|port| port.parse().ok())
+    }
+}
+
+/// A group of an [`Inventory`] as it is written in the inventory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Group {
+    /// The hosts directly in the group
+    hosts:     std::collections::BTreeSet<String>,
+    /// The groups directly in the group
+    children:  std::collections::BTreeSet<String>,
+    /// The variables set on the group
+    variables: Variables,
+}
+
+/// Hosts and groups as they are written in an inventory, before variables are
+/// inherited.
+#[derive(Debug, Default)]
+struct Builder {
+    /// The variables set on hosts, by host
+    hosts:  std::collections::BTreeMap<String, Variables>,
+    /// The groups, by name
+    groups: std::collections::BTreeMap<String, Group>,
+}
+
+impl Builder {
+    /// The group `name`, which is created if it does not exist.
+    fn group(&mut self, name: &str) -> &mut Group {
+        self.groups.entry(name.to_string()).or_default()
+    }
+
+    /// Add the host `name` with `variables` to the group `group`.
+    fn add_host(&mut self, group: &str, name: &str, variables: Variables) {
+        self.hosts
+            .entry(name.to_string())
+            .or_default()
+            .extend(variables);
+        self.group(group).hosts.insert(name.to_string());
+    }
+
+    /// Resolve group membership and variable inheritance.
+    fn build(mut self) -> InventoryResult<Inventory> {
+        let grouped: std::collections::BTreeSet<String> = self
+            .groups
+            .iter()
+            .filter(|(name, _)| *name != ALL)
+            .flat_map(|(_, group)| group.hosts.iter().cloned())
+            .collect();
+        let ungrouped: Vec<String> = self
+            .hosts
+            .keys()
+            .filter(|host| !grouped.contains(*host))
+            .cloned()
+            .collect();
+        for host in ungrouped {
+            self.group(UNGROUPED).hosts.insert(host);
+        }
+        let groups: Vec<String> = self
+            .groups
+            .keys()
+            .filter(|name| *name != ALL)
+            .cloned()
+            .collect();
+        self.group(ALL).children.extend(groups);
+
+        let mut ancestors = std::collections::BTreeMap::new();
+        for (name, group) in &self.groups {
+            if let Some(child) = group
+                .children
+                .iter()
+                .find(|child| !self.groups.contains_key(*child))
+            {
+                return Err(InventoryError::UnknownGroup(child.clone()));
+            }
+            let parents = self.ancestors(name);
+            if name != ALL && parents.contains(name) {
+                return Err(InventoryError::Cycle(name.clone()));
+            }
+            ancestors.insert(name.clone(), parents);
+        }
+
+        let mut hosts = std::collections::BTreeMap::new();
+        for (name, own_variables) in &self.hosts {
+            let mut groups = std::collections::BTreeSet::new();
+            for (group, definition) in &self.groups {
+                if definition.hosts.contains(name) {
+                    groups.insert(group.clone());
+                    groups.extend(ancestors[group].iter().cloned());
+                }
+            }
+            // A child group has more ancestors than its parents, so ordering by the
+            // number of ancestors lets variables of more specific groups win.
+            let mut ordered: Vec<&String> = groups.iter().collect();
+            ordered.sort_by_key(|group| (ancestors[*group].len(), *group));
+            let mut variables = Variables::new();
+            for group in ordered {
+                variables.extend(self.groups[group].variables.clone());
+            }
+            variables.extend(own_variables.clone());
+            hosts.insert(
+                name.clone(),
+                Host {
+                    name: name.clone(),
+                    variables,
+                    groups,
+                },
+            );
+        }
+        Ok(Inventory {
+            hosts,
+            groups: self.groups,
+        })
+    }
+
+    /// The groups `name` is a child of, directly or indirectly, including `all`.
+    fn ancestors(&self, name: &str) -> std::collections::BTreeSet<String> {
+        let mut ancestors = std::collections::BTreeSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(current) = pending.pop() {
+            for (parent, group) in &self.groups {
+                if group.children.contains(&current) && ancestors.insert(parent.clone()) {
+                    pending.push(parent.clone());
+                }
+            }
+        }
+        ancestors
+    }
+}
+
+/// Expand a host pattern with a range like `web[01:03].example.com` into the host
+/// names it stands for. Numeric ranges keep the width of leading zeros, and ranges
+/// of letters (`[a:c]`) are supported as well.
+fn expand_range(pattern: &str) -> Result<Vec<String>, String> {
+    let (Some(start), Some(end)) = (pattern.find('['), pattern.find(']')) else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let (first, last) = pattern[start + 1..end]
+        .split_once(':')
+        .filter(|_| start < end)
+        .ok_or_else(|| format!("invalid range in '{pattern}'"))?;
+    let (prefix, suffix) = (&pattern[..start], &pattern[end + 1..]);
+
+    let values: Vec<String> =
+        if let (Ok(from), Ok(to)) = (first.parse::<u64>(), last.parse::<u64>()) {
+            let width = if first.starts_with('0') {
+                first.len()
+            } else {
+                0
+            };
+            (from..=to)
+                .map(|number| format!("{number:0width$}"))
+                .collect()
+        } else {
+            let first: Vec<char> = first.chars().collect();
+            let last: Vec<char> = last.chars().collect();
+            match (first.as_slice(), last.as_slice()) {
+                (&[from], &[to]) if from.is_ascii_alphabetic() && to.is_ascii_alphabetic() => {
+                    (from..=to).map(String::from).collect()
+                },
+                _ => return Err(format!("invalid range in '{pattern}'")),
+            }
+        };
+    let mut names = Vec::with_capacity(values.len());
+    for value in values {
+        names.extend(expand_range(&format!("{prefix}{value}{suffix}"))?);
+    }
+    Ok(names)
+}
+
+/// Hosts and the groups they belong to, with their variables. See the module
+/// documentation for an overview.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inventory {
+    /// The hosts, by name
+    hosts:  std::collections::BTreeMap<String, Host>,
+    /// The groups as they are written in the inventory, by name
+    groups: std::collections::BTreeMap<String, Group>,
+}
+
+impl Inventory {
+    /// Load the inventory file at `path`. Files ending in `.yml` or `.yaml` are read
+    /// as YAML (which requires the `yaml` feature), all others in the INI format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or parsing the file fails.
+    pub fn load(path: impl AsRef<std::path::Path>) -> InventoryResult<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(FSError::from)?;
+        match path.extension().and_then(std::ffi::OsStr::to_str) {
+            #[cfg(feature = "yaml")]
+            Some("yml" | "yaml") => Self::from_yaml(&content),
+            #[cfg(not(feature = "yaml"))]
+            Some("yml" | "yaml") => Err(InventoryError::UnsupportedFormat(
+                path.to_string_lossy().into_owned(),
+            )),
+            _ => Self::from_ini(&content),
+        }
+    }
+
+    /// Parse an inventory in Ansible's INI format:
+    ///
+    /// ```ini
+    /// bastion.example.com
+    ///
+    /// [web]
+    /// web[01:03].example.com http_port=8080
+    /// canary.example.com ansible_host=10.0.0.9
+    ///
+    /// [web:vars]
+    /// ntp_server = ntp.example.com
+    ///
+    /// [production:children]
+    /// web
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the inventory is not valid.
+    pub fn from_ini(content: &str) -> InventoryResult<Self> { ini::parse(content)?.build() }
+
+    /// Parse an inventory in Ansible's YAML format, where every group may have
+    /// `hosts` (with their variables), `vars` and `children`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the inventory is not valid.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(content: &str) -> InventoryResult<Self> { yaml::parse(content)?.build() }
+
+    /// All hosts, sorted by name.
+    pub fn hosts(&self) -> impl Iterator<Item = &Host> { self.hosts.values() }
+
+    /// The host `name`, if it is in the inventory.
+    #[must_use]
+    pub fn host(&self, name: &str) -> Option<&Host> { self.hosts.get(name) }
+
+    /// The names of all groups, including `all` and `ungrouped`.
+    pub fn groups(&self) -> impl Iterator<Item = &str> { self.groups.keys().map(String::as_str) }
+
+    /// The hosts in the group `name`, directly or through its children, sorted by
+    /// name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the group does not exist.
+    pub fn group(&self, name: &str) -> InventoryResult<Vec<&Host>> {
+        if !self.groups.contains_key(name) {
+            return Err(InventoryError::UnknownGroup(name.to_string()));
+        }
+        Ok(self
+            .hosts
+            .values()
+            .filter(|host| host.groups.contains(name))
+            .collect())
+    }
+
+    /// The hosts matching the selection `expression`, sorted by name.
+    ///
+    /// Expressions consist of `group:<name>`, `host:<pattern>` (where `*` matches
+    /// any characters), `all`, or just a name (of a group or, if there is no such
+    /// group, a host), combined with `not`, `and`, `or` and parentheses. `and`
+    /// binds more strongly than `or`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the expression is not valid or names a group that does
+    /// not exist.
+    pub fn select(&self, expression: &str) -> InventoryResult<Vec<&Host>> {
+        let selection = selection::Selection::parse(expression)?;
+        let mut selected = Vec::new();
+        for host in self.hosts.values() {
+            if selection.matches(self, host)? {
+                selected.push(host);
+            }
+        }
+        Ok(selected)
+    }
+}
+
+#[cfg(test)]
+mod inventory_test {
+    use super::*;
+
+    /// An inventory using every feature of the INI format.
+    pub(super) const INI: &str = "
+bastion.example.com ansible_user=admin
+
+# Web servers
+[web]
+web[01:02].example.com http_port=8080
+canary.example.com ansible_host=10.0.0.9 ansible_port=2222 greeting=\"hello world\"
+
+[db]
+db.example.com
+
+[web:vars]
+http_port = 80
+role=web
+
+[production:children]
+web
+db
+
+[production:vars]
+role=generic
+environment=production
+";
+
+    #[test]
+    fn ranges() {
+        assert_eq!(expand_range("web"), Ok(vec![String::from("web")]));
+        assert_eq!(
+            expand_range("web[08:10]"),
+            Ok(vec![
+                String::from("web08"),
+                String::from("web09"),
+                String::from("web10")
+            ])
+        );
+        assert_eq!(
+            expand_range("[a:b]-[1:2]"),
+            Ok(["a-1", "a-2", "b-1", "b-2"].map(String::from).to_vec())
+        );
+        assert!(expand_range("web[1-2]").is_err());
+    }
+
+    #[test]
+    fn groups_and_variables() -> InventoryResult<()> {
+        let inventory = Inventory::from_ini(INI)?;
+        let names = |hosts: Vec<&Host>| -> Vec<String> {
+            hosts.into_iter().map(|host| host.name.clone()).collect()
+        };
+        assert_eq!(
+            names(inventory.group("production")?),
+            [
+                "canary.example.com",
+                "db.example.com",
+                "web01.example.com",
+                "web02.example.com"
+            ]
+        );
+        assert_eq!(
+            names(inventory.group("ungrouped")?),
+            ["bastion.example.com"]
+        );
+        assert_eq!(inventory.hosts().count(), 5);
+        assert_eq!(
+            inventory.group("nope"),
+            Err(InventoryError::UnknownGroup(String::from("nope")))
+        );
+
+        let canary = inventory
+            .host("canary.example.com")
+            .expect("canary should exist");
+        assert_eq!(canary.address(), "10.0.0.9");
+        assert_eq!(canary.port(), Some(2222));
+        assert_eq!(canary.variable("greeting"), Some("hello world"));
+        // The more specific group and the host itself win.
+        assert_eq!(canary.variable("role"), Some("web"));
+        assert_eq!(canary.variable("environment"), Some("production"));
+        assert_eq!(canary.variable("http_port"), Some("80"));
+        let web = inventory
+            .host("web01.example.com")
+            .expect("web01 should exist");
+        assert_eq!(web.variable("http_port"), Some("8080"));
+        assert!(web.groups().contains("all") && web.groups().contains("production"));
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+//! This module contains the parser and evaluator of selection expressions, which
+//! pick hosts from an inventory.
+
+use super::{
+    Host,
+    Inventory,
+    InventoryError,
+    InventoryResult,
+};
+
+/// A parsed selection expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Selection {
+    /// Every host
+    All,
+    /// Hosts in the group
+    Group(String),
+    /// Hosts whose name matches the pattern
+    Host(String),
+    /// Hosts in the group of this name or, if there is no such group, the host
+    Name(String),
+    /// Hosts the selection does not match
+    Not(Box<Self>),
+    /// Hosts both selections match
+    And(Box<Self>, Box<Self>),
+    /// Hosts either selection matches
+    Or(Box<Self>, Box<Self>),
+}
+
+/// Split `expression` into words and parentheses.
+fn tokenize(expression: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for character in expression.chars() {
+        if character.is_whitespace() || character == '(' || character == ')' {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            if !character.is_whitespace() {
+                tokens.push(character.to_string());
+            }
+        } else {
+            current.push(character);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// A recursive descent parser over the tokens of an expression.
+struct Parser {
+    /// The tokens of the expression
+    tokens:   Vec<String>,
+    /// The index of the next token
+    position: usize,
+}
+
+impl Parser {
+    /// The next token, if there is one.
+    fn peek(&self) -> Option<&str> { self.tokens.get(self.position).map(String::as_str) }
+
+    /// Consume the next token if it is `token`.
+    fn accept(&mut self, token: &str) -> bool {
+        let accepted = self.peek() == Some(token);
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    /// `or := and ("or" and)*`
+    fn or(&mut self) -> InventoryResult<Selection> {
+        let mut selection = self.and()?;
+        while self.accept("or") {
+            selection = Selection::Or(Box::new(selection), Box::new(self.and()?));
+        }
+        Ok(selection)
+    }
+
+    /// `and := not ("and" not)*`
+    fn and(&mut self) -> InventoryResult<Selection> {
+        let mut selection = self.not()?;
+        while self.accept("and") {
+            selection = Selection::And(Box::new(selection), Box::new(self.not()?));
+        }
+        Ok(selection)
+    }
+
+    /// `not := "not" not | "(" or ")" | term`
+    fn not(&mut self) -> InventoryResult<Selection> {
+        if self.accept("not") {
+            return Ok(Selection::Not(Box::new(self.not()?)));
+        }
+        if self.accept("(") {
+            let selection = self.or()?;
+            if !self.accept(")") {
+                return Err(InventoryError::InvalidSelection(String::from(
+                    "a closing parenthesis is missing",
+                )));
+            }
+            return Ok(selection);
+        }
+
+        let token = self
+            .peek()
+            .ok_or_else(|| {
+                InventoryError::InvalidSelection(String::from("the expression ends early"))
+            })?
+            .to_string();
+        if matches!(token.as_str(), ")" | "and" | "or") {
+            return Err(InventoryError::InvalidSelection(format!(
+                "unexpected '{token}'"
+            )));
+        }
+        self.position += 1;
+        let empty = || InventoryError::InvalidSelection(format!("'{token}' names nothing"));
+        Ok(match token.split_once(':') {
+            _ if token == "all" => Selection::All,
+            Some(("group" | "host", "")) => return Err(empty()),
+            Some(("group", group)) => Selection::Group(group.to_string()),
+            Some(("host", pattern)) => Selection::Host(pattern.to_string()),
+            Some((kind, _)) => {
+                return Err(InventoryError::InvalidSelection(format!(
+                    "unknown kind '{kind}'"
+                )))
+            },
+            None => Selection::Name(token.clone()),
+        })
+    }
+}
+
+impl Selection {
+    /// Parse `expression`.
+    pub(super) fn parse(expression: &str) -> InventoryResult<Self> {
+        let mut parser = Parser {
+            tokens:   tokenize(expression),
+            position: 0,
+        };
+        let selection = parser.or()?;
+        parser.peek().map_or(Ok(selection), |token| {
+            Err(InventoryError::InvalidSelection(format!(
+                "unexpected '{token}'"
+            )))
+        })
+    }
+
+    /// Whether this selection matches `host` of `inventory`.
+    pub(super) fn matches(&self, inventory: &Inventory, host: &Host) -> InventoryResult<bool> {
+        Ok(match self {
+            Self::All => true,
+            Self::Group(group) => {
+                if !inventory.groups.contains_key(group) {
+                    return Err(InventoryError::UnknownGroup(group.clone()));
+                }
+                host.groups.contains(group)
+            },
+            Self::Host(pattern) => rush_core::glob::matches(pattern, &host.name),
+            Self::Name(name) if inventory.groups.contains_key(name) => host.groups.contains(name),
+            Self::Name(name) => host.name == *name,
+            Self::Not(selection) => !selection.matches(inventory, host)?,
+            Self::And(left, right) => {
+                left.matches(inventory, host)? && right.matches(inventory, host)?
+            },
+            Self::Or(left, right) => {
+                left.matches(inventory, host)? || right.matches(inventory, host)?
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod selection_test {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let name = |name: &str| Box::new(Selection::Name(name.to_string()));
+        assert_eq!(
+            Selection::parse("a or b and not c"),
+            Ok(Selection::Or(
+                name("a"),
+                Box::new(Selection::And(
+                    name("b"),
+                    Box::new(Selection::Not(name("c")))
+                ))
+            ))
+        );
+        assert_eq!(
+            Selection::parse("(a or b) and c"),
+            Ok(Selection::And(
+                Box::new(Selection::Or(name("a"), name("b"))),
+                name("c")
+            ))
+        );
+        for invalid in [
+            "",
+            "a and",
+            "(a",
+            "a)",
+            "a b",
+            "group:",
+            "region:eu",
+            "or a",
+        ] {
+            assert!(
+                Selection::parse(invalid).is_err(),
+                "'{invalid}' should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn selecting() -> InventoryResult<()> {
+        let inventory = Inventory::from_ini(super::super::inventory_test::INI)?;
+        let select = |expression: &str| -> InventoryResult<Vec<String>> {
+            Ok(inventory
+                .select(expression)?
+                .into_iter()
+                .map(|host| host.name.clone())
+                .collect())
+        };
+        assert_eq!(
+            select("group:web and not host:canary*")?,
+            ["web01.example.com", "web02.example.com"]
+        );
+        assert_eq!(
+            select("db or bastion.example.com")?,
+            ["bastion.example.com", "db.example.com"]
+        );
+        assert_eq!(select("all and not production")?, ["bastion.example.com"]);
+        assert_eq!(select("not (web or ungrouped)")?, ["db.example.com"]);
+        assert_eq!(
+            select("group:nope"),
+            Err(InventoryError::UnknownGroup(String::from("nope")))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! This module contains the parser for inventories in Ansible's YAML format.
+
+use super::{
+    Builder,
+    InventoryError,
+    InventoryResult,
+    Variables,
+};
+
+/// An error for the YAML inventory, which has no line to point to.
+const fn invalid(message: String) -> InventoryError { InventoryError::Invalid(0, message) }
+
+/// The text of a scalar YAML `value`.
+fn scalar(value: &serde_yaml::Value) -> InventoryResult<String> {
+    match value {
+        serde_yaml::Value::Null => Ok(String::new()),
+        serde_yaml::Value::Bool(value) => Ok(value.to_string()),
+        serde_yaml::Value::Number(value) => Ok(value.to_string()),
+        serde_yaml::Value::String(value) => Ok(value.clone()),
+        _ => Err(invalid(format!("{value:?} is not a scalar"))),
+    }
+}
+
+/// The entries of a mapping, where `null` (as in `hosts:` without entries) is an
+/// empty mapping.
+fn entries(value: &serde_yaml::Value) -> InventoryResult<Vec<(String, &serde_yaml::Value)>> {
+    match value {
+        serde_yaml::Value::Null => Ok(Vec::new()),
+        serde_yaml::Value::Mapping(mapping) => mapping
+            .iter()
+            .map(|(key, value)| Ok((scalar(key)?, value)))
+            .collect(),
+        _ => Err(invalid(format!("{value:?} is not a mapping"))),
+    }
+}
+
+/// Parse variables from a mapping.
+fn variables(value: &serde_yaml::Value) -> InventoryResult<Variables> {
+    entries(value)?
+        .into_iter()
+        .map(|(key, value)| Ok((key, scalar(value)?)))
+        .collect()
+}
+
+/// Add the group `name`, described by `value`, and its children to `builder`.
+fn group(builder: &mut Builder, name: &str, value: &serde_yaml::Value) -> InventoryResult<()> {
+    builder.group(name);
+    for (key, value) in entries(value)? {
+        match key.as_str() {
+            "hosts" => {
+                for (pattern, host_variables) in entries(value)? {
+                    let host_variables = variables(host_variables)?;
+                    for host in super::expand_range(&pattern).map_err(invalid)? {
+                        builder.add_host(name, &host, host_variables.clone());
+                    }
+                }
+            },
+            "vars" => {
+                let group_variables = variables(value)?;
+                builder.group(name).variables.extend(group_variables);
+            },
+            "children" => {
+                for (child, value) in entries(value)? {
+                    builder.group(name).children.insert(child.clone());
+                    group(builder, &child, value)?;
+                }
+            },
+            _ => return Err(invalid(format!("unknown key '{key}' in group '{name}'"))),
+        }
+    }
+    Ok(())
+}
+
+/// Parse an inventory in Ansible's YAML format.
+pub(super) fn parse(content: &str) -> InventoryResult<Builder> {
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(content).map_err(|error| invalid(error.to_string()))?;
+    let mut builder = Builder::default();
+    for (name, value) in entries(&value)? {
+        group(&mut builder, &name, value)?;
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod yaml_test {
+    use super::super::Inventory;
+    use super::*;
+
+    #[test]
+    fn same_as_ini() -> InventoryResult<()> {
+        let yaml = Inventory::from_yaml(
+            "
+all:
+  hosts:
+    bastion.example.com:
+      ansible_user: admin
+  children:
+    production:
+      vars:
+        role: generic
+        environment: production
+      children:
+        web:
+          hosts:
+            web[01:02].example.com:
+              http_port: 8080
+            canary.example.com:
+              ansible_host: 10.0.0.9
+              ansible_port: 2222
+              greeting: hello world
+          vars:
+            http_port: 80
+            role: web
+        db:
+          hosts:
+            db.example.com:
+",
+        )?;
+        let ini = Inventory::from_ini(super::super::inventory_test::INI)?;
+        assert_eq!(yaml.hosts, ini.hosts);
+        assert!(matches!(
+            Inventory::from_yaml("all:\n  hosts: [a, b]"),
+            Err(InventoryError::Invalid(..))
+        ));
+        Ok(())
+    }
+}
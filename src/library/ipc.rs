@@ -0,0 +1,394 @@
+//! This module contains functionality for talking to daemon-like programs through
+//! control sockets, e.g. to trigger a reload right away.
+//!
+//! Messages are JSON documents, each preceded by its length as a 32-bit big-endian
+//! integer, sent over a Unix domain socket. A [`Server`] answers every request with
+//! a response or an error message, and a [`Client`] can send several requests over
+//! one connection.
+//!
+//! ```no_run
+//! # use rush::ipc::{Client, Server};
+//! let _server = Server::bind("/run/my-daemon.sock", |command: String| match command.as_str() {
+//!     "reload" => Ok(String::from("reloaded")),
+//!     _ => Err(format!("unknown command '{command}'")),
+//! })
+//! .unwrap();
+//!
+//! let mut client = Client::connect("/run/my-daemon.sock").unwrap();
+//! let response: String = client.request(&"reload").unwrap();
+//! ```
+
+/// Describes possible errors when talking over control sockets.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum IpcError {
+    #[error("Could not bind to '{0}': {1}")]
+    Bind(String, String),
+    #[error("Another server is listening on '{0}'")]
+    InUse(String),
+    #[error("Could not connect to '{0}': {1}")]
+    Connect(String, String),
+    #[error("The connection failed: {0}")]
+    Connection(String),
+    #[error("The message is not valid: {0}")]
+    InvalidMessage(String),
+    #[error("The message of {0} bytes exceeds the maximum size")]
+    TooLarge(usize),
+    #[error("The server could not handle the request: {0}")]
+    Remote(String),
+}
+
+/// A [`Result`] whose error variant is a [`IpcError`].
+pub type IpcResult<T> = Result<T, IpcError>;
+
+/// How long to sleep when no connection is pending before checking for shutdown again.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+/// How long a [`Client`] waits for a response by default.
+const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+/// The largest message, in bytes, that is sent or accepted.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Turn an [`std::io::Error`] into a [`IpcError`].
+fn io_error(error: &std::io::Error) -> IpcError {
+    if error.kind() == std::io::ErrorKind::UnexpectedEof {
+        IpcError::Connection(String::from("closed by the other side"))
+    } else {
+        IpcError::Connection(error.to_string())
+    }
+}
+
+/// Write `message` preceded by its length.
+pub(crate) fn write_message(stream: &mut impl std::io::Write, message: &[u8]) -> IpcResult<()> {
+    let length = u32::try_from(message.len())
+        .ok()
+        .filter(|_| message.len() <= MAX_MESSAGE_SIZE)
+        .ok_or(IpcError::TooLarge(message.len()))?;
+    stream
+        .write_all(&length.to_be_bytes())
+        .and_then(|()| stream.write_all(message))
+        .and_then(|()| stream.flush())
+        .map_err(|error| io_error(&error))
+}
+
+/// Read a message preceded by its length. Returns [`None`] if the other side closed
+/// the connection before a new message.
+pub(crate) fn read_message(stream: &mut impl std::io::Read) -> IpcResult<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match stream.read(&mut length[..1]) {
+        Ok(0) => return Ok(None),
+        Ok(_) => {},
+        Err(error) => return Err(io_error(&error)),
+    }
+    stream
+        .read_exact(&mut length[1..])
+        .map_err(|error| io_error(&error))?;
+    let length = usize::try_from(u32::from_be_bytes(length)).unwrap_or(usize::MAX);
+    if length > MAX_MESSAGE_SIZE {
+        return Err(IpcError::TooLarge(length));
+    }
+    let mut message = vec![0; length];
+    stream
+        .read_exact(&mut message)
+        .map_err(|error| io_error(&error))?;
+    Ok(Some(message))
+}
+
+/// The answer to a request: either `{"ok": <response>}` or `{"error": "<message>"}`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Envelope<T> {
+    /// The handler succeeded
+    Ok(T),
+    /// The request was invalid or the handler failed
+    Error(String),
+}
+
+/// A handler with the types of requests and responses erased: it turns a request
+/// message into a response message.
+type Handler = dyn Fn(&[u8]) -> Vec<u8> + Send + Sync;
+
+/// The streams of the connections currently being served, keyed by a unique number
+/// per connection, so they can be closed when the server stops.
+type Connections = std::sync::Arc<
+    std::sync::Mutex<std::collections::HashMap<u64, std::os::unix::net::UnixStream>>,
+>;
+
+/// Answer the requests on `stream` until the client closes the connection.
+fn serve(
+    id: u64,
+    mut stream: std::os::unix::net::UnixStream,
+    handler: &Handler,
+    connections: &Connections,
+    stop: &std::sync::atomic::AtomicBool,
+) {
+    let registered = connections.lock().is_ok_and(|mut connections| {
+        // Checking `stop` while holding the lock guarantees that every stream is
+        // either closed by the server's `Drop` or not served at all.
+        if stop.load(std::sync::atomic::Ordering::SeqCst) {
+            return false;
+        }
+        stream
+            .try_clone()
+            .map(|clone| connections.insert(id, clone))
+            .is_ok()
+    });
+    if registered && stream.set_nonblocking(false).is_ok() {
+        loop {
+            match read_message(&mut stream) {
+                Ok(Some(request)) => {
+                    if let Err(error) = write_message(&mut stream, &handler(&request)) {
+                        log::debug!("Answering a request failed: {error}");
+                        break;
+                    }
+                },
+                Ok(None) => break,
+                Err(error) => {
+                    log::debug!("Reading a request failed: {error}");
+                    break;
+                },
+            }
+        }
+    }
+    if let Ok(mut connections) = connections.lock() {
+        connections.remove(&id);
+    }
+}
+
+/// A server answering requests on a Unix domain socket in background threads, one
+/// per connection. The server stops and removes the socket when this value is
+/// dropped.
+#[derive(Debug)]
+pub struct Server {
+    /// The path of the socket
+    path:        std::path::PathBuf,
+    /// Tells the accepting thread to stop
+    stop:        std::sync::Arc<std::sync::atomic::AtomicBool>,
+    /// The connections currently being served
+    connections: Connections,
+    /// The accepting thread
+    thread:      Option<std::thread::JoinHandle<()>>,
+}
+
+impl Server {
+    /// Listen on the socket `path` and answer every request with what `handler`
+    /// returns for it. An error returned by `handler` is sent to the client, which
+    /// receives it as [`IpcError::Remote`]. Only the current user can connect.
+    ///
+    /// A socket left behind by a server that is no longer running is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if another server listens on `path`, if `path` exists but is
+    /// not a socket or if binding fails.
+    pub fn bind<Request, Response, F>(
+        path: impl AsRef<std::path::Path>,
+        handler: F,
+    ) -> IpcResult<Self>
+    where
+        Request: serde::de::DeserializeOwned,
+        Response: serde::Serialize,
+        F: Fn(Request) -> Result<Response, String> + Send + Sync + 'static,
+    {
+        use std::os::unix::fs::{
+            FileTypeExt as _,
+            PermissionsExt as _,
+        };
+
+        let path = path.as_ref().to_path_buf();
+        let display = path.to_string_lossy().into_owned();
+        let to_error = |error: std::io::Error| IpcError::Bind(display.clone(), error.to_string());
+
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(IpcError::Bind(display, String::from("not a socket")));
+            }
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                return Err(IpcError::InUse(display));
+            }
+            log::debug!("Removing stale socket '{display}'");
+            std::fs::remove_file(&path).map_err(to_error)?;
+        }
+        let listener = std::os::unix::net::UnixListener::bind(&path).map_err(to_error)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .and_then(|()| listener.set_nonblocking(true))
+            .map_err(to_error)?;
+        log::debug!("Listening on '{display}'");
+
+        let handler: std::sync::Arc<Handler> = std::sync::Arc::new(move |request: &[u8]| {
+            let response = match serde_json::from_slice(request) {
+                Ok(request) => handler(request).map_or_else(Envelope::Error, Envelope::Ok),
+                Err(error) => Envelope::Error(format!("invalid request: {error}")),
+            };
+            serde_json::to_vec(&response).unwrap_or_else(|error| {
+                serde_json::to_vec(&Envelope::<()>::Error(format!("invalid response: {error}")))
+                    .unwrap_or_default()
+            })
+        });
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let connections = Connections::default();
+        let thread = {
+            let stop = stop.clone();
+            let connections = connections.clone();
+            std::thread::spawn(move || {
+                let mut next_id = 0;
+                while !stop.load(std::sync::atomic::Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let handler = handler.clone();
+                            let connections = connections.clone();
+                            let stop = stop.clone();
+                            std::thread::spawn(move || {
+                                serve(next_id, stream, handler.as_ref(), &connections, &stop);
+                            });
+                            next_id += 1;
+                        },
+                        Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                            std::thread::sleep(POLL_INTERVAL);
+                        },
+                        Err(error) => log::warn!("Accepting a connection failed: {error}"),
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            path,
+            stop,
+            connections,
+            thread: Some(thread),
+        })
+    }
+
+    /// The path of the socket.
+    #[must_use]
+    pub const fn path(&self) -> &std::path::PathBuf { &self.path }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if let Ok(connections) = self.connections.lock() {
+            for stream in connections.values() {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        }
+        let _ = std::fs::remove_file(&self.path);
+        log::debug!("Stopped listening on '{}'", self.path.to_string_lossy());
+    }
+}
+
+/// A connection to a [`Server`], over which any number of requests can be sent one
+/// after another.
+#[derive(Debug)]
+pub struct Client {
+    /// The connection
+    stream: std::os::unix::net::UnixStream,
+}
+
+impl Client {
+    /// Connect to the server listening on the socket `path`. Responses are awaited
+    /// for 30 seconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if nothing listens on `path`.
+    pub fn connect(path: impl AsRef<std::path::Path>) -> IpcResult<Self> {
+        let path = path.as_ref();
+        let to_error = |error: std::io::Error| {
+            IpcError::Connect(path.to_string_lossy().into_owned(), error.to_string())
+        };
+        let stream = std::os::unix::net::UnixStream::connect(path).map_err(to_error)?;
+        stream
+            .set_read_timeout(Some(DEFAULT_TIMEOUT))
+            .map_err(to_error)?;
+        Ok(Self { stream })
+    }
+
+    /// Wait at most `timeout` for responses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `timeout` is zero.
+    pub fn timeout(self, timeout: std::time::Duration) -> IpcResult<Self> {
+        self.stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|error| io_error(&error))?;
+        Ok(self)
+    }
+
+    /// Send `request` and wait for the response.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IpcError::Remote`] if the server could not handle the request, or an
+    /// error if the connection fails or the response is not of the expected type.
+    pub fn request<Request, Response>(&mut self, request: &Request) -> IpcResult<Response>
+    where
+        Request: serde::Serialize + ?Sized,
+        Response: serde::de::DeserializeOwned,
+    {
+        let request = serde_json::to_vec(request)
+            .map_err(|error| IpcError::InvalidMessage(error.to_string()))?;
+        write_message(&mut self.stream, &request)?;
+        let response = read_message(&mut self.stream)?
+            .ok_or_else(|| IpcError::Connection(String::from("closed by the other side")))?;
+        match serde_json::from_slice(&response)
+            .map_err(|error| IpcError::InvalidMessage(error.to_string()))?
+        {
+            Envelope::Ok(response) => Ok(response),
+            Envelope::Error(message) => Err(IpcError::Remote(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod ipc_test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    struct Status {
+        reloads: u64,
+    }
+
+    #[test]
+    fn requests() -> IpcResult<()> {
+        let path = crate::fs::generate_test_path();
+        let reloads = std::sync::atomic::AtomicU64::new(0);
+        let server = Server::bind(&path, move |command: String| match command.as_str() {
+            "reload" => Ok(Status {
+                reloads: reloads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1,
+            }),
+            _ => Err(format!("unknown command '{command}'")),
+        })?;
+        assert_eq!(
+            Server::bind(&path, |(): ()| Ok(())).err(),
+            Some(IpcError::InUse(path.to_string_lossy().into_owned()))
+        );
+
+        let mut client = Client::connect(&path)?;
+        assert_eq!(
+            client.request::<_, Status>("reload")?,
+            Status { reloads: 1 }
+        );
+        assert_eq!(
+            client.request::<_, Status>("reload")?,
+            Status { reloads: 2 }
+        );
+        assert_eq!(
+            client.request::<_, Status>("explode"),
+            Err(IpcError::Remote(String::from("unknown command 'explode'")))
+        );
+        assert!(matches!(
+            client.request::<_, Status>(&42),
+            Err(IpcError::Remote(message)) if message.starts_with("invalid request")
+        ));
+
+        drop(server);
+        assert!(!path.exists());
+        assert!(client.request::<_, Status>("reload").is_err());
+        assert!(matches!(Client::connect(&path), Err(IpcError::Connect(..))));
+        Ok(())
+    }
+}
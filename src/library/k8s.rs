@@ -0,0 +1,240 @@
+//! This module contains functionality for managing Kubernetes clusters with
+//! `kubectl`, which needs to be installed.
+//!
+//! Instead of parsing kubectl's human-readable output, objects are requested as JSON
+//! and deserialized into whatever type the caller needs, e.g. [`serde_json::Value`]
+//! or a struct with just the fields of interest.
+
+use crate::process::{
+    Command,
+    ProcessError,
+};
+
+/// Describes possible errors when running kubectl.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum K8sError {
+    #[error("Running kubectl failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("The object '{0}' does not exist")]
+    NotFound(String),
+    #[error("Waiting for '{0}' timed out")]
+    Timeout(String),
+    #[error("The output of kubectl could not be understood: {0}")]
+    InvalidOutput(String),
+}
+
+/// A [`Result`] whose error variant is a [`K8sError`].
+pub type K8sResult<T> = Result<T, K8sError>;
+
+/// Runs kubectl against a cluster. Without further configuration, the current
+/// context and its namespace are used, just like kubectl does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Kubectl {
+    /// The kubeconfig file to use instead of `$KUBECONFIG` or `~/.kube/config`
+    kubeconfig: Option<std::path::PathBuf>,
+    /// The context to use instead of the current one
+    context:    Option<String>,
+    /// The namespace to use instead of the context's one
+    namespace:  Option<String>,
+}
+
+impl Default for Kubectl {
+    fn default() -> Self { Self::new() }
+}
+
+impl Kubectl {
+    /// Use the current context and its namespace.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            kubeconfig: None,
+            context:    None,
+            namespace:  None,
+        }
+    }
+
+    /// Read the cluster configuration from `path`.
+    #[must_use]
+    pub fn kubeconfig(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.kubeconfig = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Talk to the cluster of `context` instead of the current context. The current
+    /// context of the kubeconfig file is not changed.
+    #[must_use]
+    pub fn context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// Work in `namespace` unless a method is given another one.
+    #[must_use]
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// A kubectl invocation with the global options, working in `namespace` if given
+    /// and in the configured namespace otherwise.
+    fn command(&self, namespace: Option<&str>) -> Command {
+        let mut command = Command::new("kubectl");
+        if let Some(kubeconfig) = &self.kubeconfig {
+            command = command.arg(format!("--kubeconfig={}", kubeconfig.display()));
+        }
+        if let Some(context) = &self.context {
+            command = command.arg(format!("--context={context}"));
+        }
+        if let Some(namespace) = namespace.or(self.namespace.as_deref()) {
+            command = command.arg(format!("--namespace={namespace}"));
+        }
+        command
+    }
+
+    /// Run kubectl with `arguments` (after the global options) and return what it
+    /// wrote to standard output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if kubectl cannot be run or fails.
+    pub fn run<I, S>(&self, arguments: I) -> K8sResult<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Ok(self.command(None).args(arguments).run()?.stdout)
+    }
+
+    /// The name of the context kubectl talks to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if kubectl cannot be run or no context is selected.
+    pub fn current_context(&self) -> K8sResult<String> {
+        if let Some(context) = &self.context {
+            return Ok(context.clone());
+        }
+        Ok(self.run(["config", "current-context"])?.trim().to_string())
+    }
+
+    /// Make `context` the current context of the kubeconfig file, which affects
+    /// every later kubectl invocation, not only those of this value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if kubectl cannot be run or the context does not exist.
+    pub fn use_context(&self, context: &str) -> K8sResult<()> {
+        log::debug!("Switching kubectl context to '{context}'");
+        self.command(None)
+            .args(["config", "use-context", context])
+            .run()?;
+        Ok(())
+    }
+
+    /// Create or update the objects described by `path`, which is a manifest file or
+    /// a directory of manifests (which is searched recursively).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if kubectl cannot be run or the cluster rejects a manifest.
+    pub fn apply(&self, path: impl AsRef<std::path::Path>) -> K8sResult<()> {
+        let path = path.as_ref();
+        log::debug!("Applying '{}'", path.display());
+
+        let mut command = self
+            .command(None)
+            .arg("apply")
+            .arg(format!("--filename={}", path.display()));
+        if path.is_dir() {
+            command = command.arg("--recursive");
+        }
+        command.run()?;
+        Ok(())
+    }
+
+    /// Get the object `name` of `kind` (e.g. `deployment` or `configmap`) in
+    /// `namespace` (or the configured namespace) and deserialize it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if kubectl cannot be run, the object does not exist or it
+    /// cannot be deserialized into `T`.
+    pub fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        kind: &str,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> K8sResult<T> {
+        let object = format!("{kind}/{name}");
+        let output = self
+            .command(namespace)
+            .args(["get", &object, "--output=json"])
+            .run()
+            .map_err(|error| match error {
+                ProcessError::Failed { stderr, .. } if stderr.contains("(NotFound)") => {
+                    K8sError::NotFound(object.clone())
+                },
+                error => error.into(),
+            })?;
+        serde_json::from_str(&output.stdout)
+            .map_err(|error| K8sError::InvalidOutput(error.to_string()))
+    }
+
+    /// Wait until the rollout of `deployment` (in the configured namespace) has
+    /// finished, i.e. all replicas run the latest revision.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if kubectl cannot be run, the rollout fails or it does not
+    /// finish within `timeout`.
+    pub fn wait_for_rollout(
+        &self,
+        deployment: &str,
+        timeout: std::time::Duration,
+    ) -> K8sResult<()> {
+        let object = format!("deployment/{deployment}");
+        log::debug!("Waiting for the rollout of '{object}'");
+        self.command(None)
+            .args([
+                "rollout",
+                "status",
+                &object,
+                &format!("--timeout={}s", timeout.as_secs().max(1)),
+            ])
+            .run()
+            .map_err(|error| match error {
+                ProcessError::Failed { stderr, .. } if stderr.contains("timed out") => {
+                    K8sError::Timeout(object.clone())
+                },
+                error => error.into(),
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod k8s_test {
+    use super::*;
+
+    #[test]
+    fn global_options() {
+        assert!(Kubectl::new().command(None).arguments().is_empty());
+
+        let kubectl = Kubectl::new()
+            .kubeconfig("/etc/rush/kubeconfig")
+            .context("staging")
+            .namespace("web");
+        assert_eq!(
+            kubectl.command(None).arguments(),
+            [
+                "--kubeconfig=/etc/rush/kubeconfig",
+                "--context=staging",
+                "--namespace=web"
+            ]
+        );
+        assert_eq!(
+            kubectl.command(Some("kube-system")).arguments()[2],
+            "--namespace=kube-system"
+        );
+    }
+}
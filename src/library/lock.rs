@@ -0,0 +1,441 @@
+//! This module contains functionality for mutual exclusion between programs,
+//! possibly running on different hosts that share a filesystem (e.g. NFS).
+
+use crate::fs::FSError;
+
+/// Describes possible errors when dealing with locks.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum LockError {
+    #[error("The lock could not be acquired in time (held by {0})")]
+    Timeout(String),
+    #[error("The lock is no longer held by us")]
+    Lost,
+    #[error("Accessing the lock file failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Waiting for the lock was aborted: {0}")]
+    DeadlineExceeded(#[from] crate::deadline::DeadlineExceeded),
+}
+
+/// A [`Result`] whose error variant is a [`LockError`].
+pub type LockResult<T> = Result<T, LockError>;
+
+/// After how many seconds without renewal a lock is considered stale by default.
+const DEFAULT_STALE_AFTER_SECONDS: u64 = 300;
+
+/// The metadata stored in a lock file, describing who holds the lock.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Holder {
+    /// The host the holder runs on
+    pub host:     String,
+    /// The process ID of the holder on its host
+    pub pid:      u32,
+    /// A token unique to this acquisition of the lock
+    pub token:    String,
+    /// When the lock was acquired, in seconds since the Unix epoch
+    pub acquired: u64,
+}
+
+impl std::fmt::Display for Holder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "process {} on host '{}'", self.pid, self.host)
+    }
+}
+
+impl Holder {
+    /// Describe the current process as a holder.
+    pub(crate) fn current() -> Self {
+        let host = std::fs::read_to_string("/proc/sys/kernel/hostname").map_or_else(
+            |_| String::from("unknown"),
+            |hostname| hostname.trim().to_string(),
+        );
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let pid = std::process::id();
+        Self {
+            token: format!("{host}-{pid}-{}", now.as_nanos()),
+            host,
+            pid,
+            acquired: now.as_secs(),
+        }
+    }
+
+    /// Serialize the metadata as `key=value` lines.
+    fn serialize(&self) -> String {
+        format!(
+            "host={}\npid={}\ntoken={}\nacquired={}\n",
+            self.host, self.pid, self.token, self.acquired
+        )
+    }
+
+    /// Parse metadata written by [`Holder::serialize`].
+    fn parse(content: &str) -> Option<Self> {
+        let mut holder = Self {
+            host:     String::new(),
+            pid:      0,
+            token:    String::new(),
+            acquired: 0,
+        };
+        for line in content.lines() {
+            match line.split_once('=')? {
+                ("host", host) => holder.host = host.to_string(),
+                ("pid", pid) => holder.pid = pid.parse().ok()?,
+                ("token", token) => holder.token = token.to_string(),
+                ("acquired", acquired) => holder.acquired = acquired.parse().ok()?,
+                _ => {},
+            }
+        }
+        (!holder.token.is_empty()).then_some(holder)
+    }
+}
+
+/// A lock that is safe to use on shared filesystems such as NFS.
+///
+/// The lock is a file created with `O_EXCL` that contains metadata about its holder.
+/// A lock file that has not been renewed for longer than the staleness threshold
+/// is considered abandoned (e.g. because its holder crashed) and is taken over.
+/// Holders of long-running work must call [`LockGuard::renew`] more often than the
+/// threshold.
+///
+/// Staleness compares the modification time of the lock file, which is set by the
+/// file server, with the local clock. All hosts using the lock therefore need
+/// synchronized clocks (e.g. via NTP), and the threshold should leave a margin for
+/// the remaining clock skew.
+#[derive(Debug, Clone)]
+pub struct NetLock {
+    /// The lock file
+    path:          std::path::PathBuf,
+    /// After how long without renewal a lock is considered stale
+    stale_after:   std::time::Duration,
+    /// How long to sleep between attempts in [`NetLock::acquire`]
+    poll_interval: std::time::Duration,
+}
+
+impl NetLock {
+    /// Describe a lock backed by the file at `path`. Locks become stale after five
+    /// minutes without renewal.
+    pub fn new(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            path:          path.as_ref().to_path_buf(),
+            stale_after:   std::time::Duration::from_secs(DEFAULT_STALE_AFTER_SECONDS),
+            poll_interval: std::time::Duration::from_secs(1),
+        }
+    }
+
+    /// Consider locks stale after `duration` without renewal.
+    #[must_use]
+    pub const fn stale_after(mut self, duration: std::time::Duration) -> Self {
+        self.stale_after = duration;
+        self
+    }
+
+    /// Sleep `interval` between attempts in [`NetLock::acquire`].
+    #[must_use]
+    pub const fn poll_interval(mut self, interval: std::time::Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Read who currently holds the lock, if anyone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock file exists but cannot be read.
+    pub fn holder(&self) -> LockResult<Option<Holder>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(content) => Ok(Holder::parse(&content)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(FSError::from(error).into()),
+        }
+    }
+
+    /// Whether the lock file was last renewed longer ago than the staleness
+    /// threshold.
+    fn is_stale(&self) -> LockResult<bool> {
+        let modified = match self
+            .path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+        {
+            Ok(modified) => modified,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(FSError::from(error).into()),
+        };
+        Ok(modified.elapsed().unwrap_or_default() > self.stale_after)
+    }
+
+    /// Remove a stale lock file.
+    ///
+    /// The file is first renamed to a name unique to us and checked for staleness
+    /// again. If another process broke the lock and created a fresh one in between,
+    /// the fresh lock is put back in place instead of being removed.
+    fn break_stale_lock(&self) -> LockResult<()> {
+        let holder = self.holder()?;
+        log::warn!(
+            "Breaking stale lock '{}' held by {}",
+            self.path.to_string_lossy(),
+            holder.map_or_else(
+                || String::from("an unknown holder"),
+                |holder| holder.to_string()
+            )
+        );
+
+        let mut unique = self.path.clone().into_os_string();
+        unique.push(format!(".stale.{}", Holder::current().token));
+        let unique = std::path::PathBuf::from(unique);
+        match std::fs::rename(&self.path, &unique) {
+            Ok(()) => {},
+            // Somebody else was faster.
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(FSError::from(error).into()),
+        }
+
+        let still_stale = unique
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| modified.elapsed().unwrap_or_default() > self.stale_after)
+            .map_err(FSError::from)?;
+        if !still_stale {
+            log::debug!("Lock was renewed concurrently - restoring it");
+            // Fails if yet another lock was created in the meantime, which then wins.
+            let _ = std::fs::hard_link(&unique, &self.path);
+        }
+        std::fs::remove_file(unique).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    /// Try to acquire the lock once. Returns [`None`] if it is held by someone else.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock file cannot be created or inspected.
+    pub fn try_acquire(&self) -> LockResult<Option<LockGuard>> {
+        use std::io::Write;
+
+        if self.is_stale()? {
+            self.break_stale_lock()?;
+        }
+
+        let holder = Holder::current();
+        let mut file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.path)
+        {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => return Ok(None),
+            Err(error) => return Err(FSError::from(error).into()),
+        };
+        file.write_all(holder.serialize().as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(FSError::from)?;
+
+        log::debug!("Acquired lock '{}'", self.path.to_string_lossy());
+        Ok(Some(LockGuard {
+            path: self.path.clone(),
+            holder,
+        }))
+    }
+
+    /// Acquire the lock, waiting at most `timeout` for the current holder to release
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock could not be acquired in time or before the
+    /// [`Deadline`](crate::deadline::Deadline) in effect, or if the lock file cannot
+    /// be created or inspected.
+    pub fn acquire(&self, timeout: std::time::Duration) -> LockResult<LockGuard> {
+        let start = std::time::Instant::now();
+        loop {
+            if let Some(guard) = self.try_acquire()? {
+                return Ok(guard);
+            }
+            if start.elapsed() >= timeout {
+                let holder = self.holder()?.map_or_else(
+                    || String::from("an unknown holder"),
+                    |holder| holder.to_string(),
+                );
+                return Err(LockError::Timeout(holder));
+            }
+            crate::deadline::sleep(self.poll_interval)?;
+        }
+    }
+}
+
+/// Proof that a [`NetLock`] is held. The lock is released when this value is
+/// dropped.
+#[derive(Debug)]
+pub struct LockGuard {
+    /// The lock file
+    path:   std::path::PathBuf,
+    /// The metadata we wrote into the lock file
+    holder: Holder,
+}
+
+impl LockGuard {
+    /// The metadata describing us as the holder.
+    #[must_use]
+    pub const fn holder(&self) -> &Holder { &self.holder }
+
+    /// Whether `content` read from a lock file carries our token.
+    fn is_ours(&self, content: &str) -> bool {
+        Holder::parse(content).is_some_and(|holder| holder.token == self.holder.token)
+    }
+
+    /// Renew the lock so it does not become stale.
+    ///
+    /// The token is checked and the lock file rewritten through the same open file,
+    /// so a lock file created by someone else in the meantime is never overwritten.
+    /// The content stays the same; writing it only updates the modification time.
+    /// If a process breaking the lock moved the file away concurrently, it notices
+    /// the renewal and puts the lock back (see [`NetLock::break_stale_lock`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LockError::Lost`] if someone else took the lock over in the meantime
+    /// (because it had become stale), or an error if the lock file cannot be written.
+    pub fn renew(&self) -> LockResult<()> {
+        use std::io::{
+            Read,
+            Seek,
+            Write,
+        };
+
+        let mut file = match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)
+        {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(LockError::Lost)
+            },
+            Err(error) => return Err(FSError::from(error).into()),
+        };
+        let mut content = String::new();
+        file.read_to_string(&mut content).map_err(FSError::from)?;
+        if !self.is_ours(&content) {
+            return Err(LockError::Lost);
+        }
+        file.rewind()
+            .and_then(|()| file.write_all(self.holder.serialize().as_bytes()))
+            .and_then(|()| file.sync_all())
+            .map_err(FSError::from)?;
+        Ok(())
+    }
+
+    /// Remove the lock file if it still carries our token.
+    ///
+    /// Like [`NetLock::break_stale_lock`], the file is first renamed to a name unique
+    /// to us, so the token is checked on a file nobody else can replace. A lock that
+    /// is not ours is put back in place.
+    fn release(&self) -> LockResult<()> {
+        let mut unique = self.path.clone().into_os_string();
+        unique.push(format!(".released.{}", self.holder.token));
+        let unique = std::path::PathBuf::from(unique);
+        match std::fs::rename(&self.path, &unique) {
+            Ok(()) => {},
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(LockError::Lost)
+            },
+            Err(error) => return Err(FSError::from(error).into()),
+        }
+
+        let ours = std::fs::read_to_string(&unique).is_ok_and(|content| self.is_ours(&content));
+        if !ours {
+            // Fails if yet another lock was created in the meantime, which then wins.
+            let _ = std::fs::hard_link(&unique, &self.path);
+        }
+        std::fs::remove_file(unique).map_err(FSError::from)?;
+        if ours {
+            Ok(())
+        } else {
+            Err(LockError::Lost)
+        }
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        match self.release() {
+            Ok(()) => {},
+            Err(LockError::Lost) => log::warn!(
+                "Lock '{}' was taken over before it was released",
+                self.path.to_string_lossy()
+            ),
+            Err(error) => log::warn!(
+                "Could not release lock '{}': {error}",
+                self.path.to_string_lossy()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod net_lock_test {
+    use super::*;
+
+    fn lock_path() -> std::path::PathBuf { crate::fs::generate_test_path() }
+
+    #[test]
+    fn acquire_and_release() -> LockResult<()> {
+        let lock = NetLock::new(lock_path());
+        let guard = lock.try_acquire()?.expect("The lock should be free");
+        assert!(lock.try_acquire()?.is_none());
+        assert_eq!(lock.holder()?.as_ref(), Some(guard.holder()));
+        assert_eq!(guard.holder().pid, std::process::id());
+
+        guard.renew()?;
+        drop(guard);
+        assert!(lock.holder()?.is_none());
+        assert!(lock.try_acquire()?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn timeout() -> LockResult<()> {
+        let lock = NetLock::new(lock_path()).poll_interval(std::time::Duration::from_millis(10));
+        let _guard = lock.acquire(std::time::Duration::ZERO)?;
+        assert!(matches!(
+            lock.acquire(std::time::Duration::from_millis(30)),
+            Err(LockError::Timeout(_))
+        ));
+
+        let _deadline =
+            crate::deadline::Deadline::after(std::time::Duration::from_millis(30)).install();
+        assert!(matches!(
+            lock.acquire(std::time::Duration::from_secs(10)),
+            Err(LockError::DeadlineExceeded(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn stale_lock_is_taken_over() -> LockResult<()> {
+        let path = lock_path();
+        let first = NetLock::new(&path)
+            .try_acquire()?
+            .expect("The lock should be free");
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let lock = NetLock::new(&path).stale_after(std::time::Duration::from_millis(10));
+        let second = lock
+            .try_acquire()?
+            .expect("The stale lock should be taken over");
+
+        assert_eq!(first.renew(), Err(LockError::Lost));
+        assert_eq!(first.release(), Err(LockError::Lost));
+        drop(first);
+        assert_eq!(lock.holder()?.as_ref(), Some(second.holder()));
+
+        // Releasing leaves no renamed lock files behind.
+        drop(second);
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(!std::fs::read_dir(std::env::temp_dir())
+            .map_err(FSError::from)?
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&name)));
+        Ok(())
+    }
+}
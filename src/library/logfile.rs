@@ -0,0 +1,231 @@
+//! This module contains [`LogWriter`], which appends lines to a log file that scripts
+//! maintain on their own and which may be rotated (e.g. by `logrotate`) while they
+//! run.
+
+use crate::fs::{
+    FSError,
+    FSResult,
+};
+
+/// The file a [`LogWriter`] currently appends to.
+#[derive(Debug)]
+struct Open {
+    /// The file, opened in append mode
+    file:     std::fs::File,
+    /// The identity of the file when it was opened, if the system offers one
+    identity: Option<(u64, u64)>,
+}
+
+impl Open {
+    /// Open (and create, if needed) the file at `path` in append mode.
+    fn new(path: &std::path::Path) -> FSResult<Self> {
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let identity = crate::fs::file_identity(&file.metadata()?);
+        Ok(Self { file, identity })
+    }
+}
+
+/// Appends lines to a log file, like `>>` in a shell.
+///
+/// Before each write, the writer checks whether the path still refers to the file
+/// it opened, like `tail -F` does. If the file was moved away or deleted (as
+/// `logrotate` does), a new file is created at the path and written to instead.
+/// Rotation with `copytruncate` needs no reopening, as writes always go to the end
+/// of the file. On systems other than Unix, only deleted or moved files are noticed.
+///
+/// A writer can be shared between threads (e.g. in an [`std::sync::Arc`]); each
+/// call of [`LogWriter::write_line`] is written as a whole.
+///
+/// ```
+/// let path = std::env::temp_dir().join("rush-logfile-example.log");
+/// let log = rush::logfile::LogWriter::open(&path)
+///     .unwrap()
+///     .timestamps(true);
+/// log.write_line("Backup started").unwrap();
+/// assert!(std::fs::read_to_string(&path)
+///     .unwrap()
+///     .ends_with("Z Backup started\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct LogWriter {
+    /// The path of the log file
+    path:       std::path::PathBuf,
+    /// Whether lines are prefixed with the current time
+    timestamps: bool,
+    /// The file currently written to
+    open:       std::sync::Mutex<Open>,
+}
+
+impl LogWriter {
+    /// Open the log file at `path` for appending, creating it (and its parent
+    /// directories) if it does not exist. Lines are not timestamped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created or opened.
+    pub fn open(path: impl AsRef<std::path::Path>) -> FSResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        log::trace!("Opening log file '{}'", path.display());
+        let open = Open::new(&path)?;
+        Ok(Self {
+            path,
+            timestamps: false,
+            open: std::sync::Mutex::new(open),
+        })
+    }
+
+    /// Whether each line is prefixed with the current time (UTC) in RFC 3339
+    /// format, e.g. `2024-10-06T12:00:00.000Z Backup started`.
+    #[must_use]
+    pub const fn timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled;
+        self
+    }
+
+    /// The path of the log file.
+    #[must_use]
+    pub fn path(&self) -> &std::path::Path { &self.path }
+
+    /// The file to write to, reopened if the path no longer refers to it.
+    fn current(&self) -> FSResult<std::sync::MutexGuard<'_, Open>> {
+        let mut open = self
+            .open
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let rotated = match std::fs::metadata(&self.path) {
+            Ok(metadata) => {
+                let identity = crate::fs::file_identity(&metadata);
+                identity.is_some() && identity != open.identity
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => true,
+            Err(error) => return Err(error.into()),
+        };
+        if rotated {
+            log::debug!(
+                "Log file '{}' was rotated, reopening it",
+                self.path.display()
+            );
+            *open = Open::new(&self.path)?;
+        }
+        Ok(open)
+    }
+
+    /// Append `text` to the log file, followed by a line feed unless it ends with
+    /// one. If `text` spans several lines and timestamps are enabled, each line is
+    /// prefixed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reopening a rotated file or writing fails.
+    pub fn write_line(&self, text: impl AsRef<str>) -> FSResult<()> {
+        use std::io::Write as _;
+
+        let text = text.as_ref();
+        let mut content = String::with_capacity(text.len() + 32);
+        let timestamp = self.timestamps.then(|| {
+            crate::library::time::DateTime::from_system_time(std::time::SystemTime::now())
+                .to_rfc3339()
+        });
+        for line in text.strip_suffix('\n').unwrap_or(text).split('\n') {
+            if let Some(timestamp) = &timestamp {
+                content.push_str(timestamp);
+                content.push(' ');
+            }
+            content.push_str(line);
+            content.push('\n');
+        }
+
+        self.current()?.file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Close the log file and open the file at the path again, e.g. when a
+    /// `SIGHUP` asks for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn reopen(&self) -> FSResult<()> {
+        let open = Open::new(&self.path)?;
+        *self
+            .open
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = open;
+        Ok(())
+    }
+
+    /// Flush data written so far to the storage device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if syncing fails.
+    pub fn sync(&self) -> FSResult<()> {
+        self.open
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .file
+            .sync_data()
+            .map_err(FSError::from)
+    }
+}
+
+#[cfg(test)]
+mod logfile_test {
+    use super::*;
+    use crate::fs::generate_test_path;
+
+    #[test]
+    fn rotation_and_threads() -> FSResult<()> {
+        let directory = generate_test_path();
+        let path = directory.join("logs/app.log");
+        let log = LogWriter::open(&path)?;
+        log.write_line("first")?;
+        log.write_line("second\nthird")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "first\nsecond\nthird\n");
+
+        let rotated = directory.join("logs/app.log.1");
+        std::fs::rename(&path, &rotated)?;
+        log.write_line("after rotation")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "after rotation\n");
+        std::fs::remove_file(&path)?;
+        log.write_line("after deletion")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "after deletion\n");
+        assert_eq!(std::fs::read_to_string(&rotated)?, "first\nsecond\nthird\n");
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let log = &log;
+                scope.spawn(move || {
+                    for line in 0..50 {
+                        log.write_line(format!("thread {thread} line {line}"))
+                            .expect("Writing to the log file should succeed");
+                    }
+                });
+            }
+        });
+        let content = std::fs::read_to_string(&path)?;
+        assert_eq!(content.lines().count(), 201);
+        assert!(content
+            .lines()
+            .skip(1)
+            .all(|line| line.starts_with("thread ") && line.split(' ').count() == 4));
+
+        let log = log.timestamps(true);
+        log.write_line("stamped")?;
+        log.sync()?;
+        let content = std::fs::read_to_string(&path)?;
+        let last = content.lines().next_back().unwrap_or_default();
+        assert!(last.ends_with("Z stamped"));
+        assert_eq!(last.find('T'), Some(10));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+//! This module contains functionality for writing structured records to journald
+//! and for querying the journal.
+//!
+//! Records are sent directly to journald's native socket, so every field ends up as
+//! a proper journal field instead of being flattened into the message. Queries are
+//! answered by `journalctl -o json`.
+
+use super::Priority;
+use crate::process::{
+    Command,
+    ProcessError,
+};
+
+/// The socket journald listens on for records in the native protocol.
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+/// `EMSGSIZE` on Linux: the datagram is larger than the socket's send buffer.
+const EMSGSIZE: i32 = 90;
+/// How many bytes of `MESSAGE` are kept when a record does not fit into a single
+/// datagram. This stays well below the default socket send buffer (208 KiB).
+const TRUNCATED_MESSAGE_LENGTH: usize = 64 * 1024;
+/// Appended to a message that was cut off at [`TRUNCATED_MESSAGE_LENGTH`].
+const TRUNCATION_MARKER: &str = " [truncated]";
+
+/// Describes possible errors when dealing with journald.
+#[derive(Debug, thiserror::Error)]
+pub enum JournaldError {
+    #[error("The field name '{0}' is not a valid journal field name")]
+    InvalidFieldName(String),
+    #[error("journald is not reachable: {0}")]
+    Unavailable(String),
+    #[error("Running journalctl failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("A journal entry could not be parsed: {0}")]
+    InvalidEntry(String),
+}
+
+/// A [`Result`] whose error variant is a [`JournaldError`].
+pub type JournaldResult<T> = Result<T, JournaldError>;
+
+/// Checks whether `name` may be used as a journal field name: upper-case letters,
+/// digits and underscores, not starting with an underscore or digit.
+fn is_valid_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Serialize a record in journald's native protocol. Values containing a newline are
+/// length-prefixed, all others are written as `NAME=value`.
+fn encode(priority: Priority, message: &str, fields: &[(&str, &str)]) -> JournaldResult<Vec<u8>> {
+    let mut datagram = Vec::new();
+    let mut push_field = |name: &str, value: &str| {
+        datagram.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    };
+
+    push_field("PRIORITY", &(priority as u8).to_string());
+    push_field("MESSAGE", message);
+    for (name, value) in fields {
+        if !is_valid_field_name(name) {
+            return Err(JournaldError::InvalidFieldName((*name).to_string()));
+        }
+        push_field(name, value);
+    }
+
+    Ok(datagram)
+}
+
+/// Cut `message` off after at most [`TRUNCATED_MESSAGE_LENGTH`] bytes (on a
+/// character boundary) and mark it as truncated. Shorter messages are returned
+/// as they are.
+fn truncate(message: &str) -> std::borrow::Cow<'_, str> {
+    if message.len() <= TRUNCATED_MESSAGE_LENGTH {
+        return std::borrow::Cow::Borrowed(message);
+    }
+
+    let mut end = TRUNCATED_MESSAGE_LENGTH;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    std::borrow::Cow::Owned(format!("{}{TRUNCATION_MARKER}", &message[..end]))
+}
+
+/// Send a record with additional `fields` (e.g. `("BACKUP_TARGET", "nas")`) to
+/// journald.
+///
+/// Every record is sent as a single datagram, which is limited by the socket's
+/// send buffer (208 KiB by default). If a record is too large, it is sent again
+/// with `MESSAGE` cut off after 64 KiB and ending in `[truncated]`; the other
+/// fields are never shortened.
+///
+/// # Errors
+///
+/// Returns an error if a field name is invalid, if journald cannot be reached or
+/// if the record does not fit into a datagram even with a truncated message.
+pub fn send(priority: Priority, message: &str, fields: &[(&str, &str)]) -> JournaldResult<()> {
+    let datagram = encode(priority, message, fields)?;
+    let socket = std::os::unix::net::UnixDatagram::unbound()
+        .map_err(|error| JournaldError::Unavailable(error.to_string()))?;
+    match socket.send_to(&datagram, JOURNALD_SOCKET) {
+        Err(error) if error.raw_os_error() == Some(EMSGSIZE) => {
+            let datagram = encode(priority, &truncate(message), fields)?;
+            socket
+                .send_to(&datagram, JOURNALD_SOCKET)
+                .map_err(|error| JournaldError::Unavailable(error.to_string()))?;
+        },
+        result => {
+            result.map_err(|error| JournaldError::Unavailable(error.to_string()))?;
+        },
+    }
+    Ok(())
+}
+
+/// A [`log::Log`] implementation that forwards every record to journald, attaching
+/// the module path, file and line of the record as fields.
+#[derive(Debug)]
+pub struct Logger {
+    /// Records with a more verbose level than this are dropped
+    level: log::LevelFilter,
+}
+
+impl Logger {
+    /// Create a logger that forwards all records up to `level`.
+    #[must_use]
+    pub const fn new(level: log::LevelFilter) -> Self { Self { level } }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool { metadata.level() <= self.level }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = record
+            .line()
+            .map(|line| line.to_string())
+            .unwrap_or_default();
+        let fields = [
+            ("CODE_MODULE", record.module_path().unwrap_or_default()),
+            ("CODE_FILE", record.file().unwrap_or_default()),
+            ("CODE_LINE", line.as_str()),
+            ("TARGET", record.target()),
+        ];
+        // There is nowhere sensible to report a failure to log.
+        let _ = send(record.level().into(), &record.args().to_string(), &fields);
+    }
+
+    fn flush(&self) {}
+}
+
+/// A single entry read from the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The point in time the entry was recorded
+    pub timestamp: std::time::SystemTime,
+    /// The priority of the entry, if it has one
+    pub priority:  Option<Priority>,
+    /// The message of the entry
+    pub message:   String,
+    /// The systemd unit that produced the entry, if any
+    pub unit:      Option<String>,
+    /// The process that produced the entry, if known
+    pub pid:       Option<u32>,
+    /// All fields of the entry, including the ones above
+    pub fields:    std::collections::HashMap<String, String>,
+}
+
+impl Entry {
+    /// Parse a single line of `journalctl -o json` output.
+    fn from_json(line: &str) -> JournaldResult<Self> {
+        let value: serde_json::Value = serde_json::from_str(line)
+            .map_err(|error| JournaldError::InvalidEntry(error.to_string()))?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| JournaldError::InvalidEntry(String::from("entry is not an object")))?;
+
+        let mut fields = std::collections::HashMap::new();
+        for (name, value) in object {
+            let value = match value {
+                serde_json::Value::String(string) => string.clone(),
+                // Non-UTF-8 values are encoded as an array of bytes.
+                serde_json::Value::Array(bytes) => String::from_utf8_lossy(
+                    &bytes
+                        .iter()
+                        .filter_map(serde_json::Value::as_u64)
+                        .filter_map(|byte| u8::try_from(byte).ok())
+                        .collect::<Vec<u8>>(),
+                )
+                .into_owned(),
+                serde_json::Value::Null => continue,
+                other => other.to_string(),
+            };
+            fields.insert(name.clone(), value);
+        }
+
+        let microseconds = fields
+            .get("__REALTIME_TIMESTAMP")
+            .and_then(|timestamp| timestamp.parse::<u64>().ok())
+            .ok_or_else(|| JournaldError::InvalidEntry(String::from("entry has no timestamp")))?;
+
+        Ok(Self {
+            timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_micros(microseconds),
+            priority: fields
+                .get("PRIORITY")
+                .and_then(|priority| priority.parse().ok())
+                .and_then(Priority::from_number),
+            message: fields.get("MESSAGE").cloned().unwrap_or_default(),
+            unit: fields
+                .get("_SYSTEMD_UNIT")
+                .or_else(|| fields.get("UNIT"))
+                .cloned(),
+            pid: fields.get("_PID").and_then(|pid| pid.parse().ok()),
+            fields,
+        })
+    }
+}
+
+/// Describes a query against the journal, e.g. "logs of unit X since time T".
+/// Nothing is read until [`Query::run`] is called.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Only return entries of these units
+    units:    Vec<String>,
+    /// Only return entries recorded at or after this point in time
+    since:    Option<std::time::SystemTime>,
+    /// Only return entries recorded at or before this point in time
+    until:    Option<std::time::SystemTime>,
+    /// Only return entries with this priority or a more important one
+    priority: Option<Priority>,
+    /// Only return the most recent entries
+    lines:    Option<usize>,
+}
+
+impl Query {
+    /// Create a query that matches all entries.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Only return entries of `unit`. Can be given multiple times.
+    #[must_use]
+    pub fn unit(mut self, unit: impl AsRef<str>) -> Self {
+        self.units.push(unit.as_ref().to_string());
+        self
+    }
+
+    /// Only return entries recorded at or after `time`.
+    #[must_use]
+    pub const fn since(mut self, time: std::time::SystemTime) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// Only return entries recorded at or before `time`.
+    #[must_use]
+    pub const fn until(mut self, time: std::time::SystemTime) -> Self {
+        self.until = Some(time);
+        self
+    }
+
+    /// Only return entries with `priority` or a more important one.
+    #[must_use]
+    pub const fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Only return the `lines` most recent entries.
+    #[must_use]
+    pub const fn lines(mut self, lines: usize) -> Self {
+        self.lines = Some(lines);
+        self
+    }
+
+    /// Formats a point in time the way `journalctl` expects it.
+    fn format_time(time: std::time::SystemTime) -> String {
+        let seconds = time
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        format!("@{seconds}")
+    }
+
+    /// Build the `journalctl` invocation that answers this query.
+    fn to_command(&self) -> Command {
+        let mut command =
+            Command::new("journalctl").args(["--output=json", "--no-pager", "--quiet"]);
+        for unit in &self.units {
+            command = command.arg(format!("--unit={unit}"));
+        }
+        if let Some(since) = self.since {
+            command = command.arg(format!("--since={}", Self::format_time(since)));
+        }
+        if let Some(until) = self.until {
+            command = command.arg(format!("--until={}", Self::format_time(until)));
+        }
+        if let Some(priority) = self.priority {
+            command = command.arg(format!("--priority={}", priority as u8));
+        }
+        if let Some(lines) = self.lines {
+            command = command.arg(format!("--lines={lines}"));
+        }
+        command
+    }
+
+    /// Read all matching entries, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `journalctl` fails or prints an entry that cannot be
+    /// parsed.
+    pub fn run(&self) -> JournaldResult<Vec<Entry>> {
+        self.to_command()
+            .run()?
+            .stdout
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Entry::from_json)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod journald_test {
+    use super::*;
+
+    #[test]
+    fn encode_fields() -> JournaldResult<()> {
+        let datagram = encode(Priority::Warning, "Hello", &[("BACKUP_TARGET", "nas")])?;
+        assert_eq!(datagram, b"PRIORITY=4\nMESSAGE=Hello\nBACKUP_TARGET=nas\n");
+
+        let datagram = encode(Priority::Error, "a\nb", &[])?;
+        let mut expected = b"PRIORITY=3\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&3_u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(datagram, expected);
+
+        assert!(encode(Priority::Error, "", &[("lower", "x")]).is_err());
+        assert!(encode(Priority::Error, "", &[("_PID", "1")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_long_messages() {
+        assert_eq!(truncate("short"), "short");
+
+        let exact = "a".repeat(TRUNCATED_MESSAGE_LENGTH);
+        assert_eq!(truncate(&exact), exact);
+
+        // The cut must not split the two-byte 'é' that straddles the limit.
+        let long = format!(
+            "{}é{}",
+            "a".repeat(TRUNCATED_MESSAGE_LENGTH - 1),
+            "b".repeat(10)
+        );
+        let truncated = truncate(&long);
+        assert!(truncated.ends_with(TRUNCATION_MARKER));
+        assert_eq!(
+            truncated.len(),
+            TRUNCATED_MESSAGE_LENGTH - 1 + TRUNCATION_MARKER.len()
+        );
+    }
+
+    #[test]
+    fn parse_entry() -> JournaldResult<()> {
+        let entry = Entry::from_json(concat!(
+            r#"{"__REALTIME_TIMESTAMP":"1700000000000000","PRIORITY":"6","MESSAGE":"Started.","#,
+            r#""_SYSTEMD_UNIT":"nginx.service","_PID":"42","BINARY":[104,105]}"#
+        ))?;
+        assert_eq!(
+            entry.timestamp,
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(entry.priority, Some(Priority::Informational));
+        assert_eq!(entry.message, "Started.");
+        assert_eq!(entry.unit.as_deref(), Some("nginx.service"));
+        assert_eq!(entry.pid, Some(42));
+        assert_eq!(entry.fields["BINARY"], "hi");
+
+        assert!(Entry::from_json(r#"{"MESSAGE":"no timestamp"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn query_command() {
+        let command = Query::new()
+            .unit("nginx.service")
+            .since(std::time::UNIX_EPOCH + std::time::Duration::from_secs(10))
+            .priority(Priority::Error)
+            .to_command();
+        assert_eq!(
+            command.arguments(),
+            [
+                "--output=json",
+                "--no-pager",
+                "--quiet",
+                "--unit=nginx.service",
+                "--since=@10",
+                "--priority=3"
+            ]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! This module contains functionality for emitting and reading logs beyond what the
+//! [`log`] crate offers on its own.
+
+#[cfg(unix)]
+pub mod journald;
+#[cfg(unix)]
+pub mod syslog;
+
+/// Describes possible errors when setting up logging.
+#[derive(Debug, thiserror::Error)]
+pub enum LoggingError {
+    #[error("A logger has already been installed")]
+    AlreadyInitialized,
+    #[error("The log sink could not be set up: {0}")]
+    Sink(String),
+}
+
+/// A [`Result`] whose error variant is a [`LoggingError`].
+pub type LoggingResult<T> = Result<T, LoggingError>;
+
+/// Where log records are written to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Sink {
+    /// Print records to standard output as `LEVEL - message`
+    Stdout,
+    /// Send records to journald, see [`journald::Logger`]
+    #[cfg(unix)]
+    Journald,
+    /// Send records to a syslog daemon, filed under the given facility, see
+    /// [`syslog::Logger`]
+    #[cfg(unix)]
+    Syslog(syslog::Target, syslog::Facility),
+}
+
+/// A [`log::Log`] implementation that prints records to standard output.
+#[derive(Debug)]
+struct StdoutLogger {
+    /// Records with a more verbose level than this are dropped
+    level: log::LevelFilter,
+}
+
+impl log::Log for StdoutLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool { metadata.level() <= self.level }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            println!("{} - {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install a logger that writes all records up to `level` to `sink`. This can only
+/// be done once per program.
+///
+/// # Errors
+///
+/// Returns an error if a logger was already installed or if the sink could not be
+/// set up (e.g. because the syslog socket does not exist).
+pub fn init(sink: &Sink, level: log::LevelFilter) -> LoggingResult<()> {
+    let logger: Box<dyn log::Log> = match sink {
+        Sink::Stdout => Box::new(StdoutLogger { level }),
+        #[cfg(unix)]
+        Sink::Journald => Box::new(journald::Logger::new(level)),
+        #[cfg(unix)]
+        Sink::Syslog(target, facility) => Box::new(
+            syslog::Logger::new(target, level)
+                .map_err(|error| LoggingError::Sink(error.to_string()))?
+                .facility(*facility),
+        ),
+    };
+
+    log::set_logger(Box::leak(logger)).map_err(|_| LoggingError::AlreadyInitialized)?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// The severity of a log record as defined by syslog (RFC 5424) and used by
+/// journald.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Emergency     = 0,
+    Alert         = 1,
+    Critical      = 2,
+    Error         = 3,
+    Warning       = 4,
+    Notice        = 5,
+    Informational = 6,
+    Debug         = 7,
+}
+
+impl Priority {
+    /// Converts the numeric value used on the wire back into a [`Priority`].
+    #[must_use]
+    pub const fn from_number(number: u8) -> Option<Self> {
+        match number {
+            0 => Some(Self::Emergency),
+            1 => Some(Self::Alert),
+            2 => Some(Self::Critical),
+            3 => Some(Self::Error),
+            4 => Some(Self::Warning),
+            5 => Some(Self::Notice),
+            6 => Some(Self::Informational),
+            7 => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl From<log::Level> for Priority {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Self::Error,
+            log::Level::Warn => Self::Warning,
+            log::Level::Info => Self::Informational,
+            log::Level::Debug | log::Level::Trace => Self::Debug,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! This module contains a [`log::Log`] implementation that sends records to a syslog
+//! daemon in the RFC 5424 format, either over UDP or over a Unix datagram socket.
+
+use super::Priority;
+
+/// The maximum length of the `HOSTNAME` header field (RFC 5424, section 6).
+const MAX_HOSTNAME_LENGTH: usize = 255;
+/// The maximum length of the `APP-NAME` header field (RFC 5424, section 6).
+const MAX_APP_NAME_LENGTH: usize = 48;
+
+/// Where syslog messages are sent to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// A remote (or local) syslog daemon listening on UDP, e.g. `"logs.internal:514"`
+    Udp(String),
+    /// A local Unix datagram socket, usually `/dev/log`
+    Unix(std::path::PathBuf),
+}
+
+impl Default for Target {
+    fn default() -> Self { Self::Unix(std::path::PathBuf::from("/dev/log")) }
+}
+
+/// The syslog facility records are filed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Facility {
+    User   = 1,
+    Daemon = 3,
+    Auth   = 4,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// The socket a [`Logger`] writes to.
+#[derive(Debug)]
+enum Socket {
+    /// A UDP socket connected to the syslog daemon
+    Udp(std::net::UdpSocket),
+    /// A Unix datagram socket connected to the syslog daemon
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+/// A [`log::Log`] implementation that sends every record to a syslog daemon.
+#[derive(Debug)]
+pub struct Logger {
+    /// Records with a more verbose level than this are dropped
+    level:    log::LevelFilter,
+    /// The facility that is part of every message
+    facility: Facility,
+    /// The `HOSTNAME` field of every message
+    hostname: String,
+    /// The `APP-NAME` field of every message
+    app_name: String,
+    /// The connected socket
+    socket:   Socket,
+}
+
+impl Logger {
+    /// Connect to the syslog daemon at `target`. Records are filed under
+    /// [`Facility::User`] unless [`Logger::facility`] is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be created or connected.
+    pub fn new(target: &Target, level: log::LevelFilter) -> std::io::Result<Self> {
+        let socket = match target {
+            Target::Udp(address) => {
+                use std::net::ToSocketAddrs;
+
+                let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("'{address}' does not resolve to any address"),
+                    )
+                })?;
+                // The local socket has to be of the same address family as the daemon.
+                let local_address = if address.is_ipv6() {
+                    "[::]:0"
+                } else {
+                    "0.0.0.0:0"
+                };
+                let socket = std::net::UdpSocket::bind(local_address)?;
+                socket.connect(address)?;
+                Socket::Udp(socket)
+            },
+            Target::Unix(path) => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Socket::Unix(socket)
+            },
+        };
+
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|hostname| header_field(hostname.trim(), MAX_HOSTNAME_LENGTH))
+            .unwrap_or_default();
+        let app_name = std::env::current_exe()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| header_field(&name.to_string_lossy(), MAX_APP_NAME_LENGTH))
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            level,
+            facility: Facility::User,
+            hostname,
+            app_name,
+            socket,
+        })
+    }
+
+    /// Use `facility` instead of [`Facility::User`].
+    #[must_use]
+    pub const fn facility(mut self, facility: Facility) -> Self {
+        self.facility = facility;
+        self
+    }
+
+    /// Send a single, already formatted message.
+    fn send(&self, message: &str) -> std::io::Result<()> {
+        match &self.socket {
+            Socket::Udp(socket) => socket.send(message.as_bytes())?,
+            Socket::Unix(socket) => socket.send(message.as_bytes())?,
+        };
+        Ok(())
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool { metadata.level() <= self.level }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = format_message(
+            self.facility,
+            record.level().into(),
+            std::time::SystemTime::now(),
+            &self.hostname,
+            &self.app_name,
+            &record.args().to_string(),
+        );
+        // There is nowhere sensible to report a failure to log.
+        let _ = self.send(&message);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Make `value` usable as a header field: RFC 5424 only allows printable ASCII
+/// without spaces there, so every other character is replaced with `_` and the
+/// result is cut off after `max_length` characters.
+fn header_field(value: &str, max_length: usize) -> String {
+    value
+        .chars()
+        .map(|character| {
+            if character.is_ascii_graphic() {
+                character
+            } else {
+                '_'
+            }
+        })
+        .take(max_length)
+        .collect()
+}
+
+/// Replace an empty header field with the RFC 5424 "nil value".
+const fn or_nil(field: &str) -> &str {
+    if field.is_empty() {
+        "-"
+    } else {
+        field
+    }
+}
+
+/// Format a message according to RFC 5424. No structured data is sent.
+fn format_message(
+    facility: Facility,
+    priority: Priority,
+    time: std::time::SystemTime,
+    hostname: &str,
+    app_name: &str,
+    message: &str,
+) -> String {
+    format!(
+        "<{}>1 {} {} {} {} - - {}",
+        facility as u8 * 8 + priority as u8,
+        crate::library::time::DateTime::from_system_time(time).to_rfc3339(),
+        or_nil(hostname),
+        or_nil(app_name),
+        std::process::id(),
+        message
+    )
+}
+
+#[cfg(test)]
+mod syslog_test {
+    use super::*;
+
+    #[test]
+    fn message() {
+        let message = format_message(
+            Facility::Local0,
+            Priority::Warning,
+            std::time::UNIX_EPOCH,
+            "host",
+            "",
+            "Disk almost full",
+        );
+        assert_eq!(
+            message,
+            format!(
+                "<132>1 1970-01-01T00:00:00.000Z host - {} - - Disk almost full",
+                std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn header_fields() {
+        assert_eq!(header_field("backup", MAX_APP_NAME_LENGTH), "backup");
+        assert_eq!(
+            header_field("my backup tool", MAX_APP_NAME_LENGTH),
+            "my_backup_tool"
+        );
+        assert_eq!(
+            header_field("sauvegarde-été", MAX_APP_NAME_LENGTH),
+            "sauvegarde-_t_"
+        );
+        assert_eq!(
+            header_field(&"a".repeat(100), MAX_APP_NAME_LENGTH).len(),
+            MAX_APP_NAME_LENGTH
+        );
+        assert_eq!(header_field("", MAX_HOSTNAME_LENGTH), "");
+    }
+
+    #[test]
+    fn send_over_udp() -> std::io::Result<()> {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0")?;
+        let logger = Logger::new(
+            &Target::Udp(server.local_addr()?.to_string()),
+            log::LevelFilter::Info,
+        )?;
+        logger.send("<14>1 - - - - - - hello")?;
+
+        let mut buffer = [0; 64];
+        let length = server.recv(&mut buffer)?;
+        assert_eq!(&buffer[..length], b"<14>1 - - - - - - hello");
+        Ok(())
+    }
+
+    #[test]
+    fn send_over_udp_ipv6() -> std::io::Result<()> {
+        let server = std::net::UdpSocket::bind("[::1]:0")?;
+        let logger = Logger::new(
+            &Target::Udp(server.local_addr()?.to_string()),
+            log::LevelFilter::Info,
+        )?;
+        logger.send("<14>1 - - - - - - hello")?;
+
+        let mut buffer = [0; 64];
+        let length = server.recv(&mut buffer)?;
+        assert_eq!(&buffer[..length], b"<14>1 - - - - - - hello");
+        Ok(())
+    }
+}
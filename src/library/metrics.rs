@@ -0,0 +1,483 @@
+//! This module contains functionality for reporting metrics in the format of the
+//! `node_exporter` textfile collector, which is how scripts run by cron usually report
+//! their health to Prometheus.
+
+use crate::fs::{
+    FSError,
+    File,
+    Object,
+};
+
+/// Describes possible errors when recording and writing metrics.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum MetricsError {
+    #[error("'{0}' is not a valid metric or label name")]
+    InvalidName(String),
+    #[error("Metric '{0}' was already recorded with a different type")]
+    TypeMismatch(String),
+    #[error("The label '{0}' is given more than once")]
+    DuplicateLabel(String),
+    #[error("Counter '{0}' cannot be decreased")]
+    NegativeIncrement(String),
+    #[error("Could not parse the existing textfile line '{0}'")]
+    InvalidLine(String),
+    #[error("Writing the textfile failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`MetricsError`].
+pub type MetricsResult<T> = Result<T, MetricsError>;
+
+/// The Prometheus type of a metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl std::fmt::Display for MetricType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Counter => write!(f, "counter"),
+            Self::Gauge => write!(f, "gauge"),
+        }
+    }
+}
+
+/// A metric with all its samples, keyed by their rendered label set.
+#[derive(Debug, Clone, PartialEq)]
+struct Metric {
+    /// The type of the metric
+    kind:    MetricType,
+    /// The optional `# HELP` text
+    help:    Option<String>,
+    /// The samples, keyed by their rendered label set (e.g. `{job="a"}`)
+    samples: std::collections::BTreeMap<String, f64>,
+}
+
+/// Checks whether `name` is a valid metric name (`label == false`) or label name
+/// (`label == true`).
+fn is_valid_name(name: &str, label: bool) -> bool {
+    let mut characters = name.chars();
+    let valid_first = |c: char| c.is_ascii_alphabetic() || c == '_' || (!label && c == ':');
+    characters.next().is_some_and(valid_first)
+        && characters.all(|c| valid_first(c) || c.is_ascii_digit())
+}
+
+/// Renders a label set as `{name="value",...}`, escaping the values. The labels are
+/// sorted by name, so the same label set always renders the same, regardless of
+/// the order the labels are given in. Names starting with `__` are reserved for
+/// Prometheus.
+fn render_labels(labels: &[(&str, &str)]) -> MetricsResult<String> {
+    if labels.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut labels = labels.to_vec();
+    labels.sort_unstable_by_key(|(name, _)| *name);
+    if let Some(window) = labels.windows(2).find(|window| window[0].0 == window[1].0) {
+        return Err(MetricsError::DuplicateLabel(window[0].0.to_string()));
+    }
+
+    let mut rendered = Vec::with_capacity(labels.len());
+    for (name, value) in labels {
+        if !is_valid_name(name, true) || name.starts_with("__") {
+            return Err(MetricsError::InvalidName(name.to_string()));
+        }
+        let value = value
+            .replace('\\', r"\\")
+            .replace('"', "\\\"")
+            .replace('\n', r"\n");
+        rendered.push(format!("{name}=\"{value}\""));
+    }
+    Ok(format!("{{{}}}", rendered.join(",")))
+}
+
+/// Renders a sample value, using the spelling of the exposition format for special
+/// values.
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value.is_infinite() {
+        String::from(
+            if value.is_sign_positive() {
+                "+Inf"
+            } else {
+                "-Inf"
+            },
+        )
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reverses the escaping of `# HELP` texts done by [`Textfile::render`].
+fn unescape_help(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        match (character, characters.clone().next()) {
+            ('\\', Some('n')) => {
+                unescaped.push('\n');
+                characters.next();
+            },
+            ('\\', Some('\\')) => {
+                unescaped.push('\\');
+                characters.next();
+            },
+            (character, _) => unescaped.push(character),
+        }
+    }
+    unescaped
+}
+
+/// Parses a sample value rendered by [`format_value`].
+fn parse_value(value: &str) -> Option<f64> {
+    match value {
+        "NaN" => Some(f64::NAN),
+        "+Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        value => value.parse().ok(),
+    }
+}
+
+/// A collection of metrics that is written to a single `.prom` file. Nothing is
+/// written until [`Textfile::write`] is called.
+///
+/// Counters only keep increasing across runs of a script if the textfile is
+/// created with [`Textfile::load`]; with [`Textfile::new`], they count the current
+/// run only.
+#[derive(Debug)]
+pub struct Textfile {
+    /// The file the metrics are written to
+    file:    File,
+    /// The recorded metrics, keyed by their name
+    metrics: std::collections::BTreeMap<String, Metric>,
+}
+
+impl Textfile {
+    /// Create an empty collection that is written to `path`, which should end in
+    /// `.prom` and live in the directory the textfile collector watches.
+    pub fn new(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            file:    File::new(path),
+            metrics: std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// Read the metrics previously written to `path`, so counters continue where
+    /// the last run left off. A missing file results in an empty collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or was not written by
+    /// [`Textfile::write`].
+    pub fn load(path: impl AsRef<std::path::Path>) -> MetricsResult<Self> {
+        let mut textfile = Self::new(&path);
+        let content = match std::fs::read_to_string(path.as_ref()) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(textfile),
+            Err(error) => return Err(FSError::from(error).into()),
+        };
+
+        let mut help = None;
+        let mut current: Option<String> = None;
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            let invalid = || MetricsError::InvalidLine(line.to_string());
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                let (_, text) = rest.split_once(' ').ok_or_else(invalid)?;
+                help = Some(unescape_help(text));
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let kind = match rest.split_once(' ').ok_or_else(invalid)? {
+                    (_, "counter") => MetricType::Counter,
+                    (_, "gauge") => MetricType::Gauge,
+                    _ => return Err(invalid()),
+                };
+                let name = rest
+                    .split_once(' ')
+                    .map(|(name, _)| name)
+                    .unwrap_or_default();
+                textfile.metric(name, kind)?.help = help.take();
+                current = Some(name.to_string());
+            } else {
+                let (series, value) = line.rsplit_once(' ').ok_or_else(invalid)?;
+                let value = parse_value(value).ok_or_else(invalid)?;
+                let (name, labels) = series
+                    .find('{')
+                    .map_or((series, ""), |index| (&series[..index], &series[index..]));
+                let metric = current
+                    .as_ref()
+                    .filter(|current| current.as_str() == name)
+                    .and_then(|name| textfile.metrics.get_mut(name))
+                    .ok_or_else(invalid)?;
+                metric.samples.insert(labels.to_string(), value);
+            }
+        }
+        Ok(textfile)
+    }
+
+    /// Look up (or create) the metric `name`, making sure it has `metric_type`.
+    fn metric(&mut self, name: &str, metric_type: MetricType) -> MetricsResult<&mut Metric> {
+        if !is_valid_name(name, false) {
+            return Err(MetricsError::InvalidName(name.to_string()));
+        }
+
+        let metric = self
+            .metrics
+            .entry(name.to_string())
+            .or_insert_with(|| Metric {
+                kind:    metric_type,
+                help:    None,
+                samples: std::collections::BTreeMap::new(),
+            });
+        if metric.kind == metric_type {
+            Ok(metric)
+        } else {
+            Err(MetricsError::TypeMismatch(name.to_string()))
+        }
+    }
+
+    /// Attach a `# HELP` text to the metric `name` of type `metric_type`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or the metric was already recorded
+    /// with a different type.
+    pub fn describe(
+        &mut self,
+        name: &str,
+        metric_type: MetricType,
+        help: &str,
+    ) -> MetricsResult<()> {
+        self.metric(name, metric_type)?.help = Some(help.to_string());
+        Ok(())
+    }
+
+    /// Set the gauge `name` with `labels` to `value`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name is invalid or the metric was already recorded as
+    /// a counter.
+    pub fn set_gauge(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) -> MetricsResult<()> {
+        let labels = render_labels(labels)?;
+        self.metric(name, MetricType::Gauge)?
+            .samples
+            .insert(labels, value);
+        Ok(())
+    }
+
+    /// Set the gauge `name` with `labels` to the current Unix time in seconds, e.g.
+    /// for `backup_last_success_timestamp_seconds`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name is invalid or the metric was already recorded as
+    /// a counter.
+    pub fn set_gauge_to_current_time(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> MetricsResult<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.set_gauge(name, labels, now)
+    }
+
+    /// Increase the counter `name` with `labels` by `amount`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name is invalid, the metric was already recorded as a
+    /// gauge, or `amount` is negative (counters never decrease).
+    pub fn increment_counter(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        amount: f64,
+    ) -> MetricsResult<()> {
+        if amount < 0.0 {
+            return Err(MetricsError::NegativeIncrement(name.to_string()));
+        }
+        let labels = render_labels(labels)?;
+        *self
+            .metric(name, MetricType::Counter)?
+            .samples
+            .entry(labels)
+            .or_insert(0.0) += amount;
+        Ok(())
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        use std::fmt::Write;
+
+        let mut rendered = String::new();
+        for (name, metric) in &self.metrics {
+            if let Some(help) = &metric.help {
+                let help = help.replace('\\', r"\\").replace('\n', r"\n");
+                let _ = writeln!(rendered, "# HELP {name} {help}");
+            }
+            let _ = writeln!(rendered, "# TYPE {name} {}", metric.kind);
+            for (labels, value) in &metric.samples {
+                let _ = writeln!(rendered, "{name}{labels} {}", format_value(*value));
+            }
+        }
+        rendered
+    }
+
+    /// Write all metrics to the file. The file is replaced atomically (see
+    /// [`File::write_atomic`]), so the collector never reads a partially written
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&self) -> MetricsResult<()> {
+        log::trace!("Writing metrics to {}", self.file);
+        if self.file.path().file_name().is_none() {
+            return Err(MetricsError::InvalidName(
+                self.file.path().to_string_lossy().into_owned(),
+            ));
+        }
+        self.file.write_atomic(self.render())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod textfile_test {
+    use super::*;
+
+    #[test]
+    fn render() -> MetricsResult<()> {
+        let mut textfile = Textfile::new("/unused.prom");
+        textfile.describe("backup_runs_total", MetricType::Counter, "Number of runs")?;
+        textfile.increment_counter("backup_runs_total", &[("target", "nas")], 1.0)?;
+        textfile.increment_counter("backup_runs_total", &[("target", "nas")], 2.0)?;
+        textfile.set_gauge("backup_size_bytes", &[], 1024.0)?;
+        textfile.set_gauge("backup_info", &[("path", "C:\\ \"x\"")], 1.0)?;
+
+        assert_eq!(
+            textfile.render(),
+            concat!(
+                "# TYPE backup_info gauge\n",
+                "backup_info{path=\"C:\\\\ \\\"x\\\"\"} 1\n",
+                "# HELP backup_runs_total Number of runs\n",
+                "# TYPE backup_runs_total counter\n",
+                "backup_runs_total{target=\"nas\"} 3\n",
+                "# TYPE backup_size_bytes gauge\n",
+                "backup_size_bytes 1024\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn label_order() -> MetricsResult<()> {
+        let mut textfile = Textfile::new("/unused.prom");
+        textfile.increment_counter("jobs_total", &[("b", "2"), ("a", "1")], 1.0)?;
+        textfile.increment_counter("jobs_total", &[("a", "1"), ("b", "2")], 1.0)?;
+        assert_eq!(
+            textfile.render(),
+            "# TYPE jobs_total counter\njobs_total{a=\"1\",b=\"2\"} 2\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn special_values() -> MetricsResult<()> {
+        let mut textfile = Textfile::new("/unused.prom");
+        textfile.set_gauge("a", &[], f64::INFINITY)?;
+        textfile.set_gauge("b", &[], f64::NEG_INFINITY)?;
+        textfile.set_gauge("c", &[], f64::NAN)?;
+        assert_eq!(
+            textfile.render(),
+            "# TYPE a gauge\na +Inf\n# TYPE b gauge\nb -Inf\n# TYPE c gauge\nc NaN\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn validation() {
+        let mut textfile = Textfile::new("/unused.prom");
+        assert!(textfile.set_gauge("0_invalid", &[], 1.0).is_err());
+        assert!(textfile
+            .set_gauge("valid", &[("in-valid", "")], 1.0)
+            .is_err());
+        assert!(textfile
+            .set_gauge("valid", &[("__name__", "")], 1.0)
+            .is_err());
+        assert_eq!(
+            textfile.set_gauge("valid", &[("job", "a"), ("job", "b")], 1.0),
+            Err(MetricsError::DuplicateLabel(String::from("job")))
+        );
+        assert!(textfile.set_gauge("valid", &[], 1.0).is_ok());
+        assert_eq!(
+            textfile.increment_counter("valid", &[], 1.0),
+            Err(MetricsError::TypeMismatch(String::from("valid")))
+        );
+        assert_eq!(
+            textfile.increment_counter("runs_total", &[], -1.0),
+            Err(MetricsError::NegativeIncrement(String::from("runs_total")))
+        );
+    }
+
+    #[test]
+    fn write() -> MetricsResult<()> {
+        let path = std::env::temp_dir().join(format!("rush-metrics-{}.prom", std::process::id()));
+        let mut textfile = Textfile::new(&path);
+        textfile.set_gauge("up", &[], 1.0)?;
+        textfile.write()?;
+        assert_eq!(
+            std::fs::read_to_string(&path).map_err(FSError::from)?,
+            "# TYPE up gauge\nup 1\n"
+        );
+        std::fs::remove_file(&path).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    #[test]
+    fn load() -> MetricsResult<()> {
+        let path = crate::fs::generate_test_path();
+        assert!(Textfile::load(&path)?.render().is_empty());
+
+        // In tests, dropping a `File` deletes it, so all textfiles are kept alive.
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let mut textfile = Textfile::load(&path)?;
+            textfile.describe("runs_total", MetricType::Counter, "Runs\nso far")?;
+            textfile.increment_counter("runs_total", &[("job", "a \"b\"")], 1.0)?;
+            textfile.set_gauge("last_size", &[], f64::INFINITY)?;
+            textfile.write()?;
+            runs.push(textfile);
+        }
+
+        assert_eq!(
+            Textfile::load(&path)?.render(),
+            concat!(
+                "# TYPE last_size gauge\n",
+                "last_size +Inf\n",
+                "# HELP runs_total Runs\\nso far\n",
+                "# TYPE runs_total counter\n",
+                "runs_total{job=\"a \\\"b\\\"\"} 2\n",
+            )
+        );
+
+        std::fs::write(&path, "garbage\n").map_err(FSError::from)?;
+        assert!(matches!(
+            Textfile::load(&path),
+            Err(MetricsError::InvalidLine(_))
+        ));
+        assert_eq!(runs.len(), 2);
+        Ok(())
+    }
+}
@@ -1,2 +1,80 @@
+// Building for WASI is opt-in because modules running programs or talking to the
+// network are left out there.
+#[cfg(all(target_os = "wasi", not(feature = "wasi")))]
+compile_error!("building for WASI requires the `wasi` feature");
+
+#[cfg(all(any(feature = "http", feature = "smtp"), not(target_os = "wasi")))]
+pub mod alert;
+#[cfg(not(target_os = "wasi"))]
+pub mod backup;
+#[cfg(not(target_os = "wasi"))]
+pub mod bench;
+pub mod cli;
+pub mod crypto;
+#[cfg(feature = "embed")]
+pub mod embed;
+#[cfg(all(feature = "database", not(target_os = "wasi")))]
+pub mod db;
+pub mod deadline;
+#[cfg(not(target_os = "wasi"))]
+pub mod debug;
+#[cfg(not(target_os = "wasi"))]
+pub mod diagnostics;
+#[cfg(unix)]
+pub mod dotfiles;
+#[cfg(unix)]
+pub mod ensure;
 pub mod environment;
+#[cfg(all(feature = "ffi", unix))]
+pub mod ffi;
+#[cfg(all(feature = "http", not(target_os = "wasi")))]
+pub mod forge;
 pub mod fs;
+#[cfg(not(target_os = "wasi"))]
+pub mod health;
+#[cfg(not(target_os = "wasi"))]
+pub mod iac;
+pub mod instrument;
+pub mod inventory;
+#[cfg(unix)]
+pub mod ipc;
+#[cfg(not(target_os = "wasi"))]
+pub mod k8s;
+pub mod lock;
+pub mod logfile;
+pub mod logging;
+pub mod metrics;
+#[cfg(not(target_os = "wasi"))]
+pub mod net;
+#[cfg(not(target_os = "wasi"))]
+pub mod pipeline;
+pub mod policy;
+#[cfg(unix)]
+pub mod privileged;
+#[cfg(not(target_os = "wasi"))]
+pub mod process;
+pub mod queue;
+#[cfg(not(target_os = "wasi"))]
+pub mod remote;
+#[cfg(not(target_os = "wasi"))]
+pub mod repl;
+pub mod scaffold;
+#[cfg(not(target_os = "wasi"))]
+pub mod secrets;
+#[cfg(all(feature = "http", not(target_os = "wasi")))]
+pub mod selfupdate;
+pub mod state;
+#[cfg(feature = "sqlite")]
+pub mod store;
+#[cfg(not(target_os = "wasi"))]
+pub mod system;
+pub mod task;
+#[cfg(not(target_os = "wasi"))]
+pub mod virt;
+pub mod text;
+mod template;
+mod time;
+
+/// The pure parsing and matching logic, which is also usable without std.
+pub use rush_core;
+pub use task::scope;
@@ -0,0 +1,537 @@
+//! This module contains a client for the instance metadata services of AWS, Google
+//! Cloud and Azure.
+//!
+//! Bootstrap scripts on cloud instances usually start by asking these for the
+//! instance's identity, region, tags and credentials. On AWS, `IMDSv2` session
+//! tokens are used.
+//!
+//! All clouds serve their metadata on the link-local address `169.254.169.254`, but
+//! each speaks its own dialect; [`Client`] hides the differences.
+
+use crate::system::CloudProvider;
+
+/// The address all supported clouds serve their metadata on.
+const METADATA_ADDRESS: &str = "169.254.169.254:80";
+/// How long to wait for the metadata endpoint to accept a connection.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+/// How long to wait for the metadata endpoint to answer a request.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// How long an `IMDSv2` session token is requested for, in seconds.
+const AWS_TOKEN_TTL: u64 = 6 * 60 * 60;
+/// The Azure instance metadata API version that is used.
+const AZURE_API_VERSION: &str = "2021-02-01";
+
+/// Describes possible errors when querying the instance metadata service.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum CloudMetadataError {
+    #[error("No instance metadata service is reachable")]
+    NotInCloud,
+    #[error("The instance metadata service of {0:?} is not supported")]
+    Unsupported(CloudProvider),
+    #[error("The request for '{0}' failed: {1}")]
+    Request(String, String),
+    #[error("The response could not be understood: {0}")]
+    InvalidResponse(String),
+}
+
+/// A [`Result`] whose error variant is a [`CloudMetadataError`].
+pub type CloudMetadataResult<T> = Result<T, CloudMetadataError>;
+
+/// Temporary credentials of the identity attached to the instance.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Credentials of the IAM role attached to an AWS instance
+    Aws {
+        /// The access key ID
+        access_key_id:     String,
+        /// The secret access key
+        secret_access_key: String,
+        /// The session token that has to accompany the key
+        session_token:     String,
+        /// When the credentials expire, as an RFC 3339 timestamp
+        expiration:        String,
+    },
+    /// An `OAuth2` access token of the service account (GCP) or managed identity
+    /// (Azure) attached to the instance
+    Bearer {
+        /// The access token
+        access_token: String,
+        /// How long the token stays valid
+        expires_in:   std::time::Duration,
+    },
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Aws {
+                access_key_id,
+                expiration,
+                ..
+            } => f
+                .debug_struct("Aws")
+                .field("access_key_id", access_key_id)
+                .field("expiration", expiration)
+                .finish_non_exhaustive(),
+            Self::Bearer { expires_in, .. } => f
+                .debug_struct("Bearer")
+                .field("expires_in", expires_in)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// A client for the instance metadata service of the cloud the program runs in.
+#[derive(Debug)]
+pub struct Client {
+    /// The cloud whose dialect is spoken
+    provider:  CloudProvider,
+    /// The scheme and authority all requests go to
+    base:      String,
+    /// The HTTP agent, which enforces timeouts
+    agent:     ureq::Agent,
+    /// The current `IMDSv2` session token and when it has to be renewed (AWS only)
+    aws_token: std::sync::Mutex<Option<(String, std::time::Instant)>>,
+}
+
+/// Find out which cloud serves the metadata endpoint by asking in each cloud's
+/// dialect. Nothing is probed if the endpoint does not accept connections, so this
+/// returns quickly outside of clouds.
+#[must_use]
+pub fn detect() -> Option<CloudProvider> {
+    use std::net::ToSocketAddrs;
+
+    let address = METADATA_ADDRESS.to_socket_addrs().ok()?.next()?;
+    std::net::TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).ok()?;
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(CONNECT_TIMEOUT * 2)
+        .redirects(0)
+        .build();
+    let base = format!("http://{METADATA_ADDRESS}");
+    let answers = |request: ureq::Request| request.call().is_ok();
+
+    if agent
+        .get(&format!("{base}/computeMetadata/v1/"))
+        .set("Metadata-Flavor", "Google")
+        .call()
+        .is_ok_and(|response| response.header("Metadata-Flavor") == Some("Google"))
+    {
+        Some(CloudProvider::Gcp)
+    } else if answers(
+        agent
+            .put(&format!("{base}/latest/api/token"))
+            .set("X-aws-ec2-metadata-token-ttl-seconds", "60"),
+    ) {
+        Some(CloudProvider::Aws)
+    } else if answers(
+        agent
+            .get(&format!(
+                "{base}/metadata/instance?api-version={AZURE_API_VERSION}"
+            ))
+            .set("Metadata", "true"),
+    ) {
+        Some(CloudProvider::Azure)
+    } else if answers(agent.get(&format!("{base}/hetzner/v1/metadata"))) {
+        Some(CloudProvider::Hetzner)
+    } else if answers(agent.get(&format!("{base}/metadata/v1/id"))) {
+        Some(CloudProvider::DigitalOcean)
+    } else if answers(agent.get(&format!("{base}/openstack/latest/meta_data.json"))) {
+        Some(CloudProvider::OpenStack)
+    } else {
+        None
+    }
+}
+
+impl Client {
+    /// Create a client for the metadata service of `provider`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if typed access to the metadata of `provider` is not
+    /// supported (only AWS, GCP and Azure are).
+    pub fn new(provider: CloudProvider) -> CloudMetadataResult<Self> {
+        Self::with_base(provider, format!("http://{METADATA_ADDRESS}"))
+    }
+
+    /// Create a client for the cloud the program runs in, see [`detect`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no metadata service is reachable or if it belongs to a
+    /// cloud that is not supported.
+    pub fn detect() -> CloudMetadataResult<Self> {
+        Self::new(detect().ok_or(CloudMetadataError::NotInCloud)?)
+    }
+
+    /// Create a client that sends its requests to `base` instead of the link-local
+    /// address.
+    fn with_base(provider: CloudProvider, base: String) -> CloudMetadataResult<Self> {
+        if !matches!(
+            provider,
+            CloudProvider::Aws | CloudProvider::Gcp | CloudProvider::Azure
+        ) {
+            return Err(CloudMetadataError::Unsupported(provider));
+        }
+        Ok(Self {
+            provider,
+            base,
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .redirects(0)
+                .build(),
+            aws_token: std::sync::Mutex::new(None),
+        })
+    }
+
+    /// The cloud this client talks to.
+    #[must_use]
+    pub const fn provider(&self) -> CloudProvider { self.provider }
+
+    /// Return a valid `IMDSv2` session token, requesting a new one if needed.
+    fn aws_token(&self) -> CloudMetadataResult<String> {
+        let mut token = self
+            .aws_token
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some((token, renew_at)) = token.as_ref() {
+            if std::time::Instant::now() < *renew_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let path = "/latest/api/token";
+        let value = self
+            .agent
+            .put(&format!("{}{path}", self.base))
+            .set(
+                "X-aws-ec2-metadata-token-ttl-seconds",
+                &AWS_TOKEN_TTL.to_string(),
+            )
+            .call()
+            .map_err(|error| request_error(path, error))?
+            .into_string()
+            .map_err(|error| CloudMetadataError::InvalidResponse(error.to_string()))?;
+        // Renew well before the token expires so that it is never sent stale.
+        let renew_at =
+            std::time::Instant::now() + std::time::Duration::from_secs(AWS_TOKEN_TTL / 2);
+        *token = Some((value.clone(), renew_at));
+        drop(token);
+        Ok(value)
+    }
+
+    /// Read the raw value at `path`, e.g. `/latest/meta-data/instance-type` (AWS),
+    /// `/computeMetadata/v1/instance/machine-type` (GCP) or
+    /// `/metadata/instance/compute/vmSize?api-version=2021-02-01&format=text`
+    /// (Azure). The headers the cloud requires are added.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response is not text.
+    pub fn get(&self, path: &str) -> CloudMetadataResult<String> {
+        log::trace!("Reading instance metadata '{path}'");
+        let request = self.agent.get(&format!("{}{path}", self.base));
+        let request = match self.provider {
+            CloudProvider::Aws => request.set("X-aws-ec2-metadata-token", &self.aws_token()?),
+            CloudProvider::Gcp => request.set("Metadata-Flavor", "Google"),
+            _ => request.set("Metadata", "true"),
+        };
+        request
+            .call()
+            .map_err(|error| request_error(path, error))?
+            .into_string()
+            .map_err(|error| CloudMetadataError::InvalidResponse(error.to_string()))
+    }
+
+    /// Read the value at `path` and parse it as JSON.
+    fn get_json(&self, path: &str) -> CloudMetadataResult<serde_json::Value> {
+        serde_json::from_str(&self.get(path)?)
+            .map_err(|error| CloudMetadataError::InvalidResponse(error.to_string()))
+    }
+
+    /// The Azure compute metadata, which contains everything typed access needs.
+    fn azure_compute(&self) -> CloudMetadataResult<serde_json::Value> {
+        self.get_json(&format!(
+            "/metadata/instance/compute?api-version={AZURE_API_VERSION}"
+        ))
+    }
+
+    /// The ID of the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata service cannot be queried.
+    pub fn instance_id(&self) -> CloudMetadataResult<String> {
+        match self.provider {
+            CloudProvider::Aws => self.get("/latest/meta-data/instance-id"),
+            CloudProvider::Gcp => self.get("/computeMetadata/v1/instance/id"),
+            _ => json_string(&self.azure_compute()?, "vmId"),
+        }
+    }
+
+    /// The region the instance runs in, e.g. `eu-central-1` (AWS), `europe-west3`
+    /// (GCP) or `germanywestcentral` (Azure).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata service cannot be queried.
+    pub fn region(&self) -> CloudMetadataResult<String> {
+        match self.provider {
+            CloudProvider::Aws => self.get("/latest/meta-data/placement/region"),
+            CloudProvider::Gcp => Ok(gcp_region(&self.get("/computeMetadata/v1/instance/zone")?)),
+            _ => json_string(&self.azure_compute()?, "location"),
+        }
+    }
+
+    /// The tags of the instance. On AWS, access to tags in the instance metadata has
+    /// to be enabled. On GCP, the custom metadata attributes are returned, because
+    /// labels are not exposed to the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata service cannot be queried.
+    pub fn tags(&self) -> CloudMetadataResult<std::collections::BTreeMap<String, String>> {
+        match self.provider {
+            CloudProvider::Aws => {
+                let keys = self.get("/latest/meta-data/tags/instance")?;
+                keys.lines()
+                    .filter(|key| !key.is_empty())
+                    .map(|key| {
+                        let value = self.get(&format!(
+                            "/latest/meta-data/tags/instance/{}",
+                            super::uri_encode(key, false)
+                        ))?;
+                        Ok((key.to_string(), value))
+                    })
+                    .collect()
+            },
+            CloudProvider::Gcp => parse_gcp_attributes(
+                &self.get_json("/computeMetadata/v1/instance/attributes/?recursive=true")?,
+            ),
+            _ => parse_azure_tags(&self.azure_compute()?),
+        }
+    }
+
+    /// Temporary credentials of the IAM role (AWS), the default service account
+    /// (GCP) or the managed identity (Azure, for Azure Resource Manager) attached to
+    /// the instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata service cannot be queried or no identity is
+    /// attached to the instance.
+    pub fn credentials(&self) -> CloudMetadataResult<Credentials> {
+        match self.provider {
+            CloudProvider::Aws => {
+                let path = "/latest/meta-data/iam/security-credentials/";
+                let role = self.get(path)?;
+                let role = role.lines().next().ok_or_else(|| {
+                    CloudMetadataError::InvalidResponse(String::from(
+                        "no IAM role is attached to the instance",
+                    ))
+                })?;
+                parse_aws_credentials(&self.get_json(&format!("{path}{role}"))?)
+            },
+            CloudProvider::Gcp => parse_bearer_token(
+                &self.get_json("/computeMetadata/v1/instance/service-accounts/default/token")?,
+            ),
+            _ => parse_bearer_token(&self.get_json(&format!(
+                "/metadata/identity/oauth2/token?api-version=2018-02-01&resource={}",
+                super::uri_encode("https://management.azure.com/", false)
+            ))?),
+        }
+    }
+}
+
+/// Describe a failed request without its URL, which only repeats the path.
+fn request_error(path: &str, error: ureq::Error) -> CloudMetadataError {
+    CloudMetadataError::Request(
+        path.to_string(),
+        match error {
+            ureq::Error::Status(code, _) => format!("the service answered with status {code}"),
+            ureq::Error::Transport(transport) => transport.kind().to_string(),
+        },
+    )
+}
+
+/// The string member `name` of `value`.
+fn json_string(value: &serde_json::Value, name: &str) -> CloudMetadataResult<String> {
+    value[name]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| CloudMetadataError::InvalidResponse(format!("no '{name}' in the response")))
+}
+
+/// Turn a GCP zone (`projects/<number>/zones/europe-west3-a`) into its region
+/// (`europe-west3`).
+fn gcp_region(zone: &str) -> String {
+    let zone = zone.rsplit('/').next().unwrap_or(zone);
+    zone.rsplit_once('-')
+        .map_or(zone, |(region, _)| region)
+        .to_string()
+}
+
+/// Parse the custom metadata attributes of a GCP instance.
+fn parse_gcp_attributes(
+    value: &serde_json::Value,
+) -> CloudMetadataResult<std::collections::BTreeMap<String, String>> {
+    let attributes = value.as_object().ok_or_else(|| {
+        CloudMetadataError::InvalidResponse(String::from("attributes are not an object"))
+    })?;
+    Ok(attributes
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), String::from);
+            (key.clone(), value)
+        })
+        .collect())
+}
+
+/// Parse the `tagsList` of the Azure compute metadata.
+fn parse_azure_tags(
+    compute: &serde_json::Value,
+) -> CloudMetadataResult<std::collections::BTreeMap<String, String>> {
+    compute["tagsList"]
+        .as_array()
+        .ok_or_else(|| {
+            CloudMetadataError::InvalidResponse(String::from("no 'tagsList' in the response"))
+        })?
+        .iter()
+        .map(|tag| Ok((json_string(tag, "name")?, json_string(tag, "value")?)))
+        .collect()
+}
+
+/// Parse the credentials of an AWS IAM role.
+fn parse_aws_credentials(value: &serde_json::Value) -> CloudMetadataResult<Credentials> {
+    Ok(Credentials::Aws {
+        access_key_id:     json_string(value, "AccessKeyId")?,
+        secret_access_key: json_string(value, "SecretAccessKey")?,
+        session_token:     json_string(value, "Token")?,
+        expiration:        json_string(value, "Expiration")?,
+    })
+}
+
+/// Parse an `OAuth2` token response. GCP sends `expires_in` as a number, Azure as a
+/// string.
+fn parse_bearer_token(value: &serde_json::Value) -> CloudMetadataResult<Credentials> {
+    let expires_in = value["expires_in"]
+        .as_u64()
+        .or_else(|| value["expires_in"].as_str()?.parse().ok())
+        .ok_or_else(|| {
+            CloudMetadataError::InvalidResponse(String::from("no 'expires_in' in the response"))
+        })?;
+    Ok(Credentials::Bearer {
+        access_token: json_string(value, "access_token")?,
+        expires_in:   std::time::Duration::from_secs(expires_in),
+    })
+}
+
+#[cfg(test)]
+mod cloud_metadata_test {
+    use super::*;
+
+    #[test]
+    fn parse_responses() -> CloudMetadataResult<()> {
+        assert_eq!(
+            gcp_region("projects/1234/zones/europe-west3-a"),
+            "europe-west3"
+        );
+        assert_eq!(gcp_region("us-central1-f"), "us-central1");
+
+        let tags = parse_azure_tags(&serde_json::json!({
+            "tagsList": [{"name": "role", "value": "web"}, {"name": "env", "value": "prod"}]
+        }))?;
+        assert_eq!(tags["role"], "web");
+        assert_eq!(tags["env"], "prod");
+
+        let attributes = parse_gcp_attributes(&serde_json::json!({"role": "db"}))?;
+        assert_eq!(attributes["role"], "db");
+
+        let credentials = parse_aws_credentials(&serde_json::json!({
+            "Code": "Success",
+            "AccessKeyId": "ASIAEXAMPLE",
+            "SecretAccessKey": "secret",
+            "Token": "session",
+            "Expiration": "2024-01-01T00:00:00Z"
+        }))?;
+        assert!(!format!("{credentials:?}").contains("secret"));
+        assert!(!format!("{credentials:?}").contains("session"));
+
+        assert_eq!(
+            parse_bearer_token(&serde_json::json!({"access_token": "t", "expires_in": "3599"}))?,
+            Credentials::Bearer {
+                access_token: String::from("t"),
+                expires_in:   std::time::Duration::from_secs(3599),
+            }
+        );
+        assert!(parse_bearer_token(&serde_json::json!({"access_token": "t"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn aws_session_token() -> CloudMetadataResult<()> {
+        use std::io::{
+            BufRead,
+            Write,
+        };
+
+        // A metadata service that hands out a token and then expects it on every
+        // request.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .map_err(|error| CloudMetadataError::Request(String::new(), error.to_string()))?;
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push_str(&line.to_ascii_lowercase());
+                }
+                let body = if request.starts_with("put /latest/api/token") {
+                    "token"
+                } else if request.contains("x-aws-ec2-metadata-token: token") {
+                    "i-0123"
+                } else {
+                    ""
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let client = Client::with_base(CloudProvider::Aws, base)?;
+        assert_eq!(client.instance_id()?, "i-0123");
+        assert_eq!(client.instance_id()?, "i-0123");
+        // The token is requested once and then reused.
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|request| request.starts_with("put"))
+                .count(),
+            1
+        );
+
+        assert_eq!(
+            Client::new(CloudProvider::Hetzner).err(),
+            Some(CloudMetadataError::Unsupported(CloudProvider::Hetzner))
+        );
+        Ok(())
+    }
+}
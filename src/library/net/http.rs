@@ -0,0 +1,639 @@
+//! This module contains a minimal static file server for sharing a directory ad hoc,
+//! e.g. during provisioning or debugging, in place of `python3 -m http.server`.
+
+use std::io::{
+    BufRead,
+    Read,
+    Write,
+};
+
+/// Describes possible errors when serving files.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ServeError {
+    #[error("'{0}' is not a directory")]
+    NotADirectory(std::path::PathBuf),
+    #[error("Could not bind to '{0}': {1}")]
+    Bind(String, String),
+    #[error("The server thread panicked")]
+    Panicked,
+}
+
+/// A [`Result`] whose error variant is a [`ServeError`].
+pub type ServeResult<T> = Result<T, ServeError>;
+
+/// How long to sleep when no connection is pending before checking for shutdown again.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// The maximum length of the request line and of every header line, in bytes.
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+
+/// The maximum number of headers of a request.
+const MAX_HEADERS: usize = 100;
+
+/// Tells the threads of a server when to stop.
+#[derive(Debug, Clone)]
+struct Shutdown {
+    /// Set when the [`ServerHandle`] is dropped
+    stop:     std::sync::Arc<std::sync::atomic::AtomicBool>,
+    /// When the shutdown timeout expires, if there is one
+    deadline: Option<std::time::Instant>,
+}
+
+impl Shutdown {
+    /// Whether the server has to stop.
+    fn is_due(&self) -> bool {
+        self.stop.load(std::sync::atomic::Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| std::time::Instant::now() >= deadline)
+    }
+}
+
+/// Encode `input` as standard base64 with padding.
+fn base64(input: &[u8]) -> String {
+    /// The characters the 64 values of six bits are encoded as.
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let byte = |index: usize| u32::from(chunk.get(index).copied().unwrap_or(0));
+        let group = byte(0) << 16 | byte(1) << 8 | byte(2);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(char::from(
+                    ALPHABET[(group >> (18 - 6 * index) & 63) as usize],
+                ));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode `%XX` escapes in a URL path. Returns [`None`] for malformed escapes or
+/// paths that are not valid UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = char::from(bytes.next()?).to_digit(16)?;
+            let low = char::from(bytes.next()?).to_digit(16)?;
+            decoded.push(u8::try_from(high << 4 | low).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Whether `a` and `b` are equal, taking the same time wherever they differ, so
+/// that the time a comparison takes does not reveal how much of a credential was
+/// guessed correctly. Only the length may leak.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Escape `text` for use in HTML.
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Guess the `Content-Type` of a file from its extension.
+fn content_type(path: &std::path::Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt" | "log" | "md" | "sh" | "toml" | "yaml" | "yml") => "text/plain; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A request as far as the server cares about it.
+struct Request {
+    /// The request method, e.g. `GET`
+    method:        String,
+    /// The decoded path, without query string
+    path:          String,
+    /// The value of the `Authorization` header, if present
+    authorization: Option<String>,
+}
+
+impl Request {
+    /// Read a line of at most [`MAX_LINE_LENGTH`] bytes into `line`, replacing its
+    /// content. Returns [`None`] if the line is longer or cannot be read.
+    fn read_line(reader: &mut impl BufRead, line: &mut String) -> Option<usize> {
+        line.clear();
+        let length = reader.by_ref().take(MAX_LINE_LENGTH).read_line(line).ok()?;
+        (length < usize::try_from(MAX_LINE_LENGTH).ok()? || line.ends_with('\n')).then_some(length)
+    }
+
+    /// Read the request line and headers from `reader`. Returns [`None`] if the
+    /// request is malformed or exceeds [`MAX_LINE_LENGTH`] or [`MAX_HEADERS`].
+    fn read(reader: &mut impl BufRead) -> Option<Self> {
+        let mut line = String::new();
+        Self::read_line(reader, &mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?;
+        let path = percent_decode(target.split(['?', '#']).next()?)?;
+
+        let mut authorization = None;
+        for headers in 0.. {
+            if Self::read_line(reader, &mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if headers == MAX_HEADERS {
+                return None;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("authorization") {
+                    authorization = Some(value.trim().to_string());
+                }
+            }
+        }
+
+        Some(Self {
+            method,
+            path,
+            authorization,
+        })
+    }
+}
+
+/// Serves the files below a directory over HTTP.
+///
+/// ```no_run
+/// # use rush::net::FileServer;
+/// let server = FileServer::new("/srv/share", "0.0.0.0:8000")
+///     .basic_auth("user", "password")
+///     .shutdown_after(std::time::Duration::from_secs(30 * 60))
+///     .start()
+///     .unwrap();
+/// println!("Serving on {}", server.local_addr());
+/// server.wait().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileServer {
+    /// The directory whose files are served
+    directory:      std::path::PathBuf,
+    /// The address to listen on
+    bind_addr:      String,
+    /// The expected `Authorization` header value, if basic auth is required
+    authorization:  Option<String>,
+    /// After how long the server stops on its own
+    shutdown_after: Option<std::time::Duration>,
+}
+
+impl FileServer {
+    /// Describe a server for the files below `directory` listening on `bind_addr`
+    /// (e.g. `0.0.0.0:8000`). It does not listen until [`FileServer::start`] is
+    /// called.
+    pub fn new(directory: impl AsRef<std::path::Path>, bind_addr: impl AsRef<str>) -> Self {
+        Self {
+            directory:      directory.as_ref().to_path_buf(),
+            bind_addr:      bind_addr.as_ref().to_string(),
+            authorization:  None,
+            shutdown_after: None,
+        }
+    }
+
+    /// Require clients to authenticate with HTTP basic auth as `user` with
+    /// `password`. Note that credentials are sent in plain text over HTTP.
+    #[must_use]
+    pub fn basic_auth(mut self, user: &str, password: &str) -> Self {
+        self.authorization = Some(format!(
+            "Basic {}",
+            base64(format!("{user}:{password}").as_bytes())
+        ));
+        self
+    }
+
+    /// Stop serving after `duration`, so a forgotten server does not keep sharing
+    /// files. Transfers still running at that point are aborted.
+    #[must_use]
+    pub const fn shutdown_after(mut self, duration: std::time::Duration) -> Self {
+        self.shutdown_after = Some(duration);
+        self
+    }
+
+    /// Start serving in a background thread. The server stops when the returned
+    /// handle is dropped or the shutdown timeout expires, aborting running transfers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory does not exist or the address cannot be
+    /// bound.
+    pub fn start(self) -> ServeResult<ServerHandle> {
+        let directory = self
+            .directory
+            .canonicalize()
+            .ok()
+            .filter(|directory| directory.is_dir())
+            .ok_or_else(|| ServeError::NotADirectory(self.directory.clone()))?;
+        let to_error =
+            |error: std::io::Error| ServeError::Bind(self.bind_addr.clone(), error.to_string());
+        let listener = std::net::TcpListener::bind(&self.bind_addr).map_err(to_error)?;
+        listener.set_nonblocking(true).map_err(to_error)?;
+        let local_addr = listener.local_addr().map_err(to_error)?;
+        log::info!(
+            "Serving '{}' on http://{local_addr}",
+            directory.to_string_lossy()
+        );
+
+        let shutdown = Shutdown {
+            stop:     std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            deadline: self
+                .shutdown_after
+                .map(|duration| std::time::Instant::now() + duration),
+        };
+        let stop = shutdown.stop.clone();
+        let thread = std::thread::spawn(move || {
+            while !shutdown.is_due() {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        let directory = directory.clone();
+                        let authorization = self.authorization.clone();
+                        let shutdown = shutdown.clone();
+                        std::thread::spawn(move || {
+                            if let Err(error) =
+                                handle(stream, &directory, authorization.as_deref(), &shutdown)
+                            {
+                                log::debug!("Connection from {peer} failed: {error}");
+                            }
+                        });
+                    },
+                    Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(POLL_INTERVAL);
+                    },
+                    Err(error) => log::warn!("Accepting a connection failed: {error}"),
+                }
+            }
+            log::info!("Stopped serving on http://{local_addr}");
+        });
+
+        Ok(ServerHandle {
+            local_addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Resolve symbolic links in `path` and make sure the result is still below the
+/// (canonical) `directory`. Otherwise, returns the status to answer with.
+fn confine(
+    directory: &std::path::Path,
+    path: &std::path::Path,
+) -> Result<std::path::PathBuf, &'static str> {
+    let canonical = path.canonicalize().map_err(|_| "404 Not Found")?;
+    if canonical.starts_with(directory) {
+        Ok(canonical)
+    } else {
+        Err("403 Forbidden")
+    }
+}
+
+/// Copy `file` to `stream` until it ends or `shutdown` is due.
+fn transfer(
+    file: &mut std::fs::File,
+    stream: &mut impl Write,
+    shutdown: &Shutdown,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        if shutdown.is_due() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "the server is shutting down",
+            ));
+        }
+        let length = file.read(&mut buffer)?;
+        if length == 0 {
+            return Ok(());
+        }
+        stream.write_all(&buffer[..length])?;
+    }
+}
+
+/// Answer a single request on `stream`.
+fn handle(
+    stream: std::net::TcpStream,
+    directory: &std::path::Path,
+    authorization: Option<&str>,
+    shutdown: &Shutdown,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
+    let mut reader = std::io::BufReader::new(stream.try_clone()?);
+    let mut stream = std::io::BufWriter::new(stream);
+
+    let Some(request) = Request::read(&mut reader) else {
+        return respond(&mut stream, "400 Bad Request", &[], "Bad Request\n");
+    };
+    log::debug!("{} {}", request.method, request.path);
+    let authorized = authorization.is_none_or(|expected| {
+        request
+            .authorization
+            .as_deref()
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), expected.as_bytes()))
+    });
+    if !authorized {
+        return respond(
+            &mut stream,
+            "401 Unauthorized",
+            &[("WWW-Authenticate", "Basic realm=\"rush\"")],
+            "Unauthorized\n",
+        );
+    }
+    if request.method != "GET" && request.method != "HEAD" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            &[("Allow", "GET, HEAD")],
+            "Method Not Allowed\n",
+        );
+    }
+    let head = request.method == "HEAD";
+
+    // Reject anything that could escape the served directory, either through `..`
+    // or through symbolic links pointing outside of it.
+    let relative = std::path::Path::new(request.path.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !matches!(component, std::path::Component::Normal(_)))
+    {
+        return respond(&mut stream, "403 Forbidden", &[], "Forbidden\n");
+    }
+    let mut path = match confine(directory, &directory.join(relative)) {
+        Ok(path) => path,
+        Err(status) => return respond(&mut stream, status, &[], &format!("{}\n", &status[4..])),
+    };
+
+    if path.is_dir() {
+        if !request.path.ends_with('/') {
+            let location = format!("{}/", super::uri_encode(&request.path, true));
+            return respond(
+                &mut stream,
+                "301 Moved Permanently",
+                &[("Location", &location)],
+                "",
+            );
+        }
+        if path.join("index.html").is_file() {
+            path = match confine(directory, &path.join("index.html")) {
+                Ok(path) => path,
+                Err(status) => {
+                    return respond(&mut stream, status, &[], &format!("{}\n", &status[4..]))
+                },
+            };
+        } else {
+            let listing = listing(&path, &request.path)?;
+            let body = if head { "" } else { &listing };
+            return respond(
+                &mut stream,
+                "200 OK",
+                &[("Content-Type", "text/html; charset=utf-8")],
+                body,
+            );
+        }
+    }
+
+    let Ok(mut file) = std::fs::File::open(&path) else {
+        return respond(&mut stream, "404 Not Found", &[], "Not Found\n");
+    };
+    let length = file.metadata()?.len();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {length}\r\nConnection: \
+         close\r\n\r\n",
+        content_type(&path)
+    )?;
+    if !head {
+        transfer(&mut file, &mut stream, shutdown)?;
+    }
+    stream.flush()
+}
+
+/// Write a complete response with a small body.
+fn respond(
+    stream: &mut impl Write,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {status}\r\n")?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// Render an HTML listing of `directory`, which was requested as `request_path`.
+fn listing(directory: &std::path::Path, request_path: &str) -> std::io::Result<String> {
+    use std::fmt::Write;
+
+    let mut entries = std::fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            name
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let title = html_escape(request_path);
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n");
+    let _ = writeln!(html, "<head><title>{title}</title></head>");
+    let _ = writeln!(html, "<body>\n<h1>{title}</h1>\n<ul>");
+    for name in entries {
+        let _ = writeln!(
+            html,
+            "<li><a href=\"{}\">{}</a></li>",
+            super::uri_encode(&name, true),
+            html_escape(&name)
+        );
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+/// A running [`FileServer`]. The server stops when this value is dropped.
+#[derive(Debug)]
+pub struct ServerHandle {
+    /// The address the server listens on
+    local_addr: std::net::SocketAddr,
+    /// Tells the server thread to stop
+    stop:       std::sync::Arc<std::sync::atomic::AtomicBool>,
+    /// The server thread
+    thread:     Option<std::thread::JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    /// The address the server listens on, which is useful if it was bound to port 0.
+    #[must_use]
+    pub const fn local_addr(&self) -> std::net::SocketAddr { self.local_addr }
+
+    /// Block until the server stops because its shutdown timeout expired. Without a
+    /// timeout, this blocks until the process is terminated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server thread panicked.
+    pub fn wait(mut self) -> ServeResult<()> {
+        self.thread.take().map_or(Ok(()), |thread| {
+            thread.join().map_err(|_| ServeError::Panicked)
+        })
+    }
+}
+
+impl Drop for ServerHandle {
+    /// Stop accepting connections and abort running transfers.
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Serve the files below `directory` on `bind_addr` until the process is terminated.
+/// Use [`FileServer`] for basic auth or automatic shutdown.
+///
+/// # Errors
+///
+/// Returns an error if the directory does not exist or the address cannot be bound.
+pub fn serve(
+    directory: impl AsRef<std::path::Path>,
+    bind_addr: impl AsRef<str>,
+) -> ServeResult<()> {
+    FileServer::new(directory, bind_addr).start()?.wait()
+}
+
+#[cfg(test)]
+mod file_server_test {
+    use super::*;
+
+    /// Send a raw `GET` request for `path` and return the full response.
+    fn get(address: std::net::SocketAddr, path: &str, authorization: Option<&str>) -> String {
+        use std::io::Read;
+
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        let authorization = authorization
+            .map(|value| format!("Authorization: {value}\r\n"))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: test\r\n{authorization}\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn helpers() {
+        assert_eq!(base64(b"user:password"), "dXNlcjpwYXNzd29yZA==");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abc"), "YWJj");
+        assert_eq!(percent_decode("/a%20b/c").as_deref(), Some("/a b/c"));
+        assert_eq!(percent_decode("/a%2"), None);
+        assert!(constant_time_eq(b"Basic abc=", b"Basic abc="));
+        assert!(!constant_time_eq(b"Basic abc=", b"Basic abd="));
+        assert!(!constant_time_eq(b"Basic abc=", b"Basic abc"));
+        assert!(constant_time_eq(b"", b""));
+
+        let request = |raw: String| Request::read(&mut std::io::Cursor::new(raw));
+        assert!(request(String::from("GET / HTTP/1.1\r\nHost: a\r\n\r\n")).is_some());
+        assert!(request(format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10_000))).is_none());
+        assert!(request(format!("GET / HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(101))).is_none());
+    }
+
+    #[test]
+    fn serve_directory() {
+        let directory = std::env::temp_dir().join(format!("rush-serve-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("sub dir")).unwrap();
+        std::fs::write(directory.join("hello.txt"), "Hello\n").unwrap();
+
+        let server = FileServer::new(&directory, "127.0.0.1:0")
+            .basic_auth("user", "password")
+            .start()
+            .unwrap();
+        let address = server.local_addr();
+        let authorization = Some("Basic dXNlcjpwYXNzd29yZA==");
+
+        assert!(get(address, "/hello.txt", None).starts_with("HTTP/1.1 401"));
+        let response = get(address, "/hello.txt", authorization);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("text/plain"));
+        assert!(response.ends_with("\r\n\r\nHello\n"));
+
+        let response = get(address, "/", authorization);
+        assert!(response.contains("<a href=\"sub%20dir/\">sub dir/</a>"));
+        assert!(get(address, "/sub%20dir", authorization).starts_with("HTTP/1.1 301"));
+        assert!(get(address, "/missing", authorization).starts_with("HTTP/1.1 404"));
+        assert!(get(address, "/../etc/passwd", authorization).starts_with("HTTP/1.1 403"));
+
+        drop(server);
+        assert!(std::net::TcpStream::connect(address).is_err());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn symlinks_stay_inside() {
+        let root = crate::fs::generate_test_path();
+        std::fs::create_dir_all(root.join("served/inside")).unwrap();
+        std::fs::create_dir_all(root.join("outside")).unwrap();
+        std::fs::write(root.join("outside/secret"), "secret\n").unwrap();
+        std::fs::write(root.join("served/inside/file"), "public\n").unwrap();
+        std::os::unix::fs::symlink(root.join("outside"), root.join("served/escape")).unwrap();
+        std::os::unix::fs::symlink("inside", root.join("served/link")).unwrap();
+
+        let server = FileServer::new(root.join("served"), "127.0.0.1:0")
+            .start()
+            .unwrap();
+        let address = server.local_addr();
+        assert!(get(address, "/escape/secret", None).starts_with("HTTP/1.1 403"));
+        assert!(get(address, "/link/file", None).ends_with("\r\n\r\npublic\n"));
+
+        drop(server);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn shutdown_after() {
+        let server = FileServer::new(std::env::temp_dir(), "127.0.0.1:0")
+            .shutdown_after(std::time::Duration::from_millis(100))
+            .start()
+            .unwrap();
+        let start = std::time::Instant::now();
+        server.wait().unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    }
+}
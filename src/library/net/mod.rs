@@ -0,0 +1,44 @@
+//! This module contains functionality for transferring data over the network.
+
+#[cfg(feature = "http")]
+pub mod cloud_metadata;
+mod http;
+#[cfg(feature = "object-store")]
+pub mod object_store;
+mod proxy;
+pub mod sftp;
+pub mod wireguard;
+
+pub use http::{
+    serve,
+    FileServer,
+    ServeError,
+    ServeResult,
+    ServerHandle,
+};
+pub use proxy::{
+    forward,
+    ForwardError,
+    ForwardHandle,
+    ForwardResult,
+};
+
+/// Percent-encode `value` as required in URLs, keeping only unreserved characters.
+/// Slashes are kept if `keep_slash` is set (for paths).
+pub(crate) fn uri_encode(value: &str, keep_slash: bool) -> String {
+    use std::fmt::Write;
+
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(byte));
+            },
+            b'/' if keep_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            },
+        }
+    }
+    encoded
+}
@@ -0,0 +1,234 @@
+//! This module contains a TCP proxy for temporarily reaching a service through
+//! another host, e.g. in test scripts.
+
+/// Describes possible errors when forwarding connections.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ForwardError {
+    #[error("Could not bind to '{0}': {1}")]
+    Bind(String, String),
+}
+
+/// A [`Result`] whose error variant is a [`ForwardError`].
+pub type ForwardResult<T> = Result<T, ForwardError>;
+
+/// How long to sleep when no connection is pending before checking for shutdown again.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// The streams of the connections currently being proxied, keyed by a unique number
+/// per connection, so they can be closed when forwarding stops.
+type Connections =
+    std::sync::Arc<std::sync::Mutex<std::collections::HashMap<u64, Vec<std::net::TcpStream>>>>;
+
+/// Register `stream` as part of the connection `id`. Returns `false` if forwarding
+/// was stopped in the meantime, in which case `stream` is closed right away.
+///
+/// Checking `stop` while holding the lock guarantees that every stream is either
+/// closed by [`ForwardHandle`]'s `Drop` or here.
+fn register(
+    id: u64,
+    stream: &std::net::TcpStream,
+    connections: &Connections,
+    stop: &std::sync::atomic::AtomicBool,
+) -> std::io::Result<bool> {
+    let Ok(mut connections) = connections.lock() else {
+        return Ok(false);
+    };
+    if stop.load(std::sync::atomic::Ordering::SeqCst) {
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+        return Ok(false);
+    }
+    connections.entry(id).or_default().push(stream.try_clone()?);
+    Ok(true)
+}
+
+/// Copy everything from `from` to `to`, then signal the end of the stream to `to`.
+fn pipe(mut from: std::net::TcpStream, mut to: std::net::TcpStream) {
+    let _ = std::io::copy(&mut from, &mut to);
+    let _ = to.shutdown(std::net::Shutdown::Write);
+}
+
+/// Proxy the accepted connection `client` to `remote_addr` until either side closes
+/// it or `stop` is set.
+fn proxy(
+    id: u64,
+    client: std::net::TcpStream,
+    remote_addr: &str,
+    connections: &Connections,
+    stop: &std::sync::atomic::AtomicBool,
+) {
+    // The client is registered before connecting, so dropping the handle while the
+    // connection is being set up closes it as well.
+    let setup = || -> std::io::Result<_> {
+        client.set_nonblocking(false)?;
+        if !register(id, &client, connections, stop)? {
+            return Ok(None);
+        }
+        let remote = std::net::TcpStream::connect(remote_addr)?;
+        if !register(id, &remote, connections, stop)? {
+            let _ = client.shutdown(std::net::Shutdown::Both);
+            return Ok(None);
+        }
+        Ok(Some((client.try_clone()?, remote.try_clone()?, remote)))
+    };
+    match setup() {
+        Ok(Some((client_copy, remote_copy, remote))) => {
+            let upstream = std::thread::spawn(move || pipe(client_copy, remote));
+            pipe(remote_copy, client);
+            let _ = upstream.join();
+        },
+        Ok(None) => log::trace!("Forwarding stopped before connecting to '{remote_addr}'"),
+        Err(error) => log::warn!("Could not connect to '{remote_addr}': {error}"),
+    }
+    if let Ok(mut connections) = connections.lock() {
+        connections.remove(&id);
+    }
+}
+
+/// Forward TCP connections accepted on `local_addr` to `remote_addr` in a background
+/// thread until the returned handle is dropped.
+///
+/// ```no_run
+/// let forward = rush::net::forward("127.0.0.1:0", "db.internal:5432").unwrap();
+/// println!("Database reachable on {}", forward.local_addr());
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `local_addr` cannot be bound. Failing to reach `remote_addr`
+/// is only logged, as it is retried for every new connection.
+pub fn forward(local_addr: &str, remote_addr: &str) -> ForwardResult<ForwardHandle> {
+    let to_error =
+        |error: std::io::Error| ForwardError::Bind(local_addr.to_string(), error.to_string());
+    let listener = std::net::TcpListener::bind(local_addr).map_err(to_error)?;
+    listener.set_nonblocking(true).map_err(to_error)?;
+    let bound_addr = listener.local_addr().map_err(to_error)?;
+    log::debug!("Forwarding {bound_addr} to '{remote_addr}'");
+
+    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let connections = Connections::default();
+    let thread = {
+        let stop = stop.clone();
+        let connections = connections.clone();
+        let remote_addr = remote_addr.to_string();
+        std::thread::spawn(move || {
+            let mut next_id = 0;
+            while !stop.load(std::sync::atomic::Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((client, peer)) => {
+                        log::trace!("Forwarding connection from {peer} to '{remote_addr}'");
+                        let connections = connections.clone();
+                        let stop = stop.clone();
+                        let remote_addr = remote_addr.clone();
+                        std::thread::spawn(move || {
+                            proxy(next_id, client, &remote_addr, &connections, &stop);
+                        });
+                        next_id += 1;
+                    },
+                    Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(POLL_INTERVAL);
+                    },
+                    Err(error) => log::warn!("Accepting a connection failed: {error}"),
+                }
+            }
+        })
+    };
+
+    Ok(ForwardHandle {
+        local_addr: bound_addr,
+        stop,
+        connections,
+        thread: Some(thread),
+    })
+}
+
+/// Connections are forwarded as long as this value lives. Dropping it stops
+/// accepting connections and closes the ones still open.
+#[derive(Debug)]
+pub struct ForwardHandle {
+    /// The address connections are accepted on
+    local_addr:  std::net::SocketAddr,
+    /// Tells the accepting thread to stop
+    stop:        std::sync::Arc<std::sync::atomic::AtomicBool>,
+    /// The connections currently being proxied
+    connections: Connections,
+    /// The accepting thread
+    thread:      Option<std::thread::JoinHandle<()>>,
+}
+
+impl ForwardHandle {
+    /// The address connections are accepted on, which is useful if it was bound to
+    /// port 0.
+    #[must_use]
+    pub const fn local_addr(&self) -> std::net::SocketAddr { self.local_addr }
+}
+
+impl Drop for ForwardHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if let Ok(connections) = self.connections.lock() {
+            for stream in connections.values().flatten() {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        }
+        log::debug!("Stopped forwarding {}", self.local_addr);
+    }
+}
+
+#[cfg(test)]
+mod forward_test {
+    use super::*;
+    use std::io::{
+        Read,
+        Write,
+    };
+
+    #[test]
+    fn forward_connections() {
+        let echo = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let echo_addr = echo.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for stream in echo.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = stream.try_clone().unwrap();
+                std::thread::spawn(move || {
+                    let _ = std::io::copy(&mut reader, &mut stream);
+                });
+            }
+        });
+
+        let forward = forward("127.0.0.1:0", &echo_addr).unwrap();
+        let mut client = std::net::TcpStream::connect(forward.local_addr()).unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut answer = [0; 4];
+        client.read_exact(&mut answer).unwrap();
+        assert_eq!(&answer, b"ping");
+
+        let address = forward.local_addr();
+        drop(forward);
+        assert_eq!(client.read(&mut answer).unwrap_or(0), 0);
+        assert!(std::net::TcpStream::connect(address).is_err());
+    }
+
+    #[test]
+    fn register_after_stop() -> std::io::Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let mut client = std::net::TcpStream::connect(listener.local_addr()?)?;
+        let (accepted, _) = listener.accept()?;
+        let connections = Connections::default();
+        let stop = std::sync::atomic::AtomicBool::new(false);
+
+        assert!(register(0, &accepted, &connections, &stop)?);
+        stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        // A connection accepted just before the handle was dropped is closed.
+        assert!(!register(1, &accepted, &connections, &stop)?);
+        assert_eq!(client.read(&mut [0; 1])?, 0);
+        assert_eq!(
+            connections.lock().map(|connections| connections.len()).ok(),
+            Some(1)
+        );
+        Ok(())
+    }
+}
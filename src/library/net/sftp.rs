@@ -0,0 +1,586 @@
+//! This module contains functionality for exchanging files with SFTP and FTP
+//! servers, e.g. the drop zones many legacy integrations still use.
+//!
+//! Transfers are performed by `curl`, which needs to be installed (and built with
+//! SFTP support for [`Protocol::Sftp`]).
+
+use crate::{
+    fs::FSError,
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Describes possible errors when transferring files.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum TransferError {
+    #[error("Running curl failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("Could not parse the directory listing line '{0}'")]
+    InvalidListing(String),
+    #[error("Accessing local files failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`TransferError`].
+pub type TransferResult<T> = Result<T, TransferError>;
+
+/// The protocol spoken by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// SSH File Transfer Protocol, port 22 by default
+    Sftp,
+    /// Plain FTP, port 21 by default
+    Ftp,
+    /// FTP upgraded to TLS with `AUTH TLS` (explicit FTPS), port 21 by default
+    Ftps,
+}
+
+impl Protocol {
+    /// The URL scheme curl expects.
+    const fn scheme(self) -> &'static str {
+        match self {
+            Self::Sftp => "sftp",
+            Self::Ftp | Self::Ftps => "ftp",
+        }
+    }
+}
+
+/// How the identity of the server is verified.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HostKeyCheck {
+    /// Verify SFTP servers against `~/.ssh/known_hosts` and FTPS servers against
+    /// the system's certificate authorities
+    Default,
+    /// Require the SFTP server's host key to have this base64-encoded SHA-256
+    /// fingerprint (as shown by `ssh-keygen -l`, without the `SHA256:` prefix)
+    Sha256(String),
+    /// Do not verify the server at all
+    Insecure,
+}
+
+/// The rest of `line` after skipping `count` whitespace-separated fields, or [`None`]
+/// if nothing is left.
+fn skip_fields(line: &str, count: usize) -> Option<&str> {
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        rest = rest.split_once(char::is_whitespace)?.1.trim_start();
+    }
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// Quote `value` for a curl configuration file.
+fn quote_config(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A curl configuration file holding the credentials of a [`Server`], so they do not
+/// show up in the process list. The file is only readable by the current user and
+/// removed when this value is dropped.
+struct CredentialsFile {
+    /// The path of the file
+    path: std::path::PathBuf,
+}
+
+impl CredentialsFile {
+    /// Write a configuration file logging in as `user` with `password`.
+    fn create(user: &str, password: Option<&str>) -> TransferResult<Self> {
+        use std::io::Write as _;
+
+        /// How many credential files this process created, which keeps their names
+        /// unique.
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            ".rush-curl-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path).map_err(FSError::from)?;
+        let credentials_file = Self { path };
+
+        let credentials =
+            password.map_or_else(|| user.to_string(), |password| format!("{user}:{password}"));
+        writeln!(file, "user = {}", quote_config(&credentials)).map_err(FSError::from)?;
+        Ok(credentials_file)
+    }
+}
+
+impl Drop for CredentialsFile {
+    fn drop(&mut self) { let _ = std::fs::remove_file(&self.path); }
+}
+
+/// A file or directory on the server, as returned by [`Server::list`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemoteEntry {
+    /// The name of the entry inside its directory
+    pub name:         String,
+    /// Whether the entry is a directory
+    pub is_directory: bool,
+    /// Whether the entry is a symbolic link, whose target may be a file or a
+    /// directory (which the listing does not tell)
+    pub is_symlink:   bool,
+    /// The size of the entry in bytes; for symbolic links, the length of the target
+    pub size:         u64,
+}
+
+impl RemoteEntry {
+    /// Parse a line of a directory listing. Both the Unix `ls -l` format and the
+    /// format of Windows FTP servers are understood. Returns [`None`] for `.` and
+    /// `..`.
+    fn parse(line: &str) -> TransferResult<Option<Self>> {
+        let invalid = || TransferError::InvalidListing(line.to_string());
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let first = fields.first().ok_or_else(invalid)?;
+
+        let (is_directory, is_symlink, size, name) = if first.len() == 8 && first.contains('-') {
+            // Windows: `01-31-24  09:15AM  <DIR>  name` or `... 1024 name`
+            let size = fields.get(2).ok_or_else(invalid)?;
+            let name = skip_fields(line, 3).ok_or_else(invalid)?;
+            match *size {
+                "<DIR>" => (true, false, 0, name),
+                size => (false, false, size.parse().map_err(|_| invalid())?, name),
+            }
+        } else {
+            // Unix: `drwxr-xr-x 2 user group 4096 Jan 31 09:15 name`
+            let size = fields.get(4).ok_or_else(invalid)?;
+            let size = size.parse().map_err(|_| invalid())?;
+            let name = skip_fields(line, 8).ok_or_else(invalid)?;
+            // Symbolic links are listed as `name -> target`.
+            let name = name.split(" -> ").next().unwrap_or(name);
+            (first.starts_with('d'), first.starts_with('l'), size, name)
+        };
+
+        if name == "." || name == ".." {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            name: name.to_string(),
+            is_directory,
+            is_symlink,
+            size,
+        }))
+    }
+}
+
+/// An SFTP or FTP server files are exchanged with.
+#[derive(Clone)]
+pub struct Server {
+    /// The protocol spoken by the server
+    protocol:       Protocol,
+    /// The host name or IP address of the server
+    host:           String,
+    /// The port, if it differs from the protocol's default
+    port:           Option<u16>,
+    /// The user to log in as
+    user:           Option<String>,
+    /// The password to log in with
+    password:       Option<String>,
+    /// The private key to authenticate with (SFTP only)
+    identity_file:  Option<std::path::PathBuf>,
+    /// How the identity of the server is verified
+    host_key_check: HostKeyCheck,
+}
+
+impl std::fmt::Debug for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Server")
+            .field("protocol", &self.protocol)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("user", &self.user)
+            .field("identity_file", &self.identity_file)
+            .field("host_key_check", &self.host_key_check)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Server {
+    /// Describe the server at `host` speaking `protocol`. Without further
+    /// configuration, curl logs in anonymously (FTP) or as the current user with the
+    /// default SSH keys (SFTP).
+    pub fn new(protocol: Protocol, host: impl AsRef<str>) -> Self {
+        Self {
+            protocol,
+            host: host.as_ref().to_string(),
+            port: None,
+            user: None,
+            password: None,
+            identity_file: None,
+            host_key_check: HostKeyCheck::Default,
+        }
+    }
+
+    /// Connect to `port` instead of the protocol's default.
+    #[must_use]
+    pub const fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Log in as `user`.
+    #[must_use]
+    pub fn user(mut self, user: impl AsRef<str>) -> Self {
+        self.user = Some(user.as_ref().to_string());
+        self
+    }
+
+    /// Log in with `password`. The password is handed to curl in a temporary file
+    /// only readable by the current user, never on its command line.
+    #[must_use]
+    pub fn password(mut self, password: impl AsRef<str>) -> Self {
+        self.password = Some(password.as_ref().to_string());
+        self
+    }
+
+    /// Authenticate with the private key at `path` (SFTP only).
+    #[must_use]
+    pub fn identity_file(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.identity_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Verify the identity of the server with `check`.
+    #[must_use]
+    pub fn host_key_check(mut self, check: HostKeyCheck) -> Self {
+        self.host_key_check = check;
+        self
+    }
+
+    /// The URL of `path` on the server. For SFTP, relative paths are relative to the
+    /// home directory; for FTP, they are relative to the login directory.
+    fn url(&self, path: &str) -> String {
+        let port = self.port.map(|port| format!(":{port}")).unwrap_or_default();
+        let path = match (self.protocol, path.strip_prefix('/')) {
+            (Protocol::Sftp, Some(absolute)) => format!("/{}", super::uri_encode(absolute, true)),
+            (Protocol::Sftp, None) => format!("/~/{}", super::uri_encode(path, true)),
+            // FTP URLs are relative to the login directory unless the first slash is
+            // encoded, see RFC 1738.
+            (_, Some(absolute)) => format!("/%2F{}", super::uri_encode(absolute, true)),
+            (_, None) => format!("/{}", super::uri_encode(path, true)),
+        };
+        format!("{}://{}{port}{path}", self.protocol.scheme(), self.host)
+    }
+
+    /// The curl invocation shared by all transfers, without the credentials, which
+    /// are added by [`Server::run`].
+    fn command(&self) -> Command {
+        let mut command = Command::new("curl").args(["--silent", "--show-error"]);
+        if let Some(identity_file) = &self.identity_file {
+            command = command.arg("--key").arg(identity_file.to_string_lossy());
+        }
+        if self.protocol == Protocol::Ftps {
+            command = command.arg("--ssl-reqd");
+        }
+        match &self.host_key_check {
+            HostKeyCheck::Default => command,
+            HostKeyCheck::Sha256(fingerprint) => command.arg("--hostpubsha256").arg(fingerprint),
+            HostKeyCheck::Insecure => command.arg("--insecure"),
+        }
+    }
+
+    /// Run the curl invocation `command`, passing the credentials in a temporary
+    /// configuration file.
+    fn run(&self, command: Command) -> TransferResult<crate::process::Output> {
+        let credentials_file = self
+            .user
+            .as_ref()
+            .map(|user| CredentialsFile::create(user, self.password.as_deref()))
+            .transpose()?;
+        let command = match &credentials_file {
+            Some(file) => command.arg("--config").arg(file.path.to_string_lossy()),
+            None => command,
+        };
+        Ok(command.run()?)
+    }
+
+    /// The curl invocation uploading `local` to `remote`.
+    fn upload_command(&self, local: &std::path::Path, remote: &str, resume: bool) -> Command {
+        let command = self
+            .command()
+            .arg("--ftp-create-dirs")
+            .arg("--upload-file")
+            .arg(local.to_string_lossy());
+        let command = if resume {
+            command.args(["--continue-at", "-"])
+        } else {
+            command
+        };
+        command.arg(self.url(remote))
+    }
+
+    /// Upload the local file `local` to the path `remote` on the server, creating
+    /// missing directories. With `resume`, a partial upload is continued instead of
+    /// started over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if curl cannot be run or the transfer fails.
+    pub fn upload(
+        &self,
+        local: impl AsRef<std::path::Path>,
+        remote: &str,
+        resume: bool,
+    ) -> TransferResult<()> {
+        log::debug!(
+            "Uploading '{}' to {}",
+            local.as_ref().to_string_lossy(),
+            self.host
+        );
+        self.run(self.upload_command(local.as_ref(), remote, resume))?;
+        Ok(())
+    }
+
+    /// Download the path `remote` on the server to the local file `local`. With
+    /// `resume`, a partially downloaded file is continued instead of started over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if curl cannot be run or the transfer fails.
+    pub fn download(
+        &self,
+        remote: &str,
+        local: impl AsRef<std::path::Path>,
+        resume: bool,
+    ) -> TransferResult<()> {
+        log::debug!("Downloading '{remote}' from {}", self.host);
+        let command = self
+            .command()
+            .arg("--output")
+            .arg(local.as_ref().to_string_lossy());
+        let command = if resume {
+            command.args(["--continue-at", "-"])
+        } else {
+            command
+        };
+        self.run(command.arg(self.url(remote)))?;
+        Ok(())
+    }
+
+    /// List the entries of the directory `remote` on the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if curl cannot be run, the directory cannot be listed or the
+    /// listing has an unknown format.
+    pub fn list(&self, remote: &str) -> TransferResult<Vec<RemoteEntry>> {
+        let directory = format!("{}/", remote.trim_end_matches('/'));
+        let output = self.run(self.command().arg(self.url(&directory)))?;
+        let mut entries = Vec::new();
+        for line in output.stdout.lines().filter(|line| !line.trim().is_empty()) {
+            // Some servers start the listing with a `total <blocks>` line.
+            if line.starts_with("total ") {
+                continue;
+            }
+            entries.extend(RemoteEntry::parse(line)?);
+        }
+        Ok(entries)
+    }
+
+    /// Upload all files below the local directory `local` into the directory
+    /// `remote`, skipping files that already exist on the server with the same size.
+    /// Nothing is deleted on the server. Returns the number of uploaded files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the local directory cannot be read or a transfer fails.
+    pub fn mirror_upload(
+        &self,
+        local: impl AsRef<std::path::Path>,
+        remote: &str,
+    ) -> TransferResult<usize> {
+        let remote = remote.trim_end_matches('/');
+        // A directory that cannot be listed most likely does not exist yet.
+        let existing = self.list(remote).unwrap_or_else(|error| {
+            log::debug!("Could not list '{remote}', assuming it is empty: {error}");
+            Vec::new()
+        });
+
+        let mut uploaded = 0;
+        for entry in std::fs::read_dir(local).map_err(FSError::from)? {
+            let entry = entry.map_err(FSError::from)?;
+            let metadata = entry.metadata().map_err(FSError::from)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let target = format!("{remote}/{name}");
+
+            if metadata.is_dir() {
+                uploaded += self.mirror_upload(entry.path(), &target)?;
+            } else if !existing
+                .iter()
+                .any(|remote| remote.name == name && remote.size == metadata.len())
+            {
+                self.upload(entry.path(), &target, false)?;
+                uploaded += 1;
+            }
+        }
+        Ok(uploaded)
+    }
+
+    /// Download all files below the directory `remote` into the local directory
+    /// `local`, skipping files that already exist locally with the same size.
+    /// Nothing is deleted locally. Returns the number of downloaded files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a directory cannot be listed or created, or a transfer
+    /// fails.
+    pub fn mirror_download(
+        &self,
+        remote: &str,
+        local: impl AsRef<std::path::Path>,
+    ) -> TransferResult<usize> {
+        let remote = remote.trim_end_matches('/');
+        let local = local.as_ref();
+        std::fs::create_dir_all(local).map_err(FSError::from)?;
+
+        let mut downloaded = 0;
+        for entry in self.list(remote)? {
+            let source = format!("{remote}/{}", entry.name);
+            let target = local.join(&entry.name);
+            // Whether a symbolic link points to a directory is only known by trying to
+            // list it.
+            let symlinked_directory = entry.is_symlink && self.list(&source).is_ok();
+            if entry.is_directory || symlinked_directory {
+                downloaded += self.mirror_download(&source, &target)?;
+            } else if entry.is_symlink {
+                // The listed size is the length of the link target, not of the file.
+                self.download(&source, &target, false)?;
+                downloaded += 1;
+            } else if target.metadata().map(|metadata| metadata.len()).ok() != Some(entry.size) {
+                self.download(&source, &target, false)?;
+                downloaded += 1;
+            }
+        }
+        Ok(downloaded)
+    }
+}
+
+#[cfg(test)]
+mod server_test {
+    use super::*;
+
+    #[test]
+    fn url() {
+        let sftp = Server::new(Protocol::Sftp, "example.com");
+        assert_eq!(sftp.url("/srv/in/a b"), "sftp://example.com/srv/in/a%20b");
+        assert_eq!(sftp.url("in/"), "sftp://example.com/~/in/");
+
+        let ftp = Server::new(Protocol::Ftps, "example.com").port(2121);
+        assert_eq!(ftp.url("/srv/in"), "ftp://example.com:2121/%2Fsrv/in");
+        assert_eq!(ftp.url("in"), "ftp://example.com:2121/in");
+    }
+
+    #[test]
+    fn command() {
+        let server = Server::new(Protocol::Sftp, "example.com")
+            .user("drop")
+            .identity_file("/keys/id_ed25519")
+            .host_key_check(HostKeyCheck::Sha256(String::from("abc=")));
+        let command = server.upload_command(std::path::Path::new("/tmp/report.csv"), "in/", true);
+        assert_eq!(
+            command.arguments(),
+            [
+                "--silent",
+                "--show-error",
+                "--key",
+                "/keys/id_ed25519",
+                "--hostpubsha256",
+                "abc=",
+                "--ftp-create-dirs",
+                "--upload-file",
+                "/tmp/report.csv",
+                "--continue-at",
+                "-",
+                "sftp://example.com/~/in/",
+            ]
+        );
+
+        let server = Server::new(Protocol::Ftps, "example.com")
+            .user("drop")
+            .password("secret")
+            .host_key_check(HostKeyCheck::Insecure);
+        assert_eq!(
+            server.command().arguments()[2..],
+            ["--ssl-reqd", "--insecure"]
+        );
+        assert!(!format!("{server:?}").contains("secret"));
+    }
+
+    #[test]
+    fn credentials_file() -> TransferResult<()> {
+        let file = CredentialsFile::create("drop", Some(r#"se"cr\et"#))?;
+        let path = file.path.clone();
+        assert_eq!(
+            std::fs::read_to_string(&path).map_err(FSError::from)?,
+            "user = \"drop:se\\\"cr\\\\et\"\n"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(
+                &std::fs::metadata(&path)
+                    .map_err(FSError::from)?
+                    .permissions()
+            ) & 0o777,
+            0o600
+        );
+        drop(file);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_listing() -> TransferResult<()> {
+        assert_eq!(
+            RemoteEntry::parse("-rw-r--r--    1 1000  1000   1024 Jan 31 09:15 my report.csv")?,
+            Some(RemoteEntry {
+                name:         String::from("my report.csv"),
+                is_directory: false,
+                is_symlink:   false,
+                size:         1024,
+            })
+        );
+        assert_eq!(
+            RemoteEntry::parse("drwxr-xr-x 2 user group 4096 Jan 31  2023 archive")?,
+            Some(RemoteEntry {
+                name:         String::from("archive"),
+                is_directory: true,
+                is_symlink:   false,
+                size:         4096,
+            })
+        );
+        assert_eq!(
+            RemoteEntry::parse("lrwxrwxrwx 1 user group 7 Jan 31 09:15 latest -> archive")?,
+            Some(RemoteEntry {
+                name:         String::from("latest"),
+                is_directory: false,
+                is_symlink:   true,
+                size:         7,
+            })
+        );
+        assert_eq!(
+            RemoteEntry::parse("drwxr-xr-x 2 user group 4096 Jan 31 09:15 ..")?,
+            None
+        );
+
+        assert_eq!(
+            RemoteEntry::parse("01-31-24  09:15AM       <DIR>          archive")?,
+            Some(RemoteEntry {
+                name:         String::from("archive"),
+                is_directory: true,
+                is_symlink:   false,
+                size:         0,
+            })
+        );
+        assert_eq!(
+            RemoteEntry::parse("01-31-24  09:15AM                 1024 report.csv")?
+                .map(|entry| entry.size),
+            Some(1024)
+        );
+        assert!(RemoteEntry::parse("garbage").is_err());
+        Ok(())
+    }
+}
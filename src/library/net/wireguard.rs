@@ -0,0 +1,611 @@
+//! This module contains functionality for setting up
+//! [WireGuard](https://www.wireguard.com) tunnels: generating keys, rendering
+//! configurations in the format of `wg-quick` and applying them.
+//!
+//! Keys are generated and interfaces are managed by the `wg` and `wg-quick` tools,
+//! which need to be installed. [`mesh`] creates the configurations of a full mesh,
+//! in which every node has a direct link to every other node.
+//!
+//! ```no_run
+//! # use rush::net::wireguard::{self, KeyPair, Node};
+//! let nodes = [
+//!     Node::new("a", KeyPair::generate().unwrap(), "10.9.0.1/24").endpoint("a.example.com:51820"),
+//!     Node::new("b", KeyPair::generate().unwrap(), "10.9.0.2/24").endpoint("b.example.com:51820"),
+//! ];
+//! let configurations = wireguard::mesh(&nodes);
+//! wireguard::apply("wg0", &configurations["a"]).unwrap();
+//! ```
+
+use crate::{
+    fs::{
+        FSError,
+        FSResult,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// The directory `wg-quick` looks for configurations in.
+const CONFIG_DIRECTORY: &str = "/etc/wireguard";
+/// The keepalive interval that keeps NAT mappings of nodes without an endpoint
+/// open, in seconds.
+const KEEPALIVE: u16 = 25;
+
+/// Describes possible errors when setting up tunnels.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum WireGuardError {
+    #[error("'{0}' is not a valid interface name")]
+    InvalidName(String),
+    #[error("The key is not valid: {0}")]
+    InvalidKey(String),
+    #[error("The template uses the variable '{0}', which is not set")]
+    MissingVariable(String),
+    #[error("Running wg or wg-quick failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("Writing the configuration failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`WireGuardError`].
+pub type WireGuardResult<T> = Result<T, WireGuardError>;
+
+/// Run `program` with `arguments`, passing `input` on standard input, and return
+/// its standard output without the trailing newline.
+fn run(program: &str, arguments: &[&str], input: &str) -> WireGuardResult<String> {
+    use std::io::Write as _;
+
+    let command = Command::new(program).args(arguments);
+    log::trace!("Running {}", command);
+    let mut running = command.start(|command| {
+        command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+    })?;
+    if let Some(mut stdin) = running.take_stdin() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(ProcessError::from)?;
+    }
+    let output = running.wait_with_output()?;
+    if !output.status.success() {
+        return Err(ProcessError::Failed {
+            code:   output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// Make sure `key` looks like a key of `wg`: 32 bytes in base64.
+fn validate_key(key: &str) -> WireGuardResult<()> {
+    let valid = key.len() == 44
+        && key.ends_with('=')
+        && key[..43]
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '+' | '/'));
+    if valid {
+        Ok(())
+    } else {
+        Err(WireGuardError::InvalidKey(String::from(
+            "expected 32 bytes in base64",
+        )))
+    }
+}
+
+/// Make sure `name` is a valid name for a network interface.
+fn validate_name(name: &str) -> WireGuardResult<()> {
+    let valid = (1..=15).contains(&name.len())
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "_=+.-".contains(character));
+    if valid {
+        Ok(())
+    } else {
+        Err(WireGuardError::InvalidName(name.to_string()))
+    }
+}
+
+/// A private key and the public key that belongs to it.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct KeyPair {
+    /// The private key, in base64
+    private: String,
+    /// The public key, in base64
+    public:  String,
+}
+
+impl std::fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyPair {
+    /// Generate a new key pair with `wg genkey`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `wg` cannot be run.
+    pub fn generate() -> WireGuardResult<Self> { Self::from_private(run("wg", &["genkey"], "")?) }
+
+    /// Derive the public key for the private key `private` with `wg pubkey`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is not valid or `wg` cannot be run.
+    pub fn from_private(private: impl Into<String>) -> WireGuardResult<Self> {
+        let private = private.into();
+        validate_key(&private)?;
+        let public = run("wg", &["pubkey"], &private)?;
+        Ok(Self { private, public })
+    }
+
+    /// The private key, in base64.
+    #[must_use]
+    pub fn private_key(&self) -> &str { &self.private }
+
+    /// The public key, in base64.
+    #[must_use]
+    pub fn public_key(&self) -> &str { &self.public }
+}
+
+/// Generate a preshared key with `wg genpsk`, which adds a layer of symmetric
+/// encryption to a link.
+///
+/// # Errors
+///
+/// Returns an error if `wg` cannot be run.
+pub fn preshared_key() -> WireGuardResult<String> { run("wg", &["genpsk"], "") }
+
+/// The local side of a tunnel, the `[Interface]` section of a configuration.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Interface {
+    /// The private key of the interface
+    private_key: String,
+    /// The addresses of the interface, with prefix length
+    addresses:   Vec<String>,
+    /// The UDP port to listen on, or a random one
+    listen_port: Option<u16>,
+    /// The DNS servers to use while the interface is up
+    dns:         Vec<String>,
+}
+
+impl std::fmt::Debug for Interface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interface")
+            .field("addresses", &self.addresses)
+            .field("listen_port", &self.listen_port)
+            .field("dns", &self.dns)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Interface {
+    /// Create an interface that uses `private_key`.
+    pub fn new(private_key: impl Into<String>) -> Self {
+        Self {
+            private_key: private_key.into(),
+            addresses:   Vec::new(),
+            listen_port: None,
+            dns:         Vec::new(),
+        }
+    }
+
+    /// Assign `address` with its prefix length, e.g. `10.9.0.1/24`.
+    #[must_use]
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.addresses.push(address.into());
+        self
+    }
+
+    /// Listen on the UDP port `port` instead of a random one, which peers need to
+    /// reach this interface.
+    #[must_use]
+    pub const fn listen_port(mut self, port: u16) -> Self {
+        self.listen_port = Some(port);
+        self
+    }
+
+    /// Use the DNS server `server` while the interface is up.
+    #[must_use]
+    pub fn dns(mut self, server: impl Into<String>) -> Self {
+        self.dns.push(server.into());
+        self
+    }
+}
+
+/// The remote side of a tunnel, a `[Peer]` section of a configuration.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Peer {
+    /// A name for the peer, which is rendered as comment
+    name:                 Option<String>,
+    /// The public key of the peer
+    public_key:           String,
+    /// The preshared key of the link
+    preshared_key:        Option<String>,
+    /// The addresses traffic is routed to the peer for, and accepted from it
+    allowed_ips:          Vec<String>,
+    /// Where the peer can be reached, as `host:port`
+    endpoint:             Option<String>,
+    /// The interval of keepalive packets, in seconds
+    persistent_keepalive: Option<u16>,
+}
+
+impl std::fmt::Debug for Peer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Peer")
+            .field("name", &self.name)
+            .field("public_key", &self.public_key)
+            .field("allowed_ips", &self.allowed_ips)
+            .field("endpoint", &self.endpoint)
+            .field("persistent_keepalive", &self.persistent_keepalive)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Peer {
+    /// Create a peer with the public key `public_key`.
+    pub fn new(public_key: impl Into<String>) -> Self {
+        Self {
+            name:                 None,
+            public_key:           public_key.into(),
+            preshared_key:        None,
+            allowed_ips:          Vec::new(),
+            endpoint:             None,
+            persistent_keepalive: None,
+        }
+    }
+
+    /// Name the peer in the configuration.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Route traffic for `network` (e.g. `10.9.0.2/32`) to the peer and accept
+    /// traffic from these addresses.
+    #[must_use]
+    pub fn allowed_ip(mut self, network: impl Into<String>) -> Self {
+        self.allowed_ips.push(network.into());
+        self
+    }
+
+    /// Reach the peer at `endpoint`, given as `host:port`.
+    #[must_use]
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Use `key` (see [`preshared_key`]) as preshared key of the link. Both sides
+    /// need the same key.
+    #[must_use]
+    pub fn preshared_key(mut self, key: impl Into<String>) -> Self {
+        self.preshared_key = Some(key.into());
+        self
+    }
+
+    /// Send a keepalive packet every `seconds`, which keeps links through NAT open.
+    #[must_use]
+    pub const fn persistent_keepalive(mut self, seconds: u16) -> Self {
+        self.persistent_keepalive = Some(seconds);
+        self
+    }
+}
+
+/// A complete configuration of a tunnel interface. The [`std::fmt::Display`]
+/// implementation renders it in the format of `wg-quick`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Config {
+    /// The local side
+    pub interface: Interface,
+    /// The remote sides
+    pub peers:     Vec<Peer>,
+}
+
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let interface = &self.interface;
+        writeln!(f, "[Interface]")?;
+        writeln!(f, "PrivateKey = {}", interface.private_key)?;
+        if !interface.addresses.is_empty() {
+            writeln!(f, "Address = {}", interface.addresses.join(", "))?;
+        }
+        if let Some(port) = interface.listen_port {
+            writeln!(f, "ListenPort = {port}")?;
+        }
+        if !interface.dns.is_empty() {
+            writeln!(f, "DNS = {}", interface.dns.join(", "))?;
+        }
+
+        for peer in &self.peers {
+            writeln!(f, "\n[Peer]")?;
+            if let Some(name) = &peer.name {
+                writeln!(f, "# {name}")?;
+            }
+            writeln!(f, "PublicKey = {}", peer.public_key)?;
+            if let Some(key) = &peer.preshared_key {
+                writeln!(f, "PresharedKey = {key}")?;
+            }
+            if !peer.allowed_ips.is_empty() {
+                writeln!(f, "AllowedIPs = {}", peer.allowed_ips.join(", "))?;
+            }
+            if let Some(endpoint) = &peer.endpoint {
+                writeln!(f, "Endpoint = {endpoint}")?;
+            }
+            if let Some(seconds) = peer.persistent_keepalive {
+                writeln!(f, "PersistentKeepalive = {seconds}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Create a configuration for `interface` without peers.
+    #[must_use]
+    pub const fn new(interface: Interface) -> Self {
+        Self {
+            interface,
+            peers: Vec::new(),
+        }
+    }
+
+    /// Add `peer`.
+    #[must_use]
+    pub fn peer(mut self, peer: Peer) -> Self {
+        self.peers.push(peer);
+        self
+    }
+
+    /// Make sure all keys in the configuration look valid.
+    fn validate(&self) -> WireGuardResult<()> {
+        validate_key(&self.interface.private_key)?;
+        for peer in &self.peers {
+            validate_key(&peer.public_key)?;
+            if let Some(key) = &peer.preshared_key {
+                validate_key(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the configuration to `path`, readable only by its owner since it
+    /// contains the private key. The file is replaced atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a key is not valid or writing fails.
+    pub fn write(&self, path: impl AsRef<std::path::Path>) -> WireGuardResult<()> {
+        use std::io::Write as _;
+
+        self.validate()?;
+        crate::fs::write_atomic_with(path.as_ref(), Some(0o600), |writer| -> FSResult<()> {
+            Ok(writer.write_all(self.to_string().as_bytes())?)
+        })?;
+        Ok(())
+    }
+}
+
+/// A member of a mesh, see [`mesh`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Node {
+    /// The name of the node
+    name:     String,
+    /// The keys of the node
+    keys:     KeyPair,
+    /// The address of the node in the mesh, with prefix length
+    address:  String,
+    /// Where other nodes can reach the node, as `host:port`
+    endpoint: Option<String>,
+}
+
+impl Node {
+    /// Create a node called `name` with `keys` and the address `address` in the
+    /// mesh, given with the prefix length of the mesh network (e.g. `10.9.0.1/24`).
+    pub fn new(name: impl Into<String>, keys: KeyPair, address: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            keys,
+            address: address.into(),
+            endpoint: None,
+        }
+    }
+
+    /// Let other nodes reach this node at `endpoint`, given as `host:port`. Nodes
+    /// without an endpoint (e.g. behind NAT) can only connect to nodes with one.
+    #[must_use]
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// The address of the node without prefix length, as a single-host network.
+    fn host_network(&self) -> String {
+        let address = self
+            .address
+            .split_once('/')
+            .map_or(self.address.as_str(), |(address, _)| address);
+        if address.contains(':') {
+            format!("{address}/128")
+        } else {
+            format!("{address}/32")
+        }
+    }
+}
+
+/// Create the configurations of a full mesh of `nodes`, by node name.
+///
+/// Every node has every other node as peer and routes only that node's address to
+/// it. Nodes with an endpoint listen on its port; nodes without one send
+/// keepalives.
+#[must_use]
+pub fn mesh(nodes: &[Node]) -> std::collections::BTreeMap<String, Config> {
+    nodes
+        .iter()
+        .map(|node| {
+            let mut interface = Interface::new(node.keys.private_key()).address(&node.address);
+            if let Some(port) = node
+                .endpoint
+                .as_deref()
+                .and_then(|endpoint| endpoint.rsplit_once(':'))
+                .and_then(|(_, port)| port.parse().ok())
+            {
+                interface = interface.listen_port(port);
+            }
+            let mut config = Config::new(interface);
+            for other in nodes.iter().filter(|other| other.name != node.name) {
+                let mut peer = Peer::new(other.keys.public_key())
+                    .name(&other.name)
+                    .allowed_ip(other.host_network());
+                if let Some(endpoint) = &other.endpoint {
+                    peer = peer.endpoint(endpoint);
+                }
+                if node.endpoint.is_none() {
+                    peer = peer.persistent_keepalive(KEEPALIVE);
+                }
+                config = config.peer(peer);
+            }
+            (node.name.clone(), config)
+        })
+        .collect()
+}
+
+/// Replace every `{{ name }}` in `template` with the value of the variable `name`,
+/// e.g. to render peer configurations in a layout of one's own.
+///
+/// # Errors
+///
+/// Returns an error if the template uses a variable that is not in `variables`.
+pub fn render_template(
+    template: &str,
+    variables: &std::collections::BTreeMap<String, String>,
+) -> WireGuardResult<String> {
+    crate::library::template::render(template, variables).map_err(WireGuardError::MissingVariable)
+}
+
+/// Whether the interface `name` exists.
+fn is_up(name: &str) -> bool { Command::new("wg").args(["show", name]).run().is_ok() }
+
+/// Write `config` to `/etc/wireguard/<name>.conf` and bring the interface `name`
+/// up with it.
+///
+/// If the interface is already up, the configuration is synchronized without
+/// interrupting existing connections (addresses are not changed then).
+///
+/// # Errors
+///
+/// Returns an error if the name or a key is not valid, or if writing the
+/// configuration or running `wg-quick` fails.
+pub fn apply(name: &str, config: &Config) -> WireGuardResult<()> {
+    validate_name(name)?;
+    config.write(std::path::Path::new(CONFIG_DIRECTORY).join(format!("{name}.conf")))?;
+    if is_up(name) {
+        log::debug!("Synchronizing the configuration of WireGuard interface '{name}'");
+        let stripped = Command::new("wg-quick").args(["strip", name]).run()?;
+        run("wg", &["syncconf", name, "/dev/stdin"], &stripped.stdout)?;
+    } else {
+        log::debug!("Bringing WireGuard interface '{name}' up");
+        Command::new("wg-quick").args(["up", name]).run()?;
+    }
+    Ok(())
+}
+
+/// Bring the interface `name` down with `wg-quick`. Its configuration is kept.
+///
+/// # Errors
+///
+/// Returns an error if the name is not valid or `wg-quick` fails.
+pub fn down(name: &str) -> WireGuardResult<()> {
+    validate_name(name)?;
+    Command::new("wg-quick").args(["down", name]).run()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod wireguard_test {
+    use super::*;
+
+    /// A key pair with made-up keys, so that tests do not need `wg`.
+    fn keys(private: char, public: char) -> KeyPair {
+        KeyPair {
+            private: format!("{}=", private.to_string().repeat(43)),
+            public:  format!("{}=", public.to_string().repeat(43)),
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate_key(&keys('a', 'b').private).is_ok());
+        assert!(validate_key("short=").is_err());
+        assert!(validate_key(&format!("{}!=", "a".repeat(42))).is_err());
+        assert!(validate_name("wg0").is_ok());
+        assert!(validate_name("far-too-long-name").is_err());
+        assert!(validate_name("wg 0").is_err());
+        assert!(!format!("{:?}", keys('a', 'b')).contains("aaaa"));
+    }
+
+    #[test]
+    fn configurations() -> WireGuardResult<()> {
+        let nodes = [
+            Node::new("hub", keys('a', 'A'), "10.9.0.1/24").endpoint("hub.example.com:51821"),
+            Node::new("laptop", keys('b', 'B'), "10.9.0.2/24"),
+            Node::new("phone", keys('c', 'C'), "fd09::3/64"),
+        ];
+        let configurations = mesh(&nodes);
+        assert_eq!(
+            configurations["hub"].to_string(),
+            format!(
+                "[Interface]\nPrivateKey = {}=\nAddress = 10.9.0.1/24\nListenPort = \
+                 51821\n\n[Peer]\n# laptop\nPublicKey = {}=\nAllowedIPs = \
+                 10.9.0.2/32\n\n[Peer]\n# phone\nPublicKey = {}=\nAllowedIPs = fd09::3/128\n",
+                "a".repeat(43),
+                "B".repeat(43),
+                "C".repeat(43)
+            )
+        );
+        let laptop = configurations["laptop"].to_string();
+        assert!(laptop.contains("Endpoint = hub.example.com:51821\nPersistentKeepalive = 25\n"));
+        assert!(!laptop.contains("ListenPort"));
+
+        let file = crate::fs::generate_test_path();
+        configurations["laptop"].write(&file)?;
+        assert_eq!(
+            std::fs::read_to_string(&file).map_err(FSError::from)?,
+            laptop
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = std::fs::metadata(&file)
+                .map_err(FSError::from)?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&file).map_err(FSError::from)?;
+
+        let invalid = Config::new(Interface::new("nope"));
+        assert!(matches!(
+            invalid.write(crate::fs::generate_test_path()),
+            Err(WireGuardError::InvalidKey(_))
+        ));
+        assert_eq!(
+            render_template(
+                "[Peer]\nPublicKey = {{ key }}\n",
+                &std::collections::BTreeMap::new()
+            ),
+            Err(WireGuardError::MissingVariable(String::from("key")))
+        );
+        Ok(())
+    }
+}
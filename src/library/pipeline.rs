@@ -0,0 +1,315 @@
+//! This module contains functionality for post-processing files with external
+//! tools, generalizing scripts like "run `optipng` on every PNG and `ffmpeg` on every
+//! MOV".
+//!
+//! A [`FileProcessor`] holds rules, each consisting of a [`Matcher`] and a command
+//! template. [`FileProcessor::process`] runs the command of the first matching rule
+//! for every file in a directory tree, several files at a time, and reports which
+//! files failed instead of stopping at the first failure.
+//!
+//! ```no_run
+//! # use rush::{fs::{Directory, Object as _}, pipeline::{FileProcessor, Matcher}};
+//! let report = FileProcessor::new()
+//!     .rule(Matcher::MimeType("image/png".into()), ["optipng", "-quiet", "{}"])
+//!     .rule(Matcher::Glob("*.mov".into()), ["ffmpeg", "-i", "{}", "{.}.mp4"])
+//!     .process(&Directory::new("assets"))
+//!     .unwrap();
+//! assert!(report.is_success());
+//! ```
+
+use crate::{
+    fs::{
+        Directory,
+        FSResult,
+        File,
+        Object as _,
+    },
+    process::{
+        Command,
+        Output,
+        ProcessError,
+        ProcessResult,
+    },
+};
+
+/// Decides which files a rule of a [`FileProcessor`] applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Matcher {
+    /// A glob pattern. Patterns without `/` are matched against the file name,
+    /// others against the path relative to the processed directory. `*` matches
+    /// any characters except `/`, `**` any characters and `?` a single character
+    /// except `/`. Matching is case-sensitive.
+    Glob(String),
+    /// A MIME type like `image/png` or, to match a whole category, `image/*`. The
+    /// type is determined by [`File::mime_type`].
+    MimeType(String),
+}
+
+impl Matcher {
+    /// Whether the file at `path`, which is `relative` to the processed directory,
+    /// matches. `mime_type` is only called when it is needed.
+    fn matches(
+        &self,
+        relative: &str,
+        mime_type: &mut impl FnMut() -> Option<&'static str>,
+    ) -> bool {
+        match self {
+            Self::Glob(pattern) => {
+                let text = if pattern.contains('/') {
+                    relative
+                } else {
+                    relative.rsplit('/').next().unwrap_or(relative)
+                };
+                rush_core::glob::matches(pattern, text)
+            },
+            Self::MimeType(expected) => mime_type().is_some_and(|actual| {
+                expected.strip_suffix("/*").map_or_else(
+                    || actual == expected,
+                    |category| actual.split('/').next() == Some(category),
+                )
+            }),
+        }
+    }
+}
+
+/// A matcher and the command to run for the files it matches.
+#[derive(Debug, Clone)]
+struct Rule {
+    /// Which files the rule applies to
+    matcher: Matcher,
+    /// The program and its arguments, with placeholders
+    command: Vec<String>,
+}
+
+impl Rule {
+    /// The command for the file at `path`, with the placeholders replaced.
+    fn command(&self, path: &std::path::Path) -> Command {
+        let lossy = |path: &std::path::Path| path.to_string_lossy().into_owned();
+        let file_name = lossy(std::path::Path::new(path.file_name().unwrap_or_default()));
+        let parent = lossy(path.parent().unwrap_or_else(|| std::path::Path::new(".")));
+        let without_extension = lossy(&path.with_extension(""));
+        let path = lossy(path);
+
+        let mut arguments = self.command.iter().map(|argument| {
+            argument
+                .replace("{//}", &parent)
+                .replace("{/}", &file_name)
+                .replace("{.}", &without_extension)
+                .replace("{}", &path)
+        });
+        let program = arguments.next().unwrap_or_default();
+        Command::new(program).args(arguments)
+    }
+}
+
+/// What [`FileProcessor::process`] did with the files of a directory tree.
+#[derive(Debug)]
+pub struct Report {
+    /// The files whose command succeeded
+    pub processed: Vec<File>,
+    /// The files whose command failed, and why
+    pub failed:    Vec<(File, ProcessError)>,
+    /// The files no rule matched
+    pub skipped:   Vec<File>,
+}
+
+impl Report {
+    /// Whether the command of every matched file succeeded.
+    #[must_use]
+    pub fn is_success(&self) -> bool { self.failed.is_empty() }
+}
+
+/// Runs external tools on the files of a directory tree, picking the tool by
+/// rules. See the module documentation for an example.
+#[derive(Debug, Clone)]
+pub struct FileProcessor {
+    /// The rules, in the order they are tried
+    rules:       Vec<Rule>,
+    /// How many commands run at the same time
+    parallelism: usize,
+}
+
+impl Default for FileProcessor {
+    fn default() -> Self {
+        Self {
+            rules:       Vec::new(),
+            parallelism: std::thread::available_parallelism().map_or(1, std::num::NonZero::get),
+        }
+    }
+}
+
+impl FileProcessor {
+    /// Create a processor without rules that runs as many commands at the same time
+    /// as there are CPUs.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Run `command` (the program and its arguments) for every file `matcher`
+    /// matches, unless an earlier rule matched the file already. In the arguments,
+    /// `{}` is replaced with the path of the file, `{.}` with the path without the
+    /// extension, `{/}` with the file name and `{//}` with the parent directory,
+    /// like GNU `parallel` does.
+    #[must_use]
+    pub fn rule<I, S>(mut self, matcher: Matcher, command: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.rules.push(Rule {
+            matcher,
+            command: command
+                .into_iter()
+                .map(|argument| argument.as_ref().to_string())
+                .collect(),
+        });
+        self
+    }
+
+    /// Run at most `parallelism` (at least one) commands at the same time.
+    #[must_use]
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Run the matching command for every file in `directory` and its
+    /// subdirectories. Symbolic links are not followed. Failing commands do not stop
+    /// the other files from being processed; they are reported instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the directory tree fails.
+    pub fn process(&self, directory: &Directory) -> FSResult<Report> {
+        let mut paths = Vec::new();
+        collect_files(directory.path(), &mut paths)?;
+        paths.sort();
+
+        let mut jobs = Vec::new();
+        let mut skipped = Vec::new();
+        for path in paths {
+            let file = File::new(&path);
+            let relative = path
+                .strip_prefix(directory.path())
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let mut mime_type = None;
+            let mut mime_type = || *mime_type.get_or_insert_with(|| file.mime_type().ok());
+            match self
+                .rules
+                .iter()
+                .find(|rule| rule.matcher.matches(&relative, &mut mime_type))
+            {
+                Some(rule) => jobs.push((file, rule.command(&path))),
+                None => skipped.push(file),
+            }
+        }
+        log::debug!(
+            "Processing {} files in {} ({} skipped)",
+            jobs.len(),
+            directory,
+            skipped.len()
+        );
+
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let results = std::sync::Mutex::new(Vec::with_capacity(jobs.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..self.parallelism.min(jobs.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let Some((_, command)) = jobs.get(index) else {
+                        break;
+                    };
+                    let result: ProcessResult<Output> = command.run();
+                    if let Ok(mut results) = results.lock() {
+                        results.push((index, result));
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap_or_default();
+        results.sort_by_key(|(index, _)| *index);
+        let mut report = Report {
+            processed: Vec::new(),
+            failed: Vec::new(),
+            skipped,
+        };
+        for ((file, _), (_, result)) in jobs.into_iter().zip(results) {
+            match result {
+                Ok(_) => report.processed.push(file),
+                Err(error) => {
+                    log::warn!("Processing {} failed: {}", file, error);
+                    report.failed.push((file, error));
+                },
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Add the paths of all files in `directory` and its subdirectories to `paths`.
+fn collect_files(directory: &std::path::Path, paths: &mut Vec<std::path::PathBuf>) -> FSResult<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), paths)?;
+        } else if file_type.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod pipeline_test {
+    use super::*;
+
+    #[test]
+    fn process() -> FSResult<()> {
+        fn names(files: &[File]) -> Vec<String> {
+            files
+                .iter()
+                .map(|file| {
+                    file.path()
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        }
+
+        let directory = Directory::new(crate::fs::generate_test_path());
+        std::fs::create_dir_all(directory.path().join("sub"))?;
+        std::fs::write(directory.path().join("logo.bin"), b"\x89PNG\r\n\x1a\n")?;
+        std::fs::write(directory.path().join("a.txt"), "a")?;
+        std::fs::write(directory.path().join("sub/b.txt"), "b")?;
+        std::fs::write(directory.path().join("sub/c.md"), "c")?;
+
+        let report = FileProcessor::new()
+            .parallelism(2)
+            .rule(Matcher::MimeType(String::from("image/*")), ["false"])
+            .rule(
+                Matcher::Glob(String::from("*.txt")),
+                ["cp", "{}", "{.}.done"],
+            )
+            .process(&directory)?;
+
+        assert_eq!(names(&report.processed), ["a.txt", "b.txt"]);
+        assert_eq!(names(&report.skipped), ["c.md"]);
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].0.path().ends_with("logo.bin"));
+        assert!(!report.is_success());
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("sub/b.done"))?,
+            "b"
+        );
+
+        drop(report);
+        directory.delete_from_fs()
+    }
+}
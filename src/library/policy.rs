@@ -0,0 +1,309 @@
+//! This module contains a policy engine that restricts which operations this crate
+//! performs, as guardrails for scripts from semi-trusted sources.
+//!
+//! A [`Policy`] declares the path prefixes files and directories may be accessed
+//! under, the programs that must not be run and the maximum size of written files.
+//! While it is in effect, operations violating it are not performed; they fail with
+//! [`FSError::PolicyViolation`](crate::fs::FSError::PolicyViolation) or
+//! [`ProcessError::PolicyViolation`](crate::process::ProcessError::PolicyViolation)
+//! instead.
+//!
+//! The policy covers what goes through [`crate::fs`] (the operations of
+//! [`File`](crate::fs::File) and [`Directory`](crate::fs::Directory)) and the
+//! programs this crate runs. Other modules that access files of their own directly,
+//! like state, lock and configuration files, or that talk to the network, are not
+//! restricted by it.
+//!
+//! ```
+//! # use rush::prelude::*;
+//! use rush::{
+//!     fs::FSError,
+//!     policy::{Policy, Violation},
+//!     process::{Command, ProcessError},
+//! };
+//!
+//! let _policy = Policy::new()
+//!     .allow_path("/srv/app")
+//!     .forbid_command("rm")
+//!     .max_file_size(1024 * 1024)
+//!     .install();
+//! assert_eq!(
+//!     File::new("/etc/passwd").read(),
+//!     Err(FSError::PolicyViolation(Violation::PathNotAllowed("/etc/passwd".into())))
+//! );
+//! assert_eq!(
+//!     Command::new("/bin/rm").args(["-rf", "/srv/app"]).run(),
+//!     Err(ProcessError::PolicyViolation(Violation::CommandForbidden("/bin/rm".into())))
+//! );
+//! ```
+
+/// An operation a [`Policy`] does not allow.
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum Violation {
+    #[error("Access to '{}' is not allowed by the policy", .0.display())]
+    PathNotAllowed(std::path::PathBuf),
+    #[error("Running '{0}' is forbidden by the policy")]
+    CommandForbidden(String),
+    #[error("Writing {size} bytes to '{}' exceeds the limit of {limit} bytes", .path.display())]
+    FileTooLarge {
+        path:  std::path::PathBuf,
+        size:  u64,
+        limit: u64,
+    },
+}
+
+/// The operations this crate is allowed to perform.
+///
+/// Paths are made absolute and compared lexically, i.e. `..` components are
+/// resolved but symbolic links are not followed. Programs are forbidden by name,
+/// regardless of the directory they are run from; a forbidden program can still be
+/// run indirectly, e.g. by a shell.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Policy {
+    /// The path prefixes that may be accessed, or all paths if [`None`]
+    allowed_paths:      Option<Vec<std::path::PathBuf>>,
+    /// The names of the programs that must not be run
+    forbidden_commands: Vec<String>,
+    /// The maximum size of written files in bytes
+    max_file_size:      Option<u64>,
+}
+
+impl Policy {
+    /// A policy that allows everything.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            allowed_paths:      None,
+            forbidden_commands: vec![],
+            max_file_size:      None,
+        }
+    }
+
+    /// Allow accessing `prefix` and everything below it. Once a prefix is allowed,
+    /// paths outside of all allowed prefixes are not allowed anymore.
+    #[must_use]
+    pub fn allow_path(mut self, prefix: impl AsRef<std::path::Path>) -> Self {
+        let prefix = prefix.as_ref();
+        let prefix = std::path::absolute(prefix).unwrap_or_else(|_| prefix.to_path_buf());
+        self.allowed_paths.get_or_insert_with(Vec::new).push(prefix);
+        self
+    }
+
+    /// Forbid running the program `name`, whether it is given by name or by path.
+    #[must_use]
+    pub fn forbid_command(mut self, name: impl AsRef<str>) -> Self {
+        self.forbidden_commands.push(name.as_ref().to_string());
+        self
+    }
+
+    /// Forbid writing files larger than `bytes` bytes.
+    #[must_use]
+    pub const fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Check whether the absolute, normalized `path` may be accessed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` is outside of all allowed prefixes.
+    pub fn check_path(&self, path: &std::path::Path) -> Result<(), Violation> {
+        match &self.allowed_paths {
+            Some(prefixes) if !prefixes.iter().any(|prefix| path.starts_with(prefix)) => {
+                Err(Violation::PathNotAllowed(path.to_path_buf()))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether `program` may be run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `program` is forbidden.
+    pub fn check_command(&self, program: &str) -> Result<(), Violation> {
+        let name = std::path::Path::new(program).file_name();
+        if self
+            .forbidden_commands
+            .iter()
+            .any(|forbidden| forbidden == program || name == Some(forbidden.as_ref()))
+        {
+            return Err(Violation::CommandForbidden(program.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check whether `size` bytes may be written to the file `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `size` exceeds the maximum file size.
+    pub fn check_file_size(&self, path: &std::path::Path, size: u64) -> Result<(), Violation> {
+        match self.max_file_size {
+            Some(limit) if size > limit => Err(Violation::FileTooLarge {
+                path: path.to_path_buf(),
+                size,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Put this policy in effect on this thread, in addition to the policy in effect
+    /// for all threads. A policy installed on this thread before is restored when
+    /// the returned guard is dropped.
+    pub fn install(self) -> InstalledPolicy {
+        let previous = THREAD.replace(Some(self));
+        InstalledPolicy { previous }
+    }
+
+    /// Put this policy in effect for all threads for the rest of the program, in
+    /// addition to policies installed on single threads. It cannot be lifted, so
+    /// code that runs afterwards cannot escape it.
+    ///
+    /// # Errors
+    ///
+    /// Returns this policy if a policy is already in effect for all threads.
+    pub fn enforce_globally(self) -> Result<(), Self> { GLOBAL.set(self) }
+}
+
+/// The policy in effect for all threads, if any.
+static GLOBAL: std::sync::OnceLock<Policy> = std::sync::OnceLock::new();
+
+thread_local! {
+    /// The policy installed on this thread, if any.
+    static THREAD: std::cell::RefCell<Option<Policy>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Run `check` against every policy in effect on this thread.
+pub(crate) fn check(check: impl Fn(&Policy) -> Result<(), Violation>) -> Result<(), Violation> {
+    if let Some(policy) = GLOBAL.get() {
+        check(policy)?;
+    }
+    THREAD.with_borrow(|policy| policy.as_ref().map_or(Ok(()), &check))
+}
+
+/// A policy installed by [`Policy::install`]; dropping it lifts the policy.
+#[derive(Debug)]
+#[must_use = "the policy is lifted when this is dropped"]
+pub struct InstalledPolicy {
+    /// The policy that was installed on this thread before, if any
+    previous: Option<Policy>,
+}
+
+impl Drop for InstalledPolicy {
+    fn drop(&mut self) { THREAD.set(self.previous.take()); }
+}
+
+#[cfg(test)]
+mod policy_test {
+    use super::*;
+    use crate::{
+        fs::{
+            generate_test_path,
+            Directory,
+            FSError,
+            File,
+            MemoryBackend,
+            Object as _,
+        },
+        process::{
+            Command,
+            ProcessError,
+        },
+    };
+
+    #[test]
+    fn paths_and_sizes() {
+        let _memory = MemoryBackend::install();
+        let allowed = Directory::new(generate_test_path());
+        allowed.create_on_fs_recursive().unwrap();
+        let outside = File::new(generate_test_path());
+        outside.write_new("outside").unwrap();
+
+        let policy = Policy::new()
+            .allow_path(allowed.path())
+            .max_file_size(8)
+            .install();
+        let file = File::new(allowed.path().join("file.txt"));
+        file.write_new("12345").unwrap();
+        assert_eq!(
+            file.append("6789"),
+            Err(FSError::PolicyViolation(Violation::FileTooLarge {
+                path:  file.path().clone(),
+                size:  9,
+                limit: 8,
+            }))
+        );
+        assert_eq!(file.read().unwrap(), "12345");
+
+        let escape = allowed.path().join("../escape.txt");
+        let normalized = allowed.path().parent().unwrap().join("escape.txt");
+        assert_eq!(
+            File::new(&escape).write_new("escaped"),
+            Err(FSError::PolicyViolation(Violation::PathNotAllowed(
+                normalized.clone()
+            )))
+        );
+        assert!(outside.read().is_err());
+        assert!(file.copy_to(outside.path()).is_err());
+
+        drop(policy);
+        assert_eq!(outside.read().unwrap(), "outside");
+        assert!(!normalized.exists());
+    }
+
+    #[test]
+    fn line_and_encoding_operations() {
+        use crate::fs::{
+            Encoding,
+            SortOptions,
+        };
+
+        let _memory = MemoryBackend::install();
+        let allowed = Directory::new(generate_test_path());
+        allowed.create_on_fs_recursive().unwrap();
+        let inside = File::new(allowed.path().join("lines.txt"));
+        inside.write_new("b\na\n").unwrap();
+        let outside = File::new(generate_test_path());
+        outside.write_new("d\nc\n").unwrap();
+
+        let policy = Policy::new().allow_path(allowed.path()).install();
+        assert!(matches!(
+            inside.sort_lines(outside.path(), &SortOptions::new()),
+            Err(FSError::PolicyViolation(Violation::PathNotAllowed(_)))
+        ));
+        assert!(matches!(
+            outside.sort_lines(allowed.path().join("sorted.txt"), &SortOptions::new()),
+            Err(FSError::PolicyViolation(Violation::PathNotAllowed(_)))
+        ));
+        assert!(matches!(
+            outside.convert_encoding(Encoding::Utf8, Encoding::Utf16Le),
+            Err(FSError::PolicyViolation(Violation::PathNotAllowed(_)))
+        ));
+        let sorted = inside
+            .sort_lines(inside.path(), &SortOptions::new())
+            .unwrap();
+        assert_eq!(sorted.read().unwrap(), "a\nb\n");
+
+        drop(policy);
+        assert_eq!(outside.read().unwrap(), "d\nc\n");
+    }
+
+    #[test]
+    fn commands() {
+        let policy = Policy::new().forbid_command("false").install();
+        assert_eq!(
+            Command::new("false").output(),
+            Err(ProcessError::PolicyViolation(Violation::CommandForbidden(
+                String::from("false")
+            )))
+        );
+        assert!(Command::new("/usr/bin/false").spawn().is_err());
+        assert!(Command::new("true").run().is_ok());
+
+        drop(policy);
+        assert!(Command::new("false").output().is_ok());
+    }
+}
@@ -0,0 +1,396 @@
+//! This module contains privilege separation for scripts that need root for a few
+//! steps only.
+//!
+//! The bulk of the program runs unprivileged, and the registered privileged steps
+//! run in a helper that is started through `sudo` or `pkexec`.
+//!
+//! The helper is the program itself, started again with [`HELPER_ARGUMENT`] (or a
+//! helper subcommand, see [`Privileged::helper_arguments`]). Requests and their
+//! arguments are passed over a pipe to its standard input, never on the command
+//! line, and only steps registered with [`Privileged::step`] can be run. The helper
+//! is started on the first request and keeps running until the [`Privileged`] is
+//! dropped, so the user is asked for their password once.
+//!
+//! ```no_run
+//! use rush::privileged::Privileged;
+//!
+//! let privileged = Privileged::new().step("write-unit", |unit: String| {
+//!     std::fs::write("/etc/systemd/system/app.service", unit).map_err(|error| error.to_string())
+//! });
+//! // Serve requests and exit if this process is the helper.
+//! privileged.serve_if_helper();
+//!
+//! let unit = String::from("[Service]\nExecStart=/usr/local/bin/app\n");
+//! privileged.run::<_, ()>("write-unit", &unit).unwrap();
+//! ```
+
+use crate::{
+    ipc::{
+        read_message,
+        write_message,
+        Envelope,
+        IpcError,
+    },
+    process::ProcessError,
+};
+
+/// The first argument the program is started with when it is the helper, unless
+/// [`Privileged::helper_arguments`] says otherwise.
+pub const HELPER_ARGUMENT: &str = "--rush-privileged-helper";
+
+/// Describes possible errors when running privileged steps.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum PrivilegedError {
+    #[error("No privileged step named '{0}' is registered")]
+    UnknownStep(String),
+    #[error("Starting the privileged helper failed: {0}")]
+    Start(#[from] ProcessError),
+    #[error("Talking to the privileged helper failed: {0}")]
+    Ipc(#[from] IpcError),
+    #[error("The privileged helper exited (was elevating denied?)")]
+    Exited,
+    #[error("The privileged step failed: {0}")]
+    Step(String),
+}
+
+/// A [`Result`] whose error variant is a [`PrivilegedError`].
+pub type PrivilegedResult<T> = Result<T, PrivilegedError>;
+
+/// The program that starts the helper with elevated privileges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Elevator {
+    /// `sudo`, which asks for the password on the terminal
+    #[default]
+    Sudo,
+    /// `pkexec`, which asks through the desktop's polkit agent
+    Pkexec,
+}
+
+impl Elevator {
+    /// The name of the program.
+    const fn program(self) -> &'static str {
+        match self {
+            Self::Sudo => "sudo",
+            Self::Pkexec => "pkexec",
+        }
+    }
+}
+
+/// A privileged step with the types of its argument and result erased.
+type Step = dyn Fn(serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync;
+
+/// A request sent to the helper.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Request {
+    /// The name of the step to run
+    step:     String,
+    /// The argument of the step
+    argument: serde_json::Value,
+}
+
+/// The running helper.
+#[derive(Debug)]
+struct Helper {
+    /// The helper process
+    child:  std::process::Child,
+    /// Where requests are written to
+    input:  std::process::ChildStdin,
+    /// Where responses are read from
+    output: std::process::ChildStdout,
+}
+
+/// The privileged steps of a program, see the [module documentation](self).
+///
+/// Every process of the program has to register the same steps, as the helper runs
+/// the steps it registered itself.
+pub struct Privileged {
+    /// The registered steps by their name
+    steps:            std::collections::BTreeMap<String, Box<Step>>,
+    /// How privileges are elevated
+    elevator:         Elevator,
+    /// The arguments the program is started with as the helper
+    helper_arguments: Vec<String>,
+    /// The helper, once it was started
+    helper:           std::sync::Mutex<Option<Helper>>,
+}
+
+impl std::fmt::Debug for Privileged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Privileged")
+            .field("steps", &self.steps.keys().collect::<Vec<_>>())
+            .field("elevator", &self.elevator)
+            .field("helper_arguments", &self.helper_arguments)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Privileged {
+    fn default() -> Self { Self::new() }
+}
+
+impl Privileged {
+    /// No steps, elevating with `sudo`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            steps:            std::collections::BTreeMap::new(),
+            elevator:         Elevator::default(),
+            helper_arguments: vec![String::from(HELPER_ARGUMENT)],
+            helper:           std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Register the privileged step `name`. Its argument and result are passed as
+    /// JSON. A step registered under a name that is taken replaces the previous one.
+    #[must_use]
+    pub fn step<Argument, Output>(
+        mut self,
+        name: impl Into<String>,
+        step: impl Fn(Argument) -> Result<Output, String> + Send + Sync + 'static,
+    ) -> Self
+    where
+        Argument: serde::de::DeserializeOwned,
+        Output: serde::Serialize,
+    {
+        let step = move |argument: serde_json::Value| {
+            let argument = serde_json::from_value(argument)
+                .map_err(|error| format!("invalid argument: {error}"))?;
+            serde_json::to_value(step(argument)?)
+                .map_err(|error| format!("invalid result: {error}"))
+        };
+        self.steps.insert(name.into(), Box::new(step));
+        self
+    }
+
+    /// Elevate privileges with `elevator` instead of `sudo`.
+    #[must_use]
+    pub const fn elevator(mut self, elevator: Elevator) -> Self {
+        self.elevator = elevator;
+        self
+    }
+
+    /// Start the program as the helper with `arguments` instead of
+    /// [`HELPER_ARGUMENT`], e.g. a subcommand that calls [`Privileged::serve`].
+    #[must_use]
+    pub fn helper_arguments<I, S>(mut self, arguments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.helper_arguments = arguments
+            .into_iter()
+            .map(|argument| argument.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// If this process was started as the helper (its first argument is
+    /// [`HELPER_ARGUMENT`]), serve requests until the unprivileged process is done
+    /// and exit. Otherwise, return right away. Call this early in `main`.
+    pub fn serve_if_helper(&self) {
+        if std::env::args().nth(1).as_deref() != Some(HELPER_ARGUMENT) {
+            return;
+        }
+        let code = match self.serve() {
+            Ok(()) => 0,
+            Err(error) => {
+                log::error!("Serving privileged steps failed: {error}");
+                1
+            },
+        };
+        std::process::exit(code);
+    }
+
+    /// Serve requests from standard input until it is closed. Responses are written
+    /// to the original standard output; whatever the steps print to standard
+    /// output goes to standard error instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading a request or writing a response fails.
+    pub fn serve(&self) -> PrivilegedResult<()> {
+        use std::os::fd::AsFd as _;
+
+        log::debug!("Serving privileged steps");
+        let to_error = |error: std::io::Error| IpcError::Connection(error.to_string());
+        let mut responses = std::fs::File::from(
+            std::io::stdout()
+                .as_fd()
+                .try_clone_to_owned()
+                .map_err(to_error)?,
+        );
+        nix::unistd::dup2(2, 1).map_err(|error| IpcError::Connection(error.to_string()))?;
+
+        let mut requests = std::io::stdin().lock();
+        while let Some(request) = read_message(&mut requests)? {
+            write_message(&mut responses, &self.handle(&request))?;
+        }
+        Ok(())
+    }
+
+    /// Run the step a request message names and return the response message.
+    fn handle(&self, request: &[u8]) -> Vec<u8> {
+        let response = match serde_json::from_slice::<Request>(request) {
+            Ok(request) => match self.steps.get(&request.step) {
+                Some(step) => {
+                    log::debug!("Running privileged step '{}'", request.step);
+                    step(request.argument).map_or_else(Envelope::Error, Envelope::Ok)
+                },
+                None => Envelope::Error(format!("unknown step '{}'", request.step)),
+            },
+            Err(error) => Envelope::Error(format!("invalid request: {error}")),
+        };
+        serde_json::to_vec(&response).unwrap_or_default()
+    }
+
+    /// Start the helper with elevated privileges.
+    fn start(&self) -> PrivilegedResult<Helper> {
+        let program = self.elevator.program();
+        crate::policy::check(|policy| policy.check_command(program))
+            .map_err(ProcessError::PolicyViolation)?;
+        let executable = std::env::current_exe().map_err(ProcessError::from)?;
+        log::debug!("Starting the privileged helper with {program}");
+
+        let mut command = std::process::Command::new(program);
+        if self.elevator == Elevator::Sudo {
+            command.arg("--");
+        }
+        let mut child = command
+            .arg(executable)
+            .args(&self.helper_arguments)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .map_err(ProcessError::from)?;
+        match (child.stdin.take(), child.stdout.take()) {
+            (Some(input), Some(output)) => Ok(Helper {
+                child,
+                input,
+                output,
+            }),
+            _ => Err(PrivilegedError::Exited),
+        }
+    }
+
+    /// Run the privileged step `name` with `argument` and return its result. If this
+    /// process runs as root already, the step runs in this process.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is not registered, the helper cannot be started
+    /// (e.g. because elevating was denied) or the step fails.
+    pub fn run<Argument, Output>(&self, name: &str, argument: &Argument) -> PrivilegedResult<Output>
+    where
+        Argument: serde::Serialize + ?Sized,
+        Output: serde::de::DeserializeOwned,
+    {
+        let step = self
+            .steps
+            .get(name)
+            .ok_or_else(|| PrivilegedError::UnknownStep(name.to_string()))?;
+        let argument = serde_json::to_value(argument)
+            .map_err(|error| IpcError::InvalidMessage(error.to_string()))?;
+
+        let result = if nix::unistd::geteuid().is_root() {
+            log::debug!("Running privileged step '{name}' in this process");
+            step(argument).map_err(PrivilegedError::Step)?
+        } else {
+            let mut helper = self
+                .helper
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if helper.is_none() {
+                *helper = Some(self.start()?);
+            }
+            let response = helper.as_mut().map_or(Ok(None), |running| {
+                let request = serde_json::to_vec(&Request {
+                    step: name.to_string(),
+                    argument,
+                })
+                .map_err(|error| IpcError::InvalidMessage(error.to_string()))?;
+                write_message(&mut running.input, &request)?;
+                read_message(&mut running.output)
+            });
+            let Ok(Some(response)) = response else {
+                // Start a new helper next time, as this one is gone.
+                if let Some(mut gone) = helper.take() {
+                    let _ = gone.child.wait();
+                }
+                return Err(response.err().map_or(PrivilegedError::Exited, Into::into));
+            };
+            drop(helper);
+            match serde_json::from_slice(&response)
+                .map_err(|error| IpcError::InvalidMessage(error.to_string()))?
+            {
+                Envelope::Ok(result) => result,
+                Envelope::Error(message) => return Err(PrivilegedError::Step(message)),
+            }
+        };
+        Ok(serde_json::from_value(result)
+            .map_err(|error| IpcError::InvalidMessage(error.to_string()))?)
+    }
+}
+
+impl Drop for Privileged {
+    fn drop(&mut self) {
+        let helper = self
+            .helper
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take();
+        if let Some(Helper {
+            mut child, input, ..
+        }) = helper
+        {
+            // Closing its input tells the helper to exit.
+            drop(input);
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod privileged_test {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let privileged = Privileged::new()
+            .step("add", |(first, second): (u32, u32)| Ok(first + second))
+            .step("fail", |(): ()| Err::<(), _>(String::from("no")));
+
+        let response = |request: serde_json::Value| -> serde_json::Value {
+            serde_json::from_slice(&privileged.handle(request.to_string().as_bytes()))
+                .expect("The response should be JSON")
+        };
+        assert_eq!(
+            response(serde_json::json!({"step": "add", "argument": [1, 2]})),
+            serde_json::json!({"ok": 3})
+        );
+        assert_eq!(
+            response(serde_json::json!({"step": "fail", "argument": null})),
+            serde_json::json!({"error": "no"})
+        );
+        assert_eq!(
+            response(serde_json::json!({"step": "missing", "argument": null})),
+            serde_json::json!({"error": "unknown step 'missing'"})
+        );
+        assert!(
+            response(serde_json::json!({"step": "add", "argument": "x"}))["error"]
+                .as_str()
+                .is_some_and(|message| message.starts_with("invalid argument"))
+        );
+
+        assert_eq!(
+            privileged.run::<_, ()>("missing", &()),
+            Err(PrivilegedError::UnknownStep(String::from("missing")))
+        );
+        if nix::unistd::geteuid().is_root() {
+            assert_eq!(privileged.run::<_, u32>("add", &(2, 3)), Ok(5));
+            assert_eq!(
+                privileged.run::<_, ()>("fail", &()),
+                Err(PrivilegedError::Step(String::from("no")))
+            );
+        }
+    }
+}
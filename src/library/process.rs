@@ -0,0 +1,670 @@
+//! This module contains functionality for running external programs in an easy
+//! manner.
+
+#[cfg(target_os = "linux")]
+pub mod cgroup;
+mod fixture;
+#[cfg(target_os = "linux")]
+mod oom;
+
+pub use fixture::{
+    Fixture,
+    Interaction,
+};
+#[cfg(target_os = "linux")]
+pub use oom::{
+    oom_score_adj,
+    reserve_memory_headroom,
+    set_oom_score_adj,
+    MemoryReserve,
+    OomError,
+    OomResult,
+};
+
+/// Describes possible errors when running external programs.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ProcessError {
+    #[error("The requested program does not exist")]
+    NotFound,
+    #[error("You lack permissions to execute the program")]
+    PermissionDenied,
+    #[error("The program exited unsuccessfully (exit code {code:?}): {stderr}")]
+    Failed { code: Option<i32>, stderr: String },
+    #[error("The program did not finish within {0:?} and was killed")]
+    TimedOut(std::time::Duration),
+    #[error("The program was killed: {0}")]
+    DeadlineExceeded(#[from] crate::deadline::DeadlineExceeded),
+    #[error("The fixture cannot answer the command: {0}")]
+    Fixture(String),
+    #[error("The program is not allowed to run: {0}")]
+    PolicyViolation(crate::policy::Violation),
+    #[error("A completely unexpected error occurred")]
+    Unknown(String),
+}
+
+impl From<std::io::Error> for ProcessError {
+    fn from(error: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        match error.kind() {
+            ErrorKind::NotFound => Self::NotFound,
+            ErrorKind::PermissionDenied => Self::PermissionDenied,
+            _ => Self::Unknown(format!("{}", error.kind())),
+        }
+    }
+}
+
+/// A [`Result`] whose error variant is a [`ProcessError`].
+pub type ProcessResult<T> = Result<T, ProcessError>;
+
+/// The captured result of a program that ran to completion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    /// The exit code, or [`None`] if the program was terminated by a signal
+    pub code:   Option<i32>,
+    /// Everything the program wrote to standard output
+    pub stdout: String,
+    /// Everything the program wrote to standard error
+    pub stderr: String,
+}
+
+impl Output {
+    /// Whether the program exited with code 0.
+    #[must_use]
+    pub const fn success(&self) -> bool { matches!(self.code, Some(0)) }
+}
+
+/// The I/O scheduling class of a program, see [`Command::ionice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IoClass {
+    /// Served before all other programs; requires root privileges
+    Realtime,
+    /// Served in turns with other programs, by level
+    BestEffort,
+    /// Only served when no other program wants to do I/O
+    Idle,
+}
+
+impl IoClass {
+    /// The number `ionice` identifies the class with.
+    const fn number(self) -> u8 {
+        match self {
+            Self::Realtime => 1,
+            Self::BestEffort => 2,
+            Self::Idle => 3,
+        }
+    }
+}
+
+/// Describes an invocation of an external program. The program is not run until
+/// [`Command::output`], [`Command::run`] or [`Command::spawn`] is called.
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// The program to run
+    program:           String,
+    /// The arguments passed to the program
+    arguments:         Vec<String>,
+    /// Additional environment variables for the program
+    environment:       Vec<(String, String)>,
+    /// The working directory of the program, if it differs from ours
+    working_directory: Option<std::path::PathBuf>,
+    /// How long the program may run before it is killed
+    timeout:           Option<std::time::Duration>,
+    /// By how much the scheduling priority of the program is lowered
+    nice:              Option<i8>,
+    /// The I/O scheduling class and level of the program
+    ionice:            Option<(IoClass, u8)>,
+    /// The OOM score adjustment of the program
+    oom_score_adj:     Option<i16>,
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}", self.program)?;
+        for argument in &self.arguments {
+            write!(f, " {argument}")?;
+        }
+        write!(f, "'")
+    }
+}
+
+impl Command {
+    /// Create a new invocation of `program` without any arguments.
+    pub fn new(program: impl AsRef<str>) -> Self {
+        Self {
+            program:           program.as_ref().to_string(),
+            arguments:         Vec::new(),
+            environment:       Vec::new(),
+            working_directory: None,
+            timeout:           None,
+            nice:              None,
+            ionice:            None,
+            oom_score_adj:     None,
+        }
+    }
+
+    /// Add a single argument.
+    #[must_use]
+    pub fn arg(mut self, argument: impl AsRef<str>) -> Self {
+        self.arguments.push(argument.as_ref().to_string());
+        self
+    }
+
+    /// Add multiple arguments.
+    #[must_use]
+    pub fn args<I, S>(mut self, arguments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.arguments.extend(
+            arguments
+                .into_iter()
+                .map(|argument| argument.as_ref().to_string()),
+        );
+        self
+    }
+
+    /// Set an environment variable for the program only.
+    #[must_use]
+    pub fn env(mut self, var_name: impl AsRef<str>, var_value: impl AsRef<str>) -> Self {
+        self.environment.push((
+            var_name.as_ref().to_string(),
+            var_value.as_ref().to_string(),
+        ));
+        self
+    }
+
+    /// Run the program in `directory` instead of the current working directory.
+    #[must_use]
+    pub fn current_dir(mut self, directory: impl AsRef<std::path::Path>) -> Self {
+        self.working_directory = Some(directory.as_ref().to_path_buf());
+        self
+    }
+
+    /// Kill the program if it has not finished after `timeout`. Running it then
+    /// fails with [`ProcessError::TimedOut`].
+    #[must_use]
+    pub const fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run the program with a scheduling priority lowered by `adjustment` (or raised,
+    /// if it is negative) relative to ours, like `nice -n` does. The adjustment is
+    /// limited to -20 to 19; raising the priority requires root privileges, without
+    /// them the program runs with our priority.
+    ///
+    /// ```no_run
+    /// use rush::process::{
+    ///     Command,
+    ///     IoClass,
+    /// };
+    ///
+    /// Command::new("tar")
+    ///     .args(["-czf", "/srv/backup.tar.gz", "/srv/data"])
+    ///     .nice(10)
+    ///     .ionice(IoClass::Idle, 0)
+    ///     .run()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn nice(mut self, adjustment: i8) -> Self {
+        self.nice = Some(adjustment.clamp(-20, 19));
+        self
+    }
+
+    /// Run the program with the I/O scheduling `class` and `level` (from 0, the
+    /// highest, to 7), like `ionice` does. The level is ignored for
+    /// [`IoClass::Idle`]. This uses `ionice`, which only exists on Linux.
+    #[must_use]
+    pub fn ionice(mut self, class: IoClass, level: u8) -> Self {
+        self.ionice = Some((class, level.min(7)));
+        self
+    }
+
+    /// Run the program with the OOM score adjustment `value` (limited to -1000 to
+    /// 1000), see [`set_oom_score_adj`]; the higher it is, the earlier the OOM
+    /// killer kills the program. This uses `choom`, which only exists on Linux.
+    /// Lowering it below ours requires root privileges, without them the program
+    /// does not run.
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn oom_score_adj(mut self, value: i16) -> Self {
+        self.oom_score_adj = Some(value.clamp(
+            *oom::OOM_SCORE_ADJ_RANGE.start(),
+            *oom::OOM_SCORE_ADJ_RANGE.end(),
+        ));
+        self
+    }
+
+    /// The program that is run.
+    #[must_use]
+    pub fn program(&self) -> &str { &self.program }
+
+    /// The arguments passed to the program.
+    #[must_use]
+    pub fn arguments(&self) -> &[String] { &self.arguments }
+
+    /// Build the [`std::process::Command`] that corresponds to this invocation.
+    fn to_std(&self) -> std::process::Command {
+        // `nice`, `ionice` and `choom` run the program themselves once they have set its
+        // priority.
+        let mut invocation = Vec::new();
+        if let Some(adjustment) = self.nice {
+            invocation.extend([
+                String::from("nice"),
+                String::from("-n"),
+                adjustment.to_string(),
+            ]);
+        }
+        if let Some((class, level)) = self.ionice {
+            invocation.extend([
+                String::from("ionice"),
+                String::from("-c"),
+                class.number().to_string(),
+            ]);
+            if class != IoClass::Idle {
+                invocation.extend([String::from("-n"), level.to_string()]);
+            }
+        }
+        if let Some(value) = self.oom_score_adj {
+            invocation.extend([
+                String::from("choom"),
+                String::from("-n"),
+                value.to_string(),
+                String::from("--"),
+            ]);
+        }
+        invocation.push(self.program.clone());
+        invocation.extend(self.arguments.iter().cloned());
+
+        let mut command = std::process::Command::new(&invocation[0]);
+        command.args(&invocation[1..]);
+        command.envs(self.environment.iter().map(|(name, value)| (name, value)));
+        if let Some(directory) = &self.working_directory {
+            command.current_dir(directory);
+        }
+        command
+    }
+
+    /// Run the program to completion and capture its output. A non-zero exit code is
+    /// _not_ considered an error; use [`Command::run`] for that. With a [`Fixture`]
+    /// active on this thread, the program is recorded or replayed. The run is
+    /// reported to [`crate::instrument`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program could not be started, if the
+    /// [`Policy`](crate::policy::Policy) in effect forbids it, if an active
+    /// [`Fixture`] did not record it or if it was killed because of its timeout or
+    /// the [`Deadline`](crate::deadline::Deadline) in effect.
+    pub fn output(&self) -> ProcessResult<Output> {
+        crate::instrument::observe(
+            crate::instrument::Subsystem::Process,
+            "run",
+            self,
+            None,
+            || {
+                crate::policy::check(|policy| policy.check_command(&self.program))
+                    .map_err(ProcessError::PolicyViolation)?;
+                fixture::intercept(self, || self.run_to_completion())
+            },
+        )
+    }
+
+    /// Run the program to completion and capture its output, bypassing fixtures.
+    fn run_to_completion(&self) -> ProcessResult<Output> {
+        use std::process::Stdio;
+
+        log::trace!("Running {}", self);
+        let output = self
+            .start_unchecked(|command| {
+                command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+            })?
+            .wait_with_output()?;
+        Ok(Output {
+            code:   output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    /// Run the program to completion and capture its output, like a shell with
+    /// `set -e` would.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program could not be started or if it exited with a
+    /// non-zero exit code.
+    pub fn run(&self) -> ProcessResult<Output> {
+        let output = self.output()?;
+        if output.success() {
+            Ok(output)
+        } else {
+            log::debug!("{} exited with code {:?}", self, output.code);
+            Err(ProcessError::Failed {
+                code:   output.code,
+                stderr: output.stderr.trim().to_string(),
+            })
+        }
+    }
+
+    /// Start the program in the background. Standard input, output and error are
+    /// discarded. A [`Fixture`] does not record or replay it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program could not be started or if the
+    /// [`Policy`](crate::policy::Policy) in effect forbids it.
+    pub fn spawn(&self) -> ProcessResult<std::process::Child> {
+        use std::process::Stdio;
+        crate::policy::check(|policy| policy.check_command(&self.program))
+            .map_err(ProcessError::PolicyViolation)?;
+        log::trace!("Spawning {}", self);
+        Ok(self
+            .to_std()
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?)
+    }
+
+    /// Start the program with the standard streams `configure` sets up, for callers
+    /// that feed or read them while it runs. Waiting for it is limited by its
+    /// timeout and the [`Deadline`](crate::deadline::Deadline) in effect, see
+    /// [`Running`]. A [`Fixture`] does not record or replay it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program could not be started, if the
+    /// [`Policy`](crate::policy::Policy) in effect forbids it or if the deadline
+    /// has passed.
+    pub(crate) fn start(
+        &self,
+        configure: impl FnOnce(&mut std::process::Command),
+    ) -> ProcessResult<Running> {
+        crate::policy::check(|policy| policy.check_command(&self.program))
+            .map_err(ProcessError::PolicyViolation)?;
+        log::trace!("Starting {}", self);
+        self.start_unchecked(configure)
+    }
+
+    /// Like [`Command::start`], but without checking the policy.
+    fn start_unchecked(
+        &self,
+        configure: impl FnOnce(&mut std::process::Command),
+    ) -> ProcessResult<Running> {
+        let limit = crate::deadline::limit(self.timeout)?;
+        let mut command = self.to_std();
+        configure(&mut command);
+        Ok(Running {
+            child:    command.spawn()?,
+            deadline: limit.map(|limit| std::time::Instant::now() + limit),
+            // The deadline is what ends the program unless its own timeout does.
+            timeout:  limit.filter(|limit| self.timeout == Some(*limit)),
+        })
+    }
+}
+
+/// A program started with [`Command::start`]. If it is still running when its
+/// timeout or the [`Deadline`](crate::deadline::Deadline) in effect has passed,
+/// waiting for it kills it.
+#[derive(Debug)]
+pub(crate) struct Running {
+    /// The program
+    child:    std::process::Child,
+    /// When the program is killed, if there is a limit
+    deadline: Option<std::time::Instant>,
+    /// The timeout of the program, if it sets the limit rather than the deadline
+    timeout:  Option<std::time::Duration>,
+}
+
+#[allow(
+    clippy::missing_const_for_fn,
+    reason = "`Option::take` is not const on the pinned toolchain"
+)]
+impl Running {
+    /// Take the pipe to the standard input of the program, if it is one.
+    pub(crate) fn take_stdin(&mut self) -> Option<std::process::ChildStdin> {
+        self.child.stdin.take()
+    }
+
+    /// Take the pipe from the standard output of the program, if it is one.
+    pub(crate) fn take_stdout(&mut self) -> Option<std::process::ChildStdout> {
+        self.child.stdout.take()
+    }
+
+    /// Take the pipe from the standard error of the program, if it is one.
+    pub(crate) fn take_stderr(&mut self) -> Option<std::process::ChildStderr> {
+        self.child.stderr.take()
+    }
+}
+
+impl Running {
+    /// The process ID of the program.
+    pub(crate) fn id(&self) -> u32 { self.child.id() }
+
+    /// Kill the program. Killing a program that has exited already does nothing.
+    pub(crate) fn kill(&mut self) { let _ = self.child.kill(); }
+
+    /// The exit status of the program if it has exited, without waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if checking fails or if the limit has passed, in which case
+    /// the program is killed.
+    pub(crate) fn try_wait(&mut self) -> ProcessResult<Option<std::process::ExitStatus>> {
+        if let Some(status) = self.child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| std::time::Instant::now() >= deadline)
+        {
+            // The program may have exited in the meantime, which is fine.
+            self.child.kill().ok();
+            self.child.wait()?;
+            return Err(self.timeout.map_or_else(
+                || crate::deadline::DeadlineExceeded.into(),
+                ProcessError::TimedOut,
+            ));
+        }
+        Ok(None)
+    }
+
+    /// Wait for the program to exit and return its exit status.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting fails or if the limit has passed, in which case
+    /// the program is killed.
+    pub(crate) fn wait(&mut self) -> ProcessResult<std::process::ExitStatus> {
+        if self.deadline.is_none() {
+            return Ok(self.child.wait()?);
+        }
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(status);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    /// Wait for the program to exit like [`Running::wait`], reading its standard
+    /// output and error (if they are pipes) in the meantime, like
+    /// [`std::process::Child::wait_with_output`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting or reading fails or if the limit has passed, in
+    /// which case the program is killed.
+    pub(crate) fn wait_with_output(mut self) -> ProcessResult<std::process::Output> {
+        use std::io::Read;
+
+        /// Read `pipe` to its end on a separate thread, so that the program cannot
+        /// block on a full pipe.
+        fn drain(
+            pipe: Option<impl Read + Send + 'static>,
+        ) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+            std::thread::spawn(move || {
+                let mut content = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    pipe.read_to_end(&mut content)?;
+                }
+                Ok(content)
+            })
+        }
+
+        let stdout = drain(self.take_stdout());
+        let stderr = drain(self.take_stderr());
+        let status = self.wait()?;
+        let join = |reader: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+            reader
+                .join()
+                .map_err(|_| ProcessError::Unknown(String::from("reading the output panicked")))?
+                .map_err(ProcessError::from)
+        };
+        Ok(std::process::Output {
+            status,
+            stdout: join(stdout)?,
+            stderr: join(stderr)?,
+        })
+    }
+}
+
+/// Set the scheduling priority of this process to `level`, from -20 (the highest)
+/// to 19, like `renice` does.
+///
+/// Programs started afterwards inherit it. On Linux, this applies to the main
+/// thread and the threads it starts afterwards.
+///
+/// ```no_run
+/// // Maintenance must not slow down the services on this host.
+/// rush::process::renice_self(15).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `renice` cannot be run or fails, e.g. because raising the
+/// priority requires root privileges.
+pub fn renice_self(level: i8) -> ProcessResult<()> {
+    Command::new("renice")
+        .args([
+            level.clamp(-20, 19).to_string(),
+            String::from("-p"),
+            std::process::id().to_string(),
+        ])
+        .run()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod command_test {
+    use super::*;
+
+    #[test]
+    fn output_and_run() -> ProcessResult<()> {
+        let output = Command::new("echo").args(["Hello", "there"]).run()?;
+        assert_eq!(output.stdout, "Hello there\n");
+        assert!(output.success());
+
+        let output = Command::new("false").output()?;
+        assert_eq!(output.code, Some(1));
+        assert!(matches!(
+            Command::new("false").run(),
+            Err(ProcessError::Failed { code: Some(1), .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn environment_and_directory() -> ProcessResult<()> {
+        let output = Command::new("sh")
+            .args(["-c", "echo \"${RUSH_TEST}\" && pwd"])
+            .env("RUSH_TEST", "value")
+            .current_dir("/")
+            .run()?;
+        assert_eq!(output.stdout, "value\n/\n");
+        Ok(())
+    }
+
+    #[test]
+    fn timeout() -> ProcessResult<()> {
+        let timeout = std::time::Duration::from_millis(100);
+        let started = std::time::Instant::now();
+        assert_eq!(
+            Command::new("sleep").arg("5").timeout(timeout).output(),
+            Err(ProcessError::TimedOut(timeout))
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+        let output = Command::new("echo")
+            .arg("in time")
+            .timeout(std::time::Duration::from_secs(5))
+            .run()?;
+        assert_eq!(output.stdout, "in time\n");
+        Ok(())
+    }
+
+    #[test]
+    fn priority() -> ProcessResult<()> {
+        let ours = Command::new("nice")
+            .run()?
+            .stdout
+            .trim()
+            .parse::<i8>()
+            .unwrap();
+        let output = Command::new("nice").nice(5).run()?;
+        assert_eq!(output.stdout, format!("{}\n", (ours + 5).min(19)));
+
+        let output = Command::new("ionice").ionice(IoClass::Idle, 4).run()?;
+        assert_eq!(output.stdout, "idle\n");
+        let output = Command::new("ionice")
+            .nice(1)
+            .ionice(IoClass::BestEffort, 9)
+            .run()?;
+        assert_eq!(output.stdout, "best-effort: prio 7\n");
+        Ok(())
+    }
+
+    #[test]
+    fn start_checks_policy_and_deadline() -> ProcessResult<()> {
+        use std::process::Stdio;
+
+        let quiet = |command: &mut std::process::Command| {
+            command.stdout(Stdio::null());
+        };
+        {
+            let _policy = crate::policy::Policy::new()
+                .forbid_command("sleep")
+                .install();
+            assert!(matches!(
+                Command::new("sleep").arg("5").start(quiet),
+                Err(ProcessError::PolicyViolation(_))
+            ));
+        }
+
+        let _deadline =
+            crate::deadline::Deadline::after(std::time::Duration::from_millis(100)).install();
+        let started = std::time::Instant::now();
+        let mut running = Command::new("sleep").arg("5").start(quiet)?;
+        assert_eq!(
+            running.wait(),
+            Err(crate::deadline::DeadlineExceeded.into())
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn not_found() {
+        assert_eq!(
+            Command::new("this-program-does-not-exist").run(),
+            Err(ProcessError::NotFound)
+        );
+    }
+}
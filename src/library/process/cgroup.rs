@@ -0,0 +1,534 @@
+//! This module contains limiting the resources of programs with cgroups (version 2).
+//!
+//! This is tighter than resource limits: the limits apply to all programs in a
+//! [`Scope`] together, including everything they start.
+//!
+//! A scope is a transient cgroup that is removed, and the programs still running in
+//! it killed, when the scope is dropped. On hosts booted with systemd, the cgroup is
+//! a slice managed with `systemctl` and programs are started in it with
+//! `systemd-run`; otherwise, it is created directly below `/sys/fs/cgroup`. Both
+//! require root privileges.
+//!
+//! ```no_run
+//! use rush::process::{
+//!     cgroup::{
+//!         Limits,
+//!         Scope,
+//!     },
+//!     Command,
+//! };
+//!
+//! let limits = Limits::new()
+//!     .cpus(1.5)
+//!     .memory(2 * 1024 * 1024 * 1024)
+//!     .io_write_bandwidth("/dev/sda", 50 * 1024 * 1024);
+//! let scope = Scope::new(&limits).unwrap();
+//! scope
+//!     .command(&Command::new("rsync").args(["-a", "/srv/data/", "/mnt/backup/"]))
+//!     .run()
+//!     .unwrap();
+//! ```
+
+use super::{
+    Command,
+    ProcessError,
+};
+use crate::fs::{
+    Directory,
+    FSError,
+    File,
+    Object as _,
+};
+
+/// Describes possible errors when managing cgroups.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum CgroupError {
+    #[error("The system does not use cgroups version 2")]
+    Unsupported,
+    #[error("The limit is not valid: {0}")]
+    InvalidLimit(String),
+    #[error("Accessing the cgroup failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running a program failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`CgroupError`].
+pub type CgroupResult<T> = Result<T, CgroupError>;
+
+/// Where cgroups (version 2) are mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// The period CPU quotas are measured in, in microseconds.
+const CPU_PERIOD: u64 = 100_000;
+/// How long dropping a [`Scope`] waits for the programs in it to be killed.
+const KILL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The bandwidth limits of one block device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DeviceLimit {
+    /// The device node, e.g. `/dev/sda`
+    device: std::path::PathBuf,
+    /// The maximum bytes read per second
+    read:   Option<u64>,
+    /// The maximum bytes written per second
+    write:  Option<u64>,
+}
+
+/// The resources the programs in a [`Scope`] may use together. Resources without a
+/// limit are not limited.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// How many CPUs may be used
+    cpus:      Option<f64>,
+    /// The maximum memory in bytes
+    memory:    Option<u64>,
+    /// The maximum number of processes and threads
+    processes: Option<u64>,
+    /// The bandwidth limits per block device
+    devices:   Vec<DeviceLimit>,
+}
+
+impl Limits {
+    /// No limits at all.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Use at most `cpus` CPUs worth of time, e.g. `0.5` for half of one CPU.
+    #[must_use]
+    pub const fn cpus(mut self, cpus: f64) -> Self {
+        self.cpus = Some(cpus);
+        self
+    }
+
+    /// Use at most `bytes` of memory; programs exceeding it are killed.
+    #[must_use]
+    pub const fn memory(mut self, bytes: u64) -> Self {
+        self.memory = Some(bytes);
+        self
+    }
+
+    /// Run at most `count` processes and threads.
+    #[must_use]
+    pub const fn processes(mut self, count: u64) -> Self {
+        self.processes = Some(count);
+        self
+    }
+
+    /// Read at most `bytes_per_second` from the block device `device`, e.g.
+    /// `/dev/sda`.
+    #[must_use]
+    pub fn io_read_bandwidth(
+        mut self,
+        device: impl AsRef<std::path::Path>,
+        bytes_per_second: u64,
+    ) -> Self {
+        self.device(device).read = Some(bytes_per_second);
+        self
+    }
+
+    /// Write at most `bytes_per_second` to the block device `device`, e.g.
+    /// `/dev/sda`.
+    #[must_use]
+    pub fn io_write_bandwidth(
+        mut self,
+        device: impl AsRef<std::path::Path>,
+        bytes_per_second: u64,
+    ) -> Self {
+        self.device(device).write = Some(bytes_per_second);
+        self
+    }
+
+    /// The bandwidth limits of `device`, which are added if there are none yet.
+    fn device(&mut self, device: impl AsRef<std::path::Path>) -> &mut DeviceLimit {
+        let device = device.as_ref();
+        if let Some(index) = self.devices.iter().position(|limit| limit.device == device) {
+            return &mut self.devices[index];
+        }
+        self.devices.push(DeviceLimit {
+            device: device.to_path_buf(),
+            read:   None,
+            write:  None,
+        });
+        let last = self.devices.len() - 1;
+        &mut self.devices[last]
+    }
+
+    /// The CPU quota in microseconds per [`CPU_PERIOD`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CgroupError::InvalidLimit`] if the number of CPUs is not positive.
+    fn cpu_quota(&self) -> CgroupResult<Option<u64>> {
+        self.cpus
+            .map(|cpus| {
+                if !cpus.is_finite() || cpus <= 0.0 {
+                    return Err(CgroupError::InvalidLimit(format!("{cpus} CPUs")));
+                }
+                #[allow(
+                    clippy::cast_possible_truncation,
+                    clippy::cast_precision_loss,
+                    clippy::cast_sign_loss
+                )]
+                Ok(((cpus * CPU_PERIOD as f64).round() as u64).max(1_000))
+            })
+            .transpose()
+    }
+
+    /// The properties of a systemd unit that set these limits, e.g.
+    /// `MemoryMax=1024`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CgroupError::InvalidLimit`] if a limit is not valid.
+    fn systemd_properties(&self) -> CgroupResult<Vec<String>> {
+        let mut properties = Vec::new();
+        if let Some(quota) = self.cpu_quota()? {
+            properties.push(format!("CPUQuota={}%", quota * 100 / CPU_PERIOD));
+        }
+        if let Some(memory) = self.memory {
+            properties.push(format!("MemoryMax={memory}"));
+        }
+        if let Some(processes) = self.processes {
+            properties.push(format!("TasksMax={processes}"));
+        }
+        for limit in &self.devices {
+            let device = limit.device.display();
+            if let Some(read) = limit.read {
+                properties.push(format!("IOReadBandwidthMax={device} {read}"));
+            }
+            if let Some(write) = limit.write {
+                properties.push(format!("IOWriteBandwidthMax={device} {write}"));
+            }
+        }
+        Ok(properties)
+    }
+
+    /// The controllers and the content of the control files of a cgroup that set
+    /// these limits, e.g. `("memory", "memory.max", "1024")`. `device_number`
+    /// resolves a device node to its `major:minor` number.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CgroupError::InvalidLimit`] if a limit is not valid, or an error if
+    /// a device cannot be resolved.
+    fn control_files(
+        &self,
+        device_number: impl Fn(&std::path::Path) -> CgroupResult<String>,
+    ) -> CgroupResult<Vec<(&'static str, &'static str, String)>> {
+        let mut files = Vec::new();
+        if let Some(quota) = self.cpu_quota()? {
+            files.push(("cpu", "cpu.max", format!("{quota} {CPU_PERIOD}")));
+        }
+        if let Some(memory) = self.memory {
+            files.push(("memory", "memory.max", memory.to_string()));
+        }
+        if let Some(processes) = self.processes {
+            files.push(("pids", "pids.max", processes.to_string()));
+        }
+        for limit in &self.devices {
+            let mut line = vec![device_number(&limit.device)?];
+            if let Some(read) = limit.read {
+                line.push(format!("rbps={read}"));
+            }
+            if let Some(write) = limit.write {
+                line.push(format!("wbps={write}"));
+            }
+            files.push(("io", "io.max", line.join(" ")));
+        }
+        Ok(files)
+    }
+}
+
+/// The `major:minor` number of the block device `device`.
+fn device_number(device: &std::path::Path) -> CgroupResult<String> {
+    use std::os::unix::fs::{
+        FileTypeExt as _,
+        MetadataExt as _,
+    };
+    let metadata = std::fs::metadata(device).map_err(FSError::from)?;
+    if !metadata.file_type().is_block_device() {
+        return Err(CgroupError::InvalidLimit(format!(
+            "'{}' is not a block device",
+            device.display()
+        )));
+    }
+    let number = metadata.rdev();
+    Ok(format!(
+        "{}:{}",
+        nix::sys::stat::major(number),
+        nix::sys::stat::minor(number)
+    ))
+}
+
+/// What manages the cgroup of a [`Scope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Manager {
+    /// systemd, with a transient slice
+    Systemd,
+    /// Nothing; the cgroup is created directly below `/sys/fs/cgroup`
+    Direct,
+}
+
+/// A transient cgroup with [`Limits`] that programs are started in. Dropping it
+/// kills the programs still running in it and removes it.
+#[derive(Debug)]
+pub struct Scope {
+    /// What manages the cgroup
+    manager: Manager,
+    /// The name of the slice or cgroup
+    name:    String,
+}
+
+impl Scope {
+    /// Create a scope with `limits`, managed by systemd on hosts booted with it and
+    /// directly otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CgroupError::Unsupported`] if the system does not use cgroups
+    /// version 2, [`CgroupError::InvalidLimit`] if a limit is not valid, or an error
+    /// if the cgroup cannot be created (usually because of missing privileges).
+    pub fn new(limits: &Limits) -> CgroupResult<Self> {
+        let manager = if crate::system::booted_with_systemd()? {
+            Manager::Systemd
+        } else {
+            Manager::Direct
+        };
+        Self::with_manager(limits, manager)
+    }
+
+    /// Create a scope with `limits` that is managed by `manager`.
+    ///
+    /// # Errors
+    ///
+    /// Like [`Scope::new`].
+    pub fn with_manager(limits: &Limits, manager: Manager) -> CgroupResult<Self> {
+        if !File::new(format!("{CGROUP_ROOT}/cgroup.controllers")).exists()? {
+            return Err(CgroupError::Unsupported);
+        }
+        let token = crate::crypto::random::token_hex(8)
+            .map_err(|error| FSError::Unknown(error.to_string()))?;
+        let scope = match manager {
+            Manager::Systemd => Self {
+                manager,
+                name: format!("rush-{token}.slice"),
+            },
+            Manager::Direct => Self {
+                manager,
+                name: format!("rush-{token}"),
+            },
+        };
+        log::debug!("Creating the cgroup {}", scope.name);
+        // Dropping the scope cleans up whatever was set up before a failure.
+        match manager {
+            Manager::Systemd => scope.create_slice(limits)?,
+            Manager::Direct => scope.create_cgroup(limits)?,
+        }
+        Ok(scope)
+    }
+
+    /// Set `limits` on the slice, which systemd creates once something runs in it.
+    fn create_slice(&self, limits: &Limits) -> CgroupResult<()> {
+        let properties = limits.systemd_properties()?;
+        if !properties.is_empty() {
+            Command::new("systemctl")
+                .args(["set-property", "--runtime", &self.name])
+                .args(properties)
+                .run()?;
+        }
+        Ok(())
+    }
+
+    /// Create the cgroup and enable the controllers needed for `limits` for it.
+    fn create_cgroup(&self, limits: &Limits) -> CgroupResult<()> {
+        let files = limits.control_files(device_number)?;
+        let mut controllers = files
+            .iter()
+            .map(|(controller, ..)| format!("+{controller}"))
+            .collect::<Vec<_>>();
+        controllers.dedup();
+        if !controllers.is_empty() {
+            File::new(format!("{CGROUP_ROOT}/cgroup.subtree_control"))
+                .overwrite(controllers.join(" "))?;
+        }
+        Directory::new(self.path()).create_on_fs()?;
+        for (_, name, content) in files {
+            File::new(self.path().join(name)).overwrite(content)?;
+        }
+        Ok(())
+    }
+
+    /// What manages the cgroup.
+    #[must_use]
+    pub const fn manager(&self) -> Manager { self.manager }
+
+    /// The path of the cgroup, e.g. `/sys/fs/cgroup/rush-<random>`.
+    #[must_use]
+    pub fn path(&self) -> std::path::PathBuf {
+        match self.manager {
+            // `rush-<random>.slice` is a child of `rush.slice`.
+            Manager::Systemd => std::path::Path::new(CGROUP_ROOT)
+                .join("rush.slice")
+                .join(&self.name),
+            Manager::Direct => std::path::Path::new(CGROUP_ROOT).join(&self.name),
+        }
+    }
+
+    /// `command` changed to run in this scope; the program moves itself into the
+    /// cgroup before it starts, so everything it starts runs in the cgroup too.
+    #[must_use]
+    pub fn command(&self, command: &Command) -> Command {
+        let arguments = match self.manager {
+            Manager::Systemd => [
+                "--scope",
+                "--quiet",
+                "--collect",
+                &format!("--slice={}", self.name),
+                "--",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>(),
+            Manager::Direct => vec![
+                String::from("-c"),
+                String::from("echo $$ > \"$0\" && exec \"$@\""),
+                self.path()
+                    .join("cgroup.procs")
+                    .to_string_lossy()
+                    .into_owned(),
+            ],
+        };
+        let program = match self.manager {
+            Manager::Systemd => "systemd-run",
+            Manager::Direct => "sh",
+        };
+        Command {
+            program: String::from(program),
+            arguments: arguments
+                .into_iter()
+                .chain(std::iter::once(command.program.clone()))
+                .chain(command.arguments.iter().cloned())
+                .collect(),
+            ..command.clone()
+        }
+    }
+
+    /// Kill the programs in the cgroup and remove it.
+    fn remove(&self) -> CgroupResult<()> {
+        match self.manager {
+            Manager::Systemd => {
+                Command::new("systemctl").args(["stop", &self.name]).run()?;
+                Command::new("systemctl")
+                    .args(["revert", &self.name])
+                    .run()?;
+            },
+            Manager::Direct => {
+                if !Directory::new(self.path()).exists()? {
+                    return Ok(());
+                }
+                File::new(self.path().join("cgroup.kill")).overwrite("1")?;
+                let events = File::new(self.path().join("cgroup.events"));
+                let started = std::time::Instant::now();
+                while events.read()?.contains("populated 1") {
+                    if started.elapsed() > KILL_TIMEOUT {
+                        return Err(FSError::Unknown(String::from(
+                            "programs in the cgroup are still running",
+                        ))
+                        .into());
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                // The control files cannot be deleted, only the cgroup as a whole.
+                std::fs::remove_dir(self.path()).map_err(FSError::from)?;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        log::debug!("Removing the cgroup {}", self.name);
+        if let Err(error) = self.remove() {
+            log::warn!("Could not remove the cgroup {}: {}", self.name, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod cgroup_test {
+    use super::*;
+
+    #[test]
+    fn limits() -> CgroupResult<()> {
+        let limits = Limits::new()
+            .cpus(1.5)
+            .memory(1024)
+            .processes(64)
+            .io_read_bandwidth("/dev/sda", 10)
+            .io_write_bandwidth("/dev/sda", 20);
+        assert_eq!(
+            limits.systemd_properties()?,
+            [
+                "CPUQuota=150%",
+                "MemoryMax=1024",
+                "TasksMax=64",
+                "IOReadBandwidthMax=/dev/sda 10",
+                "IOWriteBandwidthMax=/dev/sda 20",
+            ]
+        );
+        assert_eq!(
+            limits.control_files(|_| Ok(String::from("8:0")))?,
+            [
+                ("cpu", "cpu.max", String::from("150000 100000")),
+                ("memory", "memory.max", String::from("1024")),
+                ("pids", "pids.max", String::from("64")),
+                ("io", "io.max", String::from("8:0 rbps=10 wbps=20")),
+            ]
+        );
+
+        assert!(Limits::new().systemd_properties()?.is_empty());
+        assert!(matches!(
+            Limits::new().cpus(0.0).systemd_properties(),
+            Err(CgroupError::InvalidLimit(_))
+        ));
+        assert!(matches!(
+            device_number(std::path::Path::new("/")),
+            Err(CgroupError::InvalidLimit(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn command() {
+        let scope = std::mem::ManuallyDrop::new(Scope {
+            manager: Manager::Direct,
+            name:    String::from("rush-test"),
+        });
+        let command = scope.command(&Command::new("echo").arg("hi").env("A", "b"));
+        assert_eq!(command.program(), "sh");
+        assert_eq!(
+            command.arguments()[2],
+            "/sys/fs/cgroup/rush-test/cgroup.procs"
+        );
+        assert_eq!(&command.arguments()[3..], ["echo", "hi"]);
+        assert_eq!(
+            command.environment,
+            [(String::from("A"), String::from("b"))]
+        );
+
+        let scope = std::mem::ManuallyDrop::new(Scope {
+            manager: Manager::Systemd,
+            name:    String::from("rush-test.slice"),
+        });
+        let command = scope.command(&Command::new("echo").arg("hi"));
+        assert_eq!(command.program(), "systemd-run");
+        assert!(command
+            .arguments()
+            .contains(&String::from("--slice=rush-test.slice")));
+        assert_eq!(
+            scope.path(),
+            std::path::Path::new("/sys/fs/cgroup/rush.slice/rush-test.slice")
+        );
+    }
+}
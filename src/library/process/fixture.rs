@@ -0,0 +1,313 @@
+//! This module contains a test double for running external programs: commands are
+//! recorded to a fixture file once and answered from it afterwards.
+
+use super::{
+    Command,
+    Output,
+    ProcessError,
+    ProcessResult,
+};
+
+/// One recorded invocation of a program and its outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Interaction {
+    /// The program that was run
+    pub program:   String,
+    /// The arguments passed to the program
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    /// The exit code, or [`None`] if the program was terminated by a signal
+    pub code:      Option<i32>,
+    /// Everything the program wrote to standard output
+    #[serde(default)]
+    pub stdout:    String,
+    /// Everything the program wrote to standard error
+    #[serde(default)]
+    pub stderr:    String,
+}
+
+impl Interaction {
+    /// Whether this interaction is an invocation of `command`.
+    fn matches(&self, command: &Command) -> bool {
+        self.program == command.program && self.arguments == command.arguments
+    }
+}
+
+/// Whether commands are recorded or replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Mode {
+    /// Commands are run and their outcomes recorded
+    Record,
+    /// Commands are answered from the recorded outcomes
+    Replay,
+}
+
+/// The fixture that is active on a thread.
+#[derive(Debug)]
+struct Active {
+    /// Whether commands are recorded or replayed
+    mode:         Mode,
+    /// The recorded interactions, or those not replayed yet
+    interactions: Vec<Interaction>,
+}
+
+thread_local! {
+    /// The fixture that is active on this thread, if any.
+    static ACTIVE: std::cell::RefCell<Option<Active>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Run `command` with `run`, unless a fixture on this thread replays it; record the
+/// outcome if a fixture on this thread records.
+pub(super) fn intercept(
+    command: &Command,
+    run: impl FnOnce() -> ProcessResult<Output>,
+) -> ProcessResult<Output> {
+    let mode = ACTIVE.with_borrow(|active| active.as_ref().map(|active| active.mode));
+    match mode {
+        None => run(),
+        Some(Mode::Replay) => ACTIVE.with_borrow_mut(|active| {
+            let interactions = active
+                .as_mut()
+                .map(|active| &mut active.interactions)
+                .ok_or_else(|| ProcessError::Fixture(String::from("no fixture is active")))?;
+            let index = interactions
+                .iter()
+                .position(|interaction| interaction.matches(command))
+                .ok_or_else(|| ProcessError::Fixture(format!("{command} was not recorded")))?;
+            log::trace!("Replaying {}", command);
+            let interaction = interactions.remove(index);
+            Ok(Output {
+                code:   interaction.code,
+                stdout: interaction.stdout,
+                stderr: interaction.stderr,
+            })
+        }),
+        Some(Mode::Record) => {
+            let output = run()?;
+            ACTIVE.with_borrow_mut(|active| {
+                if let Some(active) = active {
+                    active.interactions.push(Interaction {
+                        program:   command.program.clone(),
+                        arguments: command.arguments.clone(),
+                        code:      output.code,
+                        stdout:    output.stdout.clone(),
+                        stderr:    output.stderr.clone(),
+                    });
+                }
+            });
+            Ok(output)
+        },
+    }
+}
+
+/// Records or replays the programs run with [`Command::output`] and
+/// [`Command::run`], so that scripts calling `kubectl`, `terraform` and the like can
+/// be unit-tested.
+///
+/// Record once against the real tools with [`Fixture::record`] and commit the
+/// fixture file. Tests then use [`Fixture::replay`]: every command is answered from
+/// the file, in the order of recording for repeated commands, and nothing is run. A
+/// command that was not recorded fails with [`ProcessError::Fixture`].
+///
+/// A fixture applies to commands run on the thread that created it, so tests
+/// running in parallel do not interfere. [`Command::spawn`] and programs started in
+/// other ways are not covered.
+///
+/// ```no_run
+/// # use rush::process::{Command, Fixture};
+/// let fixture = Fixture::replay("tests/fixtures/deploy.json").unwrap();
+/// let pods = Command::new("kubectl").args(["get", "pods", "-o", "name"]).run().unwrap();
+/// assert_eq!(pods.stdout, "pod/web-0\n");
+/// fixture.finish().unwrap();
+/// ```
+#[derive(Debug)]
+#[must_use = "the fixture is deactivated when it is dropped"]
+pub struct Fixture {
+    /// The fixture file
+    path:     std::path::PathBuf,
+    /// Whether [`Fixture::finish`] already ran
+    finished: bool,
+}
+
+impl Fixture {
+    /// Activate `interactions` in `mode` on this thread.
+    fn activate(
+        path: &std::path::Path,
+        mode: Mode,
+        interactions: Vec<Interaction>,
+    ) -> ProcessResult<Self> {
+        ACTIVE.with_borrow_mut(|active| {
+            if active.is_some() {
+                return Err(ProcessError::Fixture(String::from(
+                    "another fixture is active on this thread",
+                )));
+            }
+            *active = Some(Active { mode, interactions });
+            Ok(())
+        })?;
+        Ok(Self {
+            path:     path.to_path_buf(),
+            finished: false,
+        })
+    }
+
+    /// Run commands on this thread as usual and record them to the fixture file at
+    /// `path`, which is written by [`Fixture::finish`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if another fixture is active on this thread.
+    pub fn record(path: impl AsRef<std::path::Path>) -> ProcessResult<Self> {
+        Self::activate(path.as_ref(), Mode::Record, vec![])
+    }
+
+    /// Answer commands on this thread from the fixture file at `path` instead of
+    /// running them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fixture file cannot be read or parsed, or if another
+    /// fixture is active on this thread.
+    pub fn replay(path: impl AsRef<std::path::Path>) -> ProcessResult<Self> {
+        let path = path.as_ref();
+        let invalid = |error: &dyn std::fmt::Display| {
+            ProcessError::Fixture(format!("'{}' cannot be read: {error}", path.display()))
+        };
+        let content = std::fs::read_to_string(path).map_err(|error| invalid(&error))?;
+        let interactions = serde_json::from_str(&content).map_err(|error| invalid(&error))?;
+        Self::activate(path, Mode::Replay, interactions)
+    }
+
+    /// Deactivate the fixture. A recording is written to the fixture file; a replay
+    /// checks that every recorded command was run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording cannot be written or if recorded commands
+    /// were not run.
+    pub fn finish(mut self) -> ProcessResult<()> {
+        self.finished = true;
+        let Some(active) = ACTIVE.take() else {
+            return Ok(());
+        };
+        match active.mode {
+            Mode::Record => {
+                let mut content = serde_json::to_string_pretty(&active.interactions)
+                    .map_err(|error| ProcessError::Fixture(error.to_string()))?;
+                content.push('\n');
+                std::fs::write(&self.path, content).map_err(|error| {
+                    ProcessError::Fixture(format!(
+                        "'{}' cannot be written: {error}",
+                        self.path.display()
+                    ))
+                })
+            },
+            Mode::Replay if active.interactions.is_empty() => Ok(()),
+            Mode::Replay => Err(ProcessError::Fixture(format!(
+                "{} recorded commands were not run, the first being '{}'",
+                active.interactions.len(),
+                active.interactions[0].program
+            ))),
+        }
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if !self.finished {
+            log::debug!(
+                "Fixture '{}' was dropped without finishing",
+                self.path.display()
+            );
+            ACTIVE.set(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod fixture_test {
+    use super::*;
+
+    #[test]
+    fn record_and_replay() -> ProcessResult<()> {
+        let path = crate::fs::generate_test_path();
+
+        let fixture = Fixture::record(&path)?;
+        assert_eq!(
+            Command::new("echo").arg("recorded").run()?.stdout,
+            "recorded\n"
+        );
+        assert_eq!(
+            Command::new("sh").args(["-c", "exit 3"]).output()?.code,
+            Some(3)
+        );
+        fixture.finish()?;
+        let recorded: Vec<Interaction> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].arguments, ["recorded"]);
+
+        // Nothing is run when replaying, so changed fixtures show.
+        let mut edited = recorded;
+        edited[0].stdout = String::from("replayed\n");
+        std::fs::write(&path, serde_json::to_string(&edited).unwrap()).unwrap();
+        let fixture = Fixture::replay(&path)?;
+        assert_eq!(
+            Command::new("echo").arg("recorded").run()?.stdout,
+            "replayed\n"
+        );
+        assert_eq!(
+            Command::new("sh").args(["-c", "exit 3"]).run(),
+            Err(ProcessError::Failed {
+                code:   Some(3),
+                stderr: String::new(),
+            })
+        );
+        assert_eq!(
+            Command::new("echo").arg("recorded").run(),
+            Err(ProcessError::Fixture(String::from(
+                "'echo recorded' was not recorded"
+            )))
+        );
+        fixture.finish()?;
+
+        std::fs::remove_file(path).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn replay_in_order() -> ProcessResult<()> {
+        let path = crate::fs::generate_test_path();
+        std::fs::write(
+            &path,
+            r#"[
+                { "program": "kubectl", "arguments": ["rollout", "status"], "code": 1 },
+                {
+                    "program": "kubectl",
+                    "arguments": ["rollout", "status"],
+                    "code": 0,
+                    "stdout": "done\n"
+                },
+                { "program": "terraform", "arguments": ["apply"], "code": 0 }
+            ]"#,
+        )
+        .unwrap();
+
+        let fixture = Fixture::replay(&path)?;
+        assert!(Fixture::record(&path).is_err());
+        let status = Command::new("kubectl").args(["rollout", "status"]);
+        assert!(status.run().is_err());
+        assert_eq!(status.run()?.stdout, "done\n");
+        assert_eq!(
+            fixture.finish(),
+            Err(ProcessError::Fixture(String::from(
+                "1 recorded commands were not run, the first being 'terraform'"
+            )))
+        );
+
+        // Without a fixture, programs run again.
+        assert_eq!(Command::new("echo").arg("live").run()?.stdout, "live\n");
+        std::fs::remove_file(path).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! This module contains protecting programs from the OOM killer, which the kernel
+//! uses to free memory when it runs out of it.
+//!
+//! The kernel kills the process with the highest score first; the score is adjusted
+//! by the `oom_score_adj` of the process, from -1000 (never kill it) to 1000 (kill
+//! it first). Programs inherit the adjustment of the program starting them.
+//!
+//! ```no_run
+//! use rush::process::{
+//!     self,
+//!     Command,
+//! };
+//!
+//! // The orchestrator must survive, the workers it starts are expendable.
+//! process::set_oom_score_adj(-900).unwrap();
+//! let reserve = process::reserve_memory_headroom(64 * 1024 * 1024).unwrap();
+//! let result = Command::new("./worker").oom_score_adj(500).run();
+//! if result.is_err() {
+//!     // Leave room for cleaning up.
+//!     reserve.release();
+//! }
+//! ```
+
+use crate::{
+    fs::FSError,
+    system::parse::{
+        read_meminfo,
+        ParseError,
+    },
+};
+
+/// Describes possible errors when protecting programs from the OOM killer.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum OomError {
+    #[error("{0} is not between -1000 and 1000")]
+    OutOfRange(i16),
+    #[error("{needed} bytes are needed but only {available} bytes are available")]
+    InsufficientMemory { needed: u64, available: u64 },
+    #[error("Accessing the OOM score failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Reading the memory statistics failed: {0}")]
+    Parse(#[from] ParseError),
+}
+
+/// A [`Result`] whose error variant is a [`OomError`].
+pub type OomResult<T> = Result<T, OomError>;
+
+/// The file holding the OOM score adjustment of this process.
+const OOM_SCORE_ADJ: &str = "/proc/self/oom_score_adj";
+/// The range of OOM score adjustments.
+pub(super) const OOM_SCORE_ADJ_RANGE: std::ops::RangeInclusive<i16> = -1000..=1000;
+
+/// The OOM score adjustment of this process.
+///
+/// # Errors
+///
+/// Returns an error if the adjustment cannot be read.
+pub fn oom_score_adj() -> OomResult<i16> {
+    let content = std::fs::read_to_string(OOM_SCORE_ADJ).map_err(FSError::from)?;
+    content.trim().parse().map_err(|_| {
+        FSError::Unknown(format!("unexpected OOM score adjustment '{content}'")).into()
+    })
+}
+
+/// Set the OOM score adjustment of this process, and of the programs it starts
+/// afterwards, to `value`. Lowering it below what it was set to before requires
+/// root privileges.
+///
+/// # Errors
+///
+/// Returns [`OomError::OutOfRange`] if `value` is not between -1000 and 1000, or an
+/// error if the adjustment cannot be changed.
+pub fn set_oom_score_adj(value: i16) -> OomResult<()> {
+    if !OOM_SCORE_ADJ_RANGE.contains(&value) {
+        return Err(OomError::OutOfRange(value));
+    }
+    log::debug!("Setting the OOM score adjustment to {value}");
+    std::fs::write(OOM_SCORE_ADJ, value.to_string()).map_err(FSError::from)?;
+    Ok(())
+}
+
+/// Memory that is set aside by [`reserve_memory_headroom`] and freed when this is
+/// [released](Self::release) or dropped.
+#[must_use = "the memory is freed when this is dropped"]
+pub struct MemoryReserve {
+    /// The memory set aside
+    ballast: Vec<u8>,
+}
+
+impl std::fmt::Debug for MemoryReserve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryReserve")
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+impl MemoryReserve {
+    /// How many bytes are set aside.
+    #[must_use]
+    pub fn size(&self) -> usize { self.ballast.len() }
+
+    /// Free the memory, e.g. when running low on memory, so that there is room for
+    /// cleaning up.
+    pub fn release(self) {
+        log::debug!("Releasing {} bytes of reserved memory", self.size());
+    }
+}
+
+/// Set aside `bytes` of memory, which can be [released](MemoryReserve::release)
+/// later when the host runs low on memory.
+///
+/// This way, there is room for cleaning up then. The memory is written to, so that
+/// it is actually allocated.
+///
+/// # Errors
+///
+/// Returns [`OomError::InsufficientMemory`] if less than `bytes` are available, or
+/// an error if the memory statistics cannot be read.
+pub fn reserve_memory_headroom(bytes: usize) -> OomResult<MemoryReserve> {
+    let available = read_meminfo()?.available;
+    let needed = bytes as u64;
+    if needed > available {
+        return Err(OomError::InsufficientMemory { needed, available });
+    }
+    log::debug!("Reserving {bytes} bytes of memory");
+    // Zeroed memory would not be allocated before it is written to.
+    Ok(MemoryReserve {
+        ballast: vec![1; bytes],
+    })
+}
+
+#[cfg(test)]
+mod oom_test {
+    use super::*;
+    use crate::process::Command;
+
+    #[test]
+    fn score_adjustment() -> OomResult<()> {
+        let ours = oom_score_adj()?;
+        set_oom_score_adj(ours)?;
+        assert_eq!(set_oom_score_adj(1001), Err(OomError::OutOfRange(1001)));
+
+        let output = Command::new("cat")
+            .arg("/proc/self/oom_score_adj")
+            .oom_score_adj(ours.max(500))
+            .run()
+            .unwrap();
+        assert_eq!(output.stdout.trim(), ours.max(500).to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn headroom() -> OomResult<()> {
+        let reserve = reserve_memory_headroom(1024 * 1024)?;
+        assert_eq!(reserve.size(), 1024 * 1024);
+        reserve.release();
+        assert!(matches!(
+            reserve_memory_headroom(usize::MAX),
+            Err(OomError::InsufficientMemory { .. })
+        ));
+        Ok(())
+    }
+}
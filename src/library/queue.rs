@@ -0,0 +1,402 @@
+//! This module contains functionality for passing jobs between programs through a
+//! spool directory, possibly shared between hosts (e.g. via NFS).
+//!
+//! A [`SpoolQueue`] consists of four subdirectories. New jobs are written to
+//! `incoming/`. A worker claims a job by renaming it to `processing/`, which only
+//! one worker can do, and renames it to `done/` or `failed/` afterwards. Jobs of
+//! workers that crashed stay in `processing/` until they become stale and are put
+//! back into `incoming/`.
+
+use crate::{
+    fs::{
+        FSError,
+        FSResult,
+    },
+    lock::Holder,
+};
+
+/// Describes possible errors when dealing with spool queues.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum QueueError {
+    #[error("The job '{0}' is no longer claimed by us")]
+    Lost(String),
+    #[error("Accessing the spool directory failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Waiting for a job was aborted: {0}")]
+    DeadlineExceeded(#[from] crate::deadline::DeadlineExceeded),
+}
+
+/// A [`Result`] whose error variant is a [`QueueError`].
+pub type QueueResult<T> = Result<T, QueueError>;
+
+/// After how many seconds without a heartbeat a claimed job is considered stale by
+/// default.
+const DEFAULT_STALE_AFTER_SECONDS: u64 = 300;
+/// The subdirectory new jobs are written to.
+const INCOMING: &str = "incoming";
+/// The subdirectory claimed jobs are moved to.
+const PROCESSING: &str = "processing";
+/// The subdirectory completed jobs are moved to.
+const DONE: &str = "done";
+/// The subdirectory failed jobs are moved to.
+const FAILED: &str = "failed";
+/// Separates the ID of a claimed job from the token of the worker that claimed it.
+const CLAIM_SEPARATOR: char = '~';
+
+/// Turn an [`std::io::Error`] into a [`QueueError`].
+fn io_error(error: std::io::Error) -> QueueError { FSError::from(error).into() }
+
+/// A job queue backed by a spool directory. See the module documentation for how
+/// it works.
+///
+/// Claimed jobs that are not completed, failed or sent a heartbeat within the
+/// staleness threshold are put back into `incoming/`. Like [`crate::lock::NetLock`],
+/// this relies on the hosts sharing the directory having synchronized clocks.
+#[derive(Debug, Clone)]
+pub struct SpoolQueue {
+    /// The spool directory
+    root:          std::path::PathBuf,
+    /// After how long without a heartbeat a claimed job is considered stale
+    stale_after:   std::time::Duration,
+    /// How long to sleep between attempts in [`SpoolQueue::wait`]
+    poll_interval: std::time::Duration,
+}
+
+impl SpoolQueue {
+    /// Describe a queue in the spool directory `root`. The subdirectories are
+    /// created when they are needed. Claimed jobs become stale after five minutes
+    /// without a heartbeat.
+    pub fn new(root: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            root:          root.as_ref().to_path_buf(),
+            stale_after:   std::time::Duration::from_secs(DEFAULT_STALE_AFTER_SECONDS),
+            poll_interval: std::time::Duration::from_secs(1),
+        }
+    }
+
+    /// Consider claimed jobs stale after `duration` without a heartbeat.
+    #[must_use]
+    pub const fn stale_after(mut self, duration: std::time::Duration) -> Self {
+        self.stale_after = duration;
+        self
+    }
+
+    /// Sleep `interval` between attempts in [`SpoolQueue::wait`].
+    #[must_use]
+    pub const fn poll_interval(mut self, interval: std::time::Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// The subdirectory `name`, which is created if it does not exist.
+    fn directory(&self, name: &str) -> QueueResult<std::path::PathBuf> {
+        let directory = self.root.join(name);
+        std::fs::create_dir_all(&directory).map_err(io_error)?;
+        Ok(directory)
+    }
+
+    /// The names of the files in the subdirectory `name`, sorted. Files being
+    /// written (starting with a dot) are left out.
+    fn names(&self, name: &str) -> QueueResult<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(self.directory(name)?).map_err(io_error)? {
+            let name = entry.map_err(io_error)?.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with('.') {
+                names.push(name.into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Add a job with `content` to the queue and return its ID. IDs start with the
+    /// time of submission, so jobs are claimed in the order they were submitted.
+    /// The job only becomes visible once it is written completely.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the job fails.
+    pub fn submit(&self, content: impl AsRef<[u8]>) -> QueueResult<String> {
+        use std::io::Write as _;
+
+        /// How many jobs this process submitted, which keeps the IDs of jobs
+        /// submitted in the same nanosecond unique.
+        static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+        let holder = Holder::current();
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let counter = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let id = crate::fs::sanitize_filename(&format!(
+            "{nanos:020}-{}-{}-{counter}",
+            holder.host, holder.pid
+        ))
+        .replace(CLAIM_SEPARATOR, "-");
+
+        let incoming = self.directory(INCOMING)?;
+        // Temporary files are hidden, so workers never claim a partial job.
+        crate::fs::write_atomic_with(&incoming.join(&id), None, |writer| -> FSResult<()> {
+            Ok(writer.write_all(content.as_ref())?)
+        })?;
+        log::debug!("Submitted job '{id}' to {}", self.root.to_string_lossy());
+        Ok(id)
+    }
+
+    /// How many jobs wait to be claimed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the spool directory fails.
+    pub fn pending(&self) -> QueueResult<usize> { Ok(self.names(INCOMING)?.len()) }
+
+    /// Put claimed jobs that became stale back into `incoming/` and return how many
+    /// there were. This is done by [`SpoolQueue::claim`] as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or renaming in the spool directory fails.
+    pub fn recover(&self) -> QueueResult<usize> {
+        let processing = self.directory(PROCESSING)?;
+        let incoming = self.directory(INCOMING)?;
+        let mut recovered = 0;
+        for name in self.names(PROCESSING)? {
+            let path = processing.join(&name);
+            let stale = match path.metadata().and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified.elapsed().unwrap_or_default() > self.stale_after,
+                // Completed in the meantime
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => false,
+                Err(error) => return Err(io_error(error)),
+            };
+            if !stale {
+                continue;
+            }
+
+            let id = name.split(CLAIM_SEPARATOR).next().unwrap_or(&name);
+            log::warn!("Recovering stale job '{id}'");
+            match std::fs::rename(&path, incoming.join(id)) {
+                Ok(()) => recovered += 1,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {},
+                Err(error) => return Err(io_error(error)),
+            }
+        }
+        Ok(recovered)
+    }
+
+    /// Claim the oldest job, after recovering stale ones. Returns [`None`] if there
+    /// is no job. If several workers try to claim the same job, only one succeeds;
+    /// the others move on to the next job.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or renaming in the spool directory fails.
+    pub fn claim(&self) -> QueueResult<Option<Job>> {
+        self.recover()?;
+        let incoming = self.directory(INCOMING)?;
+        let processing = self.directory(PROCESSING)?;
+        let token = Holder::current().token;
+
+        for id in self.names(INCOMING)? {
+            let path = processing.join(format!("{id}{CLAIM_SEPARATOR}{token}"));
+            match std::fs::rename(incoming.join(&id), &path) {
+                Ok(()) => {},
+                // Another worker was faster.
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(io_error(error)),
+            }
+            let job = Job {
+                root: self.root.clone(),
+                id,
+                path,
+            };
+            // Renaming keeps the time of submission, which would make the job stale.
+            job.heartbeat()?;
+            log::debug!("Claimed job '{}'", job.id);
+            return Ok(Some(job));
+        }
+        Ok(None)
+    }
+
+    /// Claim the oldest job, waiting at most `timeout` for one to be submitted.
+    /// Returns [`None`] if no job was submitted in time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or renaming in the spool directory fails, or if
+    /// the [`Deadline`](crate::deadline::Deadline) in effect passes first.
+    pub fn wait(&self, timeout: std::time::Duration) -> QueueResult<Option<Job>> {
+        let start = std::time::Instant::now();
+        loop {
+            if let Some(job) = self.claim()? {
+                return Ok(Some(job));
+            }
+            if start.elapsed() >= timeout {
+                return Ok(None);
+            }
+            crate::deadline::sleep(self.poll_interval)?;
+        }
+    }
+}
+
+/// A job claimed from a [`SpoolQueue`]. It has to be completed, failed or released;
+/// a job that is dropped stays claimed until it becomes stale.
+#[derive(Debug)]
+pub struct Job {
+    /// The spool directory
+    root: std::path::PathBuf,
+    /// The ID of the job
+    id:   String,
+    /// Where the job is while it is claimed
+    path: std::path::PathBuf,
+}
+
+impl Job {
+    /// The ID the job was submitted with.
+    #[must_use]
+    pub fn id(&self) -> &str { &self.id }
+
+    /// Where the content of the job is while it is claimed.
+    #[must_use]
+    pub const fn path(&self) -> &std::path::PathBuf { &self.path }
+
+    /// Read the content of the job.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::Lost`] if the job was recovered by someone else, or an
+    /// error if reading it fails.
+    pub fn read(&self) -> QueueResult<Vec<u8>> {
+        std::fs::read(&self.path).map_err(|error| self.error(error))
+    }
+
+    /// Turn `error` into [`QueueError::Lost`] if the job is gone.
+    fn error(&self, error: std::io::Error) -> QueueError {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            QueueError::Lost(self.id.clone())
+        } else {
+            io_error(error)
+        }
+    }
+
+    /// Signal that the job is still being worked on, so it does not become stale.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::Lost`] if the job was recovered by someone else because
+    /// it had become stale, or an error if updating it fails.
+    pub fn heartbeat(&self) -> QueueResult<()> {
+        std::fs::File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_modified(std::time::SystemTime::now()))
+            .map_err(|error| self.error(error))
+    }
+
+    /// Move the job to the subdirectory `name` under its ID.
+    fn finish(self, name: &str) -> QueueResult<()> {
+        let directory = self.root.join(name);
+        std::fs::create_dir_all(&directory).map_err(io_error)?;
+        std::fs::rename(&self.path, directory.join(&self.id)).map_err(|error| self.error(error))
+    }
+
+    /// Mark the job as completed by moving it to `done/`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::Lost`] if the job was recovered by someone else, or an
+    /// error if moving it fails.
+    pub fn complete(self) -> QueueResult<()> {
+        log::debug!("Completed job '{}'", self.id);
+        self.finish(DONE)
+    }
+
+    /// Mark the job as failed by moving it to `failed/`. `reason` is written next to
+    /// it, into `<ID>.reason`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::Lost`] if the job was recovered by someone else, or an
+    /// error if moving it or writing the reason fails.
+    pub fn fail(self, reason: impl AsRef<str>) -> QueueResult<()> {
+        log::warn!("Job '{}' failed: {}", self.id, reason.as_ref());
+        let directory = self.root.join(FAILED);
+        std::fs::create_dir_all(&directory)
+            .and_then(|()| {
+                std::fs::write(
+                    directory.join(format!("{}.reason", self.id)),
+                    reason.as_ref(),
+                )
+            })
+            .map_err(io_error)?;
+        self.finish(FAILED)
+    }
+
+    /// Put the job back into `incoming/`, so that it is claimed again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::Lost`] if the job was recovered by someone else, or an
+    /// error if moving it fails.
+    pub fn release(self) -> QueueResult<()> {
+        log::debug!("Releasing job '{}'", self.id);
+        self.finish(INCOMING)
+    }
+}
+
+#[cfg(test)]
+mod queue_test {
+    use super::*;
+
+    #[test]
+    fn submit_claim_finish() -> QueueResult<()> {
+        let root = crate::fs::generate_test_path();
+        let queue = SpoolQueue::new(&root).poll_interval(std::time::Duration::from_millis(10));
+        assert!(queue.claim()?.is_none());
+        assert!(queue.wait(std::time::Duration::from_millis(30))?.is_none());
+
+        let first = queue.submit("first")?;
+        let second = queue.submit("second")?;
+        let third = queue.submit("third")?;
+        assert_eq!(queue.pending()?, 3);
+
+        let job = queue
+            .claim()?
+            .ok_or_else(|| QueueError::Lost(first.clone()))?;
+        assert_eq!(
+            (job.id(), job.read()?.as_slice()),
+            (first.as_str(), &b"first"[..])
+        );
+        job.complete()?;
+        assert!(root.join(DONE).join(&first).exists());
+
+        let job = queue
+            .claim()?
+            .ok_or_else(|| QueueError::Lost(second.clone()))?;
+        job.fail("broken")?;
+        assert_eq!(
+            std::fs::read_to_string(root.join(FAILED).join(format!("{second}.reason")))
+                .map_err(io_error)?,
+            "broken"
+        );
+
+        // A job of a crashed worker is recovered once it is stale.
+        let job = queue
+            .claim()?
+            .ok_or_else(|| QueueError::Lost(third.clone()))?;
+        assert_eq!(queue.recover()?, 0);
+        let queue = queue.stale_after(std::time::Duration::ZERO);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let recovered = queue
+            .claim()?
+            .ok_or_else(|| QueueError::Lost(third.clone()))?;
+        assert_eq!(recovered.id(), third);
+        assert_eq!(job.complete(), Err(QueueError::Lost(third)));
+        recovered.release()?;
+        assert_eq!(queue.pending()?, 1);
+
+        std::fs::remove_dir_all(root).map_err(io_error)?;
+        Ok(())
+    }
+}
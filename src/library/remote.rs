@@ -0,0 +1,278 @@
+//! This module contains functionality for running a command on many hosts of an
+//! [`Inventory`] at once, over SSH.
+//!
+//! Connections are made by the `ssh` client, which needs to be installed, in batch
+//! mode: authentication has to work without prompts, e.g. with an SSH agent. The
+//! address, user, port and key of every host are taken from the `ansible_host`,
+//! `ansible_user`, `ansible_port` and `ansible_ssh_private_key_file` variables.
+//! Hosts that require answering prompts can be driven with [`Interactive`]
+//! instead.
+//!
+//! ```no_run
+//! # use rush::{inventory::Inventory, remote};
+//! let inventory = Inventory::load("inventory.ini").unwrap();
+//! let report = remote::run_on(&inventory, "group:web", "systemctl is-active nginx", 10).unwrap();
+//! print!("{report}");
+//! assert!(report.is_success());
+//! ```
+
+#[cfg(unix)]
+mod interactive;
+
+#[cfg(unix)] pub use interactive::{
+    Interactive,
+    Match,
+};
+
+use crate::{
+    inventory::{
+        Host,
+        Inventory,
+        InventoryError,
+    },
+    process::{
+        Command,
+        Output,
+        ProcessError,
+        ProcessResult,
+    },
+};
+
+/// How long `ssh` waits for a host to accept the connection.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Describes possible errors when running commands on remote hosts. Failures on
+/// single hosts are no errors; they are part of the [`Report`].
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum RemoteError {
+    #[error("Selecting the hosts failed: {0}")]
+    Inventory(#[from] InventoryError),
+    #[error("Starting the program failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("Using the terminal failed: {0}")]
+    Terminal(String),
+    #[error("The pattern is not valid: {0}")]
+    InvalidPattern(String),
+    #[error("The output did not match '{0}' in time")]
+    Timeout(String),
+    #[error("The program exited before its output matched '{0}'")]
+    Exited(String),
+}
+
+/// A [`Result`] whose error variant is a [`RemoteError`].
+pub type RemoteResult<T> = Result<T, RemoteError>;
+
+/// What happened when the command ran on one host.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HostResult {
+    /// The output of the command, or why `ssh` could not be started. If the host
+    /// cannot be reached, `ssh` exits with code 255.
+    pub output:   ProcessResult<Output>,
+    /// How long the command took, including connecting
+    pub duration: std::time::Duration,
+}
+
+impl HostResult {
+    /// Whether the command exited with code 0.
+    #[must_use]
+    pub fn success(&self) -> bool { self.output.as_ref().is_ok_and(Output::success) }
+
+    /// The exit code of the command, if it ran to completion.
+    #[must_use]
+    pub fn code(&self) -> Option<i32> { self.output.as_ref().ok().and_then(|output| output.code) }
+}
+
+/// The results of running a command on many hosts, by host name. The
+/// [`std::fmt::Display`] implementation renders a summary table.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The result of every host, by name
+    pub results: std::collections::BTreeMap<String, HostResult>,
+}
+
+impl Report {
+    /// Whether the command succeeded on every host.
+    #[must_use]
+    pub fn is_success(&self) -> bool { self.results.values().all(HostResult::success) }
+
+    /// The names of the hosts the command failed on.
+    pub fn failed(&self) -> impl Iterator<Item = &str> {
+        self.results
+            .iter()
+            .filter(|(_, result)| !result.success())
+            .map(|(host, _)| host.as_str())
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .results
+            .keys()
+            .map(|host| host.chars().count())
+            .chain(std::iter::once("HOST".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:width$}  STATUS  CODE  DURATION", "HOST")?;
+        for (host, result) in &self.results {
+            let code = result
+                .code()
+                .map_or_else(|| String::from("-"), |code| code.to_string());
+            writeln!(
+                f,
+                "{host:width$}  {:6}  {code:>4}  {:>7.1}s",
+                if result.success() { "ok" } else { "FAILED" },
+                result.duration.as_secs_f64()
+            )?;
+        }
+        let failed = self.failed().count();
+        writeln!(
+            f,
+            "\n{} succeeded, {failed} failed.",
+            self.results.len() - failed
+        )
+    }
+}
+
+/// The `ssh` invocation that connects to `host`. In batch mode, `ssh` fails instead
+/// of prompting for passwords or confirmations.
+fn ssh(host: &Host, batch: bool) -> Command {
+    let mut ssh = Command::new("ssh");
+    if batch {
+        ssh = ssh.args(["-o", "BatchMode=yes"]);
+    }
+    ssh = ssh.args([
+        "-o",
+        &format!("ConnectTimeout={}", CONNECT_TIMEOUT.as_secs()),
+    ]);
+    if let Some(port) = host.port() {
+        ssh = ssh.arg("-p").arg(port.to_string());
+    }
+    if let Some(user) = host.user() {
+        ssh = ssh.arg("-l").arg(user);
+    }
+    if let Some(key) = host.variable("ansible_ssh_private_key_file") {
+        ssh = ssh.arg("-i").arg(key);
+    }
+    ssh.arg("--").arg(host.address())
+}
+
+/// The `ssh` invocation that runs `command` on `host`.
+fn ssh_command(host: &Host, command: &str) -> Command { ssh(host, true).arg(command) }
+
+/// Call `run` for every host in `hosts`, at most `concurrency` at the same time.
+fn fan_out(
+    hosts: &[&Host],
+    concurrency: usize,
+    run: impl Fn(&Host) -> ProcessResult<Output> + Sync,
+) -> Report {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results = std::sync::Mutex::new(std::collections::BTreeMap::new());
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.max(1).min(hosts.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(host) = hosts.get(index) else {
+                    break;
+                };
+                let start = std::time::Instant::now();
+                let output = run(host);
+                let result = HostResult {
+                    output,
+                    duration: start.elapsed(),
+                };
+                if !result.success() {
+                    log::warn!("The command failed on '{}'", host.name());
+                }
+                if let Ok(mut results) = results.lock() {
+                    results.insert(host.name().to_string(), result);
+                }
+            });
+        }
+    });
+    Report {
+        results: results.into_inner().unwrap_or_default(),
+    }
+}
+
+/// Run the shell `command` on every host of `inventory` matched by `selection`.
+///
+/// See [`Inventory::select`] for the syntax of selections. The command runs on at
+/// most `concurrency` (at least one) hosts at the same time. Hosts the command
+/// fails on do not stop the others.
+///
+/// # Errors
+///
+/// Returns an error if the selection is not valid.
+pub fn run_on(
+    inventory: &Inventory,
+    selection: &str,
+    command: &str,
+    concurrency: usize,
+) -> RemoteResult<Report> {
+    let hosts = inventory.select(selection)?;
+    log::debug!(
+        "Running '{}' on {} hosts ({} at a time)",
+        command,
+        hosts.len(),
+        concurrency
+    );
+    Ok(fan_out(&hosts, concurrency, |host| {
+        ssh_command(host, command).output()
+    }))
+}
+
+#[cfg(test)]
+mod remote_test {
+    use super::*;
+
+    const INVENTORY: &str = "
+a.example.com ansible_user=admin ansible_port=2222
+b.example.com ansible_host=10.0.0.2 ansible_ssh_private_key_file=/keys/b
+c.example.com
+";
+
+    #[test]
+    fn commands() -> RemoteResult<()> {
+        let inventory = Inventory::from_ini(INVENTORY)?;
+        let command = |name: &str| {
+            ssh_command(inventory.host(name).expect("host should exist"), "uptime")
+                .arguments()
+                .join(" ")
+        };
+        assert_eq!(
+            command("a.example.com"),
+            "-o BatchMode=yes -o ConnectTimeout=10 -p 2222 -l admin -- a.example.com uptime"
+        );
+        assert_eq!(
+            command("b.example.com"),
+            "-o BatchMode=yes -o ConnectTimeout=10 -i /keys/b -- 10.0.0.2 uptime"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fanning_out() -> RemoteResult<()> {
+        let inventory = Inventory::from_ini(INVENTORY)?;
+        let hosts = inventory.select("all")?;
+        let report = fan_out(&hosts, 2, |host| match host.name() {
+            "a.example.com" => Command::new("true").output(),
+            "b.example.com" => Command::new("false").output(),
+            _ => Err(ProcessError::NotFound),
+        });
+        assert!(!report.is_success());
+        assert!(report.results["a.example.com"].success());
+        assert_eq!(report.results["b.example.com"].code(), Some(1));
+        assert_eq!(
+            report.failed().collect::<Vec<_>>(),
+            ["b.example.com", "c.example.com"]
+        );
+
+        let table = report.to_string();
+        assert!(table.starts_with("HOST           STATUS  CODE  DURATION\n"));
+        assert!(table.contains("\nb.example.com  FAILED     1 "));
+        assert!(table.contains("\nc.example.com  FAILED     - "));
+        assert!(table.ends_with("\n1 succeeded, 2 failed.\n"));
+        Ok(())
+    }
+}
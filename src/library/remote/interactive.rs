@@ -0,0 +1,308 @@
+//! This module contains functionality for automating programs that insist on a
+//! terminal, in the style of `expect`.
+
+use super::{
+    Host,
+    RemoteError,
+    RemoteResult,
+};
+use crate::process::Command;
+
+/// How long [`Interactive::expect`] waits for output by default.
+const DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Output that matched one of the patterns passed to [`Interactive::expect_any`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Match {
+    /// The index of the pattern that matched
+    pub index:  usize,
+    /// The output between the previous match and this one
+    pub before: String,
+    /// The output that matched the pattern
+    pub text:   String,
+}
+
+/// A program running on a pseudo-terminal, driven by waiting for its output to
+/// match patterns and sending it input.
+///
+/// This is meant for logging in to appliances that cannot be automated in any
+/// other way. The program is started with `setsid` (from util-linux), so that the
+/// terminal becomes its controlling terminal and prompts that read from `/dev/tty`
+/// work. Note that the terminal echoes input, so sent text shows up in the output.
+///
+/// ```no_run
+/// # use rush::{inventory::Inventory, remote::Interactive};
+/// let inventory = Inventory::from_ini("switch.example.com ansible_user=admin").unwrap();
+/// let mut session = Interactive::ssh(inventory.host("switch.example.com").unwrap()).unwrap();
+/// session
+///     .respond(
+///         &[(r"\(yes/no.*\)\?", "yes"), ("(?i)password: ?$", "hunter2")],
+///         r"[>#] ?$",
+///     )
+///     .unwrap();
+/// session.send_line("show running-config").unwrap();
+/// let config = session.expect(r"[>#] ?$").unwrap().before;
+/// session.send_line("exit").unwrap();
+/// session.wait().unwrap();
+/// ```
+pub struct Interactive {
+    /// The program
+    child:   crate::process::Running,
+    /// The controlling side of the terminal, which input is written to
+    input:   std::fs::File,
+    /// The output of the program, in the chunks it was read in
+    output:  std::sync::mpsc::Receiver<Vec<u8>>,
+    /// Output that has not been matched yet
+    pending: Vec<u8>,
+    /// How long to wait for output to match
+    timeout: std::time::Duration,
+}
+
+impl std::fmt::Debug for Interactive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Interactive")
+            .field("pid", &self.child.id())
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Interactive {
+    fn drop(&mut self) {
+        // Killing a program that has already been waited for does nothing.
+        self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Interactive {
+    /// Start `command` on a new pseudo-terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminal cannot be created or the program cannot be
+    /// started.
+    pub fn spawn(command: &Command) -> RemoteResult<Self> {
+        use std::io::Read as _;
+
+        let to_error = |error: &dyn std::fmt::Display| RemoteError::Terminal(error.to_string());
+        let terminal = nix::pty::openpty(None, None).map_err(|error| to_error(&error))?;
+        let stdio = || {
+            terminal
+                .slave
+                .try_clone()
+                .map(std::process::Stdio::from)
+                .map_err(|error| to_error(&error))
+        };
+
+        log::trace!("Spawning {} on a terminal", command);
+        // Starting `setsid` checks the policy for it, but not for the program it runs.
+        crate::policy::check(|policy| policy.check_command(command.program()))
+            .map_err(crate::process::ProcessError::PolicyViolation)?;
+        let [input, output, error] = [stdio()?, stdio()?, stdio()?];
+        let child = Command::new("setsid")
+            .arg("--ctty")
+            .arg(command.program())
+            .args(command.arguments())
+            .start(|setsid| {
+                setsid.stdin(input).stdout(output).stderr(error);
+            })?;
+        // Only the program may hold the other side open, so that reading fails once it
+        // exits.
+        drop(terminal.slave);
+
+        let input = std::fs::File::from(terminal.master);
+        let mut reader = input.try_clone().map_err(|error| to_error(&error))?;
+        let (sender, output) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(length @ 1..) = reader.read(&mut buffer) {
+                if sender.send(buffer[..length].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            input,
+            output,
+            pending: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Log in to `host` with `ssh` on a new pseudo-terminal. Unlike
+    /// [`super::run_on`], `ssh` may prompt for passwords and confirmations, which
+    /// have to be answered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminal cannot be created or `ssh` cannot be
+    /// started.
+    pub fn ssh(host: &Host) -> RemoteResult<Self> { Self::spawn(&super::ssh(host, false)) }
+
+    /// Wait at most `timeout` for output to match (30 seconds by default).
+    #[must_use]
+    pub const fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait until the output matches the regular expression `pattern`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pattern is not valid, or if the output does not match
+    /// before the timeout or before the program exits.
+    pub fn expect(&mut self, pattern: &str) -> RemoteResult<Match> { self.expect_any(&[pattern]) }
+
+    /// Wait until the output matches one of the regular expressions in `patterns`.
+    /// If several patterns match, the one matching earliest in the output wins.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is not valid, or if the output does not match
+    /// before the timeout or before the program exits.
+    pub fn expect_any(&mut self, patterns: &[&str]) -> RemoteResult<Match> {
+        let expressions = patterns
+            .iter()
+            .map(|pattern| regex::bytes::Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| RemoteError::InvalidPattern(error.to_string()))?;
+        let description = patterns.join("' or '");
+        let deadline = std::time::Instant::now() + self.timeout;
+
+        loop {
+            let earliest = expressions
+                .iter()
+                .enumerate()
+                .filter_map(|(index, expression)| {
+                    expression
+                        .find(&self.pending)
+                        .map(|found| (found.start(), index, found.end()))
+                })
+                .min();
+            if let Some((start, index, end)) = earliest {
+                let matched = Match {
+                    index,
+                    before: String::from_utf8_lossy(&self.pending[..start]).into_owned(),
+                    text: String::from_utf8_lossy(&self.pending[start..end]).into_owned(),
+                };
+                self.pending.drain(..end);
+                return Ok(matched);
+            }
+
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            match self.output.recv_timeout(remaining) {
+                Ok(chunk) => self.pending.extend(chunk),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(RemoteError::Timeout(description))
+                },
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(RemoteError::Exited(description))
+                },
+            }
+        }
+    }
+
+    /// Answer prompts until the output matches `until`. Whenever the output matches
+    /// the pattern of one of the `answers` first, its answer is sent as a line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pattern is not valid, or if the output does not match
+    /// before the timeout (which applies to every prompt) or before the program
+    /// exits.
+    pub fn respond(&mut self, answers: &[(&str, &str)], until: &str) -> RemoteResult<Match> {
+        let mut patterns = vec![until];
+        patterns.extend(answers.iter().map(|(pattern, _)| *pattern));
+        loop {
+            let matched = self.expect_any(&patterns)?;
+            match matched.index {
+                0 => return Ok(matched),
+                index => self.send_line(answers[index - 1].1)?,
+            }
+        }
+    }
+
+    /// Send `text` to the program as if it was typed. Sent text is not logged, so
+    /// it may contain passwords.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to the terminal fails.
+    pub fn send(&mut self, text: &str) -> RemoteResult<()> {
+        use std::io::Write as _;
+
+        self.input
+            .write_all(text.as_bytes())
+            .map_err(|error| RemoteError::Terminal(error.to_string()))
+    }
+
+    /// Send `line` followed by a carriage return, like pressing the enter key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to the terminal fails.
+    pub fn send_line(&mut self, line: &str) -> RemoteResult<()> { self.send(&format!("{line}\r")) }
+
+    /// Wait for the program to exit and return its exit code, or [`None`] if it was
+    /// terminated by a signal. Output that was not matched is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting for the program fails.
+    pub fn wait(mut self) -> RemoteResult<Option<i32>> { Ok(self.child.wait()?.code()) }
+}
+
+#[cfg(test)]
+mod interactive_test {
+    use super::*;
+
+    /// A shell script that prompts like a login would.
+    const SCRIPT: &str = concat!(
+        "printf 'Continue (yes/no)? '; read -r answer; ",
+        "stty -echo; printf 'Password: '; read -r password </dev/tty; stty echo; echo; ",
+        "echo \"$answer:$password\"; printf '$ '; read -r command; exit 3",
+    );
+
+    #[test]
+    fn prompts() -> RemoteResult<()> {
+        let mut session = Interactive::spawn(&Command::new("sh").args(["-c", SCRIPT]))?
+            .timeout(std::time::Duration::from_secs(10));
+        let prompt = session.respond(
+            &[(r"\(yes/no\)\? $", "yes"), ("Password: $", "hunter2")],
+            r"\$ $",
+        )?;
+        // The password is not echoed, the answer is.
+        assert!(prompt.before.contains("yes:hunter2"));
+        assert_eq!(prompt.before.matches("hunter2").count(), 1);
+        session.send_line("exit")?;
+        assert_eq!(session.wait()?, Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> RemoteResult<()> {
+        let mut session = Interactive::spawn(&Command::new("sh").args(["-c", "echo done"]))?
+            .timeout(std::time::Duration::from_millis(100));
+        assert!(matches!(
+            session.expect("("),
+            Err(RemoteError::InvalidPattern(_))
+        ));
+        assert_eq!(session.expect("done")?.text, "done");
+        assert_eq!(
+            session.expect("never"),
+            Err(RemoteError::Exited(String::from("never")))
+        );
+
+        let mut session = Interactive::spawn(&Command::new("sleep").arg("10"))?
+            .timeout(std::time::Duration::from_millis(100));
+        assert_eq!(
+            session.expect_any(&["a", "b"]),
+            Err(RemoteError::Timeout(String::from("a' or 'b")))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,562 @@
+//! This module contains an interactive interpreter for exploring what a script
+//! would do before it is written.
+//!
+//! The [`Repl`] reads one command per line, e.g. `ls /etc`, `set PROFILE prod` or
+//! `run systemctl is-active nginx`, and prints what happened. Words are split like
+//! in a shell: quotes group words, a backslash escapes the next character and
+//! `$NAME` expands to a variable of the session. In dry-run mode (`dry-run on`),
+//! commands that change something only describe what they would do. Programs add
+//! their own commands with [`Repl::command`].
+//!
+//! ```no_run
+//! # use rush::repl::Repl;
+//! Repl::new()
+//!     .dry_run(true)
+//!     .command("hello", "Greet someone", |arguments, output| {
+//!         writeln!(output, "Hello, {}!", arguments.join(" ")).map_err(|error| error.to_string())
+//!     })
+//!     .run()
+//!     .unwrap();
+//! ```
+
+use crate::fs::FSError;
+
+/// Describes possible errors when running the interactive interpreter. Errors of
+/// single commands are printed and do not end the session.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ReplError {
+    #[error("Reading input or writing output failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`ReplError`].
+pub type ReplResult<T> = Result<T, ReplError>;
+
+/// A command added with [`Repl::command`]. It receives the arguments and where to
+/// write its output, and returns a message on failure.
+type Handler = dyn FnMut(&[String], &mut dyn std::io::Write) -> Result<(), String>;
+
+/// The built-in commands, their usage and what they do.
+const BUILTINS: [(&str, &str, &str); 18] = [
+    ("help", "", "Show this help"),
+    ("exit", "", "End the session (also 'quit' or end of input)"),
+    ("pwd", "", "Print the working directory of the session"),
+    (
+        "cd",
+        "DIRECTORY",
+        "Change the working directory of the session",
+    ),
+    ("ls", "[DIRECTORY]", "List a directory"),
+    ("cat", "FILE", "Print a file"),
+    (
+        "exists",
+        "PATH",
+        "Tell whether a path exists and what it is",
+    ),
+    ("write", "FILE TEXT...", "Replace the content of a file"),
+    ("append", "FILE TEXT...", "Append a line to a file"),
+    ("mkdir", "DIRECTORY", "Create a directory and its parents"),
+    (
+        "rm",
+        "PATH",
+        "Delete a file or a directory with its content",
+    ),
+    ("mv", "FROM TO", "Move a file or directory"),
+    ("cp", "FROM TO", "Copy a file"),
+    ("env", "[NAME]", "Print one or all variables of the session"),
+    ("set", "NAME VALUE...", "Set a variable of the session"),
+    ("unset", "NAME", "Remove a variable of the session"),
+    (
+        "run",
+        "PROGRAM [ARGUMENT...]",
+        "Run a program with the session's variables",
+    ),
+    (
+        "dry-run",
+        "[on|off]",
+        "Show or change whether changes are only described",
+    ),
+];
+
+/// Whether the session goes on after a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Flow {
+    /// Read the next command
+    Continue,
+    /// End the session
+    Exit,
+}
+
+/// An interactive, line-based interpreter for the filesystem, environment and
+/// process primitives.
+///
+/// The session has its own working directory and variables (initially those of
+/// the process); neither changes the process itself.
+pub struct Repl {
+    /// What is printed before each command
+    prompt:            String,
+    /// Whether changes are only described
+    dry_run:           bool,
+    /// The directory relative paths are resolved against
+    working_directory: std::path::PathBuf,
+    /// The variables of the session
+    variables:         std::collections::BTreeMap<String, String>,
+    /// The commands added with [`Repl::command`]
+    commands:          std::collections::BTreeMap<String, (String, Box<Handler>)>,
+}
+
+impl std::fmt::Debug for Repl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Repl")
+            .field("prompt", &self.prompt)
+            .field("dry_run", &self.dry_run)
+            .field("working_directory", &self.working_directory)
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self { Self::new() }
+}
+
+impl Repl {
+    /// A session in the current working directory with the variables of the
+    /// process.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            prompt:            String::from("rush> "),
+            dry_run:           false,
+            working_directory: std::env::current_dir().unwrap_or_default(),
+            variables:         std::env::vars().collect(),
+            commands:          std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// Set the prompt (default `rush> `). An empty prompt prints nothing, which
+    /// suits reading commands from a file.
+    #[must_use]
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Start in dry-run mode, in which commands that change something only
+    /// describe what they would do.
+    #[must_use]
+    pub const fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Start in `directory` instead of the current working directory.
+    #[must_use]
+    pub fn working_directory(mut self, directory: impl AsRef<std::path::Path>) -> Self {
+        self.working_directory = directory.as_ref().to_path_buf();
+        self
+    }
+
+    /// Add the command `name`, e.g. to try a function of the program. It receives
+    /// the arguments after the name and returns a message on failure. A command
+    /// with the name of a built-in command replaces it.
+    #[must_use]
+    pub fn command(
+        mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        handler: impl FnMut(&[String], &mut dyn std::io::Write) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.commands
+            .insert(name.into(), (help.into(), Box::new(handler)));
+        self
+    }
+
+    /// Read commands from standard input until `exit` or the end of input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from standard input or writing to standard
+    /// output fails.
+    pub fn run(&mut self) -> ReplResult<()> {
+        self.run_with(std::io::stdin().lock(), std::io::stdout().lock())
+    }
+
+    /// Read commands from `input` and write to `output` until `exit` or the end of
+    /// input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from `input` or writing to `output` fails.
+    pub fn run_with(
+        &mut self,
+        mut input: impl std::io::BufRead,
+        mut output: impl std::io::Write,
+    ) -> ReplResult<()> {
+        loop {
+            if !self.prompt.is_empty() {
+                write!(output, "{}", self.prompt).map_err(FSError::from)?;
+                output.flush().map_err(FSError::from)?;
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line).map_err(FSError::from)? == 0 {
+                break;
+            }
+            if self.execute(&line, &mut output)? == Flow::Exit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the command on `line` and print its output or error to `output`.
+    fn execute(&mut self, line: &str, output: &mut dyn std::io::Write) -> ReplResult<Flow> {
+        let words = match rush_core::shell::split(line, |name| self.variables.get(name).cloned()) {
+            Ok(words) => words,
+            Err(error) => {
+                writeln!(output, "error: {error}").map_err(FSError::from)?;
+                return Ok(Flow::Continue);
+            },
+        };
+        let Some((name, arguments)) = words.split_first() else {
+            return Ok(Flow::Continue);
+        };
+
+        let result = if let Some((_, handler)) = self.commands.get_mut(name) {
+            handler(arguments, output)
+        } else {
+            match name.as_str() {
+                "exit" | "quit" => return Ok(Flow::Exit),
+                "help" => self.help(output),
+                _ => self.builtin(name, arguments, output),
+            }
+        };
+        if let Err(error) = result {
+            writeln!(output, "error: {error}").map_err(FSError::from)?;
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Print the commands.
+    fn help(&self, output: &mut dyn std::io::Write) -> Result<(), String> {
+        let mut lines = BUILTINS
+            .iter()
+            .filter(|(name, ..)| !self.commands.contains_key(*name))
+            .map(|(name, usage, help)| (format!("{name} {usage}"), (*help).to_string()))
+            .collect::<Vec<_>>();
+        lines.extend(
+            self.commands
+                .iter()
+                .map(|(name, (help, _))| (name.clone(), help.clone())),
+        );
+        let width = lines
+            .iter()
+            .map(|(usage, _)| usage.len())
+            .max()
+            .unwrap_or(0);
+        for (usage, help) in lines {
+            writeln!(output, "{:<width$}  {help}", usage.trim_end())
+                .map_err(|error| error.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Resolve `path` against the working directory of the session.
+    fn resolve(&self, path: &str) -> std::path::PathBuf { self.working_directory.join(path) }
+
+    /// Describe `what` would be done in dry-run mode. Returns whether the session
+    /// is in dry-run mode.
+    fn describe(&self, output: &mut dyn std::io::Write, what: &str) -> Result<bool, String> {
+        if self.dry_run {
+            writeln!(output, "would {what}").map_err(io_error)?;
+        }
+        Ok(self.dry_run)
+    }
+
+    /// Run the built-in command `name` after checking the number of arguments.
+    fn builtin(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+        output: &mut dyn std::io::Write,
+    ) -> Result<(), String> {
+        let Some((_, usage, _)) = BUILTINS.iter().find(|(builtin, ..)| *builtin == name) else {
+            return Err(format!("unknown command '{name}' (see 'help')"));
+        };
+        let minimum = usage
+            .split_whitespace()
+            .filter(|word| !word.starts_with('['))
+            .count();
+        let maximum = if usage.contains("...") {
+            usize::MAX
+        } else {
+            usage.split_whitespace().count()
+        };
+        if arguments.len() < minimum || arguments.len() > maximum {
+            return Err(format!("usage: {name} {usage}"));
+        }
+
+        match name {
+            "pwd" | "cd" | "env" | "set" | "unset" | "dry-run" => {
+                self.session(name, arguments, output)
+            },
+            "run" => self.run_program(arguments, output),
+            _ => self.filesystem(name, arguments, output),
+        }
+    }
+
+    /// Run a built-in command that inspects or changes the session.
+    fn session(
+        &mut self,
+        name: &str,
+        arguments: &[String],
+        output: &mut dyn std::io::Write,
+    ) -> Result<(), String> {
+        let argument = arguments.first().map_or("", String::as_str);
+        match name {
+            "pwd" => writeln!(output, "{}", self.working_directory.display()).map_err(io_error)?,
+            "cd" => {
+                let path = self.resolve(argument);
+                if !path.is_dir() {
+                    return Err(format!("'{}' is not a directory", path.display()));
+                }
+                self.working_directory = path.canonicalize().map_err(io_error)?;
+            },
+            "env" => {
+                if arguments.is_empty() {
+                    for (variable, value) in &self.variables {
+                        writeln!(output, "{variable}={value}").map_err(io_error)?;
+                    }
+                } else {
+                    let value = self
+                        .variables
+                        .get(argument)
+                        .ok_or_else(|| format!("'{argument}' is not set"))?;
+                    writeln!(output, "{value}").map_err(io_error)?;
+                }
+            },
+            "set" => {
+                self.variables
+                    .insert(argument.to_string(), arguments[1..].join(" "));
+            },
+            "unset" => {
+                self.variables.remove(argument);
+            },
+            _ => {
+                match argument {
+                    "" => {},
+                    "on" => self.dry_run = true,
+                    "off" => self.dry_run = false,
+                    _ => return Err(String::from("usage: dry-run [on|off]")),
+                }
+                let state = if self.dry_run { "on" } else { "off" };
+                writeln!(output, "dry-run is {state}").map_err(io_error)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Run a program with the variables and in the working directory of the
+    /// session.
+    fn run_program(
+        &self,
+        arguments: &[String],
+        output: &mut dyn std::io::Write,
+    ) -> Result<(), String> {
+        if self.describe(output, &format!("run '{}'", arguments.join(" ")))? {
+            return Ok(());
+        }
+        let result = crate::process::Command::new(&arguments[0])
+            .args(&arguments[1..])
+            .start(|command| {
+                command
+                    .env_clear()
+                    .envs(&self.variables)
+                    .current_dir(&self.working_directory)
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped());
+            })
+            .and_then(crate::process::Running::wait_with_output)
+            .map_err(|error| error.to_string())?;
+        output.write_all(&result.stdout).map_err(io_error)?;
+        output.write_all(&result.stderr).map_err(io_error)?;
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(format!("'{}' failed ({})", arguments[0], result.status))
+        }
+    }
+
+    /// Run a built-in command that inspects or changes the filesystem.
+    fn filesystem(
+        &self,
+        name: &str,
+        arguments: &[String],
+        output: &mut dyn std::io::Write,
+    ) -> Result<(), String> {
+        let path = self.resolve(arguments.first().map_or("", String::as_str));
+        match name {
+            "ls" => {
+                let mut entries = std::fs::read_dir(path)
+                    .map_err(io_error)?
+                    .filter_map(Result::ok)
+                    .map(|entry| {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        if entry.path().is_dir() {
+                            format!("{name}/")
+                        } else {
+                            name
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                entries.sort();
+                for entry in entries {
+                    writeln!(output, "{entry}").map_err(io_error)?;
+                }
+            },
+            "cat" => {
+                let content = std::fs::read_to_string(path).map_err(io_error)?;
+                write!(output, "{content}").map_err(io_error)?;
+            },
+            "exists" => {
+                let kind = if path.is_symlink() {
+                    "a symbolic link"
+                } else if path.is_dir() {
+                    "a directory"
+                } else if path.is_file() {
+                    "a file"
+                } else if path.exists() {
+                    "something else"
+                } else {
+                    "nothing"
+                };
+                writeln!(output, "'{}' is {kind}", path.display()).map_err(io_error)?;
+            },
+            "write" | "append" => {
+                let text = format!("{}\n", arguments[1..].join(" "));
+                let what = format!("{name} {} bytes to '{}'", text.len(), path.display());
+                if !self.describe(output, &what)? {
+                    let mut file = std::fs::OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .append(name == "append")
+                        .truncate(name == "write")
+                        .open(path)
+                        .map_err(io_error)?;
+                    std::io::Write::write_all(&mut file, text.as_bytes()).map_err(io_error)?;
+                }
+            },
+            "mkdir" => {
+                if !self.describe(
+                    output,
+                    &format!("create the directory '{}'", path.display()),
+                )? {
+                    std::fs::create_dir_all(path).map_err(io_error)?;
+                }
+            },
+            "rm" => {
+                if !path.exists() && !path.is_symlink() {
+                    return Err(format!("'{}' does not exist", path.display()));
+                }
+                if !self.describe(output, &format!("delete '{}'", path.display()))? {
+                    if path.is_dir() && !path.is_symlink() {
+                        std::fs::remove_dir_all(path).map_err(io_error)?;
+                    } else {
+                        std::fs::remove_file(path).map_err(io_error)?;
+                    }
+                }
+            },
+            _ => {
+                let target = self.resolve(&arguments[1]);
+                let verb = if name == "mv" { "move" } else { "copy" };
+                let what = format!("{verb} '{}' to '{}'", path.display(), target.display());
+                if !self.describe(output, &what)? {
+                    if name == "mv" {
+                        std::fs::rename(path, target).map_err(io_error)?;
+                    } else {
+                        std::fs::copy(path, target).map_err(io_error)?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Describe an I/O error like the filesystem functions do.
+fn io_error(error: std::io::Error) -> String { FSError::from(error).to_string() }
+
+#[cfg(test)]
+mod repl_test {
+    use super::*;
+
+    /// Run `input` in `repl` and return what it printed.
+    fn session(repl: &mut Repl, input: &str) -> String {
+        let mut output = vec![];
+        repl.run_with(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn filesystem() {
+        let directory = crate::fs::generate_test_path();
+        std::fs::create_dir(&directory).unwrap();
+        let mut repl = Repl::new().prompt("").working_directory(&directory);
+
+        let output = session(
+            &mut repl,
+            "mkdir etc\ncd etc\nwrite motd Hello there\nappend motd General Kenobi\ncat motd\ncp \
+             motd motd.bak\nls\nexists motd\nrm motd\nexists motd\ncat\n",
+        );
+        assert_eq!(
+            output,
+            "Hello there\nGeneral Kenobi\nmotd\nmotd.bak\n'{0}/etc/motd' is a \
+             file\n'{0}/etc/motd' is nothing\nerror: usage: cat FILE\n"
+                .replace("{0}", &directory.canonicalize().unwrap().to_string_lossy())
+        );
+
+        // Nothing changes in dry-run mode.
+        let output = session(&mut repl, "dry-run on\nrm motd.bak\nwrite new text\nls\n");
+        assert!(output.contains("would delete '"));
+        assert!(output.contains("would write 5 bytes to '"));
+        assert!(output.ends_with("motd.bak\n"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn environment_and_processes() {
+        let mut repl = Repl::new().prompt("> ");
+        let output = session(
+            &mut repl,
+            "set GREETING hello  world\nenv GREETING\nrun sh -c \"echo $GREETING; echo \
+             \\$GREETING\"\nunset GREETING\nenv GREETING\nrun false\nfrobnicate\nexit\nenv\n",
+        );
+        assert_eq!(
+            output,
+            "> > hello world\n> hello world\nhello world\n> > error: 'GREETING' is not set\n> \
+             error: 'false' failed (exit status: 1)\n> error: unknown command 'frobnicate' (see \
+             'help')\n> "
+        );
+    }
+
+    #[test]
+    fn custom_commands() {
+        let mut repl =
+            Repl::new()
+                .prompt("")
+                .command("greet", "Greet someone", |arguments, output| {
+                    if arguments.is_empty() {
+                        return Err(String::from("whom?"));
+                    }
+                    writeln!(output, "Hello, {}!", arguments.join(" "))
+                        .map_err(|error| error.to_string())
+                });
+        let output = session(&mut repl, "greet Ada Lovelace\ngreet\nhelp\n");
+        assert!(output.starts_with("Hello, Ada Lovelace!\nerror: whom?\n"));
+        assert!(output
+            .contains("\nrun PROGRAM [ARGUMENT...]  Run a program with the session's variables\n"));
+        assert!(output.ends_with("\ngreet                      Greet someone\n"));
+    }
+}
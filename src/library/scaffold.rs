@@ -0,0 +1,538 @@
+//! This module contains a generator for application skeletons.
+//!
+//! A [`Scaffold`] describes a directory tree whose names and contents may contain
+//! `{{ variable }}` placeholders, and [`Scaffold::render`] creates it for concrete
+//! values. It is built in code, deserialized from a declarative spec, read from a
+//! template directory ([`Scaffold::from_directory`]) or, with the `embed` feature,
+//! taken from files embedded into the binary ([`Scaffold::from_embedded`]).
+//!
+//! ```
+//! use rush::scaffold::Scaffold;
+//!
+//! let spec = serde_json::json!({
+//!     "files": [
+//!         { "path": "{{ name }}/README.md", "content": "# {{ name }}\n" },
+//!         { "path": "{{ name }}/bin/run", "content": "#!/bin/sh\n", "mode": 0o755 }
+//!     ],
+//!     "directories": [{ "path": "{{ name }}/data" }]
+//! });
+//! let scaffold: Scaffold = serde_json::from_value(spec).unwrap();
+//! let target = std::env::temp_dir().join("rush-scaffold-doc");
+//! let created = scaffold
+//!     .variable("name", "billing")
+//!     .overwrite(true)
+//!     .render(&target)
+//!     .unwrap();
+//! assert_eq!(created.len(), 2);
+//! # std::fs::remove_dir_all(target).unwrap();
+//! ```
+
+use crate::fs::FSError;
+
+/// The permissions of files without a more specific rule.
+const DEFAULT_FILE_MODE: u32 = 0o644;
+/// The permissions of the directories that are created.
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+
+/// Describes possible errors when rendering a scaffold.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ScaffoldError {
+    #[error("The variable '{0}' is used but not set")]
+    MissingVariable(String),
+    #[error("The path '{0}' is not relative or leaves the target directory")]
+    InvalidPath(String),
+    #[error("'{}' already exists", .0.display())]
+    AlreadyExists(std::path::PathBuf),
+    #[error("Reading the template or writing the skeleton failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`ScaffoldError`].
+pub type ScaffoldResult<T> = Result<T, ScaffoldError>;
+
+/// A file of a [`Scaffold`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Template {
+    /// The path relative to the target, which may contain variables
+    path:    String,
+    /// The content, in which variables are replaced if it is UTF-8
+    content: Vec<u8>,
+    /// The permissions, if they are not decided by the rules
+    mode:    Option<u32>,
+}
+
+/// A file in the declarative spec.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecFile {
+    /// The path relative to the target, which may contain variables
+    path:    String,
+    /// The content, which may contain variables
+    #[serde(default)]
+    content: String,
+    /// The permissions
+    mode:    Option<u32>,
+}
+
+/// A directory in the declarative spec.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecDirectory {
+    /// The path relative to the target, which may contain variables
+    path: String,
+    /// The permissions
+    mode: Option<u32>,
+}
+
+/// The declarative spec a [`Scaffold`] is deserialized from.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Spec {
+    /// The values of the variables
+    variables:   std::collections::BTreeMap<String, String>,
+    /// The directories to create, e.g. empty ones
+    directories: Vec<SpecDirectory>,
+    /// The files to create
+    files:       Vec<SpecFile>,
+    /// Glob patterns of files whose content is copied as it is
+    verbatim:    Vec<String>,
+}
+
+/// A directory tree with placeholders in names and contents, see the
+/// [module documentation](self).
+///
+/// It is deserialized from a spec with the optional keys `variables` (a map of
+/// names to values), `directories` (a list of `path` and `mode`), `files` (a list
+/// of `path`, `content` and `mode`) and `verbatim` (a list of glob patterns).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(from = "Spec")]
+pub struct Scaffold {
+    /// The values of the variables
+    variables:      std::collections::BTreeMap<String, String>,
+    /// The directories to create and their permissions, if given
+    directories:    Vec<(String, Option<u32>)>,
+    /// The files to create
+    files:          Vec<Template>,
+    /// Glob patterns of files whose content is copied as it is
+    verbatim:       Vec<String>,
+    /// Glob patterns and the permissions of the files matching them; the last match
+    /// wins
+    modes:          Vec<(String, u32)>,
+    /// The permissions of the directories that are created
+    directory_mode: u32,
+    /// Whether existing files are replaced
+    overwrite:      bool,
+}
+
+impl From<Spec> for Scaffold {
+    fn from(spec: Spec) -> Self {
+        let mut scaffold = Self::new();
+        scaffold.variables = spec.variables;
+        scaffold.verbatim = spec.verbatim;
+        scaffold.directories = spec
+            .directories
+            .into_iter()
+            .map(|directory| (directory.path, directory.mode))
+            .collect();
+        scaffold.files = spec
+            .files
+            .into_iter()
+            .map(|file| Template {
+                path:    file.path,
+                content: file.content.into_bytes(),
+                mode:    file.mode,
+            })
+            .collect();
+        scaffold
+    }
+}
+
+impl Default for Scaffold {
+    fn default() -> Self { Self::new() }
+}
+
+impl Scaffold {
+    /// An empty scaffold.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            variables:      std::collections::BTreeMap::new(),
+            directories:    vec![],
+            files:          vec![],
+            verbatim:       vec![],
+            modes:          vec![],
+            directory_mode: DEFAULT_DIRECTORY_MODE,
+            overwrite:      false,
+        }
+    }
+
+    /// Read the template directory `path`: every file becomes a file of the scaffold
+    /// with the same relative path and permissions, and every empty directory is
+    /// kept. Names and contents may contain placeholders.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the directory fails.
+    pub fn from_directory(path: impl AsRef<std::path::Path>) -> ScaffoldResult<Self> {
+        /// Add the files and empty directories below `directory` to `scaffold`.
+        fn read(
+            scaffold: &mut Scaffold,
+            root: &std::path::Path,
+            directory: &std::path::Path,
+        ) -> Result<(), FSError> {
+            let mut entries = std::fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(std::fs::DirEntry::path);
+            let relative = |path: &std::path::Path| {
+                path.strip_prefix(root)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            if entries.is_empty() && directory != root {
+                scaffold.directories.push((relative(directory), None));
+            }
+            for entry in entries {
+                let path = entry.path();
+                if entry.file_type()?.is_dir() {
+                    read(scaffold, root, &path)?;
+                    continue;
+                }
+                #[cfg(unix)]
+                let mode = {
+                    use std::os::unix::fs::PermissionsExt as _;
+                    Some(std::fs::metadata(&path)?.permissions().mode() & 0o7777)
+                };
+                #[cfg(not(unix))]
+                let mode = None;
+                scaffold.files.push(Template {
+                    path: relative(&path),
+                    content: std::fs::read(&path)?,
+                    mode,
+                });
+            }
+            Ok(())
+        }
+
+        let mut scaffold = Self::new();
+        read(&mut scaffold, path.as_ref(), path.as_ref())?;
+        Ok(scaffold)
+    }
+
+    /// Take every file embedded as `E` (see [`embed!`](crate::embed!)) with the same
+    /// relative path. Names and contents may contain placeholders.
+    #[cfg(feature = "embed")]
+    #[must_use]
+    pub fn from_embedded<E: crate::embed::RustEmbed>() -> Self {
+        let mut names = E::iter().collect::<Vec<_>>();
+        names.sort();
+        let mut scaffold = Self::new();
+        for name in names {
+            if let Some(file) = E::get(&name) {
+                scaffold.files.push(Template {
+                    path:    name.into_owned(),
+                    content: file.data.into_owned(),
+                    mode:    None,
+                });
+            }
+        }
+        scaffold
+    }
+
+    /// Set the variable `name`, which replaces `{{ name }}` in paths and contents.
+    #[must_use]
+    pub fn variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Add the file `path` with `content`. Both may contain placeholders.
+    #[must_use]
+    pub fn file(mut self, path: impl Into<String>, content: impl Into<String>) -> Self {
+        self.files.push(Template {
+            path:    path.into(),
+            content: content.into().into_bytes(),
+            mode:    None,
+        });
+        self
+    }
+
+    /// Add the directory `path`, e.g. one that stays empty. Directories of files are
+    /// created anyway.
+    #[must_use]
+    pub fn directory(mut self, path: impl Into<String>) -> Self {
+        self.directories.push((path.into(), None));
+        self
+    }
+
+    /// Copy the content of files whose rendered path matches the glob `pattern` as
+    /// it is, e.g. for files that contain `{{` themselves. Contents that are not
+    /// UTF-8 are always copied as they are.
+    #[must_use]
+    pub fn verbatim(mut self, pattern: impl Into<String>) -> Self {
+        self.verbatim.push(pattern.into());
+        self
+    }
+
+    /// Set the permissions of files whose rendered path matches the glob `pattern`,
+    /// e.g. `0o755` for scripts. If several rules match a file, the last one wins;
+    /// permissions given for a single file win over all rules. Other files get
+    /// `0o644`.
+    #[must_use]
+    pub fn mode(mut self, pattern: impl Into<String>, mode: u32) -> Self {
+        self.modes.push((pattern.into(), mode));
+        self
+    }
+
+    /// Set the permissions of the directories that are created (default `0o755`).
+    #[must_use]
+    pub const fn directory_mode(mut self, mode: u32) -> Self {
+        self.directory_mode = mode;
+        self
+    }
+
+    /// Whether existing files are replaced. By default, rendering fails before
+    /// anything is written if one of the files exists.
+    #[must_use]
+    pub const fn overwrite(mut self, enabled: bool) -> Self {
+        self.overwrite = enabled;
+        self
+    }
+
+    /// Render the template `path` into a path relative to the target.
+    fn path(&self, path: &str) -> ScaffoldResult<std::path::PathBuf> {
+        let rendered = crate::library::template::render(path, &self.variables)
+            .map_err(ScaffoldError::MissingVariable)?;
+        let relative = std::path::PathBuf::from(&rendered);
+        let is_inside = relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+            && relative.components().next().is_some();
+        if is_inside {
+            Ok(relative)
+        } else {
+            Err(ScaffoldError::InvalidPath(rendered))
+        }
+    }
+
+    /// Create the tree in the directory `target`, which is created if needed.
+    /// Returns the files that were written.
+    ///
+    /// All names and contents are rendered before anything is written, so a missing
+    /// variable leaves the target untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is not set, if a rendered path is absolute or
+    /// contains `..`, if a file exists and [`Scaffold::overwrite`] is not set, or if
+    /// writing fails.
+    pub fn render(
+        &self,
+        target: impl AsRef<std::path::Path>,
+    ) -> ScaffoldResult<Vec<std::path::PathBuf>> {
+        let target = target.as_ref();
+        let matches = |patterns: &[String], path: &std::path::Path| {
+            let path = path.to_string_lossy();
+            patterns
+                .iter()
+                .any(|pattern| rush_core::glob::matches(pattern, &path))
+        };
+
+        let mut files = vec![];
+        for file in &self.files {
+            let relative = self.path(&file.path)?;
+            let content = match std::str::from_utf8(&file.content) {
+                Ok(text) if !matches(&self.verbatim, &relative) => {
+                    crate::library::template::render(text, &self.variables)
+                        .map_err(ScaffoldError::MissingVariable)?
+                        .into_bytes()
+                },
+                _ => file.content.clone(),
+            };
+            let mode = file.mode.unwrap_or_else(|| {
+                self.modes
+                    .iter()
+                    .rev()
+                    .find(|(pattern, _)| matches(std::slice::from_ref(pattern), &relative))
+                    .map_or(DEFAULT_FILE_MODE, |(_, mode)| *mode)
+            });
+            let path = target.join(relative);
+            if !self.overwrite && path.symlink_metadata().is_ok() {
+                return Err(ScaffoldError::AlreadyExists(path));
+            }
+            files.push((path, content, mode));
+        }
+        let directories = self
+            .directories
+            .iter()
+            .map(|(path, mode)| Ok((target.join(self.path(path)?), *mode)))
+            .collect::<ScaffoldResult<Vec<_>>>()?;
+
+        log::debug!(
+            "Rendering {} files into '{}'",
+            files.len(),
+            target.display()
+        );
+        for (directory, mode) in directories {
+            self.create_directory(&directory)?;
+            if let Some(mode) = mode {
+                set_mode(&directory, mode)?;
+            }
+        }
+        let mut written = vec![];
+        for (path, content, mode) in files {
+            if let Some(parent) = path.parent() {
+                self.create_directory(parent)?;
+            }
+            std::fs::write(&path, content).map_err(FSError::from)?;
+            set_mode(&path, mode)?;
+            log::trace!("Rendered '{}'", path.display());
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Create `directory` and its missing parents with the directory mode.
+    fn create_directory(&self, directory: &std::path::Path) -> ScaffoldResult<()> {
+        if directory.is_dir() {
+            return Ok(());
+        }
+        if let Some(parent) = directory.parent() {
+            self.create_directory(parent)?;
+        }
+        std::fs::create_dir(directory).map_err(FSError::from)?;
+        set_mode(directory, self.directory_mode)
+    }
+}
+
+/// Set the permissions of `path` to `mode` on Unix. Elsewhere, modes are ignored.
+fn set_mode(path: &std::path::Path, mode: u32) -> ScaffoldResult<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(FSError::from)?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+#[cfg(test)]
+mod scaffold_test {
+    use super::*;
+
+    #[test]
+    fn render() -> ScaffoldResult<()> {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let target = crate::fs::generate_test_path();
+        let scaffold = Scaffold::new()
+            .variable("name", "billing")
+            .file(
+                "{{ name }}/Cargo.toml",
+                "[package]\nname = \"{{ name }}\"\n",
+            )
+            .file("{{ name }}/bin/run", "#!/bin/sh\n")
+            .file("{{ name }}/.github/ci.yml", "token: ${{ secrets.TOKEN }}\n")
+            .directory("{{ name }}/data")
+            .verbatim("*/.github/*")
+            .mode("*/bin/*", 0o755);
+        let created = scaffold.render(&target)?;
+        assert_eq!(
+            created,
+            [
+                target.join("billing/Cargo.toml"),
+                target.join("billing/bin/run"),
+                target.join("billing/.github/ci.yml"),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join("billing/Cargo.toml")).unwrap(),
+            "[package]\nname = \"billing\"\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join("billing/.github/ci.yml")).unwrap(),
+            "token: ${{ secrets.TOKEN }}\n"
+        );
+        let mode = |path: &str| {
+            std::fs::metadata(target.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode("billing/bin/run"), 0o755);
+        assert_eq!(mode("billing/Cargo.toml"), 0o644);
+        assert!(target.join("billing/data").is_dir());
+
+        assert_eq!(
+            scaffold.render(&target),
+            Err(ScaffoldError::AlreadyExists(
+                target.join("billing/Cargo.toml")
+            ))
+        );
+        assert_eq!(
+            Scaffold::new().file("{{ missing }}", "").render(&target),
+            Err(ScaffoldError::MissingVariable(String::from("missing")))
+        );
+        assert_eq!(
+            Scaffold::new().file("../escape", "").render(&target),
+            Err(ScaffoldError::InvalidPath(String::from("../escape")))
+        );
+
+        let from_directory = Scaffold::from_directory(target.join("billing"))?
+            .verbatim(".github/*")
+            .render(target.join("copy"))?;
+        assert_eq!(from_directory.len(), 3);
+        assert_eq!(mode("copy/bin/run"), 0o755);
+        assert!(target.join("copy/data").is_dir());
+
+        std::fs::remove_dir_all(target).unwrap();
+        Ok(())
+    }
+
+    #[cfg(feature = "embed")]
+    #[test]
+    fn embedded() -> ScaffoldResult<()> {
+        /// The files in `embed/fixtures/`
+        #[derive(crate::embed::RustEmbed)]
+        #[folder = "src/library/embed/fixtures"]
+        #[crate_path = "crate::embed::rust_embed"]
+        struct Fixtures;
+
+        let target = crate::fs::generate_test_path();
+        let created = Scaffold::from_embedded::<Fixtures>()
+            .mode("bin/*", 0o755)
+            .render(&target)?;
+        assert_eq!(
+            created,
+            [target.join("bin/hello"), target.join("share/greeting.txt")]
+        );
+        std::fs::remove_dir_all(target).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn spec() {
+        let spec = r#"{
+            "variables": { "name": "billing" },
+            "files": [{ "path": "{{ name }}.conf", "content": "name={{ name }}", "mode": 384 }],
+            "directories": [{ "path": "logs", "mode": 448 }]
+        }"#;
+        let scaffold: Scaffold = serde_json::from_str(spec).unwrap();
+        assert_eq!(
+            scaffold,
+            Scaffold {
+                variables: [(String::from("name"), String::from("billing"))].into(),
+                directories: vec![(String::from("logs"), Some(0o700))],
+                files: vec![Template {
+                    path:    String::from("{{ name }}.conf"),
+                    content: b"name={{ name }}".to_vec(),
+                    mode:    Some(0o600),
+                }],
+                ..Scaffold::new()
+            }
+        );
+        assert!(serde_json::from_str::<Scaffold>(r#"{ "file": [] }"#).is_err());
+    }
+}
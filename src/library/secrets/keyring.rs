@@ -0,0 +1,236 @@
+//! This module contains functionality for storing credentials in the operating
+//! system's keychain, i.e. the Secret Service on Linux, the Keychain on macOS and
+//! the Credential Manager on Windows.
+//!
+//! Headless machines like CI runners usually have no keychain. There, credentials
+//! are read from an environment variable instead, so that the same script works on
+//! a workstation and in a pipeline.
+
+/// Describes possible errors when accessing the keychain.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum KeyringError {
+    #[error("No credential for account '{account}' of service '{service}' exists")]
+    NotFound { service: String, account: String },
+    #[error("The keychain is not available: {0}")]
+    Unavailable(String),
+    #[error("The credential is not valid: {0}")]
+    Invalid(String),
+}
+
+/// A [`Result`] whose error variant is a [`KeyringError`].
+pub type KeyringResult<T> = Result<T, KeyringError>;
+
+impl From<::keyring::Error> for KeyringError {
+    fn from(error: ::keyring::Error) -> Self {
+        match error {
+            ::keyring::Error::PlatformFailure(_) | ::keyring::Error::NoStorageAccess(_) => {
+                Self::Unavailable(error.to_string())
+            },
+            // The platform error does not know the service and account, the caller
+            // fills them in.
+            ::keyring::Error::NoEntry => Self::NotFound {
+                service: String::new(),
+                account: String::new(),
+            },
+            _ => Self::Invalid(error.to_string()),
+        }
+    }
+}
+
+/// A credential in the keychain, identified by a service (e.g. the name of the
+/// script or of the API) and an account (e.g. the user name).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Entry {
+    /// The service the credential belongs to
+    service:      String,
+    /// The account the credential belongs to
+    account:      String,
+    /// The environment variable that is read when the keychain has no credential
+    env_variable: String,
+}
+
+impl Entry {
+    /// Create an entry for `account` of `service`. The environment variable used as
+    /// fallback is derived from both, see [`Entry::env_variable`].
+    pub fn new(service: impl Into<String>, account: impl Into<String>) -> Self {
+        let service = service.into();
+        let account = account.into();
+        let env_variable = env_variable_name(&service, &account);
+        Self {
+            service,
+            account,
+            env_variable,
+        }
+    }
+
+    /// Read the credential from `name` instead of the derived environment variable
+    /// when the keychain has no credential.
+    #[must_use]
+    pub fn with_env_variable(mut self, name: impl Into<String>) -> Self {
+        self.env_variable = name.into();
+        self
+    }
+
+    /// The service the credential belongs to.
+    #[must_use]
+    pub fn service(&self) -> &str { &self.service }
+
+    /// The account the credential belongs to.
+    #[must_use]
+    pub fn account(&self) -> &str { &self.account }
+
+    /// The environment variable that is read when the keychain has no credential or
+    /// is not available. Unless set with [`Entry::with_env_variable`], it consists of
+    /// the service and the account, upper-cased and joined by an underscore, with all
+    /// other characters than letters and digits replaced by underscores; the entry
+    /// for account `deploy` of service `registry.example.com` is thus read from
+    /// `REGISTRY_EXAMPLE_COM_DEPLOY`.
+    #[must_use]
+    pub fn env_variable(&self) -> &str { &self.env_variable }
+
+    /// The error for a missing credential.
+    fn not_found(&self) -> KeyringError {
+        KeyringError::NotFound {
+            service: self.service.clone(),
+            account: self.account.clone(),
+        }
+    }
+
+    /// The entry of the platform's keychain.
+    fn platform_entry(&self) -> KeyringResult<::keyring::Entry> {
+        Ok(::keyring::Entry::new(&self.service, &self.account)?)
+    }
+
+    /// Get the credential. The keychain is asked first; if it has no credential or
+    /// is not available, the environment variable is read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if neither the keychain nor the environment variable hold
+    /// the credential, or if the credential is not valid UTF-8.
+    pub fn get(&self) -> KeyringResult<String> {
+        let keychain_error = match self
+            .platform_entry()
+            .and_then(|entry| Ok(entry.get_password()?))
+        {
+            Ok(secret) => {
+                super::remember(secret.as_str());
+                return Ok(secret);
+            },
+            Err(KeyringError::Invalid(error)) => return Err(KeyringError::Invalid(error)),
+            Err(error) => error,
+        };
+
+        log::trace!(
+            "Keychain has no credential for '{}' of '{}' ({keychain_error}), reading '{}'",
+            self.account,
+            self.service,
+            self.env_variable
+        );
+        match std::env::var(&self.env_variable) {
+            Ok(secret) => {
+                super::remember(secret.as_str());
+                Ok(secret)
+            },
+            Err(std::env::VarError::NotUnicode(_)) => Err(KeyringError::Invalid(format!(
+                "environment variable '{}' is not valid UTF-8",
+                self.env_variable
+            ))),
+            Err(std::env::VarError::NotPresent) => match keychain_error {
+                KeyringError::NotFound { .. } => Err(self.not_found()),
+                error => Err(error),
+            },
+        }
+    }
+
+    /// Store `secret` in the keychain, replacing an existing credential. The
+    /// environment variable is never written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keychain is not available or rejects the credential.
+    pub fn set(&self, secret: &str) -> KeyringResult<()> {
+        log::debug!(
+            "Storing credential for '{}' of '{}' in keychain",
+            self.account,
+            self.service
+        );
+        Ok(self.platform_entry()?.set_password(secret)?)
+    }
+
+    /// Remove the credential from the keychain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keychain has no credential or is not available.
+    pub fn delete(&self) -> KeyringResult<()> {
+        log::debug!(
+            "Removing credential for '{}' of '{}' from keychain",
+            self.account,
+            self.service
+        );
+        self.platform_entry()?.delete_credential().map_err(|error| {
+            match KeyringError::from(error) {
+                KeyringError::NotFound { .. } => self.not_found(),
+                error => error,
+            }
+        })
+    }
+}
+
+/// Derive the name of the environment variable for `account` of `service`.
+fn env_variable_name(service: &str, account: &str) -> String {
+    format!("{service}_{account}")
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Get the credential for `account` of `service`, see [`Entry::get`].
+///
+/// # Errors
+///
+/// Returns an error if neither the keychain nor the environment variable hold the
+/// credential.
+pub fn get(service: &str, account: &str) -> KeyringResult<String> {
+    Entry::new(service, account).get()
+}
+
+/// Store `secret` as the credential for `account` of `service`, see [`Entry::set`].
+///
+/// # Errors
+///
+/// Returns an error if the keychain is not available or rejects the credential.
+pub fn set(service: &str, account: &str, secret: &str) -> KeyringResult<()> {
+    Entry::new(service, account).set(secret)
+}
+
+#[cfg(test)]
+mod keyring_test {
+    use super::*;
+
+    #[test]
+    fn env_variable_fallback() -> KeyringResult<()> {
+        let entry = Entry::new("registry.example.com", "rush-test-deploy");
+        assert_eq!(
+            entry.env_variable(),
+            "REGISTRY_EXAMPLE_COM_RUSH_TEST_DEPLOY"
+        );
+
+        let entry = entry.with_env_variable("RUSH_TEST_KEYRING_FALLBACK");
+        assert!(matches!(
+            entry.get(),
+            Err(KeyringError::NotFound { .. } | KeyringError::Unavailable(_))
+        ));
+        std::env::set_var("RUSH_TEST_KEYRING_FALLBACK", "hunter2");
+        assert_eq!(entry.get()?, "hunter2");
+        std::env::remove_var("RUSH_TEST_KEYRING_FALLBACK");
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+//! This module contains functionality for retrieving and storing secrets without
+//! keeping them in plaintext files.
+
+#[cfg(feature = "keyring")]
+pub mod keyring;
+mod redact;
+#[cfg(feature = "vault")]
+pub mod vault;
+
+pub use redact::{
+    redact,
+    remember,
+    REDACTED,
+};
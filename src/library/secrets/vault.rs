@@ -0,0 +1,569 @@
+//! This module contains a client for [HashiCorp Vault](https://www.vaultproject.io)
+//! and servers with the same API, like [OpenBao](https://openbao.org).
+//!
+//! Deployment scripts use it to fetch secrets like database passwords and to put
+//! them into the [`Environment`] of the processes they start, so that secrets never
+//! end up in files.
+
+use crate::environment::Environment;
+
+/// How long to wait for the server to accept a connection.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// How long to wait for the server to answer a request.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Describes possible errors when talking to Vault.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum VaultError {
+    #[error("The configuration is not valid: {0}")]
+    InvalidConfiguration(String),
+    #[error("Authenticating failed: {0}")]
+    Authentication(String),
+    #[error("The request for '{0}' failed: {1}")]
+    Request(String, String),
+    #[error("No secret exists at '{0}'")]
+    NotFound(String),
+    #[error("The secret at '{path}' has no key '{key}'")]
+    MissingKey { path: String, key: String },
+    #[error("The response could not be understood: {0}")]
+    InvalidResponse(String),
+}
+
+/// A [`Result`] whose error variant is a [`VaultError`].
+pub type VaultResult<T> = Result<T, VaultError>;
+
+/// How the client authenticates.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Auth {
+    /// A token that was obtained beforehand, e.g. by `vault login`
+    Token(String),
+    /// The `AppRole` method, which is meant for machines and scripts. The token is
+    /// obtained when the first request is sent.
+    AppRole {
+        /// The role ID, which is not secret
+        role_id:   String,
+        /// The secret ID
+        secret_id: String,
+        /// The path the method is mounted at, usually `approle`
+        mount:     String,
+    },
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Token(_) => f.write_str("Token(..)"),
+            Self::AppRole { role_id, mount, .. } => f
+                .debug_struct("AppRole")
+                .field("role_id", role_id)
+                .field("mount", mount)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl Auth {
+    /// Authenticate with the `AppRole` method mounted at `approle`.
+    pub fn app_role(role_id: impl Into<String>, secret_id: impl Into<String>) -> Self {
+        Self::AppRole {
+            role_id:   role_id.into(),
+            secret_id: secret_id.into(),
+            mount:     String::from("approle"),
+        }
+    }
+}
+
+/// A lease on a dynamic secret, e.g. database credentials Vault created for this
+/// client. The secret is revoked when the lease expires unless it is renewed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Lease {
+    /// The ID of the lease
+    pub id:        String,
+    /// How long the lease lasts from the moment it was issued or renewed
+    pub duration:  std::time::Duration,
+    /// Whether the lease can be renewed
+    pub renewable: bool,
+}
+
+/// A secret that was read from Vault.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret {
+    /// The key-value pairs of the secret
+    pub data:  std::collections::BTreeMap<String, String>,
+    /// The lease of the secret, if it is a dynamic one
+    pub lease: Option<Lease>,
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Secret")
+            .field("keys", &self.data.keys().collect::<Vec<_>>())
+            .field("lease", &self.lease)
+            .finish()
+    }
+}
+
+/// A client for a Vault server.
+#[derive(Debug)]
+pub struct Client {
+    /// The scheme and authority of the server, e.g. `https://vault.example.com:8200`
+    address:   String,
+    /// The namespace requests are sent to (Vault Enterprise only)
+    namespace: Option<String>,
+    /// How the client authenticates
+    auth:      Auth,
+    /// The HTTP agent, which enforces timeouts
+    agent:     ureq::Agent,
+    /// The token sent with every request, once it is known
+    token:     std::sync::Mutex<Option<String>>,
+}
+
+impl Client {
+    /// Create a client for the server at `address` that authenticates with `auth`.
+    pub fn new(address: impl Into<String>, auth: Auth) -> Self {
+        let token = match &auth {
+            Auth::Token(token) => Some(token.clone()),
+            Auth::AppRole { .. } => None,
+        };
+        Self {
+            address: address.into().trim_end_matches('/').to_string(),
+            namespace: None,
+            auth,
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build(),
+            token: std::sync::Mutex::new(token),
+        }
+    }
+
+    /// Create a client from the environment variables the `vault` command line tool
+    /// uses: `VAULT_ADDR`, `VAULT_NAMESPACE` and `VAULT_TOKEN`. Without a token,
+    /// `VAULT_ROLE_ID` and `VAULT_SECRET_ID` are used to authenticate with the
+    /// `AppRole` method.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address or the credentials are not set.
+    pub fn from_env() -> VaultResult<Self> {
+        let variable = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let missing = |name: &str| VaultError::InvalidConfiguration(format!("'{name}' is not set"));
+
+        let address = variable("VAULT_ADDR").ok_or_else(|| missing("VAULT_ADDR"))?;
+        let auth = if let Some(token) = variable("VAULT_TOKEN") {
+            Auth::Token(token)
+        } else {
+            Auth::app_role(
+                variable("VAULT_ROLE_ID").ok_or_else(|| {
+                    VaultError::InvalidConfiguration(String::from(
+                        "neither 'VAULT_TOKEN' nor 'VAULT_ROLE_ID' is set",
+                    ))
+                })?,
+                variable("VAULT_SECRET_ID").ok_or_else(|| missing("VAULT_SECRET_ID"))?,
+            )
+        };
+        let client = Self::new(address, auth);
+        Ok(match variable("VAULT_NAMESPACE") {
+            Some(namespace) => client.with_namespace(namespace),
+            None => client,
+        })
+    }
+
+    /// Send all requests to `namespace` (Vault Enterprise only).
+    #[must_use]
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Build a request to the API endpoint `path` (without the `/v1/` prefix).
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(
+            method,
+            &format!("{}/v1/{}", self.address, path.trim_start_matches('/')),
+        );
+        match &self.namespace {
+            Some(namespace) => request.set("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    /// Send `request`, with `body` if there is one, and parse the response.
+    fn send(
+        path: &str,
+        request: ureq::Request,
+        body: Option<&serde_json::Value>,
+    ) -> VaultResult<serde_json::Value> {
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+        match response {
+            Ok(response) => response
+                .into_json()
+                .map_err(|error| VaultError::InvalidResponse(error.to_string())),
+            Err(ureq::Error::Status(404, _)) => Err(VaultError::NotFound(path.to_string())),
+            Err(ureq::Error::Status(code, response)) => {
+                // Vault explains what went wrong in a list of errors.
+                let errors = response
+                    .into_json::<serde_json::Value>()
+                    .ok()
+                    .and_then(|body| {
+                        body["errors"].as_array().map(|errors| {
+                            errors
+                                .iter()
+                                .filter_map(serde_json::Value::as_str)
+                                .collect::<Vec<_>>()
+                                .join("; ")
+                        })
+                    })
+                    .unwrap_or_default();
+                Err(VaultError::Request(
+                    path.to_string(),
+                    format!("the server answered with status {code}: {errors}"),
+                ))
+            },
+            Err(ureq::Error::Transport(transport)) => {
+                Err(VaultError::Request(path.to_string(), transport.to_string()))
+            },
+        }
+    }
+
+    /// Obtain a token with the configured method. Nothing needs to be done for
+    /// [`Auth::Token`]. Requests log in on their own, so this only has to be called
+    /// to check the credentials early.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server rejects the credentials.
+    pub fn login(&self) -> VaultResult<()> {
+        let Auth::AppRole {
+            role_id,
+            secret_id,
+            mount,
+        } = &self.auth
+        else {
+            return Ok(());
+        };
+
+        log::debug!("Logging in to Vault with AppRole '{role_id}'");
+        let path = format!("auth/{mount}/login");
+        let response = Self::send(
+            &path,
+            self.request("POST", &path),
+            Some(&serde_json::json!({ "role_id": role_id, "secret_id": secret_id })),
+        )
+        .map_err(|error| VaultError::Authentication(error.to_string()))?;
+        let token = response["auth"]["client_token"].as_str().ok_or_else(|| {
+            VaultError::InvalidResponse(String::from("no client token in the response"))
+        })?;
+
+        *self
+            .token
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(token.to_string());
+        Ok(())
+    }
+
+    /// The token to authenticate requests with, logging in first if necessary.
+    fn token(&self) -> VaultResult<String> {
+        let token = self
+            .token
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+        if let Some(token) = token {
+            return Ok(token);
+        }
+        self.login()?;
+        self.token()
+    }
+
+    /// Send an authenticated request to the API endpoint `path`.
+    fn call(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> VaultResult<serde_json::Value> {
+        let request = self
+            .request(method, path)
+            .set("X-Vault-Token", &self.token()?);
+        Self::send(path, request, body)
+    }
+
+    /// Read the secret at `path`, which is the API path of the secret, e.g.
+    /// `secret/data/database` for version 2 of the key-value engine mounted at
+    /// `secret`, or `database/creds/readonly` for dynamic database credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the secret does not exist or the
+    /// response cannot be understood.
+    pub fn read_secret(&self, path: &str) -> VaultResult<Secret> {
+        log::trace!("Reading Vault secret '{path}'");
+        parse_secret(&self.call("GET", path, None)?)
+    }
+
+    /// Read the value of `key` in the secret at `path`, see [`Client::read_secret`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secret cannot be read or has no `key`.
+    pub fn read(&self, path: &str, key: &str) -> VaultResult<String> {
+        self.read_secret(path)?
+            .data
+            .remove(key)
+            .ok_or_else(|| VaultError::MissingKey {
+                path: path.to_string(),
+                key:  key.to_string(),
+            })
+    }
+
+    /// Renew `lease` and return the renewed lease. `increment` asks for a new
+    /// duration, which the server may shorten.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lease is not renewable or the server refuses to renew
+    /// it.
+    pub fn renew_lease(
+        &self,
+        lease: &Lease,
+        increment: Option<std::time::Duration>,
+    ) -> VaultResult<Lease> {
+        if !lease.renewable {
+            return Err(VaultError::InvalidConfiguration(format!(
+                "lease '{}' is not renewable",
+                lease.id
+            )));
+        }
+
+        log::debug!("Renewing Vault lease '{}'", lease.id);
+        let mut body = serde_json::json!({ "lease_id": lease.id });
+        if let Some(increment) = increment {
+            body["increment"] = increment.as_secs().into();
+        }
+        let response = self.call("PUT", "sys/leases/renew", Some(&body))?;
+        parse_lease(&response)
+            .ok_or_else(|| VaultError::InvalidResponse(String::from("no lease in the response")))
+    }
+
+    /// Renew the client's own token and return how long it stays valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token is not renewable or the server refuses to renew
+    /// it.
+    pub fn renew_token(
+        &self,
+        increment: Option<std::time::Duration>,
+    ) -> VaultResult<std::time::Duration> {
+        log::debug!("Renewing Vault token");
+        let body = increment.map_or_else(
+            || serde_json::json!({}),
+            |increment| serde_json::json!({ "increment": increment.as_secs() }),
+        );
+        let response = self.call("POST", "auth/token/renew-self", Some(&body))?;
+        response["auth"]["lease_duration"]
+            .as_u64()
+            .map(std::time::Duration::from_secs)
+            .ok_or_else(|| {
+                VaultError::InvalidResponse(String::from("no lease duration in the response"))
+            })
+    }
+
+    /// Read the value of `key` in the secret at `path` and add it to `environment`
+    /// as `var_name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secret cannot be read or has no `key`.
+    pub fn inject(
+        &self,
+        environment: &mut Environment,
+        path: &str,
+        key: &str,
+        var_name: &str,
+    ) -> VaultResult<()> {
+        let value = self.read(path, key)?;
+        log::trace!("Injecting key '{key}' of Vault secret '{path}' as '{var_name}'");
+        environment
+            .add(var_name, &value)
+            .map_err(|error| VaultError::InvalidConfiguration(error.to_string()))
+    }
+}
+
+/// Parse the lease of a response, if it has one.
+fn parse_lease(response: &serde_json::Value) -> Option<Lease> {
+    let id = response["lease_id"].as_str().filter(|id| !id.is_empty())?;
+    Some(Lease {
+        id:        id.to_string(),
+        duration:  std::time::Duration::from_secs(
+            response["lease_duration"].as_u64().unwrap_or_default(),
+        ),
+        renewable: response["renewable"].as_bool().unwrap_or_default(),
+    })
+}
+
+/// Parse the response to a read request.
+fn parse_secret(response: &serde_json::Value) -> VaultResult<Secret> {
+    let mut data = response["data"]
+        .as_object()
+        .ok_or_else(|| VaultError::InvalidResponse(String::from("no data in the response")))?;
+    // Version 2 of the key-value engine wraps the secret together with its metadata.
+    if let (Some(inner), true) = (
+        data.get("data").and_then(|inner| inner.as_object()),
+        data.contains_key("metadata"),
+    ) {
+        data = inner;
+    }
+
+    Ok(Secret {
+        data:  data
+            .iter()
+            .map(|(key, value)| {
+                let value = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), String::from);
+                super::remember(value.as_str());
+                (key.clone(), value)
+            })
+            .collect(),
+        lease: parse_lease(response),
+    })
+}
+
+#[cfg(test)]
+mod vault_test {
+    use super::*;
+
+    #[test]
+    fn parse_responses() -> VaultResult<()> {
+        let kv2 = serde_json::json!({
+            "lease_id": "",
+            "lease_duration": 0,
+            "renewable": false,
+            "data": {
+                "data": { "password": "hunter2", "port": 5432 },
+                "metadata": { "version": 3 }
+            }
+        });
+        let secret = parse_secret(&kv2)?;
+        assert_eq!(secret.data["password"], "hunter2");
+        assert_eq!(secret.data["port"], "5432");
+        assert_eq!(secret.lease, None);
+        assert!(!format!("{secret:?}").contains("hunter2"));
+
+        let dynamic = serde_json::json!({
+            "lease_id": "database/creds/readonly/abc",
+            "lease_duration": 90,
+            "renewable": true,
+            "data": { "username": "v-token-readonly", "password": "secret" }
+        });
+        let secret = parse_secret(&dynamic)?;
+        assert_eq!(secret.data["username"], "v-token-readonly");
+        assert_eq!(
+            secret.lease,
+            Some(Lease {
+                id:        String::from("database/creds/readonly/abc"),
+                duration:  std::time::Duration::from_secs(90),
+                renewable: true,
+            })
+        );
+
+        assert!(!format!("{:?}", Auth::app_role("role", "s3cr3t")).contains("s3cr3t"));
+        Ok(())
+    }
+
+    #[test]
+    fn app_role_and_inject() -> VaultResult<()> {
+        use std::io::{
+            BufRead,
+            Read,
+            Write,
+        };
+
+        // A server that hands out a token for the AppRole login and then expects it
+        // when the secret is read.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .map_err(|error| VaultError::Request(String::new(), error.to_string()))?;
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let line = line.to_ascii_lowercase();
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, body) = if request.starts_with("post /v1/auth/approle/login")
+                    && String::from_utf8_lossy(&body).contains("\"secret_id\":\"s3cr3t\"")
+                {
+                    ("200 OK", r#"{"auth":{"client_token":"hvs.token"}}"#)
+                } else if request.starts_with("get /v1/secret/data/database")
+                    && request.contains("x-vault-token: hvs.token")
+                {
+                    (
+                        "200 OK",
+                        r#"{"data":{"data":{"password":"hunter2"},"metadata":{}}}"#,
+                    )
+                } else {
+                    ("403 Forbidden", r#"{"errors":["permission denied"]}"#)
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let client = Client::new(address, Auth::app_role("role", "s3cr3t"));
+        let mut environment = Environment::new();
+        client.inject(
+            &mut environment,
+            "secret/data/database",
+            "password",
+            "DATABASE_PASSWORD",
+        )?;
+        server.join().unwrap();
+        assert_eq!(environment.get("DATABASE_PASSWORD"), Some("hunter2"));
+        Ok(())
+    }
+
+    #[test]
+    fn from_env_names_missing_credentials() {
+        std::env::set_var("VAULT_ADDR", "http://127.0.0.1:8200");
+        std::env::remove_var("VAULT_TOKEN");
+        std::env::remove_var("VAULT_ROLE_ID");
+        assert_eq!(
+            Client::from_env().unwrap_err(),
+            VaultError::InvalidConfiguration(String::from(
+                "neither 'VAULT_TOKEN' nor 'VAULT_ROLE_ID' is set"
+            ))
+        );
+
+        std::env::set_var("VAULT_ROLE_ID", "role");
+        std::env::remove_var("VAULT_SECRET_ID");
+        assert_eq!(
+            Client::from_env().unwrap_err(),
+            VaultError::InvalidConfiguration(String::from("'VAULT_SECRET_ID' is not set"))
+        );
+        std::env::remove_var("VAULT_ROLE_ID");
+    }
+}
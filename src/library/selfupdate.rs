@@ -0,0 +1,656 @@
+//! This module contains functionality for programs that update themselves.
+//!
+//! An [`Updater`] finds out whether a newer version was released, downloads and
+//! verifies it, replaces the running executable and goes back to the previous
+//! version if the new one misbehaves.
+//!
+//! Releases are described either by a small JSON manifest served anywhere, or by
+//! the latest release on GitHub or GitLab (see [`crate::forge`]). The new binary
+//! always has to match its SHA-256 checksum; with
+//! [`Updater::verify_signature`], it also has to carry a valid SSH signature (as
+//! created by `ssh-keygen -Y sign -n file`).
+//!
+//! ```no_run
+//! # use rush::selfupdate::{check_and_apply, Source};
+//! let source = Source::Manifest(String::from("https://example.com/tool/latest.json"));
+//! println!("{}", check_and_apply(source, env!("CARGO_PKG_VERSION")).unwrap());
+//! ```
+
+use crate::{
+    forge::{
+        Client,
+        ForgeError,
+    },
+    fs::FSError,
+    process::Command,
+};
+
+/// How long to wait for the server to accept a connection.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long a single read may stall.
+const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(90);
+/// The extension of the previous version kept for [`Updater::rollback`].
+const PREVIOUS_EXTENSION: &str = ".old";
+
+/// Describes possible errors when updating the running program.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum SelfUpdateError {
+    #[error("'{0}' is not a valid version")]
+    InvalidVersion(String),
+    #[error("The release manifest is not valid: {0}")]
+    InvalidManifest(String),
+    #[error("Downloading '{0}' failed: {1}")]
+    Download(String, String),
+    #[error("The checksum of the new version is {actual}, but {expected} was expected")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("The signature of the new version is not valid: {0}")]
+    InvalidSignature(String),
+    #[error("The new version failed its smoke test: {0}")]
+    SmokeTest(String),
+    #[error("There is no previous version at '{0}' to roll back to")]
+    NoPreviousVersion(String),
+    #[error("Talking to the forge failed: {0}")]
+    Forge(#[from] ForgeError),
+    #[error("Replacing the executable failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`SelfUpdateError`].
+pub type SelfUpdateResult<T> = Result<T, SelfUpdateError>;
+
+/// Where releases are published.
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// The URL of a JSON manifest describing the latest release, like
+    /// `{"version": "1.2.0", "url": "tool-1.2.0", "sha256": "..."}`. A relative `url`
+    /// is resolved against the manifest's URL. The signature is expected at
+    /// `<url>.sig`.
+    Manifest(String),
+    /// The latest release on a forge. The binary is the release asset `asset`, its
+    /// checksum (in the format of `sha256sum`) is `<asset>.sha256` and its signature
+    /// `<asset>.sig`. The tag is the version, with an optional leading `v`.
+    Forge {
+        /// The client for the repository
+        client: Client,
+        /// The name of the asset that is the binary
+        asset:  String,
+    },
+}
+
+/// The latest release, as described by a [`Source`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Release {
+    /// The version of the release
+    version: String,
+    /// The tag (on a forge) or the URL (of a manifest) of the binary
+    binary:  String,
+    /// The checksum of the binary, if the manifest states it
+    sha256:  Option<String>,
+}
+
+/// Describes the outcome of [`Updater::apply`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The running version is the latest one
+    UpToDate(String),
+    /// The executable was replaced with a newer version
+    Updated {
+        /// The previous version
+        from: String,
+        /// The version that was installed
+        to:   String,
+    },
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UpToDate(version) => write!(f, "Version {version} is up to date"),
+            Self::Updated { from, to } => write!(f, "Updated from version {from} to {to}"),
+        }
+    }
+}
+
+/// Updates an executable, the running one by default, from a [`Source`].
+#[derive(Debug, Clone)]
+pub struct Updater {
+    /// Where releases are published
+    source:     Source,
+    /// The version that is running
+    current:    String,
+    /// The executable to replace, or the running one
+    executable: Option<std::path::PathBuf>,
+    /// The allowed signers file and the identity signatures are verified with
+    signature:  Option<(std::path::PathBuf, String)>,
+    /// The arguments the new version is run with before it is installed
+    smoke_test: Option<Vec<String>>,
+}
+
+impl Updater {
+    /// Update from `source`, with `current` being the running version (usually
+    /// `env!("CARGO_PKG_VERSION")`).
+    pub fn new(source: Source, current: impl Into<String>) -> Self {
+        Self {
+            source,
+            current: current.into(),
+            executable: None,
+            signature: None,
+            smoke_test: None,
+        }
+    }
+
+    /// Replace the executable at `path` instead of the running one.
+    #[must_use]
+    pub fn executable(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.executable = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Require the new version to be signed by `identity` (e.g. an email address),
+    /// whose public key is listed in `allowed_signers` (see `ssh-keygen(1)`). The
+    /// signature is checked with `ssh-keygen -Y verify`.
+    #[must_use]
+    pub fn verify_signature(
+        mut self,
+        allowed_signers: impl AsRef<std::path::Path>,
+        identity: impl Into<String>,
+    ) -> Self {
+        self.signature = Some((allowed_signers.as_ref().to_path_buf(), identity.into()));
+        self
+    }
+
+    /// Run the new version with `arguments` (e.g. `--version`) before installing it.
+    /// It is only installed if it exits successfully.
+    #[must_use]
+    pub fn smoke_test<I, S>(mut self, arguments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.smoke_test = Some(
+            arguments
+                .into_iter()
+                .map(|argument| argument.as_ref().to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// The executable that is replaced.
+    fn executable_path(&self) -> SelfUpdateResult<std::path::PathBuf> {
+        match &self.executable {
+            Some(path) => Ok(path.clone()),
+            None => Ok(std::env::current_exe().map_err(FSError::from)?),
+        }
+    }
+
+    /// Look up the latest release.
+    fn latest(&self) -> SelfUpdateResult<Release> {
+        match &self.source {
+            Source::Manifest(url) => {
+                let manifest: serde_json::Value = agent()
+                    .get(url)
+                    .call()
+                    .map_err(|error| SelfUpdateError::Download(url.clone(), error.to_string()))?
+                    .into_json()
+                    .map_err(|error| SelfUpdateError::InvalidManifest(error.to_string()))?;
+                let member = |name: &str| {
+                    manifest[name]
+                        .as_str()
+                        .map(ToString::to_string)
+                        .ok_or_else(|| {
+                            SelfUpdateError::InvalidManifest(format!("'{name}' is missing"))
+                        })
+                };
+                let binary = member("url")?;
+                Ok(Release {
+                    version: member("version")?,
+                    binary:  if binary.contains("://") {
+                        binary
+                    } else {
+                        // Relative to the directory the manifest is in.
+                        format!(
+                            "{}/{binary}",
+                            url.rsplit_once('/').map_or(url.as_str(), |(base, _)| base)
+                        )
+                    },
+                    sha256:  Some(member("sha256")?),
+                })
+            },
+            Source::Forge { client, .. } => {
+                let tag = client.latest_release_tag()?;
+                Ok(Release {
+                    version: tag.trim_start_matches('v').to_string(),
+                    binary:  tag,
+                    sha256:  None,
+                })
+            },
+        }
+    }
+
+    /// Return the latest version if it is newer than the running one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the latest release cannot be looked up or a version is
+    /// not valid.
+    pub fn check(&self) -> SelfUpdateResult<Option<String>> {
+        let latest = self.latest()?;
+        Ok(is_newer(&latest.version, &self.current)?.then_some(latest.version))
+    }
+
+    /// Download the file `suffix` belongs to (the binary for an empty `suffix`, or
+    /// e.g. its checksum for `.sha256`) of `release` to `target`.
+    fn download(
+        &self,
+        release: &Release,
+        suffix: &str,
+        target: &std::path::Path,
+    ) -> SelfUpdateResult<()> {
+        match &self.source {
+            Source::Manifest(_) => {
+                let url = format!("{}{suffix}", release.binary);
+                log::debug!("Downloading '{url}'");
+                let to_error = |error: &dyn std::fmt::Display| {
+                    SelfUpdateError::Download(url.clone(), error.to_string())
+                };
+                let response = agent().get(&url).call().map_err(|error| to_error(&error))?;
+                let mut writer = std::fs::File::create(target).map_err(FSError::from)?;
+                std::io::copy(&mut response.into_reader(), &mut writer)
+                    .map_err(|error| to_error(&error))?;
+            },
+            Source::Forge { client, asset } => {
+                client.download_asset(&release.binary, &format!("{asset}{suffix}"), target)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Download the binary of `release` to `target` and verify it.
+    fn fetch(&self, release: &Release, target: &std::path::Path) -> SelfUpdateResult<()> {
+        self.download(release, "", target)?;
+
+        let expected = if let Some(sha256) = &release.sha256 {
+            sha256.clone()
+        } else {
+            let checksum = sibling(target, ".sha256");
+            let downloaded = self
+                .download(release, ".sha256", &checksum)
+                .and_then(|()| Ok(std::fs::read_to_string(&checksum).map_err(FSError::from)?));
+            let _ = std::fs::remove_file(&checksum);
+            downloaded?
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        };
+        let actual = sha256(target)?;
+        if !actual.eq_ignore_ascii_case(&expected) {
+            return Err(SelfUpdateError::ChecksumMismatch { expected, actual });
+        }
+
+        if let Some((allowed_signers, identity)) = &self.signature {
+            let signature = sibling(target, ".sig");
+            let verified = self
+                .download(release, ".sig", &signature)
+                .and_then(|()| verify_signature(target, &signature, allowed_signers, identity));
+            let _ = std::fs::remove_file(&signature);
+            verified?;
+        }
+        Ok(())
+    }
+
+    /// Make the verified binary at `new` executable, smoke test it and move it to
+    /// `executable`, keeping the previous version next to it.
+    fn install(&self, new: &std::path::Path, executable: &std::path::Path) -> SelfUpdateResult<()> {
+        let permissions = std::fs::metadata(executable)
+            .map_err(FSError::from)?
+            .permissions();
+        std::fs::set_permissions(new, permissions).map_err(FSError::from)?;
+
+        if let Some(arguments) = &self.smoke_test {
+            Command::new(new.to_string_lossy())
+                .args(arguments)
+                .run()
+                .map_err(|error| SelfUpdateError::SmokeTest(error.to_string()))?;
+        }
+
+        // The previous version is linked (or copied) first, so the executable is
+        // replaced with a single rename and never missing.
+        let previous = sibling(executable, PREVIOUS_EXTENSION);
+        let _ = std::fs::remove_file(&previous);
+        std::fs::hard_link(executable, &previous)
+            .or_else(|_| std::fs::copy(executable, &previous).map(|_| ()))
+            .map_err(FSError::from)?;
+        std::fs::rename(new, executable).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    /// Install the latest version if it is newer than the running one. The new
+    /// binary is downloaded next to the executable, verified, optionally smoke
+    /// tested and then moved over the executable in one step. The previous version
+    /// is kept for [`Updater::rollback`].
+    ///
+    /// On Unix, the running program keeps running the previous version until it is
+    /// restarted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the latest release cannot be looked up or downloaded, if
+    /// it fails verification or its smoke test, or if the executable cannot be
+    /// replaced. The executable is left untouched in all these cases.
+    pub fn apply(&self) -> SelfUpdateResult<Outcome> {
+        let latest = self.latest()?;
+        if !is_newer(&latest.version, &self.current)? {
+            log::debug!("Version {} is up to date", self.current);
+            return Ok(Outcome::UpToDate(self.current.clone()));
+        }
+
+        let executable = self.executable_path()?;
+        log::info!(
+            "Updating '{}' from version {} to {}",
+            executable.display(),
+            self.current,
+            latest.version
+        );
+        let file_name = executable
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let new = executable.with_file_name(format!(".{file_name}.{}.new", std::process::id()));
+        let installed = self
+            .fetch(&latest, &new)
+            .and_then(|()| self.install(&new, &executable));
+        if installed.is_err() {
+            let _ = std::fs::remove_file(&new);
+        }
+        installed?;
+        Ok(Outcome::Updated {
+            from: self.current.clone(),
+            to:   latest.version,
+        })
+    }
+
+    /// Go back to the version that was replaced by the last [`Updater::apply`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no previous version or it cannot be restored.
+    pub fn rollback(&self) -> SelfUpdateResult<()> {
+        let executable = self.executable_path()?;
+        let previous = sibling(&executable, PREVIOUS_EXTENSION);
+        if !previous.is_file() {
+            return Err(SelfUpdateError::NoPreviousVersion(
+                previous.to_string_lossy().into_owned(),
+            ));
+        }
+        log::info!(
+            "Rolling '{}' back to its previous version",
+            executable.display()
+        );
+        std::fs::rename(&previous, &executable).map_err(FSError::from)?;
+        Ok(())
+    }
+}
+
+/// Install the latest version from `source` over the running executable if it is
+/// newer than `current_version`. See [`Updater`] for more options.
+///
+/// # Errors
+///
+/// Returns an error if updating fails, see [`Updater::apply`].
+pub fn check_and_apply(source: Source, current_version: &str) -> SelfUpdateResult<Outcome> {
+    Updater::new(source, current_version).apply()
+}
+
+/// The HTTP agent for manifests and their binaries.
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build()
+}
+
+/// The path of `path` with `suffix` appended.
+fn sibling(path: &std::path::Path, suffix: &str) -> std::path::PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    sibling.into()
+}
+
+/// Compute the SHA-256 checksum of the file at `path`, hex-encoded.
+fn sha256(path: &std::path::Path) -> SelfUpdateResult<String> {
+    use sha2::Digest as _;
+    use std::fmt::Write as _;
+
+    let mut hasher = sha2::Sha256::new();
+    let mut file = std::fs::File::open(path).map_err(FSError::from)?;
+    std::io::copy(&mut file, &mut hasher).map_err(FSError::from)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        }))
+}
+
+/// Verify the SSH signature of the file at `path` with `ssh-keygen -Y verify`.
+fn verify_signature(
+    path: &std::path::Path,
+    signature: &std::path::Path,
+    allowed_signers: &std::path::Path,
+    identity: &str,
+) -> SelfUpdateResult<()> {
+    let input = std::fs::File::open(path).map_err(FSError::from)?;
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-n", "file", "-f"])
+        .arg(allowed_signers.to_string_lossy())
+        .arg("-I")
+        .arg(identity)
+        .arg("-s")
+        .arg(signature.to_string_lossy())
+        .start(|command| {
+            command
+                .stdin(input)
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped());
+        })
+        .and_then(crate::process::Running::wait_with_output)
+        .map_err(|error| SelfUpdateError::InvalidSignature(error.to_string()))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(SelfUpdateError::InvalidSignature(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Split a version like `v1.2.3-rc.1+build` into its numeric components and its
+/// pre-release part. Build metadata is ignored.
+fn parse_version(version: &str) -> SelfUpdateResult<(Vec<u64>, Option<&str>)> {
+    let invalid = || SelfUpdateError::InvalidVersion(version.to_string());
+    let trimmed = version.trim_start_matches('v');
+    let trimmed = trimmed
+        .split_once('+')
+        .map_or(trimmed, |(version, _)| version);
+    let (numbers, pre_release) = match trimmed.split_once('-') {
+        Some((numbers, pre_release)) => (numbers, Some(pre_release)),
+        None => (trimmed, None),
+    };
+    let numbers = numbers
+        .split('.')
+        .map(|number| number.parse().map_err(|_| invalid()))
+        .collect::<SelfUpdateResult<Vec<u64>>>()?;
+    Ok((numbers, pre_release))
+}
+
+/// Whether `candidate` is a newer version than `current`. Missing components count
+/// as zero, and a pre-release is older than the release it precedes.
+fn is_newer(candidate: &str, current: &str) -> SelfUpdateResult<bool> {
+    let (candidate_numbers, candidate_pre_release) = parse_version(candidate)?;
+    let (current_numbers, current_pre_release) = parse_version(current)?;
+    let length = candidate_numbers.len().max(current_numbers.len());
+    let padded = |numbers: &[u64]| {
+        let mut padded = numbers.to_vec();
+        padded.resize(length, 0);
+        padded
+    };
+    Ok(
+        match padded(&candidate_numbers).cmp(&padded(&current_numbers)) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => match (candidate_pre_release, current_pre_release) {
+                (None, Some(_)) => true,
+                (Some(candidate), Some(current)) => candidate > current,
+                (None | Some(_), None) => false,
+            },
+        },
+    )
+}
+
+#[cfg(test)]
+mod selfupdate_test {
+    use super::*;
+
+    /// Write a shell script that prints `version`.
+    fn script(path: &std::path::Path, version: &str) {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        std::fs::write(path, format!("#!/bin/sh\necho {version}\n")).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Run `ssh-keygen` with `arguments`.
+    fn ssh_keygen(arguments: &[&str]) { Command::new("ssh-keygen").args(arguments).run().unwrap(); }
+
+    #[test]
+    fn versions() -> SelfUpdateResult<()> {
+        assert!(is_newer("1.2.0", "1.1.9")?);
+        assert!(is_newer("v1.10", "1.9.3")?);
+        assert!(!is_newer("1.2", "1.2.0")?);
+        assert!(!is_newer("1.2.0+build.5", "1.2.0")?);
+        assert!(is_newer("1.2.0", "1.2.0-rc.1")?);
+        assert!(is_newer("1.2.0-rc.2", "1.2.0-rc.1")?);
+        assert!(!is_newer("1.2.0-rc.1", "1.2.0")?);
+        assert_eq!(
+            is_newer("latest", "1.0.0"),
+            Err(SelfUpdateError::InvalidVersion(String::from("latest")))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn update_and_roll_back() -> SelfUpdateResult<()> {
+        let releases = crate::fs::generate_test_path();
+        std::fs::create_dir_all(&releases).unwrap();
+        let binary = releases.join("tool-2.0.0");
+        script(&binary, "2.0.0");
+
+        // Sign the new version.
+        let key = releases.join("key");
+        ssh_keygen(&[
+            "-q",
+            "-t",
+            "ed25519",
+            "-N",
+            "",
+            "-C",
+            "",
+            "-f",
+            &key.to_string_lossy(),
+        ]);
+        ssh_keygen(&[
+            "-q",
+            "-Y",
+            "sign",
+            "-n",
+            "file",
+            "-f",
+            &key.to_string_lossy(),
+            &binary.to_string_lossy(),
+        ]);
+        let allowed_signers = releases.join("allowed_signers");
+        let public_key = std::fs::read_to_string(sibling(&key, ".pub")).unwrap();
+        std::fs::write(
+            &allowed_signers,
+            format!("release@example.com {public_key}"),
+        )
+        .unwrap();
+
+        let manifest = |sha256: &str| {
+            let manifest =
+                serde_json::json!({ "version": "2.0.0", "url": "tool-2.0.0", "sha256": sha256 });
+            std::fs::write(releases.join("latest.json"), manifest.to_string()).unwrap();
+        };
+        manifest(&"0".repeat(64));
+        let server = crate::net::FileServer::new(&releases, "127.0.0.1:0")
+            .start()
+            .unwrap();
+        let source = Source::Manifest(format!("http://{}/latest.json", server.local_addr()));
+
+        let installed = crate::fs::generate_test_path();
+        script(&installed, "1.0.0");
+        let updater = Updater::new(source.clone(), "1.0.0")
+            .executable(&installed)
+            .verify_signature(&allowed_signers, "release@example.com")
+            .smoke_test(["--version"]);
+        assert_eq!(updater.check()?.as_deref(), Some("2.0.0"));
+
+        // Nothing changes if the checksum does not match.
+        assert!(matches!(
+            updater.apply(),
+            Err(SelfUpdateError::ChecksumMismatch { .. })
+        ));
+        let run = || {
+            Command::new(installed.to_string_lossy())
+                .run()
+                .unwrap()
+                .stdout
+        };
+        assert_eq!(run(), "1.0.0\n");
+
+        manifest(&sha256(&binary)?);
+        let wrong_signer = Updater::new(source.clone(), "1.0.0")
+            .executable(&installed)
+            .verify_signature(&allowed_signers, "someone@example.com");
+        assert!(matches!(
+            wrong_signer.apply(),
+            Err(SelfUpdateError::InvalidSignature(_))
+        ));
+
+        assert_eq!(
+            updater.apply()?,
+            Outcome::Updated {
+                from: String::from("1.0.0"),
+                to:   String::from("2.0.0"),
+            }
+        );
+        assert_eq!(run(), "2.0.0\n");
+        assert_eq!(
+            Updater::new(source, "2.0.0")
+                .executable(&installed)
+                .apply()?,
+            Outcome::UpToDate(String::from("2.0.0"))
+        );
+        // Only the executable and its previous version are left.
+        let leftovers = std::fs::read_dir(installed.parent().unwrap())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".new"))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        updater.rollback()?;
+        assert_eq!(run(), "1.0.0\n");
+        assert!(matches!(
+            updater.rollback(),
+            Err(SelfUpdateError::NoPreviousVersion(_))
+        ));
+
+        std::fs::remove_file(installed).unwrap();
+        std::fs::remove_dir_all(releases).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+//! This module contains functionality for the small state files recurring scripts
+//! keep between runs, e.g. when something last succeeded or which items were seen
+//! already.
+//!
+//! A [`StateFile`] stores a serializable value as JSON, together with the version
+//! of its schema. Updates are done under a [`NetLock`], so concurrent runs (even on
+//! different hosts sharing the file) do not lose each other's changes, and the file
+//! is replaced atomically, so an interrupted run never leaves a truncated file
+//! behind.
+//!
+//! ```no_run
+//! # use rush::state::StateFile;
+//! #[derive(Default, serde::Serialize, serde::Deserialize)]
+//! struct Seen {
+//!     ids: Vec<u64>,
+//! }
+//!
+//! let state = StateFile::<Seen>::new("/var/lib/my-script/seen.json");
+//! let new = state.update(|seen| {
+//!     let new = !seen.ids.contains(&42);
+//!     seen.ids.push(42);
+//!     new
+//! });
+//! ```
+
+use crate::{
+    fs::{
+        FSError,
+        FSResult,
+    },
+    lock::{
+        LockError,
+        NetLock,
+    },
+};
+
+/// Describes possible errors when dealing with state files.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum StateError {
+    #[error("The state file is not valid: {0}")]
+    Invalid(String),
+    #[error("Migrating the state from version {0} failed: {1}")]
+    Migration(u32, String),
+    #[error("Locking the state file failed: {0}")]
+    Lock(#[from] LockError),
+    #[error("Accessing the state file failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`StateError`].
+pub type StateResult<T> = Result<T, StateError>;
+
+/// How long [`StateFile::update`] waits for the lock by default, in seconds.
+const DEFAULT_LOCK_TIMEOUT_SECONDS: u64 = 30;
+
+/// The content of a state file.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Content<T> {
+    /// The version of the schema of `data`
+    version: u32,
+    /// The state itself
+    data:    T,
+}
+
+/// Turns the state of one schema version into the state of the next one.
+type Migration = dyn Fn(serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync;
+
+/// A JSON file holding a value of type `T` across runs. See the module
+/// documentation for an example.
+pub struct StateFile<T> {
+    /// The state file
+    path:         std::path::PathBuf,
+    /// The current version of the schema
+    version:      u32,
+    /// The migrations, by the version they migrate from
+    migrations:   std::collections::BTreeMap<u32, Box<Migration>>,
+    /// How long to wait for the lock in [`StateFile::update`]
+    lock_timeout: std::time::Duration,
+    /// The type of the state
+    state:        std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> std::fmt::Debug for StateFile<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateFile")
+            .field("path", &self.path)
+            .field("version", &self.version)
+            .field("migrations", &self.migrations.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> StateFile<T>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + Default,
+{
+    /// Describe the state file at `path`, whose schema has version 1.
+    pub fn new(path: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            path:         path.as_ref().to_path_buf(),
+            version:      1,
+            migrations:   std::collections::BTreeMap::new(),
+            lock_timeout: std::time::Duration::from_secs(DEFAULT_LOCK_TIMEOUT_SECONDS),
+            state:        std::marker::PhantomData,
+        }
+    }
+
+    /// The schema of `T` has version `version`. Increase it whenever `T` changes
+    /// incompatibly, and register a migration from the previous version.
+    #[must_use]
+    pub const fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Use `migration` to turn state of version `from` into state of version
+    /// `from + 1`. Migrations run one after another when older state is loaded, on
+    /// the JSON representation of the state.
+    #[must_use]
+    pub fn migration<F>(mut self, from: u32, migration: F) -> Self
+    where
+        F: Fn(serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync + 'static,
+    {
+        self.migrations.insert(from, Box::new(migration));
+        self
+    }
+
+    /// Wait at most `timeout` for the lock in [`StateFile::update`].
+    #[must_use]
+    pub const fn lock_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Load the state, migrating it from older versions if needed. A missing file
+    /// yields the default state, which is what the first run of a script sees.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, if it was written by a
+    /// newer version of the schema or if a migration fails.
+    pub fn load(&self) -> StateResult<T> {
+        let json = match std::fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+            Err(error) => return Err(FSError::from(error).into()),
+        };
+        let Content { version, mut data } =
+            serde_json::from_str::<Content<serde_json::Value>>(&json)
+                .map_err(|error| StateError::Invalid(error.to_string()))?;
+        if version > self.version {
+            return Err(StateError::Invalid(format!(
+                "version {version} is newer than the supported version {}",
+                self.version
+            )));
+        }
+
+        for from in version..self.version {
+            let migration = self.migrations.get(&from).ok_or_else(|| {
+                StateError::Migration(from, String::from("no migration is registered"))
+            })?;
+            log::debug!(
+                "Migrating state '{}' from version {from}",
+                self.path.to_string_lossy()
+            );
+            data = migration(data).map_err(|error| StateError::Migration(from, error))?;
+        }
+        serde_json::from_value(data).map_err(|error| StateError::Invalid(error.to_string()))
+    }
+
+    /// Replace the state file with `data` atomically.
+    fn store(&self, data: &T) -> StateResult<()> {
+        use std::io::Write as _;
+
+        let json = serde_json::to_string_pretty(&Content {
+            version: self.version,
+            data,
+        })
+        .map_err(|error| StateError::Invalid(error.to_string()))?;
+        crate::fs::write_atomic_with(&self.path, Some(0o600), |writer| -> FSResult<()> {
+            Ok(writer.write_all(json.as_bytes())?)
+        })?;
+        Ok(())
+    }
+
+    /// Load the state, let `update` change it and write it back, all while holding
+    /// a lock on `<path>.lock`. Returns what `update` returns. The file is written
+    /// with the current schema version, only readable by its owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock cannot be acquired in time, if loading the state
+    /// fails (see [`StateFile::load`]) or if writing it fails.
+    pub fn update<R>(&self, update: impl FnOnce(&mut T) -> R) -> StateResult<R> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(FSError::from)?;
+        }
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+        let _guard = NetLock::new(lock_path)
+            .poll_interval(std::time::Duration::from_millis(50))
+            .acquire(self.lock_timeout)?;
+
+        let mut data = self.load()?;
+        let result = update(&mut data);
+        self.store(&data)?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod state_test {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    struct Counter {
+        runs: u64,
+    }
+
+    #[test]
+    fn concurrent_updates() -> StateResult<()> {
+        let path = crate::fs::generate_test_path();
+        let state = StateFile::<Counter>::new(&path);
+        assert_eq!(state.load()?, Counter::default());
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        state.update(|counter| counter.runs += 1).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(state.load()?, Counter { runs: 20 });
+
+        std::fs::remove_file(path).map_err(FSError::from)?;
+        Ok(())
+    }
+
+    #[test]
+    fn migrations() -> StateResult<()> {
+        let path = crate::fs::generate_test_path();
+        std::fs::write(&path, r#"{ "version": 1, "data": { "count": 3 } }"#)
+            .map_err(FSError::from)?;
+
+        let state = StateFile::<Counter>::new(&path)
+            .version(2)
+            .migration(1, |mut data| {
+                let count = data
+                    .as_object_mut()
+                    .and_then(|data| data.remove("count"))
+                    .ok_or_else(|| String::from("'count' is missing"))?;
+                Ok(serde_json::json!({ "runs": count }))
+            });
+        assert_eq!(state.update(|counter| counter.runs)?, 3);
+        assert!(std::fs::read_to_string(&path)
+            .map_err(FSError::from)?
+            .contains("\"version\": 2"));
+
+        assert_eq!(
+            StateFile::<Counter>::new(&path).load(),
+            Err(StateError::Invalid(String::from(
+                "version 2 is newer than the supported version 1"
+            )))
+        );
+        assert!(matches!(
+            StateFile::<Counter>::new(&path).version(3).load(),
+            Err(StateError::Migration(2, _))
+        ));
+
+        std::fs::remove_file(path).map_err(FSError::from)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! This module contains functionality for keeping structured data in a database of
+//! [SQLite](https://sqlite.org), for scripts whose state outgrows flat files (e.g.
+//! an inventory or the history of runs).
+//!
+//! ```no_run
+//! # use rush::{fs::{File, Object as _}, store::{params, Sqlite}};
+//! let mut store = Sqlite::open(File::new("/var/lib/my-script/history.db")).unwrap();
+//! store
+//!     .migrate(&["CREATE TABLE runs (started INTEGER NOT NULL, status TEXT NOT NULL);"])
+//!     .unwrap();
+//! store
+//!     .execute("INSERT INTO runs VALUES (?1, ?2)", params![1_700_000_000, "ok"])
+//!     .unwrap();
+//! let failed: Vec<i64> = store
+//!     .query_map("SELECT started FROM runs WHERE status != 'ok'", [], |row| row.get(0))
+//!     .unwrap();
+//! ```
+
+use crate::fs::{
+    FSError,
+    File,
+    Object as _,
+};
+pub use rusqlite::{
+    params,
+    Params,
+    Row,
+};
+
+/// Describes possible errors when dealing with the store.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum StoreError {
+    #[error("The database operation failed: {0}")]
+    Sqlite(String),
+    #[error("Migration {0} failed: {1}")]
+    Migration(u32, String),
+    #[error("The database has schema version {0}, which is newer than this program knows")]
+    NewerSchema(u32),
+    #[error("Accessing the database file failed: {0}")]
+    FS(#[from] FSError),
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(error: rusqlite::Error) -> Self { Self::Sqlite(error.to_string()) }
+}
+
+/// A [`Result`] whose error variant is a [`StoreError`].
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// How long to wait for other connections to release the database, in seconds.
+const BUSY_TIMEOUT_SECONDS: u64 = 5;
+
+/// A connection to a database of [SQLite](https://sqlite.org). See the module
+/// documentation for an example.
+#[derive(Debug)]
+pub struct Sqlite {
+    /// The connection
+    connection: rusqlite::Connection,
+    /// The database file, if the database is not in memory
+    file:       Option<File>,
+}
+
+impl Sqlite {
+    /// Apply the settings every connection uses: waiting for other connections
+    /// instead of failing right away and enforcing foreign keys.
+    fn configure(connection: rusqlite::Connection, file: Option<File>) -> StoreResult<Self> {
+        connection.busy_timeout(std::time::Duration::from_secs(BUSY_TIMEOUT_SECONDS))?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        Ok(Self { connection, file })
+    }
+
+    /// Open the database in `file`, creating it (and its parent directories) if it
+    /// does not exist. The database uses write-ahead logging, so readers do not
+    /// block the writer, which suits several scripts sharing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or if the file cannot be
+    /// opened as a database.
+    pub fn open(file: File) -> StoreResult<Self> {
+        if let Some(parent) = file.path().parent() {
+            std::fs::create_dir_all(parent).map_err(FSError::from)?;
+        }
+        log::trace!("Opening database {}", file);
+        let connection = rusqlite::Connection::open(file.path())?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::configure(connection, Some(file))
+    }
+
+    /// Open a database that only lives in memory, e.g. for tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be allocated.
+    pub fn in_memory() -> StoreResult<Self> {
+        Self::configure(rusqlite::Connection::open_in_memory()?, None)
+    }
+
+    /// The database file, or [`None`] if the database lives in memory.
+    #[must_use]
+    pub const fn file(&self) -> Option<&File> { self.file.as_ref() }
+
+    /// The schema version, i.e. how many migrations were applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the version fails.
+    pub fn schema_version(&self) -> StoreResult<u32> {
+        Ok(self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    /// Bring the schema up to date. `migrations` are SQL scripts (usually embedded
+    /// with [`include_str!`]); the first one creates the initial schema and every
+    /// further one changes it. Only ever append migrations. Those not applied yet
+    /// run in order, each in its own transaction together with recording the new
+    /// schema version in `PRAGMA user_version`. Returns how many were applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a migration fails (the migrations before it stay
+    /// applied) or if the database has more migrations applied than given.
+    pub fn migrate(&mut self, migrations: &[&str]) -> StoreResult<usize> {
+        let version = self.schema_version()?;
+        let known = u32::try_from(migrations.len()).unwrap_or(u32::MAX);
+        if version > known {
+            return Err(StoreError::NewerSchema(version));
+        }
+
+        let pending = migrations
+            .get(usize::try_from(version).unwrap_or(usize::MAX)..)
+            .unwrap_or_default();
+        for (number, migration) in (version + 1..).zip(pending) {
+            log::debug!("Applying database migration {number}");
+            let to_error =
+                |error: rusqlite::Error| StoreError::Migration(number, error.to_string());
+            let transaction = self.connection.transaction().map_err(to_error)?;
+            transaction.execute_batch(migration).map_err(to_error)?;
+            transaction
+                .pragma_update(None, "user_version", number)
+                .map_err(to_error)?;
+            transaction.commit().map_err(to_error)?;
+        }
+        Ok(pending.len())
+    }
+
+    /// Run the SQL statement `sql` with `params` and return how many rows it
+    /// changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statement is invalid or fails.
+    pub fn execute(&self, sql: &str, params: impl Params) -> StoreResult<usize> {
+        Ok(self.connection.execute(sql, params)?)
+    }
+
+    /// Run the SQL statements `sql`, which take no parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a statement is invalid or fails.
+    pub fn execute_batch(&self, sql: &str) -> StoreResult<()> {
+        Ok(self.connection.execute_batch(sql)?)
+    }
+
+    /// Run the query `sql` with `params` and turn every resulting row into a value
+    /// with `map`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query is invalid or fails, or if `map` fails.
+    pub fn query_map<T>(
+        &self,
+        sql: &str,
+        params: impl Params,
+        map: impl FnMut(&Row<'_>) -> rusqlite::Result<T>,
+    ) -> StoreResult<Vec<T>> {
+        let mut statement = self.connection.prepare(sql)?;
+        let rows = statement.query_map(params, map)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Run the query `sql` with `params` and turn the first resulting row into a
+    /// value with `map`. Returns [`None`] if there is no row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query is invalid or fails, or if `map` fails.
+    pub fn query_one<T>(
+        &self,
+        sql: &str,
+        params: impl Params,
+        map: impl FnOnce(&Row<'_>) -> rusqlite::Result<T>,
+    ) -> StoreResult<Option<T>> {
+        use rusqlite::OptionalExtension as _;
+
+        Ok(self.connection.query_row(sql, params, map).optional()?)
+    }
+
+    /// Run `body` in a transaction, which is committed if `body` succeeds and rolled
+    /// back otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `body`, or an error if the transaction cannot be started
+    /// or committed.
+    pub fn transaction<R>(&mut self, body: impl FnOnce(&Self) -> StoreResult<R>) -> StoreResult<R> {
+        self.connection.execute_batch("BEGIN IMMEDIATE")?;
+        match body(self) {
+            Ok(result) => {
+                self.connection.execute_batch("COMMIT")?;
+                Ok(result)
+            },
+            Err(error) => {
+                if let Err(rollback) = self.connection.execute_batch("ROLLBACK") {
+                    log::warn!("Rolling back the transaction failed: {rollback}");
+                }
+                Err(error)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod store_test {
+    use super::*;
+
+    /// The migrations of the test schema.
+    const MIGRATIONS: [&str; 2] = [
+        "CREATE TABLE hosts (name TEXT PRIMARY KEY);",
+        "ALTER TABLE hosts ADD COLUMN cores INTEGER NOT NULL DEFAULT 1;",
+    ];
+
+    #[test]
+    fn migrate_and_query() -> StoreResult<()> {
+        let path = crate::fs::generate_test_path();
+        let mut writer = Sqlite::open(File::new(&path))?;
+        assert_eq!(writer.migrate(&MIGRATIONS[..1])?, 1);
+        assert_eq!(writer.migrate(&MIGRATIONS)?, 1);
+        assert_eq!(writer.migrate(&MIGRATIONS)?, 0);
+        assert_eq!(writer.schema_version()?, 2);
+        assert_eq!(
+            writer.migrate(&MIGRATIONS[..1]),
+            Err(StoreError::NewerSchema(2))
+        );
+
+        writer.execute("INSERT INTO hosts VALUES (?1, ?2)", params!["db", 8])?;
+        let rolled_back = writer.transaction(|store| {
+            store.execute("INSERT INTO hosts (name) VALUES ('web')", [])?;
+            store.execute("INSERT INTO hosts (name) VALUES ('db')", [])
+        });
+        assert!(matches!(rolled_back, Err(StoreError::Sqlite(_))));
+
+        let store = Sqlite::open(File::new(&path))?;
+        let hosts: Vec<(String, u32)> =
+            store.query_map("SELECT name, cores FROM hosts", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        assert_eq!(hosts, [(String::from("db"), 8)]);
+        assert_eq!(
+            store.query_one("SELECT cores FROM hosts WHERE name = ?1", ["web"], |row| {
+                row.get::<_, u32>(0)
+            })?,
+            None
+        );
+        Ok(())
+    }
+}
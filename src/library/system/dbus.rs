@@ -0,0 +1,328 @@
+//! This module contains simple helpers for talking to common services over D-Bus
+//! (systemd, logind, `NetworkManager`). All calls go through `busctl`, so no D-Bus
+//! library is linked into the program.
+
+use crate::process::{
+    Command,
+    ProcessError,
+};
+
+/// Describes possible errors when talking to services over D-Bus.
+#[derive(Debug, thiserror::Error)]
+pub enum DBusError {
+    #[error("Running busctl failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("The reply could not be parsed: {0}")]
+    InvalidReply(String),
+}
+
+/// A [`Result`] whose error variant is a [`DBusError`].
+pub type DBusResult<T> = Result<T, DBusError>;
+
+/// The bus a call is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bus {
+    System,
+    User,
+}
+
+impl Bus {
+    /// The `busctl` flag that selects this bus.
+    const fn flag(self) -> &'static str {
+        match self {
+            Self::System => "--system",
+            Self::User => "--user",
+        }
+    }
+}
+
+/// Describes the object and interface a call or property lookup is directed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Destination<'a> {
+    /// The bus the service lives on
+    pub bus:       Bus,
+    /// The well-known name of the service, e.g. `org.freedesktop.systemd1`
+    pub service:   &'a str,
+    /// The object path, e.g. `/org/freedesktop/systemd1`
+    pub path:      &'a str,
+    /// The interface, e.g. `org.freedesktop.systemd1.Manager`
+    pub interface: &'a str,
+}
+
+/// The systemd manager object on the system bus.
+pub const SYSTEMD: Destination<'static> = Destination {
+    bus:       Bus::System,
+    service:   "org.freedesktop.systemd1",
+    path:      "/org/freedesktop/systemd1",
+    interface: "org.freedesktop.systemd1.Manager",
+};
+
+/// The logind manager object on the system bus.
+pub const LOGIND: Destination<'static> = Destination {
+    bus:       Bus::System,
+    service:   "org.freedesktop.login1",
+    path:      "/org/freedesktop/login1",
+    interface: "org.freedesktop.login1.Manager",
+};
+
+/// The `NetworkManager` object on the system bus.
+pub const NETWORK_MANAGER: Destination<'static> = Destination {
+    bus:       Bus::System,
+    service:   "org.freedesktop.NetworkManager",
+    path:      "/org/freedesktop/NetworkManager",
+    interface: "org.freedesktop.NetworkManager",
+};
+
+/// Extract the `data` member of a reply that `busctl --json=short` printed.
+fn parse_reply(reply: &str) -> DBusResult<serde_json::Value> {
+    let mut value: serde_json::Value = serde_json::from_str(reply.trim())
+        .map_err(|error| DBusError::InvalidReply(error.to_string()))?;
+    value
+        .get_mut("data")
+        .map(serde_json::Value::take)
+        .ok_or_else(|| DBusError::InvalidReply(format!("no 'data' member in '{}'", reply.trim())))
+}
+
+/// Call `method` on `destination`.
+///
+/// The `signature` describes the `arguments` in
+/// D-Bus type notation (e.g. `"ss"` for two strings) and must be empty if there are
+/// no arguments. The return values are returned as a JSON array.
+///
+/// # Errors
+///
+/// Returns an error if `busctl` fails (e.g. because the method call failed) or if
+/// the reply cannot be parsed.
+pub fn call(
+    destination: &Destination,
+    method: &str,
+    signature: &str,
+    arguments: &[&str],
+) -> DBusResult<serde_json::Value> {
+    log::trace!(
+        "Calling D-Bus method {}.{} on {}",
+        destination.interface,
+        method,
+        destination.path
+    );
+    let mut command = Command::new("busctl")
+        .args([destination.bus.flag(), "--json=short", "call"])
+        .args([
+            destination.service,
+            destination.path,
+            destination.interface,
+            method,
+        ]);
+    if !signature.is_empty() {
+        command = command.arg(signature).args(arguments);
+    }
+    parse_reply(&command.run()?.stdout)
+}
+
+/// Read the property `property` of `destination`.
+///
+/// # Errors
+///
+/// Returns an error if `busctl` fails (e.g. because the property does not exist) or
+/// if the reply cannot be parsed.
+pub fn get_property(destination: &Destination, property: &str) -> DBusResult<serde_json::Value> {
+    log::trace!(
+        "Reading D-Bus property {}.{} on {}",
+        destination.interface,
+        property,
+        destination.path
+    );
+    let output = Command::new("busctl")
+        .args([destination.bus.flag(), "--json=short", "get-property"])
+        .args([
+            destination.service,
+            destination.path,
+            destination.interface,
+            property,
+        ])
+        .run()?;
+    parse_reply(&output.stdout)
+}
+
+/// Query the `ActiveState` of a systemd unit, e.g. `"active"` or `"failed"`.
+///
+/// # Errors
+///
+/// Returns an error if the unit cannot be loaded or the reply cannot be parsed.
+pub fn unit_state(unit: &str) -> DBusResult<String> {
+    let reply = call(&SYSTEMD, "LoadUnit", "s", &[unit])?;
+    let path = reply
+        .get(0)
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| DBusError::InvalidReply(format!("no object path in '{reply}'")))?;
+
+    let unit_object = Destination {
+        bus: Bus::System,
+        service: SYSTEMD.service,
+        path,
+        interface: "org.freedesktop.systemd1.Unit",
+    };
+    get_property(&unit_object, "ActiveState")?
+        .as_str()
+        .map(ToString::to_string)
+        .ok_or_else(|| DBusError::InvalidReply(String::from("ActiveState is not a string")))
+}
+
+/// The overall connectivity state reported by `NetworkManager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkState {
+    Unknown,
+    Asleep,
+    Disconnected,
+    Disconnecting,
+    Connecting,
+    ConnectedLocal,
+    ConnectedSite,
+    ConnectedGlobal,
+}
+
+impl From<u64> for NetworkState {
+    fn from(state: u64) -> Self {
+        match state {
+            10 => Self::Asleep,
+            20 => Self::Disconnected,
+            30 => Self::Disconnecting,
+            40 => Self::Connecting,
+            50 => Self::ConnectedLocal,
+            60 => Self::ConnectedSite,
+            70 => Self::ConnectedGlobal,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Query the connectivity state from `NetworkManager`.
+///
+/// # Errors
+///
+/// Returns an error if `NetworkManager` is not running or the reply cannot be parsed.
+pub fn network_state() -> DBusResult<NetworkState> {
+    get_property(&NETWORK_MANAGER, "State")?
+        .as_u64()
+        .map(NetworkState::from)
+        .ok_or_else(|| DBusError::InvalidReply(String::from("State is not a number")))
+}
+
+/// How long `systemd-inhibit` is given to fail (e.g. because logind cannot be
+/// reached) before the lock is considered taken.
+const INHIBIT_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Holds a logind inhibitor lock. The lock is released when this value is dropped.
+///
+/// logind hands out inhibitor locks as file descriptors that must stay open, which
+/// `busctl` cannot do. Hence, the lock is held by a `systemd-inhibit` child process
+/// running `cat`, which exits (and thereby releases the lock) once its standard
+/// input is closed.
+#[derive(Debug)]
+pub struct InhibitGuard {
+    /// The `systemd-inhibit` process that holds the lock
+    child: crate::process::Running,
+}
+
+impl InhibitGuard {
+    /// Start `command`, which holds a lock until its standard input is closed, and
+    /// make sure it does not exit within [`INHIBIT_GRACE_PERIOD`].
+    fn hold(command: &Command) -> DBusResult<Self> {
+        use std::io::Read;
+
+        let mut child = command.start(|command| {
+            command
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::piped());
+        })?;
+
+        let started = std::time::Instant::now();
+        while started.elapsed() < INHIBIT_GRACE_PERIOD {
+            if let Some(status) = child.try_wait()? {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.take_stderr() {
+                    let _ = pipe.read_to_string(&mut stderr);
+                }
+                return Err(ProcessError::Failed {
+                    code:   status.code(),
+                    stderr: stderr.trim().to_string(),
+                }
+                .into());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        Ok(Self { child })
+    }
+}
+
+impl Drop for InhibitGuard {
+    fn drop(&mut self) {
+        log::trace!("Releasing inhibitor lock");
+        // Closing standard input ends `cat`, and `systemd-inhibit` with it.
+        drop(self.child.take_stdin());
+        if let Err(error) = self.child.wait() {
+            log::warn!("Could not release inhibitor lock: {error}");
+        }
+    }
+}
+
+/// Take an inhibitor lock from logind, e.g. to prevent the system from going to
+/// sleep while a maintenance script runs.
+///
+/// `what` is a colon-separated list such as `"sleep:shutdown"`; `application` and
+/// `reason` are shown to users that try to suspend.
+///
+/// # Errors
+///
+/// Returns an error if `systemd-inhibit` cannot be started or exits right away,
+/// e.g. because logind cannot be reached.
+pub fn inhibit(what: &str, application: &str, reason: &str) -> DBusResult<InhibitGuard> {
+    log::debug!("Inhibiting '{what}' on behalf of '{application}'");
+    InhibitGuard::hold(
+        &Command::new("systemd-inhibit")
+            .arg(format!("--what={what}"))
+            .arg(format!("--who={application}"))
+            .arg(format!("--why={reason}"))
+            .args(["--mode=block", "cat"]),
+    )
+}
+
+#[cfg(test)]
+mod dbus_test {
+    use super::*;
+
+    #[test]
+    fn parse() -> DBusResult<()> {
+        let data = parse_reply("{\"type\":\"s\",\"data\":\"active\"}\n")?;
+        assert_eq!(data.as_str(), Some("active"));
+
+        let data = parse_reply("{\"type\":\"o\",\"data\":[\"/org/freedesktop/systemd1/unit/a\"]}")?;
+        assert_eq!(data[0].as_str(), Some("/org/freedesktop/systemd1/unit/a"));
+
+        assert!(parse_reply("{\"type\":\"s\"}").is_err());
+        assert!(parse_reply("not json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hold() {
+        assert!(matches!(
+            InhibitGuard::hold(&Command::new("sh").args(["-c", "echo 'no bus' >&2; exit 1"])),
+            Err(DBusError::Process(ProcessError::Failed { code: Some(1), stderr }))
+                if stderr == "no bus"
+        ));
+
+        // Dropping the guard ends the child instead of leaving it behind.
+        let guard = InhibitGuard::hold(&Command::new("cat")).unwrap();
+        let id = guard.child.id();
+        drop(guard);
+        assert!(!std::path::Path::new(&format!("/proc/{id}")).exists());
+    }
+
+    #[test]
+    fn network_state() {
+        assert_eq!(NetworkState::from(70), NetworkState::ConnectedGlobal);
+        assert_eq!(NetworkState::from(5), NetworkState::Unknown);
+    }
+}
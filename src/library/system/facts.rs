@@ -0,0 +1,667 @@
+//! This module contains functionality for gathering facts about the host the program
+//! runs on: operating system, kernel, virtualization, cloud provider, addresses,
+//! memory, disks, GPUs and GPU computing platforms. Roles and templates can branch on
+//! these, similar to what Ansible's `setup` module provides.
+//!
+//! Gathering is best-effort. A fact that cannot be determined is left empty instead
+//! of failing the whole call.
+
+use crate::process::Command;
+
+/// Everything that is known about the host.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Facts {
+    /// The host name as the kernel knows it
+    pub hostname:       String,
+    /// The operating system, from `/etc/os-release`
+    pub os:             OperatingSystem,
+    /// The running kernel
+    pub kernel:         Kernel,
+    /// The virtualization technology the host runs in (as named by
+    /// `systemd-detect-virt`, e.g. `kvm` or `docker`), if any
+    pub virtualization: Option<String>,
+    /// The cloud the host runs in, if any
+    pub cloud:          Option<CloudProvider>,
+    /// All addresses assigned to network interfaces
+    pub addresses:      Vec<Address>,
+    /// Memory and swap
+    pub memory:         Memory,
+    /// Mounted local filesystems
+    pub disks:          Vec<Disk>,
+    /// GPUs and other accelerators, see [`gpus`]
+    pub gpus:           Vec<Gpu>,
+    /// The version of the installed CUDA toolkit, e.g. `12.2.140`
+    pub cuda:           Option<String>,
+    /// The version of the installed `ROCm` platform, e.g. `6.0.2-115`
+    pub rocm:           Option<String>,
+}
+
+/// The operating system as described by `/etc/os-release`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct OperatingSystem {
+    /// The machine-readable name, e.g. `debian`
+    pub id:          String,
+    /// Names of related distributions, e.g. `["debian"]` for Ubuntu
+    pub id_like:     Vec<String>,
+    /// The human-readable name, e.g. `Debian GNU/Linux`
+    pub name:        String,
+    /// The machine-readable version, e.g. `12`
+    pub version_id:  String,
+    /// The name and version for display, e.g. `Debian GNU/Linux 12 (bookworm)`
+    pub pretty_name: String,
+}
+
+/// The running kernel.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Kernel {
+    /// The release, e.g. `6.1.0-18-amd64`
+    pub release:      String,
+    /// The CPU architecture, e.g. `x86_64`
+    pub architecture: String,
+}
+
+/// The clouds that can be detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloudProvider {
+    Aws,
+    Azure,
+    DigitalOcean,
+    Gcp,
+    Hetzner,
+    OpenStack,
+}
+
+/// An address assigned to a network interface.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Address {
+    /// The name of the interface, e.g. `eth0`
+    pub interface:     String,
+    /// The address itself
+    pub address:       std::net::IpAddr,
+    /// The length of the network prefix, e.g. `24`
+    pub prefix_length: u8,
+}
+
+/// Memory and swap, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Memory {
+    /// Physical memory
+    pub total:      u64,
+    /// Memory that can be used without swapping
+    pub available:  u64,
+    /// Swap space
+    pub swap_total: u64,
+    /// Unused swap space
+    pub swap_free:  u64,
+}
+
+/// A mounted local filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Disk {
+    /// The device (or source) that is mounted, e.g. `/dev/sda1`
+    pub device:      String,
+    /// The type of the filesystem, e.g. `ext4`
+    pub filesystem:  String,
+    /// Where the filesystem is mounted
+    pub mount_point: std::path::PathBuf,
+    /// The size, in bytes
+    pub total:       u64,
+    /// The space available to unprivileged users, in bytes
+    pub available:   u64,
+}
+
+/// The companies whose GPUs are told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Other,
+}
+
+impl GpuVendor {
+    /// The vendor with the PCI vendor ID `id`, e.g. `0x10de`.
+    fn from_pci_id(id: &str) -> Self {
+        match id.trim_start_matches("0x") {
+            "10de" => Self::Nvidia,
+            "1002" => Self::Amd,
+            "8086" => Self::Intel,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A GPU or other accelerator on the PCI bus.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Gpu {
+    /// The PCI address, e.g. `0000:01:00.0`
+    pub pci_address:    String,
+    /// The vendor
+    pub vendor:         GpuVendor,
+    /// The PCI vendor and device ID, e.g. `10de:2204`
+    pub pci_id:         String,
+    /// The model, e.g. `NVIDIA GeForce RTX 3090`, if the driver tells
+    pub model:          Option<String>,
+    /// The kernel driver bound to the device, e.g. `nvidia` or `amdgpu`
+    pub driver:         Option<String>,
+    /// The version of the driver, if it tells
+    pub driver_version: Option<String>,
+    /// The memory of the device in bytes, if the driver tells
+    pub memory:         Option<u64>,
+}
+
+/// Gather all facts about the host. This takes up to half a second longer on hosts
+/// that are not recognizably a cloud instance, because the metadata endpoint is
+/// probed.
+#[must_use]
+pub fn facts() -> Facts {
+    log::debug!("Gathering host facts");
+    Facts {
+        hostname:       read("/proc/sys/kernel/hostname"),
+        os:             parse_os_release(&read("/etc/os-release")),
+        kernel:         Kernel {
+            release:      read("/proc/sys/kernel/osrelease"),
+            architecture: std::env::consts::ARCH.to_string(),
+        },
+        virtualization: virtualization(),
+        cloud:          cloud_provider(),
+        addresses:      addresses(),
+        memory:         parse_meminfo(&read("/proc/meminfo")),
+        disks:          disks(),
+        gpus:           gpus(),
+        cuda:           cuda_version(),
+        rocm:           Some(read("/opt/rocm/.info/version")).filter(|version| !version.is_empty()),
+    }
+}
+
+/// Read a small file and trim it. A file that cannot be read is treated as empty.
+fn read(path: impl AsRef<std::path::Path>) -> String {
+    std::fs::read_to_string(path.as_ref()).map_or_else(
+        |error| {
+            log::debug!(
+                "Could not read '{}' for facts: {}",
+                path.as_ref().display(),
+                error
+            );
+            String::new()
+        },
+        |content| content.trim().to_string(),
+    )
+}
+
+/// Parse the `KEY=value` lines of `/etc/os-release`. Values may be quoted; invalid
+/// lines are skipped.
+fn parse_os_release(content: &str) -> OperatingSystem {
+    let mut os = OperatingSystem::default();
+    for line in content.lines() {
+        let Some((key, value)) = rush_core::env::parse_line(line).ok().flatten() else {
+            continue;
+        };
+        match key.as_str() {
+            "ID" => os.id = value,
+            "ID_LIKE" => os.id_like = value.split_whitespace().map(String::from).collect(),
+            "NAME" => os.name = value,
+            "VERSION_ID" => os.version_id = value,
+            "PRETTY_NAME" => os.pretty_name = value,
+            _ => {},
+        }
+    }
+    os
+}
+
+/// Parse `/proc/meminfo`, whose values are given in KiB.
+fn parse_meminfo(content: &str) -> Memory {
+    let mut memory = Memory::default();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(kibibytes) = value
+            .split_whitespace()
+            .next()
+            .and_then(|value| value.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        let bytes = kibibytes * 1024;
+        match key {
+            "MemTotal" => memory.total = bytes,
+            "MemAvailable" => memory.available = bytes,
+            "SwapTotal" => memory.swap_total = bytes,
+            "SwapFree" => memory.swap_free = bytes,
+            _ => {},
+        }
+    }
+    memory
+}
+
+/// Ask `systemd-detect-virt` which virtualization the host runs in.
+fn virtualization() -> Option<String> {
+    // `systemd-detect-virt` prints "none" and exits with 1 on bare metal.
+    let output = Command::new("systemd-detect-virt").output().ok()?;
+    let virtualization = output.stdout.trim();
+    if !output.success() || virtualization.is_empty() || virtualization == "none" {
+        return None;
+    }
+    Some(virtualization.to_string())
+}
+
+/// Recognize the cloud from the DMI data the hypervisor provides. `fields` are the
+/// system vendor, product name, BIOS vendor and chassis asset tag, in this order.
+fn cloud_from_dmi(fields: [&str; 4]) -> Option<CloudProvider> {
+    /// The chassis asset tag of every Azure virtual machine.
+    const AZURE_ASSET_TAG: &str = "7783-7084-3265-9085-8269-3286-77";
+
+    let [system_vendor, product_name, bios_vendor, asset_tag] = fields;
+    if system_vendor.starts_with("Amazon") || bios_vendor.starts_with("Amazon") {
+        Some(CloudProvider::Aws)
+    } else if asset_tag == AZURE_ASSET_TAG {
+        Some(CloudProvider::Azure)
+    } else if system_vendor == "DigitalOcean" {
+        Some(CloudProvider::DigitalOcean)
+    } else if system_vendor == "Google" || product_name == "Google Compute Engine" {
+        Some(CloudProvider::Gcp)
+    } else if system_vendor == "Hetzner" {
+        Some(CloudProvider::Hetzner)
+    } else if product_name.starts_with("OpenStack") {
+        Some(CloudProvider::OpenStack)
+    } else {
+        None
+    }
+}
+
+/// Detect the cloud, first from DMI data and then, if that is inconclusive and the
+/// `http` feature is enabled, by probing the metadata endpoint.
+fn cloud_provider() -> Option<CloudProvider> {
+    let dmi = |name: &str| read(format!("/sys/class/dmi/id/{name}"));
+    let (system_vendor, product_name, bios_vendor, asset_tag) = (
+        dmi("sys_vendor"),
+        dmi("product_name"),
+        dmi("bios_vendor"),
+        dmi("chassis_asset_tag"),
+    );
+    let cloud = cloud_from_dmi([&system_vendor, &product_name, &bios_vendor, &asset_tag]);
+    #[cfg(feature = "http")]
+    let cloud = cloud.or_else(crate::net::cloud_metadata::detect);
+    cloud
+}
+
+/// List the addresses of all interfaces with `ip -json address show`.
+fn addresses() -> Vec<Address> {
+    Command::new("ip")
+        .args(["-json", "address", "show"])
+        .run()
+        .map_or_else(
+            |error| {
+                log::debug!("Could not list addresses for facts: {}", error);
+                Vec::new()
+            },
+            |output| parse_addresses(&output.stdout),
+        )
+}
+
+/// Parse the output of `ip -json address show`.
+fn parse_addresses(json: &str) -> Vec<Address> {
+    let Ok(serde_json::Value::Array(interfaces)) = serde_json::from_str(json) else {
+        return Vec::new();
+    };
+
+    let mut addresses = Vec::new();
+    for interface in &interfaces {
+        let name = interface["ifname"].as_str().unwrap_or_default();
+        for info in interface["addr_info"].as_array().into_iter().flatten() {
+            let address = info["local"]
+                .as_str()
+                .and_then(|address| address.parse().ok());
+            let prefix_length = info["prefixlen"]
+                .as_u64()
+                .and_then(|length| u8::try_from(length).ok());
+            if let (Some(address), Some(prefix_length)) = (address, prefix_length) {
+                addresses.push(Address {
+                    interface: name.to_string(),
+                    address,
+                    prefix_length,
+                });
+            }
+        }
+    }
+    addresses
+}
+
+/// List local, non-virtual filesystems with `df`.
+fn disks() -> Vec<Disk> {
+    Command::new("df")
+        .args(["-P", "-T", "-k", "-l"])
+        .args([
+            "-x", "tmpfs", "-x", "devtmpfs", "-x", "squashfs", "-x", "overlay",
+        ])
+        .output()
+        .map_or_else(
+            |error| {
+                log::debug!("Could not list disks for facts: {}", error);
+                Vec::new()
+            },
+            // `df` exits with 1 if a single filesystem is inaccessible, but still
+            // lists all others.
+            |output| parse_df(&output.stdout),
+        )
+}
+
+/// Describe the filesystem `path` is on, or return [`None`] if `df` cannot tell.
+#[must_use]
+pub fn disk(path: impl AsRef<std::path::Path>) -> Option<Disk> {
+    Command::new("df")
+        .args(["-P", "-T", "-k", "--"])
+        .arg(path.as_ref().to_string_lossy())
+        .run()
+        .map_err(|error| log::debug!("Could not describe the disk of a path: {}", error))
+        .ok()
+        .and_then(|output| parse_df(&output.stdout).pop())
+}
+
+/// Parse the output of `df -P -T -k`: device, type, size, used and available space
+/// (in KiB), capacity and mount point, which may contain spaces.
+fn parse_df(output: &str) -> Vec<Disk> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            if columns.len() < 7 {
+                return None;
+            }
+            Some(Disk {
+                device:      columns[0].to_string(),
+                filesystem:  columns[1].to_string(),
+                mount_point: std::path::PathBuf::from(columns[6..].join(" ")),
+                total:       columns[2].parse::<u64>().ok()? * 1024,
+                available:   columns[4].parse::<u64>().ok()? * 1024,
+            })
+        })
+        .collect()
+}
+
+/// Whether the PCI device class `class` (e.g. `0x030000`) is a display controller
+/// or a processing accelerator.
+fn is_gpu_class(class: &str) -> bool { class.starts_with("0x03") || class.starts_with("0x12") }
+
+/// The GPU described by the sysfs directory `device` of a PCI device, or [`None`]
+/// if the device is no GPU.
+fn gpu_from_sysfs(device: &std::path::Path) -> Option<Gpu> {
+    if !is_gpu_class(&read(device.join("class"))) {
+        return None;
+    }
+    let vendor = read(device.join("vendor"));
+    let driver = std::fs::read_link(device.join("driver"))
+        .ok()
+        .and_then(|link| Some(link.file_name()?.to_string_lossy().into_owned()));
+    let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+    Some(Gpu {
+        pci_address: device.file_name()?.to_string_lossy().into_owned(),
+        vendor: GpuVendor::from_pci_id(&vendor),
+        pci_id: format!(
+            "{}:{}",
+            vendor.trim_start_matches("0x"),
+            read(device.join("device")).trim_start_matches("0x")
+        ),
+        // `amdgpu` names some of its cards.
+        model: non_empty(read(device.join("product_name"))),
+        driver_version: driver
+            .as_ref()
+            .and_then(|driver| non_empty(read(format!("/sys/module/{driver}/version")))),
+        driver,
+        memory: None,
+    })
+}
+
+/// Details `nvidia-smi` knows about a GPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NvidiaGpu {
+    /// The PCI address, e.g. `00000000:01:00.0`
+    bus_id:         String,
+    /// The model
+    name:           String,
+    /// The version of the driver
+    driver_version: String,
+    /// The memory in bytes
+    memory:         Option<u64>,
+}
+
+/// Parse the output of `nvidia-smi --format=csv,noheader,nounits
+/// --query-gpu=pci.bus_id,name,driver_version,memory.total`, whose memory is given
+/// in MiB.
+fn parse_nvidia_smi(output: &str) -> Vec<NvidiaGpu> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [bus_id, name, driver_version, memory] = fields[..] else {
+                return None;
+            };
+            Some(NvidiaGpu {
+                bus_id:         bus_id.to_lowercase(),
+                name:           name.to_string(),
+                driver_version: driver_version.to_string(),
+                memory:         memory
+                    .parse::<u64>()
+                    .ok()
+                    .map(|mebibytes| mebibytes * 1024 * 1024),
+            })
+        })
+        .collect()
+}
+
+/// List the GPUs and other accelerators on the PCI bus, sorted by PCI address.
+///
+/// The vendor, driver and driver version come from sysfs. For NVIDIA GPUs, the
+/// model and memory are asked from `nvidia-smi` if it is installed; other drivers
+/// name only some models.
+#[must_use]
+pub fn gpus() -> Vec<Gpu> {
+    let devices = std::fs::read_dir("/sys/bus/pci/devices").map_or_else(
+        |error| {
+            log::debug!("Could not list PCI devices for facts: {}", error);
+            Vec::new()
+        },
+        |entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect()
+        },
+    );
+    let mut gpus: Vec<Gpu> = devices
+        .iter()
+        .filter_map(|device| gpu_from_sysfs(device))
+        .collect();
+    gpus.sort_by(|first, second| first.pci_address.cmp(&second.pci_address));
+
+    if gpus.iter().any(|gpu| gpu.vendor == GpuVendor::Nvidia) {
+        let details = Command::new("nvidia-smi")
+            .args([
+                "--query-gpu=pci.bus_id,name,driver_version,memory.total",
+                "--format=csv,noheader,nounits",
+            ])
+            .run()
+            .map_or_else(
+                |error| {
+                    log::debug!("Could not query nvidia-smi for facts: {}", error);
+                    Vec::new()
+                },
+                |output| parse_nvidia_smi(&output.stdout),
+            );
+        for gpu in &mut gpus {
+            // `nvidia-smi` uses a domain of eight instead of four digits.
+            let Some(details) = details
+                .iter()
+                .find(|details| details.bus_id.ends_with(&gpu.pci_address))
+            else {
+                continue;
+            };
+            gpu.model = Some(details.name.clone());
+            gpu.driver_version = Some(details.driver_version.clone());
+            gpu.memory = details.memory;
+        }
+    }
+    gpus
+}
+
+/// The CUDA version in `version.json` of a CUDA toolkit.
+fn cuda_version_from_json(json: &str) -> Option<String> {
+    let version: serde_json::Value = serde_json::from_str(json).ok()?;
+    Some(version["cuda"]["version"].as_str()?.to_string())
+}
+
+/// The CUDA version in the output of `nvcc --version`, e.g. `12.2.140` for
+/// `Cuda compilation tools, release 12.2, V12.2.140`.
+fn cuda_version_from_nvcc(output: &str) -> Option<String> {
+    let (_, version) = output.split_once(", V")?;
+    version.split_whitespace().next().map(String::from)
+}
+
+/// The version of the installed CUDA toolkit, from its `version.json` or, for older
+/// toolkits, from `nvcc`.
+fn cuda_version() -> Option<String> {
+    cuda_version_from_json(&read("/usr/local/cuda/version.json")).or_else(|| {
+        Command::new("nvcc")
+            .arg("--version")
+            .run()
+            .ok()
+            .and_then(|output| cuda_version_from_nvcc(&output.stdout))
+    })
+}
+
+#[cfg(test)]
+mod facts_test {
+    use super::*;
+
+    #[test]
+    fn os_release_and_meminfo() {
+        let os = parse_os_release(concat!(
+            "PRETTY_NAME=\"Ubuntu 24.04 LTS\"\n",
+            "NAME=\"Ubuntu\"\n",
+            "VERSION_ID=\"24.04\"\n",
+            "ID=ubuntu\n",
+            "ID_LIKE=debian\n",
+            "# a comment\n",
+        ));
+        assert_eq!(os.id, "ubuntu");
+        assert_eq!(os.id_like, ["debian"]);
+        assert_eq!(os.name, "Ubuntu");
+        assert_eq!(os.version_id, "24.04");
+        assert_eq!(os.pretty_name, "Ubuntu 24.04 LTS");
+
+        let memory = parse_meminfo(concat!(
+            "MemTotal:        2048 kB\n",
+            "MemFree:          512 kB\n",
+            "MemAvailable:    1024 kB\n",
+            "SwapTotal:          0 kB\n",
+            "SwapFree:           0 kB\n",
+        ));
+        assert_eq!(
+            memory,
+            Memory {
+                total:      2048 * 1024,
+                available:  1024 * 1024,
+                swap_total: 0,
+                swap_free:  0,
+            }
+        );
+    }
+
+    #[test]
+    fn addresses_and_disks() {
+        let addresses = parse_addresses(concat!(
+            r#"[{"ifname":"lo","addr_info":[{"local":"127.0.0.1","prefixlen":8}]},"#,
+            r#"{"ifname":"eth0","addr_info":[{"local":"fe80::1","prefixlen":64}]}]"#,
+        ));
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses[1].interface, "eth0");
+        assert_eq!(
+            addresses[1].address,
+            "fe80::1".parse::<std::net::IpAddr>().unwrap()
+        );
+        assert_eq!(addresses[1].prefix_length, 64);
+        assert!(parse_addresses("not json").is_empty());
+
+        let disks = parse_df(concat!(
+            "Filesystem     Type 1024-blocks    Used Available Capacity Mounted on\n",
+            "/dev/sda1      ext4    10000000 4000000   6000000      40% /\n",
+            "/dev/sdb1      xfs         2000    1000      1000      50% /mnt/my data\n",
+        ));
+        assert_eq!(disks.len(), 2);
+        assert_eq!(disks[0].total, 10_000_000 * 1024);
+        assert_eq!(disks[1].mount_point, std::path::Path::new("/mnt/my data"));
+        assert_eq!(disks[1].available, 1000 * 1024);
+    }
+
+    #[test]
+    fn cloud_detection() {
+        assert_eq!(
+            cloud_from_dmi(["Amazon EC2", "m5.large", "Amazon EC2", ""]),
+            Some(CloudProvider::Aws)
+        );
+        assert_eq!(
+            cloud_from_dmi(["Google", "Google Compute Engine", "Google", ""]),
+            Some(CloudProvider::Gcp)
+        );
+        assert_eq!(
+            cloud_from_dmi([
+                "Microsoft Corporation",
+                "Virtual Machine",
+                "Microsoft Corporation",
+                "7783-7084-3265-9085-8269-3286-77"
+            ]),
+            Some(CloudProvider::Azure)
+        );
+        assert_eq!(cloud_from_dmi(["QEMU", "Standard PC", "SeaBIOS", ""]), None);
+    }
+
+    #[test]
+    fn gpus_and_accelerators() {
+        assert!(is_gpu_class("0x030000"));
+        assert!(is_gpu_class("0x120000"));
+        assert!(!is_gpu_class("0x020000"));
+        assert_eq!(GpuVendor::from_pci_id("0x10de"), GpuVendor::Nvidia);
+        assert_eq!(GpuVendor::from_pci_id("0x1af4"), GpuVendor::Other);
+
+        assert_eq!(
+            parse_nvidia_smi(concat!(
+                "00000000:01:00.0, NVIDIA GeForce RTX 3090, 535.104.05, 24576\n",
+                "00000000:02:00.0, NVIDIA A100-SXM4-80GB, 535.104.05, [N/A]\n",
+            )),
+            [
+                NvidiaGpu {
+                    bus_id:         String::from("00000000:01:00.0"),
+                    name:           String::from("NVIDIA GeForce RTX 3090"),
+                    driver_version: String::from("535.104.05"),
+                    memory:         Some(24_576 * 1024 * 1024),
+                },
+                NvidiaGpu {
+                    bus_id:         String::from("00000000:02:00.0"),
+                    name:           String::from("NVIDIA A100-SXM4-80GB"),
+                    driver_version: String::from("535.104.05"),
+                    memory:         None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            cuda_version_from_json(r#"{"cuda":{"name":"CUDA SDK","version":"12.2.140"}}"#)
+                .as_deref(),
+            Some("12.2.140")
+        );
+        assert_eq!(cuda_version_from_json(""), None);
+        assert_eq!(
+            cuda_version_from_nvcc(concat!(
+                "nvcc: NVIDIA (R) Cuda compiler driver\n",
+                "Cuda compilation tools, release 11.8, V11.8.89\n",
+                "Build cuda_11.8.r11.8/compiler.31833905_0\n",
+            ))
+            .as_deref(),
+            Some("11.8.89")
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! This module contains managing kernel modules, as hardware-provisioning scripts
+//! do.
+//!
+//! Modules are loaded and unloaded with `modprobe`. The boot configuration consists of
+//! one file per module in `/etc/modules-load.d` (loading it at boot) and in
+//! `/etc/modprobe.d` (its parameters, or that it is blacklisted), all named
+//! `rush-<module>.conf`. Writing a file that already has the desired content changes
+//! nothing.
+//!
+//! ```no_run
+//! use rush::system::kmod;
+//!
+//! if !kmod::is_loaded("wireguard").unwrap() {
+//!     kmod::load("wireguard", &[]).unwrap();
+//! }
+//! kmod::load_at_boot("wireguard").unwrap();
+//! kmod::set_parameters("zfs", &[("zfs_arc_max", "4294967296")]).unwrap();
+//! ```
+
+use crate::{
+    fs::{
+        FSError,
+        File,
+        Object as _,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Describes possible errors when managing kernel modules.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum KmodError {
+    #[error("'{0}' is not a valid module name")]
+    InvalidName(String),
+    #[error("'{0}' is not a valid module parameter")]
+    InvalidParameter(String),
+    #[error("The module '{0}' does not exist")]
+    NotFound(String),
+    #[error("The module '{0}' is in use")]
+    InUse(String),
+    #[error("Accessing the module configuration failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running modprobe failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`KmodError`].
+pub type KmodResult<T> = Result<T, KmodError>;
+
+/// The file listing the loaded modules.
+const MODULES: &str = "/proc/modules";
+/// The directory of the files listing the modules to load at boot.
+const MODULES_LOAD_DIRECTORY: &str = "/etc/modules-load.d";
+/// The directory of the files configuring modules.
+const MODPROBE_DIRECTORY: &str = "/etc/modprobe.d";
+
+/// `name` as the kernel reports it: `-` and `_` are interchangeable in module
+/// names, and the kernel uses `_`.
+///
+/// # Errors
+///
+/// Returns [`KmodError::InvalidName`] if `name` is empty or contains characters
+/// other than ASCII letters, digits, `-` and `_`.
+fn normalize(name: &str) -> KmodResult<String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '-' | '_'))
+    {
+        return Err(KmodError::InvalidName(String::from(name)));
+    }
+    Ok(name.replace('-', "_"))
+}
+
+/// The parameters as `modprobe` takes them, e.g. `debug=1`.
+///
+/// # Errors
+///
+/// Returns [`KmodError::InvalidParameter`] if a key is not a valid name or a value
+/// contains whitespace.
+fn format_parameters(parameters: &[(&str, &str)]) -> KmodResult<Vec<String>> {
+    parameters
+        .iter()
+        .map(|(key, value)| {
+            let parameter = format!("{key}={value}");
+            if normalize(key).is_err() || value.is_empty() || value.contains(char::is_whitespace) {
+                return Err(KmodError::InvalidParameter(parameter));
+            }
+            Ok(parameter)
+        })
+        .collect()
+}
+
+/// Whether the module `name` is listed in `modules` (the content of
+/// `/proc/modules`).
+fn listed(modules: &str, name: &str) -> bool {
+    modules
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .any(|module| module == name)
+}
+
+/// Turn a failed `modprobe` invocation for `name` into the matching error.
+fn classify(error: ProcessError, name: &str) -> KmodError {
+    match &error {
+        ProcessError::Failed { stderr, .. } if stderr.contains("not found") => {
+            KmodError::NotFound(String::from(name))
+        },
+        ProcessError::Failed { stderr, .. } if stderr.contains("in use") => {
+            KmodError::InUse(String::from(name))
+        },
+        _ => KmodError::Process(error),
+    }
+}
+
+/// Write `content` to `path` unless it has that content already. Returns whether
+/// the file changed.
+fn write_config(path: &str, content: &str) -> KmodResult<bool> {
+    let file = File::new(path);
+    if file.exists()? && file.read()? == content {
+        return Ok(false);
+    }
+    log::debug!("Writing the module configuration {}", file);
+    file.create_on_fs_recursive()?;
+    file.overwrite(content)?;
+    Ok(true)
+}
+
+/// Delete `path` if it exists. Returns whether it existed.
+fn remove_config(path: &str) -> KmodResult<bool> {
+    let file = File::new(path);
+    if !file.exists()? {
+        return Ok(false);
+    }
+    log::debug!("Removing the module configuration {}", file);
+    file.delete_from_fs()?;
+    Ok(true)
+}
+
+/// Whether the kernel module `name` is loaded. Modules built into the kernel are
+/// not listed as loaded.
+///
+/// # Errors
+///
+/// Returns an error if `name` is not a valid module name or `/proc/modules` cannot
+/// be read.
+pub fn is_loaded(name: &str) -> KmodResult<bool> {
+    let name = normalize(name)?;
+    Ok(listed(&File::new(MODULES).read()?, &name))
+}
+
+/// Load the kernel module `name`, and the modules it depends on, with `parameters`
+/// (e.g. `[("debug", "1")]`). Loading a module that is loaded already changes
+/// nothing, not even its parameters.
+///
+/// # Errors
+///
+/// Returns [`KmodError::NotFound`] if there is no such module, and other errors if
+/// the name or a parameter is not valid or `modprobe` fails otherwise (e.g. when
+/// not running as `root`).
+pub fn load(name: &str, parameters: &[(&str, &str)]) -> KmodResult<()> {
+    let module = normalize(name)?;
+    let parameters = format_parameters(parameters)?;
+    log::debug!("Loading the kernel module {module}");
+    Command::new("modprobe")
+        .arg(&module)
+        .args(parameters)
+        .run()
+        .map_err(|error| classify(error, &module))?;
+    Ok(())
+}
+
+/// Unload the kernel module `name` and the modules only it depended on. Unloading
+/// a module that is not loaded changes nothing.
+///
+/// # Errors
+///
+/// Returns [`KmodError::InUse`] if the module is in use, [`KmodError::NotFound`] if
+/// there is no such module, and other errors if `modprobe` fails otherwise.
+pub fn unload(name: &str) -> KmodResult<()> {
+    let module = normalize(name)?;
+    log::debug!("Unloading the kernel module {module}");
+    Command::new("modprobe")
+        .args(["-r", &module])
+        .run()
+        .map_err(|error| classify(error, &module))?;
+    Ok(())
+}
+
+/// Load the kernel module `name` at every boot, by listing it in
+/// `/etc/modules-load.d`. Returns whether the configuration changed.
+///
+/// # Errors
+///
+/// Returns an error if `name` is not valid or the configuration cannot be written.
+pub fn load_at_boot(name: &str) -> KmodResult<bool> {
+    let module = normalize(name)?;
+    write_config(
+        &format!("{MODULES_LOAD_DIRECTORY}/rush-{module}.conf"),
+        &format!("{module}\n"),
+    )
+}
+
+/// Stop loading the kernel module `name` at boot, undoing [`load_at_boot`].
+/// Returns whether the configuration changed.
+///
+/// # Errors
+///
+/// Returns an error if `name` is not valid or the configuration cannot be removed.
+pub fn no_load_at_boot(name: &str) -> KmodResult<bool> {
+    let module = normalize(name)?;
+    remove_config(&format!("{MODULES_LOAD_DIRECTORY}/rush-{module}.conf"))
+}
+
+/// Load the kernel module `name` with `parameters` from now on.
+///
+/// The parameters are set in `/etc/modprobe.d`. Without parameters, the configuration
+/// is removed. This replaces a blacklisting by [`blacklist`]; a loaded module keeps its
+/// current parameters until it is loaded again. Returns whether the configuration
+/// changed.
+///
+/// # Errors
+///
+/// Returns an error if the name or a parameter is not valid or the configuration
+/// cannot be written.
+pub fn set_parameters(name: &str, parameters: &[(&str, &str)]) -> KmodResult<bool> {
+    let module = normalize(name)?;
+    let parameters = format_parameters(parameters)?;
+    let path = format!("{MODPROBE_DIRECTORY}/rush-{module}.conf");
+    if parameters.is_empty() {
+        return remove_config(&path);
+    }
+    write_config(
+        &path,
+        &format!("options {module} {}\n", parameters.join(" ")),
+    )
+}
+
+/// Never load the kernel module `name` automatically.
+///
+/// The module is blacklisted in `/etc/modprobe.d`. It can still be loaded explicitly.
+/// This replaces parameters set by [`set_parameters`]. Returns whether the
+/// configuration changed.
+///
+/// # Errors
+///
+/// Returns an error if `name` is not valid or the configuration cannot be written.
+pub fn blacklist(name: &str) -> KmodResult<bool> {
+    let module = normalize(name)?;
+    write_config(
+        &format!("{MODPROBE_DIRECTORY}/rush-{module}.conf"),
+        &format!("blacklist {module}\n"),
+    )
+}
+
+#[cfg(test)]
+mod kmod_test {
+    use super::*;
+
+    #[test]
+    fn names_and_parameters() {
+        assert_eq!(
+            normalize("snd-hda-intel"),
+            Ok(String::from("snd_hda_intel"))
+        );
+        assert!(matches!(
+            normalize("../evil"),
+            Err(KmodError::InvalidName(_))
+        ));
+        assert!(matches!(normalize(""), Err(KmodError::InvalidName(_))));
+        assert_eq!(
+            format_parameters(&[("debug", "1"), ("mode", "fast")]),
+            Ok(vec![String::from("debug=1"), String::from("mode=fast")])
+        );
+        assert!(matches!(
+            format_parameters(&[("debug", "1 2")]),
+            Err(KmodError::InvalidParameter(_))
+        ));
+
+        let modules = "wireguard 98304 0 - Live 0x0000000000000000\nip6_udp_tunnel 16384 1 \
+                       wireguard, Live 0x0000000000000000\n";
+        assert!(listed(modules, "wireguard"));
+        assert!(!listed(modules, "udp_tunnel"));
+
+        let failed = |stderr: &str| ProcessError::Failed {
+            code:   Some(1),
+            stderr: String::from(stderr),
+        };
+        assert_eq!(
+            classify(
+                failed("modprobe: FATAL: Module nope not found in directory"),
+                "nope"
+            ),
+            KmodError::NotFound(String::from("nope"))
+        );
+        assert_eq!(
+            classify(failed("modprobe: FATAL: Module kvm is in use."), "kvm"),
+            KmodError::InUse(String::from("kvm"))
+        );
+    }
+}
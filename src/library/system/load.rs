@@ -0,0 +1,377 @@
+//! This module contains sampling how busy the host is (load average, memory
+//! pressure and disk utilization), so that batch scripts can yield to interactive
+//! workloads.
+//!
+//! [`wait_until_idle`] waits until all configured [`Thresholds`] are met, e.g.
+//! before starting a backup or reindexing.
+//!
+//! ```no_run
+//! use rush::system::load::{self, Thresholds};
+//!
+//! let thresholds = Thresholds::new().load(2.0).memory_pressure(10.0).io_utilization(50.0);
+//! if !load::wait_until_idle(&thresholds, std::time::Duration::from_secs(3600)).unwrap() {
+//!     println!("The host stayed busy, starting anyway");
+//! }
+//! ```
+
+use crate::fs::FSError;
+
+/// Describes possible errors when sampling the load.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum LoadError {
+    #[error("This is not supported on this host: {0}")]
+    Unsupported(String),
+    #[error("'{0}' has an unexpected format")]
+    InvalidFormat(String),
+    #[error("Reading the statistics failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Waiting for the host to become idle was aborted: {0}")]
+    DeadlineExceeded(#[from] crate::deadline::DeadlineExceeded),
+}
+
+/// A [`Result`] whose error variant is a [`LoadError`].
+pub type LoadResult<T> = Result<T, LoadError>;
+
+/// The file the kernel reports the load average in.
+const LOADAVG: &str = "/proc/loadavg";
+/// The file the kernel reports the memory pressure in.
+const MEMORY_PRESSURE: &str = "/proc/pressure/memory";
+/// The file the kernel reports statistics of block devices in.
+const DISKSTATS: &str = "/proc/diskstats";
+/// The file listing the mounted filesystems.
+const MOUNTS: &str = "/proc/self/mounts";
+/// How long [`wait_until_idle`] samples the disk utilization.
+const IO_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// How long [`wait_until_idle`] waits between checks while the host is busy.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The average number of processes running or waiting for the CPU or disks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadAverage {
+    /// Over the last minute
+    pub one:     f64,
+    /// Over the last five minutes
+    pub five:    f64,
+    /// Over the last fifteen minutes
+    pub fifteen: f64,
+}
+
+/// How much of the last ten seconds tasks were stalled waiting for memory, in
+/// percent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pressure {
+    /// At least one task was stalled
+    pub some: f64,
+    /// All tasks were stalled
+    pub full: f64,
+}
+
+/// How busy the device of a mounted filesystem was, see [`io_utilization`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoUtilization {
+    /// Where the filesystem is mounted
+    pub mount_point: std::path::PathBuf,
+    /// The name of the block device, e.g. `nvme0n1p2` or `dm-0`
+    pub device:      String,
+    /// How much of the time the device was busy, in percent
+    pub percent:     f64,
+}
+
+/// The maximum load [`wait_until_idle`] waits for. Only the thresholds that are set
+/// are checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// The highest 1-minute load average
+    load:            Option<f64>,
+    /// The highest memory pressure ([`Pressure::some`])
+    memory_pressure: Option<f64>,
+    /// The highest utilization of any mounted device
+    io_utilization:  Option<f64>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self { Self::new() }
+}
+
+impl Thresholds {
+    /// No thresholds; set them with the other methods.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            load:            None,
+            memory_pressure: None,
+            io_utilization:  None,
+        }
+    }
+
+    /// Wait until the 1-minute load average is at most `maximum`. Mind that the load
+    /// grows with the number of CPUs that are busy.
+    #[must_use]
+    pub const fn load(mut self, maximum: f64) -> Self {
+        self.load = Some(maximum);
+        self
+    }
+
+    /// Wait until tasks were stalled waiting for memory at most `maximum` percent of
+    /// the last ten seconds.
+    #[must_use]
+    pub const fn memory_pressure(mut self, maximum: f64) -> Self {
+        self.memory_pressure = Some(maximum);
+        self
+    }
+
+    /// Wait until no mounted device is busy more than `maximum` percent of the time.
+    #[must_use]
+    pub const fn io_utilization(mut self, maximum: f64) -> Self {
+        self.io_utilization = Some(maximum);
+        self
+    }
+
+    /// Describe the first threshold that is exceeded, or return [`None`] if all are
+    /// met.
+    fn exceeded(&self) -> LoadResult<Option<String>> {
+        if let Some(maximum) = self.load {
+            let load = load_average()?.one;
+            if load > maximum {
+                return Ok(Some(format!("load average {load} > {maximum}")));
+            }
+        }
+        if let Some(maximum) = self.memory_pressure {
+            let pressure = memory_pressure()?.some;
+            if pressure > maximum {
+                return Ok(Some(format!("memory pressure {pressure}% > {maximum}%")));
+            }
+        }
+        if let Some(maximum) = self.io_utilization {
+            if let Some(busiest) = io_utilization(IO_SAMPLE_INTERVAL)?
+                .into_iter()
+                .find(|utilization| utilization.percent > maximum)
+            {
+                return Ok(Some(format!(
+                    "utilization of {} {:.0}% > {maximum}%",
+                    busiest.mount_point.display(),
+                    busiest.percent
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Read the file at `path` to a [`String`].
+fn read(path: &str) -> LoadResult<String> {
+    std::fs::read_to_string(path).map_err(|error| FSError::from(error).into())
+}
+
+/// Parse the content of `/proc/loadavg`, e.g. `0.52 0.58 0.59 1/467 12345`.
+fn parse_loadavg(content: &str) -> LoadResult<LoadAverage> {
+    let averages: Vec<f64> = content
+        .split_whitespace()
+        .take(3)
+        .filter_map(|average| average.parse().ok())
+        .collect();
+    let [one, five, fifteen] = averages[..] else {
+        return Err(LoadError::InvalidFormat(String::from(LOADAVG)));
+    };
+    Ok(LoadAverage { one, five, fifteen })
+}
+
+/// Parse the content of a file in `/proc/pressure`, e.g.
+/// `some avg10=1.50 avg60=0.80 avg300=0.20 total=12345` and a `full` line.
+fn parse_pressure(content: &str) -> LoadResult<Pressure> {
+    let average = |kind: &str| {
+        content
+            .lines()
+            .filter_map(|line| line.strip_prefix(kind))
+            .flat_map(str::split_whitespace)
+            .find_map(|field| field.strip_prefix("avg10=")?.parse().ok())
+            .ok_or_else(|| LoadError::InvalidFormat(String::from(MEMORY_PRESSURE)))
+    };
+    Ok(Pressure {
+        some: average("some ")?,
+        full: average("full ")?,
+    })
+}
+
+/// Parse the content of `/proc/diskstats` into the milliseconds each device has
+/// spent doing I/O.
+fn parse_diskstats(content: &str) -> std::collections::HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some((String::from(*fields.get(2)?), fields.get(12)?.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parse the content of `/proc/self/mounts` into the devices and mount points of
+/// filesystems on block devices.
+fn parse_mounts(content: &str) -> Vec<(std::path::PathBuf, std::path::PathBuf)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mount_point = fields.next()?;
+            device.starts_with("/dev/").then(|| {
+                (
+                    std::path::PathBuf::from(super::parse::unescape_octal(device)),
+                    std::path::PathBuf::from(super::parse::unescape_octal(mount_point)),
+                )
+            })
+        })
+        .collect()
+}
+
+/// The utilization in percent of a device that spent `before` and `after`
+/// milliseconds doing I/O, `elapsed` apart.
+fn utilization(before: u64, after: u64, elapsed: std::time::Duration) -> f64 {
+    let busy = std::time::Duration::from_millis(after.saturating_sub(before));
+    (busy.as_secs_f64() / elapsed.as_secs_f64() * 100.0).min(100.0)
+}
+
+/// The current load average.
+///
+/// # Errors
+///
+/// Returns an error if `/proc/loadavg` cannot be read or parsed.
+pub fn load_average() -> LoadResult<LoadAverage> { parse_loadavg(&read(LOADAVG)?) }
+
+/// The current memory pressure, from the kernel's pressure stall information.
+///
+/// # Errors
+///
+/// Returns [`LoadError::Unsupported`] if the kernel does not provide pressure stall
+/// information (it needs 4.20 or newer and may have it disabled), and other errors
+/// if it cannot be read or parsed.
+pub fn memory_pressure() -> LoadResult<Pressure> {
+    match read(MEMORY_PRESSURE) {
+        Ok(content) => parse_pressure(&content),
+        Err(LoadError::FS(FSError::NonExistent)) => Err(LoadError::Unsupported(String::from(
+            "pressure stall information",
+        ))),
+        Err(error) => Err(error),
+    }
+}
+
+/// Sample how busy the device of each filesystem mounted from a block device is
+/// during `interval`, which this function blocks for.
+///
+/// # Errors
+///
+/// Returns an error if the statistics cannot be read, or if the
+/// [`Deadline`](crate::deadline::Deadline) in effect passes during the sample.
+pub fn io_utilization(interval: std::time::Duration) -> LoadResult<Vec<IoUtilization>> {
+    let mounts = parse_mounts(&read(MOUNTS)?);
+    let start = std::time::Instant::now();
+    let before = parse_diskstats(&read(DISKSTATS)?);
+    crate::deadline::sleep(interval)?;
+    let after = parse_diskstats(&read(DISKSTATS)?);
+    let elapsed = start.elapsed();
+
+    Ok(mounts
+        .into_iter()
+        .filter_map(|(device, mount_point)| {
+            // Resolves links such as `/dev/mapper/root` to `/dev/dm-0`.
+            let device = std::fs::canonicalize(&device).unwrap_or(device);
+            let device = device.file_name()?.to_string_lossy().into_owned();
+            let percent = utilization(*before.get(&device)?, *after.get(&device)?, elapsed);
+            Some(IoUtilization {
+                mount_point,
+                device,
+                percent,
+            })
+        })
+        .collect())
+}
+
+/// Wait until the host is idle according to `thresholds`, checking every few
+/// seconds. Returns `true` once all thresholds are met and `false` if they are not
+/// met within `timeout`.
+///
+/// # Errors
+///
+/// Returns an error if a statistic cannot be sampled (see [`memory_pressure`] and
+/// [`io_utilization`]), or if the [`Deadline`](crate::deadline::Deadline) in effect
+/// passes first.
+pub fn wait_until_idle(thresholds: &Thresholds, timeout: std::time::Duration) -> LoadResult<bool> {
+    let start = std::time::Instant::now();
+    loop {
+        let Some(reason) = thresholds.exceeded()? else {
+            return Ok(true);
+        };
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            log::debug!("The host is still busy ({reason}), giving up waiting");
+            return Ok(false);
+        }
+        log::debug!("Waiting for the host to become idle ({reason})");
+        crate::deadline::sleep(remaining.min(POLL_INTERVAL))?;
+    }
+}
+
+#[cfg(test)]
+mod load_test {
+    use super::*;
+
+    #[test]
+    fn statistics() -> LoadResult<()> {
+        assert_eq!(
+            parse_loadavg("0.52 1.58 2.00 1/467 12345\n")?,
+            LoadAverage {
+                one:     0.52,
+                five:    1.58,
+                fifteen: 2.0,
+            }
+        );
+        assert!(parse_loadavg("0.52").is_err());
+
+        assert_eq!(
+            parse_pressure(concat!(
+                "some avg10=1.50 avg60=0.80 avg300=0.20 total=12345\n",
+                "full avg10=0.25 avg60=0.10 avg300=0.00 total=2345\n",
+            ))?,
+            Pressure {
+                some: 1.5,
+                full: 0.25,
+            }
+        );
+        assert!(parse_pressure("some avg10=3.00 avg60=0.00 avg300=0.00 total=1\n").is_err());
+        assert!(parse_pressure("").is_err());
+
+        let stats = parse_diskstats(concat!(
+            " 259       0 nvme0n1 100 0 200 30 40 0 50 60 0 1500 90 0 0 0 0\n",
+            " 259       2 nvme0n1p2 80 0 160 20 30 0 40 50 0 1200 70 0 0 0 0\n",
+        ));
+        assert_eq!(stats.get("nvme0n1p2"), Some(&1200));
+        assert_eq!(
+            parse_mounts(concat!(
+                "/dev/nvme0n1p2 / ext4 rw,relatime 0 0\n",
+                "proc /proc proc rw 0 0\n",
+                "/dev/sdb1 /mnt/backup\\040disk ext4 rw 0 0\n",
+            )),
+            [
+                ("/dev/nvme0n1p2".into(), "/".into()),
+                ("/dev/sdb1".into(), "/mnt/backup disk".into()),
+            ]
+        );
+        let second = std::time::Duration::from_secs(1);
+        assert!((utilization(1000, 1250, second) - 25.0).abs() < f64::EPSILON);
+        assert!((utilization(1000, 3000, second) - 100.0).abs() < f64::EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn waiting() -> LoadResult<()> {
+        assert!(wait_until_idle(
+            &Thresholds::new(),
+            std::time::Duration::ZERO
+        )?);
+        assert!(wait_until_idle(
+            &Thresholds::new().load(f64::MAX),
+            std::time::Duration::ZERO
+        )?);
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! This module contains configuring the time zone, the locale and time
+//! synchronization, which bootstrap scripts do for every base image.
+//!
+//! On hosts booted with systemd, the changes go through `timedatectl` and
+//! `localectl`. Otherwise, the configuration files are changed directly. Names are
+//! validated first either way, and nothing is changed if the configuration is as
+//! requested already.
+//!
+//! ```no_run
+//! use rush::system::localization;
+//!
+//! localization::set_timezone("Europe/Berlin").unwrap();
+//! localization::set_locale("en_US.UTF-8").unwrap();
+//! localization::enable_ntp().unwrap();
+//! ```
+
+use crate::{
+    fs::{
+        FSError,
+        File,
+        Object as _,
+        SymbolicLink,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Describes possible errors when configuring the localization.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum LocalizationError {
+    #[error("The time zone '{0}' does not exist")]
+    UnknownTimezone(String),
+    #[error("The locale '{0}' is not available")]
+    UnknownLocale(String),
+    #[error("This is not supported on this host: {0}")]
+    Unsupported(String),
+    #[error("Accessing the configuration failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running a program failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`LocalizationError`].
+pub type LocalizationResult<T> = Result<T, LocalizationError>;
+
+/// The directory of the time zone database.
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+/// The link to the configured time zone.
+const LOCALTIME: &str = "/etc/localtime";
+/// The file Debian-based distributions keep the name of the time zone in.
+const TIMEZONE: &str = "/etc/timezone";
+/// The file systemd and most distributions keep the locale in.
+const LOCALE_CONF: &str = "/etc/locale.conf";
+/// The file Debian-based distributions keep the locale in.
+const DEFAULT_LOCALE: &str = "/etc/default/locale";
+
+/// Whether `name` can be the name of a time zone, e.g. `Europe/Berlin`, without
+/// escaping the time zone database.
+fn is_timezone_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('/')
+        && name
+            .split('/')
+            .all(|component| !component.is_empty() && component != "." && component != "..")
+}
+
+/// The time zone the link `target` of `/etc/localtime` points to, e.g.
+/// `Europe/Berlin` for `../usr/share/zoneinfo/Europe/Berlin`.
+fn timezone_of_link(target: &std::path::Path) -> Option<String> {
+    let target = target.to_string_lossy();
+    let (_, name) = target.split_once("zoneinfo/")?;
+    Some(String::from(name))
+}
+
+/// The configured time zone, e.g. `Europe/Berlin`, or [`None`] if it is unknown.
+///
+/// # Errors
+///
+/// Returns an error if `timedatectl` fails.
+pub fn timezone() -> LocalizationResult<Option<String>> {
+    if super::booted_with_systemd()? {
+        let output = Command::new("timedatectl")
+            .args(["show", "--property=Timezone", "--value"])
+            .run()?;
+        let timezone = output.stdout.trim();
+        return Ok((!timezone.is_empty()).then(|| String::from(timezone)));
+    }
+    Ok(SymbolicLink::new(LOCALTIME)
+        .target()
+        .ok()
+        .and_then(|target| timezone_of_link(&target)))
+}
+
+/// Set the time zone to `name`, e.g. `Europe/Berlin` or `UTC`. Returns whether the
+/// configuration changed.
+///
+/// Without systemd, `/etc/localtime` is linked to the time zone and, on Debian-based
+/// distributions, its name is written to `/etc/timezone`.
+///
+/// # Errors
+///
+/// Returns [`LocalizationError::UnknownTimezone`] if the time zone database has no
+/// such time zone, and other errors if changing the configuration fails (usually
+/// because of missing privileges).
+pub fn set_timezone(name: &str) -> LocalizationResult<bool> {
+    let zone = File::new(format!("{ZONEINFO}/{name}"));
+    // A directory of the database (e.g. `Europe`) is no time zone either.
+    if !is_timezone_name(name) || !matches!(zone.exists(), Ok(true)) {
+        return Err(LocalizationError::UnknownTimezone(String::from(name)));
+    }
+    if timezone()?.as_deref() == Some(name) {
+        return Ok(false);
+    }
+
+    log::info!("Setting the time zone to {name}");
+    if super::booted_with_systemd()? {
+        Command::new("timedatectl")
+            .args(["set-timezone", name])
+            .run()?;
+    } else {
+        Command::new("ln")
+            .args(["-sf", &format!("{ZONEINFO}/{name}"), LOCALTIME])
+            .run()?;
+        let timezone = File::new(TIMEZONE);
+        if timezone.exists()? {
+            timezone.overwrite(format!("{name}\n"))?;
+        }
+    }
+    Ok(true)
+}
+
+/// `locale` in the form `locale -a` lists it, e.g. `en_us.utf8` for `en_US.UTF-8`,
+/// for comparing names that differ only in how the codeset is spelled.
+fn normalize_locale(locale: &str) -> String { locale.to_lowercase().replace('-', "") }
+
+/// `content`, a file of `KEY=value` lines, with `LANG` set to `locale`, or [`None`]
+/// if it is set to it already. Other lines are kept.
+fn with_lang(content: &str, locale: &str) -> Option<String> {
+    let line = format!("LANG={locale}");
+    let mut found = false;
+    let mut lines = vec![];
+    for existing in content.lines() {
+        if existing.trim_start().starts_with("LANG=") {
+            if existing.trim() == line && !found {
+                return None;
+            }
+            if !found {
+                lines.push(line.clone());
+                found = true;
+            }
+        } else {
+            lines.push(String::from(existing));
+        }
+    }
+    if !found {
+        lines.push(line);
+    }
+    Some(
+        lines
+            .into_iter()
+            .flat_map(|line| [line, String::from("\n")])
+            .collect(),
+    )
+}
+
+/// Set the system locale (`LANG`) to `locale`, e.g. `en_US.UTF-8`. Returns whether
+/// the configuration changed. The locale is used by sessions started afterwards.
+///
+/// Without systemd, `LANG` is changed in `/etc/default/locale` on Debian-based
+/// distributions and in `/etc/locale.conf` elsewhere, keeping other variables. With
+/// systemd, `localectl` unsets the other variables.
+///
+/// # Errors
+///
+/// Returns [`LocalizationError::UnknownLocale`] if `locale -a` does not list the
+/// locale (it may have to be generated first), and other errors if changing the
+/// configuration fails.
+pub fn set_locale(locale: &str) -> LocalizationResult<bool> {
+    let available = Command::new("locale").arg("-a").run()?.stdout;
+    let wanted = normalize_locale(locale);
+    if locale.contains(char::is_whitespace)
+        || !available
+            .lines()
+            .any(|available| normalize_locale(available.trim()) == wanted)
+    {
+        return Err(LocalizationError::UnknownLocale(String::from(locale)));
+    }
+
+    let systemd = super::booted_with_systemd()?;
+    // Debian's `localectl` uses `/etc/default/locale`, too.
+    let file = if File::new(DEFAULT_LOCALE).exists()? {
+        File::new(DEFAULT_LOCALE)
+    } else {
+        File::new(LOCALE_CONF)
+    };
+    let content = if file.exists()? {
+        file.read()?
+    } else {
+        String::new()
+    };
+    let Some(content) = with_lang(&content, locale) else {
+        return Ok(false);
+    };
+
+    log::info!("Setting the locale to {locale}");
+    if systemd {
+        Command::new("localectl")
+            .args(["set-locale", &format!("LANG={locale}")])
+            .run()?;
+    } else {
+        file.overwrite(content)?;
+    }
+    Ok(true)
+}
+
+/// Enable synchronizing the clock with NTP servers. Returns whether the
+/// configuration changed.
+///
+/// # Errors
+///
+/// Returns [`LocalizationError::Unsupported`] if the host was not booted with
+/// systemd, as there is no common way to enable NTP then, and other errors if
+/// `timedatectl` fails (e.g. because no NTP service is installed).
+pub fn enable_ntp() -> LocalizationResult<bool> {
+    if !super::booted_with_systemd()? {
+        return Err(LocalizationError::Unsupported(String::from(
+            "enabling NTP requires systemd",
+        )));
+    }
+    let enabled = Command::new("timedatectl")
+        .args(["show", "--property=NTP", "--value"])
+        .run()?;
+    if enabled.stdout.trim() == "yes" {
+        return Ok(false);
+    }
+    log::info!("Enabling NTP");
+    Command::new("timedatectl")
+        .args(["set-ntp", "true"])
+        .run()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod localization_test {
+    use super::*;
+
+    #[test]
+    fn names_and_files() {
+        assert!(is_timezone_name("Europe/Berlin"));
+        assert!(is_timezone_name("UTC"));
+        for invalid in ["", "/etc/passwd", "../../etc/passwd", "Europe//Berlin"] {
+            assert!(!is_timezone_name(invalid), "{invalid}");
+        }
+        assert_eq!(
+            timezone_of_link(std::path::Path::new("../usr/share/zoneinfo/Europe/Berlin"))
+                .as_deref(),
+            Some("Europe/Berlin")
+        );
+        assert_eq!(timezone_of_link(std::path::Path::new("/etc/other")), None);
+
+        assert_eq!(
+            normalize_locale("en_US.UTF-8"),
+            normalize_locale("en_US.utf8")
+        );
+        assert_ne!(
+            normalize_locale("en_US.UTF-8"),
+            normalize_locale("de_DE.UTF-8")
+        );
+
+        assert_eq!(
+            with_lang("", "en_US.UTF-8").as_deref(),
+            Some("LANG=en_US.UTF-8\n")
+        );
+        assert_eq!(
+            with_lang("LC_TIME=de_DE.UTF-8\nLANG=C.UTF-8\n", "en_US.UTF-8").as_deref(),
+            Some("LC_TIME=de_DE.UTF-8\nLANG=en_US.UTF-8\n")
+        );
+        assert_eq!(with_lang("LANG=en_US.UTF-8\n", "en_US.UTF-8"), None);
+    }
+}
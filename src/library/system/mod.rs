@@ -0,0 +1,44 @@
+//! This module contains functionality for inspecting and configuring the system the
+//! program runs on.
+
+#[cfg(feature = "dbus")]
+pub mod dbus;
+mod facts;
+pub mod kmod;
+pub mod localization;
+#[cfg(target_os = "linux")]
+pub mod load;
+pub mod parse;
+pub mod power;
+mod reboot;
+pub mod shellrc;
+#[cfg(target_os = "linux")]
+pub mod swap;
+
+pub use facts::{
+    disk,
+    facts,
+    gpus,
+    Address,
+    CloudProvider,
+    Disk,
+    Facts,
+    Gpu,
+    GpuVendor,
+    Kernel,
+    Memory,
+    OperatingSystem,
+};
+pub use reboot::{
+    reboot_and_resume,
+    resumed,
+    RebootError,
+    RebootResult,
+};
+
+/// Whether the host was booted with systemd, i.e. whether `systemctl` and friends
+/// can be used.
+pub(crate) fn booted_with_systemd() -> crate::fs::FSResult<bool> {
+    use crate::fs::Object as _;
+    crate::fs::Directory::new("/run/systemd/system").exists()
+}
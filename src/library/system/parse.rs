@@ -0,0 +1,687 @@
+//! This module contains parsers for common system files and command outputs, which
+//! scripts otherwise pick apart with `cut` and `awk`.
+//!
+//! Supported are `/etc/passwd`, `/etc/group`, `/etc/fstab`, `/proc/meminfo`,
+//! `/proc/net/tcp` and `crontab -l`.
+//!
+//! Each format has a `parse_*` function that works on the content, which is handy
+//! for tests and files from other hosts, and a `read_*` function that reads the
+//! local file (or runs the command).
+//!
+//! ```no_run
+//! use rush::system::parse;
+//!
+//! let shells: Vec<_> = parse::read_passwd()
+//!     .unwrap()
+//!     .into_iter()
+//!     .filter(|user| user.uid >= 1000)
+//!     .map(|user| user.shell)
+//!     .collect();
+//! let listening = parse::read_tcp()
+//!     .unwrap()
+//!     .into_iter()
+//!     .filter(|socket| socket.state == parse::TcpState::Listen)
+//!     .count();
+//! ```
+
+use crate::{
+    fs::FSError,
+    process::ProcessError,
+};
+
+/// Describes possible errors when parsing system files.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ParseError {
+    #[error("Line {line} is invalid: {reason}")]
+    InvalidLine { line: usize, reason: String },
+    #[error("Reading the file failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running the program failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`ParseError`].
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// A user from `/etc/passwd`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct PasswdEntry {
+    /// The login name
+    pub name:     String,
+    /// The password field, usually `x` as the hash is in `/etc/shadow`
+    pub password: String,
+    /// The user ID
+    pub uid:      u32,
+    /// The ID of the primary group
+    pub gid:      u32,
+    /// The comment field, usually the full name
+    pub gecos:    String,
+    /// The home directory
+    pub home:     std::path::PathBuf,
+    /// The login shell
+    pub shell:    std::path::PathBuf,
+}
+
+/// A group from `/etc/group`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct GroupEntry {
+    /// The name of the group
+    pub name:     String,
+    /// The password field, usually `x`
+    pub password: String,
+    /// The group ID
+    pub gid:      u32,
+    /// The users that have this group as a supplementary group
+    pub members:  Vec<String>,
+}
+
+/// A filesystem from `/etc/fstab`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct FstabEntry {
+    /// The device or source, e.g. `/dev/sda1` or `UUID=...`
+    pub device:      String,
+    /// Where the filesystem is mounted, or `none` for swap
+    pub mount_point: std::path::PathBuf,
+    /// The type of the filesystem, e.g. `ext4`
+    pub filesystem:  String,
+    /// The mount options, e.g. `["defaults", "noatime"]`
+    pub options:     Vec<String>,
+    /// Whether `dump` backs the filesystem up (0 if omitted)
+    pub dump:        u32,
+    /// The order in which `fsck` checks the filesystem (0, i.e. never, if omitted)
+    pub pass:        u32,
+}
+
+/// The memory statistics from `/proc/meminfo`, in bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MemInfo {
+    /// Usable physical memory (`MemTotal`)
+    pub total:      u64,
+    /// Unused memory (`MemFree`)
+    pub free:       u64,
+    /// Memory that can be used without swapping (`MemAvailable`)
+    pub available:  u64,
+    /// Memory used for block device buffers (`Buffers`)
+    pub buffers:    u64,
+    /// Memory used for the page cache (`Cached`)
+    pub cached:     u64,
+    /// Swap space (`SwapTotal`)
+    pub swap_total: u64,
+    /// Unused swap space (`SwapFree`)
+    pub swap_free:  u64,
+    /// All fields by their name; values given in kB are converted to bytes, counts
+    /// (e.g. `HugePages_Total`) are kept as they are
+    pub fields:     std::collections::BTreeMap<String, u64>,
+}
+
+/// The state of a TCP socket, see [`TcpSocket`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum TcpState {
+    Established,
+    SynSent,
+    SynReceived,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Close,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    NewSynReceived,
+    /// A state this library does not know, with the kernel's number for it
+    Unknown(u8),
+}
+
+impl From<u8> for TcpState {
+    fn from(state: u8) -> Self {
+        match state {
+            1 => Self::Established,
+            2 => Self::SynSent,
+            3 => Self::SynReceived,
+            4 => Self::FinWait1,
+            5 => Self::FinWait2,
+            6 => Self::TimeWait,
+            7 => Self::Close,
+            8 => Self::CloseWait,
+            9 => Self::LastAck,
+            10 => Self::Listen,
+            11 => Self::Closing,
+            12 => Self::NewSynReceived,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// A TCP socket from `/proc/net/tcp` or `/proc/net/tcp6`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct TcpSocket {
+    /// The local address
+    pub local:  std::net::SocketAddr,
+    /// The remote address, unspecified for listening sockets
+    pub remote: std::net::SocketAddr,
+    /// The state of the connection
+    pub state:  TcpState,
+    /// The ID of the user owning the socket
+    pub uid:    u32,
+    /// The inode of the socket, which links it to the process in `/proc/*/fd`
+    pub inode:  u64,
+}
+
+/// When a cron job runs, see [`CronJob`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum CronSchedule {
+    /// A shorthand like `@reboot` or `@daily`, without the `@`
+    Special(String),
+    /// The five time and date fields, e.g. `*/5` or `1-5`
+    Fields {
+        minute:       String,
+        hour:         String,
+        day_of_month: String,
+        month:        String,
+        day_of_week:  String,
+    },
+}
+
+/// A job from a crontab.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct CronJob {
+    /// When the job runs
+    pub schedule: CronSchedule,
+    /// The command, as written (`%` is not turned into line feeds)
+    pub command:  String,
+}
+
+/// The content of a user's crontab.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct Crontab {
+    /// The environment variables set for the jobs (e.g. `MAILTO`), in order
+    pub environment: Vec<(String, String)>,
+    /// The jobs, in order
+    pub jobs:        Vec<CronJob>,
+}
+
+/// An [`ParseError::InvalidLine`] for the line with index `index`.
+fn invalid(index: usize, reason: impl Into<String>) -> ParseError {
+    ParseError::InvalidLine {
+        line:   index + 1,
+        reason: reason.into(),
+    }
+}
+
+/// The lines of `content` with their index, without empty lines and comments.
+fn lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Parse a decimal number in the line with index `index`.
+fn number<T: std::str::FromStr>(index: usize, text: &str, name: &str) -> ParseResult<T> {
+    text.parse()
+        .map_err(|_| invalid(index, format!("'{text}' is not a valid {name}")))
+}
+
+/// Read the file at `path` to a [`String`].
+fn read(path: &str) -> ParseResult<String> {
+    log::trace!("Reading '{}' for parsing", path);
+    std::fs::read_to_string(path).map_err(|error| FSError::from(error).into())
+}
+
+/// Parse the content of `/etc/passwd`. NIS compatibility entries (starting with `+`
+/// or `-`) are skipped.
+///
+/// # Errors
+///
+/// Returns an error if a line does not have seven fields or its IDs are invalid.
+pub fn parse_passwd(content: &str) -> ParseResult<Vec<PasswdEntry>> {
+    lines(content)
+        .filter(|(_, line)| !line.starts_with(['+', '-']))
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let [name, password, uid, gid, gecos, home, shell] = fields[..] else {
+                return Err(invalid(index, "expected 7 fields separated by ':'"));
+            };
+            Ok(PasswdEntry {
+                name:     name.to_string(),
+                password: password.to_string(),
+                uid:      number(index, uid, "user ID")?,
+                gid:      number(index, gid, "group ID")?,
+                gecos:    gecos.to_string(),
+                home:     home.into(),
+                shell:    shell.into(),
+            })
+        })
+        .collect()
+}
+
+/// Parse the content of `/etc/group`. NIS compatibility entries (starting with `+`
+/// or `-`) are skipped.
+///
+/// # Errors
+///
+/// Returns an error if a line does not have four fields or its ID is invalid.
+pub fn parse_group(content: &str) -> ParseResult<Vec<GroupEntry>> {
+    lines(content)
+        .filter(|(_, line)| !line.starts_with(['+', '-']))
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let [name, password, gid, members] = fields[..] else {
+                return Err(invalid(index, "expected 4 fields separated by ':'"));
+            };
+            Ok(GroupEntry {
+                name:     name.to_string(),
+                password: password.to_string(),
+                gid:      number(index, gid, "group ID")?,
+                members:  members
+                    .split(',')
+                    .filter(|member| !member.is_empty())
+                    .map(String::from)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Replace the octal escapes `fstab` uses for whitespace and backslashes (e.g.
+/// `\040` for a space).
+pub(super) fn unescape_octal(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(position) = rest.find('\\') {
+        result.push_str(&rest[..position]);
+        let escape = rest.get(position + 1..position + 4);
+        if let Some(byte) = escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            result.push(char::from(byte));
+            rest = &rest[position + 4..];
+        } else {
+            result.push('\\');
+            rest = &rest[position + 1..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Parse the content of `/etc/fstab`.
+///
+/// # Errors
+///
+/// Returns an error if a line has fewer than four or more than six fields, or its
+/// last two fields are not numbers.
+pub fn parse_fstab(content: &str) -> ParseResult<Vec<FstabEntry>> {
+    lines(content)
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if !(4..=6).contains(&fields.len()) {
+                return Err(invalid(index, "expected 4 to 6 fields"));
+            }
+            let optional = |position: usize, name: &str| {
+                fields
+                    .get(position)
+                    .map_or(Ok(0), |text| number(index, text, name))
+            };
+            Ok(FstabEntry {
+                device:      unescape_octal(fields[0]),
+                mount_point: unescape_octal(fields[1]).into(),
+                filesystem:  fields[2].to_string(),
+                options:     fields[3].split(',').map(String::from).collect(),
+                dump:        optional(4, "dump frequency")?,
+                pass:        optional(5, "pass number")?,
+            })
+        })
+        .collect()
+}
+
+/// Parse the content of `/proc/meminfo`.
+///
+/// # Errors
+///
+/// Returns an error if a line is not a name followed by a number.
+pub fn parse_meminfo(content: &str) -> ParseResult<MemInfo> {
+    let mut memory = MemInfo::default();
+    for (index, line) in lines(content) {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(invalid(index, "expected a name followed by ':'"));
+        };
+        let mut value = value.split_whitespace();
+        let amount: u64 = number(index, value.next().unwrap_or_default(), "amount")?;
+        let amount = match value.next() {
+            Some("kB") => amount * 1024,
+            None => amount,
+            Some(unit) => return Err(invalid(index, format!("unknown unit '{unit}'"))),
+        };
+        match name {
+            "MemTotal" => memory.total = amount,
+            "MemFree" => memory.free = amount,
+            "MemAvailable" => memory.available = amount,
+            "Buffers" => memory.buffers = amount,
+            "Cached" => memory.cached = amount,
+            "SwapTotal" => memory.swap_total = amount,
+            "SwapFree" => memory.swap_free = amount,
+            _ => {},
+        }
+        memory.fields.insert(name.to_string(), amount);
+    }
+    Ok(memory)
+}
+
+/// Parse an address like `0100007F:0050` from `/proc/net/tcp` (or its 32-digit
+/// IPv6 equivalent). The kernel prints the address as 32-bit words in host byte
+/// order.
+fn parse_socket_address(text: &str) -> Option<std::net::SocketAddr> {
+    let (address, port) = text.split_once(':')?;
+    let word = |position: usize| -> Option<[u8; 4]> {
+        let digits = address.get(position * 8..(position + 1) * 8)?;
+        Some(u32::from_str_radix(digits, 16).ok()?.to_ne_bytes())
+    };
+    let address = match address.len() {
+        8 => std::net::IpAddr::from(word(0)?),
+        32 => {
+            let mut bytes = [0; 16];
+            for (position, chunk) in bytes.chunks_exact_mut(4).enumerate() {
+                chunk.copy_from_slice(&word(position)?);
+            }
+            std::net::IpAddr::from(bytes)
+        },
+        _ => return None,
+    };
+    Some(std::net::SocketAddr::new(
+        address,
+        u16::from_str_radix(port, 16).ok()?,
+    ))
+}
+
+/// Parse the content of `/proc/net/tcp` or `/proc/net/tcp6`. The header line is
+/// skipped.
+///
+/// # Errors
+///
+/// Returns an error if a line has too few fields or a field is invalid.
+pub fn parse_tcp(content: &str) -> ParseResult<Vec<TcpSocket>> {
+    lines(content)
+        .filter(|(_, line)| !line.starts_with("sl"))
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return Err(invalid(index, "expected at least 10 fields"));
+            }
+            let address = |text: &str| {
+                parse_socket_address(text)
+                    .ok_or_else(|| invalid(index, format!("'{text}' is not a valid address")))
+            };
+            let state = u8::from_str_radix(fields[3], 16)
+                .map_err(|_| invalid(index, format!("'{}' is not a valid state", fields[3])))?;
+            Ok(TcpSocket {
+                local:  address(fields[1])?,
+                remote: address(fields[2])?,
+                state:  state.into(),
+                uid:    number(index, fields[7], "user ID")?,
+                inode:  number(index, fields[9], "inode")?,
+            })
+        })
+        .collect()
+}
+
+/// Split the first `count` whitespace-separated fields off `line` and return them
+/// with the rest of the line.
+fn split_fields(line: &str, count: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(count);
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Some((fields, rest))
+}
+
+/// Parse a crontab as printed by `crontab -l` (without the user field of
+/// `/etc/crontab`). Values of environment variables may be quoted.
+///
+/// # Errors
+///
+/// Returns an error if a line is neither a job nor an environment variable, or a
+/// job has no command.
+pub fn parse_crontab(content: &str) -> ParseResult<Crontab> {
+    let mut crontab = Crontab::default();
+    for (index, line) in lines(content) {
+        let first = line.split_whitespace().next().unwrap_or_default();
+        let is_time_field = first
+            .chars()
+            .all(|character| character.is_ascii_digit() || "*,-/".contains(character));
+
+        if first.starts_with('@') || is_time_field {
+            let count = if first.starts_with('@') { 1 } else { 5 };
+            let Some((fields, command)) =
+                split_fields(line, count).filter(|(_, command)| !command.is_empty())
+            else {
+                return Err(invalid(index, "the job has no command"));
+            };
+            let schedule = match fields[..] {
+                [special] => CronSchedule::Special(special[1..].to_string()),
+                [minute, hour, day_of_month, month, day_of_week] => CronSchedule::Fields {
+                    minute:       minute.to_string(),
+                    hour:         hour.to_string(),
+                    day_of_month: day_of_month.to_string(),
+                    month:        month.to_string(),
+                    day_of_week:  day_of_week.to_string(),
+                },
+                _ => unreachable!("split_fields returns exactly the requested number of fields"),
+            };
+            crontab.jobs.push(CronJob {
+                schedule,
+                command: command.to_string(),
+            });
+        } else if let Some((name, value)) = line.split_once('=') {
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+                .unwrap_or(value);
+            crontab
+                .environment
+                .push((name.trim().to_string(), value.to_string()));
+        } else {
+            return Err(invalid(index, "expected a job or an environment variable"));
+        }
+    }
+    Ok(crontab)
+}
+
+/// Read and parse `/etc/passwd`, see [`parse_passwd`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn read_passwd() -> ParseResult<Vec<PasswdEntry>> { parse_passwd(&read("/etc/passwd")?) }
+
+/// Read and parse `/etc/group`, see [`parse_group`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn read_group() -> ParseResult<Vec<GroupEntry>> { parse_group(&read("/etc/group")?) }
+
+/// Read and parse `/etc/fstab`, see [`parse_fstab`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn read_fstab() -> ParseResult<Vec<FstabEntry>> { parse_fstab(&read("/etc/fstab")?) }
+
+/// Read and parse `/proc/meminfo`, see [`parse_meminfo`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn read_meminfo() -> ParseResult<MemInfo> { parse_meminfo(&read("/proc/meminfo")?) }
+
+/// Read and parse `/proc/net/tcp` and, if IPv6 is enabled, `/proc/net/tcp6`, see
+/// [`parse_tcp`].
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or parsed.
+pub fn read_tcp() -> ParseResult<Vec<TcpSocket>> {
+    let mut sockets = parse_tcp(&read("/proc/net/tcp")?)?;
+    if std::path::Path::new("/proc/net/tcp6").exists() {
+        sockets.extend(parse_tcp(&read("/proc/net/tcp6")?)?);
+    }
+    Ok(sockets)
+}
+
+/// Run `crontab -l` (for `user` if given, which usually requires root) and parse
+/// its output, see [`parse_crontab`]. A user without a crontab has an empty one.
+///
+/// # Errors
+///
+/// Returns an error if `crontab` cannot be run, fails for another reason than a
+/// missing crontab, or its output cannot be parsed.
+pub fn read_crontab(user: Option<&str>) -> ParseResult<Crontab> {
+    let mut command = crate::process::Command::new("crontab");
+    if let Some(user) = user {
+        command = command.args(["-u", user]);
+    }
+    let output = command.arg("-l").output()?;
+    if output.success() {
+        parse_crontab(&output.stdout)
+    } else if output.stderr.contains("no crontab for") {
+        Ok(Crontab::default())
+    } else {
+        Err(ProcessError::Failed {
+            code:   output.code,
+            stderr: output.stderr,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod parse_test {
+    use super::*;
+
+    #[test]
+    fn users_groups_and_filesystems() -> ParseResult<()> {
+        let users = parse_passwd(concat!(
+            "root:x:0:0:root:/root:/bin/bash\n",
+            "\n",
+            "alice:x:1000:1000:Alice Example,,,:/home/alice:/usr/bin/zsh\n",
+            "+@netgroup::::::\n",
+        ))?;
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[1].name, "alice");
+        assert_eq!(users[1].uid, 1000);
+        assert_eq!(users[1].gecos, "Alice Example,,,");
+        assert_eq!(users[1].shell, std::path::Path::new("/usr/bin/zsh"));
+        assert_eq!(
+            parse_passwd("root:x:0:0:root:/root\n"),
+            Err(ParseError::InvalidLine {
+                line:   1,
+                reason: String::from("expected 7 fields separated by ':'"),
+            })
+        );
+        assert!(parse_passwd("root:x:zero:0:root:/root:/bin/sh").is_err());
+
+        let groups = parse_group("sudo:x:27:alice,bob\nusers:x:100:\n")?;
+        assert_eq!(groups[0].members, ["alice", "bob"]);
+        assert!(groups[1].members.is_empty());
+
+        let filesystems = parse_fstab(concat!(
+            "# <file system> <mount point> <type> <options> <dump> <pass>\n",
+            "UUID=1234 / ext4 errors=remount-ro 0 1\n",
+            "/dev/sdb1 /mnt/my\\040data xfs defaults,noatime\n",
+        ))?;
+        assert_eq!(filesystems[0].pass, 1);
+        assert_eq!(
+            filesystems[1].mount_point,
+            std::path::Path::new("/mnt/my data")
+        );
+        assert_eq!(filesystems[1].options, ["defaults", "noatime"]);
+        assert_eq!(filesystems[1].dump, 0);
+        assert!(parse_fstab("/dev/sda1 /\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn meminfo_and_tcp() -> ParseResult<()> {
+        let memory = parse_meminfo(concat!(
+            "MemTotal:        2048 kB\n",
+            "MemFree:          512 kB\n",
+            "MemAvailable:    1024 kB\n",
+            "HugePages_Total:    4\n",
+        ))?;
+        assert_eq!(memory.total, 2048 * 1024);
+        assert_eq!(memory.free, 512 * 1024);
+        assert_eq!(memory.fields.get("HugePages_Total"), Some(&4));
+        assert!(parse_meminfo("MemTotal: lots\n").is_err());
+
+        // The kernel prints addresses in host byte order.
+        let local = if cfg!(target_endian = "little") {
+            "0100007F"
+        } else {
+            "7F000001"
+        };
+        let sockets = parse_tcp(&format!(
+            "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  \
+             timeout inode\n   0: {local}:0050 00000000:0000 0A 00000000:00000000 00:00000000 \
+             00000000     0        0 12345 1 0000000000000000 100 0 0 10 0\n"
+        ))?;
+        assert_eq!(
+            sockets,
+            [TcpSocket {
+                local:  "127.0.0.1:80".parse().unwrap(),
+                remote: "0.0.0.0:0".parse().unwrap(),
+                state:  TcpState::Listen,
+                uid:    0,
+                inode:  12345,
+            }]
+        );
+        let ipv6 = parse_socket_address("00000000000000000000000001000000:01BB");
+        if cfg!(target_endian = "little") {
+            assert_eq!(ipv6, "[::1]:443".parse().ok());
+        }
+        assert!(parse_tcp("   0: 0100007F:0050\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn crontab() -> ParseResult<()> {
+        let crontab = parse_crontab(concat!(
+            "MAILTO=\"admin@example.com\"\n",
+            "SHELL = /bin/bash\n",
+            "# m h dom mon dow command\n",
+            "*/5 * * * 1-5  /usr/local/bin/check --quiet  > /dev/null\n",
+            "@reboot /usr/local/bin/start\n",
+        ))?;
+        assert_eq!(
+            crontab.environment,
+            [
+                (String::from("MAILTO"), String::from("admin@example.com")),
+                (String::from("SHELL"), String::from("/bin/bash")),
+            ]
+        );
+        assert_eq!(
+            crontab.jobs[0],
+            CronJob {
+                schedule: CronSchedule::Fields {
+                    minute:       String::from("*/5"),
+                    hour:         String::from("*"),
+                    day_of_month: String::from("*"),
+                    month:        String::from("*"),
+                    day_of_week:  String::from("1-5"),
+                },
+                command:  String::from("/usr/local/bin/check --quiet  > /dev/null"),
+            }
+        );
+        assert_eq!(
+            crontab.jobs[1].schedule,
+            CronSchedule::Special(String::from("reboot"))
+        );
+        assert!(parse_crontab("0 4 * * *\n").is_err());
+        assert!(parse_crontab("not a job\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! This module contains querying the power supply and temperatures of the host, so
+//! that scripts can defer heavy work (backups, reindexing) while a laptop runs on
+//! battery or is hot.
+//!
+//! Everything is read from sysfs (`/sys/class/power_supply` and
+//! `/sys/class/thermal`). Like [`facts`](super::facts), querying is best-effort:
+//! hosts without these (e.g. containers or other operating systems) have no
+//! batteries and no thermal zones, and their power source is unknown.
+//!
+//! ```no_run
+//! use rush::system::power;
+//!
+//! if power::on_battery() || power::highest_temperature().is_some_and(|celsius| celsius > 80.0) {
+//!     println!("Deferring the backup");
+//! }
+//! ```
+
+/// The directory the kernel lists power supplies in.
+const POWER_SUPPLIES: &str = "/sys/class/power_supply";
+/// The directory the kernel lists thermal zones in.
+const THERMAL: &str = "/sys/class/thermal";
+
+/// Where the host currently draws its power from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerSource {
+    /// An external power supply, e.g. mains or USB
+    Ac,
+    /// A battery
+    Battery,
+    /// The host has neither batteries nor known external supplies, e.g. desktops
+    /// and virtual machines
+    Unknown,
+}
+
+/// What a battery is doing, as the kernel reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BatteryStatus {
+    Charging,
+    Discharging,
+    Full,
+    /// Connected to external power, but not charging (e.g. because of a charge
+    /// threshold)
+    NotCharging,
+    Unknown,
+}
+
+impl From<&str> for BatteryStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "Charging" => Self::Charging,
+            "Discharging" => Self::Discharging,
+            "Full" => Self::Full,
+            "Not charging" => Self::NotCharging,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// A battery powering the host.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Battery {
+    /// The name the kernel gives the battery, e.g. `BAT0`
+    pub name:       String,
+    /// How full the battery is, in percent
+    pub percentage: Option<u8>,
+    /// What the battery is doing
+    pub status:     BatteryStatus,
+}
+
+/// A sensor measuring the temperature of some part of the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThermalZone {
+    /// The name the kernel gives the zone, e.g. `thermal_zone0`
+    pub name:    String,
+    /// What the zone measures, e.g. `x86_pkg_temp` or `acpitz`
+    pub kind:    String,
+    /// The temperature in degrees Celsius
+    pub celsius: f64,
+}
+
+/// The trimmed content of `path`, or [`None`] if it cannot be read.
+fn read(path: &std::path::Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .map(|content| content.trim().to_string())
+        .ok()
+}
+
+/// The entries of the directory `root`, sorted, or none if it cannot be read.
+fn entries(root: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut entries: Vec<_> = std::fs::read_dir(root).map_or_else(
+        |error| {
+            log::debug!("Could not list '{}': {}", root.display(), error);
+            Vec::new()
+        },
+        |entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect()
+        },
+    );
+    entries.sort();
+    entries
+}
+
+/// The batteries in the power supply directory `root`.
+fn batteries_in(root: &std::path::Path) -> Vec<Battery> {
+    entries(root)
+        .into_iter()
+        // Batteries of peripherals (e.g. mice) have the scope `Device`.
+        .filter(|supply| {
+            read(&supply.join("type")).as_deref() == Some("Battery")
+                && read(&supply.join("scope")).as_deref() != Some("Device")
+        })
+        .filter_map(|supply| {
+            Some(Battery {
+                name:       supply.file_name()?.to_string_lossy().into_owned(),
+                percentage: read(&supply.join("capacity"))
+                    .and_then(|capacity| capacity.parse().ok()),
+                status:     read(&supply.join("status"))
+                    .as_deref()
+                    .map_or(BatteryStatus::Unknown, BatteryStatus::from),
+            })
+        })
+        .collect()
+}
+
+/// The power source according to the power supply directory `root`.
+fn source_in(root: &std::path::Path) -> PowerSource {
+    // Whether any external supply is online, if there are any.
+    let external = entries(root)
+        .iter()
+        .filter(|supply| matches!(read(&supply.join("type")).as_deref(), Some("Mains" | "USB")))
+        .map(|supply| read(&supply.join("online")).as_deref() == Some("1"))
+        .reduce(|first, second| first || second);
+    if external == Some(true) {
+        return PowerSource::Ac;
+    }
+
+    let batteries = batteries_in(root);
+    if batteries.is_empty() {
+        PowerSource::Unknown
+    } else if external == Some(false)
+        || batteries
+            .iter()
+            .any(|battery| battery.status == BatteryStatus::Discharging)
+    {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    }
+}
+
+/// The thermal zones in the thermal directory `root`.
+fn thermal_zones_in(root: &std::path::Path) -> Vec<ThermalZone> {
+    entries(root)
+        .into_iter()
+        .filter_map(|zone| {
+            let name = zone.file_name()?.to_string_lossy().into_owned();
+            if !name.starts_with("thermal_zone") {
+                return None;
+            }
+            // Disabled zones fail reading their temperature.
+            let millicelsius: i32 = read(&zone.join("temp"))?.parse().ok()?;
+            Some(ThermalZone {
+                name,
+                kind: read(&zone.join("type")).unwrap_or_default(),
+                celsius: f64::from(millicelsius) / 1000.0,
+            })
+        })
+        .collect()
+}
+
+/// Where the host currently draws its power from.
+///
+/// The host runs on [`PowerSource::Ac`] if any external supply is online. Otherwise,
+/// it runs on [`PowerSource::Battery`] if it has batteries and either has external
+/// supplies (that are offline) or a battery is discharging.
+#[must_use]
+pub fn source() -> PowerSource { source_in(std::path::Path::new(POWER_SUPPLIES)) }
+
+/// Whether the host currently runs on battery. Hosts whose power source is unknown
+/// do not.
+#[must_use]
+pub fn on_battery() -> bool { source() == PowerSource::Battery }
+
+/// The batteries powering the host, sorted by name. Batteries of peripherals, e.g.
+/// wireless mice, are left out.
+#[must_use]
+pub fn batteries() -> Vec<Battery> { batteries_in(std::path::Path::new(POWER_SUPPLIES)) }
+
+/// How full the batteries of the host are on average, in percent, or [`None`] if
+/// no battery tells.
+#[must_use]
+pub fn battery_percentage() -> Option<u8> {
+    let percentages: Vec<u32> = batteries()
+        .iter()
+        .filter_map(|battery| battery.percentage.map(u32::from))
+        .collect();
+    let count = u32::try_from(percentages.len())
+        .ok()
+        .filter(|count| *count > 0)?;
+    u8::try_from(percentages.iter().sum::<u32>() / count).ok()
+}
+
+/// The thermal zones of the host that report a temperature, sorted by name.
+#[must_use]
+pub fn thermal_zones() -> Vec<ThermalZone> { thermal_zones_in(std::path::Path::new(THERMAL)) }
+
+/// The highest temperature any thermal zone reports, in degrees Celsius, or [`None`]
+/// if there are no thermal zones.
+#[must_use]
+pub fn highest_temperature() -> Option<f64> {
+    thermal_zones()
+        .into_iter()
+        .map(|zone| zone.celsius)
+        .reduce(f64::max)
+}
+
+#[cfg(test)]
+mod power_test {
+    use super::*;
+
+    /// Create the sysfs-like directory `root/name` with the attributes `files`.
+    fn create(root: &std::path::Path, name: &str, files: &[(&str, &str)]) {
+        let directory = root.join(name);
+        std::fs::create_dir_all(&directory).unwrap();
+        for (file, content) in files {
+            std::fs::write(directory.join(file), format!("{content}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn supplies_and_thermal_zones() {
+        let root = crate::fs::generate_test_path();
+        assert_eq!(source_in(&root), PowerSource::Unknown);
+
+        create(
+            &root,
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "42"),
+                ("status", "Discharging"),
+            ],
+        );
+        create(
+            &root,
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "5")],
+        );
+        assert_eq!(
+            batteries_in(&root),
+            [Battery {
+                name:       String::from("BAT0"),
+                percentage: Some(42),
+                status:     BatteryStatus::Discharging,
+            }]
+        );
+        assert_eq!(source_in(&root), PowerSource::Battery);
+
+        create(&root, "AC", &[("type", "Mains"), ("online", "0")]);
+        create(&root, "BAT0", &[("status", "Not charging")]);
+        assert_eq!(source_in(&root), PowerSource::Battery);
+        create(
+            &root,
+            "ucsi-source-psy-USBC000:001",
+            &[("type", "USB"), ("online", "1")],
+        );
+        assert_eq!(source_in(&root), PowerSource::Ac);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        create(
+            &root,
+            "thermal_zone0",
+            &[("type", "acpitz"), ("temp", "45500")],
+        );
+        create(&root, "thermal_zone1", &[("type", "x86_pkg_temp")]);
+        create(&root, "cooling_device0", &[("type", "Processor")]);
+        assert_eq!(
+            thermal_zones_in(&root),
+            [ThermalZone {
+                name:    String::from("thermal_zone0"),
+                kind:    String::from("acpitz"),
+                celsius: 45.5,
+            }]
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
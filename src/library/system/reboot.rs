@@ -0,0 +1,367 @@
+//! This module contains rebooting the host in the middle of a script and continuing
+//! the script after the boot, which upgrades of the operating system need.
+//!
+//! [`reboot_and_resume`] records a marker, arranges for the running program to be
+//! started again with the same arguments once the host is up (with a one-shot
+//! systemd unit or, without systemd, an `@reboot` cron entry) and reboots. After
+//! the boot, [`resumed`] returns the marker, so the script can continue with the
+//! right step, and removes the unit or cron entry again.
+//!
+//! ```no_run
+//! use rush::system::{
+//!     reboot_and_resume,
+//!     resumed,
+//! };
+//!
+//! match resumed().unwrap().as_deref() {
+//!     None => {
+//!         // upgrade the packages
+//!         reboot_and_resume("packages-upgraded").unwrap();
+//!     },
+//!     Some("packages-upgraded") => {
+//!         // upgrade the release
+//!         reboot_and_resume("release-upgraded").unwrap();
+//!     },
+//!     Some(_) => {
+//!         // clean up
+//!     },
+//! }
+//! ```
+
+use crate::{
+    fs::{
+        FSError,
+        File,
+        Object as _,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Describes possible errors when rebooting and resuming.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum RebootError {
+    #[error("The marker is empty")]
+    EmptyMarker,
+    #[error("The program cannot be started again: {0}")]
+    CommandLine(String),
+    #[error("The resume state is not valid: {0}")]
+    InvalidState(String),
+    #[error("Accessing the resume state failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running a command failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`RebootError`].
+pub type RebootResult<T> = Result<T, RebootError>;
+
+/// The directory the resume state of all programs is kept in.
+const STATE_DIRECTORY: &str = "/var/lib/rush/resume";
+/// The directory the one-shot systemd units are installed to.
+const UNIT_DIRECTORY: &str = "/etc/systemd/system";
+/// The directory the `@reboot` cron entries are installed to.
+const CRON_DIRECTORY: &str = "/etc/cron.d";
+
+/// How the program is started again after the boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Mechanism {
+    /// A one-shot systemd unit
+    Systemd,
+    /// An `@reboot` entry in `/etc/cron.d`
+    Cron,
+}
+
+/// What is recorded before the reboot.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct State {
+    /// The marker passed to [`reboot_and_resume`]
+    marker:    String,
+    /// How the program is started again
+    mechanism: Mechanism,
+}
+
+/// The program to start again after the boot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Invocation {
+    /// The executable
+    program:   std::path::PathBuf,
+    /// The arguments, without the name of the program
+    arguments: Vec<String>,
+    /// The working directory
+    directory: std::path::PathBuf,
+}
+
+impl Invocation {
+    /// The invocation of the running program.
+    fn current() -> RebootResult<Self> {
+        let unknown = |error: std::io::Error| RebootError::CommandLine(error.to_string());
+        Ok(Self {
+            program:   std::env::current_exe().map_err(unknown)?,
+            arguments: std::env::args().skip(1).collect(),
+            directory: std::env::current_dir().map_err(unknown)?,
+        })
+    }
+
+    /// The name of the unit, cron entry and state file of `program`: `rush-resume-`
+    /// and the file name of the program, with everything but ASCII letters, digits,
+    /// `-` and `_` replaced by `_` (which is what `/etc/cron.d` accepts).
+    fn name(program: &std::path::Path) -> String {
+        let stem = program
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default()
+            .chars()
+            .map(|character| {
+                if character.is_ascii_alphanumeric() || character == '-' {
+                    character
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        format!("rush-resume-{stem}")
+    }
+
+    /// The one-shot systemd unit that starts this invocation once after the boot.
+    fn unit(&self) -> String {
+        // systemd expands `%` specifiers in all settings and `$` variables in
+        // `ExecStart`, so both are doubled.
+        let quote = |word: &str| {
+            let mut quoted = String::from("\"");
+            for character in word.chars() {
+                match character {
+                    '"' | '\\' => quoted.extend(['\\', character]),
+                    '\n' => quoted.push_str("\\n"),
+                    '%' => quoted.push_str("%%"),
+                    '$' => quoted.push_str("$$"),
+                    _ => quoted.push(character),
+                }
+            }
+            quoted.push('"');
+            quoted
+        };
+        let program = self.program.to_string_lossy();
+        let command = std::iter::once(program.as_ref())
+            .chain(self.arguments.iter().map(String::as_str))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+        [
+            "[Unit]",
+            &format!(
+                "Description=Resume {} after a reboot",
+                program.replace('%', "%%")
+            ),
+            "Wants=network-online.target",
+            "After=network-online.target",
+            "",
+            "[Service]",
+            "Type=oneshot",
+            &format!(
+                "WorkingDirectory={}",
+                self.directory.to_string_lossy().replace('%', "%%")
+            ),
+            &format!("ExecStart={command}"),
+            "",
+            "[Install]",
+            "WantedBy=multi-user.target",
+            "",
+        ]
+        .join("\n")
+    }
+
+    /// The `/etc/cron.d` entry that starts this invocation after the boot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RebootError::CommandLine`] if an argument contains a line break,
+    /// which cron cannot express.
+    fn cron_entry(&self) -> RebootResult<String> {
+        // cron runs the command with `sh`, but turns `%` into line breaks first.
+        let quote = |word: &str| format!("'{}'", word.replace('\'', r"'\''").replace('%', r"\%"));
+        let program = self.program.to_string_lossy();
+        let directory = self.directory.to_string_lossy();
+        let mut words = vec![program.as_ref()];
+        words.extend(self.arguments.iter().map(String::as_str));
+        if words
+            .iter()
+            .chain([&directory.as_ref()])
+            .any(|word| word.contains('\n'))
+        {
+            return Err(RebootError::CommandLine(String::from(
+                "an argument contains a line break",
+            )));
+        }
+        let command = words.into_iter().map(quote).collect::<Vec<_>>().join(" ");
+        Ok(format!(
+            "@reboot root cd {} && {command}\n",
+            quote(&directory)
+        ))
+    }
+}
+
+/// The file the resume state of the program called `name` is kept in.
+fn state_file(name: &str) -> File { File::new(format!("{STATE_DIRECTORY}/{name}.json")) }
+
+/// Reboot the host and start the running program again with the same arguments
+/// and working directory once the host is up, so it can continue after `marker`.
+///
+/// The program runs as `root` after the boot, from a one-shot systemd unit if the
+/// host runs systemd and from an `@reboot` entry in `/etc/cron.d` otherwise. Either
+/// has few environment variables set. [`resumed`] returns `marker` then and removes
+/// the unit or cron entry; if the program does not call it, it is started after
+/// every boot. This function returns once the reboot is requested, so the program
+/// should exit without doing anything else.
+///
+/// # Errors
+///
+/// Returns an error if `marker` is empty, if the unit, the cron entry or the state
+/// cannot be written (which usually requires running as `root`) or if the reboot
+/// cannot be requested.
+pub fn reboot_and_resume(marker: &str) -> RebootResult<()> {
+    if marker.is_empty() {
+        return Err(RebootError::EmptyMarker);
+    }
+    let invocation = Invocation::current()?;
+    let name = Invocation::name(&invocation.program);
+    let mechanism = if super::booted_with_systemd()? {
+        Mechanism::Systemd
+    } else {
+        Mechanism::Cron
+    };
+    log::info!(
+        "Rebooting to resume {} after '{marker}'",
+        invocation.program.display()
+    );
+
+    let state = State {
+        marker: String::from(marker),
+        mechanism,
+    };
+    let state = serde_json::to_string(&state)
+        .map_err(|error| RebootError::InvalidState(error.to_string()))?;
+    let file = state_file(&name);
+    file.create_on_fs_recursive()?;
+    file.overwrite(state)?;
+
+    match mechanism {
+        Mechanism::Systemd => {
+            File::new(format!("{UNIT_DIRECTORY}/{name}.service")).overwrite(invocation.unit())?;
+            Command::new("systemctl")
+                .args(["enable", &format!("{name}.service")])
+                .run()?;
+            Command::new("systemctl").arg("reboot").run()?;
+        },
+        Mechanism::Cron => {
+            File::new(format!("{CRON_DIRECTORY}/{name}")).overwrite(invocation.cron_entry()?)?;
+            Command::new("shutdown").args(["-r", "now"]).run()?;
+        },
+    }
+    Ok(())
+}
+
+/// The marker passed to [`reboot_and_resume`] if the running program was started
+/// again after the reboot it requested, or [`None`] otherwise.
+///
+/// Returning the marker removes the systemd unit or cron entry, so the program is
+/// only started again once.
+///
+/// # Errors
+///
+/// Returns an error if the recorded state cannot be read or the unit or cron entry
+/// cannot be removed. The state is kept then, so calling this again retries.
+pub fn resumed() -> RebootResult<Option<String>> {
+    let invocation = Invocation::current()?;
+    let name = Invocation::name(&invocation.program);
+    let file = state_file(&name);
+    if !file.exists()? {
+        return Ok(None);
+    }
+    let state: State = serde_json::from_str(&file.read()?)
+        .map_err(|error| RebootError::InvalidState(error.to_string()))?;
+    log::info!("Resuming after '{}'", state.marker);
+
+    match state.mechanism {
+        Mechanism::Systemd => {
+            Command::new("systemctl")
+                .args(["disable", &format!("{name}.service")])
+                .run()?;
+            File::new(format!("{UNIT_DIRECTORY}/{name}.service")).delete_from_fs()?;
+        },
+        Mechanism::Cron => File::new(format!("{CRON_DIRECTORY}/{name}")).delete_from_fs()?,
+    }
+    file.delete_from_fs()?;
+    Ok(Some(state.marker))
+}
+
+#[cfg(test)]
+mod reboot_test {
+    use super::*;
+
+    /// An invocation whose arguments need quoting.
+    fn invocation() -> Invocation {
+        Invocation {
+            program:   "/usr/local/bin/upgrade.rs".into(),
+            arguments: vec![
+                String::from("--to"),
+                String::from("it's 100%"),
+                String::from("$HOME \"x\""),
+            ],
+            directory: "/root".into(),
+        }
+    }
+
+    #[test]
+    fn unit_and_cron_entry() -> RebootResult<()> {
+        let invocation = invocation();
+        assert_eq!(Invocation::name(&invocation.program), "rush-resume-upgrade");
+        assert_eq!(
+            Invocation::name(std::path::Path::new("/opt/my tool.v2")),
+            "rush-resume-my_tool"
+        );
+
+        let unit = invocation.unit();
+        assert!(unit.contains("Type=oneshot\nWorkingDirectory=/root\n"));
+        assert!(unit.contains(
+            "ExecStart=\"/usr/local/bin/upgrade.rs\" \"--to\" \"it's 100%%\" \"$$HOME \
+             \\\"x\\\"\"\n"
+        ));
+        assert!(unit.contains("WantedBy=multi-user.target"));
+
+        assert_eq!(
+            invocation.cron_entry()?,
+            "@reboot root cd '/root' && '/usr/local/bin/upgrade.rs' '--to' 'it'\\''s 100\\%' \
+             '$HOME \"x\"'\n"
+        );
+        let mut invalid = invocation;
+        invalid.arguments.push(String::from("a\nb"));
+        assert!(matches!(
+            invalid.cron_entry(),
+            Err(RebootError::CommandLine(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_once() -> RebootResult<()> {
+        let _memory = crate::fs::MemoryBackend::install();
+        assert_eq!(resumed()?, None);
+        assert_eq!(reboot_and_resume(""), Err(RebootError::EmptyMarker));
+
+        let name = Invocation::name(&Invocation::current()?.program);
+        let entry = File::new(format!("{CRON_DIRECTORY}/{name}"));
+        entry.create_on_fs_recursive()?;
+        let state = state_file(&name);
+        state.create_on_fs_recursive()?;
+        state.overwrite(r#"{"marker":"upgraded","mechanism":"cron"}"#)?;
+
+        assert_eq!(resumed()?, Some(String::from("upgraded")));
+        assert!(!entry.exists()?);
+        assert_eq!(resumed()?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! This module contains helpers for the interactive configuration files of login
+//! shells, which developer-setup scripts extend with `PATH` entries, aliases and
+//! environment variables.
+//!
+//! Additions are [marked blocks](crate::fs::ensure_block), so running a script again
+//! updates its block instead of appending a duplicate, and the block can be removed
+//! again by its marker.
+//!
+//! ```no_run
+//! use rush::system::shellrc::{
+//!     add_to_profile,
+//!     remove_from_profile,
+//!     Shell,
+//! };
+//!
+//! add_to_profile("rush: cargo", "export PATH=\"$HOME/.cargo/bin:$PATH\"", &[
+//!     Shell::Bash,
+//!     Shell::Zsh,
+//! ])
+//! .unwrap();
+//! add_to_profile("rush: cargo", "fish_add_path $HOME/.cargo/bin", &[Shell::Fish]).unwrap();
+//! remove_from_profile("rush: cargo").unwrap();
+//! ```
+
+use crate::fs::FSError;
+
+/// Describes possible errors when changing shell profiles.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum ShellrcError {
+    #[error("The home directory is unknown because HOME is not set")]
+    NoHome,
+    #[error("Changing the profile failed: {0}")]
+    FS(#[from] FSError),
+}
+
+/// A [`Result`] whose error variant is a [`ShellrcError`].
+pub type ShellrcResult<T> = Result<T, ShellrcError>;
+
+/// A shell whose interactive configuration file can be changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Shell {
+    /// Bash, configured in `~/.bashrc`
+    Bash,
+    /// Zsh, configured in `$ZDOTDIR/.zshrc` (`~/.zshrc` by default)
+    Zsh,
+    /// Fish, configured in `$XDG_CONFIG_HOME/fish/config.fish`
+    /// (`~/.config/fish/config.fish` by default)
+    Fish,
+}
+
+impl Shell {
+    /// All shells.
+    pub const ALL: [Self; 3] = [Self::Bash, Self::Zsh, Self::Fish];
+
+    /// The configuration file of this shell for the current user.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `HOME` is not set.
+    pub fn profile(self) -> ShellrcResult<std::path::PathBuf> {
+        self.profile_with(|name| std::env::var_os(name))
+    }
+
+    /// The configuration file of this shell, looking up environment variables with
+    /// `variable`. Empty variables count as unset.
+    fn profile_with(
+        self,
+        variable: impl Fn(&str) -> Option<std::ffi::OsString>,
+    ) -> ShellrcResult<std::path::PathBuf> {
+        let directory = |name: &str| {
+            variable(name)
+                .filter(|value| !value.is_empty())
+                .map(std::path::PathBuf::from)
+        };
+        let home = || directory("HOME").ok_or(ShellrcError::NoHome);
+        Ok(match self {
+            Self::Bash => home()?.join(".bashrc"),
+            Self::Zsh => directory("ZDOTDIR").map_or_else(home, Ok)?.join(".zshrc"),
+            Self::Fish => directory("XDG_CONFIG_HOME")
+                .map_or_else(|| home().map(|home| home.join(".config")), Ok)?
+                .join("fish/config.fish"),
+        })
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Add `line_or_block` to the configuration files of `shells` as the block named
+/// `marker`. Returns the files that were changed.
+///
+/// Missing files are created. A block with the same marker is replaced, so calling
+/// this again with the same arguments changes nothing.
+///
+/// # Errors
+///
+/// Returns an error if `HOME` is not set or if changing a file fails. Files handled
+/// before the failure keep their changes.
+pub fn add_to_profile(
+    marker: &str,
+    line_or_block: &str,
+    shells: &[Shell],
+) -> ShellrcResult<Vec<std::path::PathBuf>> {
+    let mut changed = vec![];
+    for shell in shells {
+        let profile = shell.profile()?;
+        if crate::fs::ensure_block(&profile, marker, line_or_block)? {
+            log::debug!("Added '{marker}' to the {shell} profile");
+            changed.push(profile);
+        }
+    }
+    Ok(changed)
+}
+
+/// Remove the block named `marker` from the configuration files of all shells.
+/// Returns the files that were changed.
+///
+/// # Errors
+///
+/// Returns an error if `HOME` is not set or if changing a file fails.
+pub fn remove_from_profile(marker: &str) -> ShellrcResult<Vec<std::path::PathBuf>> {
+    let mut changed = vec![];
+    for shell in Shell::ALL {
+        let profile = shell.profile()?;
+        if crate::fs::remove_block(&profile, marker)? {
+            log::debug!("Removed '{marker}' from the {shell} profile");
+            changed.push(profile);
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod shellrc_test {
+    use super::*;
+
+    /// Look up environment variables in `set`.
+    fn variables(
+        set: &'static [(&'static str, &'static str)],
+    ) -> impl Fn(&str) -> Option<std::ffi::OsString> {
+        move |name| {
+            set.iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| std::ffi::OsString::from(value))
+        }
+    }
+
+    #[test]
+    fn profiles() {
+        let home = variables(&[("HOME", "/home/user"), ("ZDOTDIR", "")]);
+        let paths: Vec<_> = Shell::ALL
+            .iter()
+            .map(|shell| shell.profile_with(&home).unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                std::path::PathBuf::from("/home/user/.bashrc"),
+                "/home/user/.zshrc".into(),
+                "/home/user/.config/fish/config.fish".into(),
+            ]
+        );
+
+        let custom = variables(&[("ZDOTDIR", "/zsh"), ("XDG_CONFIG_HOME", "/config")]);
+        assert_eq!(Shell::Bash.profile_with(&custom), Err(ShellrcError::NoHome));
+        assert_eq!(Shell::Zsh.profile_with(&custom), Ok("/zsh/.zshrc".into()));
+        assert_eq!(
+            Shell::Fish.profile_with(&custom),
+            Ok("/config/fish/config.fish".into())
+        );
+    }
+
+    #[test]
+    fn add_and_remove() -> ShellrcResult<()> {
+        if std::env::var_os("HOME").is_none_or(|home| home.is_empty()) {
+            return Ok(());
+        }
+        let _memory = crate::fs::MemoryBackend::install();
+        let bash = Shell::Bash.profile()?;
+        let fish = Shell::Fish.profile()?;
+
+        let added = add_to_profile("test", "alias ll='ls -l'", &[Shell::Bash, Shell::Fish])?;
+        assert_eq!(added, [bash.clone(), fish.clone()]);
+        assert!(add_to_profile("test", "alias ll='ls -l'", &[Shell::Bash])?.is_empty());
+        assert_eq!(
+            add_to_profile("test", "alias ll='ls -al'", &[Shell::Bash])?,
+            std::slice::from_ref(&bash)
+        );
+        assert_eq!(remove_from_profile("test")?, [bash, fish]);
+        assert!(remove_from_profile("test")?.is_empty());
+        Ok(())
+    }
+}
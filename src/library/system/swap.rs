@@ -0,0 +1,401 @@
+//! This module contains creating and removing swap files, including enabling them
+//! and persisting them in `/etc/fstab`.
+//!
+//! [`create`] replaces the usual shell snippet of `fallocate` (or `dd`), `chmod`,
+//! `mkswap`, `swapon` and editing `/etc/fstab`. Every step is skipped if it is done
+//! already, so running a script again changes nothing.
+//!
+//! ```no_run
+//! use rush::system::swap;
+//!
+//! // 2 GiB of swap that survives reboots
+//! swap::create("/swapfile", 2 * 1024 * 1024 * 1024).unwrap();
+//! for area in swap::active().unwrap() {
+//!     println!("{}: {} of {} bytes used", area.path.display(), area.used, area.size);
+//! }
+//! ```
+
+use super::parse::{
+    parse_fstab,
+    unescape_octal,
+    ParseError,
+};
+use crate::{
+    fs::{
+        FSError,
+        File,
+        Object as _,
+        Permissions,
+    },
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// Describes possible errors when managing swap.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum SwapError {
+    #[error("A swap file needs at least one mebibyte")]
+    TooSmall,
+    #[error("'{0}' is not an absolute path")]
+    RelativePath(std::path::PathBuf),
+    #[error("'{0}' exists but is not a swap file")]
+    NotSwap(std::path::PathBuf),
+    #[error("Swap files are not supported on {0} filesystems")]
+    UnsupportedFilesystem(String),
+    #[error("{needed} bytes are needed but only {available} bytes are free")]
+    InsufficientSpace { needed: u64, available: u64 },
+    #[error("Reading the swap or filesystem table failed: {0}")]
+    Parse(#[from] ParseError),
+    #[error("Accessing the swap file failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Running a program failed: {0}")]
+    Process(#[from] ProcessError),
+}
+
+/// A [`Result`] whose error variant is a [`SwapError`].
+pub type SwapResult<T> = Result<T, SwapError>;
+
+/// The smallest swap file [`create`] makes.
+const MINIMUM_SIZE: u64 = MEBIBYTE;
+/// Swap files are allocated in multiples of this many bytes.
+const MEBIBYTE: u64 = 1024 * 1024;
+/// The file listing the active swap areas.
+const SWAPS: &str = "/proc/swaps";
+/// The filesystem table.
+const FSTAB: &str = "/etc/fstab";
+/// The signature `mkswap` writes to the end of the first page.
+const SIGNATURE: &[u8] = b"SWAPSPACE2";
+/// The page sizes a swap file may have been created with.
+const PAGE_SIZES: [usize; 4] = [4096, 8192, 16384, 65536];
+/// How many bytes of a file are read to find the signature, the largest page size.
+const SAMPLE_SIZE: u64 = 65536;
+/// The filesystems the kernel cannot swap to.
+const UNSUPPORTED_FILESYSTEMS: [&str; 9] = [
+    "tmpfs", "ramfs", "overlay", "squashfs", "nfs", "nfs4", "cifs", "smb3", "zfs",
+];
+
+/// An active swap area, as listed in `/proc/swaps`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct SwapArea {
+    /// The swap file or partition
+    pub path:     std::path::PathBuf,
+    /// Whether it is a `file` or a `partition`
+    pub kind:     String,
+    /// Its size in bytes
+    pub size:     u64,
+    /// How much of it is used, in bytes
+    pub used:     u64,
+    /// Its priority; higher priorities are used first
+    pub priority: i32,
+}
+
+/// Parse the content of `/proc/swaps`.
+fn parse_swaps(content: &str) -> SwapResult<Vec<SwapArea>> {
+    content
+        .lines()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let invalid = |reason: &str| ParseError::InvalidLine {
+                line:   index + 1,
+                reason: String::from(reason),
+            };
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [path, kind, size, used, priority] = fields[..] else {
+                return Err(invalid("expected 5 fields").into());
+            };
+            let kibibytes = |text: &str| {
+                text.parse::<u64>()
+                    .map(|kibibytes| kibibytes * 1024)
+                    .map_err(|_| invalid("the size is not a number"))
+            };
+            Ok(SwapArea {
+                path:     unescape_octal(path).into(),
+                kind:     String::from(kind),
+                size:     kibibytes(size)?,
+                used:     kibibytes(used)?,
+                priority: priority
+                    .parse()
+                    .map_err(|_| invalid("the priority is not a number"))?,
+            })
+        })
+        .collect()
+}
+
+/// The active swap areas.
+///
+/// # Errors
+///
+/// Returns an error if `/proc/swaps` cannot be read or parsed.
+pub fn active() -> SwapResult<Vec<SwapArea>> { parse_swaps(&File::new(SWAPS).read()?) }
+
+/// Whether `sample`, the start of a file, has the signature of a swap file.
+fn has_signature(sample: &[u8]) -> bool {
+    PAGE_SIZES
+        .iter()
+        .any(|page_size| sample.get(page_size - SIGNATURE.len()..*page_size) == Some(SIGNATURE))
+}
+
+/// `path` escaped for `/etc/fstab`.
+fn escape(path: &std::path::Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', r"\134")
+        .replace(' ', r"\040")
+        .replace('\t', r"\011")
+}
+
+/// Whether a line of `/etc/fstab` enables the swap file `path`.
+fn is_entry(line: &str, path: &std::path::Path) -> bool {
+    parse_fstab(line).is_ok_and(|entries| {
+        entries
+            .iter()
+            .any(|entry| entry.filesystem == "swap" && std::path::Path::new(&entry.device) == path)
+    })
+}
+
+/// `fstab` with an entry enabling the swap file `path`, or [`None`] if it has one
+/// already.
+fn with_entry(fstab: &str, path: &std::path::Path) -> Option<String> {
+    if fstab.lines().any(|line| is_entry(line, path)) {
+        return None;
+    }
+    let mut fstab = String::from(fstab);
+    if !fstab.is_empty() && !fstab.ends_with('\n') {
+        fstab.push('\n');
+    }
+    fstab.push_str(&escape(path));
+    fstab.push_str(" none swap defaults 0 0\n");
+    Some(fstab)
+}
+
+/// `fstab` without entries enabling the swap file `path`, or [`None`] if it has
+/// none.
+fn without_entry(fstab: &str, path: &std::path::Path) -> Option<String> {
+    let lines: Vec<_> = fstab.lines().filter(|line| !is_entry(line, path)).collect();
+    if lines.len() == fstab.lines().count() {
+        return None;
+    }
+    Some(lines.iter().flat_map(|line| [*line, "\n"]).collect())
+}
+
+/// Change `/etc/fstab` with `change`. Returns whether it changed.
+fn update_fstab(change: impl FnOnce(&str) -> Option<String>) -> SwapResult<bool> {
+    let fstab = File::new(FSTAB);
+    let content = if fstab.exists()? {
+        fstab.read()?
+    } else {
+        String::new()
+    };
+    match change(&content) {
+        Some(content) => {
+            log::debug!("Updating {}", fstab);
+            fstab.overwrite(content)?;
+            Ok(true)
+        },
+        None => Ok(false),
+    }
+}
+
+/// The type of the filesystem `path` is on, e.g. `ext4`.
+fn filesystem_of(path: &std::path::Path) -> SwapResult<String> {
+    let output = Command::new("findmnt")
+        .args(["--noheadings", "--output", "FSTYPE", "--target"])
+        .arg(path.to_string_lossy())
+        .run()?;
+    Ok(output.stdout.trim().to_string())
+}
+
+/// The number of bytes that can be written to the filesystem `path` is on.
+fn available_space(path: &std::path::Path) -> SwapResult<u64> {
+    let statistics = nix::sys::statvfs::statvfs(path)
+        .map_err(|error| FSError::from(std::io::Error::from(error)))?;
+    Ok(statistics.blocks_available() * statistics.fragment_size())
+}
+
+/// Allocate the swap file `path` of `size` bytes on `filesystem` and format it.
+fn allocate(path: &std::path::Path, size: u64, filesystem: &str) -> SwapResult<()> {
+    let file = File::new(path);
+    file.write_new("")?;
+    // Nobody but root may read what is swapped out.
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    if filesystem == "btrfs" {
+        // Swap files must not be copy-on-write; this only works on empty files.
+        Command::new("chattr")
+            .args(["+C"])
+            .arg(path.to_string_lossy())
+            .run()?;
+    }
+    // `fallocate` is fast, but other filesystems may leave holes the kernel rejects.
+    let mebibytes = size / MEBIBYTE;
+    if filesystem.starts_with("ext") || filesystem == "xfs" {
+        Command::new("fallocate")
+            .args(["--length", &(mebibytes * MEBIBYTE).to_string()])
+            .arg(path.to_string_lossy())
+            .run()?;
+    } else {
+        Command::new("dd")
+            .args(["if=/dev/zero", "bs=1M", &format!("count={mebibytes}")])
+            .arg(format!("of={}", path.to_string_lossy()))
+            .run()?;
+    }
+    Command::new("mkswap").arg(path.to_string_lossy()).run()?;
+    Ok(())
+}
+
+/// `path` if it is absolute. Relative paths are rejected rather than resolved, as
+/// they would never match the paths in `/proc/swaps` and `/etc/fstab`.
+fn absolute(path: &std::path::Path) -> SwapResult<&std::path::Path> {
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Err(SwapError::RelativePath(path.to_path_buf()))
+    }
+}
+
+/// Create the swap file `path` with `size` bytes (rounded up to whole mebibytes),
+/// enable it and add it to `/etc/fstab`. Returns whether anything changed.
+///
+/// An existing swap file is kept as it is, whatever its size, and only enabled and
+/// added to `/etc/fstab` if it is not yet. Unless the file exists already, it is
+/// checked that its filesystem supports swap files and has enough free space.
+///
+/// # Errors
+///
+/// Returns [`SwapError::RelativePath`] if `path` is relative, as the kernel and
+/// `/etc/fstab` list swap files by their absolute paths, [`SwapError::NotSwap`] if
+/// `path` exists but is not a swap file, [`SwapError::UnsupportedFilesystem`] or
+/// [`SwapError::InsufficientSpace`] if the file cannot be created there, and other
+/// errors if a step fails (usually because of missing privileges). A newly created file
+/// is deleted again if it cannot be formatted.
+pub fn create(path: impl AsRef<std::path::Path>, size: u64) -> SwapResult<bool> {
+    let path = absolute(path.as_ref())?;
+    if size < MINIMUM_SIZE {
+        return Err(SwapError::TooSmall);
+    }
+    let size = size.div_ceil(MEBIBYTE) * MEBIBYTE;
+    let file = File::new(path);
+    let mut changed = false;
+
+    if file.exists()? {
+        if !has_signature(&file.sample(SAMPLE_SIZE)?) {
+            return Err(SwapError::NotSwap(path.to_path_buf()));
+        }
+    } else {
+        let directory = path.parent().unwrap_or_else(|| std::path::Path::new("/"));
+        let filesystem = filesystem_of(directory)?;
+        if UNSUPPORTED_FILESYSTEMS.contains(&filesystem.as_str()) || filesystem.starts_with("fuse")
+        {
+            return Err(SwapError::UnsupportedFilesystem(filesystem));
+        }
+        let available = available_space(directory)?;
+        if available < size {
+            return Err(SwapError::InsufficientSpace {
+                needed: size,
+                available,
+            });
+        }
+
+        log::info!("Creating the swap file {} with {} bytes", file, size);
+        if let Err(error) = allocate(path, size, &filesystem) {
+            file.delete_from_fs()?;
+            return Err(error);
+        }
+        changed = true;
+    }
+
+    if !active()?.iter().any(|area| area.path == path) {
+        log::debug!("Enabling the swap file {}", file);
+        Command::new("swapon").arg(path.to_string_lossy()).run()?;
+        changed = true;
+    }
+    Ok(update_fstab(|fstab| with_entry(fstab, path))? || changed)
+}
+
+/// Disable the swap file `path`, remove it from `/etc/fstab` and delete it. Returns
+/// whether anything changed.
+///
+/// # Errors
+///
+/// Returns [`SwapError::RelativePath`] if `path` is relative,
+/// [`SwapError::NotSwap`] if `path` exists but is not a swap file (it is not
+/// deleted then), and other errors if a step fails, e.g. because the swapped
+/// out memory does not fit into the remaining memory.
+pub fn remove(path: impl AsRef<std::path::Path>) -> SwapResult<bool> {
+    let path = absolute(path.as_ref())?;
+    let file = File::new(path);
+    if file.exists()? && !has_signature(&file.sample(SAMPLE_SIZE)?) {
+        return Err(SwapError::NotSwap(path.to_path_buf()));
+    }
+    let mut changed = active()?.iter().any(|area| area.path == path);
+    if changed {
+        log::debug!("Disabling the swap file {}", file);
+        Command::new("swapoff").arg(path.to_string_lossy()).run()?;
+    }
+    changed |= update_fstab(|fstab| without_entry(fstab, path))?;
+    if file.exists()? {
+        log::info!("Deleting the swap file {}", file);
+        file.delete_from_fs()?;
+        changed = true;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod swap_test {
+    use super::*;
+
+    #[test]
+    fn swaps_signatures_and_fstab() -> SwapResult<()> {
+        let swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n/swap\\040file \
+                     \t\t\t\tfile\t\t2097148\t\t1024\t\t-2\n/dev/sda2 partition 1024 0 10\n";
+        assert_eq!(
+            parse_swaps(swaps)?,
+            [
+                SwapArea {
+                    path:     "/swap file".into(),
+                    kind:     String::from("file"),
+                    size:     2_097_148 * 1024,
+                    used:     1024 * 1024,
+                    priority: -2,
+                },
+                SwapArea {
+                    path:     "/dev/sda2".into(),
+                    kind:     String::from("partition"),
+                    size:     1024 * 1024,
+                    used:     0,
+                    priority: 10,
+                },
+            ]
+        );
+        assert!(parse_swaps("header\n/swap file\n").is_err());
+
+        let mut page = vec![0; 4096];
+        assert!(!has_signature(&page));
+        page[4086..].copy_from_slice(SIGNATURE);
+        assert!(has_signature(&page));
+
+        let path = std::path::Path::new("/swap file");
+        let fstab = "UUID=abc / ext4 defaults 0 1";
+        let added = with_entry(fstab, path).unwrap_or_default();
+        assert_eq!(
+            added,
+            "UUID=abc / ext4 defaults 0 1\n/swap\\040file none swap defaults 0 0\n"
+        );
+        assert_eq!(with_entry(&added, path), None);
+        assert_eq!(
+            without_entry(&added, path).as_deref(),
+            Some("UUID=abc / ext4 defaults 0 1\n")
+        );
+        assert_eq!(without_entry(fstab, path), None);
+
+        for path in ["swapfile", "./swapfile"] {
+            let error = Err(SwapError::RelativePath(path.into()));
+            assert_eq!(create(path, MINIMUM_SIZE), error);
+            assert_eq!(remove(path), error);
+        }
+        Ok(())
+    }
+}
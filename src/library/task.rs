@@ -0,0 +1,202 @@
+//! This module contains structured concurrency for background work: [`scope`] runs
+//! workers in parallel and only returns once all of them have finished.
+//!
+//! When a worker fails, the shared [`CancellationToken`] is cancelled so that the
+//! other workers can stop early, and [`scope`] returns the first error.
+//!
+//! ```
+//! let sizes = rush::scope(|scope| {
+//!     let small = scope.spawn(|_| Ok::<_, std::io::Error>(1 + 1));
+//!     let large = scope.spawn(|_| Ok(1_000 * 1_000));
+//!     (small.join(), large.join())
+//! })
+//! .unwrap();
+//! assert_eq!(sizes, (Some(2), Some(1_000_000)));
+//! ```
+
+use std::sync::{
+    atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    Arc,
+    Mutex,
+    PoisonError,
+};
+
+/// Tells workers that they should stop. Clones share the same state, so cancelling
+/// one cancels all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    /// Whether the token was cancelled
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled.
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Cancel the token and all of its clones.
+    pub fn cancel(&self) { self.cancelled.store(true, Ordering::SeqCst); }
+
+    /// Whether the token was cancelled. Long-running workers should check this
+    /// regularly and return early if it is.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::SeqCst) }
+}
+
+/// Cancels a token when the worker it belongs to panics.
+struct CancelOnPanic<'token>(&'token CancellationToken);
+
+impl Drop for CancelOnPanic<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.cancel();
+        }
+    }
+}
+
+/// Starts workers inside a call to [`scope`].
+#[derive(Debug)]
+pub struct Scope<'scope, 'env: 'scope, E> {
+    /// The scope the workers' threads run in
+    threads: &'scope std::thread::Scope<'scope, 'env>,
+    /// The token shared by all workers
+    token:   CancellationToken,
+    /// The first error a worker returned
+    error:   Arc<Mutex<Option<E>>>,
+}
+
+impl<'scope, E: Send + 'scope> Scope<'scope, '_, E> {
+    /// Run `work` on a new thread, which inherits the [`Deadline`] in effect. It
+    /// receives the shared [`CancellationToken`]; if it returns an error, the token is
+    /// cancelled.
+    ///
+    /// [`Deadline`]: crate::deadline::Deadline
+    pub fn spawn<T, F>(&self, work: F) -> Worker<'scope, T>
+    where
+        T: Send + 'scope,
+        F: FnOnce(&CancellationToken) -> Result<T, E> + Send + 'scope,
+    {
+        let token = self.token.clone();
+        let error = Arc::clone(&self.error);
+        let deadline = crate::deadline::current();
+        let handle = self.threads.spawn(move || {
+            let _guard = CancelOnPanic(&token);
+            let _deadline = deadline.map(crate::deadline::Deadline::install);
+            match work(&token) {
+                Ok(result) => Some(result),
+                Err(failure) => {
+                    // The error is recorded first, so that it wins over the errors of
+                    // workers that stop because of the cancellation.
+                    error
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get_or_insert(failure);
+                    token.cancel();
+                    None
+                },
+            }
+        });
+        Worker { handle }
+    }
+
+    /// The token shared by all workers of this scope.
+    #[must_use]
+    pub const fn token(&self) -> &CancellationToken { &self.token }
+}
+
+/// A worker started by [`Scope::spawn`].
+#[derive(Debug)]
+pub struct Worker<'scope, T> {
+    /// The thread the worker runs on
+    handle: std::thread::ScopedJoinHandle<'scope, Option<T>>,
+}
+
+impl<T> Worker<'_, T> {
+    /// Wait for the worker to finish and return its result, or [`None`] if it
+    /// failed. The error is returned by [`scope`].
+    ///
+    /// # Panics
+    ///
+    /// Panics with the worker's panic if it panicked.
+    #[must_use]
+    pub fn join(self) -> Option<T> {
+        self.handle
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// Run `body`, which starts workers with [`Scope::spawn`], and wait for all workers
+/// to finish, joined or not. Returns what `body` returned, or the first error of a
+/// worker.
+///
+/// # Errors
+///
+/// Returns the first error a worker returned.
+///
+/// # Panics
+///
+/// Panics if a worker that was not joined panicked. The other workers are
+/// cancelled and still waited for.
+pub fn scope<'env, E, T, F>(body: F) -> Result<T, E>
+where
+    E: Send,
+    F: for<'scope> FnOnce(&Scope<'scope, 'env, E>) -> T,
+{
+    let error = Arc::new(Mutex::new(None));
+    let result = std::thread::scope(|threads| {
+        body(&Scope {
+            threads,
+            token: CancellationToken::new(),
+            error: Arc::clone(&error),
+        })
+    });
+    // All workers have finished, so nobody else holds the lock.
+    let first_error = error.lock().unwrap_or_else(PoisonError::into_inner).take();
+    first_error.map_or(Ok(result), Err)
+}
+
+#[cfg(test)]
+mod task_test {
+    use super::*;
+
+    #[test]
+    fn first_error_cancels_siblings() {
+        let result: Result<(), String> = scope(|scope| {
+            for index in 0..3 {
+                scope.spawn(move |token| {
+                    let started = std::time::Instant::now();
+                    while !token.is_cancelled() {
+                        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+                        std::thread::sleep(std::time::Duration::from_millis(1));
+                    }
+                    Err::<(), _>(format!("worker {index} was cancelled"))
+                });
+            }
+            scope.spawn(|_| Err::<(), _>(String::from("failed")));
+        });
+        assert_eq!(result, Err(String::from("failed")));
+    }
+
+    #[test]
+    fn panics_cancel_siblings() {
+        let cancelled = std::sync::atomic::AtomicBool::new(false);
+        let result = std::panic::catch_unwind(|| {
+            scope::<(), _, _>(|scope| {
+                scope.spawn(|token| {
+                    while !token.is_cancelled() {
+                        std::thread::sleep(std::time::Duration::from_millis(1));
+                    }
+                    cancelled.store(true, Ordering::SeqCst);
+                    Ok(())
+                });
+                scope.spawn(|_| -> Result<(), ()> { panic!("the worker panics") });
+            })
+        });
+        assert!(result.is_err());
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+}
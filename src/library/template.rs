@@ -0,0 +1,24 @@
+//! This module contains a minimal template renderer for the modules that generate
+//! configuration files from user-provided templates.
+
+/// Replace every `{{ name }}` in `template` with the value of the variable `name`.
+/// If a variable is not in `variables`, its name is returned as error.
+pub fn render(
+    template: &str,
+    variables: &std::collections::BTreeMap<String, String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + length].trim();
+        let value = variables.get(name).ok_or_else(|| name.to_string())?;
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(value);
+        rest = &rest[start + length + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
@@ -0,0 +1,491 @@
+//! This module contains functionality for processing delimited text files, like
+//! the exports of spreadsheets or the output of other tools.
+//!
+//! [`join`] combines the records of two files that share a key, following the
+//! semantics of coreutils' `join`: a joined record consists of the key, the other
+//! fields of the first file and the other fields of the second file. Records whose
+//! key occurs several times are combined with every match.
+//!
+//! [`merge_logs`] interleaves the lines of several log files in chronological order.
+
+mod merge;
+
+pub use merge::{
+    merge_logs,
+    MergedLogs,
+    TimestampFormat,
+};
+
+use crate::fs::{
+    FSError,
+    File,
+    Object as _,
+};
+
+/// Describes possible errors when processing delimited files.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum TextError {
+    #[error("Accessing a file failed: {0}")]
+    FS(#[from] FSError),
+    #[error("Columns start at 1")]
+    InvalidColumn,
+    #[error("The record '{0}' is not sorted by its key")]
+    Unsorted(String),
+}
+
+/// A [`Result`] whose error variant is a [`TextError`].
+pub type TextResult<T> = Result<T, TextError>;
+
+/// A record, i.e. the fields of a line.
+pub type Record = Vec<String>;
+
+/// Which records end up in the result of a join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JoinKind {
+    /// Only records whose key is in both files
+    Inner,
+    /// Additionally records of the first file without a match, like `join -a 1`
+    Left,
+    /// Additionally records of the second file without a match, like `join -a 2`
+    Right,
+    /// Additionally records of both files without a match, like `join -a 1 -a 2`
+    Full,
+}
+
+impl JoinKind {
+    /// Whether unmatched records of the first file are kept.
+    const fn keeps_first(self) -> bool { matches!(self, Self::Left | Self::Full) }
+
+    /// Whether unmatched records of the second file are kept.
+    const fn keeps_second(self) -> bool { matches!(self, Self::Right | Self::Full) }
+}
+
+/// How the records of the two files are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JoinStrategy {
+    /// Load the second file into a hash table and stream the first. The inputs can
+    /// be in any order; the result is in the order of the first file, followed by
+    /// unmatched records of the second file.
+    Hashed,
+    /// Stream both files, which have to be sorted byte-wise by their key (e.g. with
+    /// [`crate::fs::File::sort_lines`]). Works for files of any size.
+    Sorted,
+}
+
+/// Describes a join of two delimited files.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Join {
+    /// Which records end up in the result
+    kind:        JoinKind,
+    /// How the records are matched
+    strategy:    JoinStrategy,
+    /// The key column (starting at 1) of the first and the second file
+    key_columns: (usize, usize),
+    /// The byte separating fields, or [`None`] for runs of whitespace
+    delimiter:   Option<u8>,
+    /// The value of the fields of the missing side of unmatched records
+    fill:        String,
+}
+
+impl Join {
+    /// Join two files of whitespace-separated fields on their first column, using
+    /// [`JoinStrategy::Hashed`].
+    #[must_use]
+    pub const fn new(kind: JoinKind) -> Self {
+        Self {
+            kind,
+            strategy: JoinStrategy::Hashed,
+            key_columns: (1, 1),
+            delimiter: None,
+            fill: String::new(),
+        }
+    }
+
+    /// Match records with `strategy`.
+    #[must_use]
+    pub const fn strategy(mut self, strategy: JoinStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Use column `first` (starting at 1) of the first file and column `second` of
+    /// the second file as key, like `join -1 <first> -2 <second>`.
+    #[must_use]
+    pub const fn key_columns(mut self, first: usize, second: usize) -> Self {
+        self.key_columns = (first, second);
+        self
+    }
+
+    /// Separate fields by `delimiter` instead of runs of whitespace, like `join -t`.
+    /// Joined records written to a file are separated by the same delimiter (or by
+    /// a space).
+    #[must_use]
+    pub const fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// Fill the fields of the missing side of unmatched records with `fill`, like
+    /// `join -e <fill> -o auto`. By default, they are empty. The number of fields
+    /// of a file is taken from its first record.
+    #[must_use]
+    pub fn fill(mut self, fill: impl Into<String>) -> Self {
+        self.fill = fill.into();
+        self
+    }
+
+    /// Split `line` into its fields.
+    fn split(&self, line: &str) -> Record {
+        self.delimiter.map_or_else(
+            || line.split_whitespace().map(String::from).collect(),
+            |delimiter| {
+                line.split(char::from(delimiter))
+                    .map(String::from)
+                    .collect()
+            },
+        )
+    }
+
+    /// Read the records of `file`, separating the key (in `column`) from the other
+    /// fields. Records without the key column are skipped.
+    fn records(
+        &self,
+        file: &File,
+        column: usize,
+    ) -> TextResult<impl Iterator<Item = TextResult<(String, Record)>> + '_> {
+        use std::io::BufRead as _;
+
+        let index = column.checked_sub(1).ok_or(TextError::InvalidColumn)?;
+        let reader =
+            std::io::BufReader::new(std::fs::File::open(file.path()).map_err(FSError::from)?);
+        Ok(reader.lines().filter_map(move |line| {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => return Some(Err(FSError::from(error).into())),
+            };
+            let mut fields = self.split(line.trim_end_matches('\r'));
+            (index < fields.len()).then(|| Ok((fields.remove(index), fields)))
+        }))
+    }
+
+    /// Join `first` and `second` and pass every joined record to `emit`.
+    fn run(
+        &self,
+        first: &File,
+        second: &File,
+        mut emit: impl FnMut(Record) -> TextResult<()>,
+    ) -> TextResult<()> {
+        log::trace!("Joining {first} and {second}");
+        // The number of non-key fields, which unmatched records are padded to.
+        let width = |file: &File, column: usize| -> TextResult<usize> {
+            Ok(self
+                .records(file, column)?
+                .next()
+                .transpose()?
+                .map_or(0, |(_, fields)| fields.len()))
+        };
+        let widths = (
+            width(first, self.key_columns.0)?,
+            width(second, self.key_columns.1)?,
+        );
+
+        let mut output = |key: &str, a: Option<&Record>, b: Option<&Record>| {
+            let pad = |fields: Option<&Record>, width: usize| {
+                fields
+                    .cloned()
+                    .unwrap_or_else(|| vec![self.fill.clone(); width])
+            };
+            let mut record = vec![key.to_string()];
+            record.extend(pad(a, widths.0));
+            record.extend(pad(b, widths.1));
+            emit(record)
+        };
+
+        let first_records = self.records(first, self.key_columns.0)?;
+        let second_records = self.records(second, self.key_columns.1)?;
+        match self.strategy {
+            JoinStrategy::Hashed => self.run_hashed(first_records, second_records, &mut output),
+            JoinStrategy::Sorted => self.run_sorted(first_records, second_records, &mut output),
+        }
+    }
+
+    /// Join with [`JoinStrategy::Hashed`].
+    fn run_hashed(
+        &self,
+        first: impl Iterator<Item = TextResult<(String, Record)>>,
+        second: impl Iterator<Item = TextResult<(String, Record)>>,
+        output: &mut impl FnMut(&str, Option<&Record>, Option<&Record>) -> TextResult<()>,
+    ) -> TextResult<()> {
+        // The keys in the order of their first occurrence, so unmatched records of
+        // the second file are emitted in their original order.
+        let mut keys = Vec::new();
+        let mut table = std::collections::HashMap::<String, (Vec<Record>, bool)>::new();
+        for record in second {
+            let (key, fields) = record?;
+            table
+                .entry(key)
+                .or_insert_with_key(|key| {
+                    keys.push(key.clone());
+                    (Vec::new(), false)
+                })
+                .0
+                .push(fields);
+        }
+
+        for record in first {
+            let (key, fields) = record?;
+            match table.get_mut(&key) {
+                Some((others, matched)) => {
+                    *matched = true;
+                    for other in others.iter() {
+                        output(&key, Some(&fields), Some(other))?;
+                    }
+                },
+                None if self.kind.keeps_first() => output(&key, Some(&fields), None)?,
+                None => {},
+            }
+        }
+
+        if self.kind.keeps_second() {
+            for key in keys {
+                if let Some((records, false)) = table.get(&key) {
+                    for fields in records {
+                        output(&key, None, Some(fields))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Join with [`JoinStrategy::Sorted`].
+    fn run_sorted(
+        &self,
+        first: impl Iterator<Item = TextResult<(String, Record)>>,
+        second: impl Iterator<Item = TextResult<(String, Record)>>,
+        output: &mut impl FnMut(&str, Option<&Record>, Option<&Record>) -> TextResult<()>,
+    ) -> TextResult<()> {
+        let mut first = Groups::new(first);
+        let mut second = Groups::new(second);
+        let mut a = first.next()?;
+        let mut b = second.next()?;
+        loop {
+            let ordering = match (&a, &b) {
+                (None, None) => return Ok(()),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some((key_a, _)), Some((key_b, _))) => key_a.as_bytes().cmp(key_b.as_bytes()),
+            };
+            match (ordering, &a, &b) {
+                (std::cmp::Ordering::Less, Some((key, records)), _) => {
+                    if self.kind.keeps_first() {
+                        for fields in records {
+                            output(key, Some(fields), None)?;
+                        }
+                    }
+                    a = first.next()?;
+                },
+                (std::cmp::Ordering::Greater, _, Some((key, records))) => {
+                    if self.kind.keeps_second() {
+                        for fields in records {
+                            output(key, None, Some(fields))?;
+                        }
+                    }
+                    b = second.next()?;
+                },
+                (_, Some((key, records_a)), Some((_, records_b))) => {
+                    for fields_a in records_a {
+                        for fields_b in records_b {
+                            output(key, Some(fields_a), Some(fields_b))?;
+                        }
+                    }
+                    a = first.next()?;
+                    b = second.next()?;
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Join `first` and `second` and return the joined records.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be read, if a key column is 0 or if the
+    /// files are not sorted although [`JoinStrategy::Sorted`] is used.
+    pub fn records_of(&self, first: &File, second: &File) -> TextResult<Vec<Record>> {
+        let mut records = Vec::new();
+        self.run(first, second, |record| {
+            records.push(record);
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    /// Join `first` and `second` and write the joined records to `target`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be read or written, if a key column is 0 or
+    /// if the files are not sorted although [`JoinStrategy::Sorted`] is used.
+    pub fn write(
+        &self,
+        first: &File,
+        second: &File,
+        target: impl AsRef<std::path::Path>,
+    ) -> TextResult<File> {
+        use std::io::Write as _;
+
+        let target = target.as_ref();
+        let separator = char::from(self.delimiter.unwrap_or(b' ')).to_string();
+        let mut writer =
+            std::io::BufWriter::new(std::fs::File::create(target).map_err(FSError::from)?);
+        self.run(first, second, |record| {
+            writeln!(writer, "{}", record.join(&separator)).map_err(FSError::from)?;
+            Ok(())
+        })?;
+        writer.flush().map_err(FSError::from)?;
+        Ok(File::new(target))
+    }
+}
+
+/// Groups consecutive records with the same key of a sorted input.
+struct Groups<I: Iterator> {
+    /// The records
+    records: std::iter::Peekable<I>,
+    /// The key of the previous group, to detect unsorted input
+    last:    Option<String>,
+}
+
+impl<I: Iterator> std::fmt::Debug for Groups<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Groups")
+            .field("last", &self.last)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I: Iterator<Item = TextResult<(String, Record)>>> Groups<I> {
+    /// Group the records of `records`.
+    fn new(records: I) -> Self {
+        Self {
+            records: records.peekable(),
+            last:    None,
+        }
+    }
+
+    /// The next key and all of its records.
+    fn next(&mut self) -> TextResult<Option<(String, Vec<Record>)>> {
+        let Some((key, fields)) = self.records.next().transpose()? else {
+            return Ok(None);
+        };
+        if self
+            .last
+            .as_ref()
+            .is_some_and(|last| last.as_bytes() > key.as_bytes())
+        {
+            return Err(TextError::Unsorted(key));
+        }
+
+        let mut group = vec![fields];
+        while let Some(Ok((next, _))) = self.records.peek() {
+            if *next != key {
+                break;
+            }
+            if let Some(Ok((_, fields))) = self.records.next() {
+                group.push(fields);
+            }
+        }
+        self.last = Some(key.clone());
+        Ok(Some((key, group)))
+    }
+}
+
+/// Join `first` and `second`, two files of whitespace-separated fields, on column
+/// `key_column` (starting at 1) of both, see [`Join`].
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or if `key_column` is 0.
+pub fn join(
+    first: &File,
+    second: &File,
+    key_column: usize,
+    kind: JoinKind,
+) -> TextResult<Vec<Record>> {
+    Join::new(kind)
+        .key_columns(key_column, key_column)
+        .records_of(first, second)
+}
+
+#[cfg(test)]
+mod text_test {
+    use super::*;
+    use crate::fs::generate_test_path;
+
+    /// The records as lines, for readable assertions.
+    fn lines(records: &[Record]) -> Vec<String> {
+        records.iter().map(|record| record.join(",")).collect()
+    }
+
+    #[test]
+    fn joins() -> TextResult<()> {
+        let hosts = File::new(generate_test_path());
+        hosts.write_new("alpha 10.0.0.1\nbeta 10.0.0.2\ngamma 10.0.0.3\n")?;
+        let roles = File::new(generate_test_path());
+        roles.write_new("web alpha\ndb beta\ncache beta\nproxy delta\n")?;
+
+        let inner = Join::new(JoinKind::Inner).key_columns(1, 2);
+        assert_eq!(
+            lines(&inner.records_of(&hosts, &roles)?),
+            [
+                "alpha,10.0.0.1,web",
+                "beta,10.0.0.2,db",
+                "beta,10.0.0.2,cache"
+            ]
+        );
+
+        let full = Join::new(JoinKind::Full).key_columns(1, 2).fill("-");
+        assert_eq!(
+            lines(&full.records_of(&hosts, &roles)?),
+            [
+                "alpha,10.0.0.1,web",
+                "beta,10.0.0.2,db",
+                "beta,10.0.0.2,cache",
+                "gamma,10.0.0.3,-",
+                "delta,-,proxy"
+            ]
+        );
+        assert_eq!(
+            lines(&join(&hosts, &hosts, 1, JoinKind::Left)?)[0],
+            "alpha,10.0.0.1,10.0.0.1"
+        );
+        assert_eq!(
+            join(&hosts, &roles, 0, JoinKind::Inner),
+            Err(TextError::InvalidColumn)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sorted_join() -> TextResult<()> {
+        let first = File::new(generate_test_path());
+        first.write_new("a;1\nb;2\nb;3\nd;4\n")?;
+        let second = File::new(generate_test_path());
+        second.write_new("b;x\nc;y\nd;z\n")?;
+
+        let join = Join::new(JoinKind::Full)
+            .strategy(JoinStrategy::Sorted)
+            .delimiter(b';');
+        let target = join.write(&first, &second, generate_test_path())?;
+        assert_eq!(target.read()?, "a;1;\nb;2;x\nb;3;x\nc;;y\nd;4;z\n");
+
+        let unsorted = File::new(generate_test_path());
+        unsorted.write_new("b;1\na;2\n")?;
+        assert_eq!(
+            join.records_of(&unsorted, &second),
+            Err(TextError::Unsorted(String::from("a")))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! This module contains interleaving the lines of several log files in chronological
+//! order, like `sort -m` does for sorted files.
+
+use super::{
+    TextError,
+    TextResult,
+};
+use crate::fs::{
+    FSError,
+    File,
+    Object as _,
+};
+
+/// The abbreviated month names syslog uses.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// How the timestamps of the lines of log files are written, see [`merge_logs`].
+/// The first timestamp found in a line is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimestampFormat {
+    /// RFC 3339 or ISO 8601, e.g. `2024-10-06T12:00:00.123+02:00` or
+    /// `2024-10-06 12:00:00,123`. Timestamps without an offset are taken as UTC.
+    Rfc3339,
+    /// The format of traditional syslog (RFC 3164), e.g. `Oct  6 12:00:00`. It has
+    /// no year, so files must not span New Year.
+    Syslog,
+    /// Seconds since the Unix epoch with an optional fraction, e.g.
+    /// `1728216000.123`, as used by the kernel's audit log or Squid.
+    UnixEpoch,
+}
+
+impl TimestampFormat {
+    /// The regular expression matching a timestamp.
+    const fn pattern(self) -> &'static str {
+        match self {
+            Self::Rfc3339 => concat!(
+                r"(\d{4})-(\d{2})-(\d{2})[Tt ](\d{2}):(\d{2}):(\d{2})",
+                r"(?:[.,](\d{1,9}))?\d*\s?(Z|z|[+-]\d{2}:?\d{2})?"
+            ),
+            Self::Syslog => concat!(
+                r"\b(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) +(\d{1,2}) ",
+                r"(\d{2}):(\d{2}):(\d{2})\b"
+            ),
+            Self::UnixEpoch => r"\b(\d{9,11})(?:\.(\d{1,9}))?\b",
+        }
+    }
+
+    /// The timestamp captured by [`TimestampFormat::pattern`] in nanoseconds since
+    /// the Unix epoch.
+    fn nanoseconds(self, captures: &regex::Captures) -> Option<i128> {
+        let number = |index: usize| -> Option<u64> { captures.get(index)?.as_str().parse().ok() };
+        // Fractions are padded to nanoseconds, so `.1` means 100 milliseconds.
+        let fraction = |index: usize| -> i128 {
+            captures.get(index).map_or(0, |fraction| {
+                format!("{:0<9}", fraction.as_str()).parse().unwrap_or(0)
+            })
+        };
+        let date_time = |year, month, day, first_time: usize| -> Option<i128> {
+            let date_time = crate::library::time::DateTime {
+                year,
+                month,
+                day,
+                hour: number(first_time)?,
+                minute: number(first_time + 1)?,
+                second: number(first_time + 2)?,
+                millisecond: 0,
+            };
+            Some(i128::from(date_time.to_unix_seconds()))
+        };
+
+        let (seconds, nanoseconds) = match self {
+            Self::Rfc3339 => {
+                let local = date_time(number(1)?, number(2)?, number(3)?, 4)?;
+                let offset = match captures.get(8).map(|offset| offset.as_str()) {
+                    None | Some("Z" | "z") => 0,
+                    Some(text) => {
+                        let digits = text[1..].replace(':', "");
+                        let hours: i128 = digits.get(..2)?.parse().ok()?;
+                        let minutes: i128 = digits.get(2..)?.parse().ok()?;
+                        let offset = hours * 3600 + minutes * 60;
+                        if text.starts_with('-') {
+                            -offset
+                        } else {
+                            offset
+                        }
+                    },
+                };
+                (local - offset, fraction(7))
+            },
+            Self::Syslog => {
+                let name = captures.get(1)?.as_str();
+                let month = MONTHS.iter().position(|month| *month == name)?;
+                (date_time(1970, month as u64 + 1, number(2)?, 3)?, 0)
+            },
+            Self::UnixEpoch => (i128::from(number(1)?), fraction(2)),
+        };
+        Some(seconds * 1_000_000_000 + nanoseconds)
+    }
+}
+
+/// A log file that is merged.
+#[derive(Debug)]
+struct Source {
+    /// The lines not read yet
+    lines: std::io::Lines<std::io::BufReader<std::fs::File>>,
+    /// The timestamp of the last line that had one
+    last:  i128,
+}
+
+/// The lines of several log files in chronological order, see [`merge_logs`].
+#[derive(Debug)]
+pub struct MergedLogs {
+    /// The format of the timestamps
+    format:  TimestampFormat,
+    /// The regular expression matching timestamps
+    pattern: regex::Regex,
+    /// The files
+    sources: Vec<Source>,
+    /// The next line of each file that is not exhausted, with its timestamp and the
+    /// index of its file
+    next:    std::collections::BinaryHeap<std::cmp::Reverse<(i128, usize, String)>>,
+    /// An error reading a file, which is returned after the line read before it
+    error:   Option<TextError>,
+}
+
+impl MergedLogs {
+    /// Read the next line of file `index` into [`MergedLogs::next`].
+    fn advance(&mut self, index: usize) -> TextResult<()> {
+        let Some(source) = self.sources.get_mut(index) else {
+            return Ok(());
+        };
+        let Some(line) = source.lines.next() else {
+            return Ok(());
+        };
+        let line = line.map_err(FSError::from)?;
+        if let Some(timestamp) = self
+            .pattern
+            .captures(&line)
+            .and_then(|captures| self.format.nanoseconds(&captures))
+        {
+            source.last = timestamp;
+        }
+        self.next
+            .push(std::cmp::Reverse((source.last, index, line)));
+        Ok(())
+    }
+}
+
+impl Iterator for MergedLogs {
+    type Item = TextResult<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        let std::cmp::Reverse((_, index, line)) = self.next.pop()?;
+        if let Err(error) = self.advance(index) {
+            self.error = Some(error);
+        }
+        Some(Ok(line))
+    }
+}
+
+/// Interleave the lines of `files` in chronological order, reading each file as a
+/// stream (a k-way merge, like `sort -m`).
+///
+/// Each file has to be in chronological order already, as log files are. Lines
+/// without a timestamp (e.g. the continuation lines of stack traces) keep the
+/// timestamp of the line before them, so they stay with it; lines before the
+/// first timestamp of a file come first. Lines with equal timestamps are returned
+/// in the order of `files`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be opened. Errors reading a file are returned
+/// by the iterator.
+pub fn merge_logs(files: &[File], format: TimestampFormat) -> TextResult<MergedLogs> {
+    use std::io::BufRead as _;
+
+    log::trace!("Merging {} log files", files.len());
+    let pattern = regex::Regex::new(format.pattern())
+        .map_err(|error| FSError::InvalidPattern(error.to_string()))?;
+    let sources = files
+        .iter()
+        .map(|file| {
+            let reader = std::fs::File::open(file.path()).map_err(FSError::from)?;
+            Ok(Source {
+                lines: std::io::BufReader::new(reader).lines(),
+                last:  i128::MIN,
+            })
+        })
+        .collect::<TextResult<Vec<_>>>()?;
+
+    let mut merged = MergedLogs {
+        format,
+        pattern,
+        sources,
+        next: std::collections::BinaryHeap::with_capacity(files.len()),
+        error: None,
+    };
+    for index in 0..files.len() {
+        merged.advance(index)?;
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod merge_test {
+    use super::*;
+    use crate::fs::generate_test_path;
+
+    #[test]
+    fn merges() -> TextResult<()> {
+        let web = File::new(generate_test_path());
+        web.write_new(
+            "2024-10-06T12:00:01Z web started\n2024-10-06T14:00:03+02:00 web failed\n\tat \
+             handler\n2024-10-06 12:00:05.5 web stopped\n",
+        )?;
+        let database = File::new(generate_test_path());
+        database.write_new(
+            "preamble\n2024-10-06T12:00:00.999Z db started\n2024-10-06T12:00:03.000Z db \
+             slow\n2024-10-06T12:00:05.25Z db stopped\n",
+        )?;
+
+        let lines = merge_logs(&[web, database], TimestampFormat::Rfc3339)?
+            .collect::<TextResult<Vec<_>>>()?;
+        assert_eq!(
+            lines,
+            [
+                "preamble",
+                "2024-10-06T12:00:00.999Z db started",
+                "2024-10-06T12:00:01Z web started",
+                "2024-10-06T14:00:03+02:00 web failed",
+                "\tat handler",
+                "2024-10-06T12:00:03.000Z db slow",
+                "2024-10-06T12:00:05.25Z db stopped",
+                "2024-10-06 12:00:05.5 web stopped",
+            ]
+        );
+
+        let kernel = File::new(generate_test_path());
+        kernel.write_new("Oct  6 12:00:00 host a\nOct 10 08:00:00 host c\n")?;
+        let daemon = File::new(generate_test_path());
+        daemon.write_new("Oct  9 23:59:59 host b\n")?;
+        let lines = merge_logs(&[kernel, daemon], TimestampFormat::Syslog)?
+            .collect::<TextResult<Vec<_>>>()?;
+        assert_eq!(
+            lines,
+            [
+                "Oct  6 12:00:00 host a",
+                "Oct  9 23:59:59 host b",
+                "Oct 10 08:00:00 host c"
+            ]
+        );
+
+        let audit = File::new(generate_test_path());
+        audit.write_new("msg=audit(1728216000.500:1)\nmsg=audit(1728216002.000:2)\n")?;
+        let proxy = File::new(generate_test_path());
+        proxy.write_new("1728216001.1 GET /\n")?;
+        let lines = merge_logs(&[audit, proxy], TimestampFormat::UnixEpoch)?
+            .map(|line| line.map(|line| line.chars().next_back().unwrap_or_default()))
+            .collect::<TextResult<String>>()?;
+        assert_eq!(lines, ")/)");
+
+        assert!(merge_logs(&[File::new(generate_test_path())], TimestampFormat::Rfc3339).is_err());
+        Ok(())
+    }
+}
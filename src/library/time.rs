@@ -0,0 +1,126 @@
+//! This module contains helpers for converting between [`std::time::SystemTime`] and
+//! calendar dates in UTC, which several modules need for formatting timestamps.
+
+/// A point in time broken down into its calendar components (UTC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    /// The year, e.g. 2024
+    pub year:        u64,
+    /// The month, starting at 1
+    pub month:       u64,
+    /// The day of the month, starting at 1
+    pub day:         u64,
+    /// The hour of the day
+    pub hour:        u64,
+    /// The minute of the hour
+    pub minute:      u64,
+    /// The second of the minute
+    pub second:      u64,
+    /// The millisecond of the second
+    pub millisecond: u32,
+}
+
+impl DateTime {
+    /// Break `time` down into its calendar components. Times before the Unix epoch
+    /// are clamped to the epoch.
+    pub fn from_system_time(time: std::time::SystemTime) -> Self {
+        let since_epoch = time
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let seconds = since_epoch.as_secs();
+        let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+        // Convert days since the epoch to a civil date, see
+        // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+
+        Self {
+            year: year_of_era + era * 400 + u64::from(month <= 2),
+            month,
+            day,
+            hour: seconds_of_day / 3600,
+            minute: seconds_of_day % 3600 / 60,
+            second: seconds_of_day % 60,
+            millisecond: since_epoch.subsec_millis(),
+        }
+    }
+
+    /// The seconds since the Unix epoch, ignoring milliseconds. Dates before the
+    /// epoch are clamped to the epoch.
+    pub fn to_unix_seconds(self) -> u64 {
+        // Convert the civil date to days since the epoch, see
+        // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = self.year.saturating_sub(u64::from(self.month <= 2));
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let month_index = if self.month > 2 {
+            self.month - 3
+        } else {
+            self.month + 9
+        };
+        let day_of_year = (153 * month_index + 2) / 5 + self.day.saturating_sub(1);
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = (era * 146_097 + day_of_era).saturating_sub(719_468);
+        days * 86_400 + self.hour * 3600 + self.minute * 60 + self.second
+    }
+
+    /// Format as an RFC 3339 timestamp with millisecond precision, e.g.
+    /// `2024-10-06T12:00:00.000Z`.
+    pub fn to_rfc3339(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millisecond
+        )
+    }
+
+    /// Format as an ISO 8601 basic timestamp without fractional seconds, e.g.
+    /// `20241006T120000Z`.
+    pub fn to_iso8601_basic(self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod date_time_test {
+    use super::*;
+
+    #[test]
+    fn from_system_time() {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123);
+        let date_time = DateTime::from_system_time(time);
+        assert_eq!(date_time.to_rfc3339(), "2023-11-14T22:13:20.123Z");
+        assert_eq!(date_time.to_iso8601_basic(), "20231114T221320Z");
+        assert_eq!(
+            DateTime::from_system_time(std::time::UNIX_EPOCH).to_rfc3339(),
+            "1970-01-01T00:00:00.000Z"
+        );
+
+        let days_until_leap_day = 11_016;
+        let leap_day =
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(days_until_leap_day * 86_400);
+        assert_eq!(
+            DateTime::from_system_time(leap_day).to_rfc3339(),
+            "2000-02-29T00:00:00.000Z"
+        );
+        assert_eq!(
+            DateTime::from_system_time(leap_day).to_unix_seconds(),
+            days_until_leap_day * 86_400
+        );
+        assert_eq!(date_time.to_unix_seconds(), 1_700_000_000);
+    }
+}
@@ -0,0 +1,194 @@
+//! This module contains functionality for creating cloud-init seed images (the
+//! `NoCloud` data source), which configure a virtual machine on its first boot.
+
+use super::{
+    VirtError,
+    VirtResult,
+};
+use crate::{
+    fs::FSError,
+    process::{
+        Command,
+        ProcessError,
+    },
+};
+
+/// The programs that can create the ISO image, with the arguments that make them
+/// behave like `genisoimage`, in the order they are tried.
+const ISO_TOOLS: [(&str, &[&str]); 3] = [
+    ("genisoimage", &[]),
+    ("mkisofs", &[]),
+    ("xorriso", &["-as", "mkisofs"]),
+];
+
+/// The configuration a virtual machine receives on its first boot.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Seed {
+    /// The ID cloud-init uses to tell whether it already ran on this instance
+    instance_id:    String,
+    /// The hostname of the machine
+    hostname:       String,
+    /// The user data, usually a `#cloud-config` document
+    user_data:      String,
+    /// The network configuration, if the default (DHCP on the first interface) does
+    /// not fit
+    network_config: Option<String>,
+}
+
+impl Seed {
+    /// Create a seed for a machine called `hostname` with empty user data.
+    pub fn new(hostname: impl Into<String>) -> Self {
+        let hostname = hostname.into();
+        Self {
+            instance_id: hostname.clone(),
+            hostname,
+            user_data: String::from("#cloud-config\n"),
+            network_config: None,
+        }
+    }
+
+    /// Use `instance_id` instead of the hostname as instance ID. Changing the ID
+    /// makes cloud-init run again on an existing disk.
+    #[must_use]
+    pub fn instance_id(mut self, instance_id: impl Into<String>) -> Self {
+        self.instance_id = instance_id.into();
+        self
+    }
+
+    /// Use `user_data` as user data.
+    #[must_use]
+    pub fn user_data(mut self, user_data: impl Into<String>) -> Self {
+        self.user_data = user_data.into();
+        self
+    }
+
+    /// Use `template` as user data, replacing every `{{ name }}` with the value of
+    /// the variable `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template uses a variable that is not in `variables`.
+    pub fn user_data_template(
+        self,
+        template: &str,
+        variables: &std::collections::BTreeMap<String, String>,
+    ) -> VirtResult<Self> {
+        Ok(self.user_data(render(template, variables)?))
+    }
+
+    /// Use `network_config` (version 1 or 2) as network configuration.
+    #[must_use]
+    pub fn network_config(mut self, network_config: impl Into<String>) -> Self {
+        self.network_config = Some(network_config.into());
+        self
+    }
+
+    /// The meta data, which holds the instance ID and the hostname.
+    fn meta_data(&self) -> String {
+        format!(
+            "instance-id: {}\nlocal-hostname: {}\n",
+            self.instance_id, self.hostname
+        )
+    }
+
+    /// Write the seed as an ISO image to `path`, which is attached to the machine as
+    /// a CD-ROM. One of `genisoimage`, `mkisofs` or `xorriso` needs to be installed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no program to create ISO images is installed or if
+    /// writing the files fails.
+    pub fn write_iso(&self, path: impl AsRef<std::path::Path>) -> VirtResult<()> {
+        /// How many seed directories this process created, which keeps their names
+        /// unique.
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let path = path.as_ref();
+        log::debug!("Writing cloud-init seed to '{}'", path.display());
+        let directory = std::env::temp_dir().join(format!(
+            ".rush-seed-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let result = self.write_iso_from(&directory, path);
+        let _ = std::fs::remove_dir_all(&directory);
+        result
+    }
+
+    /// Write the files of the seed to `directory` and create the image at `path`
+    /// from them.
+    fn write_iso_from(
+        &self,
+        directory: &std::path::Path,
+        path: &std::path::Path,
+    ) -> VirtResult<()> {
+        std::fs::create_dir_all(directory).map_err(FSError::from)?;
+        let mut files = vec!["user-data", "meta-data"];
+        std::fs::write(directory.join("user-data"), &self.user_data).map_err(FSError::from)?;
+        std::fs::write(directory.join("meta-data"), self.meta_data()).map_err(FSError::from)?;
+        if let Some(network_config) = &self.network_config {
+            std::fs::write(directory.join("network-config"), network_config)
+                .map_err(FSError::from)?;
+            files.push("network-config");
+        }
+
+        for (program, arguments) in ISO_TOOLS {
+            // cloud-init only recognizes the image by the volume ID `cidata`.
+            let result = Command::new(program)
+                .args(arguments.iter())
+                .args(["-output", &path.display().to_string()])
+                .args(["-volid", "cidata", "-joliet", "-rock"])
+                .args(&files)
+                .current_dir(directory)
+                .run();
+            match result {
+                Ok(_) => return Ok(()),
+                Err(ProcessError::NotFound) => {},
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Err(VirtError::NoIsoTool)
+    }
+}
+
+/// Replace every `{{ name }}` in `template` with the value of the variable `name`.
+fn render(
+    template: &str,
+    variables: &std::collections::BTreeMap<String, String>,
+) -> VirtResult<String> {
+    crate::library::template::render(template, variables).map_err(VirtError::MissingVariable)
+}
+
+#[cfg(test)]
+mod cloud_init_test {
+    use super::*;
+
+    #[test]
+    fn templates() -> VirtResult<()> {
+        let variables = [
+            (String::from("user"), String::from("tester")),
+            (String::from("key"), String::from("ssh-ed25519 AAAA")),
+        ]
+        .into_iter()
+        .collect();
+
+        let seed = Seed::new("test-vm").user_data_template(
+            "#cloud-config\nusers:\n  - name: {{ user }}\n    ssh_authorized_keys: [{{key}}]\n",
+            &variables,
+        )?;
+        assert_eq!(
+            seed.user_data,
+            "#cloud-config\nusers:\n  - name: tester\n    ssh_authorized_keys: [ssh-ed25519 \
+             AAAA]\n"
+        );
+        assert_eq!(
+            seed.meta_data(),
+            "instance-id: test-vm\nlocal-hostname: test-vm\n"
+        );
+
+        assert_eq!(
+            render("{{ missing }}", &variables),
+            Err(VirtError::MissingVariable(String::from("missing")))
+        );
+        Ok(())
+    }
+}
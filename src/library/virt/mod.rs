@@ -0,0 +1,58 @@
+//! This module contains functionality for provisioning virtual machines with QEMU
+//! and cloud-init, e.g. to test images in CI.
+//!
+//! A typical test boots a disk image with a [`Seed`] that creates a user with an SSH
+//! key, waits until SSH is reachable and then runs its checks over SSH:
+//!
+//! ```no_run
+//! # use rush::virt::{Seed, Vm};
+//! # fn main() -> rush::virt::VirtResult<()> {
+//! Seed::new("test-vm")
+//!     .user_data("#cloud-config\nssh_authorized_keys: [ssh-ed25519 AAAA...]\n")
+//!     .write_iso("/tmp/seed.iso")?;
+//! let mut vm = Vm::new("/tmp/image.qcow2")
+//!     .seed("/tmp/seed.iso")
+//!     .forward_ssh(2222)
+//!     .snapshot(true)
+//!     .launch()?;
+//! vm.wait_for_ssh(std::time::Duration::from_secs(300))?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod cloud_init;
+mod qemu;
+
+pub use cloud_init::Seed;
+pub use qemu::{
+    Accelerator,
+    RunningVm,
+    Vm,
+};
+
+use crate::{
+    fs::FSError,
+    process::ProcessError,
+};
+
+/// Describes possible errors when provisioning virtual machines.
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Hash)]
+pub enum VirtError {
+    #[error("Running an external program failed: {0}")]
+    Process(#[from] ProcessError),
+    #[error("Accessing a file failed: {0}")]
+    FS(#[from] FSError),
+    #[error("The template uses the variable '{0}', which is not set")]
+    MissingVariable(String),
+    #[error("None of the programs that create ISO images is installed")]
+    NoIsoTool,
+    #[error("The virtual machine exited unexpectedly (exit code {0:?})")]
+    Exited(Option<i32>),
+    #[error("No port on the host is forwarded to SSH")]
+    NoSshForward,
+    #[error("SSH did not become reachable within {0:?}")]
+    Timeout(std::time::Duration),
+}
+
+/// A [`Result`] whose error variant is a [`VirtError`].
+pub type VirtResult<T> = Result<T, VirtError>;
@@ -0,0 +1,351 @@
+//! This module contains functionality for running virtual machines with QEMU, which
+//! needs to be installed.
+
+use super::{
+    VirtError,
+    VirtResult,
+};
+use crate::process::{
+    Command,
+    ProcessError,
+};
+
+/// How long to wait for a connection to the forwarded SSH port and for the banner.
+const SSH_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+/// How long to wait between two attempts to reach SSH.
+const SSH_PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How the virtual machine is accelerated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Accelerator {
+    /// The Linux kernel-based virtual machine; needs access to `/dev/kvm`
+    Kvm,
+    /// The macOS hypervisor framework
+    Hvf,
+    /// Pure emulation, which works everywhere but is slow
+    Tcg,
+}
+
+impl Accelerator {
+    /// The name QEMU knows the accelerator by.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Kvm => "kvm",
+            Self::Hvf => "hvf",
+            Self::Tcg => "tcg",
+        }
+    }
+
+    /// The fastest accelerator available on this machine.
+    fn detect() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Hvf
+        } else if std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/kvm")
+            .is_ok()
+        {
+            Self::Kvm
+        } else {
+            Self::Tcg
+        }
+    }
+}
+
+/// Describes a virtual machine booting from a disk image. The machine is not started
+/// until [`Vm::launch`] is called.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Vm {
+    /// The QEMU program, which determines the emulated architecture
+    program:     String,
+    /// The disk image to boot from
+    disk:        std::path::PathBuf,
+    /// The cloud-init seed image attached as CD-ROM
+    seed:        Option<std::path::PathBuf>,
+    /// The memory in MiB
+    memory:      u32,
+    /// The number of virtual CPUs
+    cpus:        u32,
+    /// The accelerator, or [`None`] to pick the fastest available one
+    accelerator: Option<Accelerator>,
+    /// The port on the host forwarded to the SSH port of the machine
+    ssh_port:    Option<u16>,
+    /// Whether writes to the disk are discarded when the machine stops
+    snapshot:    bool,
+    /// The file the serial console is written to
+    serial_log:  Option<std::path::PathBuf>,
+}
+
+/// Escape `value` for use in a QEMU option, in which commas separate parameters.
+fn escape_option(value: &std::path::Path) -> String {
+    value.display().to_string().replace(',', ",,")
+}
+
+impl Vm {
+    /// Boot the disk image at `disk` on an x86-64 machine with 2 GiB of memory and 2
+    /// CPUs. The image format (e.g. raw or qcow2) is detected by QEMU.
+    pub fn new(disk: impl AsRef<std::path::Path>) -> Self {
+        Self {
+            program:     String::from("qemu-system-x86_64"),
+            disk:        disk.as_ref().to_path_buf(),
+            seed:        None,
+            memory:      2048,
+            cpus:        2,
+            accelerator: None,
+            ssh_port:    None,
+            snapshot:    false,
+            serial_log:  None,
+        }
+    }
+
+    /// Emulate `architecture` (e.g. `aarch64`) instead of x86-64. This runs
+    /// `qemu-system-<architecture>`, which may need further options like `-machine`
+    /// that can be passed with [`Vm::command`].
+    #[must_use]
+    pub fn architecture(mut self, architecture: &str) -> Self {
+        self.program = format!("qemu-system-{architecture}");
+        self
+    }
+
+    /// Attach the cloud-init seed image at `path`, see [`super::Seed::write_iso`].
+    #[must_use]
+    pub fn seed(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.seed = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Give the machine `memory` MiB of memory.
+    #[must_use]
+    pub const fn memory(mut self, memory: u32) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Give the machine `cpus` virtual CPUs.
+    #[must_use]
+    pub const fn cpus(mut self, cpus: u32) -> Self {
+        self.cpus = cpus;
+        self
+    }
+
+    /// Use `accelerator` instead of the fastest available one.
+    #[must_use]
+    pub const fn accelerator(mut self, accelerator: Accelerator) -> Self {
+        self.accelerator = Some(accelerator);
+        self
+    }
+
+    /// Forward `port` on the host's loopback interface to the SSH port of the
+    /// machine, which is required for [`RunningVm::wait_for_ssh`].
+    #[must_use]
+    pub const fn forward_ssh(mut self, port: u16) -> Self {
+        self.ssh_port = Some(port);
+        self
+    }
+
+    /// Whether to discard all writes to the disk when the machine stops, so that the
+    /// image can be reused for the next test.
+    #[must_use]
+    pub const fn snapshot(mut self, enabled: bool) -> Self {
+        self.snapshot = enabled;
+        self
+    }
+
+    /// Write the serial console of the machine to `path`, which is where the boot
+    /// messages end up.
+    #[must_use]
+    pub fn serial_log(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        self.serial_log = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The QEMU invocation for this machine. Further options can be added before
+    /// it is spawned by hand.
+    #[must_use]
+    pub fn command(&self) -> Command {
+        let accelerator = self.accelerator.unwrap_or_else(Accelerator::detect);
+        let mut command = Command::new(&self.program)
+            .args(["-accel", accelerator.name()])
+            .args(["-m", &self.memory.to_string()])
+            .args(["-smp", &self.cpus.to_string()])
+            .args(["-display", "none"])
+            .args([
+                "-drive",
+                &format!("file={},if=virtio", escape_option(&self.disk)),
+            ]);
+        if let Some(seed) = &self.seed {
+            command = command.args([
+                "-drive",
+                &format!("file={},media=cdrom,readonly=on", escape_option(seed)),
+            ]);
+        }
+
+        let forward = self
+            .ssh_port
+            .map(|port| format!(",hostfwd=tcp:127.0.0.1:{port}-:22"))
+            .unwrap_or_default();
+        command = command
+            .args(["-netdev", &format!("user,id=net0{forward}")])
+            .args(["-device", "virtio-net-pci,netdev=net0"]);
+
+        if self.snapshot {
+            command = command.arg("-snapshot");
+        }
+        let serial = self.serial_log.as_ref().map_or_else(
+            || String::from("none"),
+            |path| format!("file:{}", path.display()),
+        );
+        command.args(["-serial", &serial])
+    }
+
+    /// Start the machine in the background.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if QEMU could not be started.
+    pub fn launch(&self) -> VirtResult<RunningVm> {
+        log::debug!("Launching virtual machine from '{}'", self.disk.display());
+        Ok(RunningVm {
+            child:    self.command().spawn()?,
+            ssh_port: self.ssh_port,
+        })
+    }
+}
+
+/// A virtual machine that was started with [`Vm::launch`]. The machine is killed
+/// when this value is dropped.
+#[derive(Debug)]
+pub struct RunningVm {
+    /// The QEMU process
+    child:    std::process::Child,
+    /// The port on the host forwarded to the SSH port of the machine
+    ssh_port: Option<u16>,
+}
+
+impl RunningVm {
+    /// The port on the host's loopback interface that is forwarded to the SSH port
+    /// of the machine.
+    #[must_use]
+    pub const fn ssh_port(&self) -> Option<u16> { self.ssh_port }
+
+    /// Check whether the machine is still running.
+    fn check_running(&mut self) -> VirtResult<()> {
+        self.child
+            .try_wait()
+            .map_err(ProcessError::from)?
+            .map_or(Ok(()), |status| Err(VirtError::Exited(status.code())))
+    }
+
+    /// Wait until the SSH server of the machine answers on the forwarded port. Since
+    /// QEMU accepts connections on the port before the machine is up, the SSH banner
+    /// is awaited.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no port is forwarded, if the machine exits or if SSH
+    /// does not answer within `timeout`.
+    pub fn wait_for_ssh(&mut self, timeout: std::time::Duration) -> VirtResult<()> {
+        let port = self.ssh_port.ok_or(VirtError::NoSshForward)?;
+        log::debug!("Waiting for SSH on port {port}");
+
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            self.check_running()?;
+            if ssh_banner(port) {
+                return Ok(());
+            }
+            if std::time::Instant::now() >= deadline {
+                return Err(VirtError::Timeout(timeout));
+            }
+            std::thread::sleep(SSH_PROBE_INTERVAL);
+        }
+    }
+
+    /// Kill the machine and wait for QEMU to exit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if QEMU cannot be killed.
+    pub fn stop(mut self) -> VirtResult<()> {
+        log::debug!("Stopping virtual machine");
+        self.child.kill().map_err(ProcessError::from)?;
+        self.child.wait().map_err(ProcessError::from)?;
+        Ok(())
+    }
+}
+
+impl Drop for RunningVm {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Whether an SSH server answers with its banner on `port` of the loopback
+/// interface.
+fn ssh_banner(port: u16) -> bool {
+    use std::io::Read as _;
+
+    let address = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    let Ok(mut stream) = std::net::TcpStream::connect_timeout(&address, SSH_PROBE_TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(SSH_PROBE_TIMEOUT));
+    let mut banner = [0; 4];
+    stream.read_exact(&mut banner).is_ok() && &banner == b"SSH-"
+}
+
+#[cfg(test)]
+mod qemu_test {
+    use super::*;
+
+    #[test]
+    fn command_line() {
+        let vm = Vm::new("/srv/images/a,b.qcow2")
+            .seed("/tmp/seed.iso")
+            .accelerator(Accelerator::Tcg)
+            .memory(1024)
+            .forward_ssh(2222)
+            .snapshot(true);
+        let command = vm.command();
+        assert_eq!(command.program(), "qemu-system-x86_64");
+        let arguments = command.arguments().join(" ");
+        assert!(arguments.starts_with("-accel tcg -m 1024 -smp 2 -display none"));
+        assert!(arguments.contains("file=/srv/images/a,,b.qcow2,if=virtio"));
+        assert!(arguments.contains("file=/tmp/seed.iso,media=cdrom"));
+        assert!(arguments.contains("hostfwd=tcp:127.0.0.1:2222-:22"));
+        assert!(arguments.ends_with("-snapshot -serial none"));
+    }
+
+    #[test]
+    fn wait_for_ssh() -> VirtResult<()> {
+        use std::io::Write as _;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(ProcessError::from)?;
+        let port = listener.local_addr().map_err(ProcessError::from)?.port();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n");
+            }
+        });
+
+        let mut vm = RunningVm {
+            child:    Command::new("sleep").arg("10").spawn()?,
+            ssh_port: Some(port),
+        };
+        vm.wait_for_ssh(std::time::Duration::from_secs(5))?;
+        vm.stop()?;
+
+        let mut exited = RunningVm {
+            child:    Command::new("true").spawn()?,
+            ssh_port: Some(port),
+        };
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(matches!(
+            exited.wait_for_ssh(std::time::Duration::from_secs(5)),
+            Err(VirtError::Exited(Some(0)))
+        ));
+        Ok(())
+    }
+}